@@ -0,0 +1,176 @@
+#ifndef THE_CAMERA_H
+#define THE_CAMERA_H
+
+// Generated by cbindgen from src/ffi.rs, edit that instead
+
+#include <stdbool.h>
+#include <stdint.h>
+
+// What a call came to
+typedef enum TcResult {
+  TC_RESULT_OK = 0,
+  // A null pointer or a string that isn't UTF-8
+  TC_RESULT_INVALID_ARGUMENT,
+  // Reading a file failed
+  TC_RESULT_IO,
+  // The file format can't be loaded
+  TC_RESULT_UNSUPPORTED,
+  // The engine panicked, it may not work anymore
+  TC_RESULT_PANIC,
+} TcResult;
+
+typedef enum TcMouseButton {
+  TC_MOUSE_BUTTON_LEFT,
+  TC_MOUSE_BUTTON_RIGHT,
+  TC_MOUSE_BUTTON_MIDDLE,
+  TC_MOUSE_BUTTON_BACK,
+  TC_MOUSE_BUTTON_FORWARD,
+} TcMouseButton;
+
+// An engine drawing into one window of the host
+typedef struct TcEngine TcEngine;
+
+// Where the orbit camera is, angles in radians
+typedef struct TcCamera {
+  float target[3];
+  float yaw;
+  float pitch;
+  float distance;
+} TcCamera;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The message of the last call that failed on this thread, null if none did. It stays valid until the next one
+// fails.
+const char *tc_last_error(void);
+
+// Creates an engine drawing into an X11 window
+//
+// # Safety
+//
+// `display` has to be the window's Xlib `Display`, both valid until [tc_engine_destroy].
+TcEngine *tc_engine_create_xlib(void *display,
+                                unsigned long window,
+                                int screen,
+                                uint32_t width,
+                                uint32_t height);
+
+// Creates an engine drawing into a Wayland surface
+//
+// # Safety
+//
+// `display` and `surface` have to be a `wl_display` and `wl_surface`, valid until [tc_engine_destroy].
+TcEngine *tc_engine_create_wayland(void *display, void *surface, uint32_t width, uint32_t height);
+
+// Creates an engine drawing into a Win32 window, `hinstance` may be null
+//
+// # Safety
+//
+// `hwnd` has to be a window of the calling thread, valid until [tc_engine_destroy].
+TcEngine *tc_engine_create_win32(void *hwnd, void *hinstance, uint32_t width, uint32_t height);
+
+// Creates an engine drawing into a macOS `NSView`
+//
+// # Safety
+//
+// `ns_view` has to be valid until [tc_engine_destroy] and used on the main thread only.
+TcEngine *tc_engine_create_appkit(void *ns_view, uint32_t width, uint32_t height);
+
+// Frees the engine, null is ignored
+//
+// # Safety
+//
+// `engine` has to come from one of the `tc_engine_create_*` functions and isn't valid afterwards.
+void tc_engine_destroy(TcEngine *engine);
+
+// Call whenever the window's size changed, in physical pixels
+//
+// # Safety
+//
+// `engine` has to be null or a live engine.
+TcResult tc_engine_resize(TcEngine *engine, uint32_t width, uint32_t height);
+
+// Physical pixels from the top left of the window. Returns whether the engine used the event, e.g. for dragging
+// its gizmo, so the host's own tools can leave it alone.
+//
+// # Safety
+//
+// `engine` has to be null or a live engine.
+bool tc_engine_pointer_moved(TcEngine *engine, float x, float y);
+
+// The pointer left the window
+//
+// # Safety
+//
+// `engine` has to be null or a live engine.
+bool tc_engine_pointer_left(TcEngine *engine);
+
+// # Safety
+//
+// `engine` has to be null or a live engine.
+bool tc_engine_mouse_button(TcEngine *engine, TcMouseButton button, bool pressed);
+
+// In mouse wheel lines, positive away from the user
+//
+// # Safety
+//
+// `engine` has to be null or a live engine.
+bool tc_engine_scroll(TcEngine *engine, float lines);
+
+#if (defined(_WIN32) || defined(__APPLE__) || defined(__linux__))
+// A key by the platform's scancode: a Windows scancode, a macOS virtual key code, or an X11 or Wayland keycode
+// minus 8. Keys the engine doesn't know are ignored.
+//
+// # Safety
+//
+// `engine` has to be null or a live engine.
+bool tc_engine_key(TcEngine *engine, uint32_t scancode, bool pressed, bool repeat);
+#endif
+
+// What typing produced, sent after the key press that typed it
+//
+// # Safety
+//
+// `engine` has to be null or a live engine, `text` null or a NUL terminated string.
+bool tc_engine_text(TcEngine *engine, const char *text);
+
+// # Safety
+//
+// `engine` has to be null or a live engine.
+bool tc_engine_focused(TcEngine *engine, bool focused);
+
+// Adds a model to the scene and points the camera at it. OBJ, glTF and USD files are supported, FBX with the `fbx`
+// feature.
+//
+// # Safety
+//
+// `engine` has to be null or a live engine, `path` null or a NUL terminated string.
+TcResult tc_engine_load_model(TcEngine *engine, const char *path);
+
+// # Safety
+//
+// `engine` has to be null or a live engine, `camera` null or writable.
+TcResult tc_engine_get_camera(TcEngine *engine, TcCamera *camera);
+
+// Moves the camera, within its bounds
+//
+// # Safety
+//
+// `engine` has to be null or a live engine, `camera` null or readable.
+TcResult tc_engine_set_camera(TcEngine *engine, const TcCamera *camera);
+
+// Updates the scene and draws it into the window. Call it once per frame of the host, the engine doesn't
+// schedule frames itself.
+//
+// # Safety
+//
+// `engine` has to be null or a live engine.
+TcResult tc_engine_render_frame(TcEngine *engine);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* THE_CAMERA_H */
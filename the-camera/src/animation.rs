@@ -0,0 +1,257 @@
+//! Playing the animation clips of a [ModelData], one at a time, cross-fading from one to the next or blending two of
+//! them. Players handed to [crate::render_engine::RenderEngine::add_animation_player] advance with every update and
+//! move the renderables of parts that follow a joint, see [crate::model::ModelPart::joint]. Skinned parts stay in
+//! their bind pose, [AnimationPlayer::joint_matrices] has what skinning them takes.
+
+use cgmath::{Matrix4, SquareMatrix, VectorSpace};
+
+use crate::{
+    model::{slerp, AnimationClip, ModelData, Skeleton},
+    scene::{MeshHandle, Transform},
+};
+
+/// Refers to a player added with [crate::render_engine::RenderEngine::add_animation_player]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AnimationPlayerHandle(pub(crate) usize);
+
+/// A clip and how far into it the player is
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ClipTime {
+    clip: usize,
+    time: f32,
+}
+
+/// A second clip mixed over the current one
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Blend {
+    to: ClipTime,
+    /// 0 shows the current clip, 1 the blended one
+    weight: f32,
+    /// Seconds for the weight to reach 1, when the blended clip takes over. None holds the weight.
+    fade: Option<f32>,
+}
+
+/// Poses a model's skeleton by its clips as time passes
+#[derive(Debug, Clone)]
+pub struct AnimationPlayer {
+    skeleton: Skeleton,
+    clips: Vec<AnimationClip>,
+    /// For each of the model's parts, its transform into the model's space and the joint it follows
+    parts: Vec<(Matrix4<f32>, Option<usize>)>,
+    /// Inverse model space matrices of the joints at rest
+    inverse_rest: Vec<Matrix4<f32>>,
+    current: Option<ClipTime>,
+    blend: Option<Blend>,
+    /// Starts clips over after their end instead of holding their last pose
+    pub looping: bool,
+    /// How fast the clips play, 1 in real time and 0 pauses. Cross-fades take the same time at any speed.
+    pub speed: f32,
+}
+
+impl AnimationPlayer {
+    /// Plays the model's first clip, looping
+    pub fn new(model: &ModelData) -> Self {
+        let skeleton = model.skeleton.clone().unwrap_or_default();
+        let inverse_rest = skeleton
+            .model_matrices(&skeleton.rest_pose())
+            .into_iter()
+            .map(|matrix| matrix.invert().unwrap_or_else(Matrix4::identity))
+            .collect();
+        AnimationPlayer {
+            skeleton,
+            clips: model.animations.clone(),
+            parts: model
+                .parts
+                .iter()
+                .map(|part| (part.transform, part.joint))
+                .collect(),
+            inverse_rest,
+            current: (!model.animations.is_empty()).then_some(ClipTime { clip: 0, time: 0.0 }),
+            blend: None,
+            looping: true,
+            speed: 1.0,
+        }
+    }
+
+    pub fn clips(&self) -> &[AnimationClip] {
+        &self.clips
+    }
+
+    /// The index of the first clip named `name`
+    pub fn clip_named(&self, name: &str) -> Option<usize> {
+        self.clips.iter().position(|clip| clip.name == name)
+    }
+
+    /// The clip playing, the one faded to once a cross-fade finishes. None when stopped.
+    pub fn clip(&self) -> Option<usize> {
+        self.current.map(|current| current.clip)
+    }
+
+    /// Seconds into [AnimationPlayer::clip]
+    pub fn time(&self) -> Option<f32> {
+        self.current.map(|current| current.time)
+    }
+
+    /// Plays a clip from its start, ending any cross-fade or blend
+    pub fn play(&mut self, clip: usize) {
+        if !self.has_clip(clip) {
+            return;
+        }
+        self.current = Some(ClipTime { clip, time: 0.0 });
+        self.blend = None;
+    }
+
+    /// Fades from the clip playing over to another one over `seconds`, which starts from the beginning
+    pub fn cross_fade(&mut self, clip: usize, seconds: f32) {
+        if !self.has_clip(clip) {
+            return;
+        }
+        if self.current.is_none() || seconds <= 0.0 {
+            self.play(clip);
+            return;
+        }
+        self.blend = Some(Blend {
+            to: ClipTime { clip, time: 0.0 },
+            weight: 0.0,
+            fade: Some(seconds),
+        });
+    }
+
+    /// Mixes another clip over the one playing until the next [AnimationPlayer::play], both playing along. `weight`
+    /// goes from 0 for only the playing clip to 1 for only the other one, changing it keeps the other clip's time.
+    pub fn blend(&mut self, clip: usize, weight: f32) {
+        if !self.has_clip(clip) {
+            return;
+        }
+        let to = match self.blend {
+            Some(blend) if blend.to.clip == clip => blend.to,
+            _ => ClipTime { clip, time: 0.0 },
+        };
+        self.blend = Some(Blend {
+            to,
+            weight: weight.clamp(0.0, 1.0),
+            fade: None,
+        });
+    }
+
+    /// Returns to the rest pose
+    pub fn stop(&mut self) {
+        self.current = None;
+        self.blend = None;
+    }
+
+    /// Whether later updates may change the pose
+    pub fn is_playing(&self) -> bool {
+        let moving = |clip: ClipTime| {
+            self.speed != 0.0 && (self.looping || clip.time < self.clips[clip.clip].duration)
+        };
+        self.current.is_some_and(moving)
+            || self
+                .blend
+                .is_some_and(|blend| blend.fade.is_some() || moving(blend.to))
+    }
+
+    /// Moves the clips along by `seconds`, finishing cross-fades that are done
+    pub fn advance(&mut self, seconds: f32) {
+        let delta = seconds * self.speed;
+        if let Some(current) = &mut self.current {
+            current.time = Self::wrap(
+                &self.clips,
+                self.looping,
+                current.clip,
+                current.time + delta,
+            );
+        }
+        let Some(blend) = &mut self.blend else {
+            return;
+        };
+        blend.to.time = Self::wrap(
+            &self.clips,
+            self.looping,
+            blend.to.clip,
+            blend.to.time + delta,
+        );
+        if let Some(fade) = blend.fade {
+            blend.weight += seconds / fade;
+            if blend.weight >= 1.0 {
+                self.current = Some(blend.to);
+                self.blend = None;
+            }
+        }
+    }
+
+    /// Each joint's transform relative to its parent, as the clips have them now
+    pub fn pose(&self) -> Vec<Transform> {
+        let mut pose = self.skeleton.rest_pose();
+        if let Some(current) = self.current {
+            self.clips[current.clip].apply(current.time, &mut pose);
+        }
+        if let Some(blend) = self.blend {
+            let mut other = self.skeleton.rest_pose();
+            self.clips[blend.to.clip].apply(blend.to.time, &mut other);
+            for (transform, other) in pose.iter_mut().zip(other) {
+                *transform = Transform {
+                    translation: transform.translation.lerp(other.translation, blend.weight),
+                    rotation: slerp(transform.rotation, other.rotation, blend.weight),
+                    scale: transform.scale.lerp(other.scale, blend.weight),
+                };
+            }
+        }
+        pose
+    }
+
+    /// The model space matrix of every joint of the skeleton, as the clips have them now
+    pub fn joint_matrices(&self) -> Vec<Matrix4<f32>> {
+        self.skeleton.model_matrices(&self.pose())
+    }
+
+    /// For each part, where the pose moved it to in its own space, None for parts not following a joint
+    pub(crate) fn part_offsets(&self) -> Vec<Option<Matrix4<f32>>> {
+        let joints = self.joint_matrices();
+        self.parts
+            .iter()
+            .map(|&(transform, joint)| {
+                let joint = joint?;
+                let moved = joints.get(joint)? * self.inverse_rest[joint];
+                Some(transform.invert()? * moved * transform)
+            })
+            .collect()
+    }
+
+    pub(crate) fn part_count(&self) -> usize {
+        self.parts.len()
+    }
+
+    fn has_clip(&self, clip: usize) -> bool {
+        let exists = clip < self.clips.len();
+        if !exists {
+            tracing::warn!("Animation clip {clip} doesn't exist");
+        }
+        exists
+    }
+
+    /// A clip's time wrapped around its end when looping, held at its ends otherwise
+    fn wrap(clips: &[AnimationClip], looping: bool, clip: usize, time: f32) -> f32 {
+        let duration = clips[clip].duration;
+        match looping && duration > 0.0 {
+            true => time.rem_euclid(duration),
+            false => time.clamp(0.0, duration),
+        }
+    }
+}
+
+/// A player together with the meshes of its model's renderables, one for each part
+pub(crate) struct AnimatedModel {
+    pub player: AnimationPlayer,
+    pub meshes: Vec<MeshHandle>,
+}
+
+impl AnimatedModel {
+    /// Where the pose moved the meshes of parts following a joint, in each mesh's space
+    pub fn mesh_offsets(&self) -> impl Iterator<Item = (MeshHandle, Matrix4<f32>)> + '_ {
+        self.meshes
+            .iter()
+            .zip(self.player.part_offsets())
+            .filter_map(|(&mesh, offset)| Some((mesh, offset?)))
+    }
+}
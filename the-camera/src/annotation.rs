@@ -0,0 +1,113 @@
+//! Notes pinned to points in the scene, drawn over every window as a marker with a line of text facing the camera.
+//!
+//! A label can sit away from its pin, joined to it by a leader line, to keep it clear of the geometry it points at.
+//! Pins hidden behind something in the scene fade out along with their label instead of disappearing. Annotations
+//! are plain data and save to TOML, so they can be kept next to the models they belong to:
+//!
+//! ```toml
+//! [[annotation]]
+//! anchor = [0.5, 0.5, 0.5]
+//! text = "Corner"
+//! color = [1.0, 1.0, 1.0, 1.0]
+//! label = [1.0, 1.2, 0.5]
+//! ```
+
+use std::io;
+
+use cgmath::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::overlay::{OverlayGeometry, OverlayView};
+
+/// Half the size of the pin markers, in pixels
+const PIN_SIZE: f32 = 4.0;
+/// Labels without their own position are drawn this many pixels above the pin
+const LABEL_OFFSET: f32 = 14.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Annotation {
+    /// The point pinned, in world space
+    pub anchor: [f32; 3],
+    /// Drawn in the debug HUD's font, letters uppercase
+    pub text: String,
+    #[serde(default = "default_color")]
+    pub color: [f32; 4],
+    /// Where the label is drawn in world space, with a leader line to the anchor. Right above the pin if None.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<[f32; 3]>,
+}
+
+fn default_color() -> [f32; 4] {
+    [1.0; 4]
+}
+
+impl Annotation {
+    pub fn new(anchor: Vector3<f32>, text: impl Into<String>) -> Self {
+        Annotation {
+            anchor: anchor.into(),
+            text: text.into(),
+            color: default_color(),
+            label: None,
+        }
+    }
+
+    pub fn color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Draws the label at `position`, with a leader line to the anchor
+    pub fn leader(mut self, position: Vector3<f32>) -> Self {
+        self.label = Some(position.into());
+        self
+    }
+
+    /// Laid out for the window `view` looks through
+    pub(crate) fn geometry(&self, geometry: &mut OverlayGeometry, view: &OverlayView) {
+        let anchor = Vector3::from(self.anchor);
+        geometry.fade_behind(Some(anchor));
+        let (right, up) = (view.right(), view.up());
+        let size = PIN_SIZE * view.pixel_size(anchor);
+        // A diamond facing the camera
+        geometry.quad(
+            [
+                anchor - up * size,
+                anchor + right * size,
+                anchor + up * size,
+                anchor - right * size,
+            ],
+            self.color,
+        );
+        let label = match self.label {
+            Some(label) => {
+                let label = Vector3::from(label);
+                geometry.line(anchor, label, self.color);
+                label
+            }
+            None => anchor + up * (LABEL_OFFSET * view.pixel_size(anchor)),
+        };
+        geometry.text(view, label, &self.text, self.color);
+        geometry.fade_behind(None);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct AnnotationFile {
+    #[serde(default)]
+    annotation: Vec<Annotation>,
+}
+
+/// Annotations written as an array of `[[annotation]]` tables, see the [module docs](self)
+pub fn to_toml(annotations: &[Annotation]) -> String {
+    toml::to_string(&AnnotationFile {
+        annotation: annotations.to_vec(),
+    })
+    .expect("Annotations can always be written as TOML!")
+}
+
+pub fn from_toml(source: &str) -> io::Result<Vec<Annotation>> {
+    toml::from_str::<AnnotationFile>(source)
+        .map(|file| file.annotation)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
@@ -1,88 +1,459 @@
-use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{cell::RefCell, path::PathBuf, rc::Rc};
+use std::{collections::HashMap, sync::Arc};
 
 use winit::{
     application::ApplicationHandler,
     dpi::PhysicalSize,
-    event::{Event, WindowEvent},
-    window::{Window, WindowAttributes},
+    event::WindowEvent,
+    event_loop::{ActiveEventLoop, EventLoop},
+    keyboard::KeyCode,
+    window::{Window, WindowId},
 };
 
-use crate::render_engine::RenderEngine;
+use crate::{
+    background::Background, config::EngineConfig, measure::MeasureMode, recording::RecordingOutput,
+    render_engine::RenderEngine, render_engine_builder::RenderEngineBuilder, scene::Transform,
+};
 
-#[derive(Default)]
-pub struct App {
-    window: Option<Arc<Window>>,
+/// The demo app started by [crate::run]
+pub(crate) struct App {
+    /// Read from engine.toml at startup
+    config: EngineConfig,
+    windows: HashMap<WindowId, Arc<Window>>,
     render_engine: Option<RenderEngine>,
+    /// The scene, extracted into renderables every frame
+    #[cfg(feature = "hecs")]
+    world: hecs::World,
+    /// On the web the engine can't be created by blocking, it is created asynchronously and arrives as a user event
+    #[cfg(target_arch = "wasm32")]
+    proxy: winit::event_loop::EventLoopProxy<RenderEngine>,
+    #[cfg(target_arch = "wasm32")]
+    engine_pending: bool,
+    /// Paths given to the console's `load` command, the app owns the scene so it loads them
+    #[cfg(not(target_arch = "wasm32"))]
+    pending_loads: Rc<RefCell<Vec<PathBuf>>>,
 }
 
-impl ApplicationHandler for App {
-    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        if let Ok(window) = event_loop.create_window(WindowAttributes::default()) {
-            let window_handle = Arc::new(window);
-            self.window = Some(window_handle.clone());
+impl App {
+    pub fn new(event_loop: &EventLoop<RenderEngine>, config: EngineConfig) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        let _ = event_loop;
+        App {
+            config,
+            windows: HashMap::new(),
+            render_engine: None,
+            #[cfg(feature = "hecs")]
+            world: hecs::World::new(),
+            #[cfg(target_arch = "wasm32")]
+            proxy: event_loop.create_proxy(),
+            #[cfg(target_arch = "wasm32")]
+            engine_pending: false,
+            #[cfg(not(target_arch = "wasm32"))]
+            pending_loads: Rc::default(),
+        }
+    }
+
+    /// Opens a new window and hands it to the render engine, creating the engine on the first call
+    fn open_window(&mut self, event_loop: &ActiveEventLoop) {
+        // The engine isn't there yet to take another window
+        #[cfg(target_arch = "wasm32")]
+        if self.engine_pending {
+            return;
+        }
+
+        let attributes = self.config.window_attributes();
+        // Put the canvas on the page
+        #[cfg(target_arch = "wasm32")]
+        let attributes =
+            winit::platform::web::WindowAttributesExtWebSys::with_append(attributes, true);
+        let Ok(window) = event_loop.create_window(attributes) else {
+            return;
+        };
+        let window_handle = Arc::new(window);
+        self.windows
+            .insert(window_handle.id(), window_handle.clone());
+
+        match self.render_engine.as_mut() {
+            Some(render_engine) => render_engine.add_window(window_handle),
+            #[cfg(not(target_arch = "wasm32"))]
+            None => {
+                let renderer = pollster::block_on(
+                    RenderEngineBuilder::new()
+                        .config(&self.config)
+                        .build(window_handle),
+                );
+                self.install_engine(renderer);
+            }
+            #[cfg(target_arch = "wasm32")]
+            None => {
+                self.engine_pending = true;
+                let proxy = self.proxy.clone();
+                let builder = RenderEngineBuilder::new().config(&self.config);
+                wasm_bindgen_futures::spawn_local(async move {
+                    let renderer = builder.build(window_handle).await;
+                    let _ = proxy.send_event(renderer);
+                });
+            }
+        }
+    }
+
+    fn install_engine(&mut self, mut renderer: RenderEngine) {
+        let input = renderer.input_mut();
+        input.bind("exit", KeyCode::Escape);
+        input.bind("screenshot", KeyCode::F12);
+        input.bind("toggle_debug_hud", KeyCode::F3);
+        input.bind("toggle_recording", KeyCode::F9);
+        input.bind("toggle_background", KeyCode::KeyB);
+        input.bind("open_window", KeyCode::KeyN);
+        input.bind("cycle_measure", KeyCode::KeyM);
+        input.bind("toggle_probe", KeyCode::KeyP);
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let pending_loads = self.pending_loads.clone();
+            renderer.console_mut().register(
+                "load",
+                "load <path>: Adds an OBJ or glTF model, a LAS/LAZ point cloud or a voxel grid to the scene, a PNG becomes the skybox",
+                move |_, args| {
+                    let [path] = args else {
+                        return Err("Usage: load <path>".to_string());
+                    };
+                    pending_loads.borrow_mut().push(path.into());
+                    Ok(format!("Loading {path}"))
+                },
+            );
+        }
+        self.create_scene(&mut renderer);
+        self.render_engine = Some(renderer);
+        for window in self.windows.values() {
+            window.request_redraw();
+        }
+    }
+
+    /// Runs the actions triggered since the last frame, returns true if another window should be opened
+    fn handle_actions(
+        event_loop: &ActiveEventLoop,
+        window: &Window,
+        window_id: WindowId,
+        render_engine: &mut RenderEngine,
+    ) -> bool {
+        let input = render_engine.input();
+        let [exit, screenshot, toggle_debug_hud, toggle_recording, toggle_background, open_window, cycle_measure, toggle_probe] =
+            [
+                "exit",
+                "screenshot",
+                "toggle_debug_hud",
+                "toggle_recording",
+                "toggle_background",
+                "open_window",
+                "cycle_measure",
+                "toggle_probe",
+            ]
+            .map(|action| input.action_just_pressed(action));
+
+        if exit {
+            event_loop.exit();
+        }
+        // Save a screenshot of the next frame
+        if screenshot {
+            let timestamp = web_time::SystemTime::now()
+                .duration_since(web_time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            render_engine.capture_frame(window_id, format!("screenshot-{timestamp}.png"));
+            window.request_redraw();
+        }
+        if toggle_debug_hud {
+            render_engine.toggle_debug_hud();
+        }
+        // Toggle recording a frame sequence
+        if toggle_recording {
+            if render_engine.is_recording(window_id) {
+                render_engine.stop_recording(window_id);
+            } else {
+                let timestamp = web_time::SystemTime::now()
+                    .duration_since(web_time::UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs();
+                #[cfg(not(feature = "ffmpeg"))]
+                let output =
+                    RecordingOutput::ImageSequence(format!("recording-{timestamp}").into());
+                #[cfg(feature = "ffmpeg")]
+                let output = RecordingOutput::Ffmpeg {
+                    path: format!("recording-{timestamp}.mp4").into(),
+                    fps: 60,
+                };
+                render_engine.start_recording(window_id, output, 1);
+                window.request_redraw();
+            }
+        }
+        // Switch between the plain and the gradient background
+        if toggle_background {
+            let background = match render_engine.background() {
+                Background::Color(_) => Background::Gradient {
+                    top: [0.35, 0.55, 0.8, 1.0],
+                    bottom: [0.05, 0.05, 0.1, 1.0],
+                },
+                _ => Background::default(),
+            };
+            render_engine.set_background(background);
+        }
+        // Off, then measuring distances, then angles
+        if cycle_measure {
+            let measure_tool = render_engine.measure_tool_mut();
+            match (measure_tool.is_enabled(), measure_tool.mode()) {
+                (false, _) => {
+                    measure_tool.set_mode(MeasureMode::Distance);
+                    measure_tool.set_enabled(true);
+                }
+                (true, MeasureMode::Distance) => measure_tool.set_mode(MeasureMode::Angle),
+                (true, MeasureMode::Angle) => measure_tool.set_enabled(false),
+            }
+            window.request_redraw();
+        }
+        // Read the volume values under the cursor
+        if toggle_probe {
+            let probe_tool = render_engine.probe_tool_mut();
+            probe_tool.set_enabled(!probe_tool.is_enabled());
+            window.request_redraw();
+        }
+        // Another window looking at the same scene
+        open_window
+    }
+
+    /// Adds an OBJ or glTF model, a LAS/LAZ point cloud or a voxel grid to the scene and points the cameras at it, a
+    /// PNG becomes the skybox
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load_file(&mut self, path: &std::path::Path) {
+        let Some(render_engine) = self.render_engine.as_mut() else {
+            return;
+        };
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("obj") => {
+                match std::fs::read_to_string(path)
+                    .and_then(|source| crate::assets::parse_obj(&source))
+                {
+                    Ok(mesh) => self.add_model(path, mesh.into()),
+                    Err(err) => tracing::error!("Failed to load {}: {err}", path.display()),
+                }
+            }
+            Some("png") => {
+                let image =
+                    match std::fs::read(path).and_then(|bytes| crate::assets::decode_png(&bytes)) {
+                        Ok(image) => image,
+                        Err(err) => {
+                            tracing::error!("Failed to load {}: {err}", path.display());
+                            return;
+                        }
+                    };
+                let texture = render_engine.add_texture(image.width, image.height, image.rgba);
+                render_engine.set_background(Background::Skybox(texture));
+            }
+            Some("gltf" | "glb") => match crate::gltf::load(path) {
+                Ok(model) => self.add_model(path, model),
+                Err(err) => tracing::error!("Failed to load {}: {err}", path.display()),
+            },
+            // Streams in, the header is enough to frame it
+            Some("las" | "laz") => match crate::point_cloud::PointCloud::open(path) {
+                Ok(cloud) => {
+                    let (min, max) = cloud.bounds();
+                    render_engine.add_point_cloud(cloud);
+                    render_engine.frame_bounds(min, max);
+                    tracing::info!("Loading {} into the scene", path.display());
+                }
+                Err(err) => tracing::error!("Failed to load {}: {err}", path.display()),
+            },
+            Some("vxgr") => {
+                match std::fs::read(path)
+                    .and_then(|bytes| crate::voxel_grid::VoxelGrid::decode(&bytes))
+                {
+                    Ok(grid) => {
+                        let (min, max) = grid.bounds();
+                        render_engine.add_voxel_grid(grid);
+                        render_engine.frame_bounds(min, max);
+                        tracing::info!("Added {} to the scene", path.display());
+                    }
+                    Err(err) => tracing::error!("Failed to load {}: {err}", path.display()),
+                }
+            }
+            _ => tracing::warn!("Don't know how to load {}", path.display()),
+        }
+    }
+
+    /// Adds every part of a model to the scene, playing its animations, and points the cameras at it
+    #[cfg(not(target_arch = "wasm32"))]
+    fn add_model(&mut self, path: &std::path::Path, model: crate::model::ModelData) {
+        let Some(render_engine) = self.render_engine.as_mut() else {
+            return;
+        };
+        let bounds = model.bounds();
+        let player =
+            (!model.animations.is_empty()).then(|| crate::animation::AnimationPlayer::new(&model));
+        let added = model.add_to(render_engine);
+        if let Some(player) = player {
+            render_engine.add_animation_player(player, &added);
+        }
+        #[cfg(feature = "hecs")]
+        for renderable in added {
+            self.world.spawn((
+                renderable.mesh,
+                renderable.material,
+                Transform::from_matrix(renderable.model),
+            ));
+        }
+        #[cfg(not(feature = "hecs"))]
+        {
+            let mut renderables = render_engine.renderables().to_vec();
+            renderables.extend(added);
+            render_engine.set_renderables(renderables);
+        }
+        if let Some((min, max)) = bounds {
+            render_engine.frame_bounds(min, max);
+        }
+        tracing::info!("Added {} to the scene", path.display());
+    }
 
-            let (width, height) = window_handle.inner_size().into();
+    /// A single cube at the origin
+    fn create_scene(&mut self, render_engine: &mut RenderEngine) {
+        let cube = render_engine.cube_mesh();
+        #[cfg(feature = "hecs")]
+        self.world.spawn((cube, Transform::default()));
+        #[cfg(not(feature = "hecs"))]
+        render_engine.set_renderables(vec![crate::scene::Renderable::new(
+            cube,
+            render_engine.default_material(),
+            &Transform::default(),
+        )]);
+    }
+}
 
-            let renderer = pollster::block_on(async move {
-                RenderEngine::new(window_handle.clone(), width, height).await
-            });
+impl ApplicationHandler<RenderEngine> for App {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        if self.windows.is_empty() {
+            self.open_window(event_loop);
+        } else if let Some(render_engine) = self.render_engine.as_mut() {
+            // Back from the background, the windows are kept but need new surfaces
+            render_engine.resume();
+        }
+    }
+
+    fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            render_engine.suspend();
+        }
+    }
 
-            self.render_engine = Some(renderer);
+    /// The engine created asynchronously on the web
+    fn user_event(&mut self, _event_loop: &ActiveEventLoop, render_engine: RenderEngine) {
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.engine_pending = false;
         }
+        self.install_engine(render_engine);
     }
 
     fn window_event(
         &mut self,
-        event_loop: &winit::event_loop::ActiveEventLoop,
-        window_id: winit::window::WindowId,
+        event_loop: &ActiveEventLoop,
+        window_id: WindowId,
         event: winit::event::WindowEvent,
     ) {
         let (Some(window), Some(render_engine)) =
-            (self.window.as_ref(), self.render_engine.as_mut())
+            (self.windows.get(&window_id), self.render_engine.as_mut())
         else {
             return;
         };
+        // Clicks and typing on the UI's panels stay there, releases go on so nothing is left held
+        #[cfg(feature = "egui")]
+        let consumed = render_engine.process_egui_event(window, &event) && !is_release(&event);
+        #[cfg(not(feature = "egui"))]
+        let consumed = false;
+        // Presses the gizmo or a plugin consumed don't trigger the app's actions
+        if !consumed {
+            render_engine.process_window_event(window_id, &event);
+        }
+        let mut open_window = false;
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut dropped_file = None;
         match event {
-            WindowEvent::KeyboardInput {
-                event:
-                    winit::event::KeyEvent {
-                        physical_key: winit::keyboard::PhysicalKey::Code(key_code),
-                        ..
-                    },
-                ..
-            } => {
-                // Exit by pressing the escape key
-                if matches!(key_code, winit::keyboard::KeyCode::Escape) {
-                    event_loop.exit();
-                }
-            }
+            // Browsers don't hand out dropped files' paths, winit never reports them there
+            #[cfg(not(target_arch = "wasm32"))]
+            WindowEvent::DroppedFile(path) => dropped_file = Some(path),
             WindowEvent::Resized(PhysicalSize { width, height }) => {
-                render_engine.resize(width, height);
-                window.request_redraw();
+                render_engine.resize(window_id, width, height);
             }
             WindowEvent::CloseRequested => {
-                event_loop.exit();
+                render_engine.remove_window(window_id);
+                self.windows.remove(&window_id);
+                if self.windows.is_empty() {
+                    event_loop.exit();
+                }
             }
             WindowEvent::RedrawRequested => {
+                // Once per frame, the engine redraws when a bound key is pressed
+                open_window = Self::handle_actions(event_loop, window, window_id, render_engine);
+                #[cfg(feature = "hecs")]
+                render_engine.set_renderables(crate::ecs::extract_renderables(
+                    &self.world,
+                    render_engine.default_material(),
+                ));
                 render_engine.update();
-                render_engine.render_frame()
+                render_engine.render_frame(window_id);
             }
             _ => (),
         }
+
+        if open_window {
+            self.open_window(event_loop);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = dropped_file {
+            self.load_file(&path);
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        for path in self.pending_loads.take() {
+            self.load_file(&path);
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            event_loop.set_control_flow(render_engine.poll_background());
+        }
     }
 
     fn device_event(
         &mut self,
-        event_loop: &winit::event_loop::ActiveEventLoop,
+        event_loop: &ActiveEventLoop,
         device_id: winit::event::DeviceId,
         event: winit::event::DeviceEvent,
     ) {
-        let (Some(window), Some(render_engine)) =
-            (self.window.as_ref(), self.render_engine.as_mut())
-        else {
-            return;
-        };
-
-        render_engine.process_event(&event, &window);
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            render_engine.process_event(&event);
+        }
     }
 }
+
+/// A mouse button or key let go of
+#[cfg(feature = "egui")]
+fn is_release(event: &WindowEvent) -> bool {
+    use winit::event::{ElementState, KeyEvent};
+
+    matches!(
+        event,
+        WindowEvent::MouseInput {
+            state: ElementState::Released,
+            ..
+        } | WindowEvent::KeyboardInput {
+            event: KeyEvent {
+                state: ElementState::Released,
+                ..
+            },
+            ..
+        }
+    )
+}
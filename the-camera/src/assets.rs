@@ -0,0 +1,479 @@
+//! Loading asset files: from disk natively, fetched relative to the page on the web. Meshes are read from Wavefront
+//! OBJ and images from PNG, or Radiance HDR for environment panoramas.
+
+use std::{collections::HashMap, io};
+
+use crate::{
+    mesh::{MeshData, Vertex},
+    texture::TextureData,
+};
+
+/// Reads the whole file at `path`
+#[cfg(not(target_arch = "wasm32"))]
+#[tracing::instrument(level = "debug")]
+pub async fn load_bytes(path: &str) -> io::Result<Vec<u8>> {
+    std::fs::read(path)
+}
+
+/// Fetches `path` relative to the page's URL
+#[cfg(target_arch = "wasm32")]
+#[tracing::instrument(level = "debug")]
+pub async fn load_bytes(path: &str) -> io::Result<Vec<u8>> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let js_error = |err: wasm_bindgen::JsValue| io::Error::other(format!("{path}: {err:?}"));
+
+    let window = web_sys::window().ok_or_else(|| io::Error::other("No browser window"))?;
+    let response: web_sys::Response = JsFuture::from(window.fetch_with_str(path))
+        .await
+        .map_err(js_error)?
+        .dyn_into()
+        .map_err(js_error)?;
+    if !response.ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{path}: HTTP {}", response.status()),
+        ));
+    }
+    let buffer = JsFuture::from(response.array_buffer().map_err(js_error)?)
+        .await
+        .map_err(js_error)?;
+    Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+}
+
+/// Extensions [read_model] knows, lowercase
+#[cfg(not(feature = "fbx"))]
+pub const MODEL_EXTENSIONS: &[&str] = &["glb", "gltf", "obj", "usd", "usda", "usdz"];
+#[cfg(feature = "fbx")]
+pub const MODEL_EXTENSIONS: &[&str] = &["fbx", "glb", "gltf", "obj", "usd", "usda", "usdz"];
+
+/// An OBJ, glTF, USD or, with the `fbx` feature, FBX model, picked by the extension of `path`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_model(path: &std::path::Path) -> io::Result<crate::model::ModelData> {
+    match extension(path).as_deref() {
+        #[cfg(feature = "fbx")]
+        Some("fbx") => crate::fbx::decode(&std::fs::read(path)?),
+        Some("gltf" | "glb") => crate::gltf::load(path),
+        Some("usd" | "usda" | "usdz") => crate::usd::decode(&std::fs::read(path)?),
+        _ => Ok(parse_obj(&std::fs::read_to_string(path)?)?.into()),
+    }
+}
+
+/// A Radiance HDR or PNG image, picked by the extension of `path`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_image(path: &std::path::Path) -> io::Result<TextureData> {
+    let bytes = std::fs::read(path)?;
+    match extension(path).as_deref() {
+        Some("hdr") => decode_hdr(&bytes),
+        _ => decode_png(&bytes),
+    }
+}
+
+/// Lowercase, so `.OBJ` files load as well
+#[cfg(not(target_arch = "wasm32"))]
+fn extension(path: &std::path::Path) -> Option<String> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+}
+
+/// Reads the file at `path` as UTF-8 text, e.g. a shader
+pub async fn load_string(path: &str) -> io::Result<String> {
+    String::from_utf8(load_bytes(path).await?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Decodes a PNG to 8 bit RGBA, whatever color type it was saved with
+pub fn decode_png(bytes: &[u8]) -> io::Result<TextureData> {
+    let mut decoder = png::Decoder::new(bytes);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(io::Error::other)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(io::Error::other)?;
+    buffer.truncate(info.buffer_size());
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => buffer,
+        png::ColorType::Rgb => buffer
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+            .collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Indexed PNGs should have been expanded",
+            ))
+        }
+    };
+    Ok(TextureData {
+        width: info.width,
+        height: info.height,
+        rgba,
+    })
+}
+
+/// Encodes 8 bit RGBA pixels as a PNG
+pub fn encode_png(texture: &TextureData) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, texture.width, texture.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer
+        .write_image_data(&texture.rgba)
+        .map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)?;
+    Ok(bytes)
+}
+
+/// Decodes a Radiance HDR (.hdr) panorama to 8 bit RGBA, compressing its range with Reinhard's operator so sunlit
+/// skies keep their detail. Only the usual RGBE pixels stored top to bottom are supported, as most tools write them.
+pub fn decode_hdr(bytes: &[u8]) -> io::Result<TextureData> {
+    let invalid =
+        |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("HDR: {message}"));
+
+    let mut lines = bytes.split(|&byte| byte == b'\n');
+    let mut offset = 0;
+    let mut next_line = || {
+        let line = lines.next()?;
+        offset += line.len() + 1;
+        Some(String::from_utf8_lossy(line).trim().to_string())
+    };
+    let signature = next_line().ok_or_else(|| invalid("empty file"))?;
+    if !signature.starts_with("#?") {
+        return Err(invalid("not a Radiance file"));
+    }
+    // Settings up to an empty line, then the resolution
+    loop {
+        let line = next_line().ok_or_else(|| invalid("no resolution"))?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid(&format!("{format} pixels are not supported")));
+            }
+        }
+    }
+    let resolution = next_line().ok_or_else(|| invalid("no resolution"))?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (height.parse::<usize>(), width.parse::<usize>()),
+        _ => return Err(invalid(&format!("unsupported orientation {resolution}"))),
+    };
+    let (Ok(height), Ok(width)) = (height, width) else {
+        return Err(invalid("invalid resolution"));
+    };
+
+    let mut data = bytes.get(offset..).unwrap_or_default().iter().copied();
+    let mut rgbe = vec![0; width * height * 4];
+    for scanline in rgbe.chunks_mut(width * 4) {
+        let mut next = || data.next().ok_or_else(|| invalid("truncated pixels"));
+        let start = [next()?, next()?, next()?, next()?];
+        let run_length = (8..0x8000).contains(&width)
+            && start[..2] == [2, 2]
+            && usize::from(u16::from_be_bytes([start[2], start[3]])) == width;
+        if !run_length {
+            scanline[..4].copy_from_slice(&start);
+            for byte in &mut scanline[4..] {
+                *byte = next()?;
+            }
+            continue;
+        }
+        // Each channel on its own, as runs of one repeated byte or spans of literal ones
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = next()?;
+                let (count, repeated) = if count > 128 {
+                    (usize::from(count - 128), Some(next()?))
+                } else {
+                    (usize::from(count), None)
+                };
+                if count == 0 || x + count > width {
+                    return Err(invalid("corrupt run length data"));
+                }
+                for pixel in x..x + count {
+                    scanline[pixel * 4 + channel] = match repeated {
+                        Some(byte) => byte,
+                        None => next()?,
+                    };
+                }
+                x += count;
+            }
+        }
+    }
+
+    // Like PNGs, the bytes are sRGB encoded
+    let to_byte = |value: f32| {
+        (encode_srgb(value / (1.0 + value)) * 255.0)
+            .round()
+            .clamp(0.0, 255.0) as u8
+    };
+    let rgba = rgbe
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let scale = if pixel[3] == 0 {
+                0.0
+            } else {
+                2f32.powi(i32::from(pixel[3]) - 136)
+            };
+            let [r, g, b] =
+                [pixel[0], pixel[1], pixel[2]].map(|value| to_byte(f32::from(value) * scale));
+            [r, g, b, 255]
+        })
+        .collect();
+    Ok(TextureData {
+        width: width as u32,
+        height: height as u32,
+        rgba,
+    })
+}
+
+/// Linear intensity to the sRGB encoded value PNGs store and the engine draws as it is
+pub(crate) fn encode_srgb(linear: f32) -> f32 {
+    if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The linear intensity an sRGB encoded value stands for, the inverse of [encode_srgb]
+pub(crate) fn decode_srgb(encoded: f32) -> f32 {
+    if encoded <= 0.040_45 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Reads the positions, texture coordinates and faces of a Wavefront OBJ into a single mesh. Faces with more than
+/// three corners are split into a fan of triangles, vertex colors given after the position are kept, everything
+/// else like normals, groups and materials is skipped.
+pub fn parse_obj(source: &str) -> io::Result<MeshData> {
+    let invalid = |line: usize, message: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("OBJ line {}: {message}", line + 1),
+        )
+    };
+
+    let mut positions: Vec<([f32; 3], [f32; 3])> = Vec::new();
+    let mut tex_coords: Vec<[f32; 2]> = Vec::new();
+    let mut data = MeshData {
+        vertices: Vec::new(),
+        indices: Vec::new(),
+    };
+    // Corners sharing position and texture coordinates share a vertex
+    let mut vertex_indices: HashMap<(usize, Option<usize>), u16> = HashMap::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let numbers = |words: std::str::SplitWhitespace| -> io::Result<Vec<f32>> {
+            words
+                .map(|word| {
+                    word.parse()
+                        .map_err(|_| invalid(line_index, "expected a number"))
+                })
+                .collect()
+        };
+        match keyword {
+            "v" => match numbers(words)?[..] {
+                [x, y, z, r, g, b, ..] => positions.push(([x, y, z], [r, g, b])),
+                [x, y, z, ..] => positions.push(([x, y, z], [1.0; 3])),
+                _ => return Err(invalid(line_index, "a vertex needs three coordinates")),
+            },
+            // OBJ counts V from the bottom of the image, wgpu from the top
+            "vt" => match numbers(words)?[..] {
+                [u, v, ..] => tex_coords.push([u, 1.0 - v]),
+                [u] => tex_coords.push([u, 1.0]),
+                _ => return Err(invalid(line_index, "texture coordinates need a value")),
+            },
+            "f" => {
+                let mut corners = Vec::new();
+                for word in words {
+                    let mut references = word.split('/');
+                    // Indices count from 1, negative ones back from the last element read so far
+                    let resolve = |reference: Option<&str>,
+                                   count: usize|
+                     -> io::Result<Option<usize>> {
+                        let Some(reference) = reference.filter(|reference| !reference.is_empty())
+                        else {
+                            return Ok(None);
+                        };
+                        let index: i64 = reference
+                            .parse()
+                            .map_err(|_| invalid(line_index, "expected an index"))?;
+                        let resolved = if index < 0 {
+                            count as i64 + index
+                        } else {
+                            index - 1
+                        };
+                        if !(0..count as i64).contains(&resolved) {
+                            return Err(invalid(line_index, "index out of range"));
+                        }
+                        Ok(Some(resolved as usize))
+                    };
+                    let position = resolve(references.next(), positions.len())?
+                        .ok_or_else(|| invalid(line_index, "a face corner needs a position"))?;
+                    let tex_coord = resolve(references.next(), tex_coords.len())?;
+
+                    let key = (position, tex_coord);
+                    let index = match vertex_indices.get(&key) {
+                        Some(&index) => index,
+                        None => {
+                            let index = u16::try_from(data.vertices.len()).map_err(|_| {
+                                invalid(line_index, "more than 65536 vertices are not supported")
+                            })?;
+                            let (position, color) = positions[position];
+                            let tex_coord = tex_coord.map_or([0.0; 2], |index| tex_coords[index]);
+                            data.vertices.push(Vertex::new(position, color, tex_coord));
+                            vertex_indices.insert(key, index);
+                            index
+                        }
+                    };
+                    corners.push(index);
+                }
+                if corners.len() < 3 {
+                    return Err(invalid(line_index, "a face needs three corners"));
+                }
+                for pair in corners[1..].windows(2) {
+                    data.indices.extend([corners[0], pair[0], pair[1]]);
+                }
+            }
+            _ => (),
+        }
+    }
+    if data.indices.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "OBJ has no faces",
+        ));
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hdr_header(width: usize, height: usize) -> Vec<u8> {
+        format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {height} +X {width}\n").into_bytes()
+    }
+
+    #[test]
+    fn decodes_flat_hdr_pixels() {
+        let mut bytes = hdr_header(2, 1);
+        // 1.0, tone mapped to 0.5, and black
+        bytes.extend([128, 128, 128, 129, 255, 255, 255, 0]);
+        let image = decode_hdr(&bytes).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        let half = (encode_srgb(0.5) * 255.0).round() as u8;
+        assert_eq!(image.rgba, [half, half, half, 255, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn decodes_run_length_encoded_hdr_scanlines() {
+        let mut bytes = hdr_header(8, 1);
+        bytes.extend([2, 2, 0, 8]);
+        // Red as a run, green as literals, blue as a run of zeros and the exponent as a run
+        bytes.extend([128 + 8, 128]);
+        bytes.extend([8, 0, 16, 32, 48, 64, 80, 96, 112]);
+        bytes.extend([128 + 8, 0]);
+        bytes.extend([128 + 8, 129]);
+        let image = decode_hdr(&bytes).unwrap();
+        assert_eq!(image.rgba.len(), 8 * 4);
+        let pixel = |x: usize| &image.rgba[x * 4..x * 4 + 4];
+        assert_eq!(pixel(0)[1], 0);
+        assert!(pixel(7)[1] > pixel(1)[1]);
+        let red = pixel(0)[0];
+        assert!(image
+            .rgba
+            .chunks_exact(4)
+            .all(|rgba| rgba[0] == red && rgba[2] == 0));
+
+        let mut corrupt = hdr_header(8, 1);
+        corrupt.extend([2, 2, 0, 8, 128 + 9, 128]);
+        assert!(decode_hdr(&corrupt).is_err());
+    }
+
+    #[test]
+    fn rejects_other_hdr_files() {
+        assert!(decode_hdr(b"P6\n1 1\n255\n").is_err());
+        assert!(decode_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n").is_err());
+        assert!(decode_hdr(b"#?RADIANCE\n\n+Y 1 +X 1\n\0\0\0\0").is_err());
+        let mut truncated = hdr_header(2, 1);
+        truncated.extend([128, 128, 128, 129]);
+        assert!(decode_hdr(&truncated).is_err());
+    }
+
+    #[test]
+    fn png_round_trips() {
+        let texture = TextureData {
+            width: 2,
+            height: 1,
+            rgba: vec![255, 0, 0, 255, 0, 128, 255, 64],
+        };
+        let decoded = decode_png(&encode_png(&texture).unwrap()).unwrap();
+        assert_eq!((decoded.width, decoded.height), (2, 1));
+        assert_eq!(decoded.rgba, texture.rgba);
+    }
+
+    #[test]
+    fn srgb_round_trips() {
+        for value in [0.0, 0.002, 0.2, 0.5, 1.0] {
+            assert!(
+                (decode_srgb(encode_srgb(value)) - value).abs() < 1e-6,
+                "{value}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_obj_faces_into_triangles() {
+        let source = "\
+# A quad, then a triangle reusing its corners by negative indices
+v 0 0 0 1 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 1
+vn 0 0 1
+f 1/1 2/1 3/2 4/1
+f -4/1 -2/2 -1/1
+";
+        let mesh = parse_obj(source).unwrap();
+        assert_eq!(mesh.indices, [0, 1, 2, 0, 2, 3, 0, 2, 3]);
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.vertices[0].color(), [1.0, 0.0, 0.0]);
+        assert_eq!(mesh.vertices[1].color(), [1.0; 3]);
+        // V counts from the top
+        assert_eq!(mesh.vertices[0].tex_coords(), [0.0, 1.0]);
+        assert_eq!(mesh.vertices[2].tex_coords(), [1.0, 0.0]);
+    }
+
+    #[test]
+    fn rejects_malformed_objs() {
+        for source in [
+            "v 0 0 0\n",
+            "v 0 0\nf 1 1 1\n",
+            "v 0 0 0\nf 1 1\n",
+            "v 0 0 0\nf 1 2 1\n",
+            "v 0 x 0\n",
+        ] {
+            assert!(parse_obj(source).is_err(), "{source:?}");
+        }
+        let err = parse_obj("v 0 0 0\n\nf 1 1 0\n").unwrap_err();
+        assert!(err.to_string().contains("line 3"), "{err}");
+    }
+}
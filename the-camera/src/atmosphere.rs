@@ -0,0 +1,760 @@
+//! A physically based sky and aerial perspective, after Hillaire's "A Scalable and Production Ready Sky and
+//! Atmosphere Rendering Technique" (2020).
+//!
+//! The [Atmosphere] is a shell of air around a spherical planet that scatters sunlight by Rayleigh and Mie
+//! scattering and absorbs some of it in its ozone layer. Whenever it changes, compute passes precompute four lookup
+//! tables from it:
+//!
+//! - The transmittance towards the top of the atmosphere, by height and zenith angle
+//! - The light scattered more than once, by height and the sun's zenith angle
+//! - The sky seen from the camera's altitude, by direction
+//! - The light scattered towards the camera and what's left of what's behind it, by direction and distance
+//!
+//! After the main pass the sky replaces the [crate::background::Background] wherever nothing was drawn, with the
+//! sun's disk in it. Everything that was drawn fades into the haze by its distance from the camera, which is what
+//! makes distant geometry look far away. The scene is taken to be a small patch of the planet's surface at
+//! [Atmosphere::altitude], so the tables don't depend on where the camera is in it.
+//!
+//! Water surfaces reflect a highlight of [Atmosphere::sun_direction] while there is an atmosphere. The precomputed
+//! tables need compute shaders and storage textures, so WebGL2 has no atmosphere.
+
+use cgmath::{Matrix4, SquareMatrix};
+use wgpu::{BindGroup, CommandEncoder, Device, Queue, RenderPipeline, TextureFormat, TextureView};
+
+use crate::{
+    camera::camera::CameraUniform,
+    lazy_pass::LazyPass,
+    render_engine_builder::DeviceReport,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        compute::{ComputeKernel, ComputeKernelBuilder, ComputePassBuilder},
+        uniform_buffer::UniformBuffer,
+    },
+};
+
+/// Sizes of the lookup tables, the aerial perspective volume has slices of increasing distance. Its slices lie side
+/// by side in a 2D texture, GL only writes the first slice of a 3D storage texture. The slice count is in
+/// atmosphere_luts.wgsl and atmosphere.wgsl too.
+const TRANSMITTANCE_SIZE: [u32; 2] = [256, 64];
+const MULTIPLE_SCATTERING_SIZE: [u32; 2] = [32, 32];
+const SKY_VIEW_SIZE: [u32; 2] = [192, 108];
+const AERIAL_PERSPECTIVE_SIZE: [u32; 3] = [32, 32, 32];
+
+/// Kilometers from the camera the aerial perspective volume reaches, anything further is as hazy as its last slice
+const AERIAL_PERSPECTIVE_DISTANCE: f32 = 32.0;
+
+const LUT_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// The air around a planet, set with [crate::render_engine::RenderEngine::set_atmosphere]. Lengths are in
+/// kilometers and coefficients per kilometer, [Atmosphere::default] is the Earth's.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Atmosphere {
+    /// Towards the sun, doesn't have to be normalized. Below the horizon the sky goes dark.
+    pub sun_direction: [f32; 3],
+    /// Brightens the sky and haze before they're mapped to the window's range
+    pub exposure: f32,
+    /// Of the sun's disk, in radians
+    pub sun_angular_radius: f32,
+    pub planet_radius: f32,
+    /// Above the ground, where the air ends
+    pub atmosphere_height: f32,
+    /// Of air molecules at the ground for red, green and blue light, which colors the sky blue
+    pub rayleigh_scattering: [f32; 3],
+    /// Height the air's density falls to 1/e over
+    pub rayleigh_scale_height: f32,
+    /// Of aerosols at the ground, which whiten the sky around the sun and in the haze
+    pub mie_scattering: f32,
+    pub mie_absorption: f32,
+    pub mie_scale_height: f32,
+    /// From 0 for aerosols scattering evenly to almost 1 for scattering mostly forwards
+    pub mie_anisotropy: f32,
+    /// At the peak of the ozone layer, 25 kilometers up
+    pub ozone_absorption: [f32; 3],
+    /// Of the planet's surface below the horizon, it reflects light back into the air
+    pub ground_albedo: [f32; 3],
+    /// Of the scene above the ground
+    pub altitude: f32,
+    /// How far a world unit is, for the haze between the camera and what it sees. Raise it to exaggerate the haze
+    /// of a small scene.
+    pub kilometers_per_unit: f32,
+    /// Fades the drawn geometry into the haze, otherwise only the sky is drawn
+    pub aerial_perspective: bool,
+}
+
+impl Default for Atmosphere {
+    fn default() -> Self {
+        Atmosphere {
+            sun_direction: [0.3, 0.4, 0.5],
+            exposure: 10.0,
+            sun_angular_radius: 0.0047,
+            planet_radius: 6360.0,
+            atmosphere_height: 100.0,
+            rayleigh_scattering: [5.802e-3, 13.558e-3, 33.1e-3],
+            rayleigh_scale_height: 8.0,
+            mie_scattering: 3.996e-3,
+            mie_absorption: 0.444e-3,
+            mie_scale_height: 1.2,
+            mie_anisotropy: 0.8,
+            ozone_absorption: [0.650e-3, 1.881e-3, 0.085e-3],
+            ground_albedo: [0.3, 0.3, 0.3],
+            altitude: 0.2,
+            kilometers_per_unit: 0.001,
+            aerial_perspective: true,
+        }
+    }
+}
+
+impl Atmosphere {
+    pub fn with_sun_direction(mut self, sun_direction: [f32; 3]) -> Self {
+        self.sun_direction = sun_direction;
+        self
+    }
+
+    pub fn with_exposure(mut self, exposure: f32) -> Self {
+        self.exposure = exposure;
+        self
+    }
+
+    pub fn with_altitude(mut self, altitude: f32) -> Self {
+        self.altitude = altitude;
+        self
+    }
+
+    pub fn with_kilometers_per_unit(mut self, kilometers_per_unit: f32) -> Self {
+        self.kilometers_per_unit = kilometers_per_unit;
+        self
+    }
+
+    pub fn with_aerial_perspective(mut self, aerial_perspective: bool) -> Self {
+        self.aerial_perspective = aerial_perspective;
+        self
+    }
+
+    /// Thicker or thinner Mie scattering, e.g. for a hazy or a very clear day
+    pub fn with_mie(mut self, scattering: f32, absorption: f32, anisotropy: f32) -> Self {
+        self.mie_scattering = scattering;
+        self.mie_absorption = absorption;
+        self.mie_anisotropy = anisotropy;
+        self
+    }
+
+    /// The sun's direction normalized, straight up for a zero vector
+    pub(crate) fn sun(&self) -> [f32; 3] {
+        let [x, y, z] = self.sun_direction;
+        let length = (x * x + y * y + z * z).sqrt();
+        if length < 1e-20 {
+            return [0.0, 1.0, 0.0];
+        }
+        [x / length, y / length, z / length]
+    }
+}
+
+/// The [Atmosphere] as the shaders take it, bound at group 0
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct AtmosphereUBOContent {
+    rayleigh_scattering: [f32; 3],
+    rayleigh_scale_height: f32,
+    ozone_absorption: [f32; 3],
+    mie_scattering: f32,
+    ground_albedo: [f32; 3],
+    mie_absorption: f32,
+    sun_direction: [f32; 3],
+    mie_scale_height: f32,
+    planet_radius: f32,
+    top_radius: f32,
+    mie_anisotropy: f32,
+    altitude: f32,
+    aerial_perspective_distance: f32,
+    exposure: f32,
+    /// Cosine of the sun's angular radius
+    sun_cos_radius: f32,
+    /// 0 where the drawn geometry isn't hazed
+    kilometers_per_unit: f32,
+}
+
+crate::assert_uniform_layout!(AtmosphereUBOContent {
+    rayleigh_scattering: ALIGN_VEC4,
+    rayleigh_scale_height: ALIGN_SCALAR,
+    ozone_absorption: ALIGN_VEC4,
+    mie_scattering: ALIGN_SCALAR,
+    ground_albedo: ALIGN_VEC4,
+    mie_absorption: ALIGN_SCALAR,
+    sun_direction: ALIGN_VEC4,
+    mie_scale_height: ALIGN_SCALAR,
+    planet_radius: ALIGN_SCALAR,
+    top_radius: ALIGN_SCALAR,
+    mie_anisotropy: ALIGN_SCALAR,
+    altitude: ALIGN_SCALAR,
+    aerial_perspective_distance: ALIGN_SCALAR,
+    exposure: ALIGN_SCALAR,
+    sun_cos_radius: ALIGN_SCALAR,
+    kilometers_per_unit: ALIGN_SCALAR,
+});
+
+impl From<&Atmosphere> for AtmosphereUBOContent {
+    fn from(atmosphere: &Atmosphere) -> Self {
+        let height = atmosphere.atmosphere_height.max(1e-3);
+        AtmosphereUBOContent {
+            rayleigh_scattering: atmosphere.rayleigh_scattering,
+            rayleigh_scale_height: atmosphere.rayleigh_scale_height.max(1e-3),
+            ozone_absorption: atmosphere.ozone_absorption,
+            mie_scattering: atmosphere.mie_scattering,
+            ground_albedo: atmosphere.ground_albedo,
+            mie_absorption: atmosphere.mie_absorption,
+            sun_direction: atmosphere.sun(),
+            mie_scale_height: atmosphere.mie_scale_height.max(1e-3),
+            planet_radius: atmosphere.planet_radius.max(1.0),
+            top_radius: atmosphere.planet_radius.max(1.0) + height,
+            mie_anisotropy: atmosphere.mie_anisotropy.clamp(-0.999, 0.999),
+            // Inside the air, where the tables are made from
+            altitude: atmosphere.altitude.clamp(1e-3, height * 0.999),
+            aerial_perspective_distance: AERIAL_PERSPECTIVE_DISTANCE,
+            exposure: atmosphere.exposure,
+            sun_cos_radius: atmosphere.sun_angular_radius.cos(),
+            kilometers_per_unit: if atmosphere.aerial_perspective {
+                atmosphere.kilometers_per_unit.max(0.0)
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+/// Per view data of the composite, bound at group 1
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ViewUBOContent {
+    /// Pixels are unprojected from the view's depth buffer
+    inverse_view_proj: [[f32; 4]; 4],
+    camera_position: [f32; 4],
+}
+
+crate::assert_uniform_layout!(ViewUBOContent {
+    inverse_view_proj: ALIGN_VEC4,
+    camera_position: ALIGN_VEC4,
+});
+
+/// What one window or render target needs to draw the atmosphere
+pub(crate) struct AtmosphereView {
+    ubo: UniformBuffer<ViewUBOContent>,
+}
+
+/// A lookup table written by the compute passes and sampled by the later ones
+struct Lut {
+    texture: wgpu::Texture,
+    view: TextureView,
+}
+
+impl Lut {
+    fn new(device: &Device, [width, height]: [u32; 2], label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: LUT_FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Lut { texture, view }
+    }
+
+    fn size_in_bytes(&self) -> u64 {
+        let size = self.texture.size();
+        // Four 16 bit channels
+        size.width as u64 * size.height as u64 * 8
+    }
+}
+
+/// Precomputes the lookup tables and draws the sky and haze after the main pass, shared by every view
+pub(crate) struct AtmospherePass {
+    transmittance_kernel: ComputeKernel,
+    multiple_scattering_kernel: ComputeKernel,
+    sky_view_kernel: ComputeKernel,
+    aerial_perspective_kernel: ComputeKernel,
+    ubo: UniformBuffer<AtmosphereUBOContent>,
+    transmittance: Lut,
+    multiple_scattering: Lut,
+    sky_view: Lut,
+    aerial_perspective: Lut,
+    /// Reading the tables written before, for each kernel
+    transmittance_inputs: BindGroup,
+    multiple_scattering_inputs: BindGroup,
+    sky_inputs: BindGroup,
+    /// Where each kernel writes its table
+    transmittance_output: BindGroup,
+    multiple_scattering_output: BindGroup,
+    sky_view_output: BindGroup,
+    aerial_perspective_output: BindGroup,
+    pipeline: RenderPipeline,
+    draw_bind_group: BindGroup,
+    view_bind_group_layout: BindGroupLayoutWithDesc,
+    /// What the tables were computed from, None before the first time
+    computed: Option<Atmosphere>,
+}
+
+impl AtmospherePass {
+    /// None where the device can't run the compute passes. `format` has to be the engine's swapchain format, the
+    /// sky is drawn into the views' color attachments.
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        device_report: &DeviceReport,
+    ) -> Option<Self> {
+        if !device_report
+            .downlevel_flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+        {
+            tracing::debug!("The atmosphere needs compute shaders");
+            return None;
+        }
+        let _span = tracing::debug_span!("create_atmosphere_pipelines").entered();
+
+        // Each kernel reads the tables of the ones before it, so the layouts grow by a table at a time
+        let lut_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Atmosphere LUT Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let transmittance_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_types::uniform())
+            .named("atmosphere")
+            .create(device, "Atmosphere Transmittance Bind Group");
+        let multiple_scattering_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_types::uniform())
+            .named("atmosphere")
+            .next_binding_compute(binding_types::sampler(wgpu::SamplerBindingType::Filtering))
+            .named("lut_sampler")
+            .next_binding_compute(binding_types::texture2D())
+            .named("transmittance_lut")
+            .create(device, "Atmosphere Multiple Scattering Bind Group");
+        let sky_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_types::uniform())
+            .named("atmosphere")
+            .next_binding_compute(binding_types::sampler(wgpu::SamplerBindingType::Filtering))
+            .named("lut_sampler")
+            .next_binding_compute(binding_types::texture2D())
+            .named("transmittance_lut")
+            .next_binding_compute(binding_types::texture2D())
+            .named("multiple_scattering_lut")
+            .create(device, "Atmosphere Sky Bind Group");
+        let output_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_types::image2D(
+                LUT_FORMAT,
+                wgpu::StorageTextureAccess::WriteOnly,
+            ))
+            .named("output")
+            .create(device, "Atmosphere LUT Output Bind Group");
+
+        let source = include_str!("atmosphere_luts.wgsl");
+        let kernel = |entry_point, inputs: &BindGroupLayoutWithDesc, label| {
+            ComputeKernelBuilder::new(source)
+                .entry_point(entry_point)
+                .workgroup_size([8, 8, 1])
+                .bind_group_layout(&inputs.layout)
+                .bind_group_layout(&output_layout.layout)
+                .create(device, label)
+        };
+        let transmittance_kernel = kernel(
+            "transmittance",
+            &transmittance_layout,
+            "atmosphere transmittance",
+        );
+        let multiple_scattering_kernel = kernel(
+            "multiple_scattering",
+            &multiple_scattering_layout,
+            "atmosphere multiple scattering",
+        );
+        let sky_view_kernel = kernel("sky_view", &sky_layout, "atmosphere sky view");
+        let aerial_perspective_kernel = kernel(
+            "aerial_perspective",
+            &sky_layout,
+            "atmosphere aerial perspective",
+        );
+
+        let ubo = UniformBuffer::new(device);
+        let transmittance = Lut::new(device, TRANSMITTANCE_SIZE, "Atmosphere Transmittance");
+        let multiple_scattering = Lut::new(
+            device,
+            MULTIPLE_SCATTERING_SIZE,
+            "Atmosphere Multiple Scattering",
+        );
+        let sky_view = Lut::new(device, SKY_VIEW_SIZE, "Atmosphere Sky View");
+        let [width, height, slices] = AERIAL_PERSPECTIVE_SIZE;
+        let aerial_perspective = Lut::new(
+            device,
+            [width * slices, height],
+            "Atmosphere Aerial Perspective",
+        );
+
+        let transmittance_inputs = BindGroupBuilder::new(&transmittance_layout)
+            .resource(ubo.binding_resource())
+            .create(device, "Atmosphere Transmittance Bind Group");
+        let multiple_scattering_inputs = BindGroupBuilder::new(&multiple_scattering_layout)
+            .resource(ubo.binding_resource())
+            .sampler(&lut_sampler)
+            .texture(&transmittance.view)
+            .create(device, "Atmosphere Multiple Scattering Bind Group");
+        let sky_inputs = BindGroupBuilder::new(&sky_layout)
+            .resource(ubo.binding_resource())
+            .sampler(&lut_sampler)
+            .texture(&transmittance.view)
+            .texture(&multiple_scattering.view)
+            .create(device, "Atmosphere Sky Bind Group");
+        let output = |lut: &Lut, label| {
+            BindGroupBuilder::new(&output_layout)
+                .texture(&lut.view)
+                .create(device, label)
+        };
+        let transmittance_output = output(&transmittance, "Atmosphere Transmittance Output");
+        let multiple_scattering_output = output(
+            &multiple_scattering,
+            "Atmosphere Multiple Scattering Output",
+        );
+        let sky_view_output = output(&sky_view, "Atmosphere Sky View Output");
+        let aerial_perspective_output =
+            output(&aerial_perspective, "Atmosphere Aerial Perspective Output");
+
+        let draw_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_fragment(binding_types::uniform())
+            .next_binding_fragment(binding_types::sampler(wgpu::SamplerBindingType::Filtering))
+            .next_binding_fragment(binding_types::texture2D())
+            .named("transmittance_lut")
+            .next_binding_fragment(binding_types::texture2D())
+            .named("sky_view_lut")
+            .next_binding_fragment(binding_types::texture2D())
+            .named("aerial_perspective_lut")
+            .create(device, "Atmosphere Bind Group");
+        let draw_bind_group = BindGroupBuilder::new(&draw_bind_group_layout)
+            .resource(ubo.binding_resource())
+            .sampler(&lut_sampler)
+            .texture(&transmittance.view)
+            .texture(&sky_view.view)
+            .texture(&aerial_perspective.view)
+            .create(device, "Atmosphere Bind Group");
+        let view_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_fragment(binding_types::uniform())
+            .next_binding_fragment(wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            })
+            .named("depth_texture")
+            .create(device, "Atmosphere View Bind Group");
+        let pipeline = create_pipeline(
+            device,
+            format,
+            &draw_bind_group_layout,
+            &view_bind_group_layout,
+        );
+
+        Some(AtmospherePass {
+            transmittance_kernel,
+            multiple_scattering_kernel,
+            sky_view_kernel,
+            aerial_perspective_kernel,
+            ubo,
+            transmittance,
+            multiple_scattering,
+            sky_view,
+            aerial_perspective,
+            transmittance_inputs,
+            multiple_scattering_inputs,
+            sky_inputs,
+            transmittance_output,
+            multiple_scattering_output,
+            sky_view_output,
+            aerial_perspective_output,
+            pipeline,
+            draw_bind_group,
+            view_bind_group_layout,
+            computed: None,
+        })
+    }
+
+    /// Whether the tables have to be computed again for `atmosphere`
+    pub fn is_stale(&self, atmosphere: Option<&Atmosphere>) -> bool {
+        atmosphere.is_some_and(|atmosphere| self.computed.as_ref() != Some(atmosphere))
+    }
+
+    /// Records the compute passes of the tables if `atmosphere` changed since they were computed
+    pub fn update(&mut self, queue: &Queue, atmosphere: &Atmosphere, encoder: &mut CommandEncoder) {
+        if self.computed.as_ref() == Some(atmosphere) {
+            return;
+        }
+        self.ubo
+            .update_content(queue, AtmosphereUBOContent::from(atmosphere));
+        let [width, height] = TRANSMITTANCE_SIZE;
+        let transmittance = [width, height, 1];
+        let [width, height] = MULTIPLE_SCATTERING_SIZE;
+        let multiple_scattering = [width, height, 1];
+        let [width, height] = SKY_VIEW_SIZE;
+        let sky_view = [width, height, 1];
+        // One thread marches every slice of its direction
+        let [width, height, _] = AERIAL_PERSPECTIVE_SIZE;
+        let aerial_perspective = [width, height, 1];
+        ComputePassBuilder::new("atmosphere luts")
+            .dispatch(
+                &self.transmittance_kernel,
+                &[&self.transmittance_inputs, &self.transmittance_output],
+                transmittance,
+            )
+            .dispatch(
+                &self.multiple_scattering_kernel,
+                &[
+                    &self.multiple_scattering_inputs,
+                    &self.multiple_scattering_output,
+                ],
+                multiple_scattering,
+            )
+            .dispatch(
+                &self.sky_view_kernel,
+                &[&self.sky_inputs, &self.sky_view_output],
+                sky_view,
+            )
+            .dispatch(
+                &self.aerial_perspective_kernel,
+                &[&self.sky_inputs, &self.aerial_perspective_output],
+                aerial_perspective,
+            )
+            .record(encoder);
+        self.computed = Some(*atmosphere);
+    }
+
+    /// Writes the camera of a window or render target, dropping its view without an atmosphere
+    pub fn prepare(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        atmosphere: Option<&Atmosphere>,
+        view: &mut Option<AtmosphereView>,
+        camera: &CameraUniform,
+    ) {
+        if atmosphere.is_none() {
+            *view = None;
+            return;
+        }
+        let Some(inverse_view_proj) = Matrix4::from(camera.view_proj).invert() else {
+            return;
+        };
+        let view = view.get_or_insert_with(|| AtmosphereView {
+            ubo: UniformBuffer::new(device),
+        });
+        view.ubo.update_content(
+            queue,
+            ViewUBOContent {
+                inverse_view_proj: inverse_view_proj.into(),
+                camera_position: camera.view_position,
+            },
+        );
+    }
+
+    /// Records a pass drawing the sky and haze over `color`, with the view's depth buffer as `depth`
+    pub fn draw(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        view: Option<&AtmosphereView>,
+        color: &TextureView,
+        depth: &TextureView,
+    ) {
+        let (Some(view), Some(_)) = (view, &self.computed) else {
+            return;
+        };
+        // The depth buffer is recreated with the window, so the bind group can't be kept
+        let view_bind_group = BindGroupBuilder::new(&self.view_bind_group_layout)
+            .resource(view.ubo.binding_resource())
+            .texture(depth)
+            .create(device, "Atmosphere View Bind Group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("atmosphere"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: color,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.draw_bind_group, &[]);
+        render_pass.set_bind_group(1, &view_bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Bytes of the lookup tables
+    pub fn size_in_bytes(&self) -> u64 {
+        self.transmittance.size_in_bytes()
+            + self.multiple_scattering.size_in_bytes()
+            + self.sky_view.size_in_bytes()
+            + self.aerial_perspective.size_in_bytes()
+    }
+}
+
+fn create_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    draw_bind_group_layout: &BindGroupLayoutWithDesc,
+    view_bind_group_layout: &BindGroupLayoutWithDesc,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Atmosphere Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("atmosphere.wgsl").into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[
+            &draw_bind_group_layout.layout,
+            &view_bind_group_layout.layout,
+        ],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("atmosphere"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        // The sky only goes where nothing was drawn, read from the depth buffer bound as a texture
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                // The light scattered in on top of what's left of the scene behind it, keeping the scene's alpha
+                blend: Some(wgpu::BlendState {
+                    color: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::SrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    alpha: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::Zero,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// The engine's atmosphere and the pass drawing it, which is created with the first atmosphere set
+#[derive(Default)]
+pub(crate) struct Sky {
+    /// Unsupported where the device can't compute the lookup tables
+    pass: LazyPass<AtmospherePass>,
+    /// Drawn instead of the background where there is one
+    atmosphere: Option<Atmosphere>,
+}
+
+impl Sky {
+    pub fn atmosphere(&self) -> Option<&Atmosphere> {
+        self.atmosphere.as_ref()
+    }
+
+    pub fn set(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        device_report: &DeviceReport,
+        atmosphere: Option<Atmosphere>,
+    ) {
+        if atmosphere.is_some()
+            && self
+                .pass
+                .get_or_create(|| AtmospherePass::new(device, format, device_report))
+                .is_none()
+        {
+            tracing::warn!("The atmosphere needs compute shaders, it isn't drawn");
+        }
+        self.atmosphere = atmosphere;
+    }
+
+    /// The direction towards the atmosphere's sun, None without an atmosphere
+    pub fn sun(&self) -> Option<[f32; 3]> {
+        self.atmosphere.as_ref().map(Atmosphere::sun)
+    }
+
+    /// Whether the lookup tables have to be computed in the compute phase
+    pub fn is_stale(&self) -> bool {
+        self.pass
+            .get()
+            .is_some_and(|pass| pass.is_stale(self.atmosphere.as_ref()))
+    }
+
+    /// See [AtmospherePass::update]
+    pub fn update(&mut self, queue: &Queue, encoder: &mut CommandEncoder) {
+        if let (Some(pass), Some(atmosphere)) = (self.pass.get_mut(), &self.atmosphere) {
+            pass.update(queue, atmosphere, encoder);
+        }
+    }
+
+    /// See [AtmospherePass::prepare]
+    pub fn prepare(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        view: &mut Option<AtmosphereView>,
+        camera: &CameraUniform,
+    ) {
+        if let Some(pass) = self.pass.get() {
+            pass.prepare(device, queue, self.atmosphere.as_ref(), view, camera);
+        }
+    }
+
+    /// See [AtmospherePass::draw]
+    pub fn draw(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        view: Option<&AtmosphereView>,
+        color: &TextureView,
+        depth: &TextureView,
+    ) {
+        if let Some(pass) = self.pass.get() {
+            pass.draw(device, encoder, view, color, depth);
+        }
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.pass.get().map_or(0, AtmospherePass::size_in_bytes)
+    }
+
+    /// Creates the pass again on a new device, the tables are computed again with the next frame
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        device_report: &DeviceReport,
+    ) {
+        self.pass
+            .recreate(|| AtmospherePass::new(device, format, device_report));
+    }
+}
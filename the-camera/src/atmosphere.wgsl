@@ -0,0 +1,142 @@
+// Draws the sky where nothing was drawn and the haze over everything else, see atmosphere.rs
+
+// The same as in atmosphere_luts.wgsl
+struct Atmosphere {
+    rayleigh_scattering: vec3<f32>,
+    rayleigh_scale_height: f32,
+    ozone_absorption: vec3<f32>,
+    mie_scattering: f32,
+    ground_albedo: vec3<f32>,
+    mie_absorption: f32,
+    sun_direction: vec3<f32>,
+    mie_scale_height: f32,
+    planet_radius: f32,
+    top_radius: f32,
+    mie_anisotropy: f32,
+    altitude: f32,
+    aerial_perspective_distance: f32,
+    exposure: f32,
+    sun_cos_radius: f32,
+    // 0 where the drawn geometry isn't hazed
+    kilometers_per_unit: f32,
+}
+@group(0) @binding(0)
+var<uniform> atmosphere: Atmosphere;
+@group(0) @binding(1)
+var lut_sampler: sampler;
+@group(0) @binding(2)
+var transmittance_lut: texture_2d<f32>;
+@group(0) @binding(3)
+var sky_view_lut: texture_2d<f32>;
+// The slices of the volume side by side, see atmosphere.rs
+@group(0) @binding(4)
+var aerial_perspective_lut: texture_2d<f32>;
+
+// One window or render target, see atmosphere.rs
+struct View {
+    inverse_view_proj: mat4x4<f32>,
+    camera_position: vec4<f32>,
+}
+@group(1) @binding(0)
+var<uniform> view: View;
+// Of the view drawn into, written by its main pass. Bound as a float texture, GL can't load from depth textures.
+@group(1) @binding(1)
+var depth_texture: texture_2d<f32>;
+
+const PI: f32 = 3.14159265;
+// Far dimmer than the real sun, so its disk keeps its color through the tonemapping at sunset
+const SUN_LUMINANCE: f32 = 2.0;
+// The same as in atmosphere_luts.wgsl
+const AERIAL_PERSPECTIVE_SLICES: u32 = 32u;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // A triangle covering the whole view
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// The inverse of uv_direction in atmosphere_luts.wgsl
+fn direction_uv(direction: vec3<f32>) -> vec2<f32> {
+    let azimuth = atan2(direction.z, direction.x);
+    let elevation = asin(clamp(direction.y, -1.0, 1.0));
+    let v = sign(elevation) * sqrt(abs(elevation) / (0.5 * PI));
+    return vec2<f32>(azimuth / (2.0 * PI) + 0.5, v * 0.5 + 0.5);
+}
+
+// The same as in atmosphere_luts.wgsl
+fn ray_sphere(origin: vec3<f32>, direction: vec3<f32>, radius: f32) -> f32 {
+    let b = dot(origin, direction);
+    let c = dot(origin, origin) - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return -1.0;
+    }
+    let root = sqrt(discriminant);
+    if c < 0.0 {
+        return -b + root;
+    }
+    let near = -b - root;
+    if near < 0.0 {
+        return -1.0;
+    }
+    return near;
+}
+
+fn sun_transmittance(direction: vec3<f32>) -> vec3<f32> {
+    let thickness = atmosphere.top_radius - atmosphere.planet_radius;
+    let uv = vec2<f32>(direction.y * 0.5 + 0.5, sqrt(clamp(atmosphere.altitude / thickness, 0.0, 1.0)));
+    return textureSampleLevel(transmittance_lut, lut_sampler, uv, 0.0).rgb;
+}
+
+// Filtered linearly between the two slices around `w`, and within a slice without bleeding into its neighbors
+fn aerial_perspective(uv: vec2<f32>, w: f32) -> vec4<f32> {
+    let slices = f32(AERIAL_PERSPECTIVE_SLICES);
+    let width = f32(textureDimensions(aerial_perspective_lut).x) / slices;
+    let u = clamp(uv.x, 0.5 / width, 1.0 - 0.5 / width);
+    let slice = clamp(w * slices - 0.5, 0.0, slices - 1.0);
+    let first = floor(slice);
+    let second = min(first + 1.0, slices - 1.0);
+    let a = textureSampleLevel(aerial_perspective_lut, lut_sampler, vec2<f32>((first + u) / slices, uv.y), 0.0);
+    let b = textureSampleLevel(aerial_perspective_lut, lut_sampler, vec2<f32>((second + u) / slices, uv.y), 0.0);
+    return mix(a, b, slice - first);
+}
+
+// Brightens the light and maps it to below 1
+fn tonemap(luminance: vec3<f32>) -> vec3<f32> {
+    return 1.0 - exp(-luminance * atmosphere.exposure);
+}
+
+// The color is added to what's left of the scene, by the alpha
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let size = vec2<f32>(textureDimensions(depth_texture));
+    let ndc = vec2<f32>(position.x / size.x * 2.0 - 1.0, 1.0 - position.y / size.y * 2.0);
+    // The far plane may be at infinity, the near plane is always in front of the camera
+    let near_plane = view.inverse_view_proj * vec4<f32>(ndc, 0.0, 1.0);
+    let direction = normalize(near_plane.xyz / near_plane.w - view.camera_position.xyz);
+    let uv = direction_uv(direction);
+    let depth = textureLoad(depth_texture, pixel, 0).r;
+
+    if depth >= 1.0 {
+        var luminance = textureSampleLevel(sky_view_lut, lut_sampler, uv, 0.0).rgb;
+        let origin = vec3<f32>(0.0, atmosphere.planet_radius + atmosphere.altitude, 0.0);
+        let sun = dot(direction, atmosphere.sun_direction);
+        if sun > atmosphere.sun_cos_radius && ray_sphere(origin, direction, atmosphere.planet_radius) < 0.0 {
+            // Darker towards the edge of the disk
+            let edge = (1.0 - sun) / (1.0 - atmosphere.sun_cos_radius);
+            luminance += sun_transmittance(direction) * SUN_LUMINANCE * (1.0 - 0.6 * edge);
+        }
+        return vec4<f32>(tonemap(luminance), 0.0);
+    }
+    if atmosphere.kilometers_per_unit <= 0.0 {
+        discard;
+    }
+
+    let world = view.inverse_view_proj * vec4<f32>(ndc, depth, 1.0);
+    let distance = length(world.xyz / world.w - view.camera_position.xyz) * atmosphere.kilometers_per_unit;
+    let w = sqrt(clamp(distance / atmosphere.aerial_perspective_distance, 0.0, 1.0));
+    let aerial = aerial_perspective(uv, w);
+    return vec4<f32>(tonemap(aerial.rgb), aerial.a);
+}
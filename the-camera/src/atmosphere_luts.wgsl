@@ -0,0 +1,311 @@
+// The lookup tables of the atmosphere, see atmosphere.rs. Lengths are in kilometers, positions are relative to the
+// planet's center with the camera straight above it.
+
+// See atmosphere.rs
+struct Atmosphere {
+    rayleigh_scattering: vec3<f32>,
+    rayleigh_scale_height: f32,
+    ozone_absorption: vec3<f32>,
+    mie_scattering: f32,
+    ground_albedo: vec3<f32>,
+    mie_absorption: f32,
+    sun_direction: vec3<f32>,
+    mie_scale_height: f32,
+    planet_radius: f32,
+    top_radius: f32,
+    mie_anisotropy: f32,
+    altitude: f32,
+    aerial_perspective_distance: f32,
+    exposure: f32,
+    sun_cos_radius: f32,
+    kilometers_per_unit: f32,
+}
+@group(0) @binding(0)
+var<uniform> atmosphere: Atmosphere;
+// The tables of the kernels before, the transmittance kernel has neither
+@group(0) @binding(1)
+var lut_sampler: sampler;
+@group(0) @binding(2)
+var transmittance_lut: texture_2d<f32>;
+@group(0) @binding(3)
+var multiple_scattering_lut: texture_2d<f32>;
+
+// What a kernel writes
+@group(1) @binding(0)
+var output: texture_storage_2d<rgba16float, write>;
+
+const PI: f32 = 3.14159265;
+// Samples along the rays of each table
+const TRANSMITTANCE_STEPS: u32 = 40u;
+const MULTIPLE_SCATTERING_STEPS: u32 = 20u;
+const SKY_VIEW_STEPS: u32 = 48u;
+// Of every slice of the aerial perspective volume
+const AERIAL_PERSPECTIVE_STEPS: u32 = 4u;
+// Directions the light scattered more than once is gathered from
+const MULTIPLE_SCATTERING_DIRECTIONS: u32 = 64u;
+// Side by side along U of the aerial perspective table, see atmosphere.rs
+const AERIAL_PERSPECTIVE_SLICES: u32 = 32u;
+
+// Distance along the ray to the sphere around the planet's center, from inside the sphere to where the ray leaves
+// it. Negative where the ray misses it.
+fn ray_sphere(origin: vec3<f32>, direction: vec3<f32>, radius: f32) -> f32 {
+    let b = dot(origin, direction);
+    let c = dot(origin, origin) - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return -1.0;
+    }
+    let root = sqrt(discriminant);
+    if c < 0.0 {
+        return -b + root;
+    }
+    // Hits in front of the ray only
+    let near = -b - root;
+    if near < 0.0 {
+        return -1.0;
+    }
+    return near;
+}
+
+struct Medium {
+    rayleigh_scattering: vec3<f32>,
+    mie_scattering: f32,
+    extinction: vec3<f32>,
+}
+
+fn medium(position: vec3<f32>) -> Medium {
+    let height = max(length(position) - atmosphere.planet_radius, 0.0);
+    let rayleigh_density = exp(-height / atmosphere.rayleigh_scale_height);
+    let mie_density = exp(-height / atmosphere.mie_scale_height);
+    // A layer 30 kilometers thick around 25 kilometers up
+    let ozone_density = max(0.0, 1.0 - abs(height - 25.0) / 15.0);
+
+    var out: Medium;
+    out.rayleigh_scattering = atmosphere.rayleigh_scattering * rayleigh_density;
+    out.mie_scattering = atmosphere.mie_scattering * mie_density;
+    out.extinction = out.rayleigh_scattering
+        + vec3<f32>((atmosphere.mie_scattering + atmosphere.mie_absorption) * mie_density)
+        + atmosphere.ozone_absorption * ozone_density;
+    return out;
+}
+
+fn rayleigh_phase(cos_theta: f32) -> f32 {
+    return 3.0 / (16.0 * PI) * (1.0 + cos_theta * cos_theta);
+}
+
+// Cornette-Shanks, Henyey-Greenstein with the shape of the Rayleigh phase
+fn mie_phase(cos_theta: f32) -> f32 {
+    let g = atmosphere.mie_anisotropy;
+    let g2 = g * g;
+    let denominator = (2.0 + g2) * pow(1.0 + g2 - 2.0 * g * cos_theta, 1.5);
+    return 3.0 / (8.0 * PI) * (1.0 - g2) * (1.0 + cos_theta * cos_theta) / denominator;
+}
+
+// Transmittance table: the cosine of the zenith angle along U, the height along V squeezed towards the ground
+fn transmittance_uv(height: f32, cos_zenith: f32) -> vec2<f32> {
+    let thickness = atmosphere.top_radius - atmosphere.planet_radius;
+    return vec2<f32>(cos_zenith * 0.5 + 0.5, sqrt(clamp(height / thickness, 0.0, 1.0)));
+}
+
+// Of the light from the top of the atmosphere along `direction`, none where the planet is in the way
+fn transmittance_towards(position: vec3<f32>, direction: vec3<f32>) -> vec3<f32> {
+    let radius = length(position);
+    let uv = transmittance_uv(radius - atmosphere.planet_radius, dot(position / radius, direction));
+    return textureSampleLevel(transmittance_lut, lut_sampler, uv, 0.0).rgb;
+}
+
+// Multiple scattering table: the cosine of the sun's zenith angle along U, the height along V
+fn multiple_scattering_at(position: vec3<f32>, sun: vec3<f32>) -> vec3<f32> {
+    let radius = length(position);
+    let thickness = atmosphere.top_radius - atmosphere.planet_radius;
+    let uv = vec2<f32>(
+        dot(position / radius, sun) * 0.5 + 0.5,
+        clamp((radius - atmosphere.planet_radius) / thickness, 0.0, 1.0),
+    );
+    return textureSampleLevel(multiple_scattering_lut, lut_sampler, uv, 0.0).rgb;
+}
+
+// Sky view and aerial perspective tables: the azimuth around Y along U, the elevation along V squeezed towards the
+// horizon where the sky changes the most. The same as in atmosphere.wgsl.
+fn uv_direction(uv: vec2<f32>) -> vec3<f32> {
+    let azimuth = (uv.x - 0.5) * 2.0 * PI;
+    let v = uv.y * 2.0 - 1.0;
+    let elevation = sign(v) * v * v * 0.5 * PI;
+    return vec3<f32>(cos(elevation) * cos(azimuth), sin(elevation), cos(elevation) * sin(azimuth));
+}
+
+fn observer() -> vec3<f32> {
+    return vec3<f32>(0.0, atmosphere.planet_radius + atmosphere.altitude, 0.0);
+}
+
+// Of the texel's center
+fn texel_uv(id: vec2<u32>, size: vec2<u32>) -> vec2<f32> {
+    return (vec2<f32>(id) + 0.5) / vec2<f32>(size);
+}
+
+@compute @workgroup_size(8, 8)
+fn transmittance(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if any(id.xy >= size) {
+        return;
+    }
+    let uv = texel_uv(id.xy, size);
+    let cos_zenith = uv.x * 2.0 - 1.0;
+    let height = uv.y * uv.y * (atmosphere.top_radius - atmosphere.planet_radius);
+    let origin = vec3<f32>(0.0, atmosphere.planet_radius + height, 0.0);
+    let direction = vec3<f32>(sqrt(max(1.0 - cos_zenith * cos_zenith, 0.0)), cos_zenith, 0.0);
+
+    var light = vec3<f32>(0.0);
+    if ray_sphere(origin, direction, atmosphere.planet_radius) < 0.0 {
+        let ray_length = ray_sphere(origin, direction, atmosphere.top_radius);
+        let step = ray_length / f32(TRANSMITTANCE_STEPS);
+        var optical_depth = vec3<f32>(0.0);
+        for (var index = 0u; index < TRANSMITTANCE_STEPS; index++) {
+            let position = origin + direction * (f32(index) + 0.5) * step;
+            optical_depth += medium(position).extinction * step;
+        }
+        light = exp(-optical_depth);
+    }
+    textureStore(output, id.xy, vec4<f32>(light, 1.0));
+}
+
+// Sunlight reflected by the ground at `position`
+fn ground(position: vec3<f32>, sun: vec3<f32>) -> vec3<f32> {
+    let normal = normalize(position);
+    return transmittance_towards(position, sun) * atmosphere.ground_albedo / PI * max(dot(normal, sun), 0.0);
+}
+
+// The light scattered more than once is approximated from light scattered twice, gathered from every direction
+// around the point as if it scattered evenly. What fraction of light the air around scatters back is summed up as
+// a geometric series for the orders above, see section 5.5 of the paper.
+@compute @workgroup_size(8, 8)
+fn multiple_scattering(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if any(id.xy >= size) {
+        return;
+    }
+    let uv = texel_uv(id.xy, size);
+    let cos_sun = uv.x * 2.0 - 1.0;
+    let sun = vec3<f32>(0.0, cos_sun, sqrt(max(1.0 - cos_sun * cos_sun, 0.0)));
+    let height = max(uv.y * (atmosphere.top_radius - atmosphere.planet_radius), 1e-3);
+    let origin = vec3<f32>(0.0, atmosphere.planet_radius + height, 0.0);
+
+    var luminance = vec3<f32>(0.0);
+    var transfer = vec3<f32>(0.0);
+    for (var direction_index = 0u; direction_index < MULTIPLE_SCATTERING_DIRECTIONS; direction_index++) {
+        // Spread evenly over the sphere along a Fibonacci spiral
+        let y = 1.0 - (f32(direction_index) + 0.5) / f32(MULTIPLE_SCATTERING_DIRECTIONS) * 2.0;
+        let ring = sqrt(max(1.0 - y * y, 0.0));
+        let angle = f32(direction_index) * 2.39996323;
+        let direction = vec3<f32>(ring * cos(angle), y, ring * sin(angle));
+
+        let ground_distance = ray_sphere(origin, direction, atmosphere.planet_radius);
+        var ray_length = ray_sphere(origin, direction, atmosphere.top_radius);
+        if ground_distance >= 0.0 {
+            ray_length = ground_distance;
+        }
+        let step = ray_length / f32(MULTIPLE_SCATTERING_STEPS);
+        var throughput = vec3<f32>(1.0);
+        for (var index = 0u; index < MULTIPLE_SCATTERING_STEPS; index++) {
+            let position = origin + direction * (f32(index) + 0.5) * step;
+            let air = medium(position);
+            let step_transmittance = exp(-air.extinction * step);
+            let scattering = air.rayleigh_scattering + vec3<f32>(air.mie_scattering);
+            // Integrated over the step as in the paper, with the even phase of 1 / 4π
+            let integral = throughput * (1.0 - step_transmittance) / max(air.extinction, vec3<f32>(1e-9));
+            luminance += integral * scattering * transmittance_towards(position, sun) / (4.0 * PI);
+            transfer += integral * scattering;
+            throughput *= step_transmittance;
+        }
+        if ground_distance >= 0.0 {
+            luminance += throughput * ground(origin + direction * ground_distance, sun);
+        }
+    }
+    // The sphere's solid angle cancels out the even phase of the directions
+    luminance /= f32(MULTIPLE_SCATTERING_DIRECTIONS);
+    transfer /= f32(MULTIPLE_SCATTERING_DIRECTIONS);
+    textureStore(output, id.xy, vec4<f32>(luminance / (1.0 - min(transfer, vec3<f32>(0.999))), 1.0));
+}
+
+// Light scattered towards the origin along a ray and what's left of the light behind
+struct Scattered {
+    luminance: vec3<f32>,
+    transmittance: vec3<f32>,
+}
+
+// Marches from `start` to `end` along the ray, adding to what was scattered before `start`
+fn march(origin: vec3<f32>, direction: vec3<f32>, start: f32, end: f32, steps: u32, before: Scattered) -> Scattered {
+    let sun = atmosphere.sun_direction;
+    let cos_theta = dot(direction, sun);
+    let rayleigh = rayleigh_phase(cos_theta);
+    let mie = mie_phase(cos_theta);
+    let step = max(end - start, 0.0) / f32(steps);
+
+    var out = before;
+    for (var index = 0u; index < steps; index++) {
+        let position = origin + direction * (start + (f32(index) + 0.5) * step);
+        let air = medium(position);
+        let step_transmittance = exp(-air.extinction * step);
+        let scattering = air.rayleigh_scattering + vec3<f32>(air.mie_scattering);
+        let in_scattered = (air.rayleigh_scattering * rayleigh + air.mie_scattering * mie)
+            * transmittance_towards(position, sun)
+            + scattering * multiple_scattering_at(position, sun);
+        out.luminance += out.transmittance * in_scattered * (1.0 - step_transmittance)
+            / max(air.extinction, vec3<f32>(1e-9));
+        out.transmittance *= step_transmittance;
+    }
+    return out;
+}
+
+@compute @workgroup_size(8, 8)
+fn sky_view(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output);
+    if any(id.xy >= size) {
+        return;
+    }
+    let direction = uv_direction(texel_uv(id.xy, size));
+    let origin = observer();
+    let ground_distance = ray_sphere(origin, direction, atmosphere.planet_radius);
+    var ray_length = ray_sphere(origin, direction, atmosphere.top_radius);
+    if ground_distance >= 0.0 {
+        ray_length = ground_distance;
+    }
+
+    let nothing = Scattered(vec3<f32>(0.0), vec3<f32>(1.0));
+    var scattered = march(origin, direction, 0.0, ray_length, SKY_VIEW_STEPS, nothing);
+    // Below the horizon
+    if ground_distance >= 0.0 {
+        scattered.luminance += scattered.transmittance
+            * ground(origin + direction * ground_distance, atmosphere.sun_direction);
+    }
+    textureStore(output, id.xy, vec4<f32>(scattered.luminance, 1.0));
+}
+
+// Every thread marches one direction through all slices, the distances of the slices grow with the square of their
+// index so there are more of them close to the camera. Alpha is the mean transmittance.
+@compute @workgroup_size(8, 8)
+fn aerial_perspective(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(output) / vec2<u32>(AERIAL_PERSPECTIVE_SLICES, 1u);
+    if any(id.xy >= size) {
+        return;
+    }
+    let direction = uv_direction(texel_uv(id.xy, size));
+    let origin = observer();
+    // Nothing is seen through the ground
+    let ground_distance = ray_sphere(origin, direction, atmosphere.planet_radius);
+
+    var scattered = Scattered(vec3<f32>(0.0), vec3<f32>(1.0));
+    var start = 0.0;
+    for (var slice = 0u; slice < AERIAL_PERSPECTIVE_SLICES; slice++) {
+        let w = (f32(slice) + 0.5) / f32(AERIAL_PERSPECTIVE_SLICES);
+        var end = atmosphere.aerial_perspective_distance * w * w;
+        if ground_distance >= 0.0 {
+            end = min(end, ground_distance);
+        }
+        scattered = march(origin, direction, start, end, AERIAL_PERSPECTIVE_STEPS, scattered);
+        start = max(start, end);
+        let mean_transmittance = dot(scattered.transmittance, vec3<f32>(1.0 / 3.0));
+        let texel = vec2<u32>(slice * size.x + id.x, id.y);
+        textureStore(output, texel, vec4<f32>(scattered.luminance, mean_transmittance));
+    }
+}
@@ -0,0 +1,401 @@
+//! Music for shaders to react to. The app captures audio however it likes and pushes the samples
+//! into an [AudioFeed] from its capture callback on any thread:
+//!
+//! ```ignore
+//! let feed = engine.audio_feed();
+//! let stream = device.build_input_stream(&config, move |samples: &[f32], _| {
+//!     feed.push(samples, config.channels, config.sample_rate.0);
+//! }, ...);
+//! ```
+//!
+//! Every update the engine analyzes the latest samples into [AUDIO_BANDS] frequency bands and hands their
+//! magnitudes to the shaders with the camera, as `camera.audio` in the global bindings:
+//!
+//! ```wgsl
+//! struct Audio {
+//!     // From the lowest band at x of the first vector to the highest at w of the last, 0 to 1
+//!     bands: array<vec4<f32>, 4>,
+//!     // Loudness of the signal as a whole, 0 to 1
+//!     level: f32,
+//! }
+//! ```
+//!
+//! See `Camera` in `shader.wgsl` for where it sits in the uniform.
+//!
+//! With the `cpal` feature the feed captures a device itself, e.g. a microphone or the loopback of what the system
+//! plays, with [AudioFeed::capture_default_input] or [AudioFeed::capture_input].
+
+use std::{
+    collections::VecDeque,
+    f32::consts::PI,
+    sync::{Arc, Mutex},
+};
+
+#[cfg(feature = "cpal")]
+use std::io;
+
+#[cfg(feature = "cpal")]
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use web_time::Duration;
+
+/// How many frequency bands the spectrum is split into
+pub const AUDIO_BANDS: usize = 16;
+/// Samples analyzed at once, about 20 ms at 48 kHz
+const FFT_SIZE: usize = 1024;
+/// Bands are spaced evenly in pitch between these frequencies in Hz
+const LOWEST_FREQUENCY: f32 = 30.0;
+const HIGHEST_FREQUENCY: f32 = 16000.0;
+/// Magnitudes this far below a full scale sine map to 0
+const DYNAMIC_RANGE_DB: f32 = 60.0;
+/// Bands jump up right away, but fall back by this factor every second, so beats don't flicker
+const FALL_PER_SECOND: f32 = 0.02;
+
+/// The audio part of the global uniform buffer
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct AudioUniform {
+    bands: [[f32; 4]; AUDIO_BANDS / 4],
+    level: f32,
+    _padding: [f32; 3],
+}
+
+crate::assert_uniform_layout!(AudioUniform {
+    bands: ALIGN_VEC4,
+    level: ALIGN_SCALAR,
+});
+
+#[derive(Debug, Default)]
+struct Samples {
+    /// Mono, the newest [FFT_SIZE] at most
+    samples: VecDeque<f32>,
+    sample_rate: u32,
+    /// Whether anything was pushed since the last analysis
+    fresh: bool,
+}
+
+/// Where the app's audio capture pushes samples, cheap to clone and send to the capture thread
+#[derive(Debug, Clone, Default)]
+pub struct AudioFeed(Arc<Mutex<Samples>>);
+
+impl AudioFeed {
+    /// `samples` are interleaved across `channels`, which are mixed down to one
+    pub fn push(&self, samples: &[f32], channels: u16, sample_rate: u32) {
+        let channels = channels.max(1) as usize;
+        let mut shared = self.0.lock().expect("Audio feed lock poisoned!");
+        shared.sample_rate = sample_rate;
+        shared.fresh = true;
+        for frame in samples.chunks(channels) {
+            if shared.samples.len() == FFT_SIZE {
+                shared.samples.pop_front();
+            }
+            shared
+                .samples
+                .push_back(frame.iter().sum::<f32>() / frame.len() as f32);
+        }
+    }
+}
+
+/// An input device captured into an [AudioFeed], capturing stops when it's dropped. It has to stay on the thread it
+/// was started on.
+#[cfg(feature = "cpal")]
+pub struct AudioCapture {
+    _stream: cpal::Stream,
+    device_name: String,
+}
+
+#[cfg(feature = "cpal")]
+impl AudioCapture {
+    pub fn device_name(&self) -> &str {
+        &self.device_name
+    }
+}
+
+#[cfg(feature = "cpal")]
+impl AudioFeed {
+    /// Starts capturing the system's default input device into the feed
+    pub fn capture_default_input(&self) -> io::Result<AudioCapture> {
+        let device = cpal::default_host().default_input_device().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "There is no audio input device")
+        })?;
+        self.capture_input(&device)
+    }
+
+    /// Starts capturing `device` into the feed, in the format it defaults to
+    pub fn capture_input(&self, device: &cpal::Device) -> io::Result<AudioCapture> {
+        let config = device.default_input_config().map_err(io::Error::other)?;
+        let stream_config = config.config();
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => self.build_stream::<f32>(device, &stream_config),
+            cpal::SampleFormat::I16 => self.build_stream::<i16>(device, &stream_config),
+            cpal::SampleFormat::U16 => self.build_stream::<u16>(device, &stream_config),
+            cpal::SampleFormat::I32 => self.build_stream::<i32>(device, &stream_config),
+            cpal::SampleFormat::U8 => self.build_stream::<u8>(device, &stream_config),
+            format => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("Audio samples in {format} aren't supported"),
+                ))
+            }
+        }?;
+        stream.play().map_err(io::Error::other)?;
+        Ok(AudioCapture {
+            _stream: stream,
+            device_name: device.name().unwrap_or_default(),
+        })
+    }
+
+    fn build_stream<T>(
+        &self,
+        device: &cpal::Device,
+        config: &cpal::StreamConfig,
+    ) -> io::Result<cpal::Stream>
+    where
+        T: cpal::SizedSample,
+        f32: cpal::FromSample<T>,
+    {
+        let feed = self.clone();
+        let (channels, sample_rate) = (config.channels, config.sample_rate.0);
+        // Kept between callbacks, they come every few milliseconds
+        let mut converted = Vec::new();
+        device
+            .build_input_stream(
+                config,
+                move |samples: &[T], _| {
+                    converted.clear();
+                    converted.extend(samples.iter().map(|sample| sample.to_sample::<f32>()));
+                    feed.push(&converted, channels, sample_rate);
+                },
+                |err| tracing::warn!("Audio capture failed: {err}"),
+                None,
+            )
+            .map_err(io::Error::other)
+    }
+}
+
+/// Turns the fed samples into band magnitudes once per update
+#[derive(Debug, Default)]
+pub(crate) struct AudioAnalyzer {
+    feed: AudioFeed,
+    bands: [f32; AUDIO_BANDS],
+    level: f32,
+    /// Whether samples arrived for the last analysis
+    playing: bool,
+}
+
+impl AudioAnalyzer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&self) -> AudioFeed {
+        self.feed.clone()
+    }
+
+    pub fn bands(&self) -> &[f32; AUDIO_BANDS] {
+        &self.bands
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+
+    /// While samples arrive and until the bands fell back to zero after they stopped, frames are worth redrawing
+    pub fn is_playing(&self) -> bool {
+        self.playing || self.level > 0.0
+    }
+
+    /// Analyzes the newest samples, `delta` after the last time
+    pub fn analyze(&mut self, delta: Duration) {
+        let (samples, sample_rate) = {
+            let mut shared = self.feed.0.lock().expect("Audio feed lock poisoned!");
+            self.playing = std::mem::take(&mut shared.fresh);
+            let samples = if self.playing && shared.samples.len() == FFT_SIZE {
+                shared.samples.iter().copied().collect()
+            } else {
+                Vec::new()
+            };
+            (samples, shared.sample_rate)
+        };
+        let (bands, level) = if samples.is_empty() || sample_rate == 0 {
+            ([0.0; AUDIO_BANDS], 0.0)
+        } else {
+            spectrum(&samples, sample_rate)
+        };
+
+        let fall = FALL_PER_SECOND.powf(delta.as_secs_f32());
+        let smooth = |old: f32, new: f32| {
+            let fallen = old * fall;
+            // Tiny leftovers would keep the engine redrawing forever
+            if new.max(fallen) < 1e-3 {
+                0.0
+            } else {
+                new.max(fallen)
+            }
+        };
+        for (old, new) in self.bands.iter_mut().zip(bands) {
+            *old = smooth(*old, new);
+        }
+        self.level = smooth(self.level, level);
+    }
+
+    pub fn uniform(&self) -> AudioUniform {
+        let mut uniform = AudioUniform {
+            level: self.level,
+            ..Default::default()
+        };
+        for (chunk, bands) in uniform.bands.iter_mut().zip(self.bands.chunks(4)) {
+            chunk.copy_from_slice(bands);
+        }
+        uniform
+    }
+}
+
+/// Band magnitudes and overall level of [FFT_SIZE] samples, each from 0 to 1
+fn spectrum(samples: &[f32], sample_rate: u32) -> ([f32; AUDIO_BANDS], f32) {
+    let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+    // A full scale sine has an RMS of 1/sqrt(2)
+    let level = to_unit(rms * 2f32.sqrt());
+
+    // The Hann window keeps frequencies between bins from smearing over the whole spectrum
+    let mut re: Vec<f32> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, s)| s * (0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos()))
+        .collect();
+    let mut im = vec![0.0; FFT_SIZE];
+    fft(&mut re, &mut im);
+
+    // A full scale sine peaks at the window's sum over two
+    let scale = 4.0 / FFT_SIZE as f32;
+    let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+    let highest = HIGHEST_FREQUENCY.min(sample_rate as f32 / 2.0);
+    let edge = |band: usize| {
+        LOWEST_FREQUENCY * (highest / LOWEST_FREQUENCY).powf(band as f32 / AUDIO_BANDS as f32)
+    };
+    let mut bands = [0.0; AUDIO_BANDS];
+    for (band, magnitude) in bands.iter_mut().enumerate() {
+        let first = ((edge(band) / bin_width) as usize).clamp(1, FFT_SIZE / 2 - 1);
+        // Low bands are narrower than a bin, they take the one they're in
+        let last = ((edge(band + 1) / bin_width) as usize)
+            .saturating_sub(1)
+            .clamp(first, FFT_SIZE / 2 - 1);
+        let peak = (first..=last)
+            .map(|bin| (re[bin] * re[bin] + im[bin] * im[bin]).sqrt() * scale)
+            .fold(0.0, f32::max);
+        *magnitude = to_unit(peak);
+    }
+    (bands, level)
+}
+
+/// Maps an amplitude relative to full scale onto 0 to 1 over [DYNAMIC_RANGE_DB]
+fn to_unit(amplitude: f32) -> f32 {
+    if amplitude <= 0.0 {
+        return 0.0;
+    }
+    (1.0 + 20.0 * amplitude.log10() / DYNAMIC_RANGE_DB).clamp(0.0, 1.0)
+}
+
+/// In place radix-2 FFT, the length has to be a power of two
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut length = 2;
+    while length <= n {
+        let angle = -2.0 * PI / length as f32;
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + length / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        length <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, sample_rate: u32) -> Vec<f32> {
+        (0..FFT_SIZE)
+            .map(|i| (2.0 * PI * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn fft_finds_the_frequency_of_a_cosine() {
+        let mut re: Vec<f32> = (0..64)
+            .map(|i| (2.0 * PI * 5.0 * i as f32 / 64.0).cos())
+            .collect();
+        let mut im = vec![0.0; 64];
+        fft(&mut re, &mut im);
+        for bin in 0..64 {
+            let magnitude = (re[bin] * re[bin] + im[bin] * im[bin]).sqrt();
+            // Half of it at the negative frequency
+            let expected = if bin == 5 || bin == 59 { 32.0 } else { 0.0 };
+            assert!(
+                (magnitude - expected).abs() < 1e-3,
+                "bin {bin}: {magnitude}"
+            );
+        }
+    }
+
+    #[test]
+    fn fft_of_an_impulse_is_flat() {
+        let mut re = vec![0.0; 16];
+        re[0] = 1.0;
+        let mut im = vec![0.0; 16];
+        fft(&mut re, &mut im);
+        assert!(re.iter().all(|&value| (value - 1.0).abs() < 1e-6), "{re:?}");
+        assert!(im.iter().all(|&value| value.abs() < 1e-6), "{im:?}");
+    }
+
+    #[test]
+    fn a_full_scale_sine_peaks_in_its_band() {
+        // Exactly on bin 16, one of band 8's
+        let (bands, level) = spectrum(&sine(750.0, 48000), 48000);
+        assert!((level - 1.0).abs() < 1e-3, "{level}");
+        let loudest = (0..AUDIO_BANDS)
+            .max_by(|&a, &b| bands[a].total_cmp(&bands[b]))
+            .unwrap();
+        let edge = |band: usize| {
+            LOWEST_FREQUENCY
+                * (HIGHEST_FREQUENCY / LOWEST_FREQUENCY).powf(band as f32 / AUDIO_BANDS as f32)
+        };
+        assert!(
+            edge(loudest) <= 1000.0 && 1000.0 < edge(loudest + 1),
+            "{bands:?}"
+        );
+        assert!(bands[loudest] > 0.99, "{bands:?}");
+        assert!(bands[0] < 0.5, "{bands:?}");
+
+        let (bands, level) = spectrum(&[0.0; FFT_SIZE], 48000);
+        assert_eq!((bands, level), ([0.0; AUDIO_BANDS], 0.0));
+    }
+
+    #[test]
+    fn amplitudes_map_onto_the_dynamic_range() {
+        assert_eq!(to_unit(1.0), 1.0);
+        assert_eq!(to_unit(0.0), 0.0);
+        assert!((to_unit(10f32.powf(-DYNAMIC_RANGE_DB / 40.0)) - 0.5).abs() < 1e-5);
+        assert_eq!(to_unit(1e-6), 0.0);
+    }
+}
@@ -0,0 +1,249 @@
+use wgpu::{BindGroupLayout, Device, Queue, RenderPass, RenderPipeline, TextureFormat};
+
+use crate::{
+    global_bindings::GlobalBindings,
+    material_bindings::MaterialBindings,
+    mesh::{GpuMesh, MeshData, Vertex},
+    profiler::RenderStats,
+    scene::TextureHandle,
+    texture::{self, GpuTexture},
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder},
+        binding_types,
+        uniform_buffer::UniformBuffer,
+    },
+};
+
+/// What windows and render targets show behind the scene, set with
+/// [crate::render_engine::RenderEngine::set_background]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Background {
+    /// A single RGBA color
+    Color([f32; 4]),
+    /// Blends vertically from the bottom of the screen to the top
+    Gradient { top: [f32; 4], bottom: [f32; 4] },
+    /// An equirectangular panorama added with [crate::render_engine::RenderEngine::add_texture], turning with the
+    /// camera
+    Skybox(TextureHandle),
+    /// Fully transparent, so the desktop shows through windows created with `with_transparent(true)`. Falls back
+    /// to black where the surface can't be composited.
+    Transparent,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Color([0.1, 0.2, 0.3, 1.0])
+    }
+}
+
+impl Background {
+    /// Whether window surfaces need an alpha mode that lets the desktop show through
+    pub fn is_transparent(&self) -> bool {
+        match self {
+            Background::Color(color) => color[3] < 1.0,
+            Background::Transparent => true,
+            Background::Gradient { .. } | Background::Skybox(_) => false,
+        }
+    }
+
+    /// What the color target is cleared to before the background is drawn over it, if it has to be drawn
+    pub(crate) fn clear_color(&self) -> wgpu::Color {
+        let [r, g, b, a] = match self {
+            Background::Color(color) => color.map(f64::from),
+            Background::Transparent => [0.0; 4],
+            Background::Gradient { .. } | Background::Skybox(_) => [0.0, 0.0, 0.0, 1.0],
+        };
+        wgpu::Color { r, g, b, a }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GradientUBOContent {
+    top: [f32; 4],
+    bottom: [f32; 4],
+}
+
+crate::assert_uniform_layout!(GradientUBOContent {
+    top: ALIGN_VEC4,
+    bottom: ALIGN_VEC4,
+});
+
+/// Draws gradients and skyboxes at the start of the main pass. Plain colors only need the clear.
+pub(crate) struct BackgroundPass {
+    background: Background,
+    gradient_pipeline: RenderPipeline,
+    gradient_ubo: UniformBuffer<GradientUBOContent>,
+    gradient_bind_group: wgpu::BindGroup,
+    skybox_pipeline: RenderPipeline,
+    skybox_mesh: GpuMesh,
+}
+
+impl BackgroundPass {
+    /// `format` has to be the engine's swapchain format, since the background is drawn in the main pass
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        material_bindings: &MaterialBindings,
+    ) -> Self {
+        let _span = tracing::debug_span!("create_background_pipelines").entered();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Background Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("background.wgsl").into()),
+        });
+
+        let gradient_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_rendering(binding_types::uniform())
+            .create(device, "Gradient Bind Group");
+        let gradient_ubo = UniformBuffer::new(device);
+        let gradient_bind_group = BindGroupBuilder::new(&gradient_bind_group_layout)
+            .resource(gradient_ubo.binding_resource())
+            .create(device, "Gradient Bind Group");
+        let gradient_pipeline = create_background_pipeline(
+            device,
+            &shader,
+            format,
+            &[&gradient_bind_group_layout.layout],
+            "gradient",
+            &[],
+        );
+
+        let skybox_pipeline = create_background_pipeline(
+            device,
+            &shader,
+            format,
+            &[
+                global_bindings.bind_group_layouts(),
+                material_bindings.bind_group_layouts(),
+            ],
+            "skybox",
+            &[Vertex::desc()],
+        );
+
+        BackgroundPass {
+            background: Background::default(),
+            gradient_pipeline,
+            gradient_ubo,
+            gradient_bind_group,
+            skybox_pipeline,
+            skybox_mesh: GpuMesh::new(device, MeshData::cube()),
+        }
+    }
+
+    pub fn background(&self) -> Background {
+        self.background
+    }
+
+    pub fn set_background(&mut self, queue: &Queue, background: Background) {
+        if let Background::Gradient { top, bottom } = background {
+            self.gradient_ubo
+                .update_content(queue, GradientUBOContent { top, bottom });
+        }
+        self.background = background;
+    }
+
+    /// Draws the background into a freshly cleared render pass. `globals` holds the camera the skybox turns with.
+    /// A skybox whose texture doesn't exist is left out.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut RenderPass<'a>,
+        globals: &'a wgpu::BindGroup,
+        textures: &'a [GpuTexture],
+        stats: &mut RenderStats,
+    ) {
+        match self.background {
+            Background::Color(_) | Background::Transparent => {}
+            Background::Gradient { .. } => {
+                render_pass.set_pipeline(&self.gradient_pipeline);
+                render_pass.set_bind_group(0, &self.gradient_bind_group, &[]);
+                render_pass.draw(0..3, 0..1);
+                stats.pipeline_switches += 1;
+                stats.bind_group_switches += 1;
+                stats.draw(3, 1);
+            }
+            Background::Skybox(TextureHandle(texture)) => {
+                let Some(texture) = textures.get(texture) else {
+                    return;
+                };
+                render_pass.set_pipeline(&self.skybox_pipeline);
+                render_pass.set_bind_group(0, globals, &[]);
+                render_pass.set_bind_group(1, &texture.bind_group, &[]);
+                render_pass.set_vertex_buffer(0, self.skybox_mesh.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(
+                    self.skybox_mesh.index_buffer.slice(..),
+                    wgpu::IndexFormat::Uint16,
+                );
+                render_pass.draw_indexed(0..self.skybox_mesh.index_count(), 0, 0..1);
+                stats.pipeline_switches += 1;
+                stats.bind_group_switches += 2;
+                stats.draw(self.skybox_mesh.index_count(), 1);
+            }
+        }
+    }
+}
+
+/// The background is drawn first and behind everything, so it neither tests nor writes depth
+fn create_background_pipeline(
+    device: &Device,
+    shader: &wgpu::ShaderModule,
+    format: TextureFormat,
+    bind_group_layouts: &[&BindGroupLayout],
+    name: &str,
+    buffers: &[wgpu::VertexBufferLayout],
+) -> RenderPipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(name),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some(&format!("vs_{name}")),
+            buffers,
+            compilation_options: Default::default(),
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Cw,
+            // The skybox cube is seen from the inside
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+            unclipped_depth: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some(&format!("fs_{name}")),
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                // Leaves the cleared ID of nothing in place
+                Some(wgpu::ColorTargetState {
+                    format: texture::Texture::ID_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::empty(),
+                }),
+            ],
+            compilation_options: Default::default(),
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
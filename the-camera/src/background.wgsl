@@ -0,0 +1,71 @@
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+
+struct Gradient {
+    top: vec4<f32>,
+    bottom: vec4<f32>,
+}
+
+const PI: f32 = 3.14159265;
+
+// Gradient: a single triangle covering the whole screen
+
+@group(0) @binding(0)
+var<uniform> gradient: Gradient;
+
+struct GradientOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_gradient(@builtin(vertex_index) index: u32) -> GradientOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: GradientOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 1.0, 1.0);
+    // uv.y is 0 at the bottom of the screen and 1 at the top, the rest of the triangle is offscreen
+    out.color = mix(gradient.bottom, gradient.top, uv.y);
+    return out;
+}
+
+@fragment
+fn fs_gradient(in: GradientOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
+
+// Skybox: a cube around the camera, infinitely far away, showing an equirectangular panorama
+
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+@group(1) @binding(0)
+var t_sky: texture_2d<f32>;
+@group(1) @binding(1)
+var s_sky: sampler;
+
+struct SkyboxOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) direction: vec3<f32>,
+};
+
+@vertex
+fn vs_skybox(@location(0) position: vec3<f32>) -> SkyboxOutput {
+    var out: SkyboxOutput;
+    out.direction = position;
+    // w = 0 drops the camera's translation, z = w puts it on the far plane
+    let clip = camera.view_proj * vec4<f32>(position, 0.0);
+    out.clip_position = clip.xyww;
+    return out;
+}
+
+@fragment
+fn fs_skybox(in: SkyboxOutput) -> @location(0) vec4<f32> {
+    let direction = normalize(in.direction);
+    let uv = vec2<f32>(
+        atan2(direction.z, direction.x) / (2.0 * PI) + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / PI,
+    );
+    return vec4<f32>(textureSample(t_sky, s_sky, uv).rgb, 1.0);
+}
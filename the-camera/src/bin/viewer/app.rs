@@ -0,0 +1,305 @@
+use std::{
+    io,
+    path::Path,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use the_camera::{
+    animation::{AnimationPlayer, AnimationPlayerHandle},
+    background::Background,
+    config::EngineConfig,
+    model::ModelData,
+    point_cloud::{PointCloud, PointCloudHandle},
+    render_engine::RenderEngine,
+    render_engine_builder::RenderEngineBuilder,
+    scene::MeshHandle,
+    texture::TextureData,
+    voxel_grid::{VoxelGrid, VoxelGridHandle},
+};
+use winit::{
+    application::ApplicationHandler,
+    dpi::PhysicalSize,
+    event::{DeviceEvent, DeviceId, WindowEvent},
+    event_loop::ActiveEventLoop,
+    keyboard::KeyCode,
+    window::{Window, WindowId},
+};
+
+use crate::{
+    assets::{
+        extension, named, open_point_cloud, read_model, read_panorama, read_voxel_grid,
+        show_panorama, MODEL_EXTENSIONS, POINT_CLOUD_EXTENSIONS,
+    },
+    options::Options,
+};
+
+/// A single window showing one model at a time
+pub struct Viewer {
+    options: Options,
+    config: EngineConfig,
+    /// Read before the window opened, so a bad path fails right away. Uploaded once the engine is there.
+    model: Option<ModelData>,
+    /// Instead of a model, opened right away for the same reason
+    point_cloud: Option<PointCloud>,
+    voxel_grid: Option<VoxelGrid>,
+    environment: Option<TextureData>,
+    window: Option<Arc<Window>>,
+    render_engine: Option<RenderEngine>,
+    /// The shown model's meshes, freed when another one replaces it
+    meshes: Vec<MeshHandle>,
+    /// Playing the shown model's animations
+    animation: Option<AnimationPlayerHandle>,
+    /// Shown in place of a model
+    shown_cloud: Option<PointCloudHandle>,
+    shown_grid: Option<VoxelGridHandle>,
+}
+
+impl Viewer {
+    pub fn new(options: Options, config: EngineConfig) -> io::Result<Self> {
+        let (mut model, mut point_cloud, mut voxel_grid) = (None, None, None);
+        if let Some(path) = options.model.as_deref() {
+            match extension(path) {
+                Some(cloud) if POINT_CLOUD_EXTENSIONS.contains(&cloud.as_str()) => {
+                    point_cloud = Some(open_point_cloud(path).map_err(|err| named(path, err))?);
+                }
+                Some(grid) if grid == "vxgr" => {
+                    voxel_grid = Some(read_voxel_grid(path).map_err(|err| named(path, err))?);
+                }
+                _ => model = Some(read_model(path).map_err(|err| named(path, err))?),
+            }
+        }
+        let environment = match options.environment.as_deref() {
+            Some(path) => Some(read_panorama(path).map_err(|err| named(path, err))?),
+            None => None,
+        };
+        Ok(Viewer {
+            options,
+            config,
+            model,
+            point_cloud,
+            voxel_grid,
+            environment,
+            window: None,
+            render_engine: None,
+            meshes: Vec::new(),
+            animation: None,
+            shown_cloud: None,
+            shown_grid: None,
+        })
+    }
+
+    fn open_window(&mut self, event_loop: &ActiveEventLoop) {
+        let transparent = self.options.background == Some(Background::Transparent);
+        let attributes = self
+            .config
+            .window_attributes()
+            .with_transparent(transparent);
+        let window = Arc::new(
+            event_loop
+                .create_window(attributes)
+                .expect("Failed to create window!"),
+        );
+        let mut render_engine = pollster::block_on(
+            RenderEngineBuilder::new()
+                .config(&self.config)
+                .build(window.clone()),
+        );
+
+        let input = render_engine.input_mut();
+        input.bind("exit", KeyCode::Escape);
+        input.bind("screenshot", KeyCode::F12);
+        if let Some(background) = self.options.background {
+            render_engine.set_background(background);
+        }
+        if let Some(environment) = self.environment.take() {
+            show_panorama(&mut render_engine, environment);
+        }
+        self.window = Some(window);
+        self.render_engine = Some(render_engine);
+        if let Some(model) = self.model.take() {
+            self.show_model(model);
+        }
+        if let Some(cloud) = self.point_cloud.take() {
+            self.show_point_cloud(cloud);
+        }
+        if let Some(grid) = self.voxel_grid.take() {
+            self.show_voxel_grid(grid);
+        }
+    }
+
+    /// Replaces what's shown and frames it
+    fn show_model(&mut self, model: ModelData) {
+        let bounds = model.bounds();
+        self.clear();
+        let Some(render_engine) = self.render_engine.as_mut() else {
+            return;
+        };
+        let player = (!model.animations.is_empty()).then(|| AnimationPlayer::new(&model));
+        let renderables = model.add_to(render_engine);
+        self.animation =
+            player.map(|player| render_engine.add_animation_player(player, &renderables));
+        self.meshes = renderables
+            .iter()
+            .map(|renderable| renderable.mesh)
+            .collect();
+        render_engine.set_renderables(renderables);
+        self.look_at(bounds);
+    }
+
+    /// Replaces what's shown and frames it by the file's bounds while it loads
+    fn show_point_cloud(&mut self, cloud: PointCloud) {
+        let bounds = cloud.bounds();
+        self.clear();
+        let Some(render_engine) = self.render_engine.as_mut() else {
+            return;
+        };
+        render_engine.set_renderables(Vec::new());
+        self.shown_cloud = Some(render_engine.add_point_cloud(cloud));
+        self.look_at(Some(bounds));
+    }
+
+    /// Replaces what's shown and frames it
+    fn show_voxel_grid(&mut self, grid: VoxelGrid) {
+        let bounds = grid.bounds();
+        self.clear();
+        let Some(render_engine) = self.render_engine.as_mut() else {
+            return;
+        };
+        render_engine.set_renderables(Vec::new());
+        self.shown_grid = Some(render_engine.add_voxel_grid(grid));
+        self.look_at(Some(bounds));
+    }
+
+    /// Frees what's shown
+    fn clear(&mut self) {
+        let Some(render_engine) = self.render_engine.as_mut() else {
+            return;
+        };
+        for mesh in self.meshes.drain(..) {
+            render_engine.remove_mesh(mesh);
+        }
+        if let Some(animation) = self.animation.take() {
+            render_engine.remove_animation_player(animation);
+        }
+        if let Some(cloud) = self.shown_cloud.take() {
+            render_engine.remove_point_cloud(cloud);
+        }
+        if let Some(grid) = self.shown_grid.take() {
+            render_engine.remove_voxel_grid(grid);
+        }
+    }
+
+    /// Turns the camera to the `--camera` view and frames the bounds
+    fn look_at(&mut self, bounds: Option<([f32; 3], [f32; 3])>) {
+        let (Some(render_engine), Some(window)) = (self.render_engine.as_mut(), &self.window)
+        else {
+            return;
+        };
+        if let Some(view) = self.options.camera {
+            let animate = render_engine.animates_standard_views();
+            render_engine.set_animate_standard_views(false);
+            render_engine.set_standard_view(window.id(), view);
+            render_engine.set_animate_standard_views(animate);
+        }
+        if let Some((min, max)) = bounds {
+            render_engine.frame_bounds(min, max);
+        }
+    }
+
+    /// Models, point clouds and voxel grids replace the shown one, panoramas the environment
+    fn load_dropped(&mut self, path: &Path) {
+        let result = match extension(path).as_deref() {
+            Some(model) if MODEL_EXTENSIONS.contains(&model) => {
+                read_model(path).map(|model| self.show_model(model))
+            }
+            Some(cloud) if POINT_CLOUD_EXTENSIONS.contains(&cloud) => {
+                open_point_cloud(path).map(|cloud| self.show_point_cloud(cloud))
+            }
+            Some("vxgr") => read_voxel_grid(path).map(|grid| self.show_voxel_grid(grid)),
+            Some("png" | "hdr") => read_panorama(path).map(|panorama| {
+                if let Some(render_engine) = self.render_engine.as_mut() {
+                    show_panorama(render_engine, panorama);
+                }
+            }),
+            _ => {
+                tracing::warn!("Don't know how to show {}", path.display());
+                return;
+            }
+        };
+        match result {
+            Ok(()) => tracing::info!("Showing {}", path.display()),
+            Err(err) => tracing::error!("Failed to load {}: {err}", path.display()),
+        }
+    }
+}
+
+impl ApplicationHandler for Viewer {
+    fn resumed(&mut self, event_loop: &ActiveEventLoop) {
+        match self.render_engine.as_mut() {
+            // Back from the background, the window is kept but needs a new surface
+            Some(render_engine) => render_engine.resume(),
+            None => self.open_window(event_loop),
+        }
+    }
+
+    fn suspended(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            render_engine.suspend();
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        window_id: WindowId,
+        event: WindowEvent,
+    ) {
+        let Some(render_engine) = self.render_engine.as_mut() else {
+            return;
+        };
+        render_engine.process_window_event(window_id, &event);
+        match event {
+            WindowEvent::DroppedFile(path) => self.load_dropped(&path),
+            WindowEvent::Resized(PhysicalSize { width, height }) => {
+                render_engine.resize(window_id, width, height);
+            }
+            WindowEvent::CloseRequested => event_loop.exit(),
+            WindowEvent::RedrawRequested => {
+                let input = render_engine.input();
+                if input.action_just_pressed("exit") {
+                    event_loop.exit();
+                }
+                if input.action_just_pressed("screenshot") {
+                    let timestamp = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs();
+                    let path = format!("screenshot-{timestamp}.png");
+                    tracing::info!("Saving the next frame to {path}");
+                    render_engine.capture_frame(window_id, path);
+                }
+                render_engine.update();
+                render_engine.render_frame(window_id);
+            }
+            _ => (),
+        }
+    }
+
+    fn device_event(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+        _device_id: DeviceId,
+        event: DeviceEvent,
+    ) {
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            render_engine.process_event(&event);
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(render_engine) = self.render_engine.as_mut() {
+            event_loop.set_control_flow(render_engine.poll_background());
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! Reading the files given on the command line or dropped onto the window
+
+use std::{io, path::Path};
+
+use the_camera::{
+    assets, background::Background, model::ModelData, point_cloud::PointCloud,
+    render_engine::RenderEngine, texture::TextureData, voxel_grid::VoxelGrid,
+};
+
+pub use assets::MODEL_EXTENSIONS;
+
+/// Extensions [open_point_cloud] knows, lowercase
+pub const POINT_CLOUD_EXTENSIONS: &[&str] = &["las", "laz"];
+
+/// A radiance field baked into a grid, see [the_camera::voxel_grid]
+pub fn read_voxel_grid(path: &Path) -> io::Result<VoxelGrid> {
+    VoxelGrid::decode(&std::fs::read(path)?)
+}
+
+/// An OBJ, glTF, USD or, with the `fbx` feature, FBX model
+pub fn read_model(path: &Path) -> io::Result<ModelData> {
+    assets::read_model(path)
+}
+
+/// A LAS or LAZ scan, read from the file while it's shown
+pub fn open_point_cloud(path: &Path) -> io::Result<PointCloud> {
+    PointCloud::open(path)
+}
+
+/// A Radiance HDR or PNG panorama
+pub fn read_panorama(path: &Path) -> io::Result<TextureData> {
+    assets::read_image(path)
+}
+
+/// Prefixes `err` with the path it's about, for errors reported on the command line
+pub fn named(path: &Path, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {err}", path.display()))
+}
+
+/// Lowercase, so `.OBJ` files load as well
+pub fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+}
+
+/// Makes the panorama the engine's skybox
+pub fn show_panorama(render_engine: &mut RenderEngine, panorama: TextureData) {
+    let texture = render_engine.add_texture(panorama.width, panorama.height, panorama.rgba);
+    render_engine.set_background(Background::Skybox(texture));
+}
@@ -0,0 +1,57 @@
+//! Opens a model from the command line in an interactive window or renders thumbnails of it, see
+//! [options::USAGE]. Unlike the demo app it only goes through the library's public API.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod app;
+#[cfg(not(target_arch = "wasm32"))]
+mod assets;
+#[cfg(not(target_arch = "wasm32"))]
+mod options;
+#[cfg(not(target_arch = "wasm32"))]
+mod thumbnails;
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    use the_camera::config::EngineConfig;
+    use winit::event_loop::{ControlFlow, EventLoop};
+
+    use crate::{app::Viewer, options::Options};
+
+    the_camera::logging::init();
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", options::USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{err}\n\n{}", options::USAGE);
+            std::process::exit(2);
+        }
+    };
+    let config = pollster::block_on(EngineConfig::load_or_default(options.config_path()));
+    if let Some(output) = &options.thumbnails {
+        if let Err(err) = thumbnails::render(&options, &config, output) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+    let mut viewer = match Viewer::new(options, config) {
+        Ok(viewer) => viewer,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+
+    let event_loop = EventLoop::new().expect("Failed to create event loop!");
+    event_loop.set_control_flow(ControlFlow::Wait);
+    if let Err(err) = event_loop.run_app(&mut viewer) {
+        tracing::error!("Event loop failed: {err}");
+    }
+}
+
+/// The browser has no command line, the web build runs the demo app from [the_camera::run]
+#[cfg(target_arch = "wasm32")]
+fn main() {}
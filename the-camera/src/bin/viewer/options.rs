@@ -0,0 +1,151 @@
+use std::path::PathBuf;
+
+use the_camera::{background::Background, camera::view_animation::StandardView, config};
+
+pub const USAGE: &str = "\
+Usage: viewer [MODEL] [OPTIONS]
+
+Opens an OBJ, glTF (.gltf, .glb) or USD (.usda, .usdz) model in a window to orbit around, or a binary FBX one
+when built with the fbx feature. LAS point clouds stream in while they're shown, LAZ ones too when built with the
+laz feature. Voxel grids (.vxgr) are raymarched. Models dropped onto the window replace it, PNG and HDR panoramas become the environment. F12 saves a
+screenshot and Escape quits.
+
+Options:
+    --background <COLOR>   #rrggbb, #rrggbbaa, gradient or transparent
+    --environment <PATH>   PNG or Radiance HDR panorama shown around the model, instead of the background
+    --camera <VIEW>        front, back, right, left, top, bottom or isometric
+    --config <PATH>        Engine settings, engine.toml by default
+    -h, --help             Prints this
+
+Thumbnails:
+    --thumbnails <DIR>     Renders the model from around it into DIR without opening a window
+    --count <N>            How many views, 8 by default
+    --size <PIXELS>        Width and height of each, 256 by default";
+
+/// What was passed on the command line
+#[derive(Debug, Default)]
+pub struct Options {
+    pub model: Option<PathBuf>,
+    pub background: Option<Background>,
+    pub environment: Option<PathBuf>,
+    /// Where the camera looks at the model from, again for every model dropped
+    pub camera: Option<StandardView>,
+    pub config: Option<String>,
+    /// Where to write thumbnails instead of opening a window
+    pub thumbnails: Option<PathBuf>,
+    pub thumbnail_count: Option<usize>,
+    pub thumbnail_size: Option<u32>,
+}
+
+impl Options {
+    /// None if the usage was asked for
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "--background" => options.background = Some(parse_background(&value()?)?),
+                "--environment" => options.environment = Some(value()?.into()),
+                "--camera" => options.camera = Some(value()?.parse()?),
+                "--config" => options.config = Some(value()?),
+                "--thumbnails" => options.thumbnails = Some(value()?.into()),
+                "--count" => options.thumbnail_count = Some(parse_positive(&arg, &value()?)?),
+                "--size" => options.thumbnail_size = Some(parse_positive(&arg, &value()?)?),
+                _ if arg.starts_with('-') => return Err(format!("Unknown option {arg}")),
+                _ if options.model.is_some() => return Err(format!("Unexpected argument {arg}")),
+                _ => options.model = Some(arg.into()),
+            }
+        }
+        let thumbnail_settings =
+            options.thumbnail_count.is_some() || options.thumbnail_size.is_some();
+        if thumbnail_settings && options.thumbnails.is_none() {
+            return Err("--count and --size only apply with --thumbnails".to_string());
+        }
+        Ok(Some(options))
+    }
+
+    pub fn config_path(&self) -> &str {
+        self.config
+            .as_deref()
+            .unwrap_or(config::DEFAULT_CONFIG_PATH)
+    }
+}
+
+fn parse_positive<T: std::str::FromStr + Default + PartialOrd>(
+    option: &str,
+    value: &str,
+) -> Result<T, String> {
+    match value.parse() {
+        Ok(number) if number > T::default() => Ok(number),
+        _ => Err(format!("{option} needs a positive number, not {value}")),
+    }
+}
+
+fn parse_background(value: &str) -> Result<Background, String> {
+    match value {
+        // The demo app's gradient
+        "gradient" => {
+            return Ok(Background::Gradient {
+                top: [0.35, 0.55, 0.8, 1.0],
+                bottom: [0.05, 0.05, 0.1, 1.0],
+            })
+        }
+        "transparent" => return Ok(Background::Transparent),
+        _ => (),
+    }
+    let invalid = || format!("Not a color: {value}");
+    let hex = value.strip_prefix('#').ok_or_else(invalid)?;
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let mut color = [1.0; 4];
+    for (channel, index) in color.iter_mut().zip((0..hex.len()).step_by(2)) {
+        let byte = u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| invalid())?;
+        *channel = byte as f32 / 255.0;
+    }
+    Ok(Background::Color(color))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Option<Options>, String> {
+        Options::parse(args.split_whitespace().map(str::to_string))
+    }
+
+    #[test]
+    fn parses_a_model_and_options() {
+        let options =
+            parse("bunny.obj --camera top --background #ff000080 --thumbnails out --count 4")
+                .unwrap()
+                .unwrap();
+        assert_eq!(options.model, Some("bunny.obj".into()));
+        assert_eq!(options.camera, Some(StandardView::Top));
+        assert_eq!(
+            options.background,
+            Some(Background::Color([1.0, 0.0, 0.0, 128.0 / 255.0]))
+        );
+        assert_eq!(options.thumbnails, Some("out".into()));
+        assert_eq!(options.thumbnail_count, Some(4));
+        assert_eq!(options.config_path(), config::DEFAULT_CONFIG_PATH);
+        assert!(parse("--help").unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        for args in [
+            "--camera",
+            "--camera diagonal",
+            "--background #ff00",
+            "--background red",
+            "--thumbnails out --size 0",
+            "--count 4",
+            "--verbose",
+            "a.obj b.obj",
+        ] {
+            assert!(parse(args).is_err(), "{args}");
+        }
+    }
+}
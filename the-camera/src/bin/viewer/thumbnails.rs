@@ -0,0 +1,123 @@
+//! `--thumbnails`: renders the model from several sides without a window and saves the images, e.g. for an asset
+//! browser's previews
+
+use std::{f32::consts::TAU, io, path::Path};
+
+use cgmath::Vector3;
+use the_camera::{
+    camera::{
+        camera_controller::CameraController,
+        orbit_camera::OrbitCamera,
+        view_animation::{orbit_angles, StandardView},
+    },
+    config::EngineConfig,
+    render_engine_builder::RenderEngineBuilder,
+    screenshot::write_png,
+};
+
+use crate::{
+    assets::{
+        extension, named, read_model, read_panorama, read_voxel_grid, show_panorama,
+        POINT_CLOUD_EXTENSIONS,
+    },
+    options::Options,
+};
+
+pub const DEFAULT_COUNT: usize = 8;
+/// Width and height in pixels
+pub const DEFAULT_SIZE: u32 = 256;
+
+/// Writes `<output>/<model name>-<index>.png` for every view, turning around the model from the `--camera` view or
+/// from the front, right and above
+pub fn render(options: &Options, config: &EngineConfig, output: &Path) -> io::Result<()> {
+    let model_path = options
+        .model
+        .as_deref()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "--thumbnails needs a model"))?;
+    // They stream in over many frames, a thumbnail would catch them half loaded
+    if extension(model_path).is_some_and(|model| POINT_CLOUD_EXTENSIONS.contains(&model.as_str())) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "--thumbnails doesn't render point clouds",
+        ));
+    }
+    let (model, grid) = match extension(model_path).as_deref() {
+        Some("vxgr") => (
+            None,
+            Some(read_voxel_grid(model_path).map_err(|err| named(model_path, err))?),
+        ),
+        _ => (
+            Some(read_model(model_path).map_err(|err| named(model_path, err))?),
+            None,
+        ),
+    };
+    let environment = match options.environment.as_deref() {
+        Some(path) => Some(read_panorama(path).map_err(|err| named(path, err))?),
+        None => None,
+    };
+    let count = options.thumbnail_count.unwrap_or(DEFAULT_COUNT);
+    let size = options.thumbnail_size.unwrap_or(DEFAULT_SIZE);
+    let name = model_path
+        .file_stem()
+        .map_or("thumbnail".into(), |stem| stem.to_string_lossy());
+
+    let mut engine = pollster::block_on(
+        RenderEngineBuilder::new()
+            .config(config)
+            .build_headless(wgpu::TextureFormat::Rgba8Unorm),
+    );
+    if let Some(background) = options.background {
+        engine.set_background(background);
+    }
+    if let Some(environment) = environment {
+        show_panorama(&mut engine, environment);
+    }
+    let mut bounds = None;
+    if let Some(model) = model {
+        bounds = model.bounds();
+        let renderables = model.add_to(&mut engine);
+        engine.set_renderables(renderables);
+    }
+    if let Some(grid) = grid {
+        bounds = Some(grid.bounds());
+        engine.add_voxel_grid(grid);
+    }
+
+    // Like the windows' cameras, with the config's settings
+    let (pitch, yaw) = orbit_angles(
+        options
+            .camera
+            .unwrap_or(StandardView::Isometric)
+            .direction(),
+    );
+    let mut camera = OrbitCamera::new(1.0, pitch, yaw, Vector3::new(0.0, 0.0, 0.0), 1.0);
+    config
+        .camera
+        .apply(&mut camera, &mut CameraController::new(0.0, 0.0));
+    if options.camera.is_some() {
+        camera.set_pitch(pitch);
+        camera.set_yaw(yaw);
+    }
+    if let Some((min, max)) = bounds {
+        camera.frame(min.into(), max.into());
+    }
+    let start_yaw = camera.yaw;
+    let target = engine.add_render_target(size, size, Some(camera));
+
+    std::fs::create_dir_all(output).map_err(|err| named(output, err))?;
+    for index in 0..count {
+        engine
+            .render_target_mut(target)
+            .and_then(|target| target.camera.as_mut())
+            .expect("Thumbnail target has a camera!")
+            .set_yaw(start_yaw + TAU * index as f32 / count as f32);
+        engine.update();
+        let rgba = engine
+            .read_render_target(target)
+            .ok_or_else(|| io::Error::other("Failed to read back a thumbnail"))?;
+        let path = output.join(format!("{name}-{index}.png"));
+        write_png(&path, size, size, &rgba).map_err(|err| named(&path, io::Error::other(err)))?;
+        tracing::info!("Wrote {}", path.display());
+    }
+    Ok(())
+}
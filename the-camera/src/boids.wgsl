@@ -0,0 +1,125 @@
+// One step of a flock, see boids/mod.rs. Reads the boids from one buffer and writes them steered and moved into the
+// other, the next step swaps them.
+
+// See boids/pass.rs
+struct Parameters {
+    bounds_min: vec3<f32>,
+    time_step: f32,
+    bounds_max: vec3<f32>,
+    bounded: u32,
+    count: u32,
+    separation_radius: f32,
+    alignment_radius: f32,
+    cohesion_radius: f32,
+    separation_weight: f32,
+    alignment_weight: f32,
+    cohesion_weight: f32,
+    min_speed: f32,
+    max_speed: f32,
+    max_steering: f32,
+    bounds_steering: f32,
+}
+@group(0) @binding(0)
+var<uniform> parameters: Parameters;
+
+struct Boid {
+    position: vec3<f32>,
+    velocity: vec3<f32>,
+}
+@group(0) @binding(1)
+var<storage, read> boids: array<Boid>;
+@group(0) @binding(2)
+var<storage, read_write> stepped: array<Boid>;
+
+// Threads and boids of a tile
+const WORKGROUP_SIZE: u32 = 256u;
+
+// Positions with one for boids and zero for padding, and velocities
+var<workgroup> tile_positions: array<vec4<f32>, WORKGROUP_SIZE>;
+var<workgroup> tile_velocities: array<vec3<f32>, WORKGROUP_SIZE>;
+
+// Steers from the velocity towards flying full speed along the direction, nothing for no direction
+fn steer_towards(direction: vec3<f32>, velocity: vec3<f32>) -> vec3<f32> {
+    let length2 = dot(direction, direction);
+    if length2 < 1e-12 {
+        return vec3<f32>(0.0);
+    }
+    return direction * inverseSqrt(length2) * parameters.max_speed - velocity;
+}
+
+// Looks at every other boid a tile of them in shared memory at a time
+@compute @workgroup_size(256)
+fn steer(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) thread: u32,
+) {
+    // Threads past the boids still load tiles for the others
+    let index = min(id.x, parameters.count - 1u);
+    var boid = boids[index];
+
+    var away = vec3<f32>(0.0);
+    var heading = vec3<f32>(0.0);
+    var center = vec3<f32>(0.0);
+    let separation2 = parameters.separation_radius * parameters.separation_radius;
+    let alignment2 = parameters.alignment_radius * parameters.alignment_radius;
+    let cohesion2 = parameters.cohesion_radius * parameters.cohesion_radius;
+    for (var start = 0u; start < parameters.count; start += WORKGROUP_SIZE) {
+        let other = start + thread;
+        if other < parameters.count {
+            tile_positions[thread] = vec4<f32>(boids[other].position, 1.0);
+            tile_velocities[thread] = boids[other].velocity;
+        } else {
+            tile_positions[thread] = vec4<f32>(0.0);
+            tile_velocities[thread] = vec3<f32>(0.0);
+        }
+        workgroupBarrier();
+        for (var offset = 0u; offset < WORKGROUP_SIZE; offset++) {
+            let neighbor = tile_positions[offset];
+            let to_other = neighbor.xyz - boid.position;
+            let distance2 = dot(to_other, to_other);
+            // The boid itself and padding
+            if neighbor.w == 0.0 || distance2 < 1e-12 {
+                continue;
+            }
+            // Closer boids push harder
+            if distance2 < separation2 {
+                away -= to_other / distance2;
+            }
+            if distance2 < alignment2 {
+                heading += tile_velocities[offset];
+            }
+            if distance2 < cohesion2 {
+                center += to_other;
+            }
+        }
+        workgroupBarrier();
+    }
+    if id.x >= parameters.count {
+        return;
+    }
+
+    // The mean heading and the way to the center only matter for their direction, so the sums do
+    var acceleration = steer_towards(away, boid.velocity) * parameters.separation_weight
+        + steer_towards(heading, boid.velocity) * parameters.alignment_weight
+        + steer_towards(center, boid.velocity) * parameters.cohesion_weight;
+    let steering = length(acceleration);
+    if steering > parameters.max_steering {
+        acceleration *= parameters.max_steering / steering;
+    }
+    if parameters.bounded != 0u {
+        let below = boid.position < parameters.bounds_min;
+        let above = boid.position > parameters.bounds_max;
+        acceleration += (select(vec3<f32>(0.0), vec3<f32>(1.0), below) - select(vec3<f32>(0.0), vec3<f32>(1.0), above))
+            * parameters.bounds_steering;
+    }
+
+    boid.velocity += acceleration * parameters.time_step;
+    let speed = length(boid.velocity);
+    if speed > 1e-6 {
+        boid.velocity *= clamp(speed, parameters.min_speed, parameters.max_speed) / speed;
+    } else {
+        boid.velocity = vec3<f32>(parameters.min_speed, 0.0, 0.0);
+    }
+    boid.position += boid.velocity * parameters.time_step;
+    stepped[id.x] = boid;
+}
@@ -0,0 +1,285 @@
+//! Flocks of boids steered on the GPU, after Reynolds, "Flocks, Herds, and Schools: A Distributed Behavioral Model".
+//!
+//! Every boid steers by three rules looking at the boids around it: separation away from those too close, alignment
+//! with the heading of its neighbors and cohesion towards their center. Each rule has its own radius and weight in
+//! [BoidParameters]. Boids also turn back into their [Flock::bounds] and keep their speed between a minimum and a
+//! maximum.
+//!
+//! The flock is kept in two storage buffers, each step reads the boids from one and writes them moved into the other,
+//! so no boid sees its neighbors half stepped. Neighbors are found by looking at every other boid a workgroup's worth
+//! at a time through shared memory like N-body simulations do, which is simple and fast enough for a few ten thousand
+//! boids. The main pass draws the boids as cones pointing along their velocity, instanced straight from the buffer
+//! that was written last.
+//!
+//! Devices without compute shaders or storage buffers in vertex shaders, e.g. WebGL2, can't steer flocks.
+
+mod pass;
+
+use std::f32::consts::PI;
+
+use wgpu::{CommandEncoder, Device, Queue, TextureFormat};
+
+pub(crate) use self::pass::BoidsPass;
+use crate::{
+    global_bindings::GlobalBindings, lazy_pass::LazyPass, render_engine_builder::DeviceReport,
+};
+
+/// One boid as it's stored on the GPU
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Boid {
+    pub position: [f32; 3],
+    _padding: f32,
+    pub velocity: [f32; 3],
+    _padding2: f32,
+}
+
+impl Boid {
+    pub fn new(position: [f32; 3], velocity: [f32; 3]) -> Self {
+        Boid {
+            position,
+            _padding: 0.0,
+            velocity,
+            _padding2: 0.0,
+        }
+    }
+}
+
+/// How boids steer, in world units and seconds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoidParameters {
+    /// Boids closer than this are steered away from
+    pub separation_radius: f32,
+    /// Boids closer than this are flown along with
+    pub alignment_radius: f32,
+    /// Boids closer than this are steered towards
+    pub cohesion_radius: f32,
+    pub separation_weight: f32,
+    pub alignment_weight: f32,
+    pub cohesion_weight: f32,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    /// Largest acceleration the rules add up to, lower turns slower
+    pub max_steering: f32,
+    /// Acceleration back into the bounds of boids that left them
+    pub bounds_steering: f32,
+}
+
+impl Default for BoidParameters {
+    fn default() -> Self {
+        BoidParameters {
+            separation_radius: 0.05,
+            alignment_radius: 0.15,
+            cohesion_radius: 0.2,
+            separation_weight: 1.5,
+            alignment_weight: 1.0,
+            cohesion_weight: 1.0,
+            min_speed: 0.3,
+            max_speed: 0.8,
+            max_steering: 2.0,
+            bounds_steering: 4.0,
+        }
+    }
+}
+
+/// Boids steered on the GPU and drawn into every window and render target, added with
+/// [crate::render_engine::RenderEngine::add_flock]
+#[derive(Debug, Clone)]
+pub struct Flock {
+    boids: Vec<Boid>,
+    /// Counts resets of the boids, which are uploaded again when it moved on
+    revision: u64,
+    pub parameters: BoidParameters,
+    /// Simulated seconds of each step
+    pub time_step: f32,
+    /// Steps run every frame
+    pub steps_per_frame: u32,
+    /// Box from the min to the max corner the boids turn back into, None to let them roam
+    pub bounds: Option<([f32; 3], [f32; 3])>,
+    /// Stops steering, the boids are still drawn where they are
+    pub paused: bool,
+    /// Length of the drawn cones in world units, they're a third as wide
+    pub size: f32,
+    pub color: [f32; 3],
+    pub visible: bool,
+}
+
+impl Flock {
+    pub fn new(boids: Vec<Boid>) -> Self {
+        Flock {
+            boids,
+            revision: 0,
+            parameters: BoidParameters::default(),
+            time_step: 1.0 / 60.0,
+            steps_per_frame: 1,
+            bounds: None,
+            paused: false,
+            size: 0.03,
+            color: [0.9, 0.75, 0.3],
+            visible: true,
+        }
+    }
+
+    /// `count` boids spread through the box from `min` to `max` and bounded by it, flying off in every direction at
+    /// the mean of the speed limits. Spread along low discrepancy sequences, so the flock starts the same every time.
+    pub fn scattered(count: usize, min: [f32; 3], max: [f32; 3]) -> Self {
+        let parameters = BoidParameters::default();
+        let speed = (parameters.min_speed + parameters.max_speed) * 0.5;
+        // Roberts' R3 sequence, the fractions of multiples of the inverse powers of the root of x^4 = x + 1
+        let phi = 1.220_744_1_f32;
+        let steps = [1.0 / phi, 1.0 / (phi * phi), 1.0 / (phi * phi * phi)];
+        let golden_angle = PI * (3.0 - 5f32.sqrt());
+        let boids = (0..count)
+            .map(|index| {
+                let position = std::array::from_fn(|axis| {
+                    let t = (0.5 + steps[axis] * index as f32).fract();
+                    min[axis] + (max[axis] - min[axis]) * t
+                });
+                // Headings on a Fibonacci sphere
+                let y = 1.0 - 2.0 * (index as f32 + 0.5) / count as f32;
+                let ring = (1.0 - y * y).sqrt();
+                let (sin, cos) = (index as f32 * golden_angle).sin_cos();
+                Boid::new(
+                    position,
+                    [ring * cos * speed, y * speed, ring * sin * speed],
+                )
+            })
+            .collect();
+        Flock::new(boids).with_bounds(min, max)
+    }
+
+    pub fn with_parameters(mut self, parameters: BoidParameters) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    pub fn with_time_step(mut self, time_step: f32) -> Self {
+        self.time_step = time_step;
+        self
+    }
+
+    pub fn with_steps_per_frame(mut self, steps_per_frame: u32) -> Self {
+        self.steps_per_frame = steps_per_frame;
+        self
+    }
+
+    pub fn with_bounds(mut self, min: [f32; 3], max: [f32; 3]) -> Self {
+        self.bounds = Some((min, max));
+        self
+    }
+
+    pub fn with_size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_color(mut self, color: [f32; 3]) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// The boids as they were last set, the GPU moves them on without reading them back
+    pub fn boids(&self) -> &[Boid] {
+        &self.boids
+    }
+
+    /// Starts over from `boids`, they're uploaded before the next step
+    pub fn set_boids(&mut self, boids: Vec<Boid>) {
+        self.boids = boids;
+        self.revision += 1;
+    }
+
+    /// Starts over from the boids as they were last set
+    pub fn reset(&mut self) {
+        self.revision += 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlockHandle(pub(crate) usize);
+
+/// The engine's flocks and the pass steering and drawing them, which is created with the first flock
+#[derive(Default)]
+pub(crate) struct Flocks {
+    /// Unsupported where the device can't steer flocks
+    pass: LazyPass<BoidsPass>,
+    /// None where a flock was removed
+    flocks: Vec<Option<Flock>>,
+}
+
+impl Flocks {
+    pub fn add(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        device_report: &DeviceReport,
+        flock: Flock,
+    ) -> FlockHandle {
+        if self
+            .pass
+            .get_or_create(|| BoidsPass::new(device, format, global_bindings, device_report))
+            .is_none()
+        {
+            tracing::warn!("Flocks need compute shaders and vertex storage, it isn't steered");
+        }
+        self.flocks.push(Some(flock));
+        FlockHandle(self.flocks.len() - 1)
+    }
+
+    pub fn get(&self, handle: FlockHandle) -> Option<&Flock> {
+        self.flocks.get(handle.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: FlockHandle) -> Option<&mut Flock> {
+        self.flocks.get_mut(handle.0)?.as_mut()
+    }
+
+    /// Frees the flock's boids on the GPU
+    pub fn remove(&mut self, handle: FlockHandle) -> Option<Flock> {
+        let flock = self.flocks.get_mut(handle.0)?.take()?;
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_flock(handle.0);
+        }
+        Some(flock)
+    }
+
+    /// Draws the boids in the main pass, None before the first flock and where the device can't steer them
+    pub fn pass(&self) -> Option<&BoidsPass> {
+        self.pass.get()
+    }
+
+    /// Whether there are flocks to steer in the compute phase
+    pub fn is_active(&self) -> bool {
+        self.pass.get().is_some() && self.flocks.iter().any(Option::is_some)
+    }
+
+    /// Records the steps of the flocks that aren't paused, true while any of them is flying
+    pub fn step(&mut self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder) -> bool {
+        let Some(pass) = self.pass.get_mut() else {
+            return false;
+        };
+        pass.step(device, queue, &self.flocks, encoder);
+        self.flocks
+            .iter()
+            .flatten()
+            .any(|flock| !flock.paused && !flock.boids().is_empty())
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.pass.get().map_or(0, BoidsPass::size_in_bytes)
+    }
+
+    /// Creates the pass again on a new device, the flocks start over from their boids as they were last set
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        device_report: &DeviceReport,
+    ) {
+        self.pass
+            .recreate(|| BoidsPass::new(device, format, global_bindings, device_report));
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::HashMap;
+
+use wgpu::{
+    BindGroup, Buffer, CommandEncoder, Device, Queue, RenderPass, RenderPipeline, TextureFormat,
+};
+
+use super::{Boid, Flock};
+use crate::{
+    global_bindings::GlobalBindings,
+    profiler::RenderStats,
+    render_engine_builder::DeviceReport,
+    texture,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        compute::{ComputeKernel, ComputeKernelBuilder, ComputePassBuilder},
+        uniform_buffer::UniformBuffer,
+    },
+};
+
+/// Has to match `WORKGROUP_SIZE` in boids.wgsl, the boids of a tile
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Has to match `SEGMENTS` in boids_draw.wgsl, two triangles for every segment of a cone
+const CONE_VERTICES: u32 = 8 * 6;
+
+/// Per flock parameters of the steps
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct FlockUBOContent {
+    bounds_min: [f32; 3],
+    time_step: f32,
+    bounds_max: [f32; 3],
+    bounded: u32,
+    count: u32,
+    separation_radius: f32,
+    alignment_radius: f32,
+    cohesion_radius: f32,
+    separation_weight: f32,
+    alignment_weight: f32,
+    cohesion_weight: f32,
+    min_speed: f32,
+    max_speed: f32,
+    max_steering: f32,
+    bounds_steering: f32,
+    _padding: f32,
+}
+
+crate::assert_uniform_layout!(FlockUBOContent {
+    bounds_min: ALIGN_VEC4,
+    time_step: ALIGN_SCALAR,
+    bounds_max: ALIGN_VEC4,
+    bounded: ALIGN_SCALAR,
+    count: ALIGN_SCALAR,
+    separation_radius: ALIGN_SCALAR,
+    alignment_radius: ALIGN_SCALAR,
+    cohesion_radius: ALIGN_SCALAR,
+    separation_weight: ALIGN_SCALAR,
+    alignment_weight: ALIGN_SCALAR,
+    cohesion_weight: ALIGN_SCALAR,
+    min_speed: ALIGN_SCALAR,
+    max_speed: ALIGN_SCALAR,
+    max_steering: ALIGN_SCALAR,
+    bounds_steering: ALIGN_SCALAR,
+});
+
+/// Per flock draw data, bound at group 1
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawUBOContent {
+    color: [f32; 3],
+    size: f32,
+}
+
+crate::assert_uniform_layout!(DrawUBOContent {
+    color: ALIGN_VEC4,
+    size: ALIGN_SCALAR,
+});
+
+struct GpuFlock {
+    parameters: UniformBuffer<FlockUBOContent>,
+    draw_ubo: UniformBuffer<DrawUBOContent>,
+    /// The boids before and after a step, swapped every step
+    boids: [Buffer; 2],
+    /// Reading from the boids of the same index and writing the other
+    step_bind_groups: [BindGroup; 2],
+    /// Drawing the boids of the same index
+    draw_bind_groups: [BindGroup; 2],
+    /// Index of the boids the last step wrote
+    current: usize,
+    count: u32,
+    /// [Flock::revision] of the uploaded boids
+    revision: u64,
+    visible: bool,
+}
+
+/// Steers and draws every flock, shared by every view
+pub(crate) struct BoidsPass {
+    steer: ComputeKernel,
+    step_bind_group_layout: BindGroupLayoutWithDesc,
+    draw_bind_group_layout: BindGroupLayoutWithDesc,
+    pipeline: RenderPipeline,
+    flocks: HashMap<usize, GpuFlock>,
+    /// Boid counts of the flocks too large for the device's storage buffers, so they're only reported once
+    too_large: HashMap<usize, usize>,
+}
+
+impl BoidsPass {
+    /// None where the device can't run the steps or draw what they write. `format` has to be the engine's swapchain
+    /// format, since the boids are drawn in the main pass.
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        device_report: &DeviceReport,
+    ) -> Option<Self> {
+        let required = wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::VERTEX_STORAGE;
+        if !device_report.downlevel_flags.contains(required) {
+            tracing::debug!("Flocks need compute shaders and vertex storage");
+            return None;
+        }
+        let _span = tracing::debug_span!("create_boids_pipelines").entered();
+
+        let step_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_types::uniform())
+            .named("parameters")
+            .next_binding_compute(binding_types::buffer(true))
+            .named("boids")
+            .next_binding_compute(binding_types::buffer(false))
+            .named("stepped")
+            .create(device, "Boids Step Bind Group");
+        let steer = ComputeKernelBuilder::new(include_str!("../boids.wgsl"))
+            .entry_point("steer")
+            .workgroup_size([WORKGROUP_SIZE, 1, 1])
+            .bind_group_layout(&step_bind_group_layout.layout)
+            .create(device, "boids steering");
+
+        let draw_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_rendering(binding_types::uniform())
+            .next_binding_vertex(binding_types::buffer(true))
+            .create(device, "Boids Bind Group");
+        let pipeline = create_pipeline(device, format, global_bindings, &draw_bind_group_layout);
+
+        Some(BoidsPass {
+            steer,
+            step_bind_group_layout,
+            draw_bind_group_layout,
+            pipeline,
+            flocks: HashMap::new(),
+            too_large: HashMap::new(),
+        })
+    }
+
+    /// Uploads whatever changed about the flocks and records the steps of those that aren't paused
+    pub fn step(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        flocks: &[Option<Flock>],
+        encoder: &mut CommandEncoder,
+    ) {
+        let limit = device.limits().max_storage_buffer_binding_size as u64;
+        let mut stepped = Vec::new();
+        for (index, flock) in flocks.iter().enumerate() {
+            let Some(flock) = flock else {
+                continue;
+            };
+            let count = flock.boids.len();
+            if count == 0 {
+                self.flocks.remove(&index);
+                continue;
+            }
+            if count as u64 * std::mem::size_of::<Boid>() as u64 > limit {
+                if self.too_large.insert(index, count) != Some(count) {
+                    tracing::error!(
+                        "Flock of {count} boids is larger than the device's storage buffers of {limit} bytes, it \
+                         isn't steered"
+                    );
+                }
+                self.flocks.remove(&index);
+                continue;
+            }
+
+            let stale = self
+                .flocks
+                .get(&index)
+                .is_none_or(|uploaded| uploaded.count != count as u32);
+            if stale {
+                let uploaded = self.upload(device, flock);
+                self.flocks.insert(index, uploaded);
+            }
+            let uploaded = self
+                .flocks
+                .get_mut(&index)
+                .expect("Flock was just uploaded!");
+            if uploaded.revision != flock.revision || stale {
+                queue.write_buffer(&uploaded.boids[0], 0, bytemuck::cast_slice(&flock.boids));
+                uploaded.current = 0;
+                uploaded.revision = flock.revision;
+            }
+            uploaded.visible = flock.visible;
+            uploaded.parameters.update_content(queue, parameters(flock));
+            uploaded.draw_ubo.update_content(
+                queue,
+                DrawUBOContent {
+                    color: flock.color,
+                    size: flock.size,
+                },
+            );
+            if !flock.paused && flock.steps_per_frame > 0 {
+                // Every step swaps the boids, so the draw picks the ones the last step writes
+                stepped.push((index, uploaded.current, flock.steps_per_frame));
+                uploaded.current = (uploaded.current + flock.steps_per_frame as usize) % 2;
+            }
+        }
+
+        let mut pass = ComputePassBuilder::new("boids");
+        for (index, first, steps) in stepped {
+            let uploaded = &self.flocks[&index];
+            let boids = [uploaded.count, 1, 1];
+            for step in 0..steps as usize {
+                let bind_group = &uploaded.step_bind_groups[(first + step) % 2];
+                pass = pass.dispatch(&self.steer, &[bind_group], boids);
+            }
+        }
+        pass.record(encoder);
+    }
+
+    /// The buffers of `flock`, its boids are written by the caller
+    fn upload(&self, device: &Device, flock: &Flock) -> GpuFlock {
+        let count = flock.boids.len() as u64;
+        let boids = ["Boids", "Stepped Boids"].map(|label| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size: count * std::mem::size_of::<Boid>() as u64,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        });
+
+        let parameters = UniformBuffer::new(device);
+        let draw_ubo = UniformBuffer::new(device);
+        let step_bind_groups = [0, 1].map(|read| {
+            BindGroupBuilder::new(&self.step_bind_group_layout)
+                .resource(parameters.binding_resource())
+                .buffer(&boids[read])
+                .buffer(&boids[1 - read])
+                .create(device, "Boids Step Bind Group")
+        });
+        let draw_bind_groups = [0, 1].map(|read| {
+            BindGroupBuilder::new(&self.draw_bind_group_layout)
+                .resource(draw_ubo.binding_resource())
+                .buffer(&boids[read])
+                .create(device, "Boids Bind Group")
+        });
+        GpuFlock {
+            parameters,
+            draw_ubo,
+            boids,
+            step_bind_groups,
+            draw_bind_groups,
+            current: 0,
+            count: count as u32,
+            revision: flock.revision,
+            visible: flock.visible,
+        }
+    }
+
+    pub fn remove_flock(&mut self, index: usize) {
+        self.flocks.remove(&index);
+        self.too_large.remove(&index);
+    }
+
+    /// Bytes of the boids before and after a step
+    pub fn size_in_bytes(&self) -> u64 {
+        self.flocks
+            .values()
+            .map(|flock| flock.boids[0].size() + flock.boids[1].size())
+            .sum()
+    }
+
+    /// Draws the boids where the last step left them, in the main pass
+    pub fn draw(&self, render_pass: &mut RenderPass, globals: &BindGroup, stats: &mut RenderStats) {
+        if !self.flocks.values().any(|flock| flock.visible) {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, globals, &[]);
+        stats.pipeline_switches += 1;
+        stats.bind_group_switches += 1;
+        for flock in self.flocks.values().filter(|flock| flock.visible) {
+            render_pass.set_bind_group(1, &flock.draw_bind_groups[flock.current], &[]);
+            render_pass.draw(0..CONE_VERTICES, 0..flock.count);
+            stats.bind_group_switches += 1;
+            stats.draw(CONE_VERTICES, flock.count);
+        }
+    }
+}
+
+fn parameters(flock: &Flock) -> FlockUBOContent {
+    let (bounds_min, bounds_max) = flock.bounds.unwrap_or_default();
+    let parameters = &flock.parameters;
+    FlockUBOContent {
+        bounds_min,
+        time_step: flock.time_step,
+        bounds_max,
+        bounded: flock.bounds.is_some() as u32,
+        count: flock.boids.len() as u32,
+        separation_radius: parameters.separation_radius,
+        alignment_radius: parameters.alignment_radius,
+        cohesion_radius: parameters.cohesion_radius,
+        separation_weight: parameters.separation_weight,
+        alignment_weight: parameters.alignment_weight,
+        cohesion_weight: parameters.cohesion_weight,
+        // Keeps the speed clamp defined when the limits are swapped
+        min_speed: parameters.min_speed.min(parameters.max_speed),
+        max_speed: parameters.max_speed,
+        max_steering: parameters.max_steering,
+        bounds_steering: parameters.bounds_steering,
+        _padding: 0.0,
+    }
+}
+
+/// Cones along the velocities, `CONE_VERTICES` for every boid instance
+fn create_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    global_bindings: &GlobalBindings,
+    bind_group_layout: &BindGroupLayoutWithDesc,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Boids Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../boids_draw.wgsl").into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Boids Pipeline Layout"),
+        bind_group_layouts: &[
+            global_bindings.bind_group_layouts(),
+            &bind_group_layout.layout,
+        ],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Boids Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // The shader doesn't keep the winding of the cones, they're closed anyway
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+            unclipped_depth: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: texture::Texture::ID_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
+            compilation_options: Default::default(),
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
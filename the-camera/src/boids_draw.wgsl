@@ -0,0 +1,119 @@
+// See clipping.rs, the same as in shader.wgsl
+struct Clipping {
+    planes: array<vec4<f32>, 4>,
+    cap_color: vec4<f32>,
+    count: u32,
+    caps: u32,
+}
+
+struct Audio {
+    bands: array<vec4<f32>, 4>,
+    level: f32,
+}
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    clipping: Clipping,
+    audio: Audio,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// One flock, see boids/pass.rs
+struct Draw {
+    color: vec3<f32>,
+    size: f32,
+}
+@group(1) @binding(0)
+var<uniform> draw: Draw;
+
+// The same as in boids.wgsl
+struct Boid {
+    position: vec3<f32>,
+    velocity: vec3<f32>,
+}
+@group(1) @binding(1)
+var<storage, read> boids: array<Boid>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+};
+
+// Has to match CONE_VERTICES in boids/pass.rs, which is six times this
+const SEGMENTS: u32 = 8u;
+const TAU: f32 = 6.28318531;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    let boid = boids[instance];
+    // Pointing along the velocity, with another up axis when flying straight up or down
+    var forward = vec3<f32>(1.0, 0.0, 0.0);
+    if dot(boid.velocity, boid.velocity) > 1e-12 {
+        forward = normalize(boid.velocity);
+    }
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(forward.y) > 0.99 {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let right = normalize(cross(forward, up));
+    up = cross(right, forward);
+
+    // Every segment is a triangle up the side to the tip and one of the base
+    let segment = index / 6u;
+    let corner = index % 6u;
+    let half_length = draw.size * 0.5;
+    let radius = draw.size / 6.0;
+    var edge = segment;
+    if corner == 1u || corner == 5u {
+        edge = segment + 1u;
+    }
+    let angle = f32(edge) / f32(SEGMENTS) * TAU;
+    let ring = right * cos(angle) + up * sin(angle);
+    // Centered on the boid
+    var local = ring * radius - forward * half_length;
+    var normal = normalize(ring * half_length * 2.0 + forward * radius);
+    if corner == 0u {
+        let middle = (f32(segment) + 0.5) / f32(SEGMENTS) * TAU;
+        local = forward * half_length;
+        normal = normalize((right * cos(middle) + up * sin(middle)) * half_length * 2.0 + forward * radius);
+    } else if corner == 3u {
+        local = -forward * half_length;
+    }
+    if corner >= 3u {
+        normal = -forward;
+    }
+
+    let world_position = boid.position + local;
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
+    out.normal = normal;
+    return out;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    // Boids can't be picked
+    @location(1) id: u32,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    for (var index = 0u; index < camera.clipping.count; index++) {
+        let plane = camera.clipping.planes[index];
+        if dot(plane.xyz, in.world_position) < plane.w {
+            discard;
+        }
+    }
+
+    // Lit from the camera
+    let to_camera = normalize(camera.view_pos.xyz - in.world_position);
+    let light = 0.35 + 0.65 * max(dot(normalize(in.normal), to_camera), 0.0);
+    var out: FragmentOutput;
+    out.color = vec4<f32>(draw.color * light, 1.0);
+    out.id = 0u;
+    return out;
+}
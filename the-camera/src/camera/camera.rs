@@ -16,6 +16,11 @@ pub struct CameraUniform {
     pub view_proj: [[f32; 4]; 4],
 }
 
+crate::assert_uniform_layout!(CameraUniform {
+    view_position: ALIGN_VEC4,
+    view_proj: ALIGN_VEC4,
+});
+
 impl Default for CameraUniform {
     /// Creates a default [CameraUniform].
     fn default() -> Self {
@@ -1,3 +1,4 @@
 pub mod camera;
 pub mod camera_controller;
 pub mod orbit_camera;
+pub mod view_animation;
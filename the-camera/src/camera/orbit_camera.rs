@@ -13,8 +13,28 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     0.0, 0.0, 0.0, 1.0,
 );
 
+/// Maps the depth of OpenGL's clip space from -1 to 1 onto wgpu's 0 to 1, for orthographic projections.
+#[rustfmt::skip]
+pub const OPENGL_TO_WGPU_DEPTH: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+/// How an [OrbitCamera] projects the scene onto the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Projection {
+    /// Things further away look smaller.
+    #[default]
+    Perspective,
+    /// Parallel lines stay parallel, for technical views. Shows as much of the scene at the target as the
+    /// perspective would, zooming changes how much. Skyboxes need a perspective to turn with the camera, so they
+    /// aren't drawn.
+    Orthographic,
+}
+
 /// An [OrbitCamera] only permits rotation of the eye on a spherical shell around a target.
-#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
 #[derive(Debug, Clone, Copy)]
 pub struct OrbitCamera {
     /// The distance of the eye from the target.
@@ -50,6 +70,9 @@ pub struct OrbitCamera {
     /// The far clipping plane of the camera.
     pub zfar: f32,
 
+    /// Perspective or orthographic.
+    pub projection: Projection,
+
     pub uniform: CameraUniform,
 }
 
@@ -58,8 +81,25 @@ impl Camera for OrbitCamera {
         let eye = Point3::from_vec(self.eye);
         let target = Point3::from_vec(self.target);
         let view = Matrix4::look_at_rh(eye, target, self.up);
-        let proj =
-            OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect, self.znear, self.zfar);
+        let proj = match self.projection {
+            Projection::Perspective => {
+                OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect, self.znear, self.zfar)
+            }
+            Projection::Orthographic => {
+                let half_height = self.view_height(self.target) / 2.0;
+                let half_width = half_height * self.aspect;
+                // Zooming in moves the eye, what it passes stays in view
+                OPENGL_TO_WGPU_DEPTH
+                    * ortho(
+                        -half_width,
+                        half_width,
+                        -half_height,
+                        half_height,
+                        -self.zfar,
+                        self.zfar,
+                    )
+            }
+        };
         proj * view
     }
 }
@@ -87,6 +127,7 @@ impl OrbitCamera {
             fovy: cgmath::Rad(std::f32::consts::PI / 4.0),
             znear: 0.1,
             zfar: 1000.0,
+            projection: Projection::default(),
             uniform: CameraUniform::default(),
         };
         camera.update();
@@ -114,7 +155,7 @@ impl OrbitCamera {
     pub fn add_distance(&mut self, delta: f32) {
         let corrected_zoom = f32::log10(self.distance) * delta;
         self.set_distance(self.distance + corrected_zoom);
-        println!("{:}", self.distance)
+        tracing::trace!(distance = self.distance, "Camera zoomed");
     }
 
     /// Sets the pitch of the [OrbitCamera].
@@ -162,6 +203,42 @@ impl OrbitCamera {
         self.set_yaw(self.yaw + delta);
     }
 
+    /// Looks at the center of a box from the current direction, just far enough away to see all of it.
+    ///
+    /// Arguments:
+    ///
+    /// * `min`: The corner of the box with the smallest coordinates.
+    /// * `max`: The corner of the box with the largest coordinates.
+    pub fn frame(&mut self, min: Vector3<f32>, max: Vector3<f32>) {
+        self.target = (min + max) / 2.0;
+        let radius = (max - min).magnitude() / 2.0;
+        // The narrower of the vertical and horizontal field of view decides
+        let half_fovy = self.fovy.0 / 2.0;
+        let half_fov = half_fovy.min((half_fovy.tan() * self.aspect).atan());
+        self.set_distance(radius / half_fov.sin());
+    }
+
+    /// Switches between [Projection::Perspective] and [Projection::Orthographic].
+    pub fn toggle_projection(&mut self) {
+        self.projection = match self.projection {
+            Projection::Perspective => Projection::Orthographic,
+            Projection::Orthographic => Projection::Perspective,
+        };
+    }
+
+    /// The height in world units of what the camera sees at `point`.
+    ///
+    /// Arguments:
+    ///
+    /// * `point`: In world space, only its distance from the eye matters and only with a perspective.
+    pub fn view_height(&self, point: Vector3<f32>) -> f32 {
+        let distance = match self.projection {
+            Projection::Perspective => (point - self.eye).magnitude(),
+            Projection::Orthographic => self.distance,
+        };
+        2.0 * distance * (self.fovy.0 / 2.0).tan()
+    }
+
     pub fn pan(&mut self, delta: (f32, f32)) {
         self.eye.y += delta.1 * self.distance;
         self.target.y += delta.1 * self.distance;
@@ -192,7 +269,6 @@ impl OrbitCamera {
 }
 
 /// The boundaries for how an [OrbitCamera] can be rotated.
-#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
 #[derive(Debug, Clone, Copy)]
 pub struct OrbitCameraBounds {
     /// The minimum distance between the eye and the target.
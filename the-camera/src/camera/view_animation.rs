@@ -0,0 +1,114 @@
+use std::f32::consts::{PI, TAU};
+use std::time::Duration;
+
+use cgmath::{InnerSpace, Vector3};
+
+use super::orbit_camera::OrbitCamera;
+
+/// How long the camera takes to turn to a new view.
+pub const VIEW_ANIMATION_DURATION: Duration = Duration::from_millis(300);
+
+/// The views CAD packages have hotkeys for, named after the side of the scene they look at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StandardView {
+    /// Looking along -z.
+    Front,
+    Back,
+    /// Looking along -x.
+    Right,
+    Left,
+    /// Looking down.
+    Top,
+    Bottom,
+    /// From the front, right and top at once.
+    Isometric,
+}
+
+impl StandardView {
+    /// Pointing from the target towards the eye.
+    pub fn direction(self) -> Vector3<f32> {
+        match self {
+            StandardView::Front => Vector3::unit_z(),
+            StandardView::Back => -Vector3::unit_z(),
+            StandardView::Right => Vector3::unit_x(),
+            StandardView::Left => -Vector3::unit_x(),
+            StandardView::Top => Vector3::unit_y(),
+            StandardView::Bottom => -Vector3::unit_y(),
+            StandardView::Isometric => Vector3::new(1.0, 1.0, 1.0).normalize(),
+        }
+    }
+}
+
+impl std::str::FromStr for StandardView {
+    type Err = String;
+
+    /// The lowercase name, e.g. `front` or `isometric`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(match name {
+            "front" => StandardView::Front,
+            "back" => StandardView::Back,
+            "right" => StandardView::Right,
+            "left" => StandardView::Left,
+            "top" => StandardView::Top,
+            "bottom" => StandardView::Bottom,
+            "isometric" => StandardView::Isometric,
+            _ => return Err(format!("Unknown view {name}")),
+        })
+    }
+}
+
+/// The pitch and yaw an [OrbitCamera] needs to look at its target from `direction`.
+///
+/// Arguments:
+///
+/// * `direction`: Pointing from the target towards the eye, doesn't need to be normalized.
+pub fn orbit_angles(direction: Vector3<f32>) -> (f32, f32) {
+    let direction = direction.normalize();
+    (
+        direction.y.clamp(-1.0, 1.0).asin(),
+        direction.x.atan2(direction.z),
+    )
+}
+
+/// Turns an [OrbitCamera] around its target from one view to another, easing in and out.
+#[derive(Debug, Clone, Copy)]
+pub struct ViewAnimation {
+    from: (f32, f32),
+    to: (f32, f32),
+    elapsed: Duration,
+    duration: Duration,
+}
+
+impl ViewAnimation {
+    /// Starts from where `camera` looks from now.
+    ///
+    /// Arguments:
+    ///
+    /// * `direction`: Pointing from the target towards the eye at the end, doesn't need to be normalized.
+    /// * `duration`: How long the turn takes.
+    pub fn new(camera: &OrbitCamera, direction: Vector3<f32>, duration: Duration) -> Self {
+        let (pitch, yaw) = orbit_angles(direction);
+        // The short way round
+        let turn = (yaw - camera.yaw + PI).rem_euclid(TAU) - PI;
+        ViewAnimation {
+            from: (camera.pitch, camera.yaw),
+            to: (pitch, camera.yaw + turn),
+            elapsed: Duration::ZERO,
+            duration,
+        }
+    }
+
+    /// Advances the animation by `delta` and turns `camera` to match. Returns false once it's finished.
+    pub fn update(&mut self, camera: &mut OrbitCamera, delta: Duration) -> bool {
+        self.elapsed = (self.elapsed + delta).min(self.duration);
+        let t = if self.duration.is_zero() {
+            1.0
+        } else {
+            self.elapsed.as_secs_f32() / self.duration.as_secs_f32()
+        };
+        let t = t * t * (3.0 - 2.0 * t);
+        camera.set_pitch(self.from.0 + (self.to.0 - self.from.0) * t);
+        camera.set_yaw(self.from.1 + (self.to.1 - self.from.1) * t);
+        self.elapsed < self.duration
+    }
+}
@@ -0,0 +1,88 @@
+//! Planes cutting the scene open, e.g. to look at the inside of a part. Everything on the far side of any plane is
+//! left out of every window and render target, see [crate::render_engine::RenderEngine::set_clip_planes].
+//!
+//! The planes are tested per pixel in the fragment shader. wgpu has no hardware clip distances yet, so clipped
+//! triangles still cost their rasterization. Closed meshes cut open show their back faces through the cut, with caps
+//! on those are filled in with a flat color so the cut reads as a solid section. Back faces are told apart by their
+//! winding, which has to be counter-clockwise seen from outside like in OBJ and glTF files.
+
+use cgmath::{InnerSpace, Vector3};
+
+/// Planes past this many are ignored
+pub const MAX_CLIP_PLANES: usize = 4;
+
+/// Keeps the side `normal` points to, every point with `dot(normal, point) >= distance`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClipPlane {
+    /// Unit length
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl ClipPlane {
+    /// `normal` doesn't need to be normalized
+    pub fn new(normal: Vector3<f32>, distance: f32) -> Self {
+        let length = normal.magnitude();
+        ClipPlane {
+            normal: normal / length,
+            distance: distance / length,
+        }
+    }
+
+    /// Through `point`, keeping the side `normal` points to
+    pub fn through(point: Vector3<f32>, normal: Vector3<f32>) -> Self {
+        let normal = normal.normalize();
+        ClipPlane {
+            normal,
+            distance: normal.dot(point),
+        }
+    }
+
+    /// The same plane keeping the other side
+    pub fn flipped(self) -> Self {
+        ClipPlane {
+            normal: -self.normal,
+            distance: -self.distance,
+        }
+    }
+
+    /// Positive on the kept side, in world units
+    pub fn signed_distance(&self, point: Vector3<f32>) -> f32 {
+        self.normal.dot(point) - self.distance
+    }
+}
+
+/// The clipping part of the global uniform buffer
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct ClipUniform {
+    /// Normal in xyz, distance in w
+    planes: [[f32; 4]; MAX_CLIP_PLANES],
+    cap_color: [f32; 4],
+    count: u32,
+    /// 1 to fill in cuts with `cap_color`
+    caps: u32,
+    _padding: [u32; 2],
+}
+
+crate::assert_uniform_layout!(ClipUniform {
+    planes: ALIGN_VEC4,
+    cap_color: ALIGN_VEC4,
+    count: ALIGN_SCALAR,
+    caps: ALIGN_SCALAR,
+});
+
+impl ClipUniform {
+    pub fn new(planes: &[ClipPlane], cap_color: Option<[f32; 4]>) -> Self {
+        let mut uniform = ClipUniform {
+            cap_color: cap_color.unwrap_or_default(),
+            caps: cap_color.is_some() as u32,
+            ..Default::default()
+        };
+        for (slot, plane) in uniform.planes.iter_mut().zip(planes) {
+            *slot = plane.normal.extend(plane.distance).into();
+            uniform.count += 1;
+        }
+        uniform
+    }
+}
@@ -0,0 +1,80 @@
+// See clipping.rs, the same as in shader.wgsl
+struct Clipping {
+    planes: array<vec4<f32>, 4>,
+    cap_color: vec4<f32>,
+    count: u32,
+    caps: u32,
+}
+
+struct Audio {
+    bands: array<vec4<f32>, 4>,
+    level: f32,
+}
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    clipping: Clipping,
+    audio: Audio,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// One cloth, see cloth/pass.rs
+struct Cloth {
+    color: vec4<f32>,
+}
+@group(1) @binding(0)
+var<uniform> cloth: Cloth;
+
+// Written by cloth_solve.wgsl, in world space
+struct Vertex {
+    position: vec4<f32>,
+    normal: vec4<f32>,
+}
+@group(1) @binding(1)
+var<storage, read> vertices: array<Vertex>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let vertex = vertices[index];
+    let world_position = vertex.position.xyz;
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
+    out.normal = vertex.normal.xyz;
+    return out;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    // Cloth can't be picked
+    @location(1) id: u32,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    for (var index = 0u; index < camera.clipping.count; index++) {
+        let plane = camera.clipping.planes[index];
+        if dot(plane.xyz, in.world_position) < plane.w {
+            discard;
+        }
+    }
+
+    // Lit from the camera on both sides like isosurfaces, collapsed vertices have no normal
+    let to_camera = normalize(camera.view_pos.xyz - in.world_position);
+    var light = 1.0;
+    if dot(in.normal, in.normal) > 0.0 {
+        light = 0.35 + 0.65 * abs(dot(normalize(in.normal), to_camera));
+    }
+    var out: FragmentOutput;
+    out.color = vec4<f32>(cloth.color.rgb * light, 1.0);
+    out.id = 0u;
+    return out;
+}
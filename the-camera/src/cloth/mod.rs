@@ -0,0 +1,266 @@
+//! Cloth sheets simulated on the GPU with position based dynamics, e.g. flags and curtains draping over the scene.
+//!
+//! A sheet is a grid of vertices kept apart by distance constraints to the neighbors along its edges and diagonals,
+//! with weaker bending constraints reaching two vertices over. Every substep moves the vertices along their velocity
+//! and gravity, then solves the constraints a few times over. The solve is Jacobi style: each vertex adds up the
+//! corrections of its own constraints into a second buffer, so no two threads write the same vertex. Vertices are
+//! pushed out of [ClothCollider]s after every iteration and [Cloth::pin]ned ones don't move at all.
+//!
+//! After the last substep the normals are recomputed from the neighbors of every vertex and written with the
+//! positions into a vertex storage buffer, which the main pass draws with vertex pulling like isosurfaces. The CPU
+//! never sees the moved vertices.
+//!
+//! Devices without compute shaders or storage buffers in vertex shaders, e.g. WebGL2, can't simulate cloth.
+
+mod pass;
+
+use wgpu::{CommandEncoder, Device, Queue, TextureFormat};
+
+pub(crate) use self::pass::ClothPass;
+use crate::{
+    global_bindings::GlobalBindings, lazy_pass::LazyPass, render_engine_builder::DeviceReport,
+};
+
+/// Colliders of each kind a cloth is pushed out of, the rest are ignored
+pub const MAX_COLLIDERS: usize = 8;
+
+/// What a cloth can't pass through, in world units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClothCollider {
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+    },
+    /// Keeps the cloth on the side the normal points to, where `dot(normal, point) >= distance`
+    Plane {
+        normal: [f32; 3],
+        distance: f32,
+    },
+}
+
+/// A sheet of cloth simulated on the GPU and drawn into every window and render target, added with
+/// [crate::render_engine::RenderEngine::add_cloth]
+#[derive(Debug, Clone)]
+pub struct Cloth {
+    /// Vertices along the two sides
+    size: [u32; 2],
+    /// Where the vertices start from, row by row
+    rest_positions: Vec<[f32; 3]>,
+    pinned: Vec<bool>,
+    /// Counts resets, which upload the rest positions again when it moved on
+    revision: u64,
+    /// Counts changes to the pins, which are uploaded again when it moved on
+    pins_revision: u64,
+    pub gravity: [f32; 3],
+    /// Simulated seconds of each frame
+    pub time_step: f32,
+    /// Steps each frame is split into, more keep fast cloth from stretching
+    pub substeps: u32,
+    /// Times the constraints are solved every substep
+    pub iterations: u32,
+    /// From 0 to 1, how much of the stretch along the edges and diagonals is undone every iteration
+    pub stiffness: f32,
+    /// From 0 to 1, the same for folding across two vertices
+    pub bending: f32,
+    /// Part of the velocity lost every substep
+    pub damping: f32,
+    /// Distance kept to the colliders, so the cloth doesn't sink into them between vertices
+    pub thickness: f32,
+    pub colliders: Vec<ClothCollider>,
+    /// Of both sides, drawn as it is like mesh vertex colors
+    pub color: [f32; 3],
+    /// Stops the simulation, the cloth is still drawn where it is
+    pub paused: bool,
+    pub visible: bool,
+}
+
+impl Cloth {
+    /// A flat sheet of `size` vertices from the `corner` vertex along `across` to the last one of the first row and
+    /// along `down` to the last one of the first column, which have to be at right angles. The sheet's spacing is
+    /// what the constraints keep.
+    pub fn new(size: [u32; 2], corner: [f32; 3], across: [f32; 3], down: [f32; 3]) -> Self {
+        assert!(
+            size.iter().all(|&size| size >= 2),
+            "Cloth needs at least two vertices along each side!"
+        );
+        let [columns, rows] = size;
+        let mut rest_positions = Vec::with_capacity(columns as usize * rows as usize);
+        for row in 0..rows {
+            for column in 0..columns {
+                let u = column as f32 / (columns - 1) as f32;
+                let v = row as f32 / (rows - 1) as f32;
+                rest_positions.push(std::array::from_fn(|axis| {
+                    corner[axis] + across[axis] * u + down[axis] * v
+                }));
+            }
+        }
+        Cloth {
+            size,
+            pinned: vec![false; rest_positions.len()],
+            rest_positions,
+            revision: 0,
+            pins_revision: 0,
+            gravity: [0.0, -9.81, 0.0],
+            time_step: 1.0 / 60.0,
+            substeps: 8,
+            iterations: 4,
+            stiffness: 1.0,
+            bending: 0.1,
+            damping: 0.01,
+            thickness: 0.01,
+            colliders: Vec::new(),
+            color: [0.7, 0.2, 0.2],
+            paused: false,
+            visible: true,
+        }
+    }
+
+    pub fn with_pins(mut self, pins: impl IntoIterator<Item = [u32; 2]>) -> Self {
+        for pin in pins {
+            self.pin(pin);
+        }
+        self
+    }
+
+    pub fn with_collider(mut self, collider: ClothCollider) -> Self {
+        self.colliders.push(collider);
+        self
+    }
+
+    pub fn with_gravity(mut self, gravity: [f32; 3]) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    pub fn with_stiffness(mut self, stiffness: f32, bending: f32) -> Self {
+        self.stiffness = stiffness;
+        self.bending = bending;
+        self
+    }
+
+    pub fn with_color(mut self, color: [f32; 3]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// Holds the vertex at `column` and `row` where it is
+    pub fn pin(&mut self, [column, row]: [u32; 2]) {
+        if let Some(index) = self.index(column, row) {
+            self.pinned[index] = true;
+            self.pins_revision += 1;
+        }
+    }
+
+    /// Lets the vertex fall again
+    pub fn unpin(&mut self, [column, row]: [u32; 2]) {
+        if let Some(index) = self.index(column, row) {
+            self.pinned[index] = false;
+            self.pins_revision += 1;
+        }
+    }
+
+    pub fn is_pinned(&self, [column, row]: [u32; 2]) -> bool {
+        self.index(column, row)
+            .is_some_and(|index| self.pinned[index])
+    }
+
+    /// Starts over from the flat sheet
+    pub fn reset(&mut self) {
+        self.revision += 1;
+    }
+
+    fn index(&self, column: u32, row: u32) -> Option<usize> {
+        (column < self.size[0] && row < self.size[1])
+            .then_some(row as usize * self.size[0] as usize + column as usize)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClothHandle(pub(crate) usize);
+
+/// The engine's cloths and the pass simulating and drawing them, which is created with the first cloth
+#[derive(Default)]
+pub(crate) struct Cloths {
+    /// Unsupported where the device can't simulate cloth
+    pass: LazyPass<ClothPass>,
+    /// None where a cloth was removed
+    cloths: Vec<Option<Cloth>>,
+}
+
+impl Cloths {
+    pub fn add(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        device_report: &DeviceReport,
+        cloth: Cloth,
+    ) -> ClothHandle {
+        if self
+            .pass
+            .get_or_create(|| ClothPass::new(device, format, global_bindings, device_report))
+            .is_none()
+        {
+            tracing::warn!("Cloth needs compute shaders and vertex storage, it isn't simulated");
+        }
+        self.cloths.push(Some(cloth));
+        ClothHandle(self.cloths.len() - 1)
+    }
+
+    pub fn get(&self, handle: ClothHandle) -> Option<&Cloth> {
+        self.cloths.get(handle.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: ClothHandle) -> Option<&mut Cloth> {
+        self.cloths.get_mut(handle.0)?.as_mut()
+    }
+
+    /// Frees the cloth's vertices on the GPU
+    pub fn remove(&mut self, handle: ClothHandle) -> Option<Cloth> {
+        let cloth = self.cloths.get_mut(handle.0)?.take()?;
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_cloth(handle.0);
+        }
+        Some(cloth)
+    }
+
+    /// Draws the cloths in the main pass, None before the first cloth and where the device can't simulate it
+    pub fn pass(&self) -> Option<&ClothPass> {
+        self.pass.get()
+    }
+
+    /// Whether there are cloths to simulate in the compute phase
+    pub fn is_active(&self) -> bool {
+        self.pass.get().is_some() && self.cloths.iter().any(Option::is_some)
+    }
+
+    /// Records the steps of the cloths, true while any of them isn't paused
+    pub fn step(&mut self, device: &Device, queue: &Queue, encoder: &mut CommandEncoder) -> bool {
+        let Some(pass) = self.pass.get_mut() else {
+            return false;
+        };
+        pass.step(device, queue, &self.cloths, encoder);
+        self.cloths.iter().flatten().any(|cloth| !cloth.paused)
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.pass.get().map_or(0, ClothPass::size_in_bytes)
+    }
+
+    /// Creates the pass again on a new device, the cloths start over from the flat sheet
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        device_report: &DeviceReport,
+    ) {
+        self.pass
+            .recreate(|| ClothPass::new(device, format, global_bindings, device_report));
+    }
+}
@@ -0,0 +1,492 @@
+use std::collections::HashMap;
+
+use wgpu::{
+    BindGroup, Buffer, CommandEncoder, Device, Queue, RenderPass, RenderPipeline, TextureFormat,
+};
+
+use super::{Cloth, ClothCollider, MAX_COLLIDERS};
+use crate::{
+    global_bindings::GlobalBindings,
+    profiler::RenderStats,
+    render_engine_builder::DeviceReport,
+    texture,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        compute::{ComputeKernel, ComputeKernelBuilder, ComputePassBuilder},
+        uniform_buffer::UniformBuffer,
+    },
+};
+
+/// Per cloth parameters of the substeps
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ClothUBOContent {
+    gravity: [f32; 3],
+    /// Of a substep
+    time_step: f32,
+    size: [u32; 2],
+    spacing: [f32; 2],
+    stiffness: f32,
+    bending: f32,
+    damping: f32,
+    thickness: f32,
+    sphere_count: u32,
+    plane_count: u32,
+    _padding: [u32; 2],
+    spheres: [[f32; 4]; MAX_COLLIDERS],
+    planes: [[f32; 4]; MAX_COLLIDERS],
+}
+
+crate::assert_uniform_layout!(ClothUBOContent {
+    gravity: ALIGN_VEC4,
+    time_step: ALIGN_SCALAR,
+    size: ALIGN_VEC2,
+    spacing: ALIGN_VEC2,
+    stiffness: ALIGN_SCALAR,
+    bending: ALIGN_SCALAR,
+    damping: ALIGN_SCALAR,
+    thickness: ALIGN_SCALAR,
+    sphere_count: ALIGN_SCALAR,
+    plane_count: ALIGN_SCALAR,
+    spheres: ALIGN_VEC4,
+    planes: ALIGN_VEC4,
+});
+
+/// Per cloth draw data, bound at group 1
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawUBOContent {
+    color: [f32; 4],
+}
+
+crate::assert_uniform_layout!(DrawUBOContent { color: ALIGN_VEC4 });
+
+/// Position and normal of a drawn vertex, both padded to 16 bytes for the storage buffer
+const VERTEX_SIZE: u64 = 32;
+
+struct GpuCloth {
+    ubo: UniformBuffer<ClothUBOContent>,
+    draw_ubo: UniformBuffer<DrawUBOContent>,
+    /// Positions and previous positions, 32 bytes a vertex
+    points: Buffer,
+    corrected: Buffer,
+    inverse_masses: Buffer,
+    vertices: Buffer,
+    indices: Buffer,
+    size: [u32; 2],
+    index_count: u32,
+    /// [Cloth::revision] and [Cloth::pins_revision] of what was uploaded
+    revision: u64,
+    pins_revision: u64,
+    visible: bool,
+    step_bind_group: BindGroup,
+    draw_bind_group: BindGroup,
+}
+
+/// Simulates and draws every cloth, shared by every view
+pub(crate) struct ClothPass {
+    predict: ComputeKernel,
+    solve: ComputeKernel,
+    apply: ComputeKernel,
+    normals: ComputeKernel,
+    step_bind_group_layout: BindGroupLayoutWithDesc,
+    draw_bind_group_layout: BindGroupLayoutWithDesc,
+    pipeline: RenderPipeline,
+    cloths: HashMap<usize, GpuCloth>,
+    /// Sizes of the cloths too large for the device's storage buffers, so they're only reported once
+    too_large: HashMap<usize, [u32; 2]>,
+}
+
+impl ClothPass {
+    /// None where the device can't run the solver or draw what it writes. `format` has to be the engine's swapchain
+    /// format, since the cloth is drawn in the main pass.
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        device_report: &DeviceReport,
+    ) -> Option<Self> {
+        let required = wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::VERTEX_STORAGE;
+        if !device_report.downlevel_flags.contains(required) {
+            tracing::debug!("Cloth needs compute shaders and vertex storage");
+            return None;
+        }
+        let _span = tracing::debug_span!("create_cloth_pipelines").entered();
+
+        let step_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_types::uniform())
+            .named("parameters")
+            .next_binding_compute(binding_types::buffer(false))
+            .named("points")
+            .next_binding_compute(binding_types::buffer(false))
+            .named("corrected")
+            .next_binding_compute(binding_types::buffer(true))
+            .named("inverse_masses")
+            .next_binding_compute(binding_types::buffer(false))
+            .named("vertices")
+            .create(device, "Cloth Step Bind Group");
+        let source = include_str!("../cloth_solve.wgsl");
+        let kernel = |entry_point, label| {
+            ComputeKernelBuilder::new(source)
+                .entry_point(entry_point)
+                .bind_group_layout(&step_bind_group_layout.layout)
+                .create(device, label)
+        };
+        let predict = kernel("predict", "cloth prediction");
+        let solve = kernel("solve", "cloth constraints");
+        let apply = kernel("apply", "cloth collisions");
+        let normals = kernel("normals", "cloth normals");
+
+        let draw_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_rendering(binding_types::uniform())
+            .next_binding_vertex(binding_types::buffer(true))
+            .create(device, "Cloth Bind Group");
+        let pipeline = create_pipeline(device, format, global_bindings, &draw_bind_group_layout);
+
+        Some(ClothPass {
+            predict,
+            solve,
+            apply,
+            normals,
+            step_bind_group_layout,
+            draw_bind_group_layout,
+            pipeline,
+            cloths: HashMap::new(),
+            too_large: HashMap::new(),
+        })
+    }
+
+    /// Uploads whatever changed about the cloths and records the substeps of those that aren't paused
+    pub fn step(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        cloths: &[Option<Cloth>],
+        encoder: &mut CommandEncoder,
+    ) {
+        let limit = device.limits().max_storage_buffer_binding_size as u64;
+        let mut stepped = Vec::new();
+        for (index, cloth) in cloths.iter().enumerate() {
+            let Some(cloth) = cloth else {
+                continue;
+            };
+            if cloth.rest_positions.len() as u64 * VERTEX_SIZE > limit {
+                if self.too_large.insert(index, cloth.size) != Some(cloth.size) {
+                    tracing::error!(
+                        "Cloth of {:?} vertices is larger than the device's storage buffers of {limit} bytes, it \
+                         isn't simulated",
+                        cloth.size
+                    );
+                }
+                self.cloths.remove(&index);
+                continue;
+            }
+
+            let stale = self
+                .cloths
+                .get(&index)
+                .is_none_or(|uploaded| uploaded.size != cloth.size);
+            if stale {
+                let uploaded = self.upload(device, cloth);
+                self.cloths.insert(index, uploaded);
+            }
+            let uploaded = self
+                .cloths
+                .get_mut(&index)
+                .expect("Cloth was just uploaded!");
+            let reset = uploaded.revision != cloth.revision || stale;
+            if reset {
+                write_points(queue, &uploaded.points, cloth);
+                uploaded.revision = cloth.revision;
+            }
+            if uploaded.pins_revision != cloth.pins_revision || stale {
+                write_inverse_masses(queue, &uploaded.inverse_masses, cloth);
+                uploaded.pins_revision = cloth.pins_revision;
+            }
+            uploaded.visible = cloth.visible;
+            uploaded.ubo.update_content(queue, parameters(cloth));
+            let [r, g, b] = cloth.color;
+            uploaded.draw_ubo.update_content(
+                queue,
+                DrawUBOContent {
+                    color: [r, g, b, 1.0],
+                },
+            );
+            // The vertices of new and reset cloth are written once even when it's paused
+            let substeps = if cloth.paused { 0 } else { cloth.substeps };
+            if substeps > 0 || reset {
+                stepped.push((index, substeps, cloth.iterations));
+            }
+        }
+
+        let mut pass = ComputePassBuilder::new("cloth");
+        for (index, substeps, iterations) in stepped {
+            let uploaded = &self.cloths[&index];
+            let bind_group = &uploaded.step_bind_group;
+            let vertices = [uploaded.size[0] * uploaded.size[1], 1, 1];
+            for _ in 0..substeps {
+                pass = pass.dispatch(&self.predict, &[bind_group], vertices);
+                for _ in 0..iterations {
+                    pass = pass
+                        .dispatch(&self.solve, &[bind_group], vertices)
+                        .dispatch(&self.apply, &[bind_group], vertices);
+                }
+            }
+            pass = pass.dispatch(&self.normals, &[bind_group], vertices);
+        }
+        pass.record(encoder);
+    }
+
+    /// The buffers of `cloth`, its points and pins are written by the caller
+    fn upload(&self, device: &Device, cloth: &Cloth) -> GpuCloth {
+        let count = cloth.rest_positions.len() as u64;
+        let buffer = |label, size, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: wgpu::BufferUsages::STORAGE | usage,
+                mapped_at_creation: false,
+            })
+        };
+        let points = buffer("Cloth Points", count * 32, wgpu::BufferUsages::COPY_DST);
+        let corrected = buffer(
+            "Cloth Corrected Points",
+            count * 16,
+            wgpu::BufferUsages::empty(),
+        );
+        let inverse_masses = buffer(
+            "Cloth Inverse Masses",
+            count * 4,
+            wgpu::BufferUsages::COPY_DST,
+        );
+        let vertices = buffer(
+            "Cloth Vertices",
+            count * VERTEX_SIZE,
+            wgpu::BufferUsages::empty(),
+        );
+
+        // Two triangles for every cell between four vertices
+        let [columns, rows] = cloth.size;
+        let mut triangles = Vec::with_capacity((columns - 1) as usize * (rows - 1) as usize * 6);
+        for row in 0..rows - 1 {
+            for column in 0..columns - 1 {
+                let corner = row * columns + column;
+                let [top_left, top_right] = [corner, corner + 1];
+                let [bottom_left, bottom_right] = [corner + columns, corner + columns + 1];
+                triangles.extend([top_left, top_right, bottom_left]);
+                triangles.extend([top_right, bottom_right, bottom_left]);
+            }
+        }
+        let indices = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Cloth Indices"),
+                contents: bytemuck::cast_slice(&triangles),
+                usage: wgpu::BufferUsages::INDEX,
+            },
+        );
+
+        let ubo = UniformBuffer::new(device);
+        let draw_ubo = UniformBuffer::new(device);
+        let step_bind_group = BindGroupBuilder::new(&self.step_bind_group_layout)
+            .resource(ubo.binding_resource())
+            .buffer(&points)
+            .buffer(&corrected)
+            .buffer(&inverse_masses)
+            .buffer(&vertices)
+            .create(device, "Cloth Step Bind Group");
+        let draw_bind_group = BindGroupBuilder::new(&self.draw_bind_group_layout)
+            .resource(draw_ubo.binding_resource())
+            .buffer(&vertices)
+            .create(device, "Cloth Bind Group");
+        GpuCloth {
+            ubo,
+            draw_ubo,
+            points,
+            corrected,
+            inverse_masses,
+            vertices,
+            indices,
+            size: cloth.size,
+            index_count: triangles.len() as u32,
+            revision: cloth.revision,
+            pins_revision: cloth.pins_revision,
+            visible: cloth.visible,
+            step_bind_group,
+            draw_bind_group,
+        }
+    }
+
+    pub fn remove_cloth(&mut self, index: usize) {
+        self.cloths.remove(&index);
+        self.too_large.remove(&index);
+    }
+
+    /// Bytes of the points, vertices and triangles
+    pub fn size_in_bytes(&self) -> u64 {
+        self.cloths
+            .values()
+            .map(|cloth| {
+                cloth.points.size()
+                    + cloth.corrected.size()
+                    + cloth.inverse_masses.size()
+                    + cloth.vertices.size()
+                    + cloth.indices.size()
+            })
+            .sum()
+    }
+
+    /// Draws the cloths where the last substeps left them, in the main pass
+    pub fn draw(&self, render_pass: &mut RenderPass, globals: &BindGroup, stats: &mut RenderStats) {
+        if !self.cloths.values().any(|cloth| cloth.visible) {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, globals, &[]);
+        stats.pipeline_switches += 1;
+        stats.bind_group_switches += 1;
+        for cloth in self.cloths.values().filter(|cloth| cloth.visible) {
+            render_pass.set_bind_group(1, &cloth.draw_bind_group, &[]);
+            render_pass.set_index_buffer(cloth.indices.slice(..), wgpu::IndexFormat::Uint32);
+            render_pass.draw_indexed(0..cloth.index_count, 0, 0..1);
+            stats.bind_group_switches += 1;
+            stats.draw(cloth.index_count, 1);
+        }
+    }
+}
+
+fn parameters(cloth: &Cloth) -> ClothUBOContent {
+    let [columns, rows] = cloth.size;
+    let distance = |a: [f32; 3], b: [f32; 3]| {
+        (0..3)
+            .map(|axis| (a[axis] - b[axis]).powi(2))
+            .sum::<f32>()
+            .sqrt()
+    };
+    let positions = &cloth.rest_positions;
+    let spacing = [
+        distance(positions[0], positions[1]),
+        distance(positions[0], positions[columns as usize]),
+    ];
+    let mut spheres = [[0.0; 4]; MAX_COLLIDERS];
+    let mut planes = [[0.0; 4]; MAX_COLLIDERS];
+    let (mut sphere_count, mut plane_count) = (0, 0);
+    for collider in &cloth.colliders {
+        match *collider {
+            ClothCollider::Sphere { center, radius } if sphere_count < MAX_COLLIDERS => {
+                spheres[sphere_count] = [center[0], center[1], center[2], radius];
+                sphere_count += 1;
+            }
+            ClothCollider::Plane { normal, distance } if plane_count < MAX_COLLIDERS => {
+                let length = (normal[0].powi(2) + normal[1].powi(2) + normal[2].powi(2)).sqrt();
+                let normal = normal.map(|value| value / length.max(1e-20));
+                planes[plane_count] = [normal[0], normal[1], normal[2], distance];
+                plane_count += 1;
+            }
+            _ => {}
+        }
+    }
+    ClothUBOContent {
+        gravity: cloth.gravity,
+        time_step: cloth.time_step / cloth.substeps.max(1) as f32,
+        size: [columns, rows],
+        spacing,
+        stiffness: cloth.stiffness,
+        bending: cloth.bending,
+        damping: cloth.damping,
+        thickness: cloth.thickness,
+        sphere_count: sphere_count as u32,
+        plane_count: plane_count as u32,
+        _padding: [0; 2],
+        spheres,
+        planes,
+    }
+}
+
+/// The rest positions, standing still
+fn write_points(queue: &Queue, buffer: &Buffer, cloth: &Cloth) {
+    let points: Vec<[f32; 8]> = cloth
+        .rest_positions
+        .iter()
+        .map(|&[x, y, z]| [x, y, z, 1.0, x, y, z, 1.0])
+        .collect();
+    queue.write_buffer(buffer, 0, bytemuck::cast_slice(&points));
+}
+
+fn write_inverse_masses(queue: &Queue, buffer: &Buffer, cloth: &Cloth) {
+    let inverse_masses: Vec<f32> = cloth
+        .pinned
+        .iter()
+        .map(|&pinned| if pinned { 0.0 } else { 1.0 })
+        .collect();
+    queue.write_buffer(buffer, 0, bytemuck::cast_slice(&inverse_masses));
+}
+
+/// Indexed triangles pulled from bind group 1, without vertex buffers
+fn create_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    global_bindings: &GlobalBindings,
+    bind_group_layout: &BindGroupLayoutWithDesc,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Cloth Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../cloth.wgsl").into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Cloth Pipeline Layout"),
+        bind_group_layouts: &[
+            global_bindings.bind_group_layouts(),
+            &bind_group_layout.layout,
+        ],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Cloth Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // Both sides of the sheet show
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+            unclipped_depth: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: texture::Texture::ID_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
+            compilation_options: Default::default(),
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
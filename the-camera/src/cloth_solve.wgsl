@@ -0,0 +1,185 @@
+// The substeps of a cloth, see cloth/mod.rs. Every substep predicts the positions, then solves and applies the
+// constraints a few times over. Normals runs once after the last substep.
+
+const MAX_COLLIDERS: u32 = 8u;
+
+// See cloth/pass.rs
+struct Parameters {
+    gravity: vec3<f32>,
+    time_step: f32,
+    // Vertices along the two sides
+    size: vec2<u32>,
+    // Rest distance between neighbors along the two sides
+    spacing: vec2<f32>,
+    stiffness: f32,
+    bending: f32,
+    damping: f32,
+    thickness: f32,
+    sphere_count: u32,
+    plane_count: u32,
+    // Center and radius
+    spheres: array<vec4<f32>, MAX_COLLIDERS>,
+    // Normal and distance
+    planes: array<vec4<f32>, MAX_COLLIDERS>,
+}
+@group(0) @binding(0)
+var<uniform> parameters: Parameters;
+
+// Where the vertex is and where it was a substep ago, which is its velocity
+struct Point {
+    position: vec4<f32>,
+    previous: vec4<f32>,
+}
+@group(0) @binding(1)
+var<storage, read_write> points: array<Point>;
+// Positions after an iteration's corrections, applied once every vertex was corrected
+@group(0) @binding(2)
+var<storage, read_write> corrected: array<vec4<f32>>;
+// Zero for pinned vertices
+@group(0) @binding(3)
+var<storage, read> inverse_masses: array<f32>;
+
+// Drawn by cloth.wgsl
+struct Vertex {
+    position: vec4<f32>,
+    normal: vec4<f32>,
+}
+@group(0) @binding(4)
+var<storage, read_write> vertices: array<Vertex>;
+
+// Neighbors along the sides, along the diagonals and two over for bending
+const NEIGHBORS = array<vec2<i32>, 12>(
+    vec2<i32>(1, 0),
+    vec2<i32>(-1, 0),
+    vec2<i32>(0, 1),
+    vec2<i32>(0, -1),
+    vec2<i32>(1, 1),
+    vec2<i32>(-1, -1),
+    vec2<i32>(1, -1),
+    vec2<i32>(-1, 1),
+    vec2<i32>(2, 0),
+    vec2<i32>(-2, 0),
+    vec2<i32>(0, 2),
+    vec2<i32>(0, -2),
+);
+// Jacobi corrections are averaged over the constraints of a vertex, this makes up for some of the lost speed
+const RELAXATION: f32 = 1.5;
+
+fn vertex_count() -> u32 {
+    return parameters.size.x * parameters.size.y;
+}
+
+// The index of the vertex `offset` away from `index`, or -1 past the sides
+fn neighbor(index: u32, offset: vec2<i32>) -> i32 {
+    let size = vec2<i32>(parameters.size);
+    let at = vec2<i32>(i32(index % parameters.size.x), i32(index / parameters.size.x)) + offset;
+    if any(at < vec2<i32>(0)) || any(at >= size) {
+        return -1;
+    }
+    return at.y * size.x + at.x;
+}
+
+@compute @workgroup_size(64)
+fn predict(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= vertex_count() {
+        return;
+    }
+    var point = points[id.x];
+    if inverse_masses[id.x] == 0.0 {
+        point.previous = point.position;
+    } else {
+        let dt = parameters.time_step;
+        let velocity = (point.position.xyz - point.previous.xyz) * (1.0 - parameters.damping);
+        point.previous = point.position;
+        point.position = vec4<f32>(point.position.xyz + velocity + parameters.gravity * dt * dt, 1.0);
+    }
+    points[id.x] = point;
+}
+
+@compute @workgroup_size(64)
+fn solve(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= vertex_count() {
+        return;
+    }
+    let position = points[id.x].position.xyz;
+    let w = inverse_masses[id.x];
+    if w == 0.0 {
+        corrected[id.x] = vec4<f32>(position, 1.0);
+        return;
+    }
+    var correction = vec3<f32>(0.0);
+    var constraints = 0.0;
+    for (var index = 0u; index < 12u; index++) {
+        let offset = NEIGHBORS[index];
+        let other = neighbor(id.x, offset);
+        if other < 0 {
+            continue;
+        }
+        let other_w = inverse_masses[other];
+        let to_other = points[other].position.xyz - position;
+        let distance = length(to_other);
+        if distance < 1e-9 {
+            continue;
+        }
+        let rest = length(vec2<f32>(offset) * parameters.spacing);
+        var stiffness = parameters.stiffness;
+        if index >= 8u {
+            stiffness = parameters.bending;
+        }
+        correction += to_other / distance * (distance - rest) * w / (w + other_w) * stiffness;
+        constraints += 1.0;
+    }
+    corrected[id.x] = vec4<f32>(position + correction * RELAXATION / max(constraints, 1.0), 1.0);
+}
+
+// Takes the corrections and pushes the vertices out of the colliders
+@compute @workgroup_size(64)
+fn apply(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= vertex_count() {
+        return;
+    }
+    var position = corrected[id.x].xyz;
+    if inverse_masses[id.x] != 0.0 {
+        for (var index = 0u; index < parameters.sphere_count; index++) {
+            let sphere = parameters.spheres[index];
+            let from_center = position - sphere.xyz;
+            let distance = length(from_center);
+            let radius = sphere.w + parameters.thickness;
+            if distance < radius && distance > 1e-9 {
+                position = sphere.xyz + from_center / distance * radius;
+            }
+        }
+        for (var index = 0u; index < parameters.plane_count; index++) {
+            let plane = parameters.planes[index];
+            let depth = plane.w + parameters.thickness - dot(plane.xyz, position);
+            if depth > 0.0 {
+                position += plane.xyz * depth;
+            }
+        }
+    }
+    points[id.x].position = vec4<f32>(position, 1.0);
+}
+
+// Normals across the neighbors on either side, vertices on the sides take themselves for the missing one
+fn position_or(index: i32, fallback: vec3<f32>) -> vec3<f32> {
+    if index < 0 {
+        return fallback;
+    }
+    return points[index].position.xyz;
+}
+
+@compute @workgroup_size(64)
+fn normals(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= vertex_count() {
+        return;
+    }
+    let position = points[id.x].position.xyz;
+    let along_row = position_or(neighbor(id.x, vec2<i32>(1, 0)), position)
+        - position_or(neighbor(id.x, vec2<i32>(-1, 0)), position);
+    let along_column = position_or(neighbor(id.x, vec2<i32>(0, 1)), position)
+        - position_or(neighbor(id.x, vec2<i32>(0, -1)), position);
+    var vertex: Vertex;
+    vertex.position = vec4<f32>(position, 1.0);
+    vertex.normal = vec4<f32>(cross(along_column, along_row), 0.0);
+    vertices[id.x] = vertex;
+}
@@ -0,0 +1,339 @@
+//! Colormaps turning scalar values into colors, for [crate::vector_field] glyphs and streamlines,
+//! [crate::isosurface::Slice]s, [crate::heatmap]s and [crate::simulation] particles.
+//!
+//! Viridis, magma, turbo and cool to warm are built in. Others are read from text or PNG lookup tables and added
+//! with [crate::render_engine::RenderEngine::add_colormap]. Every colormap is uploaded once as a 1D lookup texture
+//! when a renderer first uses it and shared by all of them, the renderers blend between its texels. It's a 2D
+//! texture one texel tall, since GLES and WebGL2 have no 1D textures.
+
+use std::io;
+
+use wgpu::{Device, Queue, Sampler, TextureView};
+
+/// Texels of the uploaded textures
+const RESOLUTION: u32 = 256;
+
+/// A colormap added to the engine or one of the built-in ones
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Colormap(pub(crate) usize);
+
+impl Colormap {
+    /// Perceptually uniform from dark blue over green to yellow
+    pub const VIRIDIS: Colormap = Colormap(0);
+    /// Perceptually uniform from black over purple and orange to pale yellow
+    pub const MAGMA: Colormap = Colormap(1);
+    /// Rainbow like from dark blue over green and yellow to dark red, with smoother steps than the rainbow
+    pub const TURBO: Colormap = Colormap(2);
+    /// Diverging from blue over light gray to red, for values around a midpoint
+    pub const COOL_WARM: Colormap = Colormap(3);
+}
+
+impl Default for Colormap {
+    fn default() -> Self {
+        Colormap::VIRIDIS
+    }
+}
+
+/// Colors spread evenly from the smallest to the largest value, sRGB encoded from 0 to 1 as colormaps are published
+/// and the engine draws colors
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColormapLut {
+    colors: Vec<[f32; 3]>,
+}
+
+impl ColormapLut {
+    pub fn new(colors: Vec<[f32; 3]>) -> Self {
+        assert!(!colors.is_empty(), "A colormap needs at least one color!");
+        ColormapLut { colors }
+    }
+
+    /// Reads a table of one color per line, as red, green and blue separated by spaces or commas. Numbers after the
+    /// third, empty lines and everything after a `#` are skipped. Tables with any number above 1 are taken to go up
+    /// to 255.
+    pub fn parse(source: &str) -> io::Result<Self> {
+        let invalid = |line: usize, message: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Colormap line {}: {message}", line + 1),
+            )
+        };
+
+        let mut colors = Vec::new();
+        for (line_index, line) in source.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let numbers = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|word| !word.is_empty())
+                .map(|word| {
+                    word.parse::<f32>()
+                        .map_err(|_| invalid(line_index, "expected a number"))
+                })
+                .collect::<io::Result<Vec<f32>>>()?;
+            match numbers[..] {
+                [] => continue,
+                [r, g, b, ..] => colors.push([r, g, b]),
+                _ => return Err(invalid(line_index, "a color needs three numbers")),
+            }
+        }
+        if colors.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Colormap has no colors",
+            ));
+        }
+        if colors.iter().flatten().any(|&value| value > 1.0) {
+            for value in colors.iter_mut().flatten() {
+                *value /= 255.0;
+            }
+        }
+        Ok(ColormapLut::new(colors))
+    }
+
+    /// Takes the colors from the top row of a PNG, from left to right
+    pub fn decode_png(bytes: &[u8]) -> io::Result<Self> {
+        let texture = crate::assets::decode_png(bytes)?;
+        if texture.width == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "PNG is empty"));
+        }
+        let colors = texture.rgba[..texture.width as usize * 4]
+            .chunks_exact(4)
+            .map(|rgba| [rgba[0], rgba[1], rgba[2]].map(|value| value as f32 / 255.0))
+            .collect();
+        Ok(ColormapLut::new(colors))
+    }
+
+    pub fn colors(&self) -> &[[f32; 3]] {
+        &self.colors
+    }
+
+    /// The sRGB color `t` of the way from the first to the last color, blending the two around it
+    pub fn sample(&self, t: f32) -> [f32; 3] {
+        let last = self.colors.len() - 1;
+        let position = t.clamp(0.0, 1.0) * last as f32;
+        let index = (position as usize).min(last.saturating_sub(1));
+        let (a, b) = (self.colors[index], self.colors[(index + 1).min(last)]);
+        let f = position - index as f32;
+        std::array::from_fn(|channel| a[channel] + (b[channel] - a[channel]) * f)
+    }
+
+    /// Samples a fit of a colormap, given as polynomial coefficients from the constant term up
+    fn from_polynomial(coefficients: &[[f32; 3]]) -> Self {
+        let colors = (0..RESOLUTION)
+            .map(|texel| {
+                let t = texel as f32 / (RESOLUTION - 1) as f32;
+                let color: [f32; 3] = std::array::from_fn(|channel| {
+                    coefficients
+                        .iter()
+                        .rev()
+                        .fold(0.0, |sum, coefficient| sum * t + coefficient[channel])
+                });
+                color.map(|value| value.clamp(0.0, 1.0))
+            })
+            .collect();
+        ColormapLut::new(colors)
+    }
+
+    /// The lookup tables behind [Colormap::VIRIDIS] and the other constants, in their order
+    fn built_in() -> Vec<ColormapLut> {
+        // Fits by Matt Zucker for viridis and magma and by Google for turbo
+        let viridis = ColormapLut::from_polynomial(&[
+            [0.277_727_33, 0.005_407_344_5, 0.334_099_8],
+            [0.105_093_04, 1.404_613_5, 1.384_590_2],
+            [-0.330_861_83, 0.214_847_56, 0.095_095_16],
+            [-4.634_230_6, -5.799_101, -19.332_441],
+            [6.228_27, 14.179_933, 56.690_55],
+            [4.776_385, -13.745_146, -65.353_03],
+            [-5.435_456, 4.645_852_6, 26.312_435],
+        ]);
+        let magma = ColormapLut::from_polynomial(&[
+            [-0.002_136_485, -0.000_749_655, -0.005_386_128],
+            [0.251_660_54, 0.677_523_2, 2.494_026_6],
+            [8.353_717, -3.577_719_4, 0.314_467_9],
+            [-27.668_733, 14.264_731, -13.649_213],
+            [52.176_14, -27.943_607, 12.944_169],
+            [-50.768_524, 29.046_583, 4.234_153],
+            [18.655_705, -11.489_774, -5.601_961_5],
+        ]);
+        let turbo = ColormapLut::from_polynomial(&[
+            [0.135_721_38, 0.091_402_61, 0.106_673_3],
+            [4.615_392_6, 2.194_188_4, 12.641_946],
+            [-42.660_324, 4.842_966_6, -60.582_047],
+            [132.131_08, -14.185_033, 110.362_77],
+            [-152.942_4, 4.277_299, -89.903_11],
+            [59.286_38, 2.829_566, 27.348_25],
+        ]);
+        // Kenneth Moreland's diverging map, sampled at every eighth
+        let cool_warm = ColormapLut::new(
+            [
+                [59, 76, 192],
+                [98, 130, 234],
+                [141, 176, 254],
+                [184, 208, 249],
+                [221, 221, 221],
+                [245, 196, 173],
+                [244, 154, 123],
+                [222, 96, 77],
+                [180, 4, 38],
+            ]
+            .iter()
+            .map(|color| color.map(|value: u8| value as f32 / 255.0))
+            .collect(),
+        );
+        vec![viridis, magma, turbo, cool_warm]
+    }
+}
+
+/// A colormap's texture and the sampler blending its texels, bound next to each other
+pub(crate) type ColormapBinding<'a> = (&'a TextureView, &'a Sampler);
+
+/// Every colormap of an engine and their textures, uploaded when they're first bound
+pub(crate) struct ColormapTextures {
+    luts: Vec<ColormapLut>,
+    textures: Vec<Option<TextureView>>,
+    sampler: Sampler,
+}
+
+impl ColormapTextures {
+    pub fn new(device: &Device) -> Self {
+        let luts = ColormapLut::built_in();
+        ColormapTextures {
+            textures: luts.iter().map(|_| None).collect(),
+            luts,
+            sampler: create_sampler(device),
+        }
+    }
+
+    pub fn add(&mut self, lut: ColormapLut) -> Colormap {
+        self.luts.push(lut);
+        self.textures.push(None);
+        Colormap(self.luts.len() - 1)
+    }
+
+    pub fn lut(&self, colormap: Colormap) -> Option<&ColormapLut> {
+        self.luts.get(colormap.0)
+    }
+
+    /// Uploads the texture of `colormap` first if it isn't yet. Colormaps of other engines fall back to viridis.
+    pub fn binding(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        colormap: Colormap,
+    ) -> ColormapBinding<'_> {
+        let index = if colormap.0 < self.luts.len() {
+            colormap.0
+        } else {
+            tracing::warn!("Colormap {} isn't known, using viridis", colormap.0);
+            Colormap::VIRIDIS.0
+        };
+        let lut = &self.luts[index];
+        let view = self.textures[index].get_or_insert_with(|| upload(device, queue, lut));
+        (view, &self.sampler)
+    }
+
+    /// Forgets the textures of a lost device, they're uploaded again when they're next bound
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(&mut self, device: &Device) {
+        self.textures.iter_mut().for_each(|texture| *texture = None);
+        self.sampler = create_sampler(device);
+    }
+}
+
+fn create_sampler(device: &Device) -> Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Colormap Sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+/// Blended between texels as encoded, like the published tables are
+fn upload(device: &Device, queue: &Queue, lut: &ColormapLut) -> TextureView {
+    let texels: Vec<u8> = (0..RESOLUTION)
+        .flat_map(|texel| {
+            let [r, g, b] = lut
+                .sample(texel as f32 / (RESOLUTION - 1) as f32)
+                .map(|value| (value * 255.0).round() as u8);
+            [r, g, b, 255]
+        })
+        .collect();
+    let texture = wgpu::util::DeviceExt::create_texture_with_data(
+        device,
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("Colormap"),
+            size: wgpu::Extent3d {
+                width: RESOLUTION,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        &texels,
+    );
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tables_from_0_to_1_and_to_255() {
+        let lut = ColormapLut::parse("# From black to red\n0, 0, 0\n\n1 0 0 1 # alpha\n").unwrap();
+        assert_eq!(lut.colors(), [[0.0; 3], [1.0, 0.0, 0.0]]);
+        let lut = ColormapLut::parse("0 0 0\n255 51 0\n").unwrap();
+        assert_eq!(lut.colors(), [[0.0; 3], [1.0, 0.2, 0.0]]);
+    }
+
+    #[test]
+    fn rejects_malformed_tables() {
+        assert!(ColormapLut::parse("# Nothing\n").is_err());
+        assert!(ColormapLut::parse("0 0\n").is_err());
+        let err = ColormapLut::parse("0 0 0\n0 red 0\n").unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{err}");
+    }
+
+    #[test]
+    fn samples_between_colors() {
+        let lut = ColormapLut::new(vec![[0.0; 3], [1.0, 0.5, 0.0], [1.0; 3]]);
+        assert_eq!(lut.sample(0.0), [0.0; 3]);
+        assert_eq!(lut.sample(0.25), [0.5, 0.25, 0.0]);
+        assert_eq!(lut.sample(1.0), [1.0; 3]);
+        // Out of range values are clamped
+        assert_eq!(lut.sample(-1.0), [0.0; 3]);
+        assert_eq!(lut.sample(2.0), [1.0; 3]);
+        assert_eq!(ColormapLut::new(vec![[0.5; 3]]).sample(0.7), [0.5; 3]);
+    }
+
+    #[test]
+    fn reads_the_top_row_of_pngs() {
+        let texture = crate::texture::TextureData {
+            width: 2,
+            height: 2,
+            rgba: vec![0, 0, 0, 255, 255, 0, 0, 255, 9, 9, 9, 255, 9, 9, 9, 255],
+        };
+        let png = crate::assets::encode_png(&texture).unwrap();
+        let lut = ColormapLut::decode_png(&png).unwrap();
+        assert_eq!(lut.colors(), [[0.0; 3], [1.0, 0.0, 0.0]]);
+    }
+
+    #[test]
+    fn built_in_colormaps_stay_in_range() {
+        for lut in ColormapLut::built_in() {
+            assert!(lut
+                .colors()
+                .iter()
+                .flatten()
+                .all(|value| (0.0..=1.0).contains(value)));
+        }
+    }
+}
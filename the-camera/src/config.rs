@@ -0,0 +1,239 @@
+//! Settings read from an optional `engine.toml` at startup, so apps don't have to hard-code them.
+//!
+//! Every setting is optional and unset ones keep the engine's defaults. Code can still override anything after
+//! loading, either by changing the fields or by calling the [RenderEngineBuilder] methods after
+//! [RenderEngineBuilder::config].
+//!
+//! ```toml
+//! [window]
+//! title = "Viewer"
+//! width = 1280
+//! height = 720
+//!
+//! [graphics]
+//! vsync = false
+//! frame_latency = 1
+//! fps_cap = 144
+//! backend = "vulkan"
+//! power_preference = "high-performance"
+//! gpu_profiling = true
+//!
+//! [assets]
+//! root = "assets"
+//!
+//! [jobs]
+//! threads = 4
+//!
+//! [camera]
+//! distance = 3.0
+//! pitch = 0.3
+//! fov = 60.0
+//! ```
+//!
+//! [RenderEngineBuilder]: crate::render_engine_builder::RenderEngineBuilder
+//! [RenderEngineBuilder::config]: crate::render_engine_builder::RenderEngineBuilder::config
+
+use std::io;
+
+use cgmath::{Deg, Vector3};
+use serde::Deserialize;
+use winit::{dpi::LogicalSize, window::WindowAttributes};
+
+use crate::{
+    assets,
+    camera::{
+        camera_controller::CameraController,
+        orbit_camera::{OrbitCamera, Projection},
+    },
+};
+
+/// Where the demo looks for its config, relative to the working directory or the page
+pub const DEFAULT_CONFIG_PATH: &str = "engine.toml";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    pub window: WindowConfig,
+    pub graphics: GraphicsConfig,
+    pub assets: AssetConfig,
+    pub jobs: JobConfig,
+    pub camera: CameraConfig,
+}
+
+/// Used for windows created from [EngineConfig::window_attributes]
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowConfig {
+    pub title: Option<String>,
+    /// Logical size, scaled by the display's DPI factor
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GraphicsConfig {
+    /// Off presents as soon as a frame is done, which can tear
+    pub vsync: Option<bool>,
+    /// See [crate::viewport::SurfaceOptions::frame_latency]
+    pub frame_latency: Option<u32>,
+    /// Not supported by the engine yet, anything above 1 is ignored with a warning
+    pub msaa_samples: Option<u32>,
+    pub fps_cap: Option<f32>,
+    /// Only this backend is considered for the adapter
+    pub backend: Option<Backend>,
+    pub power_preference: Option<PowerPreference>,
+    /// See [crate::render_engine_builder::RenderEngineBuilder::adapter_name]
+    pub adapter: Option<String>,
+    /// See [crate::render_engine_builder::RenderEngineBuilder::gpu_profiling]
+    pub gpu_profiling: Option<bool>,
+    /// See [crate::render_engine_builder::RenderEngineBuilder::vertex_pulling]
+    pub vertex_pulling: Option<bool>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+    /// WebGPU in the browser
+    WebGpu,
+}
+
+impl Backend {
+    pub fn backends(self) -> wgpu::Backends {
+        match self {
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Gl => wgpu::Backends::GL,
+            Backend::WebGpu => wgpu::Backends::BROWSER_WEBGPU,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerPreference {
+    LowPower,
+    HighPerformance,
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(power_preference: PowerPreference) -> Self {
+        match power_preference {
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AssetConfig {
+    /// Prefixed to relative asset paths by [EngineConfig::asset_path]
+    pub root: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JobConfig {
+    /// See [crate::render_engine_builder::RenderEngineBuilder::job_threads]
+    pub threads: Option<usize>,
+}
+
+/// How the camera of every new window starts out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraConfig {
+    pub distance: Option<f32>,
+    /// In radians
+    pub pitch: Option<f32>,
+    /// In radians
+    pub yaw: Option<f32>,
+    pub target: Option<[f32; 3]>,
+    /// Vertical field of view in degrees
+    pub fov: Option<f32>,
+    pub rotate_speed: Option<f32>,
+    pub zoom_speed: Option<f32>,
+    /// Parallel projection instead of perspective
+    pub orthographic: Option<bool>,
+}
+
+impl CameraConfig {
+    /// Overwrites everything that is set in the config
+    pub fn apply(&self, camera: &mut OrbitCamera, controller: &mut CameraController) {
+        if let Some(target) = self.target {
+            camera.target = Vector3::from(target);
+        }
+        if let Some(fov) = self.fov {
+            camera.fovy = Deg(fov).into();
+        }
+        if let Some(orthographic) = self.orthographic {
+            camera.projection = if orthographic {
+                Projection::Orthographic
+            } else {
+                Projection::Perspective
+            };
+        }
+        // Going through the setters keeps the camera within its bounds and moves the eye along
+        camera.set_distance(self.distance.unwrap_or(camera.distance));
+        camera.set_pitch(self.pitch.unwrap_or(camera.pitch));
+        camera.set_yaw(self.yaw.unwrap_or(camera.yaw));
+
+        if let Some(rotate_speed) = self.rotate_speed {
+            controller.rotate_speed = rotate_speed;
+        }
+        if let Some(zoom_speed) = self.zoom_speed {
+            controller.zoom_speed = zoom_speed;
+        }
+    }
+}
+
+impl EngineConfig {
+    pub fn from_toml(source: &str) -> io::Result<Self> {
+        toml::from_str(source).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Reads the config from disk natively, fetches it relative to the page on the web
+    pub async fn load(path: &str) -> io::Result<Self> {
+        Self::from_toml(&assets::load_string(path).await?)
+    }
+
+    /// The defaults when there is no config at `path`. A config that can't be read is reported and skipped as well,
+    /// so a typo doesn't keep the app from starting.
+    pub async fn load_or_default(path: &str) -> Self {
+        match Self::load(path).await {
+            Ok(config) => config,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(err) => {
+                tracing::warn!("Ignoring {path}: {err}");
+                Self::default()
+            }
+        }
+    }
+
+    /// Window title and size from the config, the rest is left to winit
+    pub fn window_attributes(&self) -> WindowAttributes {
+        let mut attributes = WindowAttributes::default();
+        if let Some(title) = &self.window.title {
+            attributes = attributes.with_title(title);
+        }
+        if let (Some(width), Some(height)) = (self.window.width, self.window.height) {
+            attributes = attributes.with_inner_size(LogicalSize::new(width, height));
+        }
+        attributes
+    }
+
+    /// `path` below the configured asset root, unchanged if there is none or `path` is absolute
+    pub fn asset_path(&self, path: &str) -> String {
+        match &self.assets.root {
+            Some(root) if !path.starts_with('/') => {
+                format!("{}/{path}", root.trim_end_matches('/'))
+            }
+            _ => path.to_owned(),
+        }
+    }
+}
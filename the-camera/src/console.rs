@@ -0,0 +1,717 @@
+//! An in-app console for poking at the engine while it runs, opened and closed with the backtick key. It's drawn
+//! along the bottom of every window in the debug HUD's font and takes all key presses while open.
+//!
+//! A line is split into words at whitespace, double quotes keep a word with spaces together, e.g.
+//! `load "my model.obj"`. The first word names the command, the others are its arguments. The engine registers these
+//! itself:
+//!
+//! * `help [command]`: Lists the commands, or explains one.
+//! * `clear`: Empties the log.
+//! * `camera [distance|pitch|yaw|fov|target] [value]`: Prints the focused window's camera, or sets one value.
+//!   Angles are in degrees, the target is three numbers.
+//! * `projection [perspective|orthographic]`: Switches the focused window's camera, toggles without an argument.
+//! * `view <front|back|right|left|top|bottom|isometric>`: Turns the focused window's camera to a standard view.
+//! * `toggle <hud|view_cube|rulers|outline|measure>`: Shows or hides one of the engine's overlays.
+//! * `stats`: Prints the last frame's timings and counts.
+//! * `material <index> color <r> <g> <b> [a]`: Changes a material's base color.
+//! * `move <renderable> <x> <y> <z>`: Moves a renderable, until the app sets the renderables again.
+//! * `exec <path>`: Loads a [script](crate::script) of commands, not on the web.
+//! * `export <path>`: Writes the scene as a binary glTF file, see [RenderEngine::export_scene]. Not on the web.
+//!
+//! Without a focused window the camera commands act on any window. Apps add their own with [Console::register], the demo app adds `load <path>` for models, point clouds and PNG skyboxes.
+//! Up and down walk through the lines run before, escape closes the console.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use cgmath::{Deg, Vector3, Vector4};
+use web_time::Duration;
+use winit::{keyboard::KeyCode, window::WindowId};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::script::Script;
+use crate::{
+    camera::{orbit_camera::Projection, view_animation::StandardView},
+    debug_hud::{HudVertex, Quads, BACKGROUND, GLYPH_ADVANCE, LINE_HEIGHT, PADDING, PIXEL, TEXT},
+    dynamic_resolution::DynamicResolution,
+    input::InputEvent,
+    render_engine::RenderEngine,
+    scene::MaterialHandle,
+    viewport::Viewport,
+};
+
+/// What a command prints on success, or why it failed
+pub type CommandResult = Result<String, String>;
+type CommandFn = Box<dyn FnMut(&mut RenderEngine, &[String]) -> CommandResult>;
+
+/// Lines of the log kept, older ones are dropped
+const MAX_LOG: usize = 200;
+/// Lines of the log shown above the input line
+const VISIBLE_LINES: usize = 12;
+const ERROR: [f32; 4] = [0.95, 0.35, 0.3, 1.0];
+const ECHO: [f32; 4] = [0.55, 0.7, 0.9, 1.0];
+
+struct Command {
+    /// One line, shown by `help`
+    help: String,
+    /// Taken out while it runs, so it can borrow the engine the console belongs to
+    run: Option<CommandFn>,
+}
+
+struct LogLine {
+    text: String,
+    color: [f32; 4],
+}
+
+/// What the console did with an input event
+#[derive(Debug, Default)]
+pub(crate) struct ConsoleResponse {
+    /// The event was a key press meant for the console
+    pub consumed: bool,
+    pub redraw: bool,
+    /// A line to run, entered with return
+    pub submitted: Option<String>,
+}
+
+pub struct Console {
+    open: bool,
+    input: String,
+    log: VecDeque<LogLine>,
+    history: Vec<String>,
+    /// Into the history while walking through it with up and down
+    history_index: Option<usize>,
+    commands: BTreeMap<String, Command>,
+}
+
+impl Console {
+    /// Closed, with the built-in commands registered
+    pub(crate) fn new() -> Self {
+        let mut console = Console {
+            open: false,
+            input: String::new(),
+            log: VecDeque::new(),
+            history: Vec::new(),
+            history_index: None,
+            commands: BTreeMap::new(),
+        };
+        console.register(
+            "help",
+            "help [command]: Lists the commands, or explains one",
+            help,
+        );
+        console.register("clear", "clear: Empties the log", |engine, _| {
+            engine.console_mut().clear();
+            Ok(String::new())
+        });
+        console.register(
+            "camera",
+            "camera [distance|pitch|yaw|fov|target] [value]: Prints or sets the camera, angles in degrees",
+            camera,
+        );
+        console.register(
+            "projection",
+            "projection [perspective|orthographic]: Switches the camera's projection",
+            projection,
+        );
+        console.register(
+            "view",
+            "view <front|back|right|left|top|bottom|isometric>: Turns the camera to a standard view",
+            view,
+        );
+        console.register(
+            "toggle",
+            "toggle <hud|view_cube|rulers|outline|measure>: Shows or hides an overlay",
+            toggle,
+        );
+        console.register(
+            "stats",
+            "stats: Prints the last frame's timings and counts",
+            stats,
+        );
+        console.register(
+            "render_scale",
+            "render_scale [scale [sharpness]|auto <ms> [min max]|off]: Prints or sets the resolution the \
+             scene is drawn at, 0.25 to 1, or has it hold a GPU frame time",
+            render_scale,
+        );
+        console.register(
+            "material",
+            "material <index> color <r> <g> <b> [a]: Changes a material's base color",
+            material,
+        );
+        console.register(
+            "move",
+            "move <renderable> <x> <y> <z>: Moves a renderable",
+            move_renderable,
+        );
+        #[cfg(not(target_arch = "wasm32"))]
+        console.register(
+            "exec",
+            "exec <path>: Loads a script of commands, or with the rhai feature a .rhai script",
+            exec,
+        );
+        #[cfg(not(target_arch = "wasm32"))]
+        console.register(
+            "export",
+            "export <path>: Writes the scene as a .glb file",
+            export,
+        );
+        console
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn set_open(&mut self, open: bool) {
+        self.open = open;
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+    }
+
+    /// Adds a command, replacing the one of the same name if there is one. It gets the engine and the words after
+    /// its name, what it returns is printed to the log.
+    ///
+    /// Arguments:
+    ///
+    /// * `help`: One line shown by the `help` command, usually starting with how it's called.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        help: impl Into<String>,
+        run: impl FnMut(&mut RenderEngine, &[String]) -> CommandResult + 'static,
+    ) {
+        self.commands.insert(
+            name.into(),
+            Command {
+                help: help.into(),
+                run: Some(Box::new(run)),
+            },
+        );
+    }
+
+    /// Names and help lines of the registered commands, sorted by name
+    pub fn commands(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.commands
+            .iter()
+            .map(|(name, command)| (name.as_str(), command.help.as_str()))
+    }
+
+    /// Adds lines to the bottom of the log
+    pub fn print(&mut self, text: &str) {
+        self.push_log(text, TEXT);
+    }
+
+    pub fn clear(&mut self) {
+        self.log.clear();
+    }
+
+    fn push_log(&mut self, text: &str, color: [f32; 4]) {
+        for line in text.lines() {
+            if self.log.len() == MAX_LOG {
+                self.log.pop_front();
+            }
+            self.log.push_back(LogLine {
+                text: line.to_string(),
+                color,
+            });
+        }
+    }
+
+    /// Opens and closes the console on backtick and edits the input line while it's open
+    pub(crate) fn process_event(&mut self, event: &InputEvent) -> ConsoleResponse {
+        let consumed = ConsoleResponse {
+            consumed: true,
+            redraw: true,
+            submitted: None,
+        };
+        let key = match event {
+            // Releases go through, so keys held while the console opens don't get stuck
+            InputEvent::Key {
+                key,
+                pressed: true,
+                repeat,
+            } => {
+                if *key == KeyCode::Backquote {
+                    if !repeat {
+                        self.toggle();
+                    }
+                    return consumed;
+                }
+                *key
+            }
+            // Typed by the key that toggled the console
+            InputEvent::Text(text) if text == "`" => return consumed,
+            InputEvent::Text(text) if self.open => {
+                self.input.extend(text.chars().filter(|c| !c.is_control()));
+                return consumed;
+            }
+            _ => return ConsoleResponse::default(),
+        };
+        if !self.open {
+            return ConsoleResponse::default();
+        }
+
+        let mut submitted = None;
+        match key {
+            KeyCode::Enter | KeyCode::NumpadEnter => {
+                let line = std::mem::take(&mut self.input);
+                self.history_index = None;
+                if !line.trim().is_empty() {
+                    if self.history.last() != Some(&line) {
+                        self.history.push(line.clone());
+                    }
+                    submitted = Some(line);
+                }
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Escape => self.open = false,
+            KeyCode::ArrowUp => self.walk_history(true),
+            KeyCode::ArrowDown => self.walk_history(false),
+            _ => (),
+        }
+        ConsoleResponse {
+            submitted,
+            ..consumed
+        }
+    }
+
+    /// Replaces the input line with an older line from the history, or a newer one
+    fn walk_history(&mut self, older: bool) {
+        self.history_index = match (self.history_index, older) {
+            (None, false) => return,
+            (None, true) => self.history.len().checked_sub(1),
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) => Some(index + 1).filter(|&index| index < self.history.len()),
+        };
+        self.input = self
+            .history_index
+            .map(|index| self.history[index].clone())
+            .unwrap_or_default();
+    }
+
+    /// The log and the input line along the bottom of a window `width` x `height`, nothing while it's closed
+    pub(crate) fn layout(&self, width: f32, height: f32) -> Vec<HudVertex> {
+        if !self.open {
+            return Vec::new();
+        }
+        let mut quads = Quads::new(width, height);
+        let line_height = LINE_HEIGHT * PIXEL;
+        let columns = (((width - 2.0 * PADDING) / (GLYPH_ADVANCE * PIXEL)) as usize).max(1);
+        let panel_height = (VISIBLE_LINES + 1) as f32 * line_height + 2.0 * PADDING;
+        let top = height - panel_height;
+        quads.rect(0.0, top, width, panel_height, BACKGROUND);
+
+        // Long lines wrap, the newest rows that fit are shown
+        let rows: Vec<(&str, [f32; 4])> = self
+            .log
+            .iter()
+            .flat_map(|line| wrap(&line.text, columns).map(|row| (row, line.color)))
+            .collect();
+        let rows = &rows[rows.len().saturating_sub(VISIBLE_LINES)..];
+        for (index, (row, color)) in rows.iter().enumerate() {
+            quads.text(
+                PADDING,
+                top + PADDING + index as f32 * line_height,
+                row,
+                *color,
+            );
+        }
+
+        // Cut off at the front when it's too long, to keep the cursor in view
+        let input = format!("> {}_", self.input);
+        let input: String = input
+            .chars()
+            .skip(input.chars().count().saturating_sub(columns))
+            .collect();
+        let bottom = top + PADDING + VISIBLE_LINES as f32 * line_height;
+        quads.text(PADDING, bottom, &input, TEXT);
+        quads.vertices
+    }
+}
+
+/// Runs `line` as if it was entered into the console, printing it and what the command returns to the log
+pub(crate) fn run(engine: &mut RenderEngine, line: &str) -> CommandResult {
+    tracing::debug!(line, "Running console command");
+    engine.console_mut().push_log(&format!("> {line}"), ECHO);
+    let result = execute(engine, line);
+    let console = engine.console_mut();
+    match &result {
+        Ok(output) => console.push_log(output, TEXT),
+        Err(err) => console.push_log(err, ERROR),
+    }
+    result
+}
+
+/// Runs `line` without printing anything
+pub(crate) fn execute(engine: &mut RenderEngine, line: &str) -> CommandResult {
+    let words = split_words(line)?;
+    let Some((name, args)) = words.split_first() else {
+        return Ok(String::new());
+    };
+    let command = engine
+        .console_mut()
+        .commands
+        .get_mut(name)
+        .ok_or_else(|| format!("Unknown command {name}, try help"))?;
+    let mut run = command
+        .run
+        .take()
+        .ok_or_else(|| format!("{name} is already running"))?;
+    let result = run(engine, args);
+    // Unless it replaced or removed itself while running
+    if let Some(command) = engine.console_mut().commands.get_mut(name) {
+        command.run.get_or_insert(run);
+    }
+    result
+}
+
+/// Splits `line` at whitespace outside of double quotes
+fn split_words(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            // Quotes make a word even if there's nothing between them
+            '"' => {
+                quoted = !quoted;
+                word.get_or_insert_with(String::new);
+            }
+            c if c.is_whitespace() && !quoted => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quoted {
+        return Err("Missing closing quote".to_string());
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// `text` cut into pieces of at most `columns` characters
+fn wrap(text: &str, columns: usize) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let end = rest
+            .char_indices()
+            .nth(columns)
+            .map_or(rest.len(), |(index, _)| index);
+        let (row, tail) = rest.split_at(end);
+        rest = tail;
+        Some(row)
+    })
+}
+
+/// Any window if none has focus, e.g. while running a script at startup
+fn focused_window(engine: &RenderEngine) -> Result<WindowId, String> {
+    engine
+        .input()
+        .focused_window()
+        .or_else(|| engine.window_ids().next())
+        .ok_or_else(|| "There is no window".to_string())
+}
+
+fn focused_viewport(engine: &mut RenderEngine) -> Result<&mut Viewport, String> {
+    let window_id = focused_window(engine)?;
+    engine
+        .viewport_mut(window_id)
+        .ok_or_else(|| "The focused window has no viewport".to_string())
+}
+
+fn help(engine: &mut RenderEngine, args: &[String]) -> CommandResult {
+    let console = engine.console();
+    match args {
+        [] => Ok(console
+            .commands()
+            .map(|(_, help)| help)
+            .collect::<Vec<_>>()
+            .join("\n")),
+        [name] => console
+            .commands
+            .get(name)
+            .map(|command| command.help.clone())
+            .ok_or_else(|| format!("Unknown command {name}")),
+        _ => Err("Usage: help [command]".to_string()),
+    }
+}
+
+fn camera(engine: &mut RenderEngine, args: &[String]) -> CommandResult {
+    let viewport = focused_viewport(engine)?;
+    let values = args
+        .iter()
+        .skip(1)
+        .map(|arg| parse::<f32>(arg))
+        .collect::<Result<Vec<_>, _>>()?;
+    let camera = &mut viewport.camera;
+    match (args.first().map(String::as_str), values.as_slice()) {
+        (None, []) => {}
+        (Some("distance"), [distance]) => camera.set_distance(*distance),
+        (Some("pitch"), [pitch]) => camera.set_pitch(pitch.to_radians()),
+        (Some("yaw"), [yaw]) => camera.set_yaw(yaw.to_radians()),
+        (Some("fov"), [fov]) => camera.fovy = Deg(fov.clamp(1.0, 179.0)).into(),
+        (Some("target"), [x, y, z]) => {
+            camera.target = Vector3::new(*x, *y, *z);
+            // Moves the eye along
+            camera.set_distance(camera.distance);
+        }
+        _ => return Err("Usage: camera [distance|pitch|yaw|fov|target] [value]".to_string()),
+    }
+    if !args.is_empty() {
+        viewport.view_animation = None;
+    }
+    let camera = &viewport.camera;
+    Ok(format!(
+        "distance {:.3}  pitch {:.1}  yaw {:.1}  fov {:.1}\ntarget {:.3} {:.3} {:.3}",
+        camera.distance,
+        camera.pitch.to_degrees(),
+        camera.yaw.to_degrees(),
+        Deg::from(camera.fovy).0,
+        camera.target.x,
+        camera.target.y,
+        camera.target.z,
+    ))
+}
+
+fn projection(engine: &mut RenderEngine, args: &[String]) -> CommandResult {
+    let camera = &mut focused_viewport(engine)?.camera;
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] => camera.toggle_projection(),
+        ["perspective"] => camera.projection = Projection::Perspective,
+        ["orthographic"] => camera.projection = Projection::Orthographic,
+        _ => return Err("Usage: projection [perspective|orthographic]".to_string()),
+    }
+    Ok(format!("{:?}", camera.projection))
+}
+
+fn view(engine: &mut RenderEngine, args: &[String]) -> CommandResult {
+    let usage = || "Usage: view <front|back|right|left|top|bottom|isometric>".to_string();
+    let [name] = args else {
+        return Err(usage());
+    };
+    let view: StandardView = name.parse().map_err(|_| usage())?;
+    let window_id = focused_window(engine)?;
+    engine.set_standard_view(window_id, view);
+    Ok(String::new())
+}
+
+fn toggle(engine: &mut RenderEngine, args: &[String]) -> CommandResult {
+    let [name] = args else {
+        return Err("Usage: toggle <hud|view_cube|rulers|outline|measure>".to_string());
+    };
+    let on = match name.as_str() {
+        "hud" => {
+            engine.toggle_debug_hud();
+            engine.debug_hud_visible()
+        }
+        "view_cube" => {
+            engine.set_view_cube_visible(!engine.view_cube_visible());
+            engine.view_cube_visible()
+        }
+        "rulers" => {
+            engine.set_rulers_visible(!engine.rulers_visible());
+            engine.rulers_visible()
+        }
+        "outline" => {
+            let selection = engine.selection_mut();
+            selection.outline = !selection.outline;
+            selection.outline
+        }
+        "measure" => {
+            let measure_tool = engine.measure_tool_mut();
+            measure_tool.set_enabled(!measure_tool.is_enabled());
+            measure_tool.is_enabled()
+        }
+        _ => return Err(format!("Nothing called {name} to toggle")),
+    };
+    Ok(format!("{name} {}", if on { "on" } else { "off" }))
+}
+
+fn render_scale(engine: &mut RenderEngine, args: &[String]) -> CommandResult {
+    let usage = || "Usage: render_scale [scale [sharpness]|auto <ms> [min max]|off]".to_string();
+    let (auto, values) = match args.first().map(String::as_str) {
+        Some("auto") => (true, &args[1..]),
+        Some("off") if args.len() == 1 => {
+            engine.set_dynamic_resolution(None);
+            (false, &args[1..])
+        }
+        _ => (false, args),
+    };
+    let values = values
+        .iter()
+        .map(|arg| parse::<f32>(arg))
+        .collect::<Result<Vec<_>, _>>()?;
+    match (auto, values.as_slice()) {
+        (false, []) => {}
+        (false, [scale]) => {
+            engine.set_dynamic_resolution(None);
+            engine.set_render_scale(*scale);
+        }
+        (false, [scale, sharpness]) => {
+            engine.set_dynamic_resolution(None);
+            engine.set_render_scale(*scale);
+            engine.set_upscaling_sharpness(*sharpness);
+        }
+        (true, [millis, bounds @ ..]) if matches!(bounds.len(), 0 | 2) => {
+            let target = Duration::from_secs_f32(millis.max(0.0) / 1000.0);
+            let mut dynamic_resolution = DynamicResolution::new(target);
+            if let [min, max] = bounds {
+                dynamic_resolution = dynamic_resolution.with_bounds(*min, *max);
+            }
+            engine.set_dynamic_resolution(Some(dynamic_resolution));
+        }
+        _ => return Err(usage()),
+    }
+    let mut output = format!(
+        "render scale {:.2}  sharpness {:.2}",
+        engine.render_scale(),
+        engine.upscaling_sharpness()
+    );
+    if let Some(dynamic_resolution) = engine.dynamic_resolution() {
+        let (min, max) = dynamic_resolution.bounds();
+        output += &format!(
+            "\nauto {:.1} ms within {min:.2} to {max:.2}",
+            dynamic_resolution.target_frame_time.as_secs_f64() * 1000.0
+        );
+    }
+    Ok(output)
+}
+
+fn stats(engine: &mut RenderEngine, _: &[String]) -> CommandResult {
+    let frame = engine.frame_stats();
+    let stats = engine.stats();
+    let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let mut lines = vec![
+        format!(
+            "frame {:.2} ms  update {:.2} ms  render {:.2} ms",
+            millis(frame.cpu_frame_time),
+            millis(frame.cpu_update_time),
+            millis(frame.cpu_render_time),
+        ),
+        format!(
+            "draws {}  triangles {}  uploaded {} bytes",
+            stats.draw_calls, stats.triangles, stats.upload_bytes
+        ),
+        format!(
+            "bind group switches {}  pipeline switches {}",
+            stats.bind_group_switches, stats.pipeline_switches
+        ),
+        format!(
+            "vram {:.1} mb",
+            engine.estimate_vram() as f64 / (1024.0 * 1024.0)
+        ),
+    ];
+    lines.extend(
+        frame
+            .gpu_passes
+            .iter()
+            .map(|pass| format!("gpu {} {:.3} ms", pass.label, millis(pass.gpu_time))),
+    );
+    Ok(lines.join("\n"))
+}
+
+fn material(engine: &mut RenderEngine, args: &[String]) -> CommandResult {
+    let usage = || "Usage: material <index> color <r> <g> <b> [a]".to_string();
+    let [index, field, values @ ..] = args else {
+        return Err(usage());
+    };
+    let handle = MaterialHandle(parse(index)?);
+    let mut material = *engine
+        .material(handle)
+        .ok_or_else(|| format!("There is no material {index}"))?;
+    let values = values
+        .iter()
+        .map(|value| parse::<f32>(value))
+        .collect::<Result<Vec<_>, _>>()?;
+    match (field.as_str(), values.as_slice()) {
+        ("color", &[r, g, b]) => material.base_color = [r, g, b, 1.0],
+        ("color", &[r, g, b, a]) => material.base_color = [r, g, b, a],
+        _ => return Err(usage()),
+    }
+    engine.set_material(handle, material);
+    Ok(String::new())
+}
+
+fn move_renderable(engine: &mut RenderEngine, args: &[String]) -> CommandResult {
+    let [index, x, y, z] = args else {
+        return Err("Usage: move <renderable> <x> <y> <z>".to_string());
+    };
+    let index: usize = parse(index)?;
+    let mut renderables = engine.renderables().to_vec();
+    let renderable = renderables
+        .get_mut(index)
+        .ok_or_else(|| format!("There is no renderable {index}"))?;
+    renderable.model.w = Vector4::new(parse(x)?, parse(y)?, parse(z)?, 1.0);
+    engine.set_renderables(renderables);
+    Ok(String::new())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn exec(engine: &mut RenderEngine, args: &[String]) -> CommandResult {
+    let [path] = args else {
+        return Err("Usage: exec <path>".to_string());
+    };
+    let source =
+        std::fs::read_to_string(path).map_err(|err| format!("Failed to read {path}: {err}"))?;
+    #[cfg(feature = "rhai")]
+    if path.ends_with(".rhai") {
+        let script = crate::rhai_script::RhaiScript::compile(&source)
+            .map_err(|err| format!("{path} {err}"))?;
+        engine
+            .load_rhai_script(script)
+            .map_err(|err| format!("{path} {err}"))?;
+        return Ok(format!("Ran {path}"));
+    }
+    let script = Script::parse(&source).map_err(|err| format!("{path} {err}"))?;
+    engine
+        .load_script(script)
+        .map_err(|err| format!("{path} {err}"))?;
+    Ok(format!("Ran {path}"))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn export(engine: &mut RenderEngine, args: &[String]) -> CommandResult {
+    let [path] = args else {
+        return Err("Usage: export <path>".to_string());
+    };
+    let scene = engine.export_scene();
+    let glb =
+        crate::gltf::encode_glb(&scene).map_err(|err| format!("Failed to encode {path}: {err}"))?;
+    std::fs::write(path, glb).map_err(|err| format!("Failed to write {path}: {err}"))?;
+    Ok(format!("Wrote {} objects to {path}", scene.objects.len()))
+}
+
+fn parse<T: std::str::FromStr>(word: &str) -> Result<T, String> {
+    word.parse().map_err(|_| format!("{word} isn't a number"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_words_outside_of_quotes() {
+        assert_eq!(
+            split_words(r#"  exec "my script.txt"  now "" "#).unwrap(),
+            ["exec", "my script.txt", "now", ""]
+        );
+        assert!(split_words(r#"exec "open"#).is_err());
+    }
+
+    #[test]
+    fn wraps_at_characters() {
+        assert_eq!(wrap("äbcdefg", 3).collect::<Vec<_>>(), ["äbc", "def", "g"]);
+        assert_eq!(wrap("", 3).count(), 0);
+    }
+}
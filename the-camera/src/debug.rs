@@ -0,0 +1,129 @@
+//! Shapes to look at while debugging, callable from anywhere in the app without a handle to the engine:
+//!
+//! ```ignore
+//! debug::line(start, end, [1.0, 0.0, 0.0, 1.0]);
+//! debug::text3d(position, "TARGET", [1.0; 4]);
+//! ```
+//!
+//! Everything added before an update is drawn over every window until the next update, which starts over empty, so
+//! keep calling these every frame to keep the shapes on screen. They're drawn on top of the scene, not hidden by
+//! anything in front. Text keeps the same size on screen, in the built-in font of the debug HUD.
+//!
+//! The shapes are collected globally, an app running more than one engine sees them in whichever updates first.
+
+use std::sync::Mutex;
+
+use cgmath::{Rotation, Vector3};
+
+use crate::{
+    overlay::{OverlayGeometry, OverlayView},
+    scene::Transform,
+};
+
+/// Segments of each circle of a sphere
+const CIRCLE_SEGMENTS: usize = 32;
+
+static SHAPES: Mutex<DebugShapes> = Mutex::new(DebugShapes::new());
+
+/// Every shape added since the last update
+#[derive(Debug, Default)]
+pub(crate) struct DebugShapes {
+    lines: Vec<([Vector3<f32>; 2], [f32; 4])>,
+    texts: Vec<(Vector3<f32>, String, [f32; 4])>,
+}
+
+impl DebugShapes {
+    const fn new() -> Self {
+        DebugShapes {
+            lines: Vec::new(),
+            texts: Vec::new(),
+        }
+    }
+
+    /// Laid out for the window `view` looks through, text facing its camera
+    pub fn geometry(&self, view: &OverlayView) -> OverlayGeometry {
+        let mut geometry = OverlayGeometry::default();
+        for &([from, to], color) in &self.lines {
+            geometry.line(from, to, color);
+        }
+        for (position, text, color) in &self.texts {
+            geometry.text(view, *position, text, *color);
+        }
+        geometry
+    }
+}
+
+fn shapes() -> std::sync::MutexGuard<'static, DebugShapes> {
+    SHAPES.lock().expect("Debug shapes poisoned!")
+}
+
+/// Everything added since the last call, the engine takes them once per update
+pub(crate) fn take() -> DebugShapes {
+    std::mem::take(&mut *shapes())
+}
+
+pub fn line(from: Vector3<f32>, to: Vector3<f32>, color: [f32; 4]) {
+    shapes().lines.push(([from, to], color));
+}
+
+/// The edges of the axis aligned box from `min` to `max`
+pub fn aabb(min: Vector3<f32>, max: Vector3<f32>, color: [f32; 4]) {
+    let corner = |index: usize| {
+        Vector3::new(
+            if index & 1 == 0 { min.x } else { max.x },
+            if index & 2 == 0 { min.y } else { max.y },
+            if index & 4 == 0 { min.z } else { max.z },
+        )
+    };
+    let mut shapes = shapes();
+    // Every pair of corners differing in one coordinate
+    for index in 0..8 {
+        for bit in [1, 2, 4] {
+            if index & bit == 0 {
+                shapes
+                    .lines
+                    .push(([corner(index), corner(index | bit)], color));
+            }
+        }
+    }
+}
+
+/// Circles around the three axes through `center`
+pub fn sphere(center: Vector3<f32>, radius: f32, color: [f32; 4]) {
+    let axes = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+    let mut shapes = shapes();
+    for index in 0..3 {
+        let (u, v) = (axes[(index + 1) % 3], axes[(index + 2) % 3]);
+        let point = |segment: usize| {
+            let angle = segment as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            center + (u * angle.cos() + v * angle.sin()) * radius
+        };
+        for segment in 0..CIRCLE_SEGMENTS {
+            shapes
+                .lines
+                .push(([point(segment), point(segment + 1)], color));
+        }
+    }
+}
+
+/// The local X, Y and Z axes of `transform` in red, green and blue, `length` long in world units
+pub fn axes(transform: &Transform, length: f32) {
+    let origin = transform.translation;
+    let mut shapes = shapes();
+    for (axis, color) in [
+        (Vector3::unit_x(), [0.9, 0.2, 0.2, 1.0]),
+        (Vector3::unit_y(), [0.2, 0.8, 0.2, 1.0]),
+        (Vector3::unit_z(), [0.2, 0.4, 0.95, 1.0]),
+    ] {
+        let direction = transform.rotation.rotate_vector(axis);
+        shapes
+            .lines
+            .push(([origin, origin + direction * length], color));
+    }
+}
+
+/// A line of text centered on `position`, facing the camera. Letters are drawn uppercase, characters the font lacks
+/// as `?`.
+pub fn text3d(position: Vector3<f32>, text: impl Into<String>, color: [f32; 4]) {
+    shapes().texts.push((position, text.into(), color));
+}
@@ -0,0 +1,439 @@
+//! An on-screen diagnostics overlay: FPS, a graph of recent frame times, GPU time, draw calls and an estimate of the
+//! GPU memory in use, drawn over every window. Toggled with [crate::render_engine::RenderEngine::toggle_debug_hud].
+//!
+//! Text uses a built-in 5x7 pixel font, so no font files or glyph atlas are needed. Everything is drawn as flat
+//! colored quads laid out on the CPU each frame, a few hundred at most.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use wgpu::{CommandEncoder, Device, Queue, RenderPipeline, TextureFormat, TextureView};
+use winit::window::WindowId;
+
+use crate::{
+    console::Console,
+    profiler::{FrameStats, RenderStats},
+};
+
+/// Frames the graph shows
+const HISTORY: usize = 120;
+/// Screen pixels per font pixel
+pub(crate) const PIXEL: f32 = 2.0;
+/// In font pixels, the glyphs are 5 wide and 7 high
+pub(crate) const GLYPH_ADVANCE: f32 = 6.0;
+pub(crate) const LINE_HEIGHT: f32 = 10.0;
+/// Of the panel to the window's corner and to its content, in screen pixels
+const MARGIN: f32 = 8.0;
+pub(crate) const PADDING: f32 = 6.0;
+const GRAPH_HEIGHT: f32 = 40.0;
+const BAR_WIDTH: f32 = 2.0;
+/// Frame time at the top of the graph, longer frames are cut off
+const GRAPH_MAX_MS: f32 = 1000.0 / 30.0;
+const TARGET_MS: f32 = 1000.0 / 60.0;
+
+pub(crate) const BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.6];
+pub(crate) const TEXT: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+const TARGET_LINE: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+const FAST: [f32; 4] = [0.2, 0.8, 0.2, 1.0];
+const SLOW: [f32; 4] = [0.9, 0.8, 0.1, 1.0];
+const TOO_SLOW: [f32; 4] = [0.9, 0.2, 0.2, 1.0];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct HudVertex {
+    /// In clip space
+    position: [f32; 2],
+    color: [f32; 4],
+}
+
+pub(crate) struct DebugHud {
+    visible: bool,
+    frame_times: VecDeque<Duration>,
+    /// What the last update measured, the same for every window
+    lines: Vec<String>,
+    pipeline: RenderPipeline,
+    /// Rewritten every frame. One per window, since every window's passes go into the same submission and a
+    /// shared buffer would only hold the last one's quads.
+    vertex_buffers: HashMap<WindowId, wgpu::Buffer>,
+}
+
+impl DebugHud {
+    /// `format` has to be the engine's swapchain format, the HUD is drawn straight into the windows
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        DebugHud {
+            visible: false,
+            frame_times: VecDeque::with_capacity(HISTORY),
+            lines: Vec::new(),
+            pipeline: create_pipeline(device, format),
+            vertex_buffers: HashMap::new(),
+        }
+    }
+
+    /// Creates the GPU resources again on a new device, keeping the frame history
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(&mut self, device: &Device, format: TextureFormat) {
+        self.pipeline = create_pipeline(device, format);
+        self.vertex_buffers.clear();
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    /// Takes in the timings and counts of the last complete frame, call once per update. `vram_bytes` is only read
+    /// while the HUD is visible.
+    pub fn record_frame(&mut self, frame_stats: &FrameStats, stats: RenderStats, vram_bytes: u64) {
+        if self.frame_times.len() == HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_stats.cpu_frame_time);
+        if !self.visible {
+            return;
+        }
+
+        let total: Duration = self.frame_times.iter().sum();
+        let fps = if total.is_zero() {
+            0.0
+        } else {
+            self.frame_times.len() as f32 / total.as_secs_f32()
+        };
+        let gpu = if frame_stats.gpu_passes.is_empty() {
+            "GPU N/A".to_string()
+        } else {
+            format!("GPU {:.2} MS", millis(frame_stats.gpu_time()))
+        };
+        self.lines = vec![
+            format!("FPS {fps:.0}"),
+            format!("FRAME {:.2} MS", millis(frame_stats.cpu_frame_time)),
+            format!(
+                "CPU {:.2} MS",
+                millis(frame_stats.cpu_update_time + frame_stats.cpu_render_time)
+            ),
+            gpu,
+            format!("DRAWS {}  TRIS {}", stats.draw_calls, stats.triangles),
+            format!("VRAM {:.1} MB", vram_bytes as f64 / (1024.0 * 1024.0)),
+        ];
+    }
+
+    /// Records a pass drawing the HUD into the top left corner of `view`, a window of `width` x `height`, and the
+    /// console along the bottom while it's open
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        window_id: WindowId,
+        view: &TextureView,
+        width: u32,
+        height: u32,
+        console: &Console,
+    ) {
+        let (width, height) = (width as f32, height as f32);
+        let mut vertices = if self.visible && !self.lines.is_empty() {
+            self.layout(width, height)
+        } else {
+            Vec::new()
+        };
+        vertices.extend(console.layout(width, height));
+        if vertices.is_empty() {
+            return;
+        }
+        let bytes: &[u8] = bytemuck::cast_slice(&vertices);
+
+        let buffer = self
+            .vertex_buffers
+            .entry(window_id)
+            .or_insert_with(|| create_vertex_buffer(device, bytes.len() as u64));
+        if buffer.size() < bytes.len() as u64 {
+            *buffer = create_vertex_buffer(device, bytes.len() as u64);
+        }
+        queue.write_buffer(buffer, 0, bytes);
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("debug_hud"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, buffer.slice(..bytes.len() as u64));
+        render_pass.draw(0..vertices.len() as u32, 0..1);
+    }
+
+    /// Stops keeping a vertex buffer for the window
+    pub fn remove_window(&mut self, window_id: WindowId) {
+        self.vertex_buffers.remove(&window_id);
+    }
+
+    /// The panel, its text and the frame time graph below it
+    fn layout(&self, width: f32, height: f32) -> Vec<HudVertex> {
+        let mut quads = Quads::new(width, height);
+        let longest = self.lines.iter().map(String::len).max().unwrap_or(0);
+        let text_width = longest as f32 * GLYPH_ADVANCE * PIXEL;
+        let text_height = self.lines.len() as f32 * LINE_HEIGHT * PIXEL;
+        let graph_width = HISTORY as f32 * BAR_WIDTH;
+        quads.rect(
+            MARGIN,
+            MARGIN,
+            text_width.max(graph_width) + 2.0 * PADDING,
+            text_height + GRAPH_HEIGHT + 3.0 * PADDING,
+            BACKGROUND,
+        );
+
+        let left = MARGIN + PADDING;
+        for (index, line) in self.lines.iter().enumerate() {
+            let top = MARGIN + PADDING + index as f32 * LINE_HEIGHT * PIXEL;
+            quads.text(left, top, line, TEXT);
+        }
+
+        let bottom = MARGIN + 2.0 * PADDING + text_height + GRAPH_HEIGHT;
+        let target = bottom - GRAPH_HEIGHT * TARGET_MS / GRAPH_MAX_MS;
+        quads.rect(left, target, graph_width, 1.0, TARGET_LINE);
+        for (index, frame_time) in self.frame_times.iter().enumerate() {
+            let ms = millis(*frame_time);
+            let bar_height = (GRAPH_HEIGHT * ms / GRAPH_MAX_MS).clamp(1.0, GRAPH_HEIGHT);
+            let color = if ms <= TARGET_MS {
+                FAST
+            } else if ms <= GRAPH_MAX_MS {
+                SLOW
+            } else {
+                TOO_SLOW
+            };
+            quads.rect(
+                left + index as f32 * BAR_WIDTH,
+                bottom - bar_height,
+                BAR_WIDTH,
+                bar_height,
+                color,
+            );
+        }
+        quads.vertices
+    }
+}
+
+/// Collects quads given in screen pixels from the top left as triangles in clip space
+pub(crate) struct Quads {
+    pub vertices: Vec<HudVertex>,
+    width: f32,
+    height: f32,
+}
+
+impl Quads {
+    /// For a window `width` x `height` pixels
+    pub fn new(width: f32, height: f32) -> Self {
+        Quads {
+            vertices: Vec::new(),
+            width,
+            height,
+        }
+    }
+
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: [f32; 4]) {
+        let [left, right] = [x, x + width].map(|x| x / self.width * 2.0 - 1.0);
+        let [top, bottom] = [y, y + height].map(|y| 1.0 - y / self.height * 2.0);
+        let corners = [
+            [left, top],
+            [left, bottom],
+            [right, top],
+            [right, top],
+            [left, bottom],
+            [right, bottom],
+        ];
+        self.vertices
+            .extend(corners.map(|position| HudVertex { position, color }));
+    }
+
+    /// One quad per run of lit pixels in a glyph row
+    pub fn text(&mut self, x: f32, y: f32, text: &str, color: [f32; 4]) {
+        for [column, row, length] in lit_runs(text) {
+            self.rect(
+                x + column * PIXEL,
+                y + row * PIXEL,
+                length * PIXEL,
+                PIXEL,
+                color,
+            );
+        }
+    }
+}
+
+/// One `[column, row, length]` per run of lit pixels in a glyph row of `text`, in font pixels from its top left
+pub(crate) fn lit_runs(text: &str) -> impl Iterator<Item = [f32; 3]> + '_ {
+    text.chars().enumerate().flat_map(|(index, c)| {
+        let glyph_x = index as f32 * GLYPH_ADVANCE;
+        glyph(c)
+            .into_iter()
+            .enumerate()
+            .flat_map(move |(row, bits)| {
+                let lit = |column: u32| bits & (0x10 >> column) != 0;
+                let mut runs = Vec::new();
+                let mut column = 0;
+                while column < 5 {
+                    if !lit(column) {
+                        column += 1;
+                        continue;
+                    }
+                    let start = column;
+                    while column < 5 && lit(column) {
+                        column += 1;
+                    }
+                    runs.push([glyph_x + start as f32, row as f32, (column - start) as f32]);
+                }
+                runs
+            })
+    })
+}
+
+fn millis(duration: Duration) -> f32 {
+    duration.as_secs_f32() * 1000.0
+}
+
+/// Rows of a 5x7 glyph from the top, the highest of the 5 bits is the leftmost pixel. Lowercase letters are drawn
+/// as uppercase ones, characters without a glyph as `?`.
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '"' => [0x0A, 0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00],
+        '\'' => [0x0C, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
+        '|' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        '<' => [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02],
+        '>' => [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '[' => [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E],
+        ']' => [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+fn create_vertex_buffer(device: &Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Debug HUD Vertices"),
+        size: size.next_power_of_two(),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_pipeline(device: &Device, format: TextureFormat) -> RenderPipeline {
+    let _span = tracing::debug_span!("create_debug_hud_pipeline").entered();
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Debug HUD Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("debug_hud.wgsl").into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[],
+        push_constant_ranges: &[],
+    });
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("debug_hud"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<HudVertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &[
+                    wgpu::VertexAttribute {
+                        offset: 0,
+                        shader_location: 0,
+                        format: wgpu::VertexFormat::Float32x2,
+                    },
+                    wgpu::VertexAttribute {
+                        offset: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                        shader_location: 1,
+                        format: wgpu::VertexFormat::Float32x4,
+                    },
+                ],
+            }],
+            compilation_options: Default::default(),
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+            unclipped_depth: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
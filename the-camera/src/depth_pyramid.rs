@@ -0,0 +1,377 @@
+//! Hierarchical depth buffers, or Hi-Z: mip chains of the farthest and the nearest depth over ever larger blocks of a
+//! view's depth buffer.
+//!
+//! With [crate::render_engine::RenderEngine::set_build_depth_pyramids] a compute pass downsamples the depth buffer of
+//! every window and render target after its main pass, halving it a level at a time down to a single texel. Level 0
+//! is half the depth buffer's size and every texel covers all the pixels below it, odd edges included. Depth runs
+//! from 0 at the near plane to 1 at the far plane, where nothing was drawn it's 1.
+//!
+//! The farthest depth tells whether something lies behind everything drawn over a region, whatever its size, from a
+//! few texels of the right level. Vegetation culls its blades against the pyramid each view built the frame
+//! before. The nearest depth lets ray marching, e.g. for screen space reflections or ambient occlusion, step over
+//! empty space in large strides.
+//!
+//! Plugins find the pyramid of a window in [crate::plugin::PassTarget::depth_pyramid] and the one of a render target
+//! in [crate::render_target::RenderTarget::depth_pyramid]. Devices without compute shaders, e.g. WebGL2, build none.
+
+use wgpu::{BindGroup, CommandEncoder, Device, Queue, TextureFormat, TextureView};
+
+use crate::{
+    camera::camera::CameraUniform,
+    render_engine_builder::DeviceReport,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        compute::{ComputeKernel, ComputeKernelBuilder, ComputePassBuilder},
+        transient::{TransientDesc, TransientTextures},
+        uniform_buffer::UniformBuffer,
+    },
+};
+
+/// What culling kernels know about the pyramid they test against, see vegetation_cull.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DepthPyramidUBOContent {
+    /// Of the camera the depth buffer was drawn from
+    view_proj: [[f32; 4]; 4],
+    /// Of level 0
+    size: [u32; 2],
+    /// 0 before the pyramid was first built, which culls nothing
+    levels: u32,
+    _padding: u32,
+}
+
+crate::assert_uniform_layout!(DepthPyramidUBOContent {
+    view_proj: ALIGN_VEC4,
+    size: ALIGN_VEC2,
+    levels: ALIGN_SCALAR,
+});
+
+/// The mip chain of one reduction
+struct PyramidTexture {
+    texture: wgpu::Texture,
+    /// Every level
+    view: TextureView,
+    /// Each level on its own, read while building the next
+    levels: Vec<TextureView>,
+}
+
+impl PyramidTexture {
+    fn new(device: &Device, [width, height]: [u32; 2], level_count: u32, label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: DepthPyramid::FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let levels = (0..level_count)
+            .map(|level| {
+                texture.create_view(&wgpu::TextureViewDescriptor {
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        PyramidTexture {
+            texture,
+            view,
+            levels,
+        }
+    }
+}
+
+/// The farthest and nearest depth pyramids of one window or render target, as built after its last main pass
+pub struct DepthPyramid {
+    farthest: PyramidTexture,
+    nearest: PyramidTexture,
+    size: [u32; 2],
+    level_count: u32,
+    camera: CameraUniform,
+    /// Written by turns, so culling before a build still sees what the one before it was built from while the build
+    /// writes the other. Queue writes land before the whole frame, not where they're made.
+    ubos: [UniformBuffer<DepthPyramidUBOContent>; 2],
+    cull_bind_groups: [BindGroup; 2],
+    builds: usize,
+}
+
+impl DepthPyramid {
+    /// Of both pyramids, 32 bit floats can't be filtered everywhere so they're read with `textureLoad`
+    pub const FORMAT: TextureFormat = TextureFormat::R32Float;
+
+    fn new(device: &Device, cull_layout: &BindGroupLayoutWithDesc, size: [u32; 2]) -> Self {
+        let level_count = u32::BITS - size[0].max(size[1]).leading_zeros();
+        let farthest = PyramidTexture::new(device, size, level_count, "Depth Pyramid Farthest");
+        let nearest = PyramidTexture::new(device, size, level_count, "Depth Pyramid Nearest");
+        let unbuilt = DepthPyramidUBOContent {
+            view_proj: CameraUniform::default().view_proj,
+            size,
+            levels: 0,
+            _padding: 0,
+        };
+        let ubos = [0, 1].map(|_| UniformBuffer::new_with_data(device, &unbuilt));
+        let cull_bind_groups = [0, 1].map(|index| {
+            BindGroupBuilder::new(cull_layout)
+                .resource(ubos[index].binding_resource())
+                .texture(&farthest.view)
+                .create(device, "Depth Pyramid Cull Bind Group")
+        });
+        DepthPyramid {
+            farthest,
+            nearest,
+            size,
+            level_count,
+            camera: CameraUniform::default(),
+            ubos,
+            cull_bind_groups,
+            builds: 0,
+        }
+    }
+
+    /// Level 0 of a depth buffer `width` by `height` pixels
+    fn size_for(width: u32, height: u32) -> [u32; 2] {
+        [(width / 2).max(1), (height / 2).max(1)]
+    }
+
+    /// The farthest depth over each texel's pixels, in every level. Something is hidden where it's behind that.
+    pub fn farthest_view(&self) -> &TextureView {
+        &self.farthest.view
+    }
+
+    /// The nearest depth over each texel's pixels, in every level. Nothing drawn is in front of that.
+    pub fn nearest_view(&self) -> &TextureView {
+        &self.nearest.view
+    }
+
+    /// Of level 0, half the depth buffer's
+    pub fn size(&self) -> (u32, u32) {
+        (self.size[0], self.size[1])
+    }
+
+    /// Down to the level of a single texel
+    pub fn mip_level_count(&self) -> u32 {
+        self.level_count
+    }
+
+    pub fn level_size(&self, level: u32) -> (u32, u32) {
+        (
+            (self.size[0] >> level).max(1),
+            (self.size[1] >> level).max(1),
+        )
+    }
+
+    /// What the depth buffer was drawn from, to project positions into the pyramid
+    pub fn camera(&self) -> &CameraUniform {
+        &self.camera
+    }
+
+    /// Whether it holds anything yet, it's built after the view's first main pass since it was created
+    pub fn is_built(&self) -> bool {
+        self.builds > 0
+    }
+
+    /// Binds the farthest pyramid and what it was built from for culling kernels, laid out as
+    /// [DepthPyramidPass::cull_bind_group_layout]
+    pub(crate) fn cull_bind_group(&self) -> &BindGroup {
+        &self.cull_bind_groups[self.builds % 2]
+    }
+
+    /// Bytes of both pyramids
+    pub fn size_in_bytes(&self) -> u64 {
+        let texels: u64 = (0..self.level_count)
+            .map(|level| {
+                let (width, height) = self.level_size(level);
+                width as u64 * height as u64
+            })
+            .sum();
+        // Two pyramids of 32 bit floats
+        texels * 4 * 2
+    }
+}
+
+/// Builds the pyramids of every view, shared by all of them
+pub(crate) struct DepthPyramidPass {
+    kernel: ComputeKernel,
+    input_layout: BindGroupLayoutWithDesc,
+    output_layout: BindGroupLayoutWithDesc,
+    cull_layout: BindGroupLayoutWithDesc,
+    /// Each level is written into these and copied into its mip. GL reads and writes a texture within one range of
+    /// levels at a time, so a level can't be written while the one before it is read. Views of the same size share
+    /// them.
+    scratch: TransientTextures,
+    /// Culls nothing, for views without a pyramid
+    unbuilt: DepthPyramid,
+}
+
+impl DepthPyramidPass {
+    /// None where the device can't run the compute passes
+    pub fn new(device: &Device, device_report: &DeviceReport) -> Option<Self> {
+        if !device_report
+            .downlevel_flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+        {
+            tracing::debug!("Depth pyramids need compute shaders");
+            return None;
+        }
+        let _span = tracing::debug_span!("create_depth_pyramid_pipelines").entered();
+
+        let depth = wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        };
+        let input_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(depth)
+            .named("farthest")
+            .next_binding_compute(depth)
+            .named("nearest")
+            .create(device, "Depth Pyramid Input Bind Group");
+        let output =
+            binding_types::image2D(DepthPyramid::FORMAT, wgpu::StorageTextureAccess::WriteOnly);
+        let output_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(output)
+            .named("farthest")
+            .next_binding_compute(output)
+            .named("nearest")
+            .create(device, "Depth Pyramid Output Bind Group");
+        let cull_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_types::uniform())
+            .named("depth_pyramid")
+            .next_binding_compute(depth)
+            .named("farthest")
+            .create(device, "Depth Pyramid Cull Bind Group");
+        let kernel = ComputeKernelBuilder::new(include_str!("depth_pyramid.wgsl"))
+            .workgroup_size([8, 8, 1])
+            .bind_group_layout(&input_layout.layout)
+            .bind_group_layout(&output_layout.layout)
+            .create(device, "depth pyramid");
+        let unbuilt = DepthPyramid::new(device, &cull_layout, [1, 1]);
+
+        Some(DepthPyramidPass {
+            kernel,
+            input_layout,
+            output_layout,
+            cull_layout,
+            scratch: TransientTextures::new("Depth Pyramids"),
+            unbuilt,
+        })
+    }
+
+    /// Layout of [DepthPyramidPass::cull_bind_group], for kernels culling against the pyramids
+    pub fn cull_bind_group_layout(&self) -> &BindGroupLayoutWithDesc {
+        &self.cull_layout
+    }
+
+    /// The farthest pyramid a view built last and what from, or one culling nothing before it was built
+    pub fn cull_bind_group<'a>(&'a self, pyramid: Option<&'a DepthPyramid>) -> &'a BindGroup {
+        pyramid.unwrap_or(&self.unbuilt).cull_bind_group()
+    }
+
+    /// Once per frame, lets go of scratch textures no view of that size needs anymore
+    pub fn begin_frame(&mut self) {
+        self.scratch.begin_frame();
+    }
+
+    /// Records building the view's `pyramid` from its `depth` buffer of `width` by `height` pixels, drawn from
+    /// `camera`. Without `enabled` the pyramid is dropped instead.
+    #[allow(clippy::too_many_arguments)]
+    pub fn build(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        enabled: bool,
+        pyramid: &mut Option<DepthPyramid>,
+        depth: &TextureView,
+        (width, height): (u32, u32),
+        camera: &CameraUniform,
+    ) {
+        if !enabled {
+            *pyramid = None;
+            return;
+        }
+        let size = DepthPyramid::size_for(width, height);
+        if pyramid.as_ref().is_none_or(|pyramid| pyramid.size != size) {
+            *pyramid = Some(DepthPyramid::new(device, &self.cull_layout, size));
+        }
+        let pyramid = pyramid.as_mut().expect("Depth pyramid was just created!");
+
+        let desc = TransientDesc {
+            width: size[0],
+            height: size[1],
+            format: DepthPyramid::FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+            sample_count: 1,
+        };
+        let scratch = [
+            self.scratch
+                .acquire(device, desc, "Depth Pyramid Farthest Scratch"),
+            self.scratch
+                .acquire(device, desc, "Depth Pyramid Nearest Scratch"),
+        ];
+        let output = BindGroupBuilder::new(&self.output_layout)
+            .texture(&scratch[0].view)
+            .texture(&scratch[1].view)
+            .create(device, "Depth Pyramid Output Bind Group");
+        for level in 0..pyramid.level_count {
+            let (farthest, nearest) = match level {
+                0 => (depth, depth),
+                _ => (
+                    &pyramid.farthest.levels[level as usize - 1],
+                    &pyramid.nearest.levels[level as usize - 1],
+                ),
+            };
+            let input = BindGroupBuilder::new(&self.input_layout)
+                .texture(farthest)
+                .texture(nearest)
+                .create(device, "Depth Pyramid Input Bind Group");
+            let (width, height) = pyramid.level_size(level);
+            ComputePassBuilder::new("depth pyramid")
+                .dispatch(&self.kernel, &[&input, &output], [width, height, 1])
+                .record(encoder);
+            for (scratch, texture) in scratch.iter().zip([&pyramid.farthest, &pyramid.nearest]) {
+                encoder.copy_texture_to_texture(
+                    scratch.texture.as_image_copy(),
+                    wgpu::ImageCopyTexture {
+                        texture: &texture.texture,
+                        mip_level: level,
+                        origin: wgpu::Origin3d::ZERO,
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                );
+            }
+        }
+        for scratch in scratch {
+            self.scratch.release(scratch);
+        }
+
+        pyramid.builds += 1;
+        pyramid.camera = *camera;
+        let content = DepthPyramidUBOContent {
+            view_proj: camera.view_proj,
+            size,
+            levels: pyramid.level_count,
+            _padding: 0,
+        };
+        pyramid.ubos[pyramid.builds % 2].update_content(queue, content);
+    }
+
+    /// Bytes of the scratch textures, the pyramids are counted with their views
+    pub fn size_in_bytes(&self) -> u64 {
+        self.scratch.allocated_bytes()
+    }
+}
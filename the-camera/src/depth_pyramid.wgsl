@@ -0,0 +1,35 @@
+// The level before, or the depth buffer twice for level 0
+@group(0) @binding(0)
+var t_farthest: texture_2d<f32>;
+@group(0) @binding(1)
+var t_nearest: texture_2d<f32>;
+// The scratch textures the level is copied out of, see depth_pyramid.rs
+@group(1) @binding(0)
+var farthest: texture_storage_2d<r32float, write>;
+@group(1) @binding(1)
+var nearest: texture_storage_2d<r32float, write>;
+
+// Every texel takes the farthest and the nearest depth of the texels of the level before it overlaps. Odd sizes don't
+// halve evenly, there a texel covers three rows or columns so none are left out.
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let source_size = textureDimensions(t_farthest);
+    let size = max(source_size / 2u, vec2<u32>(1u));
+    if any(id.xy >= size) {
+        return;
+    }
+    let first = id.xy * source_size / size;
+    let last = ((id.xy + 1u) * source_size + size - 1u) / size - 1u;
+
+    var far = 0.0;
+    var near = 1.0;
+    for (var y = first.y; y <= last.y; y++) {
+        for (var x = first.x; x <= last.x; x++) {
+            let texel = vec2<u32>(x, y);
+            far = max(far, textureLoad(t_farthest, texel, 0).r);
+            near = min(near, textureLoad(t_nearest, texel, 0).r);
+        }
+    }
+    textureStore(farthest, id.xy, vec4<f32>(far, 0.0, 0.0, 1.0));
+    textureStore(nearest, id.xy, vec4<f32>(near, 0.0, 0.0, 1.0));
+}
@@ -0,0 +1,49 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use wgpu::{Device, DeviceLostReason};
+
+/// Gets set from wgpu's callbacks when the device is lost (driver reset, GPU removed, internal error), so the
+/// engine can rebuild everything on its next update instead of panicking inside the callback.
+#[derive(Clone, Default)]
+pub struct DeviceLostFlag(Arc<AtomicBool>);
+
+impl DeviceLostFlag {
+    /// Registers the device lost and uncaptured error callbacks on `device`.
+    ///
+    /// Uncaptured validation and out of memory errors are printed rather than panicking like wgpu's default handler,
+    /// so long running apps survive a bad frame.
+    pub fn watch(device: &Device) -> Self {
+        let flag = DeviceLostFlag::default();
+
+        let lost = flag.clone();
+        device.set_device_lost_callback(move |reason, message| match reason {
+            // Our own doing when the engine drops or replaces the device
+            DeviceLostReason::Dropped | DeviceLostReason::ReplacedCallback => {}
+            DeviceLostReason::Unknown | DeviceLostReason::Destroyed => {
+                tracing::error!("GPU device lost ({reason:?}): {message}");
+                lost.mark_lost();
+            }
+        });
+
+        let lost = flag.clone();
+        device.on_uncaptured_error(Box::new(move |error| {
+            tracing::error!("Uncaptured GPU error: {error}");
+            if matches!(error, wgpu::Error::Internal { .. }) {
+                lost.mark_lost();
+            }
+        }));
+
+        flag
+    }
+
+    pub fn mark_lost(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    pub fn is_lost(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+}
@@ -0,0 +1,320 @@
+//! Render target textures in Vulkan memory exported as Linux DMA-BUFs, see
+//! [crate::external_surface::ExternalSurface::export_dmabuf].
+//!
+//! wgpu neither enables the external memory extensions nor allocates exportable memory, so with the `dmabuf`
+//! feature the builder opens Vulkan devices itself with `VK_KHR_external_memory_fd` and
+//! `VK_EXT_external_memory_dma_buf` on top of what wgpu asks for. Exported images are allocated here and handed to
+//! wgpu as hal textures. They're linearly tiled, which every importer understands without negotiating a modifier, at
+//! the cost of some sampling speed on the host's side.
+
+use std::{
+    ffi::CStr,
+    io,
+    os::fd::{FromRawFd, OwnedFd},
+};
+
+use ash::vk;
+use wgpu::hal::api::Vulkan;
+
+/// Enabled on top of the ones wgpu needs, external memory itself is core since Vulkan 1.1
+const EXTENSIONS: [&CStr; 2] = [
+    ash::khr::external_memory_fd::NAME,
+    ash::ext::external_memory_dma_buf::NAME,
+];
+
+/// A render target's color texture as a DMA-BUF, for importing it into another graphics API, e.g. with
+/// `EGL_EXT_image_dma_buf_import` or `VK_EXT_external_memory_dma_buf`. The memory stays alive as long as both the
+/// engine's texture and `fd` do.
+#[derive(Debug)]
+pub struct DmaBuf {
+    pub fd: OwnedFd,
+    pub width: u32,
+    pub height: u32,
+    /// The DRM fourcc of the pixels, e.g. `AB24` for [wgpu::TextureFormat::Rgba8UnormSrgb]
+    pub fourcc: u32,
+    /// Always `DRM_FORMAT_MOD_LINEAR`, the image is linearly tiled
+    pub modifier: u64,
+    /// Bytes from the start of the buffer to the first row
+    pub offset: u64,
+    /// Bytes from one row to the next
+    pub stride: u64,
+}
+
+/// Opens a device on `adapter` that can export DMA-BUFs, None if it isn't a Vulkan adapter with the extensions
+/// and the builder should open it the usual way
+pub(crate) fn request_device(
+    adapter: &wgpu::Adapter,
+    descriptor: &wgpu::DeviceDescriptor,
+) -> Option<(wgpu::Device, wgpu::Queue)> {
+    // SAFETY: the raw device is created from the adapter with the extensions and features it's handed to wgpu with
+    let open_device = unsafe {
+        adapter.as_hal::<Vulkan, _, _>(|adapter| {
+            let adapter = adapter?;
+            let capabilities = adapter.physical_device_capabilities();
+            if capabilities.properties().api_version < vk::API_VERSION_1_1
+                || !EXTENSIONS
+                    .iter()
+                    .all(|extension| capabilities.supports_extension(extension))
+            {
+                return None;
+            }
+            let features = descriptor.required_features;
+            let mut extensions = adapter.required_device_extensions(features);
+            for extension in EXTENSIONS {
+                if !extensions.contains(&extension) {
+                    extensions.push(extension);
+                }
+            }
+            let mut physical_features = adapter.physical_device_features(&extensions, features);
+
+            // One queue of the first family, like wgpu opens its devices
+            let family_index = 0;
+            let family_infos = [vk::DeviceQueueCreateInfo::default()
+                .queue_family_index(family_index)
+                .queue_priorities(&[1.0])];
+            let extension_names: Vec<_> = extensions
+                .iter()
+                .map(|extension| extension.as_ptr())
+                .collect();
+            let info = physical_features.add_to_device_create(
+                vk::DeviceCreateInfo::default()
+                    .queue_create_infos(&family_infos)
+                    .enabled_extension_names(&extension_names),
+            );
+            let raw_device = adapter
+                .shared_instance()
+                .raw_instance()
+                .create_device(adapter.raw_physical_device(), &info, None)
+                .inspect_err(|err| {
+                    tracing::warn!("Failed to open a device for DMA-BUF export: {err}")
+                })
+                .ok()?;
+            adapter
+                .device_from_raw(
+                    raw_device,
+                    None,
+                    &extensions,
+                    features,
+                    &descriptor.memory_hints,
+                    family_index,
+                    0,
+                )
+                .ok()
+        })
+    }?;
+    // SAFETY: the device was opened from this adapter with the descriptor's features
+    unsafe { adapter.create_device_from_hal::<Vulkan>(open_device, descriptor, None) }
+        .inspect_err(|err| tracing::warn!("Failed to open a device for DMA-BUF export: {err}"))
+        .ok()
+}
+
+/// The Vulkan format and DRM fourcc of the engine formats DMA-BUFs can carry
+fn formats(format: wgpu::TextureFormat) -> Option<(vk::Format, u32)> {
+    use wgpu::TextureFormat::*;
+    let fourcc = |code: &[u8; 4]| u32::from_le_bytes(*code);
+    match format {
+        Rgba8Unorm => Some((vk::Format::R8G8B8A8_UNORM, fourcc(b"AB24"))),
+        Rgba8UnormSrgb => Some((vk::Format::R8G8B8A8_SRGB, fourcc(b"AB24"))),
+        Bgra8Unorm => Some((vk::Format::B8G8R8A8_UNORM, fourcc(b"AR24"))),
+        Bgra8UnormSrgb => Some((vk::Format::B8G8R8A8_SRGB, fourcc(b"AR24"))),
+        Rgba16Float => Some((vk::Format::R16G16B16A16_SFLOAT, fourcc(b"AB4H"))),
+        _ => None,
+    }
+}
+
+fn unsupported(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, message)
+}
+
+fn vulkan_error(err: vk::Result) -> io::Error {
+    io::Error::other(format!("Vulkan: {err}"))
+}
+
+/// An image and its memory, freed when dropped unless wgpu took them over
+struct Allocation {
+    device: ash::Device,
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        // SAFETY: both were created on the device and nothing uses them anymore
+        unsafe {
+            self.device.destroy_image(self.image, None);
+            self.device.free_memory(self.memory, None);
+        }
+    }
+}
+
+/// A color texture laid out like [crate::texture::Texture::create_render_target] creates them, in memory exported
+/// as a DMA-BUF
+pub(crate) fn create_exported_texture(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> io::Result<(wgpu::Texture, DmaBuf)> {
+    let (vk_format, fourcc) =
+        formats(format).ok_or_else(|| unsupported("The engine's format can't be exported"))?;
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    };
+
+    // SAFETY: the image is created and bound to memory here, then owned by the hal texture's drop callback
+    let exported = unsafe {
+        device.as_hal::<Vulkan, _, _>(|hal_device| {
+            let hal_device =
+                hal_device.ok_or_else(|| unsupported("DMA-BUFs need a Vulkan device"))?;
+            if !EXTENSIONS
+                .iter()
+                .all(|extension| hal_device.enabled_device_extensions().contains(extension))
+            {
+                return Err(unsupported("The device was opened without DMA-BUF export"));
+            }
+            let instance = hal_device.shared_instance().raw_instance();
+            let physical_device = hal_device.raw_physical_device();
+            let raw = hal_device.raw_device();
+
+            let needed = vk::FormatFeatureFlags::COLOR_ATTACHMENT
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE
+                | vk::FormatFeatureFlags::TRANSFER_SRC;
+            let linear_features = instance
+                .get_physical_device_format_properties(physical_device, vk_format)
+                .linear_tiling_features;
+            if !linear_features.contains(needed) {
+                return Err(unsupported(
+                    "The GPU can't draw into linear images of the engine's format",
+                ));
+            }
+
+            let mut external_info = vk::ExternalMemoryImageCreateInfo::default()
+                .handle_types(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT);
+            let image_info = vk::ImageCreateInfo::default()
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(vk_format)
+                .extent(vk::Extent3D {
+                    width,
+                    height,
+                    depth: 1,
+                })
+                .mip_levels(1)
+                .array_layers(1)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .tiling(vk::ImageTiling::LINEAR)
+                .usage(
+                    vk::ImageUsageFlags::COLOR_ATTACHMENT
+                        | vk::ImageUsageFlags::SAMPLED
+                        | vk::ImageUsageFlags::TRANSFER_SRC,
+                )
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .push_next(&mut external_info);
+            let image = raw.create_image(&image_info, None).map_err(vulkan_error)?;
+
+            let requirements = raw.get_image_memory_requirements(image);
+            let memory_properties = instance.get_physical_device_memory_properties(physical_device);
+            let Some(memory_type) = (0..memory_properties.memory_type_count).find(|&index| {
+                requirements.memory_type_bits & (1 << index) != 0
+                    && memory_properties.memory_types[index as usize]
+                        .property_flags
+                        .contains(vk::MemoryPropertyFlags::DEVICE_LOCAL)
+            }) else {
+                raw.destroy_image(image, None);
+                return Err(unsupported("No device memory can hold the exported image"));
+            };
+            // Importers want dedicated allocations, a DMA-BUF is the whole allocation
+            let mut dedicated_info = vk::MemoryDedicatedAllocateInfo::default().image(image);
+            let mut export_info = vk::ExportMemoryAllocateInfo::default()
+                .handle_types(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT);
+            let allocate_info = vk::MemoryAllocateInfo::default()
+                .allocation_size(requirements.size)
+                .memory_type_index(memory_type)
+                .push_next(&mut dedicated_info)
+                .push_next(&mut export_info);
+            let memory = match raw.allocate_memory(&allocate_info, None) {
+                Ok(memory) => memory,
+                Err(err) => {
+                    raw.destroy_image(image, None);
+                    return Err(vulkan_error(err));
+                }
+            };
+            let allocation = Allocation {
+                device: raw.clone(),
+                image,
+                memory,
+            };
+            raw.bind_image_memory(image, memory, 0)
+                .map_err(vulkan_error)?;
+
+            let layout = raw.get_image_subresource_layout(
+                image,
+                vk::ImageSubresource {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    array_layer: 0,
+                },
+            );
+            let fd = ash::khr::external_memory_fd::Device::new(instance, raw)
+                .get_memory_fd(
+                    &vk::MemoryGetFdInfoKHR::default()
+                        .memory(memory)
+                        .handle_type(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT),
+                )
+                .map_err(vulkan_error)?;
+            let dmabuf = DmaBuf {
+                fd: OwnedFd::from_raw_fd(fd),
+                width,
+                height,
+                fourcc,
+                modifier: 0,
+                offset: layout.offset,
+                stride: layout.row_pitch,
+            };
+
+            let mut allocation = Some(allocation);
+            let hal_texture = wgpu::hal::vulkan::Device::texture_from_raw(
+                image,
+                &wgpu::hal::TextureDescriptor {
+                    label: Some("exported render_target"),
+                    size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format,
+                    usage: wgpu::hal::TextureUses::COLOR_TARGET
+                        | wgpu::hal::TextureUses::RESOURCE
+                        | wgpu::hal::TextureUses::COPY_SRC,
+                    memory_flags: wgpu::hal::MemoryFlags::empty(),
+                    view_formats: Vec::new(),
+                },
+                Some(Box::new(move || drop(allocation.take()))),
+            );
+            Ok((hal_texture, dmabuf))
+        })
+    }
+    .ok_or_else(|| unsupported("DMA-BUFs need a Vulkan device"))??;
+
+    let (hal_texture, dmabuf) = exported;
+    // SAFETY: the hal texture was created on this device as described here
+    let texture = unsafe {
+        device.create_texture_from_hal::<Vulkan>(
+            hal_texture,
+            &wgpu::TextureDescriptor {
+                label: Some("exported render_target"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            },
+        )
+    };
+    Ok((texture, dmabuf))
+}
@@ -0,0 +1,131 @@
+//! Steers the render scale of the windows to hold a frame time, see
+//! [crate::render_engine::RenderEngine::set_dynamic_resolution].
+//!
+//! Every few frames the GPU time of the frames drawn since the last change is averaged, as timed by the GPU profiler.
+//! Above the target the scale goes down, well below it back up, and in between it's kept so the scale doesn't
+//! flip back and forth around the target. What a frame costs goes with the pixels drawn, the square of the scale, so
+//! one step usually lands close to the middle of that band.
+//!
+//! The timings are of the whole frame, render targets and plugin passes included, which the scale doesn't change.
+//! Those only make it take a few more steps to settle. Without
+//! [crate::render_engine_builder::RenderEngineBuilder::gpu_profiling], or on devices without
+//! [wgpu::Features::TIMESTAMP_QUERY], there are no timings and the scale stays as it is.
+
+use web_time::Duration;
+
+use crate::{profiler::FrameStats, upscaling::MIN_RENDER_SCALE};
+
+/// GPU frames averaged before the scale is adjusted, single frames are too noisy
+const SAMPLES: u32 = 8;
+
+/// The scale changes by at most this much at a time, as the other passes don't shrink with it
+const MAX_STEP: f32 = 0.25;
+
+/// Smaller changes aren't worth recreating every window's attachments for
+const MIN_STEP: f32 = 0.02;
+
+/// Target and bounds of the render scale, set with [crate::render_engine::RenderEngine::set_dynamic_resolution]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DynamicResolution {
+    /// GPU time a frame should take at most, e.g. 16.6 ms for 60 FPS
+    pub target_frame_time: Duration,
+    /// Bounds of the render scale, from [MIN_RENDER_SCALE] to 1
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// How far below the target frame time a frame has to get before the scale goes up again, as a fraction of it
+    pub hysteresis: f32,
+}
+
+impl DynamicResolution {
+    pub fn new(target_frame_time: Duration) -> Self {
+        DynamicResolution {
+            target_frame_time,
+            min_scale: 0.5,
+            max_scale: 1.0,
+            hysteresis: 0.2,
+        }
+    }
+
+    pub fn with_bounds(mut self, min_scale: f32, max_scale: f32) -> Self {
+        self.min_scale = min_scale;
+        self.max_scale = max_scale;
+        self
+    }
+
+    pub fn with_hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    /// Clamped to the scales windows can be drawn at
+    pub(crate) fn bounds(&self) -> (f32, f32) {
+        let min = self.min_scale.clamp(MIN_RENDER_SCALE, 1.0);
+        (min, self.max_scale.clamp(min, 1.0))
+    }
+}
+
+/// Collects the GPU timings of the frames drawn at the current scale
+#[derive(Debug, Default)]
+pub(crate) struct ResolutionController {
+    /// Timings of frames before this one were drawn at an older scale
+    changed_at: u64,
+    last_sampled: u64,
+    total: Duration,
+    samples: u32,
+}
+
+impl ResolutionController {
+    /// Starts over from frame `frame_index` on, the first drawn at a new scale
+    pub fn reset(&mut self, frame_index: u64) {
+        *self = ResolutionController {
+            changed_at: frame_index,
+            ..Default::default()
+        };
+    }
+
+    /// The scale to draw at next once enough timings of `scale` came in, None to keep it
+    pub fn update(
+        &mut self,
+        settings: &DynamicResolution,
+        scale: f32,
+        stats: &FrameStats,
+    ) -> Option<f32> {
+        let (min, max) = settings.bounds();
+        // Moved out of the bounds since the last frame, e.g. by new settings
+        if !(min..=max).contains(&scale) {
+            return Some(scale.clamp(min, max));
+        }
+        if stats.gpu_passes.is_empty()
+            || stats.gpu_frame_index < self.changed_at
+            || stats.gpu_frame_index <= self.last_sampled
+        {
+            return None;
+        }
+        self.last_sampled = stats.gpu_frame_index;
+        self.total += stats.gpu_time();
+        self.samples += 1;
+        if self.samples < SAMPLES {
+            return None;
+        }
+        let average = (self.total / self.samples).as_secs_f32();
+        self.total = Duration::ZERO;
+        self.samples = 0;
+
+        let target = settings.target_frame_time.as_secs_f32();
+        let lower = target * (1.0 - settings.hysteresis.clamp(0.0, 1.0));
+        if (lower..=target).contains(&average) || average <= 0.0 {
+            return None;
+        }
+        // Aims for the middle between the target and where the scale goes up again
+        let aim = (target + lower) * 0.5;
+        let wanted = scale * (aim / average).sqrt();
+        let next = wanted
+            .clamp(scale - MAX_STEP, scale + MAX_STEP)
+            .clamp(min, max);
+        let reaches_bound = next == min || next == max;
+        if next == scale || ((next - scale).abs() < MIN_STEP && !reaches_bound) {
+            return None;
+        }
+        Some(next)
+    }
+}
@@ -0,0 +1,52 @@
+//! Render components for apps that keep their scene in a [hecs::World].
+//!
+//! Spawn entities with a [MeshHandle] and optionally a [MaterialHandle], [Transform] and [Visibility], then hand
+//! [extract_renderables] to the engine every frame instead of mirroring the world into the engine by hand.
+
+use crate::scene::Renderable;
+pub use crate::scene::{MaterialHandle, MeshHandle, Transform};
+
+/// Hidden entities stay in the world but aren't drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Visibility(pub bool);
+
+impl Default for Visibility {
+    fn default() -> Self {
+        Visibility(true)
+    }
+}
+
+/// Collects every visible entity with a [MeshHandle]. Entities without a material use `default_material` and
+/// entities without a [Transform] sit at the origin.
+pub fn extract_renderables(
+    world: &hecs::World,
+    default_material: MaterialHandle,
+) -> Vec<Renderable> {
+    extract_renderables_and_entities(world, default_material).0
+}
+
+/// Like [extract_renderables], along with the entity of each renderable. [crate::scene::Pick::renderable] indexes
+/// both, which maps a pick back to its entity.
+pub fn extract_renderables_and_entities(
+    world: &hecs::World,
+    default_material: MaterialHandle,
+) -> (Vec<Renderable>, Vec<hecs::Entity>) {
+    world
+        .query::<(
+            &MeshHandle,
+            Option<&MaterialHandle>,
+            Option<&Transform>,
+            Option<&Visibility>,
+        )>()
+        .iter()
+        .filter(|(_, (_, _, _, visibility))| visibility.is_none_or(|visibility| visibility.0))
+        .map(|(entity, (mesh, material, transform, _))| {
+            let renderable = Renderable::new(
+                *mesh,
+                material.copied().unwrap_or(default_material),
+                &transform.copied().unwrap_or_default(),
+            );
+            (renderable, entity)
+        })
+        .unzip()
+}
@@ -0,0 +1,210 @@
+//! Immediate mode UI with [egui], drawn over a window, see [crate::render_engine::RenderEngine::egui].
+//!
+//! The engine owns one [egui::Context]. UI code runs between frames against it, anywhere before
+//! [crate::render_engine::RenderEngine::render_frame], and what it built is drawn at the window's resolution once
+//! the scene and the engine's overlays are in, under the debug HUD. Window events reach egui through
+//! [crate::render_engine::RenderEngine::process_egui_event], which the shell calls before handing them to the
+//! engine, so clicks on a panel don't also select what's behind it.
+//!
+//! The UI is shown in one window, the first one events were forwarded from.
+
+use std::{sync::Arc, time::Duration};
+
+use egui_wgpu::{Renderer, ScreenDescriptor};
+use wgpu::{CommandBuffer, CommandEncoder, Device, Queue, TextureView};
+use winit::{
+    event::WindowEvent,
+    window::{Window, WindowId},
+};
+
+use crate::plugin::{EnginePlugin, PluginContext};
+
+/// Draws the engine's [egui::Context] over its window, registered by the engine itself
+pub struct EguiPass {
+    context: egui::Context,
+    /// The window the UI is shown in and egui's input state for it
+    window: Option<(Arc<Window>, egui_winit::State)>,
+    /// Created on [EnginePlugin::init], the textures egui uploaded are gone with the device
+    renderer: Option<Renderer>,
+    /// Between [egui::Context::begin_pass] and [egui::Context::end_pass]
+    in_pass: bool,
+    /// egui asked to be drawn again right away, e.g. while a panel animates open
+    repaint: bool,
+    /// Tessellated by [EguiPass::prepare] for [EguiPass::draw]
+    prepared: Option<PreparedUi>,
+}
+
+/// A frame's UI between [EguiPass::prepare] and [EguiPass::draw]
+struct PreparedUi {
+    primitives: Vec<egui::ClippedPrimitive>,
+    screen: ScreenDescriptor,
+    /// Textures egui is done with once the UI is drawn
+    free: Vec<egui::TextureId>,
+}
+
+impl EguiPass {
+    pub fn new() -> Self {
+        EguiPass {
+            context: egui::Context::default(),
+            window: None,
+            renderer: None,
+            in_pass: false,
+            repaint: false,
+            prepared: None,
+        }
+    }
+
+    /// Starts the next frame's UI if it isn't started yet, taking the input that came in since the last one
+    pub fn context(&mut self) -> &egui::Context {
+        if !self.in_pass {
+            let input = match &mut self.window {
+                Some((window, state)) => state.take_egui_input(window),
+                None => egui::RawInput::default(),
+            };
+            self.context.begin_pass(input);
+            self.in_pass = true;
+        }
+        &self.context
+    }
+
+    /// Feeds `event` of `window` to egui, true if egui keeps it to itself, e.g. a click on a panel or typing into a
+    /// text field. Events of other windows than the UI's are left alone.
+    pub fn process_window_event(&mut self, window: &Arc<Window>, event: &WindowEvent) -> bool {
+        let (ui_window, state) = self.window.get_or_insert_with(|| {
+            let state = egui_winit::State::new(
+                self.context.clone(),
+                egui::ViewportId::ROOT,
+                window.as_ref(),
+                Some(window.scale_factor() as f32),
+                window.theme(),
+                None,
+            );
+            (window.clone(), state)
+        });
+        if ui_window.id() != window.id() {
+            return false;
+        }
+        let response = state.on_window_event(window, event);
+        self.repaint |= response.repaint;
+        response.consumed
+    }
+
+    /// The window the UI is shown in, if events were forwarded yet
+    pub fn window_id(&self) -> Option<WindowId> {
+        self.window.as_ref().map(|(window, _)| window.id())
+    }
+
+    /// Shows the UI in the window events are forwarded from next, e.g. after its window was closed
+    pub fn forget_window(&mut self, window_id: WindowId) {
+        if self.window_id() == Some(window_id) {
+            self.window = None;
+        }
+    }
+    /// Ends the UI built since the last frame if it's shown in `window_id`, uploads its textures and vertices in
+    /// `encoder` and tessellates it for [EguiPass::draw], a window `width` x `height` pixels. Returns the command
+    /// buffers of egui's paint callbacks, which have to run after `encoder` and before the draw.
+    pub(crate) fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        window_id: WindowId,
+        width: u32,
+        height: u32,
+    ) -> Vec<CommandBuffer> {
+        if !self.in_pass || self.window_id().is_some_and(|id| id != window_id) {
+            return Vec::new();
+        }
+        let Some(renderer) = &mut self.renderer else {
+            return Vec::new();
+        };
+        self.in_pass = false;
+        let output = self.context.end_pass();
+        self.repaint = output
+            .viewport_output
+            .get(&egui::ViewportId::ROOT)
+            .is_some_and(|viewport| viewport.repaint_delay == Duration::ZERO);
+        if let Some((window, state)) = &mut self.window {
+            state.handle_platform_output(window, output.platform_output);
+        }
+
+        let primitives = self
+            .context
+            .tessellate(output.shapes, output.pixels_per_point);
+        let screen = ScreenDescriptor {
+            size_in_pixels: [width, height],
+            pixels_per_point: output.pixels_per_point,
+        };
+        for (id, delta) in &output.textures_delta.set {
+            renderer.update_texture(device, queue, *id, delta);
+        }
+        // Only paint callbacks record command buffers of their own, the engine's UI has none
+        let callback_buffers =
+            renderer.update_buffers(device, queue, encoder, &primitives, &screen);
+        self.prepared = Some(PreparedUi {
+            primitives,
+            screen,
+            free: output.textures_delta.free,
+        });
+        callback_buffers
+    }
+
+    /// Records the pass drawing what [EguiPass::prepare] tessellated over `view`, nothing if it didn't
+    pub(crate) fn draw(&mut self, encoder: &mut CommandEncoder, view: &TextureView) {
+        let (Some(renderer), Some(prepared)) = (&mut self.renderer, self.prepared.take()) else {
+            return;
+        };
+        let render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("egui"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        renderer.render(
+            &mut render_pass.forget_lifetime(),
+            &prepared.primitives,
+            &prepared.screen,
+        );
+        for id in &prepared.free {
+            renderer.free_texture(id);
+        }
+    }
+}
+
+impl Default for EguiPass {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EnginePlugin for EguiPass {
+    fn init(&mut self, context: &PluginContext) {
+        // After a device loss the fonts egui uploaded are gone, and a context only sends them once
+        if self.renderer.is_some() {
+            *self = EguiPass::new();
+        }
+        self.renderer = Some(Renderer::new(
+            context.device,
+            context.format,
+            None,
+            1,
+            false,
+        ));
+    }
+
+    fn name(&self) -> &str {
+        "egui"
+    }
+
+    fn needs_redraw(&self) -> bool {
+        self.repaint
+    }
+}
@@ -0,0 +1,98 @@
+//! Notifications about what happens inside the engine, e.g. windows resizing, meshes being added or the device being
+//! replaced after it was lost. Subscribe with closures through [EventBus::subscribe], they're called right when it
+//! happens, or turn on the queue with [EventBus::set_queued] and drain it once per frame:
+//!
+//! ```no_run
+//! # fn example(engine: &mut the_camera::render_engine::RenderEngine) {
+//! engine.events_mut().set_queued(true);
+//! // Later, e.g. after every update
+//! for event in engine.events_mut().drain() {
+//!     println!("{event:?}");
+//! }
+//! # }
+//! ```
+
+use std::path::PathBuf;
+
+use winit::window::WindowId;
+
+use crate::{
+    profiler::PassTiming,
+    scene::{MeshHandle, TextureHandle},
+    selection::SelectionChange,
+};
+
+type Subscriber = Box<dyn FnMut(&EngineEvent)>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineEvent {
+    WindowAdded(WindowId),
+    WindowRemoved(WindowId),
+    /// Not sent for sizes of zero, e.g. while the window is minimized on Windows
+    Resized {
+        window_id: WindowId,
+        width: u32,
+        height: u32,
+    },
+    /// Everything on the lost device was created again on a new one by the time this is sent
+    DeviceLost,
+    MeshAdded(MeshHandle),
+    TextureAdded(TextureHandle),
+    /// A watched file changed on disk and the new version was loaded, only with the `hot-reload` feature
+    AssetReloaded(PathBuf),
+    /// Sent in the update that resolved the clicks or changes made through
+    /// [crate::render_engine::RenderEngine::selection_mut]
+    SelectionChanged(SelectionChange),
+    /// GPU timings of a frame, arriving a few frames after it was drawn. Only with GPU profiling and on devices that
+    /// support timestamp queries.
+    PassTimings {
+        frame_index: u64,
+        passes: Vec<PassTiming>,
+    },
+}
+
+/// Hands [EngineEvent]s to the subscribers and the queue
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Vec<Subscriber>,
+    /// Kept only while queueing is on, so events nobody drains don't pile up
+    queue: Option<Vec<EngineEvent>>,
+}
+
+impl EventBus {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `callback` with every event from now on, right when it's sent
+    pub fn subscribe(&mut self, callback: impl FnMut(&EngineEvent) + 'static) {
+        self.subscribers.push(Box::new(callback));
+    }
+
+    /// Keeps every event from now on until [EventBus::drain] takes it, off by default. Turning it off drops the
+    /// events not drained yet.
+    pub fn set_queued(&mut self, queued: bool) {
+        if queued != self.queue.is_some() {
+            self.queue = queued.then(Vec::new);
+        }
+    }
+
+    pub fn is_queued(&self) -> bool {
+        self.queue.is_some()
+    }
+
+    /// Takes the events queued since the last call, oldest first
+    pub fn drain(&mut self) -> impl Iterator<Item = EngineEvent> + '_ {
+        self.queue.iter_mut().flat_map(|queue| queue.drain(..))
+    }
+
+    pub(crate) fn publish(&mut self, event: EngineEvent) {
+        tracing::trace!(?event, "Engine event");
+        for subscriber in &mut self.subscribers {
+            subscriber(&event);
+        }
+        if let Some(queue) = &mut self.queue {
+            queue.push(event);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Drawing into a texture that a host application shows in its own UI, e.g. a Qt, GTK or Electron window, instead
+//! of into windows the engine owns. The engine is built without a window and draws the scene from the surface's
+//! camera on every update, while the host hands over its pointer input:
+//!
+//! ```no_run
+//! # use the_camera::{external_surface::PointerButton, render_engine_builder::RenderEngineBuilder};
+//! # async fn example() {
+//! let mut engine = RenderEngineBuilder::new()
+//!     .build_headless(wgpu::TextureFormat::Rgba8UnormSrgb)
+//!     .await;
+//! let mut surface = engine.add_external_surface(800, 600);
+//! // From the host's event handlers
+//! surface.pointer_pressed(PointerButton::Primary, [400.0, 300.0]);
+//! surface.pointer_moved(&mut engine, [420.0, 310.0]);
+//! // Once per frame of the host
+//! engine.update();
+//! let pixels = surface.read_pixels(&mut engine);
+//! # }
+//! ```
+//!
+//! Hosts drawing with wgpu on the engine's [device](crate::render_engine::RenderEngine::device) show
+//! [ExternalSurface::texture] directly, after [RenderEngine::flush]. On Linux with Vulkan and the `dmabuf` feature,
+//! [ExternalSurface::export_dmabuf] hands the texture to another graphics API, e.g. a GTK or Qt scene graph on
+//! OpenGL, without copying it. IOSurfaces and DXGI shared handles aren't exported, other hosts copy the pixels out
+//! with [ExternalSurface::read_pixels].
+
+use crate::{
+    camera::{camera_controller::CameraController, orbit_camera::OrbitCamera},
+    render_engine::RenderEngine,
+    render_target::RenderTargetHandle,
+};
+
+/// A button of the host's mouse, pen or touch input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerButton {
+    /// Usually the left mouse button, orbits the camera
+    Primary,
+    /// Usually the right mouse button, pans the camera like the middle one
+    Secondary,
+    Middle,
+}
+
+/// A render target with a camera driven by the host's input, see the [module docs](self)
+pub struct ExternalSurface {
+    target: RenderTargetHandle,
+    /// How fast dragging orbits and scrolling zooms
+    pub camera_controller: CameraController,
+    /// The button held and where the pointer was last, in pixels from the top left of the surface
+    drag: Option<(PointerButton, [f32; 2])>,
+}
+
+impl ExternalSurface {
+    pub(crate) fn new(target: RenderTargetHandle, camera_controller: CameraController) -> Self {
+        ExternalSurface {
+            target,
+            camera_controller,
+            drag: None,
+        }
+    }
+
+    /// The render target drawn into, materials can show it like any other
+    pub fn target(&self) -> RenderTargetHandle {
+        self.target
+    }
+
+    /// Call it when the host's widget showing the surface changes size, the next update draws at the new size
+    pub fn resize(&self, engine: &mut RenderEngine, width: u32, height: u32) {
+        engine.resize_render_target(self.target, width, height);
+    }
+
+    pub fn camera<'a>(&self, engine: &'a RenderEngine) -> &'a OrbitCamera {
+        engine
+            .render_target(self.target)
+            .and_then(|target| target.camera.as_ref())
+            .expect("External surfaces keep their camera!")
+    }
+
+    /// Changes show up with the next update
+    pub fn camera_mut<'a>(&self, engine: &'a mut RenderEngine) -> &'a mut OrbitCamera {
+        engine
+            .render_target_mut(self.target)
+            .and_then(|target| target.camera.as_mut())
+            .expect("External surfaces keep their camera!")
+    }
+
+    /// Starts dragging from `position`, in pixels from the top left of the surface
+    pub fn pointer_pressed(&mut self, button: PointerButton, position: [f32; 2]) {
+        self.drag = Some((button, position));
+    }
+
+    pub fn pointer_released(&mut self, button: PointerButton) {
+        if self.drag.is_some_and(|(held, _)| held == button) {
+            self.drag = None;
+        }
+    }
+
+    /// Orbits the camera while the primary button is held and pans it while another one is
+    pub fn pointer_moved(&mut self, engine: &mut RenderEngine, position: [f32; 2]) {
+        let Some((button, last)) = &mut self.drag else {
+            return;
+        };
+        let [x, y] = [position[0] - last[0], position[1] - last[1]];
+        *last = position;
+        let speed = self.camera_controller.rotate_speed;
+        let button = *button;
+        let camera = self.camera_mut(engine);
+        match button {
+            PointerButton::Primary => {
+                camera.add_yaw(-x * speed);
+                camera.add_pitch(y * speed);
+            }
+            PointerButton::Secondary | PointerButton::Middle => {
+                camera.pan((x * speed / 2.0, y * speed / 2.0));
+            }
+        }
+    }
+
+    /// Zooms in for positive `lines`, turning a mouse wheel away from the user
+    pub fn scrolled(&self, engine: &mut RenderEngine, lines: f32) {
+        let zoom_speed = self.camera_controller.zoom_speed;
+        self.camera_mut(engine).add_distance(-lines * zoom_speed);
+    }
+
+    /// What the last update drew once [RenderEngine::flush] submitted it, in the engine's format. It's only valid on
+    /// the engine's device.
+    pub fn texture<'a>(&self, engine: &'a RenderEngine) -> &'a wgpu::Texture {
+        engine
+            .render_target(self.target)
+            .expect("External surfaces keep their render target!")
+            .color_texture()
+    }
+
+    /// Exports the texture updates draw into as a DMA-BUF. The host imports it once and shows it after every
+    /// [RenderEngine::flush] once the GPU is done, e.g. after [wgpu::Device::poll] waited. Export again after
+    /// [ExternalSurface::resize] or a device loss, they allocate a new texture.
+    #[cfg(all(feature = "dmabuf", target_os = "linux"))]
+    pub fn export_dmabuf(
+        &self,
+        engine: &mut RenderEngine,
+    ) -> std::io::Result<crate::dmabuf::DmaBuf> {
+        engine.export_render_target(self.target)
+    }
+
+    /// Copies out what the last update drew as tightly packed RGBA8 pixels, blocking until the GPU is done
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_pixels(&self, engine: &mut RenderEngine) -> Option<Vec<u8>> {
+        engine.read_render_target(self.target)
+    }
+}
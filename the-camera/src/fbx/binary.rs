@@ -0,0 +1,484 @@
+//! Reading binary FBX 7.x files into their tree of nodes, each with a name, a list of typed properties and children
+
+use std::io;
+
+const MAGIC: &[u8] = b"Kaydara FBX Binary  \0";
+/// Magic, two more bytes and the version
+const HEADER_SIZE: usize = 27;
+/// Node headers have 64 bit offsets from here on
+const WIDE_VERSION: u32 = 7500;
+/// Deeper files are rejected rather than risking the stack
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub name: String,
+    pub properties: Vec<Property>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn child(&self, name: &str) -> Option<&Node> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Node> + 'a {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    pub fn property(&self, index: usize) -> Option<&Property> {
+        self.properties.get(index)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Property {
+    Bool(bool),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    /// Names of objects are `name\0\x01class`
+    String(String),
+    Raw(Vec<u8>),
+    Bools(Vec<bool>),
+    I32s(Vec<i32>),
+    I64s(Vec<i64>),
+    F32s(Vec<f32>),
+    F64s(Vec<f64>),
+}
+
+impl Property {
+    /// Any integer or boolean
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Property::Bool(value) => Some(i64::from(value)),
+            Property::I16(value) => Some(i64::from(value)),
+            Property::I32(value) => Some(i64::from(value)),
+            Property::I64(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Any number
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Property::F32(value) => Some(f64::from(value)),
+            Property::F64(value) => Some(value),
+            _ => self.as_i64().map(|value| value as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Property::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Any array of numbers
+    pub fn to_f64s(&self) -> Option<Vec<f64>> {
+        Some(match self {
+            Property::F32s(values) => values.iter().map(|&value| f64::from(value)).collect(),
+            Property::F64s(values) => values.clone(),
+            Property::I32s(values) => values.iter().map(|&value| f64::from(value)).collect(),
+            Property::I64s(values) => values.iter().map(|&value| value as f64).collect(),
+            _ => return None,
+        })
+    }
+
+    /// Any array of integers
+    pub fn to_i64s(&self) -> Option<Vec<i64>> {
+        Some(match self {
+            Property::I32s(values) => values.iter().map(|&value| i64::from(value)).collect(),
+            Property::I64s(values) => values.clone(),
+            _ => return None,
+        })
+    }
+}
+
+/// Whether the bytes start like a binary FBX file, and its version
+pub fn version(bytes: &[u8]) -> Option<u32> {
+    if !bytes.starts_with(MAGIC) {
+        return None;
+    }
+    let version = bytes.get(23..HEADER_SIZE)?;
+    Some(u32::from_le_bytes([
+        version[0], version[1], version[2], version[3],
+    ]))
+}
+
+/// The top level nodes, like `GlobalSettings`, `Objects` and `Connections`
+pub fn parse(bytes: &[u8]) -> io::Result<Vec<Node>> {
+    let version = version(bytes).ok_or_else(|| invalid("not a binary FBX file"))?;
+    let mut reader = Reader {
+        bytes,
+        position: HEADER_SIZE,
+        wide: version >= WIDE_VERSION,
+    };
+    let mut nodes = Vec::new();
+    while let Some(node) = reader.node(0)? {
+        nodes.push(node);
+    }
+    Ok(nodes)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("FBX: {message}"))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    wide: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.position..self.position + count)
+            .ok_or_else(|| invalid("unexpected end of file"))?;
+        self.position += count;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        Ok(self.take(N)?.try_into().expect("Took N bytes!"))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    /// Offsets and counts in node headers
+    fn header_field(&mut self) -> io::Result<usize> {
+        let field = if self.wide {
+            u64::from_le_bytes(self.array()?)
+        } else {
+            u64::from(self.u32()?)
+        };
+        usize::try_from(field).map_err(|_| invalid("offset out of range"))
+    }
+
+    /// None for the empty record ending a list of nodes, or at the end of the file
+    fn node(&mut self, depth: usize) -> io::Result<Option<Node>> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("nodes nested too deeply"));
+        }
+        let header_size = if self.wide { 25 } else { 13 };
+        if self.position + header_size > self.bytes.len() {
+            return Ok(None);
+        }
+        let end = self.header_field()?;
+        let property_count = self.header_field()?;
+        let _property_list_size = self.header_field()?;
+        let name_length = usize::from(self.take(1)?[0]);
+        if end == 0 {
+            return Ok(None);
+        }
+        if end > self.bytes.len() || end < self.position {
+            return Err(invalid("node ends outside of the file"));
+        }
+        let name = String::from_utf8_lossy(self.take(name_length)?).into_owned();
+        let properties = (0..property_count)
+            .map(|_| self.property())
+            .collect::<io::Result<_>>()?;
+        let mut children = Vec::new();
+        while self.position < end {
+            match self.node(depth + 1)? {
+                Some(child) => children.push(child),
+                None => break,
+            }
+        }
+        self.position = end;
+        Ok(Some(Node {
+            name,
+            properties,
+            children,
+        }))
+    }
+
+    fn property(&mut self) -> io::Result<Property> {
+        let code = self.take(1)?[0];
+        Ok(match code {
+            b'C' => Property::Bool(self.take(1)?[0] != 0),
+            b'Y' => Property::I16(i16::from_le_bytes(self.array()?)),
+            b'I' => Property::I32(i32::from_le_bytes(self.array()?)),
+            b'L' => Property::I64(i64::from_le_bytes(self.array()?)),
+            b'F' => Property::F32(f32::from_le_bytes(self.array()?)),
+            b'D' => Property::F64(f64::from_le_bytes(self.array()?)),
+            b'S' | b'R' => {
+                let length = self.u32()? as usize;
+                let bytes = self.take(length)?;
+                match code {
+                    b'S' => Property::String(String::from_utf8_lossy(bytes).into_owned()),
+                    _ => Property::Raw(bytes.to_vec()),
+                }
+            }
+            b'b' => Property::Bools(self.elements(1, |bytes| bytes[0] != 0)?),
+            b'i' => Property::I32s(self.elements(4, |bytes| {
+                i32::from_le_bytes(bytes.try_into().expect("Chunks of 4!"))
+            })?),
+            b'l' => Property::I64s(self.elements(8, |bytes| {
+                i64::from_le_bytes(bytes.try_into().expect("Chunks of 8!"))
+            })?),
+            b'f' => Property::F32s(self.elements(4, |bytes| {
+                f32::from_le_bytes(bytes.try_into().expect("Chunks of 4!"))
+            })?),
+            b'd' => Property::F64s(self.elements(8, |bytes| {
+                f64::from_le_bytes(bytes.try_into().expect("Chunks of 8!"))
+            })?),
+            _ => {
+                return Err(invalid(&format!(
+                    "unknown property type {:?}",
+                    code as char
+                )))
+            }
+        })
+    }
+
+    /// An array property's elements, stored as they are or zlib compressed
+    fn elements<T>(&mut self, size: usize, element: impl Fn(&[u8]) -> T) -> io::Result<Vec<T>> {
+        let count = self.u32()? as usize;
+        let encoding = self.u32()?;
+        let stored_size = self.u32()? as usize;
+        let stored = self.take(stored_size)?;
+        let inflated;
+        let bytes = match encoding {
+            0 => stored,
+            1 => {
+                inflated = miniz_oxide::inflate::decompress_to_vec_zlib(stored)
+                    .map_err(|err| invalid(&format!("corrupt compressed array: {err}")))?;
+                &inflated
+            }
+            _ => return Err(invalid(&format!("unknown array encoding {encoding}"))),
+        };
+        if bytes.len() != count * size {
+            return Err(invalid("array size doesn't match its length"));
+        }
+        Ok(bytes.chunks_exact(size).map(element).collect())
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    pub(in crate::fbx) fn node(name: &str, properties: Vec<Property>, children: Vec<Node>) -> Node {
+        Node {
+            name: name.to_string(),
+            properties,
+            children,
+        }
+    }
+
+    /// A binary FBX file of the `version` holding `nodes`, with arrays stored as they are
+    pub(in crate::fbx) fn write(version: u32, nodes: &[Node]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend([0x1a, 0]);
+        bytes.extend(version.to_le_bytes());
+        let wide = version >= WIDE_VERSION;
+        for node in nodes {
+            write_node(&mut bytes, node, wide);
+        }
+        bytes.resize(bytes.len() + if wide { 25 } else { 13 }, 0);
+        bytes
+    }
+
+    fn write_node(bytes: &mut Vec<u8>, node: &Node, wide: bool) {
+        let field_size = if wide { 8 } else { 4 };
+        let start = bytes.len();
+        bytes.resize(start + 3 * field_size, 0);
+        bytes.push(node.name.len() as u8);
+        bytes.extend(node.name.as_bytes());
+        let properties = bytes.len();
+        for property in &node.properties {
+            write_property(bytes, property);
+        }
+        let property_list_size = bytes.len() - properties;
+        if !node.children.is_empty() {
+            for child in &node.children {
+                write_node(bytes, child, wide);
+            }
+            bytes.resize(bytes.len() + 3 * field_size + 1, 0);
+        }
+        let fields = [bytes.len(), node.properties.len(), property_list_size];
+        for (index, field) in fields.into_iter().enumerate() {
+            let at = start + index * field_size;
+            bytes[at..at + field_size].copy_from_slice(&(field as u64).to_le_bytes()[..field_size]);
+        }
+    }
+
+    fn write_property(bytes: &mut Vec<u8>, property: &Property) {
+        let mut array = |code: u8, count: usize, elements: Vec<u8>| {
+            bytes.push(code);
+            bytes.extend((count as u32).to_le_bytes());
+            bytes.extend(0u32.to_le_bytes());
+            bytes.extend((elements.len() as u32).to_le_bytes());
+            bytes.extend(elements);
+        };
+        match property {
+            Property::Bools(values) => array(
+                b'b',
+                values.len(),
+                values.iter().map(|&value| u8::from(value)).collect(),
+            ),
+            Property::I32s(values) => array(
+                b'i',
+                values.len(),
+                values
+                    .iter()
+                    .flat_map(|value| value.to_le_bytes())
+                    .collect(),
+            ),
+            Property::I64s(values) => array(
+                b'l',
+                values.len(),
+                values
+                    .iter()
+                    .flat_map(|value| value.to_le_bytes())
+                    .collect(),
+            ),
+            Property::F32s(values) => array(
+                b'f',
+                values.len(),
+                values
+                    .iter()
+                    .flat_map(|value| value.to_le_bytes())
+                    .collect(),
+            ),
+            Property::F64s(values) => array(
+                b'd',
+                values.len(),
+                values
+                    .iter()
+                    .flat_map(|value| value.to_le_bytes())
+                    .collect(),
+            ),
+            Property::Bool(value) => bytes.extend([b'C', u8::from(*value)]),
+            Property::I16(value) => {
+                bytes.push(b'Y');
+                bytes.extend(value.to_le_bytes());
+            }
+            Property::I32(value) => {
+                bytes.push(b'I');
+                bytes.extend(value.to_le_bytes());
+            }
+            Property::I64(value) => {
+                bytes.push(b'L');
+                bytes.extend(value.to_le_bytes());
+            }
+            Property::F32(value) => {
+                bytes.push(b'F');
+                bytes.extend(value.to_le_bytes());
+            }
+            Property::F64(value) => {
+                bytes.push(b'D');
+                bytes.extend(value.to_le_bytes());
+            }
+            Property::String(string) => {
+                bytes.push(b'S');
+                bytes.extend((string.len() as u32).to_le_bytes());
+                bytes.extend(string.as_bytes());
+            }
+            Property::Raw(raw) => {
+                bytes.push(b'R');
+                bytes.extend((raw.len() as u32).to_le_bytes());
+                bytes.extend(raw);
+            }
+        }
+    }
+
+    fn tree() -> Vec<Node> {
+        let properties = vec![
+            Property::Bool(true),
+            Property::I16(-2),
+            Property::I32(-3),
+            Property::I64(1 << 40),
+            Property::F32(0.5),
+            Property::F64(-0.25),
+            Property::String("Cube\0\u{1}Model".into()),
+            Property::Raw(vec![1, 2, 3]),
+        ];
+        let arrays = vec![
+            Property::Bools(vec![true, false]),
+            Property::I32s(vec![0, 1, -3]),
+            Property::I64s(vec![7]),
+            Property::F32s(vec![1.5, 2.5]),
+            Property::F64s(vec![]),
+        ];
+        vec![
+            node("Header", properties, vec![node("Empty", vec![], vec![])]),
+            node("Objects", vec![], vec![node("Arrays", arrays, vec![])]),
+        ]
+    }
+
+    #[test]
+    fn reads_every_property_type() {
+        // Both sizes of node headers
+        for version in [7400, 7500] {
+            let bytes = write(version, &tree());
+            assert_eq!(super::version(&bytes), Some(version));
+            let nodes = parse(&bytes).unwrap();
+            assert_eq!(nodes.len(), 2);
+            for (node, expected) in nodes.iter().zip(&tree()) {
+                assert_eq!(node.name, expected.name);
+                assert_eq!(node.properties, expected.properties);
+                assert_eq!(node.children.len(), 1);
+                assert_eq!(node.children[0].properties, expected.children[0].properties);
+            }
+            let arrays = nodes[1].child("Arrays").unwrap();
+            assert_eq!(arrays.property(1).unwrap().to_i64s(), Some(vec![0, 1, -3]));
+            assert_eq!(arrays.property(3).unwrap().to_f64s(), Some(vec![1.5, 2.5]));
+            assert_eq!(
+                nodes[0].property(3).unwrap().as_f64(),
+                Some((1u64 << 40) as f64)
+            );
+        }
+    }
+
+    #[test]
+    fn inflates_compressed_arrays() {
+        let values: Vec<u8> = (0..100i32).flat_map(|value| value.to_le_bytes()).collect();
+        let compressed = miniz_oxide::deflate::compress_to_vec_zlib(&values, 6);
+        let mut bytes = write(
+            7400,
+            &[node("Array", vec![Property::I32s(vec![0])], vec![])],
+        );
+        // Swaps the one stored element for the compressed ones
+        let array = HEADER_SIZE + 13 + "Array".len();
+        let mut replacement = 100u32.to_le_bytes().to_vec();
+        replacement.extend(1u32.to_le_bytes());
+        replacement.extend((compressed.len() as u32).to_le_bytes());
+        replacement.extend(&compressed);
+        bytes.splice(array + 1..array + 17, replacement);
+        let end = (bytes.len() - 13) as u32;
+        bytes[HEADER_SIZE..HEADER_SIZE + 4].copy_from_slice(&end.to_le_bytes());
+
+        let nodes = parse(&bytes).unwrap();
+        assert_eq!(nodes[0].properties, [Property::I32s((0..100).collect())]);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        assert_eq!(version(b"; FBX 7.4.0 project file"), None);
+        assert!(parse(b"Kaydara FBX Binary  \0\x1a\0").is_err());
+
+        let bytes = write(7400, &tree());
+        assert!(parse(&bytes[..bytes.len() / 2]).is_err());
+        let mut unknown = bytes.clone();
+        let first_property = HEADER_SIZE + 13 + "Header".len();
+        unknown[first_property] = b'?';
+        let err = parse(&unknown).unwrap_err();
+        assert!(err.to_string().contains("unknown property type"), "{err}");
+
+        let mut nested = node("Leaf", vec![], vec![]);
+        for _ in 0..MAX_DEPTH + 2 {
+            nested = node("Nested", vec![], vec![nested]);
+        }
+        let err = parse(&write(7400, &[nested])).unwrap_err();
+        assert!(err.to_string().contains("nested too deeply"), "{err}");
+    }
+}
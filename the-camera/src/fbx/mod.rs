@@ -0,0 +1,1009 @@
+//! Importing binary FBX 7.x files, only with the `fbx` feature: meshes with their texture coordinates, vertex colors
+//! and materials' diffuse color and opacity, placed by the node hierarchy, along with the skeleton, skin weights and
+//! animation stacks of skinned meshes, see [crate::model]. ASCII files, textures, cameras, lights, blend shapes and
+//! curve tangents are left out, keyframes are interpolated linearly.
+
+mod binary;
+
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+};
+
+use cgmath::{Deg, InnerSpace, Matrix, Matrix3, Matrix4, Quaternion, SquareMatrix, Vector3};
+
+use self::binary::{Node, Property};
+use crate::{
+    mesh::Vertex,
+    model::{
+        AnimationClip, Channel, ChannelProperty, Joint, ModelData, ModelPart, PolygonBuilder,
+        Skeleton, Skin,
+    },
+    scene::{Material, Transform},
+};
+
+/// Of FBX times
+const TICKS_PER_SECOND: f64 = 46_186_158_000.0;
+/// Files older than 7.0 store objects differently
+const MIN_VERSION: u32 = 7000;
+
+/// Reads the meshes, skeleton and animations of a binary FBX file, in a Y up space measured in meters whatever the
+/// file's axes and units are
+pub fn decode(bytes: &[u8]) -> io::Result<ModelData> {
+    let unsupported = |message: String| io::Error::new(io::ErrorKind::Unsupported, message);
+    let Some(version) = binary::version(bytes) else {
+        let ascii = bytes.starts_with(b"; FBX")
+            || std::str::from_utf8(bytes).is_ok_and(|text| text.contains("FBXHeaderExtension:"));
+        return Err(match ascii {
+            true => unsupported("ASCII FBX files aren't supported, export them as binary".into()),
+            false => io::Error::new(io::ErrorKind::InvalidData, "Not an FBX file"),
+        });
+    };
+    if version < MIN_VERSION {
+        return Err(unsupported(format!(
+            "FBX {version} files aren't supported, only 7.0 and later"
+        )));
+    }
+    let nodes = binary::parse(bytes)?;
+    let document = Document::new(&nodes);
+
+    let axes = nodes
+        .iter()
+        .find(|node| node.name == "GlobalSettings")
+        .map_or_else(Matrix4::identity, axis_conversion);
+    let mut importer = Importer {
+        document: &document,
+        axes,
+        world_transforms: HashMap::new(),
+        joint_indices: HashMap::new(),
+    };
+    let mut model = ModelData {
+        skeleton: importer.skeleton(),
+        ..Default::default()
+    };
+    for (&id, node) in &document.objects {
+        if node.name == "Model" && subclass(node) == "Mesh" {
+            importer.mesh(id, node, &mut model)?;
+        }
+    }
+    if model.parts.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "FBX file has no meshes",
+        ));
+    }
+    // Objects are kept by ID, sorting by name keeps the order from one load to the next
+    model.parts.sort_by(|a, b| a.name.cmp(&b.name));
+    if model.skeleton.is_some() {
+        model.animations = importer.animations();
+    }
+    Ok(model)
+}
+
+/// The objects and how they're connected, which is most of an FBX file's meaning
+struct Document<'a> {
+    objects: HashMap<i64, &'a Node>,
+    /// Child, parent and the parent's property for property connections, in the file's order
+    connections: Vec<(i64, i64, Option<&'a str>)>,
+}
+
+impl<'a> Document<'a> {
+    fn new(nodes: &'a [Node]) -> Self {
+        let top = |name: &str| nodes.iter().find(|node| node.name == name);
+        let objects = top("Objects")
+            .into_iter()
+            .flat_map(|objects| &objects.children)
+            .filter_map(|object| Some((object.property(0)?.as_i64()?, object)))
+            .collect();
+        let connections = top("Connections")
+            .into_iter()
+            .flat_map(|connections| connections.children_named("C"))
+            .filter_map(|connection| {
+                let child = connection.property(1)?.as_i64()?;
+                let parent = connection.property(2)?.as_i64()?;
+                let property = match connection.property(0)?.as_str()? {
+                    "OP" => Some(connection.property(3)?.as_str()?),
+                    _ => None,
+                };
+                Some((child, parent, property))
+            })
+            .collect();
+        Document {
+            objects,
+            connections,
+        }
+    }
+
+    /// Objects of the `kind`, like `Model` or `Geometry`, connected to `parent`. With a `property`, only the ones
+    /// connected to that property.
+    fn children<'b>(
+        &'b self,
+        parent: i64,
+        kind: &'b str,
+        property: Option<&'b str>,
+    ) -> impl Iterator<Item = (i64, &'a Node)> + 'b {
+        self.connections
+            .iter()
+            .filter(move |&&(_, to, to_property)| to == parent && to_property == property)
+            .filter_map(move |&(child, ..)| {
+                let node = *self.objects.get(&child)?;
+                (node.name == kind).then_some((child, node))
+            })
+    }
+
+    /// The objects `child` is connected to, with the property it's connected to
+    fn parents(&self, child: i64) -> impl Iterator<Item = (i64, &'a Node, Option<&'a str>)> + '_ {
+        self.connections
+            .iter()
+            .filter(move |&&(from, ..)| from == child)
+            .filter_map(|&(_, parent, property)| {
+                Some((parent, *self.objects.get(&parent)?, property))
+            })
+    }
+
+    /// The model a model hangs from, None for roots
+    fn parent_model(&self, model: i64) -> Option<i64> {
+        self.parents(model)
+            .find(|(_, node, property)| node.name == "Model" && property.is_none())
+            .map(|(parent, ..)| parent)
+    }
+}
+
+struct Importer<'a, 'b> {
+    document: &'b Document<'a>,
+    /// From the file's axes and units to the engine's
+    axes: Matrix4<f32>,
+    world_transforms: HashMap<i64, Matrix4<f32>>,
+    /// Index into the skeleton's joints of each joint model
+    joint_indices: HashMap<i64, usize>,
+}
+
+impl Importer<'_, '_> {
+    /// Where a model is in the file's space, through all of its parents
+    fn world_transform(&mut self, model: i64) -> Matrix4<f32> {
+        if let Some(&transform) = self.world_transforms.get(&model) {
+            return transform;
+        }
+        let local = self
+            .document
+            .objects
+            .get(&model)
+            .map_or_else(Matrix4::identity, |node| local_transform(node));
+        let transform = match self.document.parent_model(model) {
+            Some(parent) => self.world_transform(parent) * local,
+            None => local,
+        };
+        self.world_transforms.insert(model, transform);
+        transform
+    }
+
+    /// Limb nodes, the models skin clusters follow and every model above them, parents first
+    fn skeleton(&mut self) -> Option<Skeleton> {
+        let document = self.document;
+        let mut joints = HashSet::new();
+        for (&id, node) in &document.objects {
+            let bone = match node.name.as_str() {
+                "Model" => subclass(node) == "LimbNode",
+                "Deformer" if subclass(node) == "Cluster" => {
+                    for (model, _) in document.children(id, "Model", None) {
+                        joints.insert(model);
+                    }
+                    false
+                }
+                _ => false,
+            };
+            if bone {
+                joints.insert(id);
+            }
+        }
+        let bones: Vec<i64> = joints.iter().copied().collect();
+        for bone in bones {
+            let mut model = bone;
+            while let Some(parent) = document.parent_model(model) {
+                joints.insert(parent);
+                model = parent;
+            }
+        }
+        if joints.is_empty() {
+            return None;
+        }
+
+        let mut roots: Vec<(i64, &Node)> = document
+            .objects
+            .iter()
+            .filter(|&(&id, _)| joints.contains(&id) && document.parent_model(id).is_none())
+            .map(|(&id, &node)| (id, node))
+            .collect();
+        roots.sort_by_key(|&(_, node)| object_name(node));
+        let mut skeleton = Skeleton::default();
+        let mut pending: Vec<(i64, &Node, Option<usize>)> = roots
+            .into_iter()
+            .rev()
+            .map(|(id, node)| (id, node, None))
+            .collect();
+        while let Some((id, node, parent)) = pending.pop() {
+            let local = local_transform(node);
+            let rest = match parent {
+                Some(_) => local,
+                None => self.axes * local,
+            };
+            self.joint_indices.insert(id, skeleton.joints.len());
+            skeleton.joints.push(Joint {
+                name: object_name(node),
+                parent,
+                rest: Transform::from_matrix(rest),
+            });
+            let index = skeleton.joints.len() - 1;
+            let children: Vec<_> = document
+                .children(id, "Model", None)
+                .filter(|(child, _)| joints.contains(child))
+                .collect();
+            pending.extend(
+                children
+                    .into_iter()
+                    .rev()
+                    .map(|(child, node)| (child, node, Some(index))),
+            );
+        }
+        Some(skeleton)
+    }
+
+    fn mesh(&mut self, id: i64, model: &Node, data: &mut ModelData) -> io::Result<()> {
+        let document = self.document;
+        let name = object_name(model);
+        let invalid = |message: &str| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{name}: {message}"))
+        };
+        let Some((geometry_id, geometry)) = document
+            .children(id, "Geometry", None)
+            .find(|(_, geometry)| subclass(geometry) == "Mesh")
+        else {
+            return Ok(());
+        };
+        let points: Vec<[f32; 3]> = geometry
+            .child("Vertices")
+            .and_then(|vertices| vertices.property(0)?.to_f64s())
+            .ok_or_else(|| invalid("no vertices"))?
+            .chunks_exact(3)
+            .map(|point| [point[0] as f32, point[1] as f32, point[2] as f32])
+            .collect();
+        let polygon_vertices = geometry
+            .child("PolygonVertexIndex")
+            .and_then(|indices| indices.property(0)?.to_i64s())
+            .ok_or_else(|| invalid("no polygons"))?;
+        let tex_coords = LayerElement::read(geometry, "LayerElementUV", "UV", 2);
+        let colors = LayerElement::read(geometry, "LayerElementColor", "Colors", 4);
+        let material_slots = LayerElement::read(geometry, "LayerElementMaterial", "Materials", 1);
+        let mut materials: Vec<Material> = document
+            .children(id, "Material", None)
+            .map(|(material_id, material)| self.material(material_id, material))
+            .collect();
+        if materials.is_empty() {
+            materials.push(Material::default());
+        }
+
+        let transform = self.axes * self.world_transform(id) * geometric_transform(model);
+        // Faces are counter-clockwise like the engine's, unless a transform mirrors them
+        let flip = transform.determinant() < 0.0;
+        let mut polygons: Vec<PolygonBuilder<Corner>> = materials
+            .iter()
+            .map(|_| PolygonBuilder::default())
+            .collect();
+        let mut corners = Vec::new();
+        let mut polygon = 0;
+        for (corner, &index) in polygon_vertices.iter().enumerate() {
+            // The last corner of each polygon is stored as its bitwise complement
+            let point = if index < 0 { !index } else { index } as usize;
+            if point >= points.len() {
+                return Err(invalid("polygon vertex index out of range"));
+            }
+            let tex_coord = tex_coords
+                .as_ref()
+                .and_then(|uv| uv.element(polygon, corner, point));
+            let color = colors
+                .as_ref()
+                .and_then(|colors| colors.element(polygon, corner, point));
+            corners.push((point, tex_coord, color));
+            if index >= 0 {
+                continue;
+            }
+            let slot = material_slots
+                .as_ref()
+                .and_then(|slots| Some(slots.value(slots.element(polygon, corner, point)?)[0]))
+                .map_or(0, |slot| slot as usize);
+            let builder = polygons
+                .get_mut(slot)
+                .ok_or_else(|| invalid("material index out of range"))?;
+            builder.add(&corners, flip, |&(point, tex_coord, color)| {
+                // FBX counts V from the bottom of the image, wgpu from the top
+                let [u, v] = tex_coord
+                    .and_then(|element| Some(tex_coords.as_ref()?.value(element)))
+                    .map_or([0.0, 1.0], |uv| [uv[0] as f32, uv[1] as f32]);
+                let color = color
+                    .and_then(|element| Some(colors.as_ref()?.value(element)))
+                    .map_or([1.0; 3], |rgba| {
+                        [rgba[0] as f32, rgba[1] as f32, rgba[2] as f32]
+                    });
+                Vertex::new(points[point], color, [u, 1.0 - v])
+            });
+            corners.clear();
+            polygon += 1;
+        }
+
+        let influences = self.influences(geometry_id, points.len());
+        for (builder, material) in polygons.into_iter().zip(materials) {
+            for (mesh, keys) in builder.finish() {
+                let skin = influences.as_ref().map(|(skin, influences)| {
+                    let (vertex_joints, vertex_weights) = keys
+                        .iter()
+                        .map(|&(point, ..)| strongest_four(&influences[point]))
+                        .unzip();
+                    Skin {
+                        vertex_joints,
+                        vertex_weights,
+                        ..skin.clone()
+                    }
+                });
+                data.parts.push(ModelPart {
+                    name: name.clone(),
+                    mesh,
+                    material,
+                    texture: None,
+                    transform,
+                    skin,
+                    joint: None,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The joints of the geometry's skin without any vertices yet, and each control point's joints and weights
+    fn influences(&self, geometry: i64, points: usize) -> Option<(Skin, Vec<Influences>)> {
+        let document = self.document;
+        let (skin, _) = document
+            .children(geometry, "Deformer", None)
+            .find(|(_, deformer)| subclass(deformer) == "Skin")?;
+        let mut joints = Vec::new();
+        let mut inverse_binds = Vec::new();
+        let mut influences = vec![Vec::new(); points];
+        for (cluster_id, cluster) in document.children(skin, "Deformer", None) {
+            if subclass(cluster) != "Cluster" {
+                continue;
+            }
+            let Some(joint) = document
+                .children(cluster_id, "Model", None)
+                .find_map(|(model, _)| self.joint_indices.get(&model).copied())
+            else {
+                continue;
+            };
+            let array = |name: &str| {
+                cluster
+                    .child(name)
+                    .and_then(|node| node.property(0)?.to_f64s())
+            };
+            let matrix = |name: &str| array(name).and_then(|values| matrix(&values));
+            // Where the mesh and the joint were when the skin was bound, both in the file's space
+            let mesh_bind = matrix("Transform").unwrap_or_else(Matrix4::identity);
+            let joint_bind = matrix("TransformLink").unwrap_or_else(Matrix4::identity);
+            let slot = joints.len() as u16;
+            joints.push(joint);
+            inverse_binds.push(joint_bind.invert().unwrap_or_else(Matrix4::identity) * mesh_bind);
+            let (Some(indices), Some(weights)) = (array("Indexes"), array("Weights")) else {
+                continue;
+            };
+            for (&point, &weight) in indices.iter().zip(&weights) {
+                if let Some(influences) = influences.get_mut(point as usize) {
+                    influences.push((slot, weight as f32));
+                }
+            }
+        }
+        let skin = Skin {
+            joints,
+            inverse_binds,
+            ..Default::default()
+        };
+        (!skin.joints.is_empty()).then_some((skin, influences))
+    }
+
+    fn material(&self, id: i64, material: &Node) -> Material {
+        if self
+            .document
+            .children(id, "Texture", Some("DiffuseColor"))
+            .next()
+            .is_some()
+        {
+            tracing::warn!(
+                "{}: textures aren't supported, using the diffuse color",
+                object_name(material)
+            );
+        }
+        // FBX's defaults
+        let [r, g, b] = vector(material, "DiffuseColor")
+            .or_else(|| vector(material, "Diffuse"))
+            .map_or([0.8; 3], Into::into);
+        let opacity = number(material, "Opacity").unwrap_or(1.0);
+        Material {
+            base_color: [r, g, b, opacity],
+            ..Default::default()
+        }
+    }
+
+    /// A clip for every animation stack, from its first layer's curves moving joints
+    fn animations(&self) -> Vec<AnimationClip> {
+        let document = self.document;
+        let mut stacks: Vec<(i64, &Node)> = document
+            .objects
+            .iter()
+            .filter(|(_, node)| node.name == "AnimationStack")
+            .map(|(&id, &node)| (id, node))
+            .collect();
+        stacks.sort_by_key(|&(_, stack)| object_name(stack));
+
+        let mut clips = Vec::new();
+        for (stack, stack_node) in stacks {
+            let Some((layer, _)) = document.children(stack, "AnimationLayer", None).next() else {
+                continue;
+            };
+            let mut channels = Vec::new();
+            for (curve_node, curve_node_data) in
+                document.children(layer, "AnimationCurveNode", None)
+            {
+                for (model, model_node, property) in document.parents(curve_node) {
+                    let Some(&joint) = self.joint_indices.get(&model) else {
+                        continue;
+                    };
+                    let property = match property {
+                        Some("Lcl Translation") => ChannelProperty::Translation,
+                        Some("Lcl Rotation") => ChannelProperty::Rotation,
+                        Some("Lcl Scaling") => ChannelProperty::Scale,
+                        _ => continue,
+                    };
+                    let root = document.parent_model(model).is_none();
+                    if let Some(channel) = self.channel(
+                        curve_node,
+                        curve_node_data,
+                        model_node,
+                        joint,
+                        property,
+                        root,
+                    ) {
+                        channels.push(channel);
+                    }
+                }
+            }
+            if channels.is_empty() {
+                continue;
+            }
+            let duration = channels
+                .iter()
+                .filter_map(|channel| channel.times.last().copied())
+                .fold(0.0, f32::max);
+            clips.push(AnimationClip {
+                name: object_name(stack_node),
+                duration,
+                channels,
+            });
+        }
+        clips
+    }
+
+    /// Samples the curve node's X, Y and Z curves at all of their keyframes
+    fn channel(
+        &self,
+        curve_node: i64,
+        curve_node_data: &Node,
+        model: &Node,
+        joint: usize,
+        property: ChannelProperty,
+        root: bool,
+    ) -> Option<Channel> {
+        let current = match property {
+            ChannelProperty::Translation => vector(model, "Lcl Translation"),
+            ChannelProperty::Rotation => vector(model, "Lcl Rotation"),
+            ChannelProperty::Scale => vector(model, "Lcl Scaling"),
+        }
+        .unwrap_or(match property {
+            ChannelProperty::Scale => Vector3::new(1.0, 1.0, 1.0),
+            _ => Vector3::new(0.0, 0.0, 0.0),
+        });
+        let curves: [Option<Curve>; 3] = ["d|X", "d|Y", "d|Z"].map(|axis| {
+            let (_, curve) = self
+                .document
+                .children(curve_node, "AnimationCurve", Some(axis))
+                .next()?;
+            Curve::read(curve)
+        });
+        let defaults: [f32; 3] = ["d|X", "d|Y", "d|Z"]
+            .iter()
+            .zip([current.x, current.y, current.z])
+            .map(|(axis, current)| number(curve_node_data, axis).unwrap_or(current))
+            .collect::<Vec<_>>()
+            .try_into()
+            .ok()?;
+        let mut times: Vec<f64> = curves
+            .iter()
+            .flatten()
+            .flat_map(|curve| curve.times.iter().copied())
+            .collect();
+        if times.is_empty() {
+            return None;
+        }
+        times.sort_by(f64::total_cmp);
+        times.dedup();
+
+        let order = rotation_order(model);
+        let pre_rotation = euler(
+            0,
+            vector(model, "PreRotation").unwrap_or(Vector3::new(0.0, 0.0, 0.0)),
+        );
+        let post_rotation = euler(
+            0,
+            vector(model, "PostRotation").unwrap_or(Vector3::new(0.0, 0.0, 0.0)),
+        );
+        let (axes_rotation, axes_scale) = decompose_rotation(self.axes);
+        let values = times
+            .iter()
+            .map(|&time| {
+                let [x, y, z] = [0, 1, 2].map(|axis| {
+                    curves[axis]
+                        .as_ref()
+                        .map_or(defaults[axis], |curve| curve.sample(time))
+                });
+                let value = Vector3::new(x, y, z);
+                match property {
+                    ChannelProperty::Translation => {
+                        let translation = if root {
+                            (self.axes * value.extend(1.0)).truncate()
+                        } else {
+                            value
+                        };
+                        translation.extend(0.0).into()
+                    }
+                    ChannelProperty::Rotation => {
+                        let matrix = pre_rotation
+                            * euler(order, value)
+                            * post_rotation.invert().unwrap_or_else(Matrix4::identity);
+                        let (rotation, _) = decompose_rotation(matrix);
+                        let rotation = if root {
+                            axes_rotation * rotation
+                        } else {
+                            rotation
+                        };
+                        [rotation.v.x, rotation.v.y, rotation.v.z, rotation.s]
+                    }
+                    ChannelProperty::Scale => {
+                        let scale = if root { value * axes_scale } else { value };
+                        scale.extend(0.0).into()
+                    }
+                }
+            })
+            .collect();
+        Some(Channel {
+            joint,
+            property,
+            times: times
+                .into_iter()
+                .map(|time| (time / TICKS_PER_SECOND) as f32)
+                .collect(),
+            values,
+        })
+    }
+}
+
+/// A polygon corner's control point, and its texture coordinate and color elements
+type Corner = (usize, Option<usize>, Option<usize>);
+/// The joint slots in a [Skin] that move a control point, with their weights
+type Influences = Vec<(u16, f32)>;
+
+/// An `AnimationCurve`'s keyframes, times in FBX ticks
+struct Curve {
+    times: Vec<f64>,
+    values: Vec<f32>,
+}
+
+impl Curve {
+    fn read(curve: &Node) -> Option<Self> {
+        let times = curve.child("KeyTime")?.property(0)?.to_f64s()?;
+        let values: Vec<f32> = curve
+            .child("KeyValueFloat")?
+            .property(0)?
+            .to_f64s()?
+            .into_iter()
+            .map(|value| value as f32)
+            .collect();
+        (!times.is_empty() && times.len() == values.len()).then_some(Curve { times, values })
+    }
+
+    /// Linearly between the keyframes around `time`, held before the first and after the last
+    fn sample(&self, time: f64) -> f32 {
+        let next = self.times.partition_point(|&key| key <= time);
+        if next == 0 {
+            return self.values[0];
+        }
+        if next == self.times.len() {
+            return self.values[next - 1];
+        }
+        let (start, end) = (self.times[next - 1], self.times[next]);
+        let t = ((time - start) / (end - start)) as f32;
+        self.values[next - 1] + (self.values[next] - self.values[next - 1]) * t
+    }
+}
+
+/// A per polygon, per corner or per control point array of a geometry, like its texture coordinates
+struct LayerElement {
+    mapping: Mapping,
+    /// `width` numbers for each element
+    values: Vec<f64>,
+    indices: Option<Vec<i64>>,
+    width: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mapping {
+    AllSame,
+    ByPolygon,
+    ByPolygonVertex,
+    ByControlPoint,
+}
+
+impl LayerElement {
+    /// The first layer's element, e.g. a `LayerElementUV` with its `UV` and `UVIndex` arrays
+    fn read(geometry: &Node, element: &str, values: &str, width: usize) -> Option<Self> {
+        let element = geometry
+            .children_named(element)
+            .min_by_key(|element| element.property(0).and_then(Property::as_i64))?;
+        let text = |name: &str| element.child(name)?.property(0)?.as_str();
+        let mapping = match text("MappingInformationType")? {
+            "AllSame" => Mapping::AllSame,
+            "ByPolygon" => Mapping::ByPolygon,
+            "ByPolygonVertex" => Mapping::ByPolygonVertex,
+            "ByVertice" | "ByVertex" | "ByControlPoint" => Mapping::ByControlPoint,
+            mapping => {
+                tracing::warn!(
+                    "Skipping {element:?} mapped {mapping}",
+                    element = element.name
+                );
+                return None;
+            }
+        };
+        let array = |name: &str| element.child(name)?.property(0);
+        // Material slots are indices already
+        let direct = text("ReferenceInformationType") == Some("Direct") || width == 1;
+        let indices = match direct {
+            true => None,
+            false => Some(array(&format!("{values}Index"))?.to_i64s()?),
+        };
+        Some(LayerElement {
+            mapping,
+            values: array(values)?.to_f64s()?,
+            indices,
+            width,
+        })
+    }
+
+    fn element(&self, polygon: usize, corner: usize, point: usize) -> Option<usize> {
+        let element = match self.mapping {
+            Mapping::AllSame => 0,
+            Mapping::ByPolygon => polygon,
+            Mapping::ByPolygonVertex => corner,
+            Mapping::ByControlPoint => point,
+        };
+        let element = match &self.indices {
+            Some(indices) => usize::try_from(*indices.get(element)?).ok()?,
+            None => element,
+        };
+        ((element + 1) * self.width <= self.values.len()).then_some(element)
+    }
+
+    fn value(&self, element: usize) -> &[f64] {
+        &self.values[element * self.width..(element + 1) * self.width]
+    }
+}
+
+/// An object's name without the class FBX appends to it
+fn object_name(object: &Node) -> String {
+    let name = object
+        .property(1)
+        .and_then(Property::as_str)
+        .unwrap_or_default();
+    name.split("\0\u{1}").next().unwrap_or(name).to_string()
+}
+
+/// E.g. `Mesh` or `LimbNode` for models, `Skin` or `Cluster` for deformers
+fn subclass(object: &Node) -> &str {
+    object
+        .property(2)
+        .and_then(Property::as_str)
+        .unwrap_or_default()
+}
+
+/// The values of an entry in a node's `Properties70`, after its name, its types and its flags
+fn values<'a>(node: &'a Node, name: &str) -> Option<&'a [Property]> {
+    node.child("Properties70")?
+        .children_named("P")
+        .find(|entry| entry.property(0).and_then(Property::as_str) == Some(name))
+        .map(|entry| entry.properties.get(4..).unwrap_or_default())
+}
+
+fn number(node: &Node, name: &str) -> Option<f32> {
+    Some(values(node, name)?.first()?.as_f64()? as f32)
+}
+
+fn vector(node: &Node, name: &str) -> Option<Vector3<f32>> {
+    let values = values(node, name)?;
+    let component = |index: usize| Some(values.get(index)?.as_f64()? as f32);
+    Some(Vector3::new(component(0)?, component(1)?, component(2)?))
+}
+
+/// 16 numbers, each 4 of them a column
+fn matrix(values: &[f64]) -> Option<Matrix4<f32>> {
+    let values: [f64; 16] = values.try_into().ok()?;
+    let column = |index: usize| {
+        let column = &values[index * 4..index * 4 + 4];
+        [column[0], column[1], column[2], column[3]]
+            .map(|value| value as f32)
+            .into()
+    };
+    Some(Matrix4::from_cols(
+        column(0),
+        column(1),
+        column(2),
+        column(3),
+    ))
+}
+
+/// From the file's axes to X right, Y up and Z towards the viewer, and from its units to meters
+fn axis_conversion(settings: &Node) -> Matrix4<f32> {
+    let setting = |name: &str, default: f32| number(settings, name).unwrap_or(default);
+    let axis = |name: &str, sign: &str, default: f32| {
+        let mut row = Vector3::new(0.0, 0.0, 0.0);
+        row[(setting(name, default) as usize).min(2)] = setting(sign, 1.0).signum();
+        row
+    };
+    let rows = [
+        axis("CoordAxis", "CoordAxisSign", 0.0),
+        axis("UpAxis", "UpAxisSign", 1.0),
+        axis("FrontAxis", "FrontAxisSign", 2.0),
+    ];
+    // Centimeters unless the file says otherwise
+    let scale = setting("UnitScaleFactor", 1.0) / 100.0;
+    Matrix4::from(Matrix3::from_cols(rows[0], rows[1], rows[2]).transpose())
+        * Matrix4::from_scale(scale)
+}
+
+/// FBX's rotation orders by their enum value, the axis rotated around first comes first
+fn rotation_order(model: &Node) -> usize {
+    number(model, "RotationOrder").map_or(0, |order| order as usize)
+}
+
+fn euler(order: usize, degrees: Vector3<f32>) -> Matrix4<f32> {
+    let [first, second, third] = match order {
+        1 => [0, 2, 1],
+        2 => [1, 2, 0],
+        3 => [1, 0, 2],
+        4 => [2, 0, 1],
+        5 => [2, 1, 0],
+        // XYZ, and spheric XYZ that only differs in interpolation
+        _ => [0, 1, 2],
+    };
+    let rotation = |axis: usize| match axis {
+        0 => Matrix4::from_angle_x(Deg(degrees.x)),
+        1 => Matrix4::from_angle_y(Deg(degrees.y)),
+        _ => Matrix4::from_angle_z(Deg(degrees.z)),
+    };
+    rotation(third) * rotation(second) * rotation(first)
+}
+
+/// A model relative to its parent, with the pivots and offsets FBX places rotation and scaling around
+fn local_transform(model: &Node) -> Matrix4<f32> {
+    let zero = Vector3::new(0.0, 0.0, 0.0);
+    let vector = |name: &str, default: Vector3<f32>| vector(model, name).unwrap_or(default);
+    let inverse = |matrix: Matrix4<f32>| matrix.invert().unwrap_or_else(Matrix4::identity);
+    let scaling = vector("Lcl Scaling", Vector3::new(1.0, 1.0, 1.0));
+
+    let rotation_pivot = Matrix4::from_translation(vector("RotationPivot", zero));
+    let scaling_pivot = Matrix4::from_translation(vector("ScalingPivot", zero));
+    Matrix4::from_translation(vector("Lcl Translation", zero))
+        * Matrix4::from_translation(vector("RotationOffset", zero))
+        * rotation_pivot
+        * euler(0, vector("PreRotation", zero))
+        * euler(rotation_order(model), vector("Lcl Rotation", zero))
+        * inverse(euler(0, vector("PostRotation", zero)))
+        * inverse(rotation_pivot)
+        * Matrix4::from_translation(vector("ScalingOffset", zero))
+        * scaling_pivot
+        * Matrix4::from_nonuniform_scale(scaling.x, scaling.y, scaling.z)
+        * inverse(scaling_pivot)
+}
+
+/// Applies to the model's geometry only, not to its children
+fn geometric_transform(model: &Node) -> Matrix4<f32> {
+    let zero = Vector3::new(0.0, 0.0, 0.0);
+    let scaling = vector(model, "GeometricScaling").unwrap_or(Vector3::new(1.0, 1.0, 1.0));
+    Matrix4::from_translation(vector(model, "GeometricTranslation").unwrap_or(zero))
+        * euler(0, vector(model, "GeometricRotation").unwrap_or(zero))
+        * Matrix4::from_nonuniform_scale(scaling.x, scaling.y, scaling.z)
+}
+
+/// The rotation of a matrix without shear, and its scale along the first axis
+fn decompose_rotation(matrix: Matrix4<f32>) -> (Quaternion<f32>, f32) {
+    let columns = [
+        matrix.x.truncate(),
+        matrix.y.truncate(),
+        matrix.z.truncate(),
+    ];
+    let scale = columns[0].magnitude();
+    let [x, y, z] = columns.map(|column| column.normalize());
+    (
+        Quaternion::from(Matrix3::from_cols(x, y, z)).normalize(),
+        scale,
+    )
+}
+
+/// The four joints with the most weight, normalized to add up to one
+fn strongest_four(influences: &[(u16, f32)]) -> ([u16; 4], [f32; 4]) {
+    let mut influences = influences.to_vec();
+    influences.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let mut joints = [0; 4];
+    let mut weights = [0.0; 4];
+    for (index, &(joint, weight)) in influences.iter().take(4).enumerate() {
+        joints[index] = joint;
+        weights[index] = weight;
+    }
+    let total: f32 = weights.iter().sum();
+    if total > 0.0 {
+        weights = weights.map(|weight| weight / total);
+    }
+    (joints, weights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fbx::binary::tests::{node, write};
+
+    fn string(string: &str) -> Property {
+        Property::String(string.to_string())
+    }
+
+    /// A `Properties70` entry of numbers
+    fn entry(name: &str, values: &[f64]) -> Node {
+        let mut properties = vec![string(name), string(""), string(""), string("A")];
+        properties.extend(values.iter().map(|&value| Property::F64(value)));
+        node("P", properties, vec![])
+    }
+
+    fn connection(child: i64, parent: i64) -> Node {
+        node(
+            "C",
+            vec![string("OO"), Property::I64(child), Property::I64(parent)],
+            vec![],
+        )
+    }
+
+    /// A red triangle moved by `translation`, in a file measured in meters
+    fn triangle_file(translation: [f64; 3]) -> Vec<u8> {
+        let settings = node(
+            "GlobalSettings",
+            vec![],
+            vec![node(
+                "Properties70",
+                vec![],
+                vec![entry("UnitScaleFactor", &[100.0])],
+            )],
+        );
+        let model = node(
+            "Model",
+            vec![Property::I64(1), string("Tri\0\u{1}Model"), string("Mesh")],
+            vec![node(
+                "Properties70",
+                vec![],
+                vec![entry("Lcl Translation", &translation)],
+            )],
+        );
+        let geometry = node(
+            "Geometry",
+            vec![
+                Property::I64(2),
+                string("Tri\0\u{1}Geometry"),
+                string("Mesh"),
+            ],
+            vec![
+                node(
+                    "Vertices",
+                    vec![Property::F64s(vec![
+                        0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+                    ])],
+                    vec![],
+                ),
+                // The last corner of a polygon is complemented
+                node(
+                    "PolygonVertexIndex",
+                    vec![Property::I32s(vec![0, 1, !2])],
+                    vec![],
+                ),
+            ],
+        );
+        let material = node(
+            "Material",
+            vec![Property::I64(3), string("Red\0\u{1}Material"), string("")],
+            vec![node(
+                "Properties70",
+                vec![],
+                vec![entry("DiffuseColor", &[1.0, 0.0, 0.0])],
+            )],
+        );
+        let connections = vec![connection(1, 0), connection(2, 1), connection(3, 1)];
+        write(
+            7400,
+            &[
+                settings,
+                node("Objects", vec![], vec![model, geometry, material]),
+                node("Connections", vec![], connections),
+            ],
+        )
+    }
+
+    #[test]
+    fn decodes_a_mesh_with_its_transform_and_material() {
+        let model = decode(&triangle_file([1.0, 2.0, 3.0])).unwrap();
+        assert_eq!(model.parts.len(), 1);
+        let part = &model.parts[0];
+        assert_eq!(part.name, "Tri");
+        let positions: Vec<_> = part.mesh.vertices.iter().map(Vertex::position).collect();
+        assert_eq!(
+            positions,
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+        );
+        assert_eq!(part.mesh.indices.len(), 3);
+        assert_eq!(
+            part.transform,
+            Matrix4::from_translation([1.0, 2.0, 3.0].into())
+        );
+        assert_eq!(part.material.base_color, [1.0, 0.0, 0.0, 1.0]);
+        assert!(model.skeleton.is_none());
+    }
+
+    #[test]
+    fn rejects_unsupported_files() {
+        let err = decode(b"; FBX 7.4.0 project file\nFBXHeaderExtension:  {").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        let mut old = triangle_file([0.0; 3]);
+        old[23..27].copy_from_slice(&6100u32.to_le_bytes());
+        assert_eq!(decode(&old).unwrap_err().kind(), io::ErrorKind::Unsupported);
+        assert!(decode(&write(7400, &[])).is_err());
+    }
+
+    #[test]
+    fn converts_axes_and_units() {
+        // Z up, in centimeters
+        let settings = node(
+            "GlobalSettings",
+            vec![],
+            vec![node(
+                "Properties70",
+                vec![],
+                vec![
+                    entry("UpAxis", &[2.0]),
+                    entry("FrontAxis", &[1.0]),
+                    entry("FrontAxisSign", &[-1.0]),
+                ],
+            )],
+        );
+        let axes = axis_conversion(&settings);
+        let up = axes * cgmath::Vector4::unit_z();
+        assert!(
+            (up - cgmath::Vector4::unit_y() * 0.01).magnitude() < 1e-6,
+            "{up:?}"
+        );
+    }
+
+    #[test]
+    fn keeps_the_strongest_four_influences() {
+        let (joints, weights) = strongest_four(&[(0, 0.1), (1, 0.4), (2, 0.2), (3, 0.2), (4, 0.1)]);
+        assert_eq!(joints[..2], [1, 2]);
+        assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        assert!((weights[0] - 0.4 / 0.9).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,513 @@
+//! A C ABI for embedding the engine in hosts written in other languages, e.g. a C++ editor or a C# tool, that own
+//! the window and its event loop. Only with the `ffi` feature. The declarations are in `include/the_camera.h`,
+//! generated with `cbindgen --config cbindgen.toml --output include/the_camera.h`.
+//!
+//! ```c
+//! TcEngine *engine = tc_engine_create_xlib(display, window, screen, 1280, 720);
+//! if (!engine) {
+//!     fprintf(stderr, "%s\n", tc_last_error());
+//!     return 1;
+//! }
+//! tc_engine_load_model(engine, "model.obj");
+//! while (running) {
+//!     // For every event of the host's window
+//!     tc_engine_pointer_moved(engine, x, y);
+//!     tc_engine_render_frame(engine);
+//! }
+//! tc_engine_destroy(engine);
+//! ```
+//!
+//! Functions passed a null engine do nothing. Failures return a [TcResult] other than [TcResult::Ok] and leave a
+//! message for [tc_last_error], panics included, they don't cross into the host. An engine has to stay on the
+//! thread that created it.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, c_ulong, c_void, CStr, CString},
+    num::NonZeroIsize,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    ptr::{self, NonNull},
+};
+
+use wgpu::rwh::{
+    AppKitDisplayHandle, AppKitWindowHandle, RawDisplayHandle, RawWindowHandle,
+    WaylandDisplayHandle, WaylandWindowHandle, Win32WindowHandle, WindowsDisplayHandle,
+    XlibDisplayHandle, XlibWindowHandle,
+};
+use winit::{event::MouseButton, window::WindowId};
+
+use crate::{assets, input::InputEvent, render_engine::RenderEngine};
+
+/// An engine drawing into one window of the host
+pub struct TcEngine {
+    engine: RenderEngine,
+    window_id: WindowId,
+}
+
+/// What a call came to
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcResult {
+    Ok = 0,
+    /// A null pointer or a string that isn't UTF-8
+    InvalidArgument,
+    /// Reading a file failed
+    Io,
+    /// The file format can't be loaded
+    Unsupported,
+    /// The engine panicked, it may not work anymore
+    Panic,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TcMouseButton {
+    Left,
+    Right,
+    Middle,
+    Back,
+    Forward,
+}
+
+/// Where the orbit camera is, angles in radians
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TcCamera {
+    pub target: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// The message of the last call that failed on this thread, null if none did. It stays valid until the next one
+/// fails.
+#[no_mangle]
+pub extern "C" fn tc_last_error() -> *const c_char {
+    LAST_ERROR.with_borrow(|error| error.as_ref().map_or(ptr::null(), |error| error.as_ptr()))
+}
+
+/// Creates an engine drawing into an X11 window
+///
+/// # Safety
+///
+/// `display` has to be the window's Xlib `Display`, both valid until [tc_engine_destroy].
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_create_xlib(
+    display: *mut c_void,
+    window: c_ulong,
+    screen: c_int,
+    width: u32,
+    height: u32,
+) -> *mut TcEngine {
+    let Some(display) = NonNull::new(display) else {
+        return null_argument("display");
+    };
+    create(
+        XlibDisplayHandle::new(Some(display), screen).into(),
+        XlibWindowHandle::new(window).into(),
+        width,
+        height,
+    )
+}
+
+/// Creates an engine drawing into a Wayland surface
+///
+/// # Safety
+///
+/// `display` and `surface` have to be a `wl_display` and `wl_surface`, valid until [tc_engine_destroy].
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_create_wayland(
+    display: *mut c_void,
+    surface: *mut c_void,
+    width: u32,
+    height: u32,
+) -> *mut TcEngine {
+    let Some(display) = NonNull::new(display) else {
+        return null_argument("display");
+    };
+    let Some(surface) = NonNull::new(surface) else {
+        return null_argument("surface");
+    };
+    create(
+        WaylandDisplayHandle::new(display).into(),
+        WaylandWindowHandle::new(surface).into(),
+        width,
+        height,
+    )
+}
+
+/// Creates an engine drawing into a Win32 window, `hinstance` may be null
+///
+/// # Safety
+///
+/// `hwnd` has to be a window of the calling thread, valid until [tc_engine_destroy].
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_create_win32(
+    hwnd: *mut c_void,
+    hinstance: *mut c_void,
+    width: u32,
+    height: u32,
+) -> *mut TcEngine {
+    let Some(hwnd) = NonZeroIsize::new(hwnd as isize) else {
+        return null_argument("hwnd");
+    };
+    let mut window = Win32WindowHandle::new(hwnd);
+    window.hinstance = NonZeroIsize::new(hinstance as isize);
+    create(
+        WindowsDisplayHandle::new().into(),
+        window.into(),
+        width,
+        height,
+    )
+}
+
+/// Creates an engine drawing into a macOS `NSView`
+///
+/// # Safety
+///
+/// `ns_view` has to be valid until [tc_engine_destroy] and used on the main thread only.
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_create_appkit(
+    ns_view: *mut c_void,
+    width: u32,
+    height: u32,
+) -> *mut TcEngine {
+    let Some(ns_view) = NonNull::new(ns_view) else {
+        return null_argument("ns_view");
+    };
+    create(
+        AppKitDisplayHandle::new().into(),
+        AppKitWindowHandle::new(ns_view).into(),
+        width,
+        height,
+    )
+}
+
+/// Frees the engine, null is ignored
+///
+/// # Safety
+///
+/// `engine` has to come from one of the `tc_engine_create_*` functions and isn't valid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_destroy(engine: *mut TcEngine) {
+    if !engine.is_null() {
+        catch((), || drop(Box::from_raw(engine)));
+    }
+}
+
+/// Call whenever the window's size changed, in physical pixels
+///
+/// # Safety
+///
+/// `engine` has to be null or a live engine.
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_resize(
+    engine: *mut TcEngine,
+    width: u32,
+    height: u32,
+) -> TcResult {
+    with_engine(engine, |engine| {
+        engine.engine.resize(engine.window_id, width, height);
+        TcResult::Ok
+    })
+}
+
+/// Physical pixels from the top left of the window. Returns whether the engine used the event, e.g. for dragging
+/// its gizmo, so the host's own tools can leave it alone.
+///
+/// # Safety
+///
+/// `engine` has to be null or a live engine.
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_pointer_moved(engine: *mut TcEngine, x: f32, y: f32) -> bool {
+    send(engine, InputEvent::CursorMoved([x, y]))
+}
+
+/// The pointer left the window
+///
+/// # Safety
+///
+/// `engine` has to be null or a live engine.
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_pointer_left(engine: *mut TcEngine) -> bool {
+    send(engine, InputEvent::CursorLeft)
+}
+
+/// # Safety
+///
+/// `engine` has to be null or a live engine.
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_mouse_button(
+    engine: *mut TcEngine,
+    button: TcMouseButton,
+    pressed: bool,
+) -> bool {
+    let button = match button {
+        TcMouseButton::Left => MouseButton::Left,
+        TcMouseButton::Right => MouseButton::Right,
+        TcMouseButton::Middle => MouseButton::Middle,
+        TcMouseButton::Back => MouseButton::Back,
+        TcMouseButton::Forward => MouseButton::Forward,
+    };
+    send(engine, InputEvent::MouseButton { button, pressed })
+}
+
+/// In mouse wheel lines, positive away from the user
+///
+/// # Safety
+///
+/// `engine` has to be null or a live engine.
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_scroll(engine: *mut TcEngine, lines: f32) -> bool {
+    send(engine, InputEvent::Scroll(lines))
+}
+
+/// A key by the platform's scancode: a Windows scancode, a macOS virtual key code, or an X11 or Wayland keycode
+/// minus 8. Keys the engine doesn't know are ignored.
+///
+/// # Safety
+///
+/// `engine` has to be null or a live engine.
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_key(
+    engine: *mut TcEngine,
+    scancode: u32,
+    pressed: bool,
+    repeat: bool,
+) -> bool {
+    use winit::{keyboard::PhysicalKey, platform::scancode::PhysicalKeyExtScancode};
+
+    let PhysicalKey::Code(key) = PhysicalKey::from_scancode(scancode) else {
+        return false;
+    };
+    send(
+        engine,
+        InputEvent::Key {
+            key,
+            pressed,
+            repeat,
+        },
+    )
+}
+
+/// What typing produced, sent after the key press that typed it
+///
+/// # Safety
+///
+/// `engine` has to be null or a live engine, `text` null or a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_text(engine: *mut TcEngine, text: *const c_char) -> bool {
+    match string_argument(text, "text") {
+        Ok(text) => send(engine, InputEvent::Text(text.to_string())),
+        Err(_) => false,
+    }
+}
+
+/// # Safety
+///
+/// `engine` has to be null or a live engine.
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_focused(engine: *mut TcEngine, focused: bool) -> bool {
+    send(engine, InputEvent::Focused(focused))
+}
+
+/// Adds a model to the scene and points the camera at it. OBJ, glTF and USD files are supported, FBX with the `fbx`
+/// feature.
+///
+/// # Safety
+///
+/// `engine` has to be null or a live engine, `path` null or a NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_load_model(
+    engine: *mut TcEngine,
+    path: *const c_char,
+) -> TcResult {
+    let path = match string_argument(path, "path") {
+        Ok(path) => Path::new(path),
+        Err(result) => return result,
+    };
+    with_engine(engine, |engine| {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        if !extension
+            .is_some_and(|extension| assets::MODEL_EXTENSIONS.contains(&extension.as_str()))
+        {
+            return fail(
+                TcResult::Unsupported,
+                format!(
+                    "Can't load {}, the supported models are {}",
+                    path.display(),
+                    assets::MODEL_EXTENSIONS.join(", ")
+                ),
+            );
+        }
+        let model = match assets::read_model(path) {
+            Ok(model) => model,
+            Err(err) => return fail(TcResult::Io, format!("{}: {err}", path.display())),
+        };
+        let engine = &mut engine.engine;
+        let bounds = model.bounds();
+        let mut renderables = engine.renderables().to_vec();
+        renderables.extend(model.add_to(engine));
+        engine.set_renderables(renderables);
+        if let Some((min, max)) = bounds {
+            engine.frame_bounds(min, max);
+        }
+        TcResult::Ok
+    })
+}
+
+/// # Safety
+///
+/// `engine` has to be null or a live engine, `camera` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_get_camera(
+    engine: *mut TcEngine,
+    camera: *mut TcCamera,
+) -> TcResult {
+    let Some(out) = camera.as_mut() else {
+        return fail(TcResult::InvalidArgument, "camera is null");
+    };
+    with_engine(engine, |engine| {
+        let camera = &engine
+            .engine
+            .viewport(engine.window_id)
+            .expect("Engine has its window!")
+            .camera;
+        *out = TcCamera {
+            target: camera.target.into(),
+            yaw: camera.yaw,
+            pitch: camera.pitch,
+            distance: camera.distance,
+        };
+        TcResult::Ok
+    })
+}
+
+/// Moves the camera, within its bounds
+///
+/// # Safety
+///
+/// `engine` has to be null or a live engine, `camera` null or readable.
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_set_camera(
+    engine: *mut TcEngine,
+    camera: *const TcCamera,
+) -> TcResult {
+    let Some(&pose) = camera.as_ref() else {
+        return fail(TcResult::InvalidArgument, "camera is null");
+    };
+    with_engine(engine, |engine| {
+        let camera = &mut engine
+            .engine
+            .viewport_mut(engine.window_id)
+            .expect("Engine has its window!")
+            .camera;
+        camera.target = pose.target.into();
+        camera.set_distance(pose.distance);
+        camera.set_pitch(pose.pitch);
+        camera.set_yaw(pose.yaw);
+        engine.engine.request_redraw();
+        TcResult::Ok
+    })
+}
+
+/// Updates the scene and draws it into the window. Call it once per frame of the host, the engine doesn't
+/// schedule frames itself.
+///
+/// # Safety
+///
+/// `engine` has to be null or a live engine.
+#[no_mangle]
+pub unsafe extern "C" fn tc_engine_render_frame(engine: *mut TcEngine) -> TcResult {
+    with_engine(engine, |engine| {
+        engine.engine.update();
+        engine.engine.render_frame(engine.window_id);
+        TcResult::Ok
+    })
+}
+
+unsafe fn create(
+    display: RawDisplayHandle,
+    window: RawWindowHandle,
+    width: u32,
+    height: u32,
+) -> *mut TcEngine {
+    catch(ptr::null_mut(), || {
+        let engine = pollster::block_on(RenderEngine::from_raw_handles(
+            display,
+            window,
+            [width, height],
+        ));
+        let window_id = engine.window_ids().next().expect("Engine has its window!");
+        Box::into_raw(Box::new(TcEngine { engine, window_id }))
+    })
+}
+
+/// Runs `f` on the engine, null is an invalid argument
+unsafe fn with_engine(
+    engine: *mut TcEngine,
+    f: impl FnOnce(&mut TcEngine) -> TcResult,
+) -> TcResult {
+    match engine.as_mut() {
+        Some(engine) => catch(TcResult::Panic, || f(engine)),
+        None => fail(TcResult::InvalidArgument, "engine is null"),
+    }
+}
+
+/// Whether the engine used the event
+unsafe fn send(engine: *mut TcEngine, event: InputEvent) -> bool {
+    let Some(engine) = engine.as_mut() else {
+        return false;
+    };
+    catch(false, || {
+        engine.engine.process_input_event(engine.window_id, &event)
+    })
+}
+
+unsafe fn string_argument<'a>(string: *const c_char, name: &str) -> Result<&'a str, TcResult> {
+    if string.is_null() {
+        return Err(fail(TcResult::InvalidArgument, format!("{name} is null")));
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map_err(|_| fail(TcResult::InvalidArgument, format!("{name} isn't UTF-8")))
+}
+
+/// Runs `f`, turning a panic into `on_panic` with its message left for [tc_last_error]
+fn catch<T>(on_panic: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Unknown panic".to_string());
+        set_last_error(format!("The engine panicked: {message}"));
+        on_panic
+    })
+}
+
+fn fail(result: TcResult, message: impl Into<String>) -> TcResult {
+    set_last_error(message);
+    result
+}
+
+fn null_argument(name: &str) -> *mut TcEngine {
+    set_last_error(format!("{name} is null"));
+    ptr::null_mut()
+}
+
+fn set_last_error(message: impl Into<String>) {
+    // Interior NULs would cut the message short anyway
+    let message = message.into().replace('\0', " ");
+    let message = CString::new(message).expect("NULs were replaced!");
+    LAST_ERROR.with_borrow_mut(|error| *error = Some(message));
+}
@@ -0,0 +1,163 @@
+//! Animated textures played from a sequence of images, for video billboards, sprite sheet effects and the like.
+//! Materials show one with [crate::scene::Material::flipbook], each at its own rate, and windows keep redrawing
+//! while any is playing.
+
+use crate::{
+    material_bindings::MaterialBindings,
+    texture::{Texture, TextureData},
+    wgpu_utils::resource_cache::SamplerCache,
+};
+
+/// Refers to a flipbook added with [crate::render_engine::RenderEngine::add_flipbook]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlipbookHandle(pub(crate) usize);
+
+/// Where a flipbook's frames live on the GPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlipbookStorage {
+    /// Every frame in a layer of one array texture, so changing frames costs nothing
+    #[default]
+    Array,
+    /// Only the frame being shown, replaced as the flipbook plays, for sequences too long to keep on the GPU.
+    /// Materials playing it at different rates all show the frame of the first of them.
+    Streamed,
+}
+
+/// How a material plays a flipbook, starting over whenever the material is set to a different playback
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlipbookPlayback {
+    pub flipbook: FlipbookHandle,
+    /// 0 holds the first frame
+    pub frames_per_second: f32,
+    /// Starts over after the last frame instead of holding it
+    pub looping: bool,
+}
+
+impl FlipbookPlayback {
+    /// 24 frames per second, looping
+    pub fn new(flipbook: FlipbookHandle) -> Self {
+        FlipbookPlayback {
+            flipbook,
+            frames_per_second: 24.0,
+            looping: true,
+        }
+    }
+
+    /// Which of `frame_count` frames shows `elapsed` seconds after the playback started
+    pub fn frame(&self, elapsed: f32, frame_count: usize) -> usize {
+        let frame = (elapsed * self.frames_per_second.max(0.0)) as usize;
+        match self.looping {
+            true => frame % frame_count.max(1),
+            false => frame.min(frame_count.saturating_sub(1)),
+        }
+    }
+
+    /// Whether later frames differ from the one shown `elapsed` seconds in
+    pub fn is_playing(&self, elapsed: f32, frame_count: usize) -> bool {
+        self.frames_per_second > 0.0
+            && frame_count > 1
+            && (self.looping || self.frame(elapsed, frame_count) + 1 < frame_count)
+    }
+}
+
+/// A flipbook's frames uploaded to the GPU, together with the images they were uploaded from
+pub(crate) struct GpuFlipbook {
+    pub frames: Vec<TextureData>,
+    pub storage: FlipbookStorage,
+    texture: Texture,
+    /// Binds each layer at the material group, a single one for streamed flipbooks
+    bind_groups: Vec<wgpu::BindGroup>,
+    /// In the texture of a streamed flipbook
+    uploaded: usize,
+}
+
+impl GpuFlipbook {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        samplers: &SamplerCache,
+        frames: Vec<TextureData>,
+        storage: FlipbookStorage,
+        material_bindings: &MaterialBindings,
+    ) -> Self {
+        let first = frames.first().expect("Flipbook without frames!");
+        let (width, height) = (first.width, first.height);
+        for frame in &frames {
+            assert!(
+                frame.width == width && frame.height == height,
+                "Flipbook frames differ in size!"
+            );
+            assert_eq!(
+                frame.rgba.len(),
+                width as usize * height as usize * 4,
+                "Texture data doesn't match its size!"
+            );
+        }
+        let layers = match storage {
+            FlipbookStorage::Array => frames.len() as u32,
+            FlipbookStorage::Streamed => 1,
+        };
+        let rgba: Vec<u8> = frames[..layers as usize]
+            .iter()
+            .flat_map(|frame| frame.rgba.iter().copied())
+            .collect();
+        let texture = Texture::from_rgba_layers(
+            device,
+            queue,
+            samplers,
+            width,
+            height,
+            layers,
+            &rgba,
+            "Flipbook Texture",
+        );
+        let bind_groups = (0..layers)
+            .map(|layer| {
+                let view = texture.view_with(&wgpu::TextureViewDescriptor {
+                    dimension: Some(wgpu::TextureViewDimension::D2),
+                    base_array_layer: layer,
+                    array_layer_count: Some(1),
+                    ..Default::default()
+                });
+                material_bindings.create_bind_group_with_view(device, &view, &texture.sampler)
+            })
+            .collect();
+
+        GpuFlipbook {
+            frames,
+            storage,
+            texture,
+            bind_groups,
+            uploaded: 0,
+        }
+    }
+
+    /// Makes the texture of a streamed flipbook hold `frame`, array flipbooks already hold every frame
+    pub fn show(&mut self, queue: &wgpu::Queue, frame: usize) {
+        if self.storage != FlipbookStorage::Streamed || frame == self.uploaded {
+            return;
+        }
+        let Some(data) = self.frames.get(frame) else {
+            return;
+        };
+        queue.write_texture(
+            self.texture.texture.as_image_copy(),
+            &data.rgba,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(data.width * 4),
+                rows_per_image: Some(data.height),
+            },
+            self.texture.texture.size(),
+        );
+        self.uploaded = frame;
+    }
+
+    pub fn bind_group(&self, frame: usize) -> &wgpu::BindGroup {
+        &self.bind_groups[frame.min(self.bind_groups.len() - 1)]
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.texture.size_in_bytes()
+    }
+}
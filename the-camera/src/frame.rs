@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use winit::window::WindowId;
+
+use crate::{
+    audio::AudioUniform, camera::camera::CameraUniform, clipping::ClipUniform, debug::DebugShapes,
+    object_bindings::ObjectUBOContent, occlusion::OcclusionProxy,
+};
+
+/// The state of one frame as it moves through the engine's phases:
+///
+/// 1. simulate: device recovery, asset reloads, plugins and cameras advance
+/// 2. extract: everything the GPU needs is copied out of the engine and scene into plain data here, without
+///    touching the device
+/// 3. prepare: the extracted data is uploaded
+/// 4. compute: plugins record their compute passes, which run after the uploads and before any drawing
+/// 5. render: render targets and every window encode and submit their passes from the extracted data
+///
+/// [crate::render_engine::RenderEngine::update] runs the first four and draws the render targets,
+/// [crate::render_engine::RenderEngine::render_frame] draws each window.
+#[derive(Debug, Default)]
+pub struct FrameContext {
+    /// Counts up by one with every update
+    pub frame_index: u64,
+    /// Time since the previous update
+    pub delta_time: Duration,
+    /// Moving average of [FrameContext::delta_time], clamped so it doesn't jump after the app sat idle. Better
+    /// suited for movement and animation.
+    pub smoothed_delta_time: Duration,
+
+    pub(crate) cameras: Vec<(WindowId, CameraUniform)>,
+    /// Cameras of the render targets that get the scene drawn into them, by target index
+    pub(crate) target_cameras: Vec<(usize, CameraUniform)>,
+    /// Shared by every view
+    pub(crate) clipping: ClipUniform,
+    pub(crate) audio: AudioUniform,
+    pub(crate) objects: Vec<ObjectUBOContent>,
+    pub(crate) draws: Vec<Draw>,
+    /// Every meshlet of every drawn mesh, culled per view, see [crate::meshlet]
+    #[cfg(feature = "meshlets")]
+    pub(crate) meshlet_instances: Vec<crate::meshlet::MeshletInstance>,
+    /// Model matrices of the meshes drawn as meshlets
+    #[cfg(feature = "meshlets")]
+    pub(crate) meshlet_transforms: Vec<[[f32; 4]; 4]>,
+    /// Added through [crate::debug] since the previous update
+    pub(crate) debug_shapes: DebugShapes,
+}
+
+/// One object to draw, resolved to indices
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Draw {
+    /// Index into the renderables it was extracted from
+    pub renderable: usize,
+    /// The object slot holding its transform and color
+    pub slot: usize,
+    pub mesh: usize,
+    /// What its material samples, None for plain colors
+    pub texture: Option<DrawTexture>,
+    /// The bounding box tested to skip drawing it, see [crate::scene::Renderable::occlusion_query]
+    pub occlusion: Option<OcclusionProxy>,
+    /// First and number of its meshlet instances, drawn instead of the whole mesh
+    #[cfg(feature = "meshlets")]
+    pub meshlets: Option<(u32, u32)>,
+}
+
+/// The texture a draw's material samples, resolved to indices
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum DrawTexture {
+    RenderTarget(usize),
+    /// An image added with [crate::render_engine::RenderEngine::add_texture]
+    Image(usize),
+    /// Flipbook and frame, see [crate::flipbook]
+    Flipbook(usize, usize),
+    #[cfg(feature = "ffmpeg")]
+    Video(usize),
+}
+
+impl FrameContext {
+    /// Number of objects drawn into every window this frame
+    pub fn draw_count(&self) -> usize {
+        self.draws.len()
+    }
+}
@@ -0,0 +1,90 @@
+use web_time::{Duration, Instant};
+
+/// Sleeping is only accurate to about a millisecond on most systems, the rest of the wait is spent spinning
+#[cfg(not(target_arch = "wasm32"))]
+const SPIN_THRESHOLD: Duration = Duration::from_micros(1500);
+
+/// Longer frame times are clamped before smoothing, so simulations don't jump after the app slept waiting for input
+const MAX_SMOOTHED_DELTA: Duration = Duration::from_millis(100);
+
+/// Weight of the newest frame time in the moving average
+const SMOOTHING_FACTOR: f64 = 0.1;
+
+/// Caps how often frames start, independently from the present mode. Without a cap Mailbox and Immediate present as
+/// fast as the GPU allows, thousands of frames per second for a simple scene.
+#[derive(Debug)]
+pub struct FrameLimiter {
+    target_frame_time: Option<Duration>,
+    last_frame: Instant,
+}
+
+impl FrameLimiter {
+    /// `target_fps` of None leaves frames uncapped
+    pub fn new(target_fps: Option<f32>) -> Self {
+        let mut limiter = FrameLimiter {
+            target_frame_time: None,
+            last_frame: Instant::now(),
+        };
+        limiter.set_target_fps(target_fps);
+        limiter
+    }
+
+    /// Rates without a frame time that fits a [Duration], e.g. 0, negative or NaN, leave frames uncapped
+    pub fn set_target_fps(&mut self, target_fps: Option<f32>) {
+        self.target_frame_time =
+            target_fps.and_then(|fps| Duration::try_from_secs_f32(1.0 / fps).ok());
+    }
+
+    pub fn target_fps(&self) -> Option<f32> {
+        self.target_frame_time
+            .map(|frame_time| 1.0 / frame_time.as_secs_f32())
+    }
+
+    /// Blocks until the next frame is due, sleeping for most of the wait and spinning for the last bit to hit the
+    /// target precisely. Returns when the frame started.
+    ///
+    /// The browser paces frames itself and doesn't allow blocking, so on the web this never waits.
+    pub fn wait(&mut self) -> Instant {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(target_frame_time) = self.target_frame_time {
+            let deadline = self.last_frame + target_frame_time;
+            loop {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                let remaining = deadline - now;
+                if remaining > SPIN_THRESHOLD {
+                    std::thread::sleep(remaining - SPIN_THRESHOLD);
+                } else {
+                    std::hint::spin_loop();
+                }
+            }
+        }
+
+        self.last_frame = Instant::now();
+        self.last_frame
+    }
+}
+
+/// Exponential moving average of frame times, for movement and animations that shouldn't stutter with every
+/// frame time hiccup
+#[derive(Debug, Default)]
+pub struct DeltaSmoother {
+    average: Option<Duration>,
+}
+
+impl DeltaSmoother {
+    /// Adds the latest frame time and returns the smoothed one
+    pub fn update(&mut self, delta_time: Duration) -> Duration {
+        let delta_time = delta_time.min(MAX_SMOOTHED_DELTA);
+        let average = match self.average {
+            Some(average) => {
+                average.mul_f64(1.0 - SMOOTHING_FACTOR) + delta_time.mul_f64(SMOOTHING_FACTOR)
+            }
+            None => delta_time,
+        };
+        self.average = Some(average);
+        average
+    }
+}
@@ -0,0 +1,557 @@
+//! Handles for moving, rotating and scaling one [Transform] with the mouse: arrows and planes to translate, rings to
+//! rotate and axes ending in boxes to scale, drawn over every window.
+//!
+//! The engine owns a single [Gizmo], see [crate::render_engine::RenderEngine::gizmo_mut]. Attach the transform of the
+//! object to edit and copy [Gizmo::transform] back into the scene every frame. Handles keep the same size on screen
+//! however far away they are. They're hit tested against the ray under the cursor on the CPU, so hovering and dragging
+//! respond right away, and the main pass never sees them, so picking goes through to the scene. Clicks that miss the
+//! handles go on to the [crate::selection].
+
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Quaternion, Rad, Rotation, Rotation3, Vector3};
+use winit::{event::MouseButton, window::WindowId};
+
+use crate::{
+    input::InputEvent,
+    overlay::{OverlayGeometry, OverlayView},
+    scene::Transform,
+};
+
+/// Handle length on screen in pixels, unless changed with [Gizmo::set_size]
+const DEFAULT_SIZE: f32 = 100.0;
+/// How close the cursor has to come to a handle to grab it, in pixels
+const GRAB_DISTANCE: f32 = 8.0;
+/// The rest are fractions of the handle length
+const ARROW_HEAD_LENGTH: f32 = 0.2;
+const ARROW_HEAD_RADIUS: f32 = 0.06;
+const PLANE_START: f32 = 0.25;
+const PLANE_END: f32 = 0.45;
+const SCALE_BOX_SIZE: f32 = 0.08;
+const RING_SEGMENTS: usize = 48;
+/// Smallest factor a scale drag multiplies by, scaling through zero would mirror the object
+const MIN_SCALE_FACTOR: f32 = 0.01;
+
+const HIGHLIGHT: [f32; 4] = [1.0, 0.85, 0.1, 1.0];
+const PLANE_ALPHA: f32 = 0.35;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GizmoMode {
+    #[default]
+    Translate,
+    Rotate,
+    Scale,
+}
+
+/// Which axes translation and rotation handles follow. Scaling always happens along the object's own axes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GizmoSpace {
+    #[default]
+    World,
+    /// Rotated with the attached transform
+    Local,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    const ALL: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    fn color(self) -> [f32; 4] {
+        match self {
+            Axis::X => [0.9, 0.2, 0.2, 1.0],
+            Axis::Y => [0.2, 0.8, 0.2, 1.0],
+            Axis::Z => [0.2, 0.4, 0.95, 1.0],
+        }
+    }
+}
+
+/// A part of the gizmo the cursor can grab
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GizmoHandle {
+    /// Moves along the axis
+    Arrow(Axis),
+    /// Moves in the plane the axis is the normal of
+    Plane(Axis),
+    /// Rotates around the axis
+    Ring(Axis),
+    /// Scales along the axis
+    ScaleAxis(Axis),
+}
+
+impl GizmoHandle {
+    fn axis(self) -> Axis {
+        match self {
+            GizmoHandle::Arrow(axis)
+            | GizmoHandle::Plane(axis)
+            | GizmoHandle::Ring(axis)
+            | GizmoHandle::ScaleAxis(axis) => axis,
+        }
+    }
+}
+
+/// A half line from the camera through a pixel
+#[derive(Debug, Clone, Copy)]
+pub struct Ray {
+    pub origin: Vector3<f32>,
+    /// Normalized
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn at(&self, distance: f32) -> Vector3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    /// Where the ray comes closest to the line through `point` along the normalized `direction`: as the distance
+    /// along the ray, the distance along the line from `point`, and how far apart they are there. None if the two
+    /// are parallel.
+    pub fn closest_to_line(
+        &self,
+        point: Vector3<f32>,
+        direction: Vector3<f32>,
+    ) -> Option<(f32, f32, f32)> {
+        let offset = self.origin - point;
+        let cos = self.direction.dot(direction);
+        let denominator = 1.0 - cos * cos;
+        if denominator < 1e-6 {
+            return None;
+        }
+        let along_ray = (cos * direction.dot(offset) - self.direction.dot(offset)) / denominator;
+        let along_line = (direction.dot(offset) - cos * self.direction.dot(offset)) / denominator;
+        let gap = (self.at(along_ray) - (point + direction * along_line)).magnitude();
+        Some((along_ray, along_line, gap))
+    }
+
+    /// Where the ray goes through the plane through `point`, None if it runs parallel or away from it
+    pub fn intersect_plane(
+        &self,
+        point: Vector3<f32>,
+        normal: Vector3<f32>,
+    ) -> Option<Vector3<f32>> {
+        let denominator = normal.dot(self.direction);
+        if denominator.abs() < 1e-6 {
+            return None;
+        }
+        let distance = normal.dot(point - self.origin) / denominator;
+        (distance >= 0.0).then(|| self.at(distance))
+    }
+}
+
+/// How a drag measures movement, fixed when the handle is grabbed
+#[derive(Debug, Clone, Copy)]
+enum Grab {
+    /// Distance along the handle's axis
+    Along(f32),
+    /// Point on the handle's plane
+    At(Vector3<f32>),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Drag {
+    window_id: WindowId,
+    handle: GizmoHandle,
+    /// The transform when the handle was grabbed, every drag step starts from it
+    start: Transform,
+    grab: Grab,
+}
+
+/// What handling an input event did, see [Gizmo::process_event]
+#[derive(Debug, Default)]
+pub(crate) struct GizmoResponse {
+    /// The event grabbed or moved a handle and shouldn't reach anything else
+    pub consumed: bool,
+    pub redraw: bool,
+}
+
+/// Moves, rotates and scales the attached [Transform] with the mouse, see the [module docs](self)
+pub struct Gizmo {
+    mode: GizmoMode,
+    space: GizmoSpace,
+    size: f32,
+    transform: Option<Transform>,
+    hovered: Option<(WindowId, GizmoHandle)>,
+    drag: Option<Drag>,
+    /// Last cursor position in every window, it's only reported when it moves
+    cursors: HashMap<WindowId, [f32; 2]>,
+}
+
+impl Default for Gizmo {
+    fn default() -> Self {
+        Gizmo {
+            mode: GizmoMode::default(),
+            space: GizmoSpace::default(),
+            size: DEFAULT_SIZE,
+            transform: None,
+            hovered: None,
+            drag: None,
+            cursors: HashMap::new(),
+        }
+    }
+}
+
+impl Gizmo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mode(&self) -> GizmoMode {
+        self.mode
+    }
+
+    /// Switches the handles, a drag in progress is dropped
+    pub fn set_mode(&mut self, mode: GizmoMode) {
+        if mode != self.mode {
+            self.mode = mode;
+            self.drag = None;
+            self.hovered = None;
+        }
+    }
+
+    pub fn space(&self) -> GizmoSpace {
+        self.space
+    }
+
+    pub fn set_space(&mut self, space: GizmoSpace) {
+        self.space = space;
+    }
+
+    /// Length of the handles on screen, in physical pixels
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+
+    pub fn set_size(&mut self, pixels: f32) {
+        self.size = pixels.max(1.0);
+    }
+
+    /// Shows the handles at `transform`, replacing the one attached before
+    pub fn attach(&mut self, transform: Transform) {
+        self.transform = Some(transform);
+        self.drag = None;
+    }
+
+    /// Hides the handles
+    pub fn detach(&mut self) {
+        self.transform = None;
+        self.drag = None;
+        self.hovered = None;
+    }
+
+    /// The attached transform with every drag so far applied
+    pub fn transform(&self) -> Option<Transform> {
+        self.transform
+    }
+
+    /// Whether a handle is held, the camera doesn't follow the mouse meanwhile
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    /// The handle being dragged, otherwise the one under the cursor
+    pub fn active_handle(&self) -> Option<GizmoHandle> {
+        self.drag
+            .map(|drag| drag.handle)
+            .or(self.hovered.map(|(_, handle)| handle))
+    }
+
+    /// Hovers, grabs, drags and lets go of handles with the left mouse button. `view` is the camera of the window
+    /// the event happened in, None if it can't be used.
+    pub(crate) fn process_event(
+        &mut self,
+        window_id: WindowId,
+        event: &InputEvent,
+        view: Option<&OverlayView>,
+    ) -> GizmoResponse {
+        let Some(view) = view else {
+            return GizmoResponse::default();
+        };
+        match event {
+            &InputEvent::CursorMoved(cursor) => {
+                self.cursors.insert(window_id, cursor);
+                if self.drag.is_some_and(|drag| drag.window_id == window_id) {
+                    self.drag_to(view, cursor);
+                    return GizmoResponse {
+                        consumed: true,
+                        redraw: true,
+                    };
+                }
+                let hovered = self
+                    .hit_test(view, cursor)
+                    .map(|handle| (window_id, handle));
+                let redraw = hovered != self.hovered;
+                self.hovered = hovered;
+                GizmoResponse {
+                    redraw,
+                    ..Default::default()
+                }
+            }
+            InputEvent::CursorLeft => {
+                self.cursors.remove(&window_id);
+                let redraw = self
+                    .hovered
+                    .is_some_and(|(hovered_window, _)| hovered_window == window_id);
+                if redraw {
+                    self.hovered = None;
+                }
+                GizmoResponse {
+                    redraw,
+                    ..Default::default()
+                }
+            }
+            InputEvent::MouseButton {
+                button: MouseButton::Left,
+                pressed: true,
+            } => {
+                let Some(&cursor) = self.cursors.get(&window_id) else {
+                    return GizmoResponse::default();
+                };
+                if let Some(drag) = self.grab(window_id, view, cursor) {
+                    self.drag = Some(drag);
+                    return GizmoResponse {
+                        consumed: true,
+                        redraw: true,
+                    };
+                }
+                GizmoResponse::default()
+            }
+            InputEvent::MouseButton {
+                button: MouseButton::Left,
+                pressed: false,
+            } if self.drag.is_some() => {
+                self.drag = None;
+                GizmoResponse {
+                    consumed: true,
+                    redraw: true,
+                }
+            }
+            _ => GizmoResponse::default(),
+        }
+    }
+
+    /// Direction of the handle's axis for `transform`
+    fn axis_direction(&self, axis: Axis, transform: &Transform) -> Vector3<f32> {
+        let unit = match axis {
+            Axis::X => Vector3::unit_x(),
+            Axis::Y => Vector3::unit_y(),
+            Axis::Z => Vector3::unit_z(),
+        };
+        if self.mode == GizmoMode::Scale || self.space == GizmoSpace::Local {
+            transform.rotation.rotate_vector(unit)
+        } else {
+            unit
+        }
+    }
+
+    /// The two axes spanning the plane `axis` is the normal of
+    fn plane_directions(&self, axis: Axis, transform: &Transform) -> (Vector3<f32>, Vector3<f32>) {
+        let [u, v] = match axis {
+            Axis::X => [Axis::Y, Axis::Z],
+            Axis::Y => [Axis::Z, Axis::X],
+            Axis::Z => [Axis::X, Axis::Y],
+        };
+        (
+            self.axis_direction(u, transform),
+            self.axis_direction(v, transform),
+        )
+    }
+
+    fn handles(&self) -> impl Iterator<Item = GizmoHandle> {
+        let mode = self.mode;
+        Axis::ALL.into_iter().flat_map(move |axis| match mode {
+            GizmoMode::Translate => vec![GizmoHandle::Arrow(axis), GizmoHandle::Plane(axis)],
+            GizmoMode::Rotate => vec![GizmoHandle::Ring(axis)],
+            GizmoMode::Scale => vec![GizmoHandle::ScaleAxis(axis)],
+        })
+    }
+
+    /// The handle under the cursor. Planes win inside their square, otherwise the handle closest to the ray.
+    fn hit_test(&self, view: &OverlayView, cursor: [f32; 2]) -> Option<GizmoHandle> {
+        let transform = self.transform?;
+        let center = transform.translation;
+        let pixel = view.pixel_size(center);
+        let length = self.size * pixel;
+        let ray = view.ray(cursor);
+
+        self.handles()
+            .filter_map(|handle| {
+                let direction = self.axis_direction(handle.axis(), &transform);
+                let miss = match handle {
+                    GizmoHandle::Arrow(_) | GizmoHandle::ScaleAxis(_) => {
+                        let (along_ray, along_axis, gap) =
+                            ray.closest_to_line(center, direction)?;
+                        (along_ray > 0.0 && (0.0..=length).contains(&along_axis)).then_some(gap)?
+                    }
+                    GizmoHandle::Plane(axis) => {
+                        let offset = ray.intersect_plane(center, direction)? - center;
+                        let (u, v) = self.plane_directions(axis, &transform);
+                        let square = PLANE_START * length..=PLANE_END * length;
+                        (square.contains(&offset.dot(u)) && square.contains(&offset.dot(v)))
+                            .then_some(0.0)?
+                    }
+                    GizmoHandle::Ring(_) => {
+                        let offset = ray.intersect_plane(center, direction)? - center;
+                        (offset.magnitude() - length).abs()
+                    }
+                };
+                (miss < GRAB_DISTANCE * pixel).then_some((miss, handle))
+            })
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, handle)| handle)
+    }
+
+    fn grab(&self, window_id: WindowId, view: &OverlayView, cursor: [f32; 2]) -> Option<Drag> {
+        let start = self.transform?;
+        let handle = self.hit_test(view, cursor)?;
+        let ray = view.ray(cursor);
+        let direction = self.axis_direction(handle.axis(), &start);
+        let grab = match handle {
+            GizmoHandle::Arrow(_) | GizmoHandle::ScaleAxis(_) => {
+                Grab::Along(ray.closest_to_line(start.translation, direction)?.1)
+            }
+            GizmoHandle::Plane(_) | GizmoHandle::Ring(_) => {
+                Grab::At(ray.intersect_plane(start.translation, direction)?)
+            }
+        };
+        Some(Drag {
+            window_id,
+            handle,
+            start,
+            grab,
+        })
+    }
+
+    /// Applies the drag so far to the transform it started from. Cursor positions the handle can't follow, e.g.
+    /// along a plane seen edge on, keep the transform as it is.
+    fn drag_to(&mut self, view: &OverlayView, cursor: [f32; 2]) {
+        let Some(Drag {
+            handle,
+            start,
+            grab,
+            ..
+        }) = self.drag
+        else {
+            return;
+        };
+        let ray = view.ray(cursor);
+        let center = start.translation;
+        let direction = self.axis_direction(handle.axis(), &start);
+        let mut transform = start;
+        match (handle, grab) {
+            (GizmoHandle::Arrow(_), Grab::Along(grabbed)) => {
+                let Some((_, along_axis, _)) = ray.closest_to_line(center, direction) else {
+                    return;
+                };
+                transform.translation = center + direction * (along_axis - grabbed);
+            }
+            (GizmoHandle::Plane(_), Grab::At(grabbed)) => {
+                let Some(point) = ray.intersect_plane(center, direction) else {
+                    return;
+                };
+                transform.translation = center + (point - grabbed);
+            }
+            (GizmoHandle::Ring(_), Grab::At(grabbed)) => {
+                let Some(point) = ray.intersect_plane(center, direction) else {
+                    return;
+                };
+                let (from, to) = (grabbed - center, point - center);
+                let angle = direction.dot(from.cross(to)).atan2(from.dot(to));
+                transform.rotation =
+                    Quaternion::from_axis_angle(direction, Rad(angle)) * start.rotation;
+            }
+            (GizmoHandle::ScaleAxis(axis), Grab::Along(grabbed)) => {
+                let Some((_, along_axis, _)) = ray.closest_to_line(center, direction) else {
+                    return;
+                };
+                if grabbed.abs() < f32::EPSILON {
+                    return;
+                }
+                let factor = (along_axis / grabbed).max(MIN_SCALE_FACTOR);
+                transform.scale[axis.index()] = start.scale[axis.index()] * factor;
+            }
+            _ => return,
+        }
+        self.transform = Some(transform);
+    }
+
+    /// The handles as seen through `view`, the hovered or dragged one highlighted
+    pub(crate) fn geometry(&self, window_id: WindowId, view: &OverlayView) -> OverlayGeometry {
+        let mut geometry = OverlayGeometry::default();
+        let Some(transform) = self.transform else {
+            return geometry;
+        };
+        let center = transform.translation;
+        let length = self.size * view.pixel_size(center);
+        let highlighted = self.drag.map(|drag| drag.handle).or(self
+            .hovered
+            .filter(|(hovered_window, _)| *hovered_window == window_id)
+            .map(|(_, handle)| handle));
+
+        for handle in self.handles() {
+            let axis = handle.axis();
+            let direction = self.axis_direction(axis, &transform);
+            let (u, v) = self.plane_directions(axis, &transform);
+            let color = if highlighted == Some(handle) {
+                HIGHLIGHT
+            } else {
+                axis.color()
+            };
+            match handle {
+                GizmoHandle::Arrow(_) => {
+                    let base = center + direction * length * (1.0 - ARROW_HEAD_LENGTH);
+                    geometry.line(center, base, color);
+                    geometry.cone(
+                        base,
+                        center + direction * length,
+                        (
+                            u * ARROW_HEAD_RADIUS * length,
+                            v * ARROW_HEAD_RADIUS * length,
+                        ),
+                        color,
+                    );
+                }
+                GizmoHandle::Plane(_) => {
+                    let corner = |a: f32, b: f32| center + (u * a + v * b) * length;
+                    let [r, g, b, _] = color;
+                    geometry.quad(
+                        [
+                            corner(PLANE_START, PLANE_START),
+                            corner(PLANE_END, PLANE_START),
+                            corner(PLANE_END, PLANE_END),
+                            corner(PLANE_START, PLANE_END),
+                        ],
+                        [r, g, b, PLANE_ALPHA],
+                    );
+                }
+                GizmoHandle::Ring(_) => {
+                    let point = |segment: usize| {
+                        let angle = segment as f32 / RING_SEGMENTS as f32 * std::f32::consts::TAU;
+                        center + (u * angle.cos() + v * angle.sin()) * length
+                    };
+                    for segment in 0..RING_SEGMENTS {
+                        geometry.line(point(segment), point(segment + 1), color);
+                    }
+                }
+                GizmoHandle::ScaleAxis(_) => {
+                    let tip = center + direction * length;
+                    geometry.line(center, tip, color);
+                    geometry.cube(
+                        tip,
+                        [direction, u, v].map(|axis| axis * SCALE_BOX_SIZE * length / 2.0),
+                        color,
+                    );
+                }
+            }
+        }
+        geometry
+    }
+}
@@ -1,9 +1,12 @@
 use crate::{
+    audio::AudioUniform,
     camera::camera::CameraUniform,
+    clipping::ClipUniform,
     wgpu_utils::{
         binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
         binding_types,
         uniform_buffer::UniformBuffer,
+        uploader::Uploader,
     },
 };
 
@@ -11,15 +14,38 @@ use crate::{
 #[derive(Copy, Clone, Debug)]
 pub struct GlobalUBOContent {
     camera: CameraUniform,
+    clipping: ClipUniform,
+    audio: AudioUniform,
 }
 
 unsafe impl bytemuck::Pod for GlobalUBOContent {}
 unsafe impl bytemuck::Zeroable for GlobalUBOContent {}
 
+crate::assert_uniform_layout!(GlobalUBOContent {
+    camera: ALIGN_VEC4,
+    clipping: ALIGN_VEC4,
+    audio: ALIGN_VEC4,
+});
+
 pub type GlobalUBO = UniformBuffer<GlobalUBOContent>;
 
-pub fn update_global_ubo(ubo: &mut GlobalUBO, queue: &wgpu::Queue, camera: CameraUniform) {
-    ubo.update_content(queue, GlobalUBOContent { camera });
+pub fn update_global_ubo(
+    ubo: &mut GlobalUBO,
+    uploader: &mut Uploader,
+    device: &wgpu::Device,
+    camera: CameraUniform,
+    clipping: ClipUniform,
+    audio: AudioUniform,
+) {
+    ubo.stage_content(
+        uploader,
+        device,
+        GlobalUBOContent {
+            camera,
+            clipping,
+            audio,
+        },
+    );
 }
 
 pub struct GlobalBindings {
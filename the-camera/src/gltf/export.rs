@@ -0,0 +1,291 @@
+//! Writing scenes out as binary glTF, for taking generated or edited content over to other tools
+
+use std::io;
+
+use cgmath::{Matrix4, SquareMatrix};
+
+use super::{json::Json, CHUNK_BIN, CHUNK_JSON, GLB_MAGIC};
+use crate::{
+    assets::{decode_srgb, encode_png},
+    mesh::MeshData,
+    texture::TextureData,
+};
+
+const FLOAT: usize = 5126;
+const UNSIGNED_SHORT: usize = 5123;
+const ARRAY_BUFFER: usize = 34962;
+const ELEMENT_ARRAY_BUFFER: usize = 34963;
+
+/// What [encode_glb] writes, e.g. gathered from the engine with
+/// [crate::render_engine::RenderEngine::export_scene]
+#[derive(Debug, Clone, Default)]
+pub struct ExportScene {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<ExportMaterial>,
+    /// The images materials sample
+    pub textures: Vec<TextureData>,
+    pub objects: Vec<ExportObject>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportMaterial {
+    /// sRGB encoded, like [crate::scene::Material::base_color]
+    pub base_color: [f32; 4],
+    /// Index into [ExportScene::textures]
+    pub texture: Option<usize>,
+}
+
+/// One mesh placed in the scene, with indices into the [ExportScene]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExportObject {
+    pub mesh: usize,
+    pub material: usize,
+    pub transform: Matrix4<f32>,
+}
+
+/// Writes a `.glb` with a node for every object, all in its binary chunk. Materials are unlit, like the engine draws
+/// them, with textures stored as PNGs. Objects referring to meshes, materials or textures that don't exist are left
+/// out.
+pub fn encode_glb(scene: &ExportScene) -> io::Result<Vec<u8>> {
+    let mut document = Writer::default();
+
+    let meshes: Vec<Option<[usize; 4]>> = scene
+        .meshes
+        .iter()
+        .map(|mesh| document.mesh(mesh))
+        .collect();
+    for texture in &scene.textures {
+        let png = encode_png(texture)?;
+        let view = document.view(&png, None);
+        document.images.push(Json::object([
+            ("bufferView", view.into()),
+            ("mimeType", "image/png".into()),
+        ]));
+    }
+    let materials: Vec<Json> = scene
+        .materials
+        .iter()
+        .map(|material| material_json(material, scene.textures.len()))
+        .collect();
+
+    let mut gltf_meshes = Vec::new();
+    // Meshes drawn with several materials become a glTF mesh for each, sharing their accessors
+    let mut mesh_materials = Vec::new();
+    let mut nodes = Vec::new();
+    for object in &scene.objects {
+        let Some(Some([position, color, tex_coord, indices])) = meshes.get(object.mesh).copied()
+        else {
+            continue;
+        };
+        if object.material >= materials.len() {
+            continue;
+        }
+        let key = (object.mesh, object.material);
+        let gltf_mesh = match mesh_materials.iter().position(|&other| other == key) {
+            Some(gltf_mesh) => gltf_mesh,
+            None => {
+                gltf_meshes.push(Json::object([(
+                    "primitives",
+                    Json::Array(vec![Json::object([
+                        (
+                            "attributes",
+                            Json::object([
+                                ("POSITION", position.into()),
+                                ("COLOR_0", color.into()),
+                                ("TEXCOORD_0", tex_coord.into()),
+                            ]),
+                        ),
+                        ("indices", indices.into()),
+                        ("material", object.material.into()),
+                    ])]),
+                )]));
+                mesh_materials.push(key);
+                gltf_meshes.len() - 1
+            }
+        };
+        let mut node = Json::object([("mesh", gltf_mesh.into())]);
+        if object.transform != Matrix4::identity() {
+            let matrix: &[f32; 16] = object.transform.as_ref();
+            node.insert("matrix", *matrix);
+        }
+        nodes.push(node);
+    }
+
+    let mut root = Json::object([
+        (
+            "asset",
+            Json::object([
+                ("version", "2.0".into()),
+                ("generator", "the-camera".into()),
+            ]),
+        ),
+        ("extensionsUsed", ["KHR_materials_unlit"].into()),
+        ("scene", 0.into()),
+        (
+            "scenes",
+            Json::Array(vec![Json::object([(
+                "nodes",
+                (0..nodes.len()).collect::<Vec<_>>().into(),
+            )])]),
+        ),
+        ("nodes", Json::Array(nodes)),
+        ("meshes", Json::Array(gltf_meshes)),
+        ("materials", Json::Array(materials)),
+        ("accessors", Json::Array(document.accessors)),
+        ("bufferViews", Json::Array(document.views)),
+        (
+            "buffers",
+            Json::Array(vec![Json::object([(
+                "byteLength",
+                document.binary.len().into(),
+            )])]),
+        ),
+    ]);
+    if !document.images.is_empty() {
+        let textures: Vec<Json> = (0..document.images.len())
+            .map(|image| Json::object([("source", image.into())]))
+            .collect();
+        root.insert("images", Json::Array(document.images));
+        root.insert("textures", Json::Array(textures));
+    }
+
+    let json = root.to_string().into_bytes();
+    Ok(glb(json, document.binary))
+}
+
+/// Accessors and buffer views written so far, with the binary chunk they point into
+#[derive(Default)]
+struct Writer {
+    binary: Vec<u8>,
+    views: Vec<Json>,
+    accessors: Vec<Json>,
+    images: Vec<Json>,
+}
+
+impl Writer {
+    /// Appends a buffer view, returning its index
+    fn view(&mut self, bytes: &[u8], target: Option<usize>) -> usize {
+        // Offsets of float data have to be multiples of 4
+        self.binary.resize(self.binary.len().next_multiple_of(4), 0);
+        let mut view = Json::object([
+            ("buffer", 0.into()),
+            ("byteOffset", self.binary.len().into()),
+            ("byteLength", bytes.len().into()),
+        ]);
+        if let Some(target) = target {
+            view.insert("target", target);
+        }
+        self.binary.extend_from_slice(bytes);
+        self.views.push(view);
+        self.views.len() - 1
+    }
+
+    /// Appends an accessor of all the floats, `width` to an element, returning its index
+    fn floats(&mut self, floats: &[f32], width: usize, kind: &str, bounds: bool) -> usize {
+        let view = self.view(bytemuck::cast_slice(floats), Some(ARRAY_BUFFER));
+        let mut accessor = Json::object([
+            ("bufferView", view.into()),
+            ("componentType", FLOAT.into()),
+            ("count", (floats.len() / width).into()),
+            ("type", kind.into()),
+        ]);
+        // Positions need their bounds
+        if bounds {
+            let mut min = vec![f32::INFINITY; width];
+            let mut max = vec![f32::NEG_INFINITY; width];
+            for element in floats.chunks_exact(width) {
+                for (axis, &value) in element.iter().enumerate() {
+                    min[axis] = min[axis].min(value);
+                    max[axis] = max[axis].max(value);
+                }
+            }
+            accessor.insert("min", min);
+            accessor.insert("max", max);
+        }
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+
+    /// The position, color, texture coordinate and index accessors of a mesh, None for meshes without triangles
+    fn mesh(&mut self, mesh: &MeshData) -> Option<[usize; 4]> {
+        if mesh.vertices.is_empty() || mesh.indices.len() < 3 {
+            return None;
+        }
+        let positions: Vec<f32> = mesh
+            .vertices
+            .iter()
+            .flat_map(|vertex| vertex.position())
+            .collect();
+        // glTF vertex colors are linear, the engine's are drawn as they are
+        let colors: Vec<f32> = mesh
+            .vertices
+            .iter()
+            .flat_map(|vertex| vertex.color().map(decode_srgb))
+            .collect();
+        let tex_coords: Vec<f32> = mesh
+            .vertices
+            .iter()
+            .flat_map(|vertex| vertex.tex_coords())
+            .collect();
+        let position = self.floats(&positions, 3, "VEC3", true);
+        let color = self.floats(&colors, 3, "VEC3", false);
+        let tex_coord = self.floats(&tex_coords, 2, "VEC2", false);
+        let view = self.view(
+            bytemuck::cast_slice(&mesh.indices),
+            Some(ELEMENT_ARRAY_BUFFER),
+        );
+        self.accessors.push(Json::object([
+            ("bufferView", view.into()),
+            ("componentType", UNSIGNED_SHORT.into()),
+            ("count", mesh.indices.len().into()),
+            ("type", "SCALAR".into()),
+        ]));
+        Some([position, color, tex_coord, self.accessors.len() - 1])
+    }
+}
+
+fn material_json(material: &ExportMaterial, texture_count: usize) -> Json {
+    let [r, g, b, alpha] = material.base_color;
+    let mut pbr = Json::object([
+        (
+            "baseColorFactor",
+            [decode_srgb(r), decode_srgb(g), decode_srgb(b), alpha].into(),
+        ),
+        ("metallicFactor", 0.0.into()),
+        ("roughnessFactor", 1.0.into()),
+    ]);
+    if let Some(texture) = material.texture.filter(|&texture| texture < texture_count) {
+        pbr.insert(
+            "baseColorTexture",
+            Json::object([("index", texture.into())]),
+        );
+    }
+    let mut json = Json::object([
+        ("pbrMetallicRoughness", pbr),
+        (
+            "extensions",
+            Json::object([("KHR_materials_unlit", Json::object([]))]),
+        ),
+    ]);
+    if alpha < 1.0 {
+        json.insert("alphaMode", "BLEND");
+    }
+    json
+}
+
+/// The container around the document and its binary chunk, both padded to 4 bytes
+fn glb(mut json: Vec<u8>, mut binary: Vec<u8>) -> Vec<u8> {
+    json.resize(json.len().next_multiple_of(4), b' ');
+    binary.resize(binary.len().next_multiple_of(4), 0);
+    let length = 12 + 8 + json.len() + 8 + binary.len();
+    let mut glb = Vec::with_capacity(length);
+    glb.extend_from_slice(GLB_MAGIC);
+    glb.extend_from_slice(&2u32.to_le_bytes());
+    glb.extend_from_slice(&(length as u32).to_le_bytes());
+    for (kind, chunk) in [(CHUNK_JSON, &json), (CHUNK_BIN, &binary)] {
+        glb.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+        glb.extend_from_slice(&kind.to_le_bytes());
+        glb.extend_from_slice(chunk);
+    }
+    glb
+}
@@ -0,0 +1,452 @@
+//! Just enough JSON for glTF documents: the whole document is parsed into a tree of [Json] values, and written back
+//! out from one with [Json]'s [Display] implementation
+
+use std::{
+    fmt::{self, Display, Write},
+    io,
+};
+
+/// Deeper documents are rejected rather than risking the stack
+const MAX_DEPTH: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Members in the document's order
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// An object of the members given, for writing documents
+    pub fn object<const N: usize>(members: [(&str, Json); N]) -> Self {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /// Adds a member to an object, other values stay as they are
+    pub fn insert(&mut self, name: &str, value: impl Into<Json>) {
+        if let Json::Object(members) = self {
+            members.push((name.to_string(), value.into()));
+        }
+    }
+
+    /// An object's member, None for other values
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Json::Number(number) => Some(number),
+            _ => None,
+        }
+    }
+
+    pub fn as_f32(&self) -> Option<f32> {
+        self.as_f64().map(|number| number as f32)
+    }
+
+    /// Whole, non-negative numbers, like the indices glTF uses to refer to objects
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_f64()
+            .filter(|number| number.fract() == 0.0 && *number >= 0.0)
+            .map(|number| number as usize)
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Json::Bool(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// An array's elements, empty for other values so missing arrays read as empty ones
+    pub fn elements(&self) -> &[Json] {
+        match self {
+            Json::Array(elements) => elements,
+            _ => &[],
+        }
+    }
+
+    /// An array of `N` numbers
+    pub fn as_floats<const N: usize>(&self) -> Option<[f32; N]> {
+        let elements = self.elements();
+        if elements.len() != N {
+            return None;
+        }
+        let mut floats = [0.0; N];
+        for (float, element) in floats.iter_mut().zip(elements) {
+            *float = element.as_f32()?;
+        }
+        Some(floats)
+    }
+}
+
+impl From<f32> for Json {
+    fn from(number: f32) -> Self {
+        // Through its shortest decimal, so 0.2 gets written as 0.2 and not 0.20000000298023224
+        Json::Number(number.to_string().parse().unwrap_or(f64::NAN))
+    }
+}
+
+impl From<usize> for Json {
+    fn from(number: usize) -> Self {
+        Json::Number(number as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(string: &str) -> Self {
+        Json::String(string.to_string())
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(elements: Vec<T>) -> Self {
+        Json::Array(elements.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Json>, const N: usize> From<[T; N]> for Json {
+    fn from(elements: [T; N]) -> Self {
+        Json::Array(elements.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Compact, without any whitespace
+impl Display for Json {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => formatter.write_str("null"),
+            Json::Bool(value) => write!(formatter, "{value}"),
+            // JSON has no infinities or NaN
+            Json::Number(number) if !number.is_finite() => formatter.write_str("null"),
+            Json::Number(number) => write!(formatter, "{number}"),
+            Json::String(string) => write_string(formatter, string),
+            Json::Array(elements) => {
+                formatter.write_char('[')?;
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        formatter.write_char(',')?;
+                    }
+                    write!(formatter, "{element}")?;
+                }
+                formatter.write_char(']')
+            }
+            Json::Object(members) => {
+                formatter.write_char('{')?;
+                for (index, (name, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        formatter.write_char(',')?;
+                    }
+                    write_string(formatter, name)?;
+                    write!(formatter, ":{value}")?;
+                }
+                formatter.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(formatter: &mut fmt::Formatter, string: &str) -> fmt::Result {
+    formatter.write_char('"')?;
+    for character in string.chars() {
+        match character {
+            '"' => formatter.write_str("\\\""),
+            '\\' => formatter.write_str("\\\\"),
+            '\n' => formatter.write_str("\\n"),
+            '\r' => formatter.write_str("\\r"),
+            '\t' => formatter.write_str("\\t"),
+            control if control < ' ' => write!(formatter, "\\u{:04x}", control as u32),
+            _ => formatter.write_char(character),
+        }?;
+    }
+    formatter.write_char('"')
+}
+
+pub fn parse(text: &str) -> io::Result<Json> {
+    let mut parser = Parser {
+        bytes: text.as_bytes(),
+        position: 0,
+    };
+    let value = parser.value(0)?;
+    parser.whitespace();
+    if parser.position != parser.bytes.len() {
+        return Err(parser.error("trailing characters after the document"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("JSON: {message} at byte {}", self.position),
+        )
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> io::Result<()> {
+        self.whitespace();
+        if self.peek() != Some(byte) {
+            return Err(self.error(&format!("expected {:?}", byte as char)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    /// Whether the next non-whitespace byte is `byte`, skipping it if so
+    fn accept(&mut self, byte: u8) -> bool {
+        self.whitespace();
+        let accepted = self.peek() == Some(byte);
+        if accepted {
+            self.position += 1;
+        }
+        accepted
+    }
+
+    fn value(&mut self, depth: usize) -> io::Result<Json> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("nested too deeply"));
+        }
+        self.whitespace();
+        match self.peek() {
+            Some(b'{') => {
+                self.position += 1;
+                let mut members = Vec::new();
+                if !self.accept(b'}') {
+                    loop {
+                        self.whitespace();
+                        let name = self.string()?;
+                        self.expect(b':')?;
+                        members.push((name, self.value(depth + 1)?));
+                        if !self.accept(b',') {
+                            break;
+                        }
+                    }
+                    self.expect(b'}')?;
+                }
+                Ok(Json::Object(members))
+            }
+            Some(b'[') => {
+                self.position += 1;
+                let mut elements = Vec::new();
+                if !self.accept(b']') {
+                    loop {
+                        elements.push(self.value(depth + 1)?);
+                        if !self.accept(b',') {
+                            break;
+                        }
+                    }
+                    self.expect(b']')?;
+                }
+                Ok(Json::Array(elements))
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.literal("true", Json::Bool(true)),
+            Some(b'f') => self.literal("false", Json::Bool(false)),
+            Some(b'n') => self.literal("null", Json::Null),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end")),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> io::Result<Json> {
+        if !self.bytes[self.position..].starts_with(word.as_bytes()) {
+            return Err(self.error("unexpected word"));
+        }
+        self.position += word.len();
+        Ok(value)
+    }
+
+    fn number(&mut self) -> io::Result<Json> {
+        let start = self.position;
+        while matches!(
+            self.peek(),
+            Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+        ) {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|number| number.parse().ok())
+            .map(Json::Number)
+            .ok_or_else(|| self.error("malformed number"))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.position += 1;
+        let mut string = Vec::new();
+        loop {
+            let byte = self
+                .peek()
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.position += 1;
+            match byte {
+                b'"' => break,
+                b'\\' => {
+                    let escape = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.position += 1;
+                    let character = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return Err(self.error("unknown escape")),
+                    };
+                    let mut buffer = [0; 4];
+                    string.extend_from_slice(character.encode_utf8(&mut buffer).as_bytes());
+                }
+                _ => string.push(byte),
+            }
+        }
+        String::from_utf8(string).map_err(|_| self.error("string isn't UTF-8"))
+    }
+
+    /// After `\u`, with the low half of a surrogate pair in its own escape
+    fn unicode_escape(&mut self) -> io::Result<char> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if !self.bytes[self.position..].starts_with(b"\\u") {
+                return Err(self.error("unpaired surrogate"));
+            }
+            self.position += 2;
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("unpaired surrogate"));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid code point"))
+    }
+
+    fn hex4(&mut self) -> io::Result<u32> {
+        let digits = self
+            .bytes
+            .get(self.position..self.position + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| self.error("malformed \\u escape"))?;
+        self.position += 4;
+        Ok(digits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_document() {
+        let json = parse(
+            r#" {"asset": {"version": "2.0"}, "scene": 0, "nodes": [{"translation": [1, -2.5, 3e2]}],
+                "extras": {"flag": true, "none": null, "off": false}} "#,
+        )
+        .unwrap();
+        assert_eq!(
+            json.get("asset")
+                .and_then(|asset| asset.get("version"))
+                .and_then(Json::as_str),
+            Some("2.0")
+        );
+        assert_eq!(json.get("scene").and_then(Json::as_usize), Some(0));
+        let node = &json.get("nodes").unwrap().elements()[0];
+        assert_eq!(
+            node.get("translation").and_then(Json::as_floats),
+            Some([1.0, -2.5, 300.0])
+        );
+        let extras = json.get("extras").unwrap();
+        assert_eq!(extras.get("flag").and_then(Json::as_bool), Some(true));
+        assert_eq!(extras.get("none"), Some(&Json::Null));
+        assert_eq!(extras.get("off").and_then(Json::as_bool), Some(false));
+        assert_eq!(json.get("missing"), None);
+    }
+
+    #[test]
+    fn unescapes_strings() {
+        let json =
+            parse(r#"["a\"b\\c\/d\n\t", "\u00e9\u4e2d", "\ud83d\ude00", "\u0001"]"#).unwrap();
+        let strings: Vec<_> = json.elements().iter().filter_map(Json::as_str).collect();
+        assert_eq!(strings, ["a\"b\\c/d\n\t", "é中", "😀", "\u{1}"]);
+    }
+
+    #[test]
+    fn rejects_malformed_documents() {
+        for text in [
+            "",
+            "{",
+            "[1,]",
+            r#"{"a" 1}"#,
+            r#""unterminated"#,
+            r#""\ud83d""#,
+            r#""\x""#,
+            "tru",
+            "1 2",
+            "--1",
+        ] {
+            assert!(parse(text).is_err(), "{text:?} parsed");
+        }
+        assert!(parse(&"[".repeat(MAX_DEPTH + 2)).is_err());
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        let json = Json::object([
+            ("name", "quote \" backslash \\ newline \n bell \u{7}".into()),
+            ("values", vec![0.5f32, -1.0, 1e-3].into()),
+            ("count", 3usize.into()),
+            ("empty", Json::Array(Vec::new())),
+        ]);
+        assert_eq!(parse(&json.to_string()).unwrap(), json);
+        // Infinities become null rather than invalid JSON
+        assert_eq!(Json::Number(f64::INFINITY).to_string(), "null");
+    }
+}
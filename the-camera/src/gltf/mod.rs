@@ -0,0 +1,1134 @@
+//! Loading glTF 2.0 models, `.gltf` with external or embedded buffers and binary `.glb`: the triangles of the
+//! default scene's meshes with their texture coordinates and vertex colors, placed by the node hierarchy.
+//!
+//! The engine draws unlit, so materials come down to a color: the base color and its PNG texture, brightened by the
+//! emissive color and strength of `KHR_materials_emissive_strength` and made see-through by
+//! `KHR_materials_transmission`. The coats of `KHR_materials_clearcoat` and the index of refraction of
+//! `KHR_materials_ior` reflect the nearest reflection probe, see [Material::clearcoat] and [Material::ior]. Other
+//! textures, normals, skins, morph targets, cameras and lights are left out.
+//!
+//! Files with animations get a skeleton with a joint for every node, which the parts of its meshes follow, and a clip
+//! for every animation moving the nodes' translation, rotation and scale, see [crate::animation::AnimationPlayer].
+//! Cubic spline keyframes are interpolated linearly between their values.
+//!
+//! Scenes go the other way with [encode_glb].
+
+mod export;
+mod json;
+
+use std::{fmt::Display, io, path::Path};
+
+use cgmath::{Matrix4, Quaternion, SquareMatrix, Vector3, Vector4};
+
+pub use self::export::{encode_glb, ExportMaterial, ExportObject, ExportScene};
+use self::json::Json;
+use crate::{
+    assets::{decode_png, encode_srgb},
+    mesh::Vertex,
+    model::{
+        AnimationClip, Channel, ChannelProperty, Joint, ModelData, ModelPart, PolygonBuilder,
+        Skeleton,
+    },
+    scene::{Material, Transform},
+    texture::TextureData,
+};
+
+const GLB_MAGIC: &[u8] = b"glTF";
+const CHUNK_JSON: u32 = 0x4E4F_534A;
+const CHUNK_BIN: u32 = 0x004E_4942;
+/// Extensions files may require without changing the parts this loader reads, or only changing the material ones
+const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "KHR_materials_clearcoat",
+    "KHR_materials_emissive_strength",
+    "KHR_materials_ior",
+    "KHR_materials_transmission",
+    "KHR_materials_unlit",
+    "KHR_mesh_quantization",
+    "KHR_texture_transform",
+];
+
+/// Reads a `.gltf` or `.glb` file, with the buffers it refers to next to it
+pub fn load(path: impl AsRef<Path>) -> io::Result<ModelData> {
+    let path = path.as_ref();
+    let directory = path.parent().unwrap_or(Path::new(""));
+    decode(&std::fs::read(path)?, |uri| {
+        std::fs::read(directory.join(percent_decode(uri)))
+    })
+}
+
+/// Reads a glTF document or GLB container, `resolve` loads the buffers it refers to by URI. Data URIs and the GLB's
+/// own binary chunk need no resolving.
+pub fn decode(
+    bytes: &[u8],
+    mut resolve: impl FnMut(&str) -> io::Result<Vec<u8>>,
+) -> io::Result<ModelData> {
+    let (json, mut binary) = if bytes.starts_with(GLB_MAGIC) {
+        let (json, binary) = glb_chunks(bytes)?;
+        (json, binary.map(<[u8]>::to_vec))
+    } else {
+        (bytes, None)
+    };
+    let text = std::str::from_utf8(json).map_err(|_| invalid("document isn't UTF-8"))?;
+    let root = json::parse(text.trim_start_matches('\u{feff}'))?;
+
+    let version = root
+        .get("asset")
+        .and_then(|asset| asset.get("version")?.as_str())
+        .unwrap_or_default();
+    if !version.starts_with("2.") {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("glTF {version} files aren't supported, only 2.0"),
+        ));
+    }
+    if let Some(extension) = root
+        .get("extensionsRequired")
+        .map_or(&[][..], Json::elements)
+        .iter()
+        .filter_map(Json::as_str)
+        .find(|extension| !SUPPORTED_EXTENSIONS.contains(extension))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("glTF file requires {extension}, which isn't supported"),
+        ));
+    }
+
+    let buffers = elements(&root, "buffers")
+        .iter()
+        .enumerate()
+        .map(
+            |(index, buffer)| match buffer.get("uri").and_then(Json::as_str) {
+                Some(uri) => match uri.strip_prefix("data:") {
+                    Some(data) => data_uri(data),
+                    None => resolve(uri),
+                },
+                // Only the first buffer of a GLB may leave out its URI, it's the binary chunk
+                None => match index {
+                    0 => binary.take().ok_or_else(|| invalid("buffer 0 has no data")),
+                    _ => Err(invalid(format!("buffer {index} has no URI"))),
+                },
+            },
+        )
+        .collect::<io::Result<_>>()?;
+    let mut document = Document {
+        root: &root,
+        buffers,
+        textures: Vec::new(),
+    };
+    let mut images = Vec::new();
+    document.textures = document.base_color_textures(&mut resolve, &mut images);
+
+    let animated = !elements(&root, "animations").is_empty();
+    let mut scene = Scene {
+        model: ModelData {
+            skeleton: animated.then(Skeleton::default),
+            ..Default::default()
+        },
+        joints: vec![None; elements(&root, "nodes").len()],
+        visited: vec![false; elements(&root, "nodes").len()],
+    };
+    for node in document.scene_roots() {
+        document.node(node, Matrix4::identity(), None, &mut scene)?;
+    }
+    let Scene {
+        mut model, joints, ..
+    } = scene;
+    model.textures = images;
+    if model.parts.is_empty() {
+        return Err(invalid("scene has no triangles"));
+    }
+    if animated {
+        model.animations = elements(&root, "animations")
+            .iter()
+            .enumerate()
+            .map(|(index, animation)| document.animation(index, animation, &joints))
+            .collect::<io::Result<_>>()?;
+    }
+    Ok(model)
+}
+
+/// The JSON chunk and the binary chunk that may follow it
+fn glb_chunks(bytes: &[u8]) -> io::Result<(&[u8], Option<&[u8]>)> {
+    let word = |offset: usize| {
+        bytes
+            .get(offset..offset + 4)
+            .map(|word| u32::from_le_bytes(word.try_into().expect("Took 4 bytes!")))
+            .ok_or_else(|| invalid("unexpected end of GLB"))
+    };
+    let version = word(4)?;
+    if version != 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("GLB version {version} isn't supported, only 2"),
+        ));
+    }
+    let length = (word(8)? as usize).min(bytes.len());
+    let mut chunks = Vec::new();
+    let mut offset = 12;
+    while offset + 8 <= length {
+        let (size, kind) = (word(offset)? as usize, word(offset + 4)?);
+        let data = bytes
+            .get(offset + 8..offset + 8 + size)
+            .ok_or_else(|| invalid("GLB chunk ends outside of the file"))?;
+        chunks.push((kind, data));
+        // Chunks are padded to 4 bytes
+        offset += 8 + size.next_multiple_of(4);
+    }
+    match chunks[..] {
+        [(CHUNK_JSON, json), ..] => Ok((
+            json,
+            chunks
+                .get(1)
+                .filter(|&&(kind, _)| kind == CHUNK_BIN)
+                .map(|&(_, data)| data),
+        )),
+        _ => Err(invalid("GLB doesn't start with a JSON chunk")),
+    }
+}
+
+/// The nodes walked so far and what they added
+struct Scene {
+    model: ModelData,
+    /// The joint each node became, in animated files
+    joints: Vec<Option<usize>>,
+    visited: Vec<bool>,
+}
+
+struct Document<'a> {
+    root: &'a Json,
+    buffers: Vec<Vec<u8>>,
+    /// The [ModelData::textures] index of every glTF texture, None for those no base color samples or that failed
+    /// to load
+    textures: Vec<Option<usize>>,
+}
+
+impl Document<'_> {
+    fn object(&self, kind: &str, index: usize) -> io::Result<&Json> {
+        elements(self.root, kind)
+            .get(index)
+            .ok_or_else(|| invalid(format!("{kind} {index} doesn't exist")))
+    }
+
+    /// The default scene's nodes, or every node without a parent in files without scenes
+    fn scene_roots(&self) -> Vec<usize> {
+        let scene = self.root.get("scene").and_then(Json::as_usize).unwrap_or(0);
+        if let Some(scene) = elements(self.root, "scenes").get(scene) {
+            return indices(scene, "nodes");
+        }
+        let nodes = elements(self.root, "nodes");
+        let children: Vec<usize> = nodes
+            .iter()
+            .flat_map(|node| indices(node, "children"))
+            .collect();
+        (0..nodes.len())
+            .filter(|node| !children.contains(node))
+            .collect()
+    }
+
+    /// Adds the node's meshes and those of its children, and their joints in animated files
+    fn node(
+        &self,
+        index: usize,
+        parent: Matrix4<f32>,
+        parent_joint: Option<usize>,
+        scene: &mut Scene,
+    ) -> io::Result<()> {
+        let node = self.object("nodes", index)?;
+        // Nodes have a single parent, reaching one twice means the hierarchy loops back on itself
+        if std::mem::replace(&mut scene.visited[index], true) {
+            return Err(invalid(format!("node {index} is its own ancestor")));
+        }
+        let name = match node.get("name").and_then(Json::as_str) {
+            Some(name) => name.to_string(),
+            None => format!("node {index}"),
+        };
+        let local = local_transform(node);
+        let transform = parent * local;
+        let joint = scene.model.skeleton.as_mut().map(|skeleton| {
+            skeleton.joints.push(Joint {
+                name: name.clone(),
+                parent: parent_joint,
+                rest: Transform::from_matrix(local),
+            });
+            skeleton.joints.len() - 1
+        });
+        scene.joints[index] = joint;
+        if let Some(mesh) = node.get("mesh").and_then(Json::as_usize) {
+            self.mesh(mesh, &name, transform, joint, &mut scene.model)?;
+        }
+        for child in indices(node, "children") {
+            self.node(child, transform, joint, scene)?;
+        }
+        Ok(())
+    }
+
+    fn mesh(
+        &self,
+        index: usize,
+        name: &str,
+        transform: Matrix4<f32>,
+        joint: Option<usize>,
+        model: &mut ModelData,
+    ) -> io::Result<()> {
+        let mesh = self.object("meshes", index)?;
+        for (primitive_index, primitive) in elements(mesh, "primitives").iter().enumerate() {
+            let triangles = match primitive.get("mode").and_then(Json::as_usize).unwrap_or(4) {
+                mode @ 4..=6 => mode,
+                _ => {
+                    tracing::warn!(
+                        "{name}: primitive {primitive_index} isn't made of triangles, skipping it"
+                    );
+                    continue;
+                }
+            };
+            let attribute = |name: &str| {
+                primitive
+                    .get("attributes")
+                    .and_then(|attributes| attributes.get(name)?.as_usize())
+                    .map(|accessor| self.accessor(accessor))
+                    .transpose()
+            };
+            let Some(positions) = attribute("POSITION")? else {
+                tracing::warn!("{name}: primitive {primitive_index} has no positions, skipping it");
+                continue;
+            };
+            if positions.width != 3 {
+                return Err(invalid(format!("{name}: positions aren't 3D")));
+            }
+            let tex_coords = attribute("TEXCOORD_0")?;
+            let colors = attribute("COLOR_0")?;
+            let corners: Vec<usize> = match primitive.get("indices").and_then(Json::as_usize) {
+                Some(accessor) => self
+                    .accessor(accessor)?
+                    .values
+                    .iter()
+                    .map(|&index| index as usize)
+                    .collect(),
+                None => (0..positions.count()).collect(),
+            };
+            if let Some(&corner) = corners.iter().find(|&&corner| corner >= positions.count()) {
+                return Err(invalid(format!("{name}: vertex {corner} doesn't exist")));
+            }
+            let (material, texture) = match primitive.get("material").and_then(Json::as_usize) {
+                Some(material) => self.material(material)?,
+                None => (Material::default(), None),
+            };
+
+            // Faces are counter-clockwise like the engine's, unless the transform mirrors them
+            let flip = transform.determinant() < 0.0;
+            let mut polygons = PolygonBuilder::<usize>::default();
+            let vertex = |&corner: &usize| {
+                let position = positions.element(corner);
+                // glTF counts V from the top of the image, like wgpu
+                let tex_coord =
+                    tex_coords.as_ref().map_or([0.0, 0.0], |tex_coords| {
+                        match tex_coords.element(corner) {
+                            &[u, v, ..] => [u as f32, v as f32],
+                            _ => [0.0, 0.0],
+                        }
+                    });
+                let color =
+                    colors
+                        .as_ref()
+                        .map_or([1.0; 3], |colors| match colors.element(corner) {
+                            &[r, g, b, ..] => [r, g, b].map(|linear| encode_srgb(linear as f32)),
+                            _ => [1.0; 3],
+                        });
+                let position = [position[0], position[1], position[2]].map(|axis| axis as f32);
+                Vertex::new(position, color, tex_coord)
+            };
+            for triangle in triangle_corners(triangles, corners.len()) {
+                let keys = triangle.map(|corner| corners[corner]);
+                polygons.add(&keys, flip, vertex);
+            }
+
+            // Meshes with more vertices than 16 bit indices reach come out as several parts
+            model
+                .parts
+                .extend(polygons.finish().into_iter().map(|(mesh, _)| ModelPart {
+                    name: name.to_string(),
+                    mesh,
+                    material,
+                    texture,
+                    transform,
+                    skin: None,
+                    joint,
+                }));
+        }
+        Ok(())
+    }
+
+    /// Decodes the images of the textures materials take their base color from into `images`, once for every image
+    /// however many textures share it. Returns where each texture's image went.
+    fn base_color_textures(
+        &self,
+        mut resolve: impl FnMut(&str) -> io::Result<Vec<u8>>,
+        images: &mut Vec<TextureData>,
+    ) -> Vec<Option<usize>> {
+        let textures = elements(self.root, "textures");
+        let used: Vec<usize> = elements(self.root, "materials")
+            .iter()
+            .filter_map(|material| {
+                material
+                    .get("pbrMetallicRoughness")?
+                    .get("baseColorTexture")?
+                    .get("index")?
+                    .as_usize()
+            })
+            .collect();
+        // The index in `images` of every glTF image decoded so far
+        let mut decoded: Vec<(usize, Option<usize>)> = Vec::new();
+        (0..textures.len())
+            .map(|texture| {
+                if !used.contains(&texture) {
+                    return None;
+                }
+                let image = textures[texture].get("source").and_then(Json::as_usize)?;
+                if let Some(&(_, index)) = decoded.iter().find(|&&(other, _)| other == image) {
+                    return index;
+                }
+                let index = match self.image(image, &mut resolve) {
+                    Ok(data) => {
+                        images.push(data);
+                        Some(images.len() - 1)
+                    }
+                    Err(err) => {
+                        tracing::warn!(
+                            "Image {image} failed to load, using the base color factor: {err}"
+                        );
+                        None
+                    }
+                };
+                decoded.push((image, index));
+                index
+            })
+            .collect()
+    }
+
+    /// A PNG image, from a buffer view or a URI
+    fn image(
+        &self,
+        index: usize,
+        resolve: impl FnOnce(&str) -> io::Result<Vec<u8>>,
+    ) -> io::Result<TextureData> {
+        let image = self.object("images", index)?;
+        let bytes = match (
+            image.get("bufferView").and_then(Json::as_usize),
+            image.get("uri").and_then(Json::as_str),
+        ) {
+            (Some(view), _) => self.view(view)?.to_vec(),
+            (None, Some(uri)) => match uri.strip_prefix("data:") {
+                Some(data) => data_uri(data)?,
+                None => resolve(uri)?,
+            },
+            (None, None) => return Err(invalid(format!("image {index} has no data"))),
+        };
+        if !bytes.starts_with(b"\x89PNG") {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "only PNG images are supported",
+            ));
+        }
+        decode_png(&bytes)
+    }
+
+    /// The material and the [ModelData::textures] index of the image it samples
+    fn material(&self, index: usize) -> io::Result<(Material, Option<usize>)> {
+        let material = self.object("materials", index)?;
+        let name = match material.get("name").and_then(Json::as_str) {
+            Some(name) => name.to_string(),
+            None => format!("material {index}"),
+        };
+        let extension = |extension: &str| {
+            material
+                .get("extensions")
+                .and_then(|extensions| extensions.get(extension))
+        };
+        let factor = |object: Option<&Json>, name: &str, default: f32| {
+            object
+                .and_then(|object| object.get(name)?.as_f32())
+                .unwrap_or(default)
+        };
+        let pbr = material.get("pbrMetallicRoughness");
+        let base_color_texture = pbr.and_then(|pbr| pbr.get("baseColorTexture"));
+        if base_color_texture
+            .and_then(|texture| texture.get("texCoord")?.as_usize())
+            .is_some_and(|set| set != 0)
+        {
+            tracing::warn!(
+                "{name}: only the first texture coordinates are read, the texture may be misplaced"
+            );
+        }
+        if base_color_texture
+            .and_then(|texture| texture.get("extensions")?.get("KHR_texture_transform"))
+            .is_some()
+        {
+            tracing::warn!(
+                "{name}: texture transforms aren't supported, the texture may be misplaced"
+            );
+        }
+        let texture = base_color_texture
+            .and_then(|texture| texture.get("index")?.as_usize())
+            .and_then(|texture| self.textures.get(texture).copied().flatten());
+        let [r, g, b, alpha] = pbr
+            .and_then(|pbr| pbr.get("baseColorFactor")?.as_floats())
+            .unwrap_or([1.0; 4]);
+
+        // Light the surface gives off on its own, adding to the color it shows without any lighting
+        let emissive_strength = factor(
+            extension("KHR_materials_emissive_strength"),
+            "emissiveStrength",
+            1.0,
+        );
+        let emissive = material
+            .get("emissiveFactor")
+            .and_then(Json::as_floats::<3>)
+            .unwrap_or([0.0; 3])
+            .map(|channel| channel * emissive_strength);
+
+        // Light passing through the surface, the background shows through instead of the base color
+        let transmission = extension("KHR_materials_transmission");
+        if transmission
+            .is_some_and(|transmission| transmission.get("transmissionTexture").is_some())
+        {
+            tracing::warn!("{name}: textures aren't supported, using the transmission factor");
+        }
+        let transmission = factor(transmission, "transmissionFactor", 0.0).clamp(0.0, 1.0);
+
+        // A reflective layer on top, and how much the surface under it reflects
+        let clearcoat = extension("KHR_materials_clearcoat");
+        if clearcoat.is_some_and(|clearcoat| {
+            clearcoat.get("clearcoatTexture").is_some()
+                || clearcoat.get("clearcoatRoughnessTexture").is_some()
+        }) {
+            tracing::warn!("{name}: textures aren't supported, using the clearcoat factors");
+        }
+        let clearcoat_roughness =
+            factor(clearcoat, "clearcoatRoughnessFactor", 0.0).clamp(0.0, 1.0);
+        let clearcoat = factor(clearcoat, "clearcoatFactor", 0.0).clamp(0.0, 1.0);
+        // Without the extension the surface reflects nothing, rather than like glTF's default of 1.5
+        let ior = factor(extension("KHR_materials_ior"), "ior", 1.0).max(1.0);
+
+        let alpha = match material.get("alphaMode").and_then(Json::as_str) {
+            Some("BLEND") => alpha,
+            // The engine ignores the alpha of textures and vertices, the whole surface is either above the cutoff or
+            // below it
+            Some("MASK") => match alpha >= factor(Some(material), "alphaCutoff", 0.5) {
+                true => 1.0,
+                false => 0.0,
+            },
+            // The default, ignoring alpha
+            _ => 1.0,
+        } * (1.0 - transmission);
+        let color = |channel: usize| encode_srgb(([r, g, b][channel] + emissive[channel]).min(1.0));
+        let material = Material {
+            base_color: [color(0), color(1), color(2), alpha],
+            clearcoat,
+            clearcoat_roughness,
+            ior,
+            ..Default::default()
+        };
+        Ok((material, texture))
+    }
+
+    /// A clip with a channel for every node translation, rotation and scale the animation moves
+    fn animation(
+        &self,
+        index: usize,
+        animation: &Json,
+        joints: &[Option<usize>],
+    ) -> io::Result<AnimationClip> {
+        let name = match animation.get("name").and_then(Json::as_str) {
+            Some(name) => name.to_string(),
+            None => format!("animation {index}"),
+        };
+        let samplers = elements(animation, "samplers");
+        let mut channels = Vec::new();
+        for channel in elements(animation, "channels") {
+            let target = channel.get("target");
+            let property = match target.and_then(|target| target.get("path")?.as_str()) {
+                Some("translation") => ChannelProperty::Translation,
+                Some("rotation") => ChannelProperty::Rotation,
+                Some("scale") => ChannelProperty::Scale,
+                _ => {
+                    tracing::debug!(
+                        "{name}: only node transforms are animated, skipping a channel"
+                    );
+                    continue;
+                }
+            };
+            // Nodes outside of the scene have no joint
+            let Some(joint) = target
+                .and_then(|target| target.get("node")?.as_usize())
+                .and_then(|node| joints.get(node).copied().flatten())
+            else {
+                continue;
+            };
+            let sampler = channel
+                .get("sampler")
+                .and_then(Json::as_usize)
+                .and_then(|sampler| samplers.get(sampler))
+                .ok_or_else(|| invalid(format!("{name}: channel without a sampler")))?;
+            let accessor = |name: &str| {
+                sampler
+                    .get(name)
+                    .and_then(Json::as_usize)
+                    .ok_or_else(|| invalid(format!("sampler without {name}")))
+                    .and_then(|accessor| self.accessor(accessor))
+            };
+            let times: Vec<f32> = accessor("input")?
+                .values
+                .iter()
+                .map(|&time| time as f32)
+                .collect();
+            let output = accessor("output")?;
+            let value = |element: usize| {
+                let mut value = [0.0; 4];
+                for (value, &component) in value.iter_mut().zip(output.element(element)) {
+                    *value = component as f32;
+                }
+                value
+            };
+            let interpolation = sampler.get("interpolation").and_then(Json::as_str);
+            // Cubic splines store an in tangent, the value and an out tangent for every keyframe
+            let stride = match interpolation {
+                Some("CUBICSPLINE") => 3,
+                _ => 1,
+            };
+            if output.count() != times.len() * stride {
+                return Err(invalid(format!(
+                    "{name}: keyframe times and values don't match"
+                )));
+            }
+            let mut channel = Channel {
+                joint,
+                property,
+                times: Vec::with_capacity(times.len()),
+                values: Vec::with_capacity(times.len()),
+            };
+            for (keyframe, &time) in times.iter().enumerate() {
+                let value = value(keyframe * stride + stride / 2);
+                // Steps jump to the next value at its keyframe, holding the previous one up to it
+                if interpolation == Some("STEP") {
+                    if let Some(&previous) = channel.values.last() {
+                        channel.times.push(time);
+                        channel.values.push(previous);
+                    }
+                }
+                channel.times.push(time);
+                channel.values.push(value);
+            }
+            channels.push(channel);
+        }
+        let duration = channels
+            .iter()
+            .filter_map(|channel| channel.times.last().copied())
+            .fold(0.0, f32::max);
+        Ok(AnimationClip {
+            name,
+            duration,
+            channels,
+        })
+    }
+
+    fn accessor(&self, index: usize) -> io::Result<Accessor> {
+        let accessor = self.object("accessors", index)?;
+        let name = format!("accessor {index}");
+        let count = accessor
+            .get("count")
+            .and_then(Json::as_usize)
+            .ok_or_else(|| invalid(format!("{name} has no count")))?;
+        let width = match accessor.get("type").and_then(Json::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            Some("MAT2") => 4,
+            Some("MAT3") => 9,
+            Some("MAT4") => 16,
+            _ => return Err(invalid(format!("{name} has an unknown type"))),
+        };
+        let component = Component::of(accessor)
+            .ok_or_else(|| invalid(format!("{name} has an unknown component type")))?;
+        let normalized = accessor
+            .get("normalized")
+            .and_then(Json::as_bool)
+            .unwrap_or(false);
+
+        // Accessors without a buffer view are all zeros, unless sparse values replace some of them
+        let mut values = match accessor.get("bufferView").and_then(Json::as_usize) {
+            Some(view) => self.read(
+                view,
+                accessor
+                    .get("byteOffset")
+                    .and_then(Json::as_usize)
+                    .unwrap_or(0),
+                component,
+                normalized,
+                count,
+                width,
+            )?,
+            None => vec![0.0; count * width],
+        };
+        if let Some(sparse) = accessor.get("sparse") {
+            let replaced = sparse.get("count").and_then(Json::as_usize).unwrap_or(0);
+            let part = |name: &str| -> io::Result<(usize, usize)> {
+                let part = sparse.get(name);
+                let view = part
+                    .and_then(|part| part.get("bufferView")?.as_usize())
+                    .ok_or_else(|| invalid(format!("sparse {name} has no buffer view")))?;
+                let offset = part
+                    .and_then(|part| part.get("byteOffset")?.as_usize())
+                    .unwrap_or(0);
+                Ok((view, offset))
+            };
+            let (view, offset) = part("indices")?;
+            let index_component = sparse
+                .get("indices")
+                .and_then(Component::of)
+                .ok_or_else(|| invalid("sparse indices have an unknown component type"))?;
+            let indices = self.read(view, offset, index_component, false, replaced, 1)?;
+            let (view, offset) = part("values")?;
+            let replacements = self.read(view, offset, component, normalized, replaced, width)?;
+            for (&element, replacement) in indices.iter().zip(replacements.chunks_exact(width)) {
+                let element = element as usize;
+                values
+                    .get_mut(element * width..(element + 1) * width)
+                    .ok_or_else(|| {
+                        invalid(format!("{name} replaces element {element}, out of range"))
+                    })?
+                    .copy_from_slice(replacement);
+            }
+        }
+        Ok(Accessor { width, values })
+    }
+
+    /// The bytes of a buffer view
+    fn view(&self, view: usize) -> io::Result<&[u8]> {
+        let buffer_view = self.object("bufferViews", view)?;
+        let buffer = buffer_view
+            .get("buffer")
+            .and_then(Json::as_usize)
+            .and_then(|buffer| self.buffers.get(buffer))
+            .ok_or_else(|| invalid(format!("buffer view {view} has no buffer")))?;
+        let start = buffer_view
+            .get("byteOffset")
+            .and_then(Json::as_usize)
+            .unwrap_or(0);
+        let length = buffer_view
+            .get("byteLength")
+            .and_then(Json::as_usize)
+            .unwrap_or(0);
+        start
+            .checked_add(length)
+            .and_then(|end| buffer.get(start..end))
+            .ok_or_else(|| invalid(format!("buffer view {view} ends outside of its buffer")))
+    }
+
+    /// `count` elements of `width` components from a buffer view, packed unless the view has a stride
+    fn read(
+        &self,
+        view: usize,
+        offset: usize,
+        component: Component,
+        normalized: bool,
+        count: usize,
+        width: usize,
+    ) -> io::Result<Vec<f64>> {
+        let buffer_view = self.object("bufferViews", view)?;
+        let bytes = self.view(view)?;
+        let element_size = component.size() * width;
+        let stride = match buffer_view.get("byteStride").and_then(Json::as_usize) {
+            // Elements are aligned to 4 bytes and can't overlap
+            Some(stride) if stride < element_size || stride % 4 != 0 => {
+                return Err(invalid(format!(
+                    "buffer view {view} has a byteStride of {stride} for elements of {element_size} bytes"
+                )));
+            }
+            Some(stride) => stride,
+            None => element_size,
+        };
+        let end = match count.checked_sub(1) {
+            Some(last) => last
+                .checked_mul(stride)
+                .and_then(|start| start.checked_add(offset))
+                .and_then(|start| start.checked_add(element_size)),
+            None => Some(0),
+        };
+        if end.is_none_or(|end| end > bytes.len()) {
+            return Err(invalid(format!(
+                "accessor ends outside of buffer view {view}"
+            )));
+        }
+        let mut values = Vec::with_capacity(count * width);
+        for element in 0..count {
+            let element = &bytes[offset + element * stride..][..element_size];
+            values.extend(
+                element
+                    .chunks_exact(component.size())
+                    .map(|bytes| component.value(bytes, normalized)),
+            );
+        }
+        Ok(values)
+    }
+}
+
+/// An accessor's elements, converted to floating point
+struct Accessor {
+    width: usize,
+    values: Vec<f64>,
+}
+
+impl Accessor {
+    fn count(&self) -> usize {
+        self.values.len() / self.width
+    }
+
+    fn element(&self, index: usize) -> &[f64] {
+        &self.values[index * self.width..(index + 1) * self.width]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Component {
+    I8,
+    U8,
+    I16,
+    U16,
+    U32,
+    F32,
+}
+
+impl Component {
+    /// From an accessor's `componentType`
+    fn of(accessor: &Json) -> Option<Self> {
+        Some(match accessor.get("componentType")?.as_usize()? {
+            5120 => Component::I8,
+            5121 => Component::U8,
+            5122 => Component::I16,
+            5123 => Component::U16,
+            5125 => Component::U32,
+            5126 => Component::F32,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Component::I8 | Component::U8 => 1,
+            Component::I16 | Component::U16 => 2,
+            Component::U32 | Component::F32 => 4,
+        }
+    }
+
+    /// Normalized integers map to 0 to 1, or -1 to 1 when signed
+    fn value(self, bytes: &[u8], normalized: bool) -> f64 {
+        let (value, max) = match self {
+            Component::I8 => (f64::from(bytes[0] as i8), 127.0),
+            Component::U8 => (f64::from(bytes[0]), 255.0),
+            Component::I16 => (f64::from(i16::from_le_bytes([bytes[0], bytes[1]])), 32767.0),
+            Component::U16 => (f64::from(u16::from_le_bytes([bytes[0], bytes[1]])), 65535.0),
+            Component::U32 => (
+                f64::from(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+                f64::from(u32::MAX),
+            ),
+            Component::F32 => {
+                return f64::from(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            }
+        };
+        match normalized {
+            true => (value / max).max(-1.0),
+            false => value,
+        }
+    }
+}
+
+/// Which of a primitive's corners make up each triangle, for lists, strips and fans
+fn triangle_corners(mode: usize, corners: usize) -> Vec<[usize; 3]> {
+    match mode {
+        // Strips alternate their winding, every other triangle swaps two corners to keep them facing the same way
+        5 => (0..corners.saturating_sub(2))
+            .map(|first| match first % 2 {
+                0 => [first, first + 1, first + 2],
+                _ => [first + 1, first, first + 2],
+            })
+            .collect(),
+        6 => (1..corners.saturating_sub(1))
+            .map(|first| [0, first, first + 1])
+            .collect(),
+        _ => (0..corners / 3)
+            .map(|triangle| [triangle * 3, triangle * 3 + 1, triangle * 3 + 2])
+            .collect(),
+    }
+}
+
+/// A node's matrix, or its translation, rotation and scale
+fn local_transform(node: &Json) -> Matrix4<f32> {
+    if let Some(matrix) = node.get("matrix").and_then(Json::as_floats::<16>) {
+        // Column major, like cgmath's
+        let column = |index: usize| {
+            Vector4::new(
+                matrix[index * 4],
+                matrix[index * 4 + 1],
+                matrix[index * 4 + 2],
+                matrix[index * 4 + 3],
+            )
+        };
+        return Matrix4::from_cols(column(0), column(1), column(2), column(3));
+    }
+    let translation = node
+        .get("translation")
+        .and_then(Json::as_floats)
+        .map_or(Vector3::new(0.0, 0.0, 0.0), Vector3::from);
+    let rotation = node
+        .get("rotation")
+        .and_then(Json::as_floats)
+        .map_or(Quaternion::new(1.0, 0.0, 0.0, 0.0), |[x, y, z, w]| {
+            Quaternion::new(w, x, y, z)
+        });
+    let [x, y, z] = node
+        .get("scale")
+        .and_then(Json::as_floats)
+        .unwrap_or([1.0; 3]);
+    Matrix4::from_translation(translation)
+        * Matrix4::from(rotation)
+        * Matrix4::from_nonuniform_scale(x, y, z)
+}
+
+fn elements<'a>(object: &'a Json, name: &str) -> &'a [Json] {
+    object.get(name).map_or(&[], Json::elements)
+}
+
+/// An array of object indices
+fn indices(object: &Json, name: &str) -> Vec<usize> {
+    elements(object, name)
+        .iter()
+        .filter_map(Json::as_usize)
+        .collect()
+}
+
+/// The bytes of a data URI, after `data:`
+fn data_uri(data: &str) -> io::Result<Vec<u8>> {
+    let (media_type, payload) = data
+        .split_once(',')
+        .ok_or_else(|| invalid("malformed data URI"))?;
+    match media_type.ends_with(";base64") {
+        true => base64(payload),
+        false => Ok(percent_decode(payload).into_bytes()),
+    }
+}
+
+fn base64(text: &str) -> io::Result<Vec<u8>> {
+    let sextet = |byte: u8| -> io::Result<u32> {
+        Ok(match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err(invalid("malformed base64 in a data URI")),
+        }
+        .into())
+    };
+    let text = text.trim_end_matches('=').as_bytes();
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    for chunk in text.chunks(4) {
+        let mut bits = 0;
+        for (index, &byte) in chunk.iter().enumerate() {
+            bits |= sextet(byte)? << (18 - 6 * index);
+        }
+        let decoded = bits.to_be_bytes();
+        // Each character holds 6 bits, the last chunk may end partway through a byte
+        bytes.extend_from_slice(&decoded[1..chunk.len()]);
+    }
+    Ok(bytes)
+}
+
+/// URIs escape spaces and the like as `%20`
+fn percent_decode(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn invalid(message: impl Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("glTF: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::MeshData;
+
+    /// Colors go through sRGB and back
+    fn assert_color(color: [f32; 4], expected: [f32; 4]) {
+        for (channel, wanted) in color.into_iter().zip(expected) {
+            assert!((channel - wanted).abs() < 1e-5, "{color:?} != {expected:?}");
+        }
+    }
+
+    /// A triangle in `tri.bin`, its node moved by `translation`
+    fn triangle_document(translation: [f32; 3]) -> (String, Vec<u8>) {
+        let positions: [f32; 9] = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let [x, y, z] = translation;
+        let document = format!(
+            r#"{{"asset": {{"version": "2.0"}}, "scene": 0, "scenes": [{{"nodes": [0]}}],
+                "nodes": [{{"mesh": 0, "translation": [{x}, {y}, {z}]}}],
+                "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "material": 0}}]}}],
+                "materials": [{{"pbrMetallicRoughness": {{"baseColorFactor": [1, 0, 0, 1]}}}}],
+                "accessors": [{{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}}],
+                "bufferViews": [{{"buffer": 0, "byteLength": 36}}],
+                "buffers": [{{"uri": "tri.bin", "byteLength": 36}}]}}"#
+        );
+        (document, bytemuck::cast_slice(&positions).to_vec())
+    }
+
+    #[test]
+    fn decodes_a_triangle_with_an_external_buffer() {
+        let (document, buffer) = triangle_document([1.0, 2.0, 3.0]);
+        let model = decode(document.as_bytes(), |uri| {
+            assert_eq!(uri, "tri.bin");
+            Ok(buffer.clone())
+        })
+        .unwrap();
+        assert_eq!(model.parts.len(), 1);
+        let part = &model.parts[0];
+        let positions: Vec<_> = part.mesh.vertices.iter().map(Vertex::position).collect();
+        assert_eq!(
+            positions,
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+        );
+        assert_eq!(part.mesh.indices.len(), 3);
+        assert_eq!(
+            part.transform,
+            Matrix4::from_translation([1.0, 2.0, 3.0].into())
+        );
+        assert_color(part.material.base_color, [1.0, 0.0, 0.0, 1.0]);
+        assert!(part.texture.is_none());
+    }
+
+    #[test]
+    fn rejects_unsupported_documents() {
+        let (document, buffer) = triangle_document([0.0; 3]);
+        let resolve = |_: &str| Ok(buffer.clone());
+        let old = document.replace(r#""version": "2.0""#, r#""version": "1.0""#);
+        assert!(decode(old.as_bytes(), resolve).is_err());
+        let required = document.replacen(
+            '{',
+            r#"{"extensionsRequired": ["KHR_draco_mesh_compression"], "#,
+            1,
+        );
+        assert!(decode(required.as_bytes(), resolve).is_err());
+        assert!(decode(b"{", resolve).is_err());
+    }
+
+    #[test]
+    fn round_trips_through_glb() {
+        let transform =
+            Matrix4::from_translation([0.5, -1.0, 2.0].into()) * Matrix4::from_scale(2.0);
+        let scene = ExportScene {
+            meshes: vec![MeshData::cube()],
+            materials: vec![ExportMaterial {
+                base_color: [0.25, 0.5, 0.75, 1.0],
+                texture: None,
+            }],
+            textures: Vec::new(),
+            objects: vec![ExportObject {
+                mesh: 0,
+                material: 0,
+                transform,
+            }],
+        };
+        let glb = encode_glb(&scene).unwrap();
+        assert!(glb.starts_with(GLB_MAGIC));
+        let model = decode(&glb, |uri| panic!("{uri} isn't in the GLB")).unwrap();
+
+        assert_eq!(model.parts.len(), 1);
+        let part = &model.parts[0];
+        let cube = MeshData::cube();
+        assert_eq!(part.mesh.indices.len(), cube.indices.len());
+        let position = |vertex: &Vertex| vertex.position();
+        let decoded: Vec<_> = part
+            .mesh
+            .indices
+            .iter()
+            .map(|&index| position(&part.mesh.vertices[index as usize]))
+            .collect();
+        let original: Vec<_> = cube
+            .indices
+            .iter()
+            .map(|&index| position(&cube.vertices[index as usize]))
+            .collect();
+        assert_eq!(decoded, original);
+        assert_eq!(part.transform, transform);
+        assert_color(part.material.base_color, [0.25, 0.5, 0.75, 1.0]);
+    }
+
+    #[test]
+    fn rejects_accessors_overflowing_their_views() {
+        let (document, buffer) = triangle_document([0.0; 3]);
+        let resolve = |_: &str| Ok(buffer.clone());
+        let huge_count =
+            document.replace(r#""count": 3"#, &format!(r#""count": {}"#, usize::MAX / 4));
+        assert!(decode(huge_count.as_bytes(), resolve).is_err());
+        let huge_offset = document.replace(
+            r#""buffer": 0,"#,
+            &format!(r#""buffer": 0, "byteOffset": {},"#, usize::MAX),
+        );
+        assert!(decode(huge_offset.as_bytes(), resolve).is_err());
+    }
+
+    #[test]
+    fn rejects_strides_that_overlap_or_misalign_elements() {
+        let (document, buffer) = triangle_document([0.0; 3]);
+        let resolve = |_: &str| Ok(buffer.clone());
+        let with_stride = |stride: usize| {
+            document.replace(
+                r#""buffer": 0,"#,
+                &format!(r#""buffer": 0, "byteStride": {stride},"#),
+            )
+        };
+        assert!(decode(with_stride(12).as_bytes(), resolve).is_ok());
+        assert!(decode(with_stride(8).as_bytes(), resolve).is_err());
+        assert!(decode(with_stride(14).as_bytes(), resolve).is_err());
+    }
+
+    #[test]
+    fn splits_strips_and_fans_into_triangles() {
+        assert_eq!(triangle_corners(4, 6), [[0, 1, 2], [3, 4, 5]]);
+        assert_eq!(triangle_corners(5, 4), [[0, 1, 2], [2, 1, 3]]);
+        assert_eq!(triangle_corners(6, 4), [[0, 1, 2], [0, 2, 3]]);
+        assert!(triangle_corners(5, 2).is_empty());
+    }
+
+    #[test]
+    fn decodes_data_uris() {
+        assert_eq!(
+            data_uri("application/octet-stream;base64,Zm9vYmE=").unwrap(),
+            b"fooba"
+        );
+        assert_eq!(data_uri(";base64,Zm9vYmFy").unwrap(), b"foobar");
+        assert_eq!(data_uri("text/plain,a%20b").unwrap(), b"a b");
+        assert!(data_uri("base64 without a comma").is_err());
+        assert!(base64("Zm9v!").is_err());
+        assert_eq!(percent_decode("my%20model.bin"), "my model.bin");
+    }
+}
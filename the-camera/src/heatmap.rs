@@ -0,0 +1,656 @@
+//! Heatmaps of where weighted points fall on screen, e.g. gaze samples or clicks of an interaction study.
+//!
+//! Each point is splatted into a float density texture as a Gaussian [Heatmap::radius] pixels wide, adding up where
+//! points overlap. The density is then mapped through the heatmap's [Colormap] and blended over the window, fading
+//! in from nothing so the scene shows where few points fell. Points are either in world space and follow the camera,
+//! or fixed on the screen.
+//!
+//! Heatmaps are drawn over the scene without depth testing, under the overlays and plots. The density texture needs
+//! blending into half floats, devices without it, e.g. some WebGL2 browsers, don't draw heatmaps.
+
+use std::collections::HashMap;
+
+use wgpu::{
+    Adapter, BindGroup, Buffer, CommandEncoder, Device, Queue, RenderPipeline, TextureFormat,
+    TextureView,
+};
+use winit::window::WindowId;
+
+use crate::{
+    colormap::{Colormap, ColormapTextures},
+    global_bindings::GlobalBindings,
+    lazy_pass::LazyPass,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        uniform_buffer::UniformBuffer,
+    },
+};
+
+const DENSITY_FORMAT: TextureFormat = TextureFormat::R16Float;
+
+/// A point splatted into a [Heatmap]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct HeatmapPoint {
+    /// In world units, or for [HeatmapSpace::Screen] from 0 at the window's top left to 1 at its bottom right with Z
+    /// unused
+    pub position: [f32; 3],
+    /// Density added at the point's center
+    pub weight: f32,
+}
+
+/// What [HeatmapPoint::position] is relative to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeatmapSpace {
+    /// Projected through the camera of each window, points behind it are skipped
+    #[default]
+    World,
+    /// Fractions of the window, the same in every window
+    Screen,
+}
+
+/// Weighted points drawn as a heatmap over every window, added with
+/// [crate::render_engine::RenderEngine::add_heatmap]
+#[derive(Debug, Clone)]
+pub struct Heatmap {
+    points: Vec<HeatmapPoint>,
+    /// Counts changes to the points, which are uploaded again when it moved on
+    revision: u64,
+    pub space: HeatmapSpace,
+    /// Of each point's splat, in pixels
+    pub radius: f32,
+    /// Density mapped to the last color of the colormap, what the weights of overlapping points add up to
+    pub max_density: f32,
+    pub colormap: Colormap,
+    /// Of the densest areas, the rest fades from transparent
+    pub opacity: f32,
+    pub visible: bool,
+}
+
+impl Heatmap {
+    pub fn new(space: HeatmapSpace) -> Self {
+        Heatmap {
+            points: Vec::new(),
+            revision: 0,
+            space,
+            radius: 24.0,
+            max_density: 1.0,
+            colormap: Colormap::TURBO,
+            opacity: 0.75,
+            visible: true,
+        }
+    }
+
+    pub fn with_points(mut self, points: Vec<HeatmapPoint>) -> Self {
+        self.points = points;
+        self
+    }
+
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    pub fn with_max_density(mut self, max_density: f32) -> Self {
+        self.max_density = max_density;
+        self
+    }
+
+    pub fn with_colormap(mut self, colormap: Colormap) -> Self {
+        self.colormap = colormap;
+        self
+    }
+
+    pub fn with_opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity;
+        self
+    }
+
+    pub fn points(&self) -> &[HeatmapPoint] {
+        &self.points
+    }
+
+    /// Adds a point, e.g. the latest gaze sample. The points are uploaded again before the next frame.
+    pub fn push(&mut self, position: [f32; 3], weight: f32) {
+        self.points.push(HeatmapPoint { position, weight });
+        self.revision += 1;
+    }
+
+    pub fn extend(&mut self, points: impl IntoIterator<Item = HeatmapPoint>) {
+        self.points.extend(points);
+        self.revision += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.revision += 1;
+    }
+
+    /// For changing points in place, they're all uploaded again before the next frame
+    pub fn points_mut(&mut self) -> &mut Vec<HeatmapPoint> {
+        self.revision += 1;
+        &mut self.points
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HeatmapHandle(pub(crate) usize);
+
+/// Per heatmap draw data, bound by both the splats and the composite
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct HeatmapUBOContent {
+    radius: f32,
+    max_density: f32,
+    opacity: f32,
+    /// 1 for [HeatmapSpace::Screen]
+    screen: u32,
+}
+
+crate::assert_uniform_layout!(HeatmapUBOContent {
+    radius: ALIGN_SCALAR,
+    max_density: ALIGN_SCALAR,
+    opacity: ALIGN_SCALAR,
+    screen: ALIGN_SCALAR,
+});
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ScreenUBOContent {
+    size: [f32; 2],
+    _padding: [f32; 2],
+}
+
+struct GpuHeatmap {
+    ubo: UniformBuffer<HeatmapUBOContent>,
+    bind_group: BindGroup,
+    /// [Heatmap::colormap] of the bind group
+    colormap: Colormap,
+    /// None without points
+    points: Option<Buffer>,
+    count: u32,
+    /// [Heatmap::revision] of the uploaded points
+    revision: u64,
+}
+
+/// The density of one window, shared by its heatmaps one after the other
+struct WindowDensity {
+    size: [u32; 2],
+    view: TextureView,
+    density_bind_group: BindGroup,
+    screen: UniformBuffer<ScreenUBOContent>,
+    screen_bind_group: BindGroup,
+}
+
+/// Splats and composites the heatmaps into the windows
+pub(crate) struct HeatmapPass {
+    splat_pipeline: RenderPipeline,
+    composite_pipeline: RenderPipeline,
+    heatmap_bind_group_layout: BindGroupLayoutWithDesc,
+    screen_bind_group_layout: BindGroupLayoutWithDesc,
+    density_bind_group_layout: BindGroupLayoutWithDesc,
+    heatmaps: HashMap<usize, GpuHeatmap>,
+    windows: HashMap<WindowId, WindowDensity>,
+}
+
+impl HeatmapPass {
+    /// `format` has to be the engine's swapchain format, the heatmaps are drawn straight into the windows. None
+    /// where the density can't be blended.
+    pub fn new(
+        device: &Device,
+        adapter: &Adapter,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+    ) -> Option<Self> {
+        let features = adapter.get_texture_format_features(DENSITY_FORMAT);
+        if !features
+            .allowed_usages
+            .contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+            || !features
+                .flags
+                .contains(wgpu::TextureFormatFeatureFlags::BLENDABLE)
+        {
+            return None;
+        }
+        let _span = tracing::debug_span!("create_heatmap_pipelines").entered();
+        let heatmap_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_rendering(binding_types::uniform())
+            .next_binding_fragment(binding_types::texture2D())
+            .next_binding_fragment(binding_types::sampler(wgpu::SamplerBindingType::Filtering))
+            .create(device, "Heatmap Bind Group");
+        let screen_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_vertex(binding_types::uniform())
+            .create(device, "Heatmap Screen Bind Group");
+        let density_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_fragment(wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            })
+            .create(device, "Heatmap Density Bind Group");
+
+        let create_pipeline = |label: &str,
+                               source: &str,
+                               bind_group_layouts: &[&wgpu::BindGroupLayout],
+                               buffers: &[wgpu::VertexBufferLayout],
+                               target: wgpu::ColorTargetState| {
+            let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(label),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers,
+                    compilation_options: Default::default(),
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(target)],
+                    compilation_options: Default::default(),
+                }),
+                multiview: None,
+                cache: None,
+            })
+        };
+        let additive = wgpu::BlendComponent {
+            src_factor: wgpu::BlendFactor::One,
+            dst_factor: wgpu::BlendFactor::One,
+            operation: wgpu::BlendOperation::Add,
+        };
+        let splat_pipeline = create_pipeline(
+            "heatmap splat",
+            include_str!("heatmap.wgsl"),
+            &[
+                global_bindings.bind_group_layouts(),
+                &heatmap_bind_group_layout.layout,
+                &screen_bind_group_layout.layout,
+            ],
+            &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<HeatmapPoint>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Instance,
+                attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32],
+            }],
+            wgpu::ColorTargetState {
+                format: DENSITY_FORMAT,
+                blend: Some(wgpu::BlendState {
+                    color: additive,
+                    alpha: additive,
+                }),
+                write_mask: wgpu::ColorWrites::ALL,
+            },
+        );
+        let composite_pipeline = create_pipeline(
+            "heatmap composite",
+            include_str!("heatmap_composite.wgsl"),
+            &[
+                &heatmap_bind_group_layout.layout,
+                &density_bind_group_layout.layout,
+            ],
+            &[],
+            wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            },
+        );
+
+        Some(HeatmapPass {
+            splat_pipeline,
+            composite_pipeline,
+            heatmap_bind_group_layout,
+            screen_bind_group_layout,
+            density_bind_group_layout,
+            heatmaps: HashMap::new(),
+            windows: HashMap::new(),
+        })
+    }
+
+    /// Uploads whatever changed about the heatmaps and frees the GPU data of removed ones
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        colormaps: &mut ColormapTextures,
+        heatmaps: &[Option<Heatmap>],
+    ) {
+        self.heatmaps
+            .retain(|&index, _| matches!(heatmaps.get(index), Some(Some(_))));
+        for (index, heatmap) in heatmaps.iter().enumerate() {
+            let Some(heatmap) = heatmap else {
+                continue;
+            };
+            let stale = self
+                .heatmaps
+                .get(&index)
+                .is_none_or(|gpu_heatmap| gpu_heatmap.colormap != heatmap.colormap);
+            if stale {
+                let ubo = UniformBuffer::new(device);
+                let (colormap, sampler) = colormaps.binding(device, queue, heatmap.colormap);
+                let bind_group = BindGroupBuilder::new(&self.heatmap_bind_group_layout)
+                    .resource(ubo.binding_resource())
+                    .texture(colormap)
+                    .sampler(sampler)
+                    .create(device, "Heatmap Bind Group");
+                let points = self.heatmaps.remove(&index).and_then(|old| old.points);
+                self.heatmaps.insert(
+                    index,
+                    GpuHeatmap {
+                        ubo,
+                        bind_group,
+                        colormap: heatmap.colormap,
+                        points,
+                        count: 0,
+                        // Uploads the points again
+                        revision: u64::MAX,
+                    },
+                );
+            }
+            let gpu_heatmap = self
+                .heatmaps
+                .get_mut(&index)
+                .expect("Heatmap was just created!");
+            gpu_heatmap.ubo.update_content(
+                queue,
+                HeatmapUBOContent {
+                    radius: heatmap.radius,
+                    max_density: heatmap.max_density,
+                    opacity: heatmap.opacity,
+                    screen: (heatmap.space == HeatmapSpace::Screen) as u32,
+                },
+            );
+            if gpu_heatmap.revision == heatmap.revision {
+                continue;
+            }
+            gpu_heatmap.revision = heatmap.revision;
+            gpu_heatmap.count = heatmap.points.len() as u32;
+            let bytes: &[u8] = bytemuck::cast_slice(&heatmap.points);
+            if bytes.is_empty() {
+                continue;
+            }
+            if gpu_heatmap
+                .points
+                .as_ref()
+                .is_none_or(|points| points.size() < bytes.len() as u64)
+            {
+                gpu_heatmap.points = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Heatmap Points"),
+                    size: (bytes.len() as u64).next_power_of_two(),
+                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                }));
+            }
+            if let Some(points) = &gpu_heatmap.points {
+                queue.write_buffer(points, 0, bytes);
+            }
+        }
+    }
+
+    /// Records the passes drawing the visible `heatmaps` into `view`, a window `width` x `height` pixels seen
+    /// through `globals`
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        window_id: WindowId,
+        view: &TextureView,
+        globals: &BindGroup,
+        width: u32,
+        height: u32,
+        heatmaps: &[Option<Heatmap>],
+    ) {
+        let drawn: Vec<&GpuHeatmap> = heatmaps
+            .iter()
+            .enumerate()
+            .filter(|(_, heatmap)| heatmap.as_ref().is_some_and(|heatmap| heatmap.visible))
+            .filter_map(|(index, _)| self.heatmaps.get(&index))
+            .filter(|gpu_heatmap| gpu_heatmap.count > 0)
+            .collect();
+        if drawn.is_empty() || width == 0 || height == 0 {
+            return;
+        }
+        let density = self.windows.entry(window_id).or_insert_with(|| {
+            create_density(
+                device,
+                &self.screen_bind_group_layout,
+                &self.density_bind_group_layout,
+                [width, height],
+            )
+        });
+        if density.size != [width, height] {
+            *density = create_density(
+                device,
+                &self.screen_bind_group_layout,
+                &self.density_bind_group_layout,
+                [width, height],
+            );
+        }
+        density.screen.update_content(
+            queue,
+            ScreenUBOContent {
+                size: [width as f32, height as f32],
+                _padding: [0.0; 2],
+            },
+        );
+
+        for gpu_heatmap in drawn {
+            let Some(points) = &gpu_heatmap.points else {
+                continue;
+            };
+            {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("heatmap splats"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &density.view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                render_pass.set_pipeline(&self.splat_pipeline);
+                render_pass.set_bind_group(0, globals, &[]);
+                render_pass.set_bind_group(1, &gpu_heatmap.bind_group, &[]);
+                render_pass.set_bind_group(2, &density.screen_bind_group, &[]);
+                render_pass.set_vertex_buffer(0, points.slice(..));
+                render_pass.draw(0..6, 0..gpu_heatmap.count);
+            }
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("heatmap"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            render_pass.set_pipeline(&self.composite_pipeline);
+            render_pass.set_bind_group(0, &gpu_heatmap.bind_group, &[]);
+            render_pass.set_bind_group(1, &density.density_bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    /// Stops keeping a density texture for the window
+    pub fn remove_window(&mut self, window_id: WindowId) {
+        self.windows.remove(&window_id);
+    }
+
+    /// Bytes of the density textures and the uploaded points
+    pub fn size_in_bytes(&self) -> u64 {
+        let densities: u64 = self
+            .windows
+            .values()
+            .map(|density| density.size[0] as u64 * density.size[1] as u64 * 2)
+            .sum();
+        let points: u64 = self
+            .heatmaps
+            .values()
+            .filter_map(|gpu_heatmap| gpu_heatmap.points.as_ref())
+            .map(Buffer::size)
+            .sum();
+        densities + points
+    }
+}
+
+fn create_density(
+    device: &Device,
+    screen_bind_group_layout: &BindGroupLayoutWithDesc,
+    density_bind_group_layout: &BindGroupLayoutWithDesc,
+    [width, height]: [u32; 2],
+) -> WindowDensity {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Heatmap Density"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: DENSITY_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let density_bind_group = BindGroupBuilder::new(density_bind_group_layout)
+        .texture(&view)
+        .create(device, "Heatmap Density Bind Group");
+    let screen = UniformBuffer::new(device);
+    let screen_bind_group = BindGroupBuilder::new(screen_bind_group_layout)
+        .resource(screen.binding_resource())
+        .create(device, "Heatmap Screen Bind Group");
+    WindowDensity {
+        size: [width, height],
+        view,
+        density_bind_group,
+        screen,
+        screen_bind_group,
+    }
+}
+
+/// The engine's heatmaps and the pass drawing them into the windows, which is created with the first heatmap
+#[derive(Default)]
+pub(crate) struct Heatmaps {
+    /// Unsupported where the density can't be blended
+    pass: LazyPass<HeatmapPass>,
+    /// None where a heatmap was removed
+    heatmaps: Vec<Option<Heatmap>>,
+}
+
+impl Heatmaps {
+    pub fn add(
+        &mut self,
+        device: &Device,
+        adapter: &Adapter,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        heatmap: Heatmap,
+    ) -> HeatmapHandle {
+        if self
+            .pass
+            .get_or_create(|| HeatmapPass::new(device, adapter, format, global_bindings))
+            .is_none()
+        {
+            tracing::warn!("Heatmaps need blending into half float textures, it isn't drawn");
+        }
+        self.heatmaps.push(Some(heatmap));
+        HeatmapHandle(self.heatmaps.len() - 1)
+    }
+
+    pub fn get(&self, handle: HeatmapHandle) -> Option<&Heatmap> {
+        self.heatmaps.get(handle.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: HeatmapHandle) -> Option<&mut Heatmap> {
+        self.heatmaps.get_mut(handle.0)?.as_mut()
+    }
+
+    /// The points are freed on the GPU by the next prepare
+    pub fn remove(&mut self, handle: HeatmapHandle) -> Option<Heatmap> {
+        self.heatmaps.get_mut(handle.0)?.take()
+    }
+
+    /// See [HeatmapPass::prepare]
+    pub fn prepare(&mut self, device: &Device, queue: &Queue, colormaps: &mut ColormapTextures) {
+        if let Some(pass) = self.pass.get_mut() {
+            pass.prepare(device, queue, colormaps, &self.heatmaps);
+        }
+    }
+
+    /// See [HeatmapPass::draw]
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        window_id: WindowId,
+        view: &TextureView,
+        globals: &BindGroup,
+        width: u32,
+        height: u32,
+    ) {
+        if let Some(pass) = self.pass.get_mut() {
+            pass.draw(
+                device,
+                queue,
+                encoder,
+                window_id,
+                view,
+                globals,
+                width,
+                height,
+                &self.heatmaps,
+            );
+        }
+    }
+
+    pub fn remove_window(&mut self, window_id: WindowId) {
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_window(window_id);
+        }
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.pass.get().map_or(0, HeatmapPass::size_in_bytes)
+    }
+
+    /// Creates the pass again on a new device, the points are uploaded again by the next prepare
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(
+        &mut self,
+        device: &Device,
+        adapter: &Adapter,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+    ) {
+        self.pass
+            .recreate(|| HeatmapPass::new(device, adapter, format, global_bindings));
+    }
+}
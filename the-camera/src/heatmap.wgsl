@@ -0,0 +1,91 @@
+// Points splatted into the density texture of a window, see heatmap.rs. Overlapping splats add up.
+
+// See clipping.rs, the same as in shader.wgsl
+struct Clipping {
+    planes: array<vec4<f32>, 4>,
+    cap_color: vec4<f32>,
+    count: u32,
+    caps: u32,
+}
+
+struct Audio {
+    bands: array<vec4<f32>, 4>,
+    level: f32,
+}
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    clipping: Clipping,
+    audio: Audio,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// One heatmap, see heatmap.rs
+struct Heatmap {
+    // Of a splat, in pixels
+    radius: f32,
+    max_density: f32,
+    opacity: f32,
+    // 1 for points given as fractions of the window
+    screen: u32,
+}
+@group(1) @binding(0)
+var<uniform> heatmap: Heatmap;
+
+struct Screen {
+    size: vec2<f32>,
+}
+@group(2) @binding(0)
+var<uniform> screen: Screen;
+
+struct Point {
+    @location(0) position: vec3<f32>,
+    @location(1) weight: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // From -1 to 1 across the splat
+    @location(0) offset: vec2<f32>,
+    @location(1) @interpolate(flat) weight: f32,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, point: Point) -> VertexOutput {
+    let corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    var center = vec4<f32>(point.position.x * 2.0 - 1.0, 1.0 - point.position.y * 2.0, 0.0, 1.0);
+    if heatmap.screen == 0u {
+        center = camera.view_proj * vec4<f32>(point.position, 1.0);
+    }
+    let corner = corners[index];
+    var out: VertexOutput;
+    // Behind the camera, collapsed outside of the clip volume
+    if center.w <= 0.0 {
+        out.clip_position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+        return out;
+    }
+    let ndc = center.xy / center.w + corner * heatmap.radius * 2.0 / screen.size;
+    out.clip_position = vec4<f32>(ndc, 0.0, 1.0);
+    out.offset = corner;
+    out.weight = point.weight;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // A Gaussian that's down to 2% at the radius, cut off there
+    let distance2 = dot(in.offset, in.offset);
+    if distance2 > 1.0 {
+        discard;
+    }
+    return vec4<f32>(in.weight * exp(-4.0 * distance2), 0.0, 0.0, 0.0);
+}
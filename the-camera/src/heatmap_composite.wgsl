@@ -0,0 +1,37 @@
+// A window's density mapped through the heatmap's colormap and blended over the window, see heatmap.rs
+
+// The same as in heatmap.wgsl
+struct Heatmap {
+    radius: f32,
+    max_density: f32,
+    opacity: f32,
+    screen: u32,
+}
+@group(0) @binding(0)
+var<uniform> heatmap: Heatmap;
+@group(0) @binding(1)
+var colormap: texture_2d<f32>;
+@group(0) @binding(2)
+var colormap_sampler: sampler;
+
+@group(1) @binding(0)
+var density: texture_2d<f32>;
+
+// One triangle covering the window
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let value = textureLoad(density, vec2<i32>(position.xy), 0).r;
+    let t = clamp(value / max(heatmap.max_density, 1e-20), 0.0, 1.0);
+    // Between the centers of the first and the last texel, see colormap.rs
+    let texels = f32(textureDimensions(colormap).x);
+    let color = textureSample(colormap, colormap_sampler, vec2<f32>((t * (texels - 1.0) + 0.5) / texels, 0.5)).rgb;
+    // Fully opaque from a fifth of the way up, so the scene shows through where few points fell
+    let alpha = heatmap.opacity * clamp(t * 5.0, 0.0, 1.0);
+    return vec4<f32>(color, alpha);
+}
@@ -0,0 +1,84 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver},
+};
+
+use cgmath::Matrix4;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::scene::{MaterialHandle, MeshHandle, TextureHandle};
+
+/// What the engine uploaded from a watched file, replaced when the file changes
+pub(crate) enum WatchedAsset {
+    Texture(TextureHandle),
+    /// Mesh, material and transform of each part, in the order of the file
+    Model(Vec<(MeshHandle, MaterialHandle, Matrix4<f32>)>),
+}
+
+/// Watches asset files (shaders, textures, models) on disk and reports which of them changed.
+///
+/// The parent directory of each asset is watched rather than the file itself, because most editors save by
+/// writing a temporary file and renaming it over the original, which would otherwise drop the watch.
+pub struct AssetWatcher {
+    watcher: RecommendedWatcher,
+    receiver: Receiver<notify::Result<notify::Event>>,
+    watched_files: Vec<PathBuf>,
+    watched_dirs: Vec<PathBuf>,
+}
+
+impl AssetWatcher {
+    /// Creates a watcher with nothing registered yet
+    pub fn new() -> notify::Result<Self> {
+        let (sender, receiver) = channel();
+        // notify calls this from its own thread, the channel hands the events over to the render thread
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        })?;
+
+        Ok(AssetWatcher {
+            watcher,
+            receiver,
+            watched_files: Vec::new(),
+            watched_dirs: Vec::new(),
+        })
+    }
+
+    /// Start watching the file at `path`. Paths are canonicalized so they can be compared against event paths, the
+    /// canonical one is returned.
+    pub fn watch(&mut self, path: impl AsRef<Path>) -> notify::Result<PathBuf> {
+        let path = path.as_ref().canonicalize()?;
+        let dir = path.parent().unwrap_or(&path).to_path_buf();
+
+        if !self.watched_dirs.contains(&dir) {
+            self.watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+            self.watched_dirs.push(dir);
+        }
+        if !self.watched_files.contains(&path) {
+            self.watched_files.push(path.clone());
+        }
+        Ok(path)
+    }
+
+    /// Drains all pending file system events and returns each changed asset once.
+    ///
+    /// Never blocks, so it is cheap enough to call every frame.
+    pub fn poll_changes(&self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for event in self.receiver.try_iter() {
+            let Ok(event) = event else {
+                continue;
+            };
+            if !matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_)) {
+                continue;
+            }
+            for path in event.paths {
+                // The file might have been removed again in the meantime, fall back to the raw event path
+                let path = path.canonicalize().unwrap_or(path);
+                if self.watched_files.contains(&path) && !changed.contains(&path) {
+                    changed.push(path);
+                }
+            }
+        }
+        changed
+    }
+}
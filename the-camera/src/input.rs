@@ -0,0 +1,352 @@
+//! Keyboard and mouse state gathered from input events, read once per frame instead of matching events.
+//!
+//! The engine feeds every event it's handed into its [Input], see [crate::render_engine::RenderEngine::input]. Events
+//! are [InputEvent]s, which shells other than winit, e.g. SDL2 or tao, build from their own events and hand to
+//! [crate::render_engine::RenderEngine::process_input_event]. What
+//! changed since the previous update, like [Input::just_pressed] or [Input::mouse_delta], resets at the end of each
+//! update, so read it before calling [crate::render_engine::RenderEngine::update].
+//!
+//! Named actions decouple what a key does from which key it is:
+//!
+//! ```ignore
+//! input.bind("screenshot", KeyCode::F12);
+//! if input.action_just_pressed("screenshot") { ... }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use winit::{
+    dpi::PhysicalPosition,
+    event::{DeviceEvent, ElementState, KeyEvent, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+    window::WindowId,
+};
+
+use crate::xr::{Hand, Pose};
+
+/// What the engine needs to know from the windowing library about the keyboard and mouse. Keys, mouse buttons and
+/// window IDs are winit's types, plain values other libraries' map to, e.g. with `WindowId::from(u64)`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputEvent {
+    /// By position on the keyboard, held keys may repeat their presses
+    Key {
+        key: KeyCode,
+        pressed: bool,
+        repeat: bool,
+    },
+    /// What typing produced in the current layout, sent after the key press that typed it
+    Text(String),
+    MouseButton {
+        button: MouseButton,
+        pressed: bool,
+    },
+    /// Physical pixels from the top left of the window
+    CursorMoved([f32; 2]),
+    CursorLeft,
+    /// In mouse wheel lines or touchpad pixels, positive away from the user
+    Scroll(f32),
+    Focused(bool),
+    /// Raw mouse movement in unspecified device units, not limited to the window. Which window it's sent to
+    /// doesn't matter.
+    MouseMotion([f32; 2]),
+    /// Where a headset's controller is, None once it's no longer tracked, see [crate::xr]
+    ControllerPose {
+        hand: Hand,
+        pose: Option<Pose>,
+    },
+}
+
+impl InputEvent {
+    /// The winit adapter, a key press that typed something comes out as [InputEvent::Key] and [InputEvent::Text].
+    /// Keys winit can't identify are left out.
+    pub fn from_window_event(event: &WindowEvent) -> Vec<InputEvent> {
+        let event = match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                let mut events = Vec::new();
+                let KeyEvent {
+                    physical_key,
+                    state,
+                    repeat,
+                    text,
+                    ..
+                } = event;
+                if let PhysicalKey::Code(key) = physical_key {
+                    events.push(InputEvent::Key {
+                        key: *key,
+                        pressed: *state == ElementState::Pressed,
+                        repeat: *repeat,
+                    });
+                }
+                if let Some(text) = text.as_ref().filter(|_| *state == ElementState::Pressed) {
+                    events.push(InputEvent::Text(text.to_string()));
+                }
+                return events;
+            }
+            WindowEvent::MouseInput { state, button, .. } => InputEvent::MouseButton {
+                button: *button,
+                pressed: *state == ElementState::Pressed,
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                InputEvent::CursorMoved([position.x as f32, position.y as f32])
+            }
+            WindowEvent::CursorLeft { .. } => InputEvent::CursorLeft,
+            WindowEvent::MouseWheel { delta, .. } => InputEvent::Scroll(match delta {
+                MouseScrollDelta::LineDelta(_, lines) => *lines,
+                MouseScrollDelta::PixelDelta(PhysicalPosition { y, .. }) => *y as f32,
+            }),
+            WindowEvent::Focused(focused) => InputEvent::Focused(*focused),
+            _ => return Vec::new(),
+        };
+        vec![event]
+    }
+
+    pub fn from_device_event(event: &DeviceEvent) -> Option<InputEvent> {
+        match event {
+            DeviceEvent::MouseMotion { delta } => {
+                Some(InputEvent::MouseMotion([delta.0 as f32, delta.1 as f32]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Presses of keys and mouse buttons, the events that can be consumed
+    pub(crate) fn is_press(&self) -> bool {
+        matches!(
+            self,
+            InputEvent::Key { pressed: true, .. } | InputEvent::MouseButton { pressed: true, .. }
+        )
+    }
+}
+
+/// A key or mouse button
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    /// By position on the keyboard, not by the character it types in the current layout
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl From<KeyCode> for Button {
+    fn from(key: KeyCode) -> Self {
+        Button::Key(key)
+    }
+}
+
+impl From<MouseButton> for Button {
+    fn from(button: MouseButton) -> Self {
+        Button::Mouse(button)
+    }
+}
+
+/// What the cursor does over a window, see [crate::render_engine::RenderEngine::set_cursor_mode]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CursorMode {
+    #[default]
+    Normal,
+    /// Invisible over the window, but free to leave it
+    Hidden,
+    /// Visible and kept inside the window
+    Confined,
+    /// Invisible and held in place, for mouse look. Movement still arrives as [Input::mouse_delta].
+    Locked,
+}
+
+#[derive(Debug, Default)]
+pub struct Input {
+    pressed: HashSet<Button>,
+    just_pressed: HashSet<Button>,
+    just_released: HashSet<Button>,
+    /// Physical pixels from the top left of the window the cursor is in
+    cursor: Option<(WindowId, [f32; 2])>,
+    focused_window: Option<WindowId>,
+    /// Raw mouse movement, not limited to the windows or slowed down by the cursor hitting the screen edge
+    mouse_delta: [f32; 2],
+    /// In mouse wheel lines or touchpad pixels, a line is about a pixel. Positive away from the user.
+    scroll_delta: f32,
+    actions: HashMap<String, Vec<Button>>,
+    controllers: HashMap<Hand, Pose>,
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Updates the state from an event of `window_id`. Presses can be left out, e.g. if a plugin consumed them, but
+    /// releases have to come through or buttons stay held.
+    pub fn process_input_event(&mut self, window_id: WindowId, event: &InputEvent) {
+        match event {
+            InputEvent::Key { key, pressed, .. } => self.set_button(Button::Key(*key), *pressed),
+            InputEvent::MouseButton { button, pressed } => {
+                self.set_button(Button::Mouse(*button), *pressed);
+            }
+            InputEvent::CursorMoved(position) => self.cursor = Some((window_id, *position)),
+            InputEvent::CursorLeft if self.cursor_window() == Some(window_id) => {
+                self.cursor = None;
+            }
+            InputEvent::Scroll(delta) => self.scroll_delta += delta,
+            InputEvent::Focused(true) => self.focused_window = Some(window_id),
+            InputEvent::Focused(false) => {
+                if self.focused_window == Some(window_id) {
+                    self.focused_window = None;
+                }
+                // Releases go to the window that has focus by then, nothing would ever let go of these
+                self.just_released.extend(self.pressed.drain());
+            }
+            InputEvent::MouseMotion(delta) => self.add_mouse_motion(*delta),
+            InputEvent::ControllerPose { hand, pose } => match pose {
+                Some(pose) => {
+                    self.controllers.insert(*hand, *pose);
+                }
+                None => {
+                    self.controllers.remove(hand);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    /// [Input::process_input_event] for winit's window events
+    pub fn process_window_event(&mut self, window_id: WindowId, event: &WindowEvent) {
+        for event in InputEvent::from_window_event(event) {
+            self.process_input_event(window_id, &event);
+        }
+    }
+
+    /// [Input::process_input_event] for winit's raw mouse motion
+    pub fn process_device_event(&mut self, event: &DeviceEvent) {
+        if let Some(InputEvent::MouseMotion(delta)) = InputEvent::from_device_event(event) {
+            self.add_mouse_motion(delta);
+        }
+    }
+
+    fn add_mouse_motion(&mut self, [x, y]: [f32; 2]) {
+        self.mouse_delta[0] += x;
+        self.mouse_delta[1] += y;
+    }
+
+    pub(crate) fn focus(&mut self, window_id: WindowId) {
+        self.focused_window = Some(window_id);
+    }
+
+    /// Forgets a closed window, it won't report losing focus or the cursor anymore
+    pub(crate) fn remove_window(&mut self, window_id: WindowId) {
+        if self.focused_window == Some(window_id) {
+            self.focused_window = None;
+        }
+        if self.cursor_window() == Some(window_id) {
+            self.cursor = None;
+        }
+    }
+
+    fn set_button(&mut self, button: Button, pressed: bool) {
+        match pressed {
+            // Held keys repeat their presses
+            true if self.pressed.insert(button) => {
+                self.just_pressed.insert(button);
+            }
+            true => (),
+            false => {
+                if self.pressed.remove(&button) {
+                    self.just_released.insert(button);
+                }
+            }
+        }
+    }
+
+    /// Starts over collecting what changed, the engine calls this at the end of every update
+    pub fn end_frame(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+        self.mouse_delta = [0.0; 2];
+        self.scroll_delta = 0.0;
+    }
+
+    pub fn pressed(&self, button: impl Into<Button>) -> bool {
+        self.pressed.contains(&button.into())
+    }
+
+    /// Pressed since the previous update
+    pub fn just_pressed(&self, button: impl Into<Button>) -> bool {
+        self.just_pressed.contains(&button.into())
+    }
+
+    /// Released since the previous update
+    pub fn just_released(&self, button: impl Into<Button>) -> bool {
+        self.just_released.contains(&button.into())
+    }
+
+    pub fn cursor_position(&self) -> Option<[f32; 2]> {
+        self.cursor.map(|(_, position)| position)
+    }
+
+    /// The window the cursor is over, if any
+    pub fn cursor_window(&self) -> Option<WindowId> {
+        self.cursor.map(|(window_id, _)| window_id)
+    }
+
+    pub fn focused_window(&self) -> Option<WindowId> {
+        self.focused_window
+    }
+
+    /// How far the mouse moved since the previous update, in unspecified device units
+    pub fn mouse_delta(&self) -> [f32; 2] {
+        self.mouse_delta
+    }
+
+    /// How far the wheel turned since the previous update, in lines or pixels, positive away from the user
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+
+    /// Where the controller was last reported, None while it isn't tracked
+    pub fn controller_pose(&self, hand: Hand) -> Option<Pose> {
+        self.controllers.get(&hand).copied()
+    }
+
+    /// Adds `button` to those triggering `action`, any of them does
+    pub fn bind(&mut self, action: impl Into<String>, button: impl Into<Button>) {
+        let buttons = self.actions.entry(action.into()).or_default();
+        let button = button.into();
+        if !buttons.contains(&button) {
+            buttons.push(button);
+        }
+    }
+
+    /// Removes every binding of `action`
+    pub fn unbind(&mut self, action: &str) {
+        self.actions.remove(action);
+    }
+
+    /// The buttons triggering `action`
+    pub fn bindings(&self, action: &str) -> &[Button] {
+        self.actions.get(action).map_or(&[], Vec::as_slice)
+    }
+
+    /// Whether any button bound to `action` is held
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|button| self.pressed.contains(button))
+    }
+
+    pub fn action_just_pressed(&self, action: &str) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|button| self.just_pressed.contains(button))
+    }
+
+    pub fn action_just_released(&self, action: &str) -> bool {
+        self.bindings(action)
+            .iter()
+            .any(|button| self.just_released.contains(button))
+    }
+
+    /// Whether a button bound to some action was pressed since the previous update
+    pub(crate) fn any_action_just_pressed(&self) -> bool {
+        self.actions
+            .values()
+            .flatten()
+            .any(|button| self.just_pressed.contains(button))
+    }
+}
@@ -0,0 +1,128 @@
+// See clipping.rs, the same as in shader.wgsl
+struct Clipping {
+    planes: array<vec4<f32>, 4>,
+    cap_color: vec4<f32>,
+    count: u32,
+    caps: u32,
+}
+
+struct Audio {
+    bands: array<vec4<f32>, 4>,
+    level: f32,
+}
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    clipping: Clipping,
+    audio: Audio,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// See irradiance_volume/pass.rs
+struct Irradiance {
+    min: vec3<f32>,
+    baked: u32,
+    max: vec3<f32>,
+    probe_radius: f32,
+    counts: vec3<u32>,
+}
+@group(1) @binding(0)
+var<uniform> irradiance: Irradiance;
+@group(1) @binding(2)
+var t_irradiance_red: texture_2d<f32>;
+@group(1) @binding(3)
+var t_irradiance_green: texture_2d<f32>;
+@group(1) @binding(4)
+var t_irradiance_blue: texture_2d<f32>;
+
+const MAX_PROBES_PER_AXIS = 16u;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    // From -1 to 1 across the square
+    @location(1) corner: vec2<f32>,
+    @location(2) @interpolate(flat) texel: vec2<u32>,
+    @location(3) @interpolate(flat) right: vec3<f32>,
+    @location(4) @interpolate(flat) up: vec3<f32>,
+    @location(5) @interpolate(flat) toward_eye: vec3<f32>,
+};
+
+const CORNERS = array<vec2<f32>, 6>(
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(1.0, -1.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(-1.0, 1.0),
+);
+
+// A square around the instance's probe, facing the camera
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, @builtin(instance_index) probe: u32) -> VertexOutput {
+    let counts = irradiance.counts;
+    let cell = vec3<u32>(probe % counts.x, probe / counts.x % counts.y, probe / (counts.x * counts.y));
+    // A single probe along an axis sits in the middle
+    let spread = vec3<f32>(cell) / vec3<f32>(max(counts, vec3<u32>(2u)) - 1u);
+    let t = select(spread, vec3<f32>(0.5), counts == vec3<u32>(1u));
+    let center = mix(irradiance.min, irradiance.max, t);
+
+    let toward_eye = normalize(camera.view_pos.xyz - center);
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(toward_eye.y) > 0.99 {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let right = normalize(cross(up, toward_eye));
+    up = cross(toward_eye, right);
+    let corner = CORNERS[index];
+    let world_position = center + (right * corner.x + up * corner.y) * irradiance.probe_radius;
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
+    out.corner = corner;
+    out.texel = vec2<u32>(cell.z * MAX_PROBES_PER_AXIS + cell.x, cell.y);
+    out.right = right;
+    out.up = up;
+    out.toward_eye = toward_eye;
+    return out;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    // Probes can't be picked
+    @location(1) id: u32,
+};
+
+// The disk of the square as the half of a sphere facing the camera, lit by the probe along its normals. Probes that
+// aren't baked yet are grey.
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    for (var index = 0u; index < camera.clipping.count; index++) {
+        let plane = camera.clipping.planes[index];
+        if dot(plane.xyz, in.world_position) < plane.w {
+            discard;
+        }
+    }
+    let distance_squared = dot(in.corner, in.corner);
+    if distance_squared > 1.0 {
+        discard;
+    }
+    let normal = in.right * in.corner.x + in.up * in.corner.y + in.toward_eye * sqrt(1.0 - distance_squared);
+
+    var out: FragmentOutput;
+    out.color = vec4<f32>(vec3<f32>(0.5), 1.0);
+    if irradiance.baked != 0u {
+        let basis = vec4<f32>(1.0, normal);
+        let light = vec3<f32>(
+            dot(textureLoad(t_irradiance_red, in.texel, 0), basis),
+            dot(textureLoad(t_irradiance_green, in.texel, 0), basis),
+            dot(textureLoad(t_irradiance_blue, in.texel, 0), basis),
+        );
+        out.color = vec4<f32>(max(light, vec3<f32>(0.0)), 1.0);
+    }
+    out.id = 0u;
+    return out;
+}
@@ -0,0 +1,223 @@
+//! Ambient light for objects moving through a scene, from a grid of probes baked from it.
+//!
+//! Every probe of an [IrradianceVolume] draws the scene around it into the six faces of a small cube, a few probes
+//! every frame until all of them are baked. A compute shader projects each cube onto the first two bands of
+//! spherical harmonics, four coefficients per color channel, already convolved with the cosine lobe so a single dot
+//! product with the normal gives the light falling onto a surface.
+//!
+//! Materials with [crate::scene::Material::lit_by_probes] are left out of the bake and have their color lit by the
+//! probes around them, interpolated between the eight nearest. The scene has no lights of its own, what the probes
+//! see is the colors of everything around them, so a white object in a room of red walls turns red. Meshes carry no
+//! normals, the light falls onto each triangle as if it were flat.
+//!
+//! [IrradianceVolume::show_probes] draws every probe as a sphere lit by it. Devices without compute shaders, e.g.
+//! WebGL2, can't bake the probes and light nothing by them.
+
+mod pass;
+
+use wgpu::{Device, Queue, TextureFormat};
+
+pub(crate) use self::pass::{IrradianceTextures, IrradianceVolumePass};
+pub use self::pass::{BAKE_RESOLUTION, MAX_PROBES_PER_AXIS};
+use crate::{
+    audio::AudioUniform,
+    clipping::ClipUniform,
+    global_bindings::GlobalBindings,
+    lazy_pass::LazyPass,
+    render_engine_builder::DeviceReport,
+    wgpu_utils::{resource_cache::SamplerCache, uploader::Uploader},
+};
+
+/// A box of probes spread evenly from corner to corner, set with
+/// [crate::render_engine::RenderEngine::set_irradiance_volume]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IrradianceVolume {
+    /// Corners of the box, the outermost probes sit on its faces. Objects outside it are lit by the probes nearest
+    /// to them.
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    /// Probes along X, Y and Z, from 1 to [MAX_PROBES_PER_AXIS]
+    pub counts: [u32; 3],
+    /// Distances from each probe the scene is drawn between
+    pub near: f32,
+    pub far: f32,
+    /// Draws every probe as a sphere of [IrradianceVolume::probe_radius] lit by it
+    pub show_probes: bool,
+    pub probe_radius: f32,
+}
+
+impl IrradianceVolume {
+    pub fn new(min: [f32; 3], max: [f32; 3], counts: [u32; 3]) -> Self {
+        IrradianceVolume {
+            min,
+            max,
+            counts,
+            near: 0.05,
+            far: 1000.0,
+            show_probes: false,
+            probe_radius: 0.1,
+        }
+    }
+
+    pub fn with_range(mut self, near: f32, far: f32) -> Self {
+        self.near = near;
+        self.far = far;
+        self
+    }
+
+    /// Draws the probes as spheres `radius` wide
+    pub fn with_probes_shown(mut self, radius: f32) -> Self {
+        self.show_probes = true;
+        self.probe_radius = radius;
+        self
+    }
+
+    /// Clamped to what the textures hold
+    pub(crate) fn probe_counts(&self) -> [u32; 3] {
+        self.counts.map(|count| count.clamp(1, MAX_PROBES_PER_AXIS))
+    }
+
+    pub(crate) fn probe_count(&self) -> usize {
+        self.probe_counts().iter().product::<u32>() as usize
+    }
+
+    /// Cell and position of the probe at `index`, counting along X first, then Y, then Z
+    pub(crate) fn probe(&self, index: usize) -> ([u32; 3], [f32; 3]) {
+        let counts = self.probe_counts();
+        let [x_count, y_count, _] = counts;
+        let index = index as u32;
+        let cell = [
+            index % x_count,
+            index / x_count % y_count,
+            index / (x_count * y_count),
+        ];
+        let position = [0, 1, 2].map(|axis| {
+            let t = match counts[axis] {
+                1 => 0.5,
+                count => cell[axis] as f32 / (count - 1) as f32,
+            };
+            self.min[axis] + (self.max[axis] - self.min[axis]) * t
+        });
+        (cell, position)
+    }
+
+    /// Whether the probes have to be baked again going from `self` to `other`, drawing their spheres doesn't change
+    /// what they see
+    pub(crate) fn bakes_like(&self, other: &IrradianceVolume) -> bool {
+        self.min == other.min
+            && self.max == other.max
+            && self.probe_counts() == other.probe_counts()
+            && self.near == other.near
+            && self.far == other.far
+    }
+}
+
+/// The engine's irradiance volume and the pass baking it, which is created with the first volume set
+#[derive(Default)]
+pub(crate) struct Irradiance {
+    /// Unsupported where the device can't bake irradiance volumes
+    pass: LazyPass<IrradianceVolumePass>,
+    /// Lights the materials with [crate::scene::Material::lit_by_probes] where there is one
+    volume: Option<IrradianceVolume>,
+}
+
+impl Irradiance {
+    pub fn volume(&self) -> Option<&IrradianceVolume> {
+        self.volume.as_ref()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn set(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        samplers: &SamplerCache,
+        global_bindings: &GlobalBindings,
+        textures: &IrradianceTextures,
+        device_report: &DeviceReport,
+        volume: Option<IrradianceVolume>,
+    ) {
+        let create = || {
+            IrradianceVolumePass::new(
+                device,
+                format,
+                samplers,
+                global_bindings,
+                textures,
+                device_report,
+            )
+        };
+        if volume.is_some() && self.pass.get_or_create(create).is_none() {
+            tracing::warn!("Irradiance volumes need compute shaders, it isn't baked");
+        }
+        self.volume = volume;
+    }
+
+    /// See [IrradianceVolumePass::bake]
+    pub fn bake(&mut self) {
+        if let Some(pass) = self.pass.get_mut() {
+            pass.bake();
+        }
+    }
+
+    /// See [IrradianceVolumePass::prepare], true while the probes are baked a few at a time
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        uploader: &mut Uploader,
+        textures: &mut IrradianceTextures,
+        clipping: ClipUniform,
+        audio: AudioUniform,
+    ) -> bool {
+        let Some(pass) = self.pass.get_mut() else {
+            return false;
+        };
+        pass.prepare(
+            device,
+            queue,
+            uploader,
+            textures,
+            self.volume.as_ref(),
+            clipping,
+            audio,
+        );
+        pass.is_baking()
+    }
+
+    /// Bakes the probes and draws their spheres in the main pass, None before the first volume and where the device
+    /// can't bake it
+    pub fn pass(&self) -> Option<&IrradianceVolumePass> {
+        self.pass.get()
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.pass
+            .get()
+            .map_or(0, IrradianceVolumePass::size_in_bytes)
+    }
+
+    /// Creates the pass again on a new device, the volume is baked again from the start
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        samplers: &SamplerCache,
+        global_bindings: &GlobalBindings,
+        textures: &IrradianceTextures,
+        device_report: &DeviceReport,
+    ) {
+        self.pass.recreate(|| {
+            IrradianceVolumePass::new(
+                device,
+                format,
+                samplers,
+                global_bindings,
+                textures,
+                device_report,
+            )
+        });
+    }
+}
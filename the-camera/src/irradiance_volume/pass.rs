@@ -0,0 +1,511 @@
+use wgpu::{
+    BindGroup, CommandEncoder, Device, Queue, RenderPass, RenderPipeline, TextureFormat,
+    TextureView,
+};
+
+use super::IrradianceVolume;
+use crate::{
+    audio::AudioUniform,
+    clipping::ClipUniform,
+    global_bindings::{update_global_ubo, GlobalBindings, GlobalUBO},
+    occlusion::OcclusionQueries,
+    profiler::RenderStats,
+    reflection_probe::cube_face_camera,
+    render_engine_builder::DeviceReport,
+    texture::{self, Texture},
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        compute::{ComputeKernel, ComputeKernelBuilder, ComputePassBuilder},
+        resource_cache::SamplerCache,
+        uniform_buffer::UniformBuffer,
+        uploader::Uploader,
+    },
+};
+
+/// Probes along each axis the coefficient textures hold. Has to match irradiance_volume.wgsl and shader.wgsl.
+pub const MAX_PROBES_PER_AXIS: u32 = 16;
+/// Width and height of every face a probe draws, in pixels. Has to match irradiance_volume_bake.wgsl.
+pub const BAKE_RESOLUTION: u32 = 16;
+/// Probes baked in one frame. Has to match irradiance_volume_bake.wgsl.
+const PROBES_PER_FRAME: usize = 8;
+/// Every Z slice of probes lies next to the one before along X, so the textures can be 2D
+const COEFFICIENTS_SIZE: [u32; 2] = [
+    MAX_PROBES_PER_AXIS * MAX_PROBES_PER_AXIS,
+    MAX_PROBES_PER_AXIS,
+];
+/// Four 16 bit floats, the projection writes them packed into pairs of u32
+const COEFFICIENTS_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+const COEFFICIENT_BYTES: u64 = 8;
+
+/// The volume as the materials and spheres see it
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct IrradianceUBOContent {
+    min: [f32; 3],
+    /// 1 once every probe was baked, objects aren't lit before
+    baked: u32,
+    max: [f32; 3],
+    probe_radius: f32,
+    counts: [u32; 3],
+    _padding: u32,
+}
+
+crate::assert_uniform_layout!(IrradianceUBOContent {
+    min: ALIGN_VEC4,
+    baked: ALIGN_SCALAR,
+    max: ALIGN_VEC4,
+    probe_radius: ALIGN_SCALAR,
+    counts: ALIGN_VEC4,
+});
+
+/// Which texel each probe baked this frame writes, in the order of its faces
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct BakeUBOContent {
+    /// X and Y, the rest is padding
+    texels: [[u32; 4]; PROBES_PER_FRAME],
+}
+
+crate::assert_uniform_layout!(BakeUBOContent { texels: ALIGN_VEC4 });
+
+/// The coefficients of every probe, one texture per color channel, bound with every material so any object can be
+/// lit by them. The textures hold the largest volume from the start, so material bind groups never change with it.
+pub(crate) struct IrradianceTextures {
+    channels: [Texture; 3],
+    ubo: UniformBuffer<IrradianceUBOContent>,
+}
+
+impl IrradianceTextures {
+    pub fn new(device: &Device, samplers: &SamplerCache) -> Self {
+        let [width, height] = COEFFICIENTS_SIZE;
+        IrradianceTextures {
+            channels: ["irradiance_red", "irradiance_green", "irradiance_blue"].map(|label| {
+                Texture::create_copy_target(
+                    device,
+                    samplers,
+                    width,
+                    height,
+                    COEFFICIENTS_FORMAT,
+                    label,
+                )
+            }),
+            ubo: UniformBuffer::new_with_data(device, &IrradianceUBOContent::default()),
+        }
+    }
+
+    /// Adds the uniform, the sampler and the three channels to a bind group of [IrradianceTextures::layout]
+    pub fn bind<'a>(&'a self, builder: BindGroupBuilder<'a>) -> BindGroupBuilder<'a> {
+        let builder = builder
+            .resource(self.ubo.binding_resource())
+            .sampler(&self.channels[0].sampler);
+        self.channels
+            .iter()
+            .fold(builder, |builder, channel| builder.texture(&channel.view))
+    }
+
+    /// Adds the bindings of [IrradianceTextures::bind] to a layout, for the vertex shaders of the probe spheres too
+    pub fn layout(builder: BindGroupLayoutBuilder) -> BindGroupLayoutBuilder {
+        let builder = builder
+            .next_binding_rendering(binding_types::uniform())
+            .named("irradiance")
+            .next_binding_rendering(binding_types::sampler(wgpu::SamplerBindingType::Filtering))
+            .named("s_irradiance");
+        (0..3).fold(builder, |builder, _| {
+            builder.next_binding_rendering(binding_types::texture2D())
+        })
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        let [width, height] = COEFFICIENTS_SIZE;
+        3 * width as u64 * height as u64 * COEFFICIENT_BYTES
+    }
+}
+
+/// Bakes the probes of the volume a few per frame and draws their spheres
+pub(crate) struct IrradianceVolumePass {
+    kernel: ComputeKernel,
+    bake_ubo: UniformBuffer<BakeUBOContent>,
+    /// Written by the projection and copied into the textures, laid out like them one channel after the other
+    coefficients: wgpu::Buffer,
+    /// A row of six faces for every probe baked in a frame, copied from the face drawn. Not an array texture since GL
+    /// takes those with layers in multiples of six for cube arrays.
+    faces: wgpu::Texture,
+    bake_bind_group: BindGroup,
+    pipeline: RenderPipeline,
+    draw_bind_group: BindGroup,
+    /// Every face is drawn here, then copied into the faces
+    color: Texture,
+    depth: Texture,
+    ids: Texture,
+    /// Never queried, the faces draw everything
+    occlusion: OcclusionQueries,
+    /// The camera of every face baked in a frame
+    cameras: Vec<(GlobalUBO, GlobalBindings)>,
+    /// The volume being baked or baked last, None before the first
+    baking: Option<IrradianceVolume>,
+    /// Probes of the volume baked so far
+    next_probe: usize,
+    /// Probes baked this frame
+    due: usize,
+    /// Whether the textures hold every probe of the volume's grid, they're kept while it's baked again
+    lit: bool,
+}
+
+impl IrradianceVolumePass {
+    /// None where the device can't project the faces. `format` has to be the engine's swapchain format, the faces
+    /// are drawn by the main pass.
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        samplers: &SamplerCache,
+        global_bindings: &GlobalBindings,
+        textures: &IrradianceTextures,
+        device_report: &DeviceReport,
+    ) -> Option<Self> {
+        if !device_report
+            .downlevel_flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+        {
+            tracing::debug!("Baking irradiance volumes needs compute shaders");
+            return None;
+        }
+        let _span = tracing::debug_span!("create_irradiance_volume_pipelines").entered();
+
+        let bake_ubo = UniformBuffer::new(device);
+        let [width, height] = COEFFICIENTS_SIZE;
+        let coefficients = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Irradiance Coefficients"),
+            size: 3 * width as u64 * height as u64 * COEFFICIENT_BYTES,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let faces = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("irradiance_faces"),
+            size: wgpu::Extent3d {
+                width: 6 * BAKE_RESOLUTION,
+                height: PROBES_PER_FRAME as u32 * BAKE_RESOLUTION,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let faces_view = faces.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let bake_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_types::uniform())
+            .named("bake")
+            .next_binding_compute(binding_types::texture2D())
+            .named("t_faces")
+            .next_binding_compute(binding_types::buffer(false))
+            .named("coefficients")
+            .create(device, "Irradiance Bake Bind Group");
+        let bake_bind_group = BindGroupBuilder::new(&bake_layout)
+            .resource(bake_ubo.binding_resource())
+            .texture(&faces_view)
+            .buffer(&coefficients)
+            .create(device, "Irradiance Bake Bind Group");
+        let kernel = ComputeKernelBuilder::new(include_str!("../irradiance_volume_bake.wgsl"))
+            .entry_point("project")
+            .workgroup_size([64, 1, 1])
+            .bind_group_layout(&bake_layout.layout)
+            .create(device, "irradiance projection");
+
+        let draw_layout = IrradianceTextures::layout(BindGroupLayoutBuilder::new())
+            .create(device, "Irradiance Probes Bind Group");
+        let draw_bind_group = textures
+            .bind(BindGroupBuilder::new(&draw_layout))
+            .create(device, "Irradiance Probes Bind Group");
+        let pipeline = create_pipeline(device, format, global_bindings, &draw_layout);
+
+        Some(IrradianceVolumePass {
+            kernel,
+            bake_ubo,
+            coefficients,
+            faces,
+            bake_bind_group,
+            pipeline,
+            draw_bind_group,
+            color: Texture::create_render_target(
+                device,
+                samplers,
+                BAKE_RESOLUTION,
+                BAKE_RESOLUTION,
+                format,
+                "irradiance_face",
+            ),
+            depth: Texture::create_depth_texture_with_size(
+                device,
+                samplers,
+                BAKE_RESOLUTION,
+                BAKE_RESOLUTION,
+                "irradiance_depth",
+            ),
+            ids: Texture::create_id_texture(
+                device,
+                samplers,
+                BAKE_RESOLUTION,
+                BAKE_RESOLUTION,
+                "irradiance_ids",
+            ),
+            occlusion: OcclusionQueries::new(),
+            cameras: (0..6 * PROBES_PER_FRAME)
+                .map(|_| {
+                    let ubo = GlobalUBO::new(device);
+                    let mut bindings = GlobalBindings::new(device);
+                    bindings.create_bind_group(device, &ubo);
+                    (ubo, bindings)
+                })
+                .collect(),
+            baking: None,
+            next_probe: 0,
+            due: 0,
+            lit: false,
+        })
+    }
+
+    /// Starts baking the volume over when it moved or its grid changed, picks the probes to bake this frame and
+    /// uploads their faces' cameras, along with the volume for the materials
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        uploader: &mut Uploader,
+        textures: &mut IrradianceTextures,
+        volume: Option<&IrradianceVolume>,
+        clipping: ClipUniform,
+        audio: AudioUniform,
+    ) {
+        let Some(volume) = volume else {
+            self.baking = None;
+            self.due = 0;
+            self.lit = false;
+            textures
+                .ubo
+                .update_content(queue, IrradianceUBOContent::default());
+            return;
+        };
+        if !self.baking.is_some_and(|baking| baking.bakes_like(volume)) {
+            self.next_probe = 0;
+            self.lit = false;
+        }
+        self.baking = Some(*volume);
+
+        let count = volume.probe_count();
+        self.due = (count - self.next_probe.min(count)).min(PROBES_PER_FRAME);
+        let mut content = BakeUBOContent::default();
+        for slot in 0..self.due {
+            let ([x, y, z], position) = volume.probe(self.next_probe + slot);
+            for face in 0..6 {
+                update_global_ubo(
+                    &mut self.cameras[slot * 6 + face].0,
+                    uploader,
+                    device,
+                    cube_face_camera(position, volume.near, volume.far, face),
+                    clipping,
+                    audio,
+                );
+            }
+            content.texels[slot] = [z * MAX_PROBES_PER_AXIS + x, y, 0, 0];
+        }
+        self.bake_ubo.update_content(queue, content);
+        self.next_probe += self.due;
+        // The last probes are projected before any view draws this frame
+        self.lit |= self.next_probe == count;
+
+        textures.ubo.update_content(
+            queue,
+            IrradianceUBOContent {
+                min: volume.min,
+                baked: self.lit as u32,
+                max: volume.max,
+                probe_radius: volume.probe_radius,
+                counts: volume.probe_counts(),
+                _padding: 0,
+            },
+        );
+    }
+
+    /// Bakes every probe again, keeping the light of the old ones until then
+    pub fn bake(&mut self) {
+        self.next_probe = 0;
+    }
+
+    /// Whether probes are left to bake in the next frames
+    pub fn is_baking(&self) -> bool {
+        self.baking
+            .is_some_and(|volume| self.next_probe < volume.probe_count())
+    }
+
+    /// The faces to draw this frame, by [IrradianceVolumePass::globals]
+    pub fn due_faces(&self) -> std::ops::Range<usize> {
+        0..self.due * 6
+    }
+
+    /// Bound while drawing the face
+    pub fn globals(&self, face: usize) -> &BindGroup {
+        self.cameras[face].1.bind_groups()
+    }
+
+    pub fn occlusion(&self) -> &OcclusionQueries {
+        &self.occlusion
+    }
+
+    pub fn color_view(&self) -> &TextureView {
+        &self.color.view
+    }
+
+    pub fn depth_view(&self) -> &TextureView {
+        &self.depth.view
+    }
+
+    pub fn id_view(&self) -> &TextureView {
+        &self.ids.view
+    }
+
+    /// Copies what the main pass just drew into the face's place, the probe's row and the face's column
+    pub fn copy_face(&self, encoder: &mut CommandEncoder, face: usize) {
+        encoder.copy_texture_to_texture(
+            self.color.texture.as_image_copy(),
+            wgpu::ImageCopyTexture {
+                texture: &self.faces,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: (face % 6) as u32 * BAKE_RESOLUTION,
+                    y: (face / 6) as u32 * BAKE_RESOLUTION,
+                    z: 0,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::Extent3d {
+                width: BAKE_RESOLUTION,
+                height: BAKE_RESOLUTION,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Projects the faces drawn this frame onto their probes' coefficients and copies them into the textures
+    pub fn project(&self, encoder: &mut CommandEncoder, textures: &IrradianceTextures) {
+        if self.due == 0 {
+            return;
+        }
+        // A workgroup for every probe
+        ComputePassBuilder::new("irradiance bake")
+            .dispatch_workgroups(
+                &self.kernel,
+                &[&self.bake_bind_group],
+                [self.due as u32, 1, 1],
+            )
+            .record(encoder);
+        let [width, height] = COEFFICIENTS_SIZE;
+        for (channel, texture) in textures.channels.iter().enumerate() {
+            encoder.copy_buffer_to_texture(
+                wgpu::ImageCopyBuffer {
+                    buffer: &self.coefficients,
+                    layout: wgpu::ImageDataLayout {
+                        offset: channel as u64 * width as u64 * height as u64 * COEFFICIENT_BYTES,
+                        bytes_per_row: Some(width * COEFFICIENT_BYTES as u32),
+                        rows_per_image: Some(height),
+                    },
+                },
+                texture.texture.as_image_copy(),
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+
+    /// Draws every probe as a sphere lit by it, if the volume shows them
+    pub fn draw(&self, render_pass: &mut RenderPass, globals: &BindGroup, stats: &mut RenderStats) {
+        let Some(volume) = self.baking.filter(|volume| volume.show_probes) else {
+            return;
+        };
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, globals, &[]);
+        render_pass.set_bind_group(1, &self.draw_bind_group, &[]);
+        stats.pipeline_switches += 1;
+        stats.bind_group_switches += 2;
+        // A square facing the camera for every probe
+        let count = volume.probe_count() as u32;
+        render_pass.draw(0..6, 0..count);
+        stats.draw(6, count);
+    }
+
+    /// Bytes of the faces and coefficients
+    pub fn size_in_bytes(&self) -> u64 {
+        let layer = BAKE_RESOLUTION as u64 * BAKE_RESOLUTION as u64 * 4;
+        self.color.size_in_bytes()
+            + self.depth.size_in_bytes()
+            + self.ids.size_in_bytes()
+            + layer * 6 * PROBES_PER_FRAME as u64
+            + self.coefficients.size()
+    }
+}
+
+/// Probe spheres in the main pass, written into the depth buffer
+fn create_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    global_bindings: &GlobalBindings,
+    bind_group_layout: &BindGroupLayoutWithDesc,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Irradiance Probes Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../irradiance_volume.wgsl").into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Irradiance Probes Pipeline Layout"),
+        bind_group_layouts: &[
+            global_bindings.bind_group_layouts(),
+            &bind_group_layout.layout,
+        ],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Irradiance Probes Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: texture::Texture::ID_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
+            compilation_options: Default::default(),
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
@@ -0,0 +1,81 @@
+// Texel of every probe baked this frame, see irradiance_volume/pass.rs
+struct Bake {
+    texels: array<vec4<u32>, 8>,
+}
+@group(0) @binding(0)
+var<uniform> bake: Bake;
+// A row for every probe of its six faces, each laid out like a cubemap's but holding the world mirrored along Z
+@group(0) @binding(1)
+var t_faces: texture_2d<f32>;
+// Every channel's texture after the other, each texel as two pairs of 16 bit floats
+@group(0) @binding(2)
+var<storage, read_write> coefficients: array<vec2<u32>>;
+
+const SIZE = 16u;
+const THREADS = 64u;
+const TEXTURE_WIDTH = 256u;
+const TEXTURE_HEIGHT = 16u;
+
+// Towards the texel at `st` from -1 to 1 across the face, rows running down, as cubemaps are laid out
+fn face_direction(face: u32, st: vec2<f32>) -> vec3<f32> {
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -st.y, -st.x); }
+        case 1u: { return vec3<f32>(-1.0, -st.y, st.x); }
+        case 2u: { return vec3<f32>(st.x, 1.0, st.y); }
+        case 3u: { return vec3<f32>(st.x, -1.0, -st.y); }
+        case 4u: { return vec3<f32>(st.x, -st.y, 1.0); }
+        default: { return vec3<f32>(-st.x, -st.y, -1.0); }
+    }
+}
+
+var<workgroup> red: array<vec4<f32>, THREADS>;
+var<workgroup> green: array<vec4<f32>, THREADS>;
+var<workgroup> blue: array<vec4<f32>, THREADS>;
+
+// One workgroup per probe, every thread sums the texels it's given, weighted by the solid angle they cover and the
+// first two bands of spherical harmonics towards them
+@compute @workgroup_size(64, 1, 1)
+fn project(@builtin(workgroup_id) workgroup: vec3<u32>, @builtin(local_invocation_index) thread: u32) {
+    let probe = workgroup.x;
+    var sums = array<vec4<f32>, 3>(vec4<f32>(0.0), vec4<f32>(0.0), vec4<f32>(0.0));
+    for (var texel = thread; texel < 6u * SIZE * SIZE; texel += THREADS) {
+        let face = texel / (SIZE * SIZE);
+        let pixel = vec2<u32>(texel % SIZE, texel / SIZE % SIZE);
+        let st = (vec2<f32>(pixel) + 0.5) / f32(SIZE) * 2.0 - 1.0;
+        let solid_angle = 4.0 / f32(SIZE * SIZE) / pow(1.0 + dot(st, st), 1.5);
+        var direction = normalize(face_direction(face, st));
+        direction.z = -direction.z;
+        let basis = vec4<f32>(0.282095, 0.488603 * direction) * solid_angle;
+        let radiance = textureLoad(t_faces, pixel + vec2<u32>(face, probe) * SIZE, 0).rgb;
+        sums[0] += radiance.r * basis;
+        sums[1] += radiance.g * basis;
+        sums[2] += radiance.b * basis;
+    }
+    red[thread] = sums[0];
+    green[thread] = sums[1];
+    blue[thread] = sums[2];
+    workgroupBarrier();
+    for (var stride = THREADS / 2u; stride > 0u; stride /= 2u) {
+        if thread < stride {
+            red[thread] += red[thread + stride];
+            green[thread] += green[thread + stride];
+            blue[thread] += blue[thread + stride];
+        }
+        workgroupBarrier();
+    }
+    if thread != 0u {
+        return;
+    }
+
+    // Convolved with the cosine lobe and divided by pi, then multiplied by the constant of each basis function, so
+    // the light reflected off a white surface facing n is the dot product with (1, n)
+    let scale = vec4<f32>(0.282095, vec3<f32>(0.488603 * 2.0 / 3.0));
+    let texel = bake.texels[probe].xy;
+    let index = texel.y * TEXTURE_WIDTH + texel.x;
+    var channels = array<vec4<f32>, 3>(red[0], green[0], blue[0]);
+    for (var channel = 0u; channel < 3u; channel++) {
+        let scaled = channels[channel] * scale;
+        coefficients[channel * TEXTURE_WIDTH * TEXTURE_HEIGHT + index] =
+            vec2<u32>(pack2x16float(scaled.xy), pack2x16float(scaled.zw));
+    }
+}
@@ -0,0 +1,331 @@
+//! Isosurfaces of scalar volumes, extracted on the GPU with marching cubes every frame. Meant for watching live
+//! simulations, e.g. a density or temperature field, where the values change between frames.
+//!
+//! The values are uploaded into a 3D float texture whenever they change. A compute pass then walks every cell of
+//! the grid, looks up the triangles for its corners being above or below [Isosurface::isovalue] and appends them to
+//! a storage buffer, together with the count of an indirect draw. The main pass draws them from that buffer with
+//! vertex pulling, the CPU never sees the triangles, so moving the isovalue only costs the extraction.
+//!
+//! The case table is built when the pass is created by walking along the faces of a cell, the triangles face
+//! towards lower values. Faces with diagonal corners above the isovalue always separate those corners, so both cells
+//! sharing a face cut it the same way and the surface has no holes.
+//!
+//! [Slice]s cut through a volume along a plane and color the values on it. They sample the same texture and only
+//! update a few uniforms when moved, so dragging one through the volume is cheap. [crate::probe] reads the values
+//! under the cursor.
+//!
+//! Devices without compute shaders, indirect draws or storage buffers in vertex shaders, e.g. WebGL2, can't draw
+//! isosurfaces or slices.
+
+mod pass;
+mod slice;
+
+use cgmath::{Matrix4, SquareMatrix};
+use wgpu::{CommandEncoder, Device, Queue, TextureFormat};
+
+use crate::{
+    colormap::ColormapTextures, global_bindings::GlobalBindings, lazy_pass::LazyPass,
+    render_engine_builder::DeviceReport,
+};
+
+pub(crate) use self::pass::IsosurfacePass;
+pub use self::slice::{Slice, SliceHandle, SlicePlane};
+
+pub struct Isosurface {
+    size: [u32; 3],
+    values: Vec<f32>,
+    /// Counts changes to the values, which are uploaded again when it moved on
+    revision: u64,
+    /// Box the values fill, in the volume's own units
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    /// Where the box is placed in the scene
+    pub transform: Matrix4<f32>,
+    /// The surface is where the values cross this
+    pub isovalue: f32,
+    /// Linear RGB of the surface
+    pub color: [f32; 3],
+    /// Triangles beyond this are dropped. Every one takes 96 bytes of GPU memory.
+    pub max_triangles: u32,
+    /// Whether the surface is extracted and drawn, slices through the volume are drawn either way
+    pub visible: bool,
+}
+
+impl Isosurface {
+    /// `values` are at the corners of the grid's cells, X first, then Y, then Z
+    pub fn new(
+        size: [u32; 3],
+        min: [f32; 3],
+        max: [f32; 3],
+        isovalue: f32,
+        values: Vec<f32>,
+    ) -> Self {
+        assert_values(size, &values);
+        Isosurface {
+            size,
+            values,
+            revision: 0,
+            min,
+            max,
+            transform: Matrix4::identity(),
+            isovalue,
+            color: [0.8, 0.8, 0.8],
+            max_triangles: 1 << 18,
+            visible: true,
+        }
+    }
+
+    /// A grid of `size` values from the `min` to the `max` corner, each one what `value` returns for its position
+    pub fn from_fn(
+        size: [u32; 3],
+        min: [f32; 3],
+        max: [f32; 3],
+        isovalue: f32,
+        mut value: impl FnMut([f32; 3]) -> f32,
+    ) -> Self {
+        let mut values = Vec::with_capacity(size.iter().map(|&size| size as usize).product());
+        for z in 0..size[2] {
+            for y in 0..size[1] {
+                for x in 0..size[0] {
+                    let position = std::array::from_fn(|axis| {
+                        let t = [x, y, z][axis] as f32 / (size[axis] - 1).max(1) as f32;
+                        min[axis] + (max[axis] - min[axis]) * t
+                    });
+                    values.push(value(position));
+                }
+            }
+        }
+        Self::new(size, min, max, isovalue, values)
+    }
+
+    pub fn size(&self) -> [u32; 3] {
+        self.size
+    }
+
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// Replaces the values, e.g. with the next step of a simulation. They're uploaded before the next frame.
+    pub fn set_values(&mut self, size: [u32; 3], values: Vec<f32>) {
+        assert_values(size, &values);
+        self.size = size;
+        self.values = values;
+        self.revision += 1;
+    }
+
+    /// For changing values in place, they're all uploaded again before the next frame
+    pub fn values_mut(&mut self) -> &mut [f32] {
+        self.revision += 1;
+        &mut self.values
+    }
+
+    pub fn with_color(mut self, color: [f32; 3]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_max_triangles(mut self, max_triangles: u32) -> Self {
+        self.max_triangles = max_triangles;
+        self
+    }
+
+    /// The value at a position in the volume's own units, filtered linearly between the grid's values. None outside
+    /// of the box.
+    pub fn sample(&self, position: [f32; 3]) -> Option<f32> {
+        if self.size.contains(&0) {
+            return None;
+        }
+        let mut cell = [0; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            let extent = self.max[axis] - self.min[axis];
+            let t = if extent > 0.0 {
+                (position[axis] - self.min[axis]) / extent
+            } else {
+                0.0
+            };
+            if !(0.0..=1.0).contains(&t) {
+                return None;
+            }
+            let last = self.size[axis] - 1;
+            let texel = t * last as f32;
+            cell[axis] = (texel as u32).min(last.saturating_sub(1));
+            fraction[axis] = if last == 0 {
+                0.0
+            } else {
+                texel - cell[axis] as f32
+            };
+        }
+
+        let mut sum = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = [0; 3];
+            for axis in 0..3 {
+                let up = corner >> axis & 1 == 1;
+                index[axis] = (cell[axis] + up as u32).min(self.size[axis] - 1);
+                weight *= if up {
+                    fraction[axis]
+                } else {
+                    1.0 - fraction[axis]
+                };
+            }
+            let [x, y, z] = index.map(|index| index as usize);
+            let [width, height, _] = self.size.map(|size| size as usize);
+            sum += self.values[(z * height + y) * width + x] * weight;
+        }
+        Some(sum)
+    }
+}
+
+fn assert_values(size: [u32; 3], values: &[f32]) {
+    assert_eq!(
+        values.len(),
+        size.iter().map(|&size| size as usize).product::<usize>(),
+        "Values don't match the volume's size!"
+    );
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IsosurfaceHandle(pub(crate) usize);
+
+/// The engine's isosurfaces and slices and the pass extracting and drawing them, which is created with the first
+/// surface or slice
+#[derive(Default)]
+pub(crate) struct Isosurfaces {
+    /// Unsupported where the device can't extract isosurfaces
+    pass: LazyPass<IsosurfacePass>,
+    /// None where a surface was removed
+    surfaces: Vec<Option<Isosurface>>,
+    /// None where a slice was removed
+    slices: Vec<Option<Slice>>,
+}
+
+impl Isosurfaces {
+    pub fn add(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        device_report: &DeviceReport,
+        surface: Isosurface,
+    ) -> IsosurfaceHandle {
+        if self
+            .pass
+            .get_or_create(|| IsosurfacePass::new(device, format, global_bindings, device_report))
+            .is_none()
+        {
+            tracing::warn!("Isosurfaces need compute shaders, indirect draws and vertex storage, it isn't drawn");
+        }
+        self.surfaces.push(Some(surface));
+        IsosurfaceHandle(self.surfaces.len() - 1)
+    }
+
+    pub fn get(&self, handle: IsosurfaceHandle) -> Option<&Isosurface> {
+        self.surfaces.get(handle.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: IsosurfaceHandle) -> Option<&mut Isosurface> {
+        self.surfaces.get_mut(handle.0)?.as_mut()
+    }
+
+    /// Frees the surface's values and triangles on the GPU
+    pub fn remove(&mut self, handle: IsosurfaceHandle) -> Option<Isosurface> {
+        let surface = self.surfaces.get_mut(handle.0)?.take()?;
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_surface(handle.0);
+        }
+        Some(surface)
+    }
+
+    /// Every surface, None where one was removed
+    pub fn surfaces(&self) -> &[Option<Isosurface>] {
+        &self.surfaces
+    }
+
+    pub fn add_slice(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        device_report: &DeviceReport,
+        slice: Slice,
+    ) -> SliceHandle {
+        if self
+            .pass
+            .get_or_create(|| IsosurfacePass::new(device, format, global_bindings, device_report))
+            .is_none()
+        {
+            tracing::warn!(
+                "Slices are drawn with isosurfaces, which this device can't draw, it isn't drawn"
+            );
+        }
+        self.slices.push(Some(slice));
+        SliceHandle(self.slices.len() - 1)
+    }
+
+    pub fn slice(&self, handle: SliceHandle) -> Option<&Slice> {
+        self.slices.get(handle.0)?.as_ref()
+    }
+
+    pub fn slice_mut(&mut self, handle: SliceHandle) -> Option<&mut Slice> {
+        self.slices.get_mut(handle.0)?.as_mut()
+    }
+
+    pub fn remove_slice(&mut self, handle: SliceHandle) -> Option<Slice> {
+        let slice = self.slices.get_mut(handle.0)?.take()?;
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_slice(handle.0);
+        }
+        Some(slice)
+    }
+
+    /// Draws the surfaces and slices in the main pass, None before the first of them and where the device can't
+    /// extract them
+    pub fn pass(&self) -> Option<&IsosurfacePass> {
+        self.pass.get()
+    }
+
+    /// Whether there are surfaces to extract in the compute phase
+    pub fn is_active(&self) -> bool {
+        self.pass.get().is_some() && self.surfaces.iter().any(Option::is_some)
+    }
+
+    /// See [IsosurfacePass::extract]
+    pub fn extract(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        colormaps: &mut ColormapTextures,
+        encoder: &mut CommandEncoder,
+    ) {
+        if let Some(pass) = self.pass.get_mut() {
+            pass.extract(
+                device,
+                queue,
+                &self.surfaces,
+                &self.slices,
+                colormaps,
+                encoder,
+            );
+        }
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.pass.get().map_or(0, IsosurfacePass::size_in_bytes)
+    }
+
+    /// Creates the pass again on a new device, the values are uploaded again on the next extraction
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        device_report: &DeviceReport,
+    ) {
+        self.pass
+            .recreate(|| IsosurfacePass::new(device, format, global_bindings, device_report));
+    }
+}
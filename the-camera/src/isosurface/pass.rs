@@ -0,0 +1,671 @@
+use std::collections::HashMap;
+
+use wgpu::{
+    BindGroup, CommandEncoder, Device, Queue, RenderPass, RenderPipeline, TextureFormat,
+    TextureView,
+};
+
+use super::{Isosurface, Slice};
+use crate::{
+    colormap::{Colormap, ColormapTextures},
+    global_bindings::GlobalBindings,
+    profiler::RenderStats,
+    render_engine_builder::DeviceReport,
+    texture,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        compute::{ComputeKernel, ComputeKernelBuilder, ComputePassBuilder},
+        uniform_buffer::UniformBuffer,
+    },
+};
+
+/// Corners of a cell, as the bits of their index: 1 is +X, 2 is +Y and 4 is +Z
+const EDGES: [[usize; 2]; 12] = [
+    [0, 1],
+    [2, 3],
+    [4, 5],
+    [6, 7],
+    [0, 2],
+    [1, 3],
+    [4, 6],
+    [5, 7],
+    [0, 4],
+    [1, 5],
+    [2, 6],
+    [3, 7],
+];
+/// Corners of each face of a cell, counter-clockwise seen from outside
+const FACES: [[usize; 4]; 6] = [
+    [4, 6, 2, 0],
+    [1, 3, 7, 5],
+    [0, 1, 5, 4],
+    [6, 7, 3, 2],
+    [2, 3, 1, 0],
+    [4, 5, 7, 6],
+];
+/// Entries per case in the table: the triangle count, then three edges for each of at most 5 triangles
+const CASE_SIZE: usize = 16;
+/// Cells each workgroup of the extraction kernel walks along each axis
+const WORKGROUP_SIZE: u32 = 4;
+
+/// The triangles of every corner case, [CASE_SIZE] entries each
+fn case_table() -> Vec<u32> {
+    let edge = |a: usize, b: usize| {
+        EDGES
+            .iter()
+            .position(|edge| *edge == [a.min(b), a.max(b)])
+            .expect("Face corners are joined by an edge!") as u32
+    };
+    let mut table = vec![0; 256 * CASE_SIZE];
+    for case in 0..256 {
+        let above = |corner: usize| case >> corner & 1 == 1;
+        // Every run of corners above the isovalue along a face is cut off by a segment from the edge it starts at to
+        // the one it ends at. Each crossed edge then starts one segment and ends another, so they join into loops.
+        let mut segments = HashMap::new();
+        for face in FACES {
+            for start in 0..4 {
+                if above(face[(start + 3) % 4]) || !above(face[start]) {
+                    continue;
+                }
+                let mut end = start;
+                while above(face[(end + 1) % 4]) {
+                    end += 1;
+                }
+                segments.insert(
+                    edge(face[(start + 3) % 4], face[start]),
+                    edge(face[end % 4], face[(end + 1) % 4]),
+                );
+            }
+        }
+
+        let entries = &mut table[case * CASE_SIZE..(case + 1) * CASE_SIZE];
+        let mut triangles = 0;
+        while let Some(&first) = segments.keys().next() {
+            let mut loop_edges = vec![first];
+            let mut next = segments.remove(&first).expect("Segments join into loops!");
+            while next != first {
+                loop_edges.push(next);
+                next = segments.remove(&next).expect("Segments join into loops!");
+            }
+            for fan in 1..loop_edges.len() - 1 {
+                let triangle = [loop_edges[0], loop_edges[fan], loop_edges[fan + 1]];
+                entries[1 + triangles * 3..4 + triangles * 3].copy_from_slice(&triangle);
+                triangles += 1;
+            }
+        }
+        entries[0] = triangles as u32;
+    }
+    table
+}
+
+/// Per surface parameters, bound by the extraction and the draws
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct IsosurfaceUBOContent {
+    model: [[f32; 4]; 4],
+    color: [f32; 4],
+    min: [f32; 3],
+    isovalue: f32,
+    max: [f32; 3],
+    max_triangles: u32,
+    size: [u32; 3],
+    _padding: u32,
+}
+
+crate::assert_uniform_layout!(IsosurfaceUBOContent {
+    model: ALIGN_VEC4,
+    color: ALIGN_VEC4,
+    min: ALIGN_VEC4,
+    isovalue: ALIGN_SCALAR,
+    max: ALIGN_VEC4,
+    max_triangles: ALIGN_SCALAR,
+    size: ALIGN_VEC4,
+});
+
+/// Per slice parameters, the plane and the box in the volume's units
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SliceUBOContent {
+    model: [[f32; 4]; 4],
+    min: [f32; 3],
+    low: f32,
+    max: [f32; 3],
+    high: f32,
+    point: [f32; 3],
+    _padding0: f32,
+    normal: [f32; 3],
+    _padding1: f32,
+    size: [u32; 3],
+    _padding2: u32,
+}
+
+crate::assert_uniform_layout!(SliceUBOContent {
+    model: ALIGN_VEC4,
+    min: ALIGN_VEC4,
+    low: ALIGN_SCALAR,
+    max: ALIGN_VEC4,
+    high: ALIGN_SCALAR,
+    point: ALIGN_VEC4,
+    normal: ALIGN_VEC4,
+    size: ALIGN_VEC4,
+});
+
+/// Position and normal of an extracted vertex, both padded to 16 bytes for the storage buffer
+const VERTEX_SIZE: u64 = 32;
+
+struct GpuIsosurface {
+    ubo: UniformBuffer<IsosurfaceUBOContent>,
+    values: wgpu::Texture,
+    values_view: TextureView,
+    /// Tells the texture apart from earlier ones of the same surface, for the slices binding it
+    upload: u64,
+    /// [Isosurface::revision] of the uploaded values
+    revision: u64,
+    /// Smallest and largest uploaded value
+    range: [f32; 2],
+    /// [Isosurface::visible]
+    visible: bool,
+    vertices: wgpu::Buffer,
+    /// Vertex count, instance count, first vertex and first instance, written by the extraction
+    draw: wgpu::Buffer,
+    extract_bind_group: BindGroup,
+    draw_bind_group: BindGroup,
+}
+
+struct GpuSlice {
+    ubo: UniformBuffer<SliceUBOContent>,
+    bind_group: BindGroup,
+    /// The surface and [GpuIsosurface::upload] of the texture the bind group holds
+    volume: usize,
+    upload: u64,
+    /// [Slice::colormap] of the bind group
+    colormap: Colormap,
+}
+
+/// Extracts and draws every isosurface and slice, shared by every view
+pub(crate) struct IsosurfacePass {
+    reset: ComputeKernel,
+    extract: ComputeKernel,
+    finish: ComputeKernel,
+    extract_bind_group_layout: BindGroupLayoutWithDesc,
+    draw_bind_group_layout: BindGroupLayoutWithDesc,
+    slice_bind_group_layout: BindGroupLayoutWithDesc,
+    pipeline: RenderPipeline,
+    slice_pipeline: RenderPipeline,
+    cases: wgpu::Buffer,
+    surfaces: HashMap<usize, GpuIsosurface>,
+    slices: HashMap<usize, GpuSlice>,
+    /// Textures uploaded so far
+    uploads: u64,
+    /// Sizes of the surfaces too large for the device's 3D textures, so they're only reported once
+    too_large: HashMap<usize, [u32; 3]>,
+}
+
+impl IsosurfacePass {
+    /// None where the device can't run the extraction or draw what it writes. `format` has to be the engine's
+    /// swapchain format, since the surfaces are drawn in the main pass.
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        device_report: &DeviceReport,
+    ) -> Option<Self> {
+        let required = wgpu::DownlevelFlags::COMPUTE_SHADERS
+            | wgpu::DownlevelFlags::INDIRECT_EXECUTION
+            | wgpu::DownlevelFlags::VERTEX_STORAGE;
+        if !device_report.downlevel_flags.contains(required) {
+            tracing::debug!("Isosurfaces need compute shaders, indirect draws and vertex storage");
+            return None;
+        }
+        let _span = tracing::debug_span!("create_isosurface_pipelines").entered();
+
+        let extract_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_types::uniform())
+            .named("surface")
+            .next_binding_compute(wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D3,
+                multisampled: false,
+            })
+            .named("values")
+            .next_binding_compute(binding_types::buffer(true))
+            .named("cases")
+            .next_binding_compute(binding_types::buffer(false))
+            .named("vertices")
+            .next_binding_compute(binding_types::buffer(false))
+            .named("draw")
+            .create(device, "Isosurface Extraction Bind Group");
+        let source = include_str!("../isosurface_extract.wgsl");
+        let kernel = |entry_point, workgroup_size, label| {
+            ComputeKernelBuilder::new(source)
+                .entry_point(entry_point)
+                .workgroup_size(workgroup_size)
+                .bind_group_layout(&extract_bind_group_layout.layout)
+                .create(device, label)
+        };
+        let reset = kernel("reset", [1, 1, 1], "isosurface reset");
+        let extract = kernel("extract", [WORKGROUP_SIZE; 3], "isosurface extraction");
+        let finish = kernel("finish", [1, 1, 1], "isosurface finish");
+
+        let draw_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_rendering(binding_types::uniform())
+            .next_binding_vertex(binding_types::buffer(true))
+            .create(device, "Isosurface Bind Group");
+        let pipeline = create_pipeline(
+            device,
+            format,
+            global_bindings,
+            &draw_bind_group_layout,
+            include_str!("../isosurface.wgsl"),
+            "isosurface",
+        );
+
+        let slice_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_rendering(binding_types::uniform())
+            .next_binding_fragment(wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D3,
+                multisampled: false,
+            })
+            .next_binding_fragment(binding_types::texture2D())
+            .next_binding_fragment(binding_types::sampler(wgpu::SamplerBindingType::Filtering))
+            .create(device, "Isosurface Slice Bind Group");
+        let slice_pipeline = create_pipeline(
+            device,
+            format,
+            global_bindings,
+            &slice_bind_group_layout,
+            include_str!("../isosurface_slice.wgsl"),
+            "isosurface slice",
+        );
+
+        let cases = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Marching Cubes Cases"),
+                contents: bytemuck::cast_slice(&case_table()),
+                usage: wgpu::BufferUsages::STORAGE,
+            },
+        );
+
+        Some(IsosurfacePass {
+            reset,
+            extract,
+            finish,
+            extract_bind_group_layout,
+            draw_bind_group_layout,
+            slice_bind_group_layout,
+            pipeline,
+            slice_pipeline,
+            cases,
+            surfaces: HashMap::new(),
+            slices: HashMap::new(),
+            uploads: 0,
+            too_large: HashMap::new(),
+        })
+    }
+
+    /// Uploads whatever changed about the surfaces and slices and records the extraction of the visible surfaces
+    pub fn extract(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        surfaces: &[Option<Isosurface>],
+        slices: &[Option<Slice>],
+        colormaps: &mut ColormapTextures,
+        encoder: &mut CommandEncoder,
+    ) {
+        let mut pass = ComputePassBuilder::new("isosurfaces");
+        let mut extracted = Vec::new();
+        for (index, surface) in surfaces.iter().enumerate() {
+            let Some(surface) = surface else {
+                continue;
+            };
+            if surface.size.iter().any(|&size| size < 2) {
+                continue;
+            }
+            let limit = device.limits().max_texture_dimension_3d;
+            if surface.size.iter().any(|&size| size > limit) {
+                if self.too_large.insert(index, surface.size) != Some(surface.size) {
+                    tracing::error!(
+                        "Isosurface volume of {:?} values is larger than the device's 3D textures of {limit} texels \
+                         a side, it isn't drawn",
+                        surface.size
+                    );
+                }
+                self.surfaces.remove(&index);
+                continue;
+            }
+            let max_triangles = self.max_triangles(device, surface);
+            let stale = self.surfaces.get(&index).is_none_or(|uploaded| {
+                uploaded.values.size() != extent(surface.size)
+                    || uploaded.vertices.size() != max_triangles as u64 * 3 * VERTEX_SIZE
+            });
+            if stale {
+                let uploaded = self.upload(device, surface, max_triangles);
+                self.surfaces.insert(index, uploaded);
+            }
+            let uploaded = self
+                .surfaces
+                .get_mut(&index)
+                .expect("Surface was just uploaded!");
+            if uploaded.revision != surface.revision || stale {
+                write_values(queue, &uploaded.values, surface);
+                uploaded.revision = surface.revision;
+                uploaded.range = surface
+                    .values
+                    .iter()
+                    .fold([f32::INFINITY, f32::NEG_INFINITY], |[low, high], &value| {
+                        [low.min(value), high.max(value)]
+                    });
+            }
+            uploaded.visible = surface.visible;
+            uploaded.ubo.update_content(
+                queue,
+                IsosurfaceUBOContent {
+                    model: surface.transform.into(),
+                    color: [surface.color[0], surface.color[1], surface.color[2], 1.0],
+                    min: surface.min,
+                    isovalue: surface.isovalue,
+                    max: surface.max,
+                    max_triangles,
+                    size: surface.size,
+                    _padding: 0,
+                },
+            );
+            if surface.visible {
+                extracted.push((index, surface.size));
+            }
+        }
+        for (index, size) in extracted {
+            let bind_group = &self.surfaces[&index].extract_bind_group;
+            let cells = size.map(|size| size - 1);
+            pass = pass
+                .dispatch(&self.reset, &[bind_group], [1, 1, 1])
+                .dispatch(&self.extract, &[bind_group], cells)
+                .dispatch(&self.finish, &[bind_group], [1, 1, 1]);
+        }
+        pass.record(encoder);
+
+        for (index, slice) in slices.iter().enumerate() {
+            let Some(slice) = slice else {
+                continue;
+            };
+            let volume = slice.volume.0;
+            let (Some(uploaded), Some(Some(surface))) =
+                (self.surfaces.get(&volume), surfaces.get(volume))
+            else {
+                // The volume was removed or can't be uploaded
+                self.slices.remove(&index);
+                continue;
+            };
+            let stale = self.slices.get(&index).is_none_or(|gpu_slice| {
+                gpu_slice.volume != volume
+                    || gpu_slice.upload != uploaded.upload
+                    || gpu_slice.colormap != slice.colormap
+            });
+            if stale {
+                let ubo = UniformBuffer::new(device);
+                let (colormap, sampler) = colormaps.binding(device, queue, slice.colormap);
+                let bind_group = BindGroupBuilder::new(&self.slice_bind_group_layout)
+                    .resource(ubo.binding_resource())
+                    .texture(&uploaded.values_view)
+                    .texture(colormap)
+                    .sampler(sampler)
+                    .create(device, "Isosurface Slice Bind Group");
+                self.slices.insert(
+                    index,
+                    GpuSlice {
+                        ubo,
+                        bind_group,
+                        volume,
+                        upload: uploaded.upload,
+                        colormap: slice.colormap,
+                    },
+                );
+            }
+            let [low, high] = slice.value_range.unwrap_or(uploaded.range);
+            let (point, normal) = slice.plane.point_normal(surface.min, surface.max);
+            let gpu_slice = self
+                .slices
+                .get_mut(&index)
+                .expect("Slice was just created!");
+            gpu_slice.ubo.update_content(
+                queue,
+                SliceUBOContent {
+                    model: surface.transform.into(),
+                    min: surface.min,
+                    low,
+                    max: surface.max,
+                    high,
+                    point,
+                    _padding0: 0.0,
+                    normal,
+                    _padding1: 0.0,
+                    size: surface.size,
+                    _padding2: 0,
+                },
+            );
+        }
+    }
+
+    /// [Isosurface::max_triangles], limited to what fits into one storage buffer binding
+    fn max_triangles(&self, device: &Device, surface: &Isosurface) -> u32 {
+        let limit = device.limits().max_storage_buffer_binding_size as u64 / (3 * VERTEX_SIZE);
+        if surface.max_triangles as u64 > limit {
+            tracing::warn!(
+                "Isosurfaces can have at most {limit} triangles on this device, {} were asked for",
+                surface.max_triangles
+            );
+        }
+        surface.max_triangles.min(limit as u32).max(1)
+    }
+
+    fn upload(
+        &mut self,
+        device: &Device,
+        surface: &Isosurface,
+        max_triangles: u32,
+    ) -> GpuIsosurface {
+        let values = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Isosurface Values"),
+            size: extent(surface.size),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let values_view = values.create_view(&wgpu::TextureViewDescriptor::default());
+        let vertices = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Isosurface Vertices"),
+            size: max_triangles as u64 * 3 * VERTEX_SIZE,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let draw = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Isosurface Draw"),
+            size: std::mem::size_of::<wgpu::util::DrawIndirectArgs>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+            mapped_at_creation: false,
+        });
+        let ubo = UniformBuffer::new(device);
+        let extract_bind_group = BindGroupBuilder::new(&self.extract_bind_group_layout)
+            .resource(ubo.binding_resource())
+            .texture(&values_view)
+            .buffer(&self.cases)
+            .buffer(&vertices)
+            .buffer(&draw)
+            .create(device, "Isosurface Extraction Bind Group");
+        let draw_bind_group = BindGroupBuilder::new(&self.draw_bind_group_layout)
+            .resource(ubo.binding_resource())
+            .buffer(&vertices)
+            .create(device, "Isosurface Bind Group");
+        self.uploads += 1;
+        GpuIsosurface {
+            ubo,
+            values,
+            values_view,
+            upload: self.uploads,
+            revision: surface.revision,
+            // Computed when the values are written
+            range: [0.0, 0.0],
+            visible: surface.visible,
+            vertices,
+            draw,
+            extract_bind_group,
+            draw_bind_group,
+        }
+    }
+
+    /// Frees a surface that was removed, slices through it are freed with the next extraction
+    pub fn remove_surface(&mut self, index: usize) {
+        self.surfaces.remove(&index);
+        self.too_large.remove(&index);
+    }
+
+    pub fn remove_slice(&mut self, index: usize) {
+        self.slices.remove(&index);
+    }
+
+    /// Bytes of the values and triangle buffers
+    pub fn size_in_bytes(&self) -> u64 {
+        self.surfaces
+            .values()
+            .map(|surface| {
+                let size = surface.values.size();
+                size.width as u64 * size.height as u64 * size.depth_or_array_layers as u64 * 4
+                    + surface.vertices.size()
+            })
+            .sum()
+    }
+
+    /// Draws what the extraction wrote and the slices, in the main pass
+    pub fn draw(&self, render_pass: &mut RenderPass, globals: &BindGroup, stats: &mut RenderStats) {
+        if self.surfaces.is_empty() {
+            return;
+        }
+        render_pass.set_bind_group(0, globals, &[]);
+        stats.bind_group_switches += 1;
+        if self.surfaces.values().any(|surface| surface.visible) {
+            render_pass.set_pipeline(&self.pipeline);
+            stats.pipeline_switches += 1;
+        }
+        for surface in self.surfaces.values().filter(|surface| surface.visible) {
+            render_pass.set_bind_group(1, &surface.draw_bind_group, &[]);
+            render_pass.draw_indirect(&surface.draw, 0);
+            stats.bind_group_switches += 1;
+            // The CPU doesn't know how many triangles were extracted
+            stats.draw(0, 1);
+        }
+        if !self.slices.is_empty() {
+            render_pass.set_pipeline(&self.slice_pipeline);
+            stats.pipeline_switches += 1;
+        }
+        for slice in self.slices.values() {
+            render_pass.set_bind_group(1, &slice.bind_group, &[]);
+            render_pass.draw(0..6, 0..1);
+            stats.bind_group_switches += 1;
+            stats.draw(6, 1);
+        }
+    }
+}
+
+/// Draws into the main pass from bind group 1, without vertex buffers
+fn create_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    global_bindings: &GlobalBindings,
+    bind_group_layout: &BindGroupLayoutWithDesc,
+    source: &str,
+    label: &str,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[
+            global_bindings.bind_group_layouts(),
+            &bind_group_layout.layout,
+        ],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // Surfaces cut open by the box are seen from inside, slices from both sides
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+            unclipped_depth: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: texture::Texture::ID_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
+            compilation_options: Default::default(),
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
+
+fn extent([width, height, depth]: [u32; 3]) -> wgpu::Extent3d {
+    wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: depth,
+    }
+}
+
+fn write_values(queue: &Queue, texture: &wgpu::Texture, surface: &Isosurface) {
+    let [width, height, _] = surface.size;
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        bytemuck::cast_slice(&surface.values),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(width * 4),
+            rows_per_image: Some(height),
+        },
+        extent(surface.size),
+    );
+}
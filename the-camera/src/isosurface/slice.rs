@@ -0,0 +1,78 @@
+use cgmath::{InnerSpace, Vector3};
+
+use super::IsosurfaceHandle;
+use crate::colormap::Colormap;
+
+/// Where a [Slice] cuts its volume, in the volume's own units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SlicePlane {
+    /// Across an axis, 0 for X, 1 for Y and 2 for Z, `fraction` of the way from the box's min to its max corner
+    Axis { axis: usize, fraction: f32 },
+    /// Through `point`, facing along `normal`
+    Arbitrary { point: [f32; 3], normal: [f32; 3] },
+}
+
+impl SlicePlane {
+    /// A point on the plane and its unit normal, for a volume filling the box from `min` to `max`
+    pub fn point_normal(&self, min: [f32; 3], max: [f32; 3]) -> ([f32; 3], [f32; 3]) {
+        match *self {
+            SlicePlane::Axis { axis, fraction } => {
+                let axis = axis.min(2);
+                let mut point: [f32; 3] =
+                    std::array::from_fn(|index| (min[index] + max[index]) / 2.0);
+                point[axis] = min[axis] + (max[axis] - min[axis]) * fraction;
+                let mut normal = [0.0; 3];
+                normal[axis] = 1.0;
+                (point, normal)
+            }
+            SlicePlane::Arbitrary { point, normal } => {
+                let normal = Vector3::from(normal);
+                let normal = if normal.magnitude2() > 0.0 {
+                    normal.normalize()
+                } else {
+                    Vector3::unit_z()
+                };
+                (point, normal.into())
+            }
+        }
+    }
+}
+
+/// A plane through an [super::Isosurface]'s volume colored by the values on it. Added with
+/// [crate::render_engine::RenderEngine::add_slice].
+///
+/// Slices are opaque and write depth, so [crate::probe] reads the values on them and they hide the surface behind
+/// them. Moving [Slice::plane] through [crate::render_engine::RenderEngine::slice_mut] every frame is cheap.
+#[derive(Debug, Clone)]
+pub struct Slice {
+    /// The volume cut, the slice isn't drawn once it's removed
+    pub volume: IsosurfaceHandle,
+    pub plane: SlicePlane,
+    /// Values mapped to the ends of the colormap, from the volume's smallest to its largest value when None
+    pub value_range: Option<[f32; 2]>,
+    pub colormap: Colormap,
+}
+
+impl Slice {
+    pub fn new(volume: IsosurfaceHandle, plane: SlicePlane) -> Self {
+        Slice {
+            volume,
+            plane,
+            value_range: None,
+            colormap: Colormap::COOL_WARM,
+        }
+    }
+
+    pub fn with_value_range(mut self, low: f32, high: f32) -> Self {
+        self.value_range = Some([low, high]);
+        self
+    }
+
+    pub fn with_colormap(mut self, colormap: Colormap) -> Self {
+        self.colormap = colormap;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SliceHandle(pub(crate) usize);
@@ -0,0 +1,123 @@
+// Marching cubes over a volume of values, see isosurface.rs
+struct Surface {
+    model: mat4x4<f32>,
+    color: vec4<f32>,
+    min: vec3<f32>,
+    isovalue: f32,
+    max: vec3<f32>,
+    max_triangles: u32,
+    size: vec3<u32>,
+}
+@group(0) @binding(0)
+var<uniform> surface: Surface;
+@group(0) @binding(1)
+var values: texture_3d<f32>;
+// For every case: the triangle count, then three edges per triangle, CASE_SIZE entries
+@group(0) @binding(2)
+var<storage, read> cases: array<u32>;
+
+struct Vertex {
+    position: vec4<f32>,
+    normal: vec4<f32>,
+}
+@group(0) @binding(3)
+var<storage, read_write> vertices: array<Vertex>;
+
+// Laid out like wgpu::util::DrawIndirectArgs
+struct Draw {
+    vertex_count: atomic<u32>,
+    instance_count: u32,
+    first_vertex: u32,
+    first_instance: u32,
+}
+@group(0) @binding(4)
+var<storage, read_write> draw: Draw;
+
+const CASE_SIZE: u32 = 16u;
+// The same as EDGES in isosurface.rs, corners are the bits of their index: 1 is +X, 2 is +Y and 4 is +Z
+const EDGES = array<vec2<u32>, 12>(
+    vec2<u32>(0u, 1u),
+    vec2<u32>(2u, 3u),
+    vec2<u32>(4u, 5u),
+    vec2<u32>(6u, 7u),
+    vec2<u32>(0u, 2u),
+    vec2<u32>(1u, 3u),
+    vec2<u32>(4u, 6u),
+    vec2<u32>(5u, 7u),
+    vec2<u32>(0u, 4u),
+    vec2<u32>(1u, 5u),
+    vec2<u32>(2u, 6u),
+    vec2<u32>(3u, 7u),
+);
+
+@compute @workgroup_size(1)
+fn reset() {
+    atomicStore(&draw.vertex_count, 0u);
+    draw.instance_count = 1u;
+    draw.first_vertex = 0u;
+    draw.first_instance = 0u;
+}
+
+fn corner_offset(corner: u32) -> vec3<u32> {
+    return vec3<u32>(corner & 1u, (corner >> 1u) & 1u, (corner >> 2u) & 1u);
+}
+
+fn value_at(texel: vec3<i32>) -> f32 {
+    let clamped = clamp(texel, vec3<i32>(0), vec3<i32>(surface.size) - 1);
+    return textureLoad(values, clamped, 0).r;
+}
+
+// Towards higher values, in values per texel
+fn gradient(texel: vec3<i32>) -> vec3<f32> {
+    return vec3<f32>(
+        value_at(texel + vec3<i32>(1, 0, 0)) - value_at(texel - vec3<i32>(1, 0, 0)),
+        value_at(texel + vec3<i32>(0, 1, 0)) - value_at(texel - vec3<i32>(0, 1, 0)),
+        value_at(texel + vec3<i32>(0, 0, 1)) - value_at(texel - vec3<i32>(0, 0, 1)),
+    ) * 0.5;
+}
+
+@compute @workgroup_size(4, 4, 4)
+fn extract(@builtin(global_invocation_id) cell: vec3<u32>) {
+    if any(cell + 1u >= surface.size) {
+        return;
+    }
+    var corner_values: array<f32, 8>;
+    var case_index = 0u;
+    for (var corner = 0u; corner < 8u; corner++) {
+        corner_values[corner] = textureLoad(values, cell + corner_offset(corner), 0).r;
+        if corner_values[corner] > surface.isovalue {
+            case_index |= 1u << corner;
+        }
+    }
+    let entries = case_index * CASE_SIZE;
+    let count = cases[entries] * 3u;
+    if count == 0u {
+        return;
+    }
+    let first = atomicAdd(&draw.vertex_count, count);
+    if first + count > surface.max_triangles * 3u {
+        return;
+    }
+
+    let texel_size = (surface.max - surface.min) / vec3<f32>(surface.size - 1u);
+    var edges = EDGES;
+    for (var index = 0u; index < count; index++) {
+        let edge = edges[cases[entries + 1u + index]];
+        let a = corner_values[edge.x];
+        let b = corner_values[edge.y];
+        let t = clamp((surface.isovalue - a) / (b - a), 0.0, 1.0);
+        let texel_a = vec3<i32>(cell + corner_offset(edge.x));
+        let texel_b = vec3<i32>(cell + corner_offset(edge.y));
+        let texel = mix(vec3<f32>(texel_a), vec3<f32>(texel_b), t);
+        // Facing towards lower values, like the triangles
+        let normal = -mix(gradient(texel_a), gradient(texel_b), t) / texel_size;
+        vertices[first + index] = Vertex(vec4<f32>(surface.min + texel * texel_size, 1.0), vec4<f32>(normal, 0.0));
+    }
+}
+
+// Drops the count of the triangles that didn't fit
+@compute @workgroup_size(1)
+fn finish() {
+    let count = atomicLoad(&draw.vertex_count);
+    atomicStore(&draw.vertex_count, min(count, surface.max_triangles * 3u));
+}
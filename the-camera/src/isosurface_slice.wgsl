@@ -0,0 +1,128 @@
+// See clipping.rs, the same as in shader.wgsl
+struct Clipping {
+    planes: array<vec4<f32>, 4>,
+    cap_color: vec4<f32>,
+    count: u32,
+    caps: u32,
+}
+
+struct Audio {
+    bands: array<vec4<f32>, 4>,
+    level: f32,
+}
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    clipping: Clipping,
+    audio: Audio,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// One slice, see isosurface/pass.rs. Positions are in the volume's units.
+struct Slice {
+    model: mat4x4<f32>,
+    min: vec3<f32>,
+    low: f32,
+    max: vec3<f32>,
+    high: f32,
+    point: vec3<f32>,
+    normal: vec3<f32>,
+    size: vec3<u32>,
+}
+@group(1) @binding(0)
+var<uniform> slice: Slice;
+@group(1) @binding(1)
+var values: texture_3d<f32>;
+@group(1) @binding(2)
+var colormap: texture_2d<f32>;
+@group(1) @binding(3)
+var colormap_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) local_position: vec3<f32>,
+};
+
+// A square on the plane around the box's center, large enough to cover the box however it's turned. The fragments
+// outside of the box are discarded.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let corners = array<vec2<f32>, 6>(
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(-1.0, 1.0),
+    );
+    let normal = slice.normal;
+    let box_center = (slice.min + slice.max) * 0.5;
+    let center = box_center + normal * dot(slice.point - box_center, normal);
+    var helper = vec3<f32>(1.0, 0.0, 0.0);
+    if abs(normal.x) > 0.9 {
+        helper = vec3<f32>(0.0, 1.0, 0.0);
+    }
+    let u = normalize(cross(normal, helper));
+    let v = cross(normal, u);
+    let half_size = length(slice.max - slice.min) * 0.5;
+    let corner = corners[index];
+    let local_position = center + (u * corner.x + v * corner.y) * half_size;
+
+    let world_position = (slice.model * vec4<f32>(local_position, 1.0)).xyz;
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
+    out.local_position = local_position;
+    return out;
+}
+
+fn load(texel: vec3<u32>) -> f32 {
+    return textureLoad(values, texel, 0).r;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    // Slices can't be picked
+    @location(1) id: u32,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    let t = (in.local_position - slice.min) / (slice.max - slice.min);
+    // Filtered by hand, float textures can't be filtered everywhere
+    let last = slice.size - vec3<u32>(1u);
+    let texel = clamp(t, vec3<f32>(0.0), vec3<f32>(1.0)) * vec3<f32>(last);
+    let cell = min(vec3<u32>(texel), last - vec3<u32>(1u));
+    let f = texel - vec3<f32>(cell);
+    let x0 = mix(load(cell), load(cell + vec3<u32>(1u, 0u, 0u)), f.x);
+    let x1 = mix(load(cell + vec3<u32>(0u, 1u, 0u)), load(cell + vec3<u32>(1u, 1u, 0u)), f.x);
+    let x2 = mix(load(cell + vec3<u32>(0u, 0u, 1u)), load(cell + vec3<u32>(1u, 0u, 1u)), f.x);
+    let x3 = mix(load(cell + vec3<u32>(0u, 1u, 1u)), load(cell + vec3<u32>(1u, 1u, 1u)), f.x);
+    let value = mix(mix(x0, x1, f.y), mix(x2, x3, f.y), f.z);
+
+    var blend = 0.5;
+    if slice.high > slice.low {
+        blend = clamp((value - slice.low) / (slice.high - slice.low), 0.0, 1.0);
+    }
+    // Between the centers of the first and the last texel, see colormap.rs. Sampled before any fragment is
+    // discarded, while control flow is uniform.
+    let texels = f32(textureDimensions(colormap).x);
+    let color = textureSample(colormap, colormap_sampler, vec2<f32>((blend * (texels - 1.0) + 0.5) / texels, 0.5)).rgb;
+
+    for (var index = 0u; index < camera.clipping.count; index++) {
+        let plane = camera.clipping.planes[index];
+        if dot(plane.xyz, in.world_position) < plane.w {
+            discard;
+        }
+    }
+    if any(t < vec3<f32>(-1e-4)) || any(t > vec3<f32>(1.0 + 1e-4)) {
+        discard;
+    }
+    var out: FragmentOutput;
+    out.color = vec4<f32>(color, 1.0);
+    out.id = 0u;
+    return out;
+}
@@ -0,0 +1,360 @@
+//! A small job system for CPU work spread over threads. The engine records long draw lists, samples animations and
+//! the terrain tiles and writes screenshots on it, plugins get it in their [crate::plugin::PluginContext]. Culling
+//! happens on the GPU and assets are decoded by whoever reads them, neither goes through here.
+//!
+//! [JobSystem::spawn] hands `'static` jobs to long-lived workers. Every worker takes jobs from its own queue first and
+//! steals from the back of the others' when it runs dry, so a burst of jobs queued at once spreads over all of them.
+//! The loops over slices, [JobSystem::for_each] and [JobSystem::map], borrow their data and run on the same workers
+//! with the calling thread joining in. Threads claim chunks one after another, the faster ones end up doing more.
+//!
+//! There are no threads on wasm, everything runs on the calling thread right away.
+
+use std::{
+    collections::VecDeque,
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{channel, Receiver, TryRecvError},
+        Arc, Condvar, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+type Task = Box<dyn FnOnce() + Send>;
+
+/// The work of a loop over a slice, borrowed for as long as [JobSystem::run_scoped] makes sure it lives
+type ScopedWork = &'static (dyn Fn() + Sync);
+
+/// Chunks per thread in [JobSystem::for_each] and [JobSystem::map], more balance uneven work better
+const CHUNKS_PER_THREAD: usize = 4;
+
+struct Shared {
+    /// One per worker
+    queues: Vec<Mutex<VecDeque<Task>>>,
+    /// Jobs queued and not taken yet, workers sleep while there are none
+    queued: Mutex<usize>,
+    wake: Condvar,
+    shutdown: AtomicBool,
+    /// Queue the next spawned job goes to, round robin
+    next_queue: AtomicUsize,
+}
+
+impl Shared {
+    /// The front of the worker's own queue, otherwise the back of someone else's
+    fn take(&self, worker: usize) -> Option<Task> {
+        let count = self.queues.len();
+        let task = (0..count).find_map(|offset| {
+            let mut queue = self.queues[(worker + offset) % count]
+                .lock()
+                .expect("Job queue poisoned!");
+            if offset == 0 {
+                queue.pop_front()
+            } else {
+                queue.pop_back()
+            }
+        })?;
+        *self.queued.lock().expect("Job queue poisoned!") -= 1;
+        Some(task)
+    }
+
+    fn run_worker(&self, worker: usize) {
+        loop {
+            if let Some(task) = self.take(worker) {
+                task();
+                continue;
+            }
+            let mut queued = self.queued.lock().expect("Job queue poisoned!");
+            while *queued == 0 && !self.shutdown.load(Ordering::Acquire) {
+                queued = self.wake.wait(queued).expect("Job queue poisoned!");
+            }
+            if *queued == 0 {
+                return;
+            }
+        }
+    }
+}
+
+/// How far the workers helping with a loop over a slice got
+#[derive(Default)]
+struct ScopeState {
+    /// Helpers in the middle of the work
+    running: usize,
+    /// The calling thread is done, helpers starting now have nothing left to do and must not touch the work
+    closed: bool,
+    /// The first helper panic, passed on to the calling thread
+    panic: Option<Box<dyn std::any::Any + Send>>,
+}
+
+#[derive(Default)]
+struct Scope {
+    state: Mutex<ScopeState>,
+    done: Condvar,
+}
+
+impl Scope {
+    /// Runs `work` unless the calling thread closed the scope already
+    fn help(&self, work: ScopedWork) {
+        {
+            let mut state = self.state.lock().expect("Job scope poisoned!");
+            if state.closed {
+                return;
+            }
+            state.running += 1;
+        }
+        let result = std::panic::catch_unwind(AssertUnwindSafe(work));
+        let mut state = self.state.lock().expect("Job scope poisoned!");
+        state.running -= 1;
+        if let Err(panic) = result {
+            state.panic.get_or_insert(panic);
+        }
+        drop(state);
+        self.done.notify_all();
+    }
+
+    /// Keeps helpers from starting and waits for the ones running, returning the first panic among them
+    fn close(&self) -> Option<Box<dyn std::any::Any + Send>> {
+        let mut state = self.state.lock().expect("Job scope poisoned!");
+        state.closed = true;
+        while state.running > 0 {
+            state = self.done.wait(state).expect("Job scope poisoned!");
+        }
+        state.panic.take()
+    }
+}
+
+/// The result of a job handed to [JobSystem::spawn]. Dropping it lets the job run to completion unobserved.
+pub struct Job<T> {
+    result: Receiver<std::thread::Result<T>>,
+}
+
+impl<T> Job<T> {
+    /// The result if the job is done, only returned once. Panics if the job did.
+    pub fn try_take(&self) -> Option<T> {
+        match self.result.try_recv() {
+            Ok(result) => Some(result.unwrap_or_else(|panic| std::panic::resume_unwind(panic))),
+            Err(TryRecvError::Empty | TryRecvError::Disconnected) => None,
+        }
+    }
+
+    /// Blocks until the job is done. Panics if the job did.
+    pub fn wait(self) -> T {
+        self.result
+            .recv()
+            .expect("Job was dropped before it finished!")
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+}
+
+/// Runs CPU work on a fixed number of threads, see the [module docs](self)
+pub struct JobSystem {
+    threads: usize,
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl JobSystem {
+    /// One thread per core, as far as the platform tells
+    pub fn default_threads() -> usize {
+        std::thread::available_parallelism().map_or(1, std::num::NonZero::get)
+    }
+
+    /// Starts `threads` workers, at least one. Ignored on wasm, where everything runs on the calling thread.
+    pub fn new(threads: usize) -> Self {
+        let threads = if cfg!(target_arch = "wasm32") {
+            1
+        } else {
+            threads.max(1)
+        };
+        let shared = Arc::new(Shared {
+            queues: (0..threads).map(|_| Mutex::new(VecDeque::new())).collect(),
+            queued: Mutex::new(0),
+            wake: Condvar::new(),
+            shutdown: AtomicBool::new(false),
+            next_queue: AtomicUsize::new(0),
+        });
+        let workers = if cfg!(target_arch = "wasm32") {
+            Vec::new()
+        } else {
+            (0..threads)
+                .map(|worker| {
+                    let shared = shared.clone();
+                    std::thread::Builder::new()
+                        .name(format!("job worker {worker}"))
+                        .spawn(move || shared.run_worker(worker))
+                        .expect("Failed to start job worker!")
+                })
+                .collect()
+        };
+
+        JobSystem {
+            threads,
+            shared,
+            workers,
+        }
+    }
+
+    /// Threads work is spread over
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Runs `job` on a worker. A panicking job doesn't take the worker down, it's passed on to whoever takes the
+    /// result.
+    pub fn spawn<T: Send + 'static>(&self, job: impl FnOnce() -> T + Send + 'static) -> Job<T> {
+        let (sender, result) = channel();
+        let task = move || {
+            let _ = sender.send(std::panic::catch_unwind(AssertUnwindSafe(job)));
+        };
+        if self.workers.is_empty() {
+            task();
+        } else {
+            self.queue(Box::new(task));
+        }
+        Job { result }
+    }
+
+    fn queue(&self, task: Task) {
+        let queue = self.shared.next_queue.fetch_add(1, Ordering::Relaxed) % self.threads;
+        // Counted while queueing, a worker taking the job right away waits for the count before taking it off again
+        let mut queued = self.shared.queued.lock().expect("Job queue poisoned!");
+        self.shared.queues[queue]
+            .lock()
+            .expect("Job queue poisoned!")
+            .push_back(task);
+        *queued += 1;
+        drop(queued);
+        self.shared.wake.notify_one();
+    }
+
+    /// Calls `f` on every item, spread over the threads. Returns once all calls did.
+    pub fn for_each<T: Send>(&self, items: &mut [T], f: impl Fn(&mut T) + Sync) {
+        let chunk_size = self.chunk_size(items.len());
+        let chunk_count = items.len().div_ceil(chunk_size);
+        let chunks = Mutex::new(items.chunks_mut(chunk_size));
+        self.run_scoped(chunk_count, || loop {
+            let Some(chunk) = chunks.lock().expect("Job chunks poisoned!").next() else {
+                return;
+            };
+            chunk.iter_mut().for_each(&f);
+        });
+    }
+
+    /// Calls `f` on every item, spread over the threads, and collects the results in the order of the items
+    pub fn map<T: Sync, R: Send>(&self, items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+        let mut results: Vec<Option<R>> = (0..items.len()).map(|_| None).collect();
+        let chunk_size = self.chunk_size(items.len());
+        let chunks = Mutex::new(items.chunks(chunk_size).zip(results.chunks_mut(chunk_size)));
+        self.run_scoped(items.len().div_ceil(chunk_size), || loop {
+            let Some((items, results)) = chunks.lock().expect("Job chunks poisoned!").next() else {
+                return;
+            };
+            for (item, result) in items.iter().zip(results) {
+                *result = Some(f(item));
+            }
+        });
+        results
+            .into_iter()
+            .map(|result| result.expect("Every item was mapped!"))
+            .collect()
+    }
+
+    fn chunk_size(&self, len: usize) -> usize {
+        len.div_ceil(self.threads * CHUNKS_PER_THREAD).max(1)
+    }
+
+    /// Runs `work` on the calling thread and as many workers more as there are chunks for them. `work` claims chunks
+    /// until there are none left, so once the calling thread's call returns only the helpers already running have any
+    /// left, and the ones starting later do nothing. That also keeps loops started from inside jobs from waiting on
+    /// workers busy with something else.
+    fn run_scoped(&self, chunks: usize, work: impl Fn() + Sync) {
+        let helpers = self.threads.min(chunks).saturating_sub(1);
+        if helpers == 0 || self.workers.is_empty() {
+            work();
+            return;
+        }
+        let work: &(dyn Fn() + Sync) = &work;
+        // SAFETY: Helpers only call the work between registering as running in an open scope and unregistering, and
+        // this doesn't return before closing the scope and waiting for the running ones, unwinding or not
+        let work: ScopedWork = unsafe { std::mem::transmute(work) };
+        let scope = Arc::new(Scope::default());
+        for _ in 0..helpers {
+            let scope = scope.clone();
+            self.queue(Box::new(move || scope.help(work)));
+        }
+        let result = std::panic::catch_unwind(AssertUnwindSafe(work));
+        let helper_panic = scope.close();
+        if let Err(panic) = result {
+            std::panic::resume_unwind(panic);
+        }
+        if let Some(panic) = helper_panic {
+            std::panic::resume_unwind(panic);
+        }
+    }
+}
+
+impl Drop for JobSystem {
+    /// Lets the workers finish the queued jobs, then stops them
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Release);
+        {
+            // Taking the lock makes sure no worker is between checking the flag and going to sleep
+            let _queued = self.shared.queued.lock().expect("Job queue poisoned!");
+            self.shared.wake.notify_all();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loops_cover_every_item_in_order() {
+        let jobs = JobSystem::new(4);
+        let items: Vec<u32> = (0..1000).collect();
+        assert_eq!(
+            jobs.map(&items, |item| item * 2),
+            (0..2000).step_by(2).collect::<Vec<_>>()
+        );
+
+        let mut items = items;
+        jobs.for_each(&mut items, |item| *item += 1);
+        assert_eq!(items, (1..1001).collect::<Vec<_>>());
+        assert!(jobs.map(&[] as &[u32], |item| *item).is_empty());
+    }
+
+    #[test]
+    fn loops_inside_jobs_finish() {
+        let jobs = Arc::new(JobSystem::new(2));
+        let spawned: Vec<_> = (0..8)
+            .map(|job| {
+                let jobs = jobs.clone();
+                jobs.clone().spawn(move || {
+                    let items: Vec<u64> = (0..100).map(|item| item * job).collect();
+                    jobs.map(&items, |item| item + 1).iter().sum::<u64>()
+                })
+            })
+            .collect();
+        for (job, spawned) in spawned.into_iter().enumerate() {
+            assert_eq!(spawned.wait(), 4950 * job as u64 + 100);
+        }
+    }
+
+    #[test]
+    fn panics_reach_the_caller() {
+        let jobs = JobSystem::new(4);
+        let items: Vec<u32> = (0..100).collect();
+        let panicked = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            jobs.map(&items, |&item| assert!(item != 70))
+        }));
+        assert!(panicked.is_err());
+        // The workers survive it
+        assert_eq!(jobs.map(&items, |item| item + 1)[99], 100);
+        assert!(
+            std::panic::catch_unwind(AssertUnwindSafe(|| jobs.spawn(|| panic!()).wait())).is_err()
+        );
+    }
+}
@@ -0,0 +1,47 @@
+//! The passes of subsystems most scenes don't use, cloth or terrains for example, are only created once something is
+//! added to them, so engines that never need them, like headless thumbnail renders, don't compile their pipelines.
+
+/// A pass created on first use with [LazyPass::get_or_create]
+#[derive(Default)]
+pub(crate) enum LazyPass<P> {
+    /// Nothing needed it yet
+    #[default]
+    Pending,
+    /// The device can't run it
+    Unsupported,
+    Created(P),
+}
+
+impl<P> LazyPass<P> {
+    pub fn get(&self) -> Option<&P> {
+        match self {
+            LazyPass::Created(pass) => Some(pass),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut P> {
+        match self {
+            LazyPass::Created(pass) => Some(pass),
+            _ => None,
+        }
+    }
+
+    /// Creates the pass with `create` the first time, `create` returning None where the device can't run it. It
+    /// isn't tried again on the same device then.
+    pub fn get_or_create(&mut self, create: impl FnOnce() -> Option<P>) -> Option<&mut P> {
+        if matches!(self, LazyPass::Pending) {
+            *self = create().map_or(LazyPass::Unsupported, LazyPass::Created);
+        }
+        self.get_mut()
+    }
+
+    /// Creates the pass again with `create` on a new device if anything needed it on the old one, which gets to try
+    /// again where the old device couldn't run it. Lost devices aren't replaced on the web.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(&mut self, create: impl FnOnce() -> Option<P>) {
+        if !matches!(self, LazyPass::Pending) {
+            *self = create().map_or(LazyPass::Unsupported, LazyPass::Created);
+        }
+    }
+}
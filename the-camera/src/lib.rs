@@ -0,0 +1,154 @@
+// These block the thread, spawn new ones or load native libraries, none of which the browser's main thread can
+#[cfg(all(feature = "cpal", target_arch = "wasm32"))]
+compile_error!("The `cpal` feature captures audio through the system's native APIs and is only available natively");
+#[cfg(all(feature = "hot-reload", target_arch = "wasm32"))]
+compile_error!("The `hot-reload` feature watches files on disk and is only available natively");
+#[cfg(all(feature = "parallel-encoding", target_arch = "wasm32"))]
+compile_error!("The `parallel-encoding` feature records the main pass on threads and is only available natively");
+#[cfg(all(feature = "xr", target_arch = "wasm32"))]
+compile_error!(
+    "The `xr` feature talks to the OpenXR runtime through Vulkan and is only available natively"
+);
+
+use winit::event_loop::{ControlFlow, EventLoop};
+
+use crate::{
+    app::App,
+    config::{EngineConfig, DEFAULT_CONFIG_PATH},
+    render_engine::RenderEngine,
+};
+
+pub mod animation;
+pub mod annotation;
+mod app;
+pub mod assets;
+pub mod atmosphere;
+pub mod audio;
+pub mod background;
+pub mod boids;
+pub mod camera;
+pub mod clipping;
+pub mod cloth;
+pub mod colormap;
+pub mod config;
+pub mod console;
+pub mod debug;
+mod debug_hud;
+pub mod depth_pyramid;
+mod device_lost;
+#[cfg(all(feature = "dmabuf", target_os = "linux"))]
+pub mod dmabuf;
+pub mod dynamic_resolution;
+#[cfg(feature = "hecs")]
+pub mod ecs;
+#[cfg(feature = "egui")]
+pub mod egui_pass;
+pub mod events;
+pub mod external_surface;
+#[cfg(feature = "fbx")]
+pub mod fbx;
+#[cfg(all(feature = "ffi", not(target_arch = "wasm32")))]
+pub mod ffi;
+pub mod flipbook;
+pub mod frame;
+pub mod frame_pacing;
+pub mod gizmo;
+mod global_bindings;
+pub mod gltf;
+pub mod heatmap;
+#[cfg(feature = "hot-reload")]
+mod hot_reload;
+pub mod input;
+pub mod irradiance_volume;
+pub mod isosurface;
+pub mod jobs;
+mod lazy_pass;
+pub mod logging;
+mod main_pass;
+mod material_bindings;
+pub mod measure;
+pub mod mesh;
+#[cfg(feature = "meshlets")]
+pub mod meshlet;
+pub mod model;
+mod object_bindings;
+mod occlusion;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod offscreen;
+mod outline;
+mod overlay;
+pub mod physics;
+pub mod platform;
+pub mod plot;
+pub mod plugin;
+pub mod point_cloud;
+pub mod probe;
+pub mod profiler;
+#[cfg(all(feature = "python", not(target_arch = "wasm32")))]
+mod python;
+pub mod recording;
+pub mod reflection_probe;
+pub mod render_engine;
+pub mod render_engine_builder;
+pub mod render_target;
+#[cfg(feature = "rhai")]
+pub mod rhai_script;
+mod rulers;
+pub mod scene;
+pub mod screenshot;
+pub mod script;
+pub mod selection;
+pub mod simulation;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod snapshot;
+#[cfg(feature = "sync")]
+pub mod sync;
+pub mod terrain;
+pub mod texture;
+pub mod upscaling;
+pub mod usd;
+pub mod vector_field;
+pub mod vegetation;
+mod vertex_pulling;
+#[cfg(feature = "ffmpeg")]
+pub mod video;
+mod view_cube;
+pub mod viewport;
+pub mod voxel_grid;
+pub mod water;
+pub mod wgpu_utils;
+pub mod xr;
+
+/// Runs the demo app: an orbit camera around a cube, N opens another window, B switches the background, F12 saves a
+/// screenshot, F9 toggles recording and M cycles through measuring distances, angles and nothing. The numpad switches
+/// between standard views and projections, see [render_engine::RenderEngine::input], and the backtick key opens the
+/// [console]. OBJ models dropped onto a window or loaded from the console are added to the scene and PNG panoramas
+/// become the skybox. On the web the canvas is appended to the page body, call
+/// this once the wasm module is loaded. Settings are taken from an engine.toml next to it, if there is one.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen::prelude::wasm_bindgen)]
+pub fn run() {
+    #[cfg(target_arch = "wasm32")]
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+    logging::init();
+
+    let event_loop = EventLoop::<RenderEngine>::with_user_event()
+        .build()
+        .expect("Failed to create event loop!");
+    // Sleep until there's input or the engine asks for a redraw, see App::about_to_wait
+    event_loop.set_control_flow(ControlFlow::Wait);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let config = pollster::block_on(EngineConfig::load_or_default(DEFAULT_CONFIG_PATH));
+        let mut app = App::new(&event_loop, config);
+        let _ = event_loop.run_app(&mut app);
+    }
+    // The browser owns the event loop, winit hands control back to it right away. The config has to be fetched
+    // first, so the app only starts once it arrived.
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_futures::spawn_local(async move {
+        let config = EngineConfig::load_or_default(DEFAULT_CONFIG_PATH).await;
+        let app = App::new(&event_loop, config);
+        winit::platform::web::EventLoopExtWebSys::spawn_app(event_loop, app);
+    });
+}
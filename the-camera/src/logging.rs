@@ -0,0 +1,64 @@
+//! The engine reports through [tracing]: events for what happened, spans around initialization, pipeline creation,
+//! asset loads and every pass of a frame. Nothing shows up until a subscriber is installed, either the app's own or
+//! the one set up by [init].
+
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+/// Used when `RUST_LOG` isn't set. wgpu's own logging is noisy below warnings.
+pub const DEFAULT_FILTER: &str = "info,wgpu_core=warn,wgpu_hal=warn,naga=warn";
+
+/// Prints events and span timings to stderr natively and to the browser console on the web, filtered by `RUST_LOG`
+/// (e.g. `RUST_LOG=the_camera=debug` to see per pass timings) or [DEFAULT_FILTER]. wgpu's `log` records are
+/// forwarded as well.
+///
+/// Does nothing if a subscriber was installed before.
+pub fn init() {
+    init_with_filter(DEFAULT_FILTER);
+}
+
+/// Like [init], with `default_filter` in the `RUST_LOG` syntax instead of [DEFAULT_FILTER]
+pub fn init_with_filter(default_filter: &str) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_filter));
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        // Closing a span reports how long it took
+        .with_span_events(FmtSpan::CLOSE);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let _ = subscriber.with_writer(std::io::stderr).try_init();
+    // The browser has no clock for the timestamps and no terminal for colors
+    #[cfg(target_arch = "wasm32")]
+    let _ = subscriber
+        .without_time()
+        .with_ansi(false)
+        .with_writer(console::ConsoleWriter::default)
+        .try_init();
+}
+
+#[cfg(target_arch = "wasm32")]
+mod console {
+    use std::io;
+
+    /// Collects one formatted event and logs it to the browser console when dropped
+    #[derive(Default)]
+    pub struct ConsoleWriter(Vec<u8>);
+
+    impl io::Write for ConsoleWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Drop for ConsoleWriter {
+        fn drop(&mut self) {
+            let line = String::from_utf8_lossy(&self.0);
+            web_sys::console::log_1(&line.trim_end().into());
+        }
+    }
+}
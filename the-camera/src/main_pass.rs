@@ -0,0 +1,344 @@
+use wgpu::{
+    BindGroup, CommandBuffer, Device, RenderPassTimestampWrites, RenderPipeline, TextureView,
+};
+
+use crate::{
+    background::BackgroundPass,
+    boids::BoidsPass,
+    cloth::ClothPass,
+    flipbook::GpuFlipbook,
+    frame::{Draw, DrawTexture},
+    irradiance_volume::IrradianceVolumePass,
+    isosurface::IsosurfacePass,
+    material_bindings::MaterialBindings,
+    mesh::MeshPool,
+    object_bindings::ObjectBindings,
+    occlusion::{OcclusionPass, OcclusionQueries},
+    point_cloud::{PointCloudPass, PointCloudView},
+    profiler::RenderStats,
+    render_target::RenderTarget,
+    simulation::SimulationPass,
+    terrain::{TerrainPass, TerrainView},
+    texture::GpuTexture,
+    vector_field::VectorFieldPass,
+    vegetation::{VegetationPass, VegetationView},
+    water::WaterPass,
+    wgpu_utils::debug_scope::GpuDebugScope,
+};
+
+/// With the `parallel-encoding` feature, draw lists shorter than this are still recorded on the calling thread since
+/// spawning threads would cost more than it saves
+#[cfg(feature = "parallel-encoding")]
+const MIN_DRAWS_PER_ENCODER: usize = 256;
+
+/// Everything needed to record the main pass of one window or render target
+pub(crate) struct MainPass<'a> {
+    /// Names the pass in graphics debuggers and profiles
+    pub label: &'a str,
+    pub device: &'a Device,
+    pub pipeline: &'a RenderPipeline,
+    pub meshes: &'a MeshPool,
+    pub object_bindings: &'a ObjectBindings,
+    pub material_bindings: &'a MaterialBindings,
+    pub render_targets: &'a [RenderTarget],
+    pub textures: &'a [GpuTexture],
+    pub flipbooks: &'a [GpuFlipbook],
+    #[cfg(feature = "ffmpeg")]
+    pub videos: &'a [crate::video::GpuVideo],
+    pub background: &'a BackgroundPass,
+    pub occlusion_pass: &'a OcclusionPass,
+    /// Draws the points in the last pass, None without point clouds
+    pub point_cloud_pass: Option<&'a PointCloudPass>,
+    /// The nodes the view picked, drawn in the last pass
+    pub point_clouds: Option<&'a PointCloudView>,
+    /// Draws every glyph and streamline set in the last pass, None without any
+    pub vector_field_pass: Option<&'a VectorFieldPass>,
+    /// Draws every isosurface in the last pass, None without any and where the device can't extract them
+    pub isosurface_pass: Option<&'a IsosurfacePass>,
+    /// Draws the particles of every simulation in the last pass, None without any and where the device can't run them
+    pub simulation_pass: Option<&'a SimulationPass>,
+    /// Draws every cloth in the last pass, None without any and where the device can't simulate it
+    pub cloth_pass: Option<&'a ClothPass>,
+    /// Draws the boids of every flock in the last pass, None where the device can't steer them
+    pub boids_pass: Option<&'a BoidsPass>,
+    /// Draws every water surface in the last pass, None without any
+    pub water_pass: Option<&'a WaterPass>,
+    /// Draws the terrains in the last pass, None without any
+    pub terrain_pass: Option<&'a TerrainPass>,
+    /// The tiles picked for this view, None without terrains
+    pub terrain: Option<&'a TerrainView>,
+    /// Draws the culled vegetation in the last pass, None without any and where the device can't cull it
+    pub vegetation_pass: Option<&'a VegetationPass>,
+    /// The blades culled for this view, None without vegetation
+    pub vegetation: Option<&'a VegetationView>,
+    /// Draws the spheres of the irradiance probes in the last pass when they're shown, None where the device can't
+    /// bake them and for the views baking and capturing probes
+    pub irradiance_volume_pass: Option<&'a IrradianceVolumePass>,
+    /// The view's queries, drawn at the end of the last pass
+    pub occlusion: &'a OcclusionQueries,
+    /// Records the chunks of long draw lists
+    #[cfg(feature = "parallel-encoding")]
+    pub jobs: &'a crate::jobs::JobSystem,
+    /// Binds the vertices at group 3 instead of a vertex buffer when the pipeline pulls them, see [crate::vertex_pulling]
+    pub vertex_pulling: Option<&'a BindGroup>,
+    /// The view's culled meshlet draws, for draws with [Draw::meshlets]
+    #[cfg(feature = "meshlets")]
+    pub meshlet_draws: Option<&'a crate::wgpu_utils::indirect::IndirectBuffer>,
+    pub color: &'a TextureView,
+    pub depth: &'a TextureView,
+    /// Receives the ID of each object drawn, see [crate::texture::Texture::ID_FORMAT]
+    pub ids: &'a TextureView,
+    pub globals: &'a BindGroup,
+    /// The render target being drawn into, objects sampling it are skipped since a texture can't be both
+    pub drawing_into: Option<usize>,
+    /// Written at the start of the first pass and the end of the last one when profiling
+    pub timestamps: Option<RenderPassTimestampWrites<'a>>,
+}
+
+impl MainPass<'_> {
+    /// Records `draws` into command buffers that have to be submitted in order, along with what was recorded
+    #[cfg(not(feature = "parallel-encoding"))]
+    pub fn encode_all(&self, draws: &[Draw]) -> (Vec<CommandBuffer>, RenderStats) {
+        let (command_buffer, stats) = self.encode(draws, true, true);
+        (vec![command_buffer], stats)
+    }
+
+    /// Records `draws` into command buffers that have to be submitted in order, along with what was recorded.
+    ///
+    /// Long draw lists are split into chunks recorded on the job threads, each into its own encoder and render pass.
+    /// Only the first pass clears the targets, the others load what the previous ones drew.
+    #[cfg(feature = "parallel-encoding")]
+    pub fn encode_all(&self, draws: &[Draw]) -> (Vec<CommandBuffer>, RenderStats) {
+        let chunk_size = draws
+            .len()
+            .div_ceil(self.jobs.threads())
+            .max(MIN_DRAWS_PER_ENCODER);
+        if draws.len() <= chunk_size {
+            let (command_buffer, stats) = self.encode(draws, true, true);
+            return (vec![command_buffer], stats);
+        }
+
+        let chunks: Vec<_> = draws.chunks(chunk_size).enumerate().collect();
+        let encoded = self.jobs.map(&chunks, |&(index, chunk)| {
+            self.encode(chunk, index == 0, index == chunks.len() - 1)
+        });
+        let mut stats = RenderStats::default();
+        let command_buffers = encoded
+            .into_iter()
+            .map(|(command_buffer, chunk_stats)| {
+                stats += chunk_stats;
+                command_buffer
+            })
+            .collect();
+        (command_buffers, stats)
+    }
+
+    /// Records `draws` in a render pass of their own. A clearing pass draws the background first, the first and last
+    /// pass write the timestamps.
+    fn encode(&self, draws: &[Draw], clear: bool, last: bool) -> (CommandBuffer, RenderStats) {
+        let mut stats = RenderStats::default();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Main Pass Encoder"),
+            });
+
+        {
+            let mut encoder = GpuDebugScope::new(&mut encoder, self.label);
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: self.color,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: if clear {
+                                wgpu::LoadOp::Clear(self.background.background().clear_color())
+                            } else {
+                                wgpu::LoadOp::Load
+                            },
+                            store: wgpu::StoreOp::Store,
+                        },
+                    }),
+                    // 0 marks pixels without an object
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: self.ids,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: if clear {
+                                wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT)
+                            } else {
+                                wgpu::LoadOp::Load
+                            },
+                            store: wgpu::StoreOp::Store,
+                        },
+                    }),
+                ],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    //attach depth texture to stencil attatchement of render pass
+                    view: self.depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: if clear {
+                            wgpu::LoadOp::Clear(1.0)
+                        } else {
+                            wgpu::LoadOp::Load
+                        },
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: self.occlusion.query_set().filter(|_| last),
+                timestamp_writes: self
+                    .timestamps
+                    .clone()
+                    .map(|timestamps| RenderPassTimestampWrites {
+                        beginning_of_pass_write_index: timestamps
+                            .beginning_of_pass_write_index
+                            .filter(|_| clear),
+                        end_of_pass_write_index: timestamps
+                            .end_of_pass_write_index
+                            .filter(|_| last),
+                        ..timestamps
+                    })
+                    // Passes in the middle of a split draw list write neither
+                    .filter(|_| clear || last),
+            });
+
+            if clear {
+                let mut render_pass = GpuDebugScope::new(&mut render_pass, "background");
+                self.background
+                    .draw(&mut render_pass, self.globals, self.textures, &mut stats);
+            }
+            {
+                let mut render_pass = GpuDebugScope::new(&mut render_pass, "objects");
+                render_pass.set_bind_group(0, self.globals, &[]);
+
+                render_pass.set_pipeline(self.pipeline);
+                stats.pipeline_switches += 1;
+                stats.bind_group_switches += 1;
+                match self.vertex_pulling {
+                    Some(vertices) => {
+                        render_pass.set_bind_group(3, vertices, &[]);
+                        stats.bind_group_switches += 1;
+                    }
+                    None => render_pass.set_vertex_buffer(0, self.meshes.vertex_buffer().slice(..)),
+                }
+                render_pass.set_index_buffer(
+                    self.meshes.index_buffer().slice(..),
+                    wgpu::IndexFormat::Uint16,
+                );
+                for draw in draws {
+                    if self.drawing_into.is_some_and(|target| {
+                        draw.texture == Some(DrawTexture::RenderTarget(target))
+                    }) {
+                        continue;
+                    }
+                    if draw
+                        .occlusion
+                        .is_some_and(|proxy| self.occlusion.is_occluded(proxy.renderable))
+                    {
+                        continue;
+                    }
+                    let Some(mesh) = self.meshes.get(draw.mesh) else {
+                        continue;
+                    };
+                    render_pass.set_bind_group(
+                        1,
+                        self.object_bindings.bind_group(),
+                        &[self.object_bindings.offset(draw.slot)],
+                    );
+                    let material = match draw.texture {
+                        Some(DrawTexture::RenderTarget(target)) => {
+                            &self.render_targets[target].material_bind_group
+                        }
+                        Some(DrawTexture::Image(texture)) => &self.textures[texture].bind_group,
+                        Some(DrawTexture::Flipbook(flipbook, frame)) => {
+                            self.flipbooks[flipbook].bind_group(frame)
+                        }
+                        #[cfg(feature = "ffmpeg")]
+                        Some(DrawTexture::Video(video)) => self.videos[video].bind_group(),
+                        None => self.material_bindings.white_bind_group(),
+                    };
+                    render_pass.set_bind_group(2, material, &[]);
+                    stats.bind_group_switches += 2;
+                    // Counts every meshlet, the CPU doesn't know which ones were culled
+                    #[cfg(feature = "meshlets")]
+                    if let (Some((first, count)), Some(meshlet_draws)) =
+                        (draw.meshlets, self.meshlet_draws)
+                    {
+                        let (first, count) = (first as usize, count as usize);
+                        meshlet_draws.draw_range(&mut render_pass, first..first + count);
+                        stats.draw(mesh.index_range().len() as u32, 1);
+                        continue;
+                    }
+                    render_pass.draw_indexed(mesh.index_range(), mesh.base_vertex(), 0..1);
+                    stats.draw(mesh.index_range().len() as u32, 1);
+                }
+            }
+
+            if let (true, Some(point_cloud_pass)) = (last, self.point_cloud_pass) {
+                let mut render_pass = GpuDebugScope::new(&mut render_pass, "point clouds");
+                point_cloud_pass.draw(
+                    &mut render_pass,
+                    self.point_clouds,
+                    self.globals,
+                    &mut stats,
+                );
+            }
+            if let (true, Some(terrain_pass)) = (last, self.terrain_pass) {
+                let mut render_pass = GpuDebugScope::new(&mut render_pass, "terrains");
+                terrain_pass.draw(&mut render_pass, self.terrain, self.globals, &mut stats);
+            }
+            if let (true, Some(vegetation_pass)) = (last, self.vegetation_pass) {
+                let mut render_pass = GpuDebugScope::new(&mut render_pass, "vegetation");
+                vegetation_pass.draw(&mut render_pass, self.vegetation, self.globals, &mut stats);
+            }
+            if let (true, Some(irradiance_volume_pass)) = (last, self.irradiance_volume_pass) {
+                let mut render_pass = GpuDebugScope::new(&mut render_pass, "irradiance probes");
+                irradiance_volume_pass.draw(&mut render_pass, self.globals, &mut stats);
+            }
+            if let (true, Some(vector_field_pass)) = (last, self.vector_field_pass) {
+                let mut render_pass = GpuDebugScope::new(&mut render_pass, "vector fields");
+                vector_field_pass.draw(&mut render_pass, self.globals, &mut stats);
+            }
+            if let (true, Some(isosurface_pass)) = (last, self.isosurface_pass) {
+                let mut render_pass = GpuDebugScope::new(&mut render_pass, "isosurfaces");
+                isosurface_pass.draw(&mut render_pass, self.globals, &mut stats);
+            }
+            if let (true, Some(simulation_pass)) = (last, self.simulation_pass) {
+                let mut render_pass = GpuDebugScope::new(&mut render_pass, "simulations");
+                simulation_pass.draw(&mut render_pass, self.globals, &mut stats);
+            }
+            if let (true, Some(cloth_pass)) = (last, self.cloth_pass) {
+                let mut render_pass = GpuDebugScope::new(&mut render_pass, "cloth");
+                cloth_pass.draw(&mut render_pass, self.globals, &mut stats);
+            }
+            if let (true, Some(boids_pass)) = (last, self.boids_pass) {
+                let mut render_pass = GpuDebugScope::new(&mut render_pass, "boids");
+                boids_pass.draw(&mut render_pass, self.globals, &mut stats);
+            }
+            if let (true, Some(water_pass)) = (last, self.water_pass) {
+                let mut render_pass = GpuDebugScope::new(&mut render_pass, "water");
+                water_pass.draw(
+                    &mut render_pass,
+                    self.globals,
+                    self.textures,
+                    self.material_bindings,
+                    &mut stats,
+                );
+            }
+            // Boxes are tested once everything that could hide them was drawn
+            if last {
+                let mut render_pass = GpuDebugScope::new(&mut render_pass, "occlusion queries");
+                self.occlusion_pass.draw(
+                    &mut render_pass,
+                    self.occlusion,
+                    self.object_bindings,
+                    &mut stats,
+                );
+            }
+        }
+
+        (encoder.finish(), stats)
+    }
+}
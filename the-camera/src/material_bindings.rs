@@ -0,0 +1,105 @@
+use crate::{
+    irradiance_volume::IrradianceTextures,
+    reflection_probe::ProbeTextures,
+    texture::Texture,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        resource_cache::SamplerCache,
+    },
+};
+
+/// The texture a material samples, bound at group 2 along with the reflection probes and the irradiance volume.
+/// Materials without a texture bind a white texel.
+pub struct MaterialBindings {
+    bind_group_layout: BindGroupLayoutWithDesc,
+    white_bind_group: wgpu::BindGroup,
+    probes: ProbeTextures,
+    irradiance: IrradianceTextures,
+}
+
+impl MaterialBindings {
+    /// `format` has to be the engine's swapchain format, see [ProbeTextures::new]
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        samplers: &SamplerCache,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let bind_group_layout = IrradianceTextures::layout(ProbeTextures::layout(
+            BindGroupLayoutBuilder::new()
+                .next_binding_fragment(binding_types::texture2D())
+                .named("t_material")
+                .next_binding_fragment(binding_types::sampler(wgpu::SamplerBindingType::Filtering))
+                .named("s_material"),
+        ))
+        .create(device, "Material Bind Group");
+
+        let probes = ProbeTextures::new(device, samplers, format);
+        let irradiance = IrradianceTextures::new(device, samplers);
+        let white = Texture::create_white(device, queue, samplers);
+        let white_bind_group = irradiance
+            .bind(
+                probes.bind(
+                    BindGroupBuilder::new(&bind_group_layout)
+                        .texture(&white.view)
+                        .sampler(&white.sampler),
+                ),
+            )
+            .create(device, "White Material Bind Group");
+
+        MaterialBindings {
+            bind_group_layout,
+            white_bind_group,
+            probes,
+            irradiance,
+        }
+    }
+
+    pub fn create_bind_group(&self, device: &wgpu::Device, texture: &Texture) -> wgpu::BindGroup {
+        self.create_bind_group_with_view(device, &texture.view, &texture.sampler)
+    }
+
+    /// For a view of part of a texture, e.g. one layer of an array texture
+    pub fn create_bind_group_with_view(
+        &self,
+        device: &wgpu::Device,
+        view: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        self.irradiance
+            .bind(
+                self.probes.bind(
+                    BindGroupBuilder::new(&self.bind_group_layout)
+                        .texture(view)
+                        .sampler(sampler),
+                ),
+            )
+            .create(device, "Material Bind Group")
+    }
+
+    pub fn bind_group_layouts(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout.layout
+    }
+
+    /// Bound by materials without a texture
+    pub fn white_bind_group(&self) -> &wgpu::BindGroup {
+        &self.white_bind_group
+    }
+
+    pub(crate) fn probes(&self) -> &ProbeTextures {
+        &self.probes
+    }
+
+    pub(crate) fn probes_mut(&mut self) -> &mut ProbeTextures {
+        &mut self.probes
+    }
+
+    pub(crate) fn irradiance(&self) -> &IrradianceTextures {
+        &self.irradiance
+    }
+
+    pub(crate) fn irradiance_mut(&mut self) -> &mut IrradianceTextures {
+        &mut self.irradiance
+    }
+}
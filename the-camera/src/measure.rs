@@ -0,0 +1,281 @@
+//! Measuring distances and angles between points clicked on the scene, for inspecting models.
+//!
+//! While the tool is on, left clicks place points where they hit the geometry instead of changing the
+//! [crate::selection]. Two points make a distance and three an angle at the middle one, drawn over every window with
+//! a label of the value. The next click starts over. Points are found through
+//! [crate::render_engine::RenderEngine::read_depth_at], where the depth buffer can't be read (WebGL2) clicks place
+//! nothing.
+
+use std::collections::HashMap;
+
+use cgmath::{Deg, InnerSpace, Rad, Vector3};
+use winit::{event::MouseButton, window::WindowId};
+
+use crate::{
+    input::InputEvent,
+    overlay::{OverlayGeometry, OverlayView},
+    selection::CLICK_DISTANCE,
+    wgpu_utils::readback::ReadbackFuture,
+};
+
+/// Half the size of the point markers, in pixels
+const MARKER_SIZE: f32 = 4.0;
+/// Labels are drawn this many pixels above what they measure
+const LABEL_OFFSET: f32 = 16.0;
+const ARC_SEGMENTS: usize = 16;
+/// Of the shorter arm
+const ARC_RADIUS: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MeasureMode {
+    /// Between two points
+    #[default]
+    Distance,
+    /// At the second of three points, between the lines to the other two
+    Angle,
+}
+
+impl MeasureMode {
+    pub fn point_count(self) -> usize {
+        match self {
+            MeasureMode::Distance => 2,
+            MeasureMode::Angle => 3,
+        }
+    }
+}
+
+/// See the [module docs](self), off until [MeasureTool::set_enabled]
+pub struct MeasureTool {
+    enabled: bool,
+    mode: MeasureMode,
+    points: Vec<Vector3<f32>>,
+    pub color: [f32; 4],
+    cursors: HashMap<WindowId, [f32; 2]>,
+    press: Option<(WindowId, [f32; 2])>,
+    /// Clicks waiting for their depth, each with where it was and the view it was seen through
+    clicks: Vec<(ReadbackFuture<f32>, [f32; 2], OverlayView)>,
+}
+
+impl Default for MeasureTool {
+    fn default() -> Self {
+        MeasureTool {
+            enabled: false,
+            mode: MeasureMode::default(),
+            points: Vec::new(),
+            color: [1.0, 0.85, 0.2, 1.0],
+            cursors: HashMap::new(),
+            press: None,
+            clicks: Vec::new(),
+        }
+    }
+}
+
+impl MeasureTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turning the tool off clears the measurement
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.clear();
+        }
+    }
+
+    pub fn mode(&self) -> MeasureMode {
+        self.mode
+    }
+
+    /// Starts over measuring the new way
+    pub fn set_mode(&mut self, mode: MeasureMode) {
+        if mode != self.mode {
+            self.mode = mode;
+            self.clear();
+        }
+    }
+
+    /// Placed so far, at most [MeasureMode::point_count] of them
+    pub fn points(&self) -> &[Vector3<f32>] {
+        &self.points
+    }
+
+    /// Whether every point of the measurement is placed
+    pub fn is_complete(&self) -> bool {
+        self.points.len() == self.mode.point_count()
+    }
+
+    /// Between the two points of a complete distance measurement, in world units
+    pub fn distance(&self) -> Option<f32> {
+        match (self.mode, self.points.as_slice()) {
+            (MeasureMode::Distance, [a, b]) => Some((b - a).magnitude()),
+            _ => None,
+        }
+    }
+
+    /// At the middle point of a complete angle measurement
+    pub fn angle(&self) -> Option<Deg<f32>> {
+        match (self.mode, self.points.as_slice()) {
+            (MeasureMode::Angle, [a, vertex, b]) => Some((a - vertex).angle(b - vertex).into()),
+            _ => None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.press = None;
+        self.clicks.clear();
+    }
+
+    /// Places a point, starting over if the measurement was complete
+    pub fn add_point(&mut self, point: Vector3<f32>) {
+        if self.is_complete() {
+            self.points.clear();
+        }
+        self.points.push(point);
+    }
+
+    /// Tracks left clicks, returning where one happened while the tool is on. The engine reads the depth there and
+    /// hands it to [MeasureTool::add_click].
+    pub(crate) fn process_event(
+        &mut self,
+        window_id: WindowId,
+        event: &InputEvent,
+    ) -> Option<[f32; 2]> {
+        match event {
+            InputEvent::CursorMoved(cursor) => {
+                self.cursors.insert(window_id, *cursor);
+            }
+            InputEvent::CursorLeft => {
+                self.cursors.remove(&window_id);
+            }
+            InputEvent::MouseButton {
+                button: MouseButton::Left,
+                pressed: true,
+            } => {
+                self.press = self
+                    .cursors
+                    .get(&window_id)
+                    .map(|&start| (window_id, start));
+            }
+            InputEvent::MouseButton {
+                button: MouseButton::Left,
+                pressed: false,
+            } => {
+                let (press_window, start) = self.press.take()?;
+                let end = *self.cursors.get(&window_id)?;
+                let moved = (end[0] - start[0]).hypot(end[1] - start[1]);
+                // Dragging turns the camera, it doesn't measure
+                if self.enabled && press_window == window_id && moved <= CLICK_DISTANCE {
+                    return Some(end);
+                }
+            }
+            _ => (),
+        }
+        None
+    }
+
+    /// Waits for the depth at a click `cursor` in a window seen through `view`
+    pub(crate) fn add_click(
+        &mut self,
+        depth: ReadbackFuture<f32>,
+        cursor: [f32; 2],
+        view: OverlayView,
+    ) {
+        self.clicks.push((depth, cursor, view));
+    }
+
+    /// Whether the next click's depth arrived, to be placed in the next update
+    pub(crate) fn has_click_ready(&self) -> bool {
+        self.clicks
+            .first()
+            .is_some_and(|(depth, ..)| depth.is_ready())
+    }
+
+    /// Places the clicks whose depth arrived, in the order they happened
+    pub(crate) fn resolve_clicks(&mut self) {
+        while let Some((depth, ..)) = self.clicks.first_mut() {
+            let Some(depth) = depth.try_take() else {
+                break;
+            };
+            let (_, cursor, view) = self.clicks.remove(0);
+            // Nothing was drawn there
+            if let Some(depth) = depth.filter(|&depth| depth < 1.0) {
+                self.add_point(view.unproject(cursor, depth));
+            }
+        }
+    }
+
+    /// The points, the lines between them and the value, laid out for the window `view` looks through
+    pub(crate) fn geometry(&self, view: &OverlayView) -> OverlayGeometry {
+        let mut geometry = OverlayGeometry::default();
+        let (right, up) = (view.right(), view.up());
+        for &point in &self.points {
+            let size = MARKER_SIZE * view.pixel_size(point);
+            geometry.cube(
+                point,
+                [right * size, up * size, right.cross(up) * size],
+                self.color,
+            );
+        }
+        for pair in self.points.windows(2) {
+            geometry.line(pair[0], pair[1], self.color);
+        }
+
+        let label = |geometry: &mut OverlayGeometry, position: Vector3<f32>, text: &str| {
+            let above = position + up * (LABEL_OFFSET * view.pixel_size(position));
+            geometry.text(view, above, text, self.color);
+        };
+        if let Some(distance) = self.distance() {
+            let middle = (self.points[0] + self.points[1]) / 2.0;
+            label(&mut geometry, middle, &format!("{distance:.3}"));
+        }
+        if let Some(angle) = self.angle() {
+            let (a, vertex, b) = (self.points[0], self.points[1], self.points[2]);
+            arc(&mut geometry, vertex, a - vertex, b - vertex, self.color);
+            label(&mut geometry, vertex, &format!("{:.1} DEG", angle.0));
+        }
+        geometry
+    }
+
+    /// Forgets a closed window
+    pub(crate) fn remove_window(&mut self, window_id: WindowId) {
+        self.cursors.remove(&window_id);
+        if self
+            .press
+            .is_some_and(|(press_window, _)| press_window == window_id)
+        {
+            self.press = None;
+        }
+    }
+}
+
+/// From arm `a` to arm `b` around `vertex`
+fn arc(
+    geometry: &mut OverlayGeometry,
+    vertex: Vector3<f32>,
+    a: Vector3<f32>,
+    b: Vector3<f32>,
+    color: [f32; 4],
+) {
+    let radius = ARC_RADIUS * a.magnitude().min(b.magnitude());
+    let Rad(angle) = a.angle(b);
+    let from = a.normalize();
+    // Towards b in the plane of both arms, there is none for arms on one line
+    let towards = b.normalize() - from * angle.cos();
+    if towards.magnitude2() < 1e-6 || radius <= 0.0 {
+        return;
+    }
+    let towards = towards.normalize();
+    let point = |segment: usize| {
+        let t = segment as f32 / ARC_SEGMENTS as f32 * angle;
+        vertex + (from * t.cos() + towards * t.sin()) * radius
+    };
+    for segment in 0..ARC_SEGMENTS {
+        geometry.line(point(segment), point(segment + 1), color);
+    }
+}
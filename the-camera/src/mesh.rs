@@ -1,11 +1,36 @@
+use std::ops::Range;
+
+use crate::wgpu_utils::buffer_arena::{ArenaAllocation, BufferArena};
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
     position: [f32; 3],
     color: [f32; 3],
+    tex_coords: [f32; 2],
 }
 
 impl Vertex {
+    pub fn new(position: [f32; 3], color: [f32; 3], tex_coords: [f32; 2]) -> Self {
+        Vertex {
+            position,
+            color,
+            tex_coords,
+        }
+    }
+
+    pub fn position(&self) -> [f32; 3] {
+        self.position
+    }
+
+    pub fn color(&self) -> [f32; 3] {
+        self.color
+    }
+
+    pub fn tex_coords(&self) -> [f32; 2] {
+        self.tex_coords
+    }
+
     pub fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
@@ -21,47 +46,364 @@ impl Vertex {
                     shader_location: 1,
                     format: wgpu::VertexFormat::Float32x3,
                 },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 6]>() as wgpu::BufferAddress,
+                    shader_location: 2,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
             ],
         }
     }
 }
 
+/// Texture coordinates are projected along the z axis, so only the front and back faces show a texture undistorted
 pub const VERTICES: &[Vertex] = &[
     Vertex {
         position: [-0.5, 0.5, 0.5],
         color: [1.0, 0.0, 0.0],
+        tex_coords: [0.0, 0.0],
     },
     Vertex {
         position: [-0.5, -0.5, 0.5],
         color: [0.0, 1.0, 0.0],
+        tex_coords: [0.0, 1.0],
     },
     Vertex {
         position: [0.5, -0.5, 0.5],
         color: [0.0, 0.0, 1.0],
+        tex_coords: [1.0, 1.0],
     },
     Vertex {
         position: [0.5, 0.5, 0.5],
         color: [1.0, 1.0, 0.0],
+        tex_coords: [1.0, 0.0],
     },
     Vertex {
         position: [-0.5, 0.5, -0.5],
         color: [1.0, 0.0, 1.0],
+        tex_coords: [0.0, 0.0],
     },
     Vertex {
         position: [-0.5, -0.5, -0.5],
         color: [0.0, 1.0, 1.0],
+        tex_coords: [0.0, 1.0],
     },
     Vertex {
         position: [0.5, -0.5, -0.5],
         color: [0.5, 0.0, 0.0],
+        tex_coords: [1.0, 1.0],
     },
     Vertex {
         position: [0.5, 0.5, -0.5],
         color: [0.0, 0.5, 0.0],
+        tex_coords: [1.0, 0.0],
     },
 ];
 
+/// Counter-clockwise seen from outside
 pub const INDICES: &[u16] = &[
-    0, 1, 2, 0, 2, 3, 4, 5, 6, 4, 6, 7, 0, 1, 5, 0, 5, 4, 3, 2, 6, 3, 6, 7, 0, 4, 7, 0, 7, 3, 1, 5,
+    0, 1, 2, 0, 2, 3, 4, 6, 5, 4, 7, 6, 0, 5, 1, 0, 4, 5, 3, 2, 6, 3, 6, 7, 0, 7, 4, 0, 3, 7, 1, 5,
     6, 1, 6, 2,
 ];
+
+/// CPU-side copy of a mesh. The engine keeps it around to upload the mesh again after a device loss.
+#[derive(Debug, Clone)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u16>,
+}
+
+impl MeshData {
+    /// The unit cube built from [VERTICES] and [INDICES]
+    pub fn cube() -> Self {
+        MeshData {
+            vertices: VERTICES.to_vec(),
+            indices: INDICES.to_vec(),
+        }
+    }
+
+    /// A `width` by `height` rectangle in the XY plane facing +Z, with texture coordinates covering it once. Good for
+    /// showing a render target as a screen or mirror.
+    pub fn quad(width: f32, height: f32) -> Self {
+        let (x, y) = (width / 2.0, height / 2.0);
+        let white = [1.0; 3];
+        MeshData {
+            vertices: vec![
+                Vertex::new([-x, y, 0.0], white, [0.0, 0.0]),
+                Vertex::new([-x, -y, 0.0], white, [0.0, 1.0]),
+                Vertex::new([x, -y, 0.0], white, [1.0, 1.0]),
+                Vertex::new([x, y, 0.0], white, [1.0, 0.0]),
+            ],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        }
+    }
+
+    /// Smallest and largest vertex position along each axis, None without vertices
+    pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        let first = self.vertices.first()?.position;
+        Some(
+            self.vertices
+                .iter()
+                .fold((first, first), |(min, max), vertex| {
+                    (
+                        [0, 1, 2].map(|axis| min[axis].min(vertex.position[axis])),
+                        [0, 1, 2].map(|axis| max[axis].max(vertex.position[axis])),
+                    )
+                }),
+        )
+    }
+}
+
+/// A mesh uploaded to the GPU together with the data it was uploaded from
+pub struct GpuMesh {
+    pub data: MeshData,
+    pub vertex_buffer: wgpu::Buffer,
+    pub index_buffer: wgpu::Buffer,
+}
+
+impl GpuMesh {
+    pub fn new(device: &wgpu::Device, data: MeshData) -> Self {
+        let vertex_buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex Buffer"),
+                contents: bytemuck::cast_slice(&data.vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            },
+        );
+
+        let index_buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Index Buffer"),
+                contents: bytemuck::cast_slice(&data.indices),
+                usage: wgpu::BufferUsages::INDEX,
+            },
+        );
+
+        GpuMesh {
+            data,
+            vertex_buffer,
+            index_buffer,
+        }
+    }
+
+    pub fn index_count(&self) -> u32 {
+        self.data.indices.len() as u32
+    }
+}
+
+/// Where one mesh of a [MeshPool] lives in the shared buffers
+pub struct PooledMesh {
+    pub data: MeshData,
+    vertices: ArenaAllocation,
+    indices: ArenaAllocation,
+    bounds: Option<([f32; 3], [f32; 3])>,
+    #[cfg(feature = "meshlets")]
+    meshlets: Vec<crate::meshlet::Meshlet>,
+}
+
+impl PooledMesh {
+    /// Clusters of the mesh's triangles, see [crate::meshlet]
+    #[cfg(feature = "meshlets")]
+    pub fn meshlets(&self) -> &[crate::meshlet::Meshlet] {
+        &self.meshlets
+    }
+
+    /// See [MeshData::bounds]
+    pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        self.bounds
+    }
+
+    /// Added to every index, since the mesh's indices start at zero
+    pub fn base_vertex(&self) -> i32 {
+        (self.vertices.offset / std::mem::size_of::<Vertex>() as u64) as i32
+    }
+
+    /// The range of indices to pass to `draw_indexed`
+    pub fn index_range(&self) -> Range<u32> {
+        let first = (self.indices.offset / std::mem::size_of::<u16>() as u64) as u32;
+        first..first + self.data.indices.len() as u32
+    }
+
+    /// Draws `instances` with the indirect draw starting at `first_instance`, see
+    /// [crate::wgpu_utils::indirect::IndirectBuffer]
+    pub fn indirect_args(&self, instances: Range<u32>) -> wgpu::util::DrawIndexedIndirectArgs {
+        let indices = self.index_range();
+        wgpu::util::DrawIndexedIndirectArgs {
+            index_count: indices.end - indices.start,
+            instance_count: instances.end - instances.start,
+            first_index: indices.start,
+            base_vertex: self.base_vertex(),
+            first_instance: instances.start,
+        }
+    }
+}
+
+/// The engine's meshes, all sharing one vertex and one index buffer so a whole pass binds them once.
+///
+/// Removed meshes leave their slot and buffer ranges to the next ones added.
+pub struct MeshPool {
+    vertices: BufferArena,
+    indices: BufferArena,
+    meshes: Vec<Option<PooledMesh>>,
+    #[cfg(feature = "meshlets")]
+    generation: u64,
+}
+
+impl MeshPool {
+    /// With `vertex_pulling` the vertices can also be bound as a storage buffer, see [crate::vertex_pulling]
+    pub fn new(device: &wgpu::Device, vertex_pulling: bool) -> Self {
+        let vertex_usage = if vertex_pulling {
+            wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE
+        } else {
+            wgpu::BufferUsages::VERTEX
+        };
+        MeshPool {
+            vertices: BufferArena::new(
+                device,
+                "Mesh Pool Vertices",
+                vertex_usage,
+                std::mem::size_of::<Vertex>() as u64,
+                1 << 16,
+            ),
+            indices: BufferArena::new(
+                device,
+                "Mesh Pool Indices",
+                wgpu::BufferUsages::INDEX,
+                wgpu::COPY_BUFFER_ALIGNMENT,
+                1 << 14,
+            ),
+            meshes: Vec::new(),
+            #[cfg(feature = "meshlets")]
+            generation: 0,
+        }
+    }
+
+    /// Uploads `data` and returns the index of its slot
+    pub fn insert(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, data: MeshData) -> usize {
+        let mesh = Some(self.upload(device, queue, data));
+        #[cfg(feature = "meshlets")]
+        {
+            self.generation += 1;
+        }
+        match self.meshes.iter().position(Option::is_none) {
+            Some(index) => {
+                self.meshes[index] = mesh;
+                index
+            }
+            None => {
+                self.meshes.push(mesh);
+                self.meshes.len() - 1
+            }
+        }
+    }
+
+    fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, data: MeshData) -> PooledMesh {
+        // Reorders the indices, so it has to happen before they are uploaded
+        #[cfg(feature = "meshlets")]
+        let mut data = data;
+        #[cfg(feature = "meshlets")]
+        let meshlets = crate::meshlet::build_meshlets(&mut data);
+        let vertex_bytes: &[u8] = bytemuck::cast_slice(&data.vertices);
+        let vertices = self
+            .vertices
+            .allocate(device, queue, vertex_bytes.len() as u64);
+        self.vertices.write(queue, &vertices, vertex_bytes);
+
+        // Buffer writes have to be a multiple of 4 bytes long
+        let mut index_bytes = bytemuck::cast_slice::<u16, u8>(&data.indices).to_vec();
+        index_bytes.resize(
+            index_bytes
+                .len()
+                .next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT as usize),
+            0,
+        );
+        let indices = self
+            .indices
+            .allocate(device, queue, index_bytes.len() as u64);
+        self.indices.write(queue, &indices, &index_bytes);
+
+        PooledMesh {
+            bounds: data.bounds(),
+            data,
+            vertices,
+            indices,
+            #[cfg(feature = "meshlets")]
+            meshlets,
+        }
+    }
+
+    /// Frees the mesh in slot `index`, returning its data
+    pub fn remove(&mut self, index: usize) -> Option<MeshData> {
+        let mesh = self.meshes.get_mut(index)?.take()?;
+        #[cfg(feature = "meshlets")]
+        {
+            self.generation += 1;
+        }
+        self.vertices.free(mesh.vertices);
+        self.indices.free(mesh.indices);
+        Some(mesh.data)
+    }
+
+    /// Uploads `data` into the taken slot `index`, so handles to it show the new mesh. Returns the old data.
+    #[cfg(feature = "hot-reload")]
+    pub fn replace(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        index: usize,
+        data: MeshData,
+    ) -> Option<MeshData> {
+        let old = self.remove(index)?;
+        let mesh = self.upload(device, queue, data);
+        self.meshes[index] = Some(mesh);
+        Some(old)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&PooledMesh> {
+        self.meshes.get(index)?.as_ref()
+    }
+
+    /// Number of slots, including empty ones
+    #[cfg(feature = "meshlets")]
+    pub fn slot_count(&self) -> usize {
+        self.meshes.len()
+    }
+
+    /// Changes whenever a mesh is added or removed
+    #[cfg(feature = "meshlets")]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Uploads every mesh again into a pool on `device`, keeping their slots
+    pub fn recreate(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        vertex_pulling: bool,
+    ) -> Self {
+        let mut pool = MeshPool::new(device, vertex_pulling);
+        for mesh in &self.meshes {
+            let mesh = mesh
+                .as_ref()
+                .map(|mesh| pool.upload(device, queue, mesh.data.clone()));
+            pool.meshes.push(mesh);
+        }
+        pool
+    }
+
+    pub fn vertex_buffer(&self) -> &wgpu::Buffer {
+        self.vertices.buffer()
+    }
+
+    pub fn index_buffer(&self) -> &wgpu::Buffer {
+        self.indices.buffer()
+    }
+
+    /// What the pool's buffers take up, free space included
+    pub fn size_in_bytes(&self) -> u64 {
+        self.vertices.buffer().size() + self.indices.buffer().size()
+    }
+}
@@ -0,0 +1,369 @@
+//! Meshlet rendering, an experiment for very high polygon scenes behind the `meshlets` feature.
+//!
+//! Every mesh is split into clusters of up to [MESHLET_TRIANGLES] triangles when it is added, each with a bounding
+//! sphere and a normal cone. Before a view's main pass, a compute shader tests every cluster of every drawn mesh
+//! against the view's frustum and cone, and writes one indirect draw per cluster, with no instances for the culled
+//! ones. Each mesh then draws its clusters with a single multi draw where the device has
+//! [wgpu::Features::MULTI_DRAW_INDIRECT].
+//!
+//! Cone culling assumes triangles wind counter-clockwise seen from the front, like in glTF and OBJ files. The back
+//! sides of open meshes disappear with it, as they would with back face culling. Clusters whose triangles point in
+//! too many directions, e.g. those of the built-in cube, are never cone culled.
+//!
+//! Devices without compute shaders or indirect execution, e.g. WebGL2, draw whole meshes as usual.
+
+use std::collections::VecDeque;
+
+use cgmath::{InnerSpace, Vector3};
+use wgpu::{CommandBuffer, Device, Queue};
+
+use crate::{
+    camera::camera::CameraUniform,
+    frame::FrameContext,
+    mesh::{MeshData, MeshPool},
+    render_engine_builder::DeviceReport,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        compute::{ComputeKernel, ComputeKernelBuilder, ComputePassBuilder},
+        indirect::IndirectBuffer,
+        storage_buffer::{StorageAccess, StorageVec},
+        uniform_buffer::UniformBuffer,
+    },
+};
+
+/// Most triangles in one meshlet, a common size for GPU work distribution
+pub const MESHLET_TRIANGLES: usize = 64;
+
+/// Clusters whose triangles point further apart than this from their average normal are never cone culled
+const MIN_CONE_DOT: f32 = 0.1;
+
+/// A cluster of a mesh's triangles with the bounds used to cull it
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Meshlet {
+    /// Bounding sphere in the mesh's space
+    pub center: [f32; 3],
+    pub radius: f32,
+    /// Average normal of the triangles
+    pub cone_axis: [f32; 3],
+    /// The cluster is facing away from a camera at `camera` if
+    /// `dot(center - camera, cone_axis) >= cone_cutoff * length(center - camera) + radius`. 1 disables the test.
+    pub cone_cutoff: f32,
+    /// Into the mesh's indices, which are reordered so every meshlet's triangles are contiguous
+    pub first_index: u32,
+    pub index_count: u32,
+    pub _padding: [u32; 2],
+}
+
+/// Splits `data` into meshlets, reordering its indices so each meshlet is one range of them.
+///
+/// Meshlets are grown from a seed triangle to its neighbours sharing a vertex, breadth first, so each stays compact.
+pub fn build_meshlets(data: &mut MeshData) -> Vec<Meshlet> {
+    let triangles = data.indices.len() / 3;
+    let mut vertex_triangles = vec![Vec::new(); data.vertices.len()];
+    for triangle in 0..triangles {
+        for &index in &data.indices[triangle * 3..triangle * 3 + 3] {
+            vertex_triangles[index as usize].push(triangle);
+        }
+    }
+
+    let mut used = vec![false; triangles];
+    let mut indices = Vec::with_capacity(triangles * 3);
+    let mut meshlets = Vec::new();
+    let mut cluster = Vec::with_capacity(MESHLET_TRIANGLES);
+    let mut frontier = VecDeque::new();
+    for seed in 0..triangles {
+        if used[seed] {
+            continue;
+        }
+        used[seed] = true;
+        frontier.push_back(seed);
+        while let Some(triangle) = frontier.pop_front() {
+            cluster.push(triangle);
+            if cluster.len() == MESHLET_TRIANGLES {
+                break;
+            }
+            for &index in &data.indices[triangle * 3..triangle * 3 + 3] {
+                for &neighbour in &vertex_triangles[index as usize] {
+                    if !used[neighbour] {
+                        used[neighbour] = true;
+                        frontier.push_back(neighbour);
+                    }
+                }
+            }
+        }
+        // Seen but not taken, they seed or join a later meshlet
+        for triangle in frontier.drain(..) {
+            used[triangle] = false;
+        }
+
+        let first_index = indices.len() as u32;
+        for &triangle in &cluster {
+            indices.extend_from_slice(&data.indices[triangle * 3..triangle * 3 + 3]);
+        }
+        meshlets.push(meshlet_bounds(
+            data,
+            &indices[first_index as usize..],
+            first_index,
+        ));
+        cluster.clear();
+    }
+
+    // Indices past the last whole triangle aren't drawn either way
+    indices.extend_from_slice(&data.indices[triangles * 3..]);
+    data.indices = indices;
+    meshlets
+}
+
+fn meshlet_bounds(data: &MeshData, indices: &[u16], first_index: u32) -> Meshlet {
+    let position = |index: u16| Vector3::from(data.vertices[index as usize].position());
+
+    let mut min = Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+    let mut max = -min;
+    for &index in indices {
+        let position = position(index);
+        min = Vector3::new(
+            min.x.min(position.x),
+            min.y.min(position.y),
+            min.z.min(position.z),
+        );
+        max = Vector3::new(
+            max.x.max(position.x),
+            max.y.max(position.y),
+            max.z.max(position.z),
+        );
+    }
+    let center = (min + max) / 2.0;
+    let radius = indices
+        .iter()
+        .map(|&index| (position(index) - center).magnitude())
+        .fold(0.0, f32::max);
+
+    let normals: Vec<_> = indices
+        .chunks_exact(3)
+        .filter_map(|triangle| {
+            let [a, b, c] = [0, 1, 2].map(|corner| position(triangle[corner]));
+            let normal = (b - a).cross(c - a);
+            (normal.magnitude2() > f32::EPSILON).then(|| normal.normalize())
+        })
+        .collect();
+    let sum = normals
+        .iter()
+        .fold(Vector3::new(0.0, 0.0, 0.0), |sum, normal| sum + normal);
+    let (cone_axis, cone_cutoff) = if sum.magnitude2() > f32::EPSILON {
+        let axis = sum.normalize();
+        let min_dot = normals
+            .iter()
+            .map(|normal| normal.dot(axis))
+            .fold(1.0, f32::min);
+        let cutoff = if min_dot <= MIN_CONE_DOT {
+            1.0
+        } else {
+            (1.0 - min_dot * min_dot).sqrt()
+        };
+        (axis, cutoff)
+    } else {
+        (Vector3::new(0.0, 0.0, 1.0), 1.0)
+    };
+
+    Meshlet {
+        center: center.into(),
+        radius,
+        cone_axis: cone_axis.into(),
+        cone_cutoff,
+        first_index,
+        index_count: indices.len() as u32,
+        _padding: [0; 2],
+    }
+}
+
+/// A [Meshlet] placed in the mesh pool's buffers, as the culling shader reads it
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuMeshlet {
+    center: [f32; 3],
+    radius: f32,
+    cone_axis: [f32; 3],
+    cone_cutoff: f32,
+    first_index: u32,
+    index_count: u32,
+    base_vertex: i32,
+    _padding: u32,
+}
+
+/// One meshlet of one drawn mesh
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct MeshletInstance {
+    /// Into every mesh's meshlets, see [MeshletCulling::first_meshlet]
+    pub meshlet: u32,
+    /// Into [FrameContext::meshlet_transforms]
+    pub transform: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullUniform {
+    camera: CameraUniform,
+    instance_count: u32,
+    _padding: [u32; 3],
+}
+
+crate::assert_uniform_layout!(CullUniform {
+    camera: ALIGN_VEC4,
+    instance_count: ALIGN_SCALAR,
+});
+
+/// The culling kernel and the meshlets of every mesh, shared by all views
+pub(crate) struct MeshletCulling {
+    kernel: ComputeKernel,
+    bind_group_layout: BindGroupLayoutWithDesc,
+    meshlets: StorageVec<GpuMeshlet>,
+    /// Index of each mesh slot's first meshlet in `meshlets`
+    first_meshlets: Vec<Option<u32>>,
+    /// [MeshPool::generation] the meshlets were gathered at
+    generation: Option<u64>,
+    instances: StorageVec<MeshletInstance>,
+    transforms: StorageVec<[[f32; 4]; 4]>,
+}
+
+impl MeshletCulling {
+    /// None where the device can't run the culling shader or draw what it writes
+    pub fn new(device: &Device, device_report: &DeviceReport) -> Option<Self> {
+        let required =
+            wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::INDIRECT_EXECUTION;
+        if !device_report.downlevel_flags.contains(required) {
+            tracing::warn!(
+                "Meshlets need compute shaders and indirect draws, drawing whole meshes"
+            );
+            return None;
+        }
+
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_types::uniform())
+            .named("cull")
+            .next_binding_compute(StorageAccess::ReadOnly.binding_type())
+            .named("meshlets")
+            .next_binding_compute(StorageAccess::ReadOnly.binding_type())
+            .named("instances")
+            .next_binding_compute(StorageAccess::ReadOnly.binding_type())
+            .named("transforms")
+            .next_binding_compute(StorageAccess::ReadWrite.binding_type())
+            .named("draws")
+            .create(device, "Meshlet Culling Bind Group");
+        let kernel = ComputeKernelBuilder::new(include_str!("meshlet_cull.wgsl"))
+            .bind_group_layout(&bind_group_layout.layout)
+            .create(device, "meshlet culling");
+
+        Some(MeshletCulling {
+            kernel,
+            bind_group_layout,
+            meshlets: StorageVec::new(device, StorageAccess::ReadOnly),
+            first_meshlets: Vec::new(),
+            generation: None,
+            instances: StorageVec::new(device, StorageAccess::ReadOnly),
+            transforms: StorageVec::new(device, StorageAccess::ReadOnly),
+        })
+    }
+
+    /// Gathers the meshlets of every mesh again after meshes were added or removed
+    pub fn sync(&mut self, device: &Device, queue: &Queue, meshes: &MeshPool) {
+        if self.generation == Some(meshes.generation()) {
+            return;
+        }
+        self.generation = Some(meshes.generation());
+
+        let mut meshlets = Vec::new();
+        self.first_meshlets = (0..meshes.slot_count())
+            .map(|slot| {
+                let mesh = meshes.get(slot)?;
+                let first = meshlets.len() as u32;
+                let first_index = mesh.index_range().start;
+                meshlets.extend(mesh.meshlets().iter().map(|meshlet| GpuMeshlet {
+                    center: meshlet.center,
+                    radius: meshlet.radius,
+                    cone_axis: meshlet.cone_axis,
+                    cone_cutoff: meshlet.cone_cutoff,
+                    first_index: first_index + meshlet.first_index,
+                    index_count: meshlet.index_count,
+                    base_vertex: mesh.base_vertex(),
+                    _padding: 0,
+                }));
+                Some(first)
+            })
+            .collect();
+        self.meshlets.update(device, queue, &meshlets);
+    }
+
+    /// Where the meshlets of the mesh in `slot` start, None if it has none
+    pub fn first_meshlet(&self, slot: usize) -> Option<u32> {
+        self.first_meshlets.get(slot).copied().flatten()
+    }
+
+    /// Uploads the meshlets drawn this frame
+    pub fn upload(&mut self, device: &Device, queue: &Queue, frame: &FrameContext) {
+        self.instances
+            .update(device, queue, &frame.meshlet_instances);
+        self.transforms
+            .update(device, queue, &frame.meshlet_transforms);
+    }
+
+    /// Culls this frame's meshlets for a view seen from `camera`, writing the draws of `view`. None without
+    /// meshlets to draw.
+    pub fn cull(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        device_report: &DeviceReport,
+        view: &mut Option<MeshletView>,
+        camera: &CameraUniform,
+    ) -> Option<CommandBuffer> {
+        if self.instances.is_empty() {
+            return None;
+        }
+        let count = self.instances.len() as u32;
+        let view = view.get_or_insert_with(|| MeshletView {
+            uniform: UniformBuffer::new(device),
+            draws: IndirectBuffer::new(device, device_report.downlevel_flags, count as usize),
+        });
+        view.uniform.update_content(
+            queue,
+            CullUniform {
+                camera: *camera,
+                instance_count: count,
+                _padding: [0; 3],
+            },
+        );
+        view.draws.set_gpu_written(device, count as usize);
+
+        // The buffers may have been replaced since the last frame, a bind group is cheap to create
+        let bind_group = BindGroupBuilder::new(&self.bind_group_layout)
+            .resource(view.uniform.binding_resource())
+            .resource(self.meshlets.binding_resource())
+            .resource(self.instances.binding_resource())
+            .resource(self.transforms.binding_resource())
+            .resource(view.draws.binding_resource())
+            .create(device, "Meshlet Culling Bind Group");
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Meshlet Culling Encoder"),
+        });
+        ComputePassBuilder::new("meshlet culling")
+            .dispatch(&self.kernel, &[&bind_group], [count, 1, 1])
+            .record(&mut encoder);
+        Some(encoder.finish())
+    }
+}
+
+/// The meshlet draws of one window or render target
+pub(crate) struct MeshletView {
+    uniform: UniformBuffer<CullUniform>,
+    draws: IndirectBuffer,
+}
+
+impl MeshletView {
+    /// One draw per [MeshletInstance] of the frame, in the same order
+    pub fn draws(&self) -> &IndirectBuffer {
+        &self.draws
+    }
+}
@@ -0,0 +1,98 @@
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+
+struct Cull {
+    camera: Camera,
+    instance_count: u32,
+}
+
+struct Meshlet {
+    center: vec3<f32>,
+    radius: f32,
+    cone_axis: vec3<f32>,
+    cone_cutoff: f32,
+    first_index: u32,
+    index_count: u32,
+    base_vertex: i32,
+}
+
+struct Instance {
+    meshlet: u32,
+    transform: u32,
+}
+
+// Laid out like wgpu's DrawIndexedIndirectArgs
+struct DrawArgs {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> cull: Cull;
+@group(0) @binding(1)
+var<storage, read> meshlets: array<Meshlet>;
+@group(0) @binding(2)
+var<storage, read> instances: array<Instance>;
+@group(0) @binding(3)
+var<storage, read> transforms: array<mat4x4<f32>>;
+@group(0) @binding(4)
+var<storage, read_write> draws: array<DrawArgs>;
+
+// Whether the sphere is entirely outside one of the frustum's planes, taken from the rows of the view projection
+fn outside_frustum(center: vec3<f32>, radius: f32) -> bool {
+    let m = transpose(cull.camera.view_proj);
+    // Depth runs from 0 to 1, so the near plane is the third row on its own
+    var planes = array<vec4<f32>, 6>(
+        m[3] + m[0],
+        m[3] - m[0],
+        m[3] + m[1],
+        m[3] - m[1],
+        m[2],
+        m[3] - m[2],
+    );
+    for (var i = 0u; i < 6u; i++) {
+        let plane = planes[i];
+        if dot(plane.xyz, center) + plane.w < -radius * length(plane.xyz) {
+            return true;
+        }
+    }
+    return false;
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= cull.instance_count {
+        return;
+    }
+    let instance = instances[index];
+    let meshlet = meshlets[instance.meshlet];
+    let model = transforms[instance.transform];
+
+    let center = (model * vec4<f32>(meshlet.center, 1.0)).xyz;
+    let scales = vec3<f32>(length(model[0].xyz), length(model[1].xyz), length(model[2].xyz));
+    let radius = meshlet.radius * max(scales.x, max(scales.y, scales.z));
+
+    var visible = !outside_frustum(center, radius);
+    // The cone only stays a cone under uniform scaling
+    let uniform_scale = max(scales.x, max(scales.y, scales.z)) - min(scales.x, min(scales.y, scales.z))
+        < 0.01 * scales.x;
+    if visible && uniform_scale && meshlet.cone_cutoff < 1.0 {
+        let axis = normalize((model * vec4<f32>(meshlet.cone_axis, 0.0)).xyz);
+        let to_center = center - cull.camera.view_pos.xyz;
+        visible = dot(to_center, axis) < meshlet.cone_cutoff * length(to_center) + radius;
+    }
+
+    var args: DrawArgs;
+    args.index_count = meshlet.index_count;
+    args.instance_count = select(0u, 1u, visible);
+    args.first_index = meshlet.first_index;
+    args.base_vertex = meshlet.base_vertex;
+    args.first_instance = 0u;
+    draws[index] = args;
+}
@@ -0,0 +1,337 @@
+//! Models made of several meshes, each with its own material and placement, as scene formats like USD describe them.
+//! Skinned models also bring their skeleton and animations along, and parts may follow a joint as a whole. The engine
+//! moves the latter with [crate::animation::AnimationPlayer], skinning is up to the app for now.
+
+use std::{collections::HashMap, hash::Hash};
+
+use cgmath::{InnerSpace, Matrix4, Quaternion, SquareMatrix, Vector3, Vector4, VectorSpace};
+
+use crate::{
+    mesh::{MeshData, Vertex},
+    render_engine::RenderEngine,
+    scene::{Material, Renderable, Transform},
+    texture::TextureData,
+};
+
+/// One mesh of a [ModelData]
+#[derive(Debug, Clone)]
+pub struct ModelPart {
+    /// Where the part came from in its file, e.g. a prim path, for log messages and UIs
+    pub name: String,
+    pub mesh: MeshData,
+    pub material: Material,
+    /// Index into [ModelData::textures] of the image the material samples, it becomes the [Material::image] once the
+    /// model is added to the engine
+    pub texture: Option<usize>,
+    /// From the part's mesh to the model's space
+    pub transform: Matrix4<f32>,
+    /// Which of the [ModelData::skeleton]'s joints move the mesh's vertices
+    pub skin: Option<Skin>,
+    /// The joint of the [ModelData::skeleton] the whole part moves with, for animations moving meshes rather than
+    /// their vertices
+    pub joint: Option<usize>,
+}
+
+/// CPU-side copy of a model, ready for [ModelData::add_to]
+#[derive(Debug, Clone, Default)]
+pub struct ModelData {
+    pub parts: Vec<ModelPart>,
+    /// The images the parts' materials sample, see [ModelPart::texture]
+    pub textures: Vec<TextureData>,
+    pub skeleton: Option<Skeleton>,
+    /// Moving the skeleton's joints
+    pub animations: Vec<AnimationClip>,
+}
+
+/// Joints that the vertices of skinned parts or whole parts follow, parents listed before their children
+#[derive(Debug, Clone, Default)]
+pub struct Skeleton {
+    pub joints: Vec<Joint>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub name: String,
+    /// Index of the parent joint, None for roots
+    pub parent: Option<usize>,
+    /// Relative to the parent joint, or to the model for roots, when no animation moves it
+    pub rest: Transform,
+}
+
+/// Up to four joints per vertex of a part's mesh, with weights adding up to one
+#[derive(Debug, Clone, Default)]
+pub struct Skin {
+    /// Indices into [Skeleton::joints], which [Skin::vertex_joints] index in turn
+    pub joints: Vec<usize>,
+    /// For each of [Skin::joints], from the part's mesh to the joint's space when the skin was bound to it
+    pub inverse_binds: Vec<Matrix4<f32>>,
+    pub vertex_joints: Vec<[u16; 4]>,
+    pub vertex_weights: Vec<[f32; 4]>,
+}
+
+/// Keyframes for the joints of a [Skeleton]
+#[derive(Debug, Clone)]
+pub struct AnimationClip {
+    pub name: String,
+    /// In seconds, up to the last keyframe
+    pub duration: f32,
+    pub channels: Vec<Channel>,
+}
+
+/// Keyframes of one property of one joint, linearly interpolated in between. Properties without a channel keep
+/// their [Joint::rest] value.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub joint: usize,
+    pub property: ChannelProperty,
+    /// In seconds, ascending
+    pub times: Vec<f32>,
+    /// One for each time, xyz for translation and scale and a quaternion xyzw for rotation
+    pub values: Vec<[f32; 4]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelProperty {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+impl ModelData {
+    /// Smallest and largest position along each axis of the transformed parts, None without vertices
+    pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        let mut bounds: Option<([f32; 3], [f32; 3])> = None;
+        for part in &self.parts {
+            let Some((min, max)) = part.mesh.bounds() else {
+                continue;
+            };
+            // The corners of the part's box, the transformed box around them holds every vertex
+            for corner in 0..8 {
+                let pick = |axis: usize| {
+                    if corner & (1 << axis) == 0 {
+                        min[axis]
+                    } else {
+                        max[axis]
+                    }
+                };
+                let point = part.transform * Vector4::new(pick(0), pick(1), pick(2), 1.0);
+                let point = [point.x, point.y, point.z];
+                let (all_min, all_max) = bounds.get_or_insert((point, point));
+                for axis in 0..3 {
+                    all_min[axis] = all_min[axis].min(point[axis]);
+                    all_max[axis] = all_max[axis].max(point[axis]);
+                }
+            }
+        }
+        bounds
+    }
+
+    /// Uploads every part's mesh and material, parts with the same material share one. Returns what to hand to
+    /// [RenderEngine::set_renderables], their meshes can be freed again with [RenderEngine::remove_mesh].
+    pub fn add_to(mut self, engine: &mut RenderEngine) -> Vec<Renderable> {
+        self.upload_textures(engine);
+        let mut materials = Vec::new();
+        self.parts
+            .into_iter()
+            .map(|part| {
+                let material = match materials
+                    .iter()
+                    .find(|(material, _)| *material == part.material)
+                {
+                    Some(&(_, handle)) => handle,
+                    None if part.material == Material::default() => engine.default_material(),
+                    None => {
+                        let handle = engine.add_material(part.material);
+                        materials.push((part.material, handle));
+                        handle
+                    }
+                };
+                Renderable {
+                    mesh: engine.add_mesh(part.mesh),
+                    material,
+                    model: part.transform,
+                    occlusion_query: false,
+                }
+            })
+            .collect()
+    }
+
+    /// Uploads the [ModelData::textures] and points the parts' materials at them
+    pub(crate) fn upload_textures(&mut self, engine: &mut RenderEngine) {
+        let handles: Vec<_> = std::mem::take(&mut self.textures)
+            .into_iter()
+            .map(|texture| engine.add_texture(texture.width, texture.height, texture.rgba))
+            .collect();
+        for part in &mut self.parts {
+            if let Some(&handle) = part.texture.and_then(|texture| handles.get(texture)) {
+                part.material.image = Some(handle);
+            }
+        }
+    }
+}
+
+impl Skeleton {
+    /// Every joint's [Joint::rest]
+    pub fn rest_pose(&self) -> Vec<Transform> {
+        self.joints.iter().map(|joint| joint.rest).collect()
+    }
+
+    /// The model space matrix of every joint in `pose`, one transform per joint relative to its parent
+    pub fn model_matrices(&self, pose: &[Transform]) -> Vec<Matrix4<f32>> {
+        let mut matrices: Vec<Matrix4<f32>> = Vec::with_capacity(self.joints.len());
+        for (joint, transform) in self.joints.iter().zip(pose) {
+            let local = transform.matrix();
+            let matrix = match joint.parent.and_then(|parent| matrices.get(parent)) {
+                Some(parent) => parent * local,
+                None => local,
+            };
+            matrices.push(matrix);
+        }
+        matrices
+    }
+}
+
+impl AnimationClip {
+    /// Moves the joints of `pose`, e.g. a [Skeleton::rest_pose], to where the clip has them `time` seconds in
+    pub fn apply(&self, time: f32, pose: &mut [Transform]) {
+        for channel in &self.channels {
+            let (Some(transform), Some([x, y, z, w])) =
+                (pose.get_mut(channel.joint), channel.sample(time))
+            else {
+                continue;
+            };
+            match channel.property {
+                ChannelProperty::Translation => transform.translation = Vector3::new(x, y, z),
+                ChannelProperty::Rotation => transform.rotation = Quaternion::new(w, x, y, z),
+                ChannelProperty::Scale => transform.scale = Vector3::new(x, y, z),
+            }
+        }
+    }
+}
+
+impl Channel {
+    /// The value `time` seconds in, holding the first and last keyframe outside of them. None without keyframes.
+    pub fn sample(&self, time: f32) -> Option<[f32; 4]> {
+        let next = self.times.partition_point(|&keyframe| keyframe <= time);
+        let (Some(&from), Some(&to)) = (
+            self.values.get(next.saturating_sub(1)),
+            self.values
+                .get(next.min(self.values.len().saturating_sub(1))),
+        ) else {
+            return None;
+        };
+        if next == 0 || next >= self.times.len() {
+            return Some(from);
+        }
+        let (start, end) = (self.times[next - 1], self.times[next]);
+        let amount = (time - start) / (end - start);
+        Some(match self.property {
+            ChannelProperty::Rotation => {
+                let [x, y, z, w] = from;
+                let from = Quaternion::new(w, x, y, z);
+                let [x, y, z, w] = to;
+                let rotation = slerp(from, Quaternion::new(w, x, y, z), amount);
+                [rotation.v.x, rotation.v.y, rotation.v.z, rotation.s]
+            }
+            _ => Vector4::from(from).lerp(Vector4::from(to), amount).into(),
+        })
+    }
+}
+
+/// Along the shorter way around
+pub(crate) fn slerp(from: Quaternion<f32>, to: Quaternion<f32>, amount: f32) -> Quaternion<f32> {
+    let to = if from.dot(to) < 0.0 { -to } else { to };
+    from.slerp(to, amount).normalize()
+}
+
+/// A single part, e.g. a model read from OBJ
+impl From<MeshData> for ModelData {
+    fn from(mesh: MeshData) -> Self {
+        ModelData {
+            parts: vec![ModelPart {
+                name: String::new(),
+                mesh,
+                material: Material::default(),
+                texture: None,
+                transform: Matrix4::identity(),
+                skin: None,
+                joint: None,
+            }],
+            ..Default::default()
+        }
+    }
+}
+
+/// Collects polygons into meshes for a loader, starting another mesh whenever 16 bit indices run out. Corners with the
+/// same key, e.g. the same position and texture coordinate indices, share a vertex.
+pub(crate) struct PolygonBuilder<K> {
+    /// With the key of each vertex
+    meshes: Vec<(MeshData, Vec<K>)>,
+    vertex_indices: HashMap<K, u16>,
+}
+
+impl<K> Default for PolygonBuilder<K> {
+    fn default() -> Self {
+        PolygonBuilder {
+            meshes: vec![Self::empty_mesh()],
+            vertex_indices: HashMap::new(),
+        }
+    }
+}
+
+impl<K> PolygonBuilder<K> {
+    fn empty_mesh() -> (MeshData, Vec<K>) {
+        let mesh = MeshData {
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        };
+        (mesh, Vec::new())
+    }
+}
+
+impl<K: Clone + Eq + Hash> PolygonBuilder<K> {
+    /// Adds a polygon as a fan of triangles, turned around with `flip`. `vertex` makes the vertex for a key seen for
+    /// the first time, polygons with less than three corners are skipped.
+    pub fn add(&mut self, corners: &[K], flip: bool, mut vertex: impl FnMut(&K) -> Vertex) {
+        if corners.len() < 3 {
+            return;
+        }
+        let (mesh, _) = self
+            .meshes
+            .last()
+            .expect("There is always a mesh being built!");
+        if mesh.vertices.len() + corners.len() > usize::from(u16::MAX) + 1 {
+            self.meshes.push(Self::empty_mesh());
+            self.vertex_indices.clear();
+        }
+        let (mesh, keys) = self
+            .meshes
+            .last_mut()
+            .expect("There is always a mesh being built!");
+        let indices: Vec<u16> = corners
+            .iter()
+            .map(|key| {
+                *self.vertex_indices.entry(key.clone()).or_insert_with(|| {
+                    mesh.vertices.push(vertex(key));
+                    keys.push(key.clone());
+                    (mesh.vertices.len() - 1) as u16
+                })
+            })
+            .collect();
+        for pair in indices[1..].windows(2) {
+            if flip {
+                mesh.indices.extend([indices[0], pair[1], pair[0]]);
+            } else {
+                mesh.indices.extend([indices[0], pair[0], pair[1]]);
+            }
+        }
+    }
+
+    /// The meshes with at least one triangle, each with the keys of its vertices
+    pub fn finish(self) -> Vec<(MeshData, Vec<K>)> {
+        self.meshes
+            .into_iter()
+            .filter(|(mesh, _)| !mesh.indices.is_empty())
+            .collect()
+    }
+}
@@ -0,0 +1,120 @@
+use crate::wgpu_utils::{
+    binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+    binding_types,
+    dynamic_uniform_buffer::DynamicUniformBuffer,
+    per_frame::PerFrame,
+    uploader::Uploader,
+};
+
+/// Per object data, bound at group 1
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct ObjectUBOContent {
+    pub model: [[f32; 4]; 4],
+    pub color: [f32; 4],
+    /// Written into the ID attachment for picking, 0 for nothing to pick
+    pub id: u32,
+    /// See [crate::scene::Material::reflectivity]
+    pub reflectivity: f32,
+    pub roughness: f32,
+    /// See [crate::scene::Material::clearcoat]
+    pub clearcoat: f32,
+    pub clearcoat_roughness: f32,
+    pub ior: f32,
+    /// Cube of the reflection probe the object reflects, see [crate::reflection_probe]
+    pub probe: u32,
+    /// 1 for objects the irradiance volume lights, see [crate::scene::Material::lit_by_probes]
+    pub lit: u32,
+}
+
+unsafe impl bytemuck::Pod for ObjectUBOContent {}
+unsafe impl bytemuck::Zeroable for ObjectUBOContent {}
+
+crate::assert_uniform_layout!(ObjectUBOContent {
+    model: ALIGN_VEC4,
+    color: ALIGN_VEC4,
+    id: ALIGN_SCALAR,
+    reflectivity: ALIGN_SCALAR,
+    roughness: ALIGN_SCALAR,
+    clearcoat: ALIGN_SCALAR,
+    clearcoat_roughness: ALIGN_SCALAR,
+    ior: ALIGN_SCALAR,
+    probe: ALIGN_SCALAR,
+    lit: ALIGN_SCALAR,
+});
+
+/// One frame's object uniforms and the bind group holding them
+struct ObjectFrame {
+    ubo: DynamicUniformBuffer<ObjectUBOContent>,
+    bind_group: wgpu::BindGroup,
+}
+
+/// The uniforms of every object drawn in a frame, suballocated from one buffer behind a single bind group.
+///
+/// Objects are placed by draw order and each draw binds the group with its own dynamic offset. There is a buffer per
+/// frame in flight, each only grows when more objects are drawn than ever before.
+pub struct ObjectBindings {
+    bind_group_layout: BindGroupLayoutWithDesc,
+    frames: PerFrame<ObjectFrame>,
+}
+
+impl ObjectBindings {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_rendering(binding_types::uniform_dynamic(std::mem::size_of::<
+                ObjectUBOContent,
+            >() as u64))
+            .create(device, "Object Bind Group");
+        let frames = PerFrame::new(|_| {
+            let ubo = DynamicUniformBuffer::new(device);
+            let bind_group = Self::create_bind_group(device, &bind_group_layout, &ubo);
+            ObjectFrame { ubo, bind_group }
+        });
+
+        ObjectBindings {
+            bind_group_layout,
+            frames,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &BindGroupLayoutWithDesc,
+        ubo: &DynamicUniformBuffer<ObjectUBOContent>,
+    ) -> wgpu::BindGroup {
+        BindGroupBuilder::new(layout)
+            .resource(ubo.binding_resource())
+            .create(device, "Object Bind Group")
+    }
+
+    /// Replaces the objects with `contents`, in draw order, in the next frame's buffer
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        uploader: &mut Uploader,
+        contents: &[ObjectUBOContent],
+    ) {
+        let frame = self.frames.advance();
+        frame.ubo.clear();
+        for content in contents {
+            frame.ubo.push(content);
+        }
+        if frame.ubo.upload(device, uploader) {
+            frame.bind_group = Self::create_bind_group(device, &self.bind_group_layout, &frame.ubo);
+        }
+    }
+
+    pub fn bind_group_layouts(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout.layout
+    }
+
+    /// Shared by every object of the current frame, see [ObjectBindings::offset]
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.frames.current().bind_group
+    }
+
+    /// The dynamic offset of the object drawn `index`th this frame
+    pub fn offset(&self, index: usize) -> u32 {
+        self.frames.current().ubo.offset(index)
+    }
+}
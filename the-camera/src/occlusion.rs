@@ -0,0 +1,307 @@
+//! Occlusion culling for renderables with [crate::scene::Renderable::occlusion_query].
+//!
+//! After each view's objects are drawn, the bounding boxes of those renderables are drawn once more against the
+//! depth buffer with an occlusion query each, without touching color or depth. Renderables whose box had no visible
+//! samples are skipped in that view until a later query sees it again. Results arrive a frame or two late, so an
+//! object coming out from behind another can pop in slightly delayed.
+
+use std::collections::HashSet;
+
+use cgmath::{Matrix4, Vector3, Vector4};
+use wgpu::{Device, RenderPass, RenderPipeline, TextureFormat};
+
+use crate::{
+    camera::camera::CameraUniform,
+    global_bindings::GlobalBindings,
+    mesh::{GpuMesh, MeshData, Vertex},
+    object_bindings::ObjectBindings,
+    profiler::RenderStats,
+    texture,
+    wgpu_utils::readback::ReadbackRing,
+};
+
+/// A renderable's bounding box drawn for its occlusion query
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct OcclusionProxy {
+    /// Index into the renderables, results are kept by it
+    pub renderable: usize,
+    /// The object slot holding the box's transform
+    pub slot: usize,
+    /// The box in world space, to tell whether the camera is inside it
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl OcclusionProxy {
+    /// The model matrix stretching the unit cube over `bounds` of a mesh placed with `model`, along with the box's
+    /// world space extent
+    pub fn transform(
+        model: Matrix4<f32>,
+        (min, max): ([f32; 3], [f32; 3]),
+    ) -> (Matrix4<f32>, [f32; 3], [f32; 3]) {
+        let center = (Vector3::from(min) + Vector3::from(max)) / 2.0;
+        let size = Vector3::from(max) - Vector3::from(min);
+        let proxy = model
+            * Matrix4::from_translation(center)
+            * Matrix4::from_nonuniform_scale(size.x, size.y, size.z);
+
+        let mut world_min = [f32::INFINITY; 3];
+        let mut world_max = [f32::NEG_INFINITY; 3];
+        for corner in 0..8 {
+            let local = [0, 1, 2].map(|axis| {
+                if corner >> axis & 1 == 0 {
+                    min[axis]
+                } else {
+                    max[axis]
+                }
+            });
+            let world = model * Vector4::new(local[0], local[1], local[2], 1.0);
+            for axis in 0..3 {
+                world_min[axis] = world_min[axis].min(world[axis]);
+                world_max[axis] = world_max[axis].max(world[axis]);
+            }
+        }
+        (proxy, world_min, world_max)
+    }
+
+    /// Boxes around the camera would be clipped by the near plane or seen from the inside, so they are always drawn
+    fn contains(&self, position: [f32; 4]) -> bool {
+        (0..3).all(|axis| {
+            let margin = (self.max[axis] - self.min[axis]) * 0.05 + 0.1;
+            position[axis] >= self.min[axis] - margin && position[axis] <= self.max[axis] + margin
+        })
+    }
+}
+
+/// The pipeline drawing the bounding boxes, shared by every view
+pub(crate) struct OcclusionPass {
+    pipeline: RenderPipeline,
+    cube: GpuMesh,
+}
+
+impl OcclusionPass {
+    /// `format` has to be the engine's swapchain format, since the boxes are drawn in the main pass
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        object_bindings: &ObjectBindings,
+    ) -> Self {
+        let _span = tracing::debug_span!("create_occlusion_pipeline").entered();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Occlusion Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("occlusion.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                global_bindings.bind_group_layouts(),
+                object_bindings.bind_group_layouts(),
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("occlusion"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[Vertex::desc()],
+                compilation_options: Default::default(),
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Cw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+                unclipped_depth: false,
+            },
+            // Tests against what was drawn, including the object itself where the box touches its surface
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::empty(),
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: texture::Texture::ID_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::empty(),
+                    }),
+                ],
+                compilation_options: Default::default(),
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        OcclusionPass {
+            pipeline,
+            cube: GpuMesh::new(device, MeshData::cube()),
+        }
+    }
+
+    /// Draws the boxes of `queries` with a query each. The render pass has to have been begun with
+    /// [OcclusionQueries::query_set] and have the camera bound at group 0.
+    pub fn draw(
+        &self,
+        render_pass: &mut RenderPass,
+        queries: &OcclusionQueries,
+        object_bindings: &ObjectBindings,
+        stats: &mut RenderStats,
+    ) {
+        if queries.queried.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, self.cube.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.cube.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        stats.pipeline_switches += 1;
+        for (query, proxy) in queries.queried.iter().enumerate() {
+            render_pass.set_bind_group(
+                1,
+                object_bindings.bind_group(),
+                &[object_bindings.offset(proxy.slot)],
+            );
+            render_pass.begin_occlusion_query(query as u32);
+            render_pass.draw_indexed(0..self.cube.index_count(), 0, 0..1);
+            render_pass.end_occlusion_query();
+            stats.bind_group_switches += 1;
+            stats.draw(self.cube.index_count(), 1);
+        }
+    }
+}
+
+const RESULT_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+
+/// The queries of one window or render target and what they found
+pub(crate) struct OcclusionQueries {
+    query_set: Option<wgpu::QuerySet>,
+    resolve_buffer: Option<wgpu::Buffer>,
+    capacity: u32,
+    /// The proxies drawn in the current frame, by query index
+    queried: Vec<OcclusionProxy>,
+    /// Renderables whose box had no visible samples in the latest result
+    occluded: HashSet<usize>,
+    /// Sample counts of each submission, tagged with the renderables they belong to
+    readbacks: ReadbackRing<Vec<usize>>,
+}
+
+impl Default for OcclusionQueries {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OcclusionQueries {
+    pub fn new() -> Self {
+        OcclusionQueries {
+            query_set: None,
+            resolve_buffer: None,
+            capacity: 0,
+            queried: Vec::new(),
+            occluded: HashSet::new(),
+            readbacks: ReadbackRing::new("Occlusion Readback Buffer"),
+        }
+    }
+
+    /// Picks the proxies to query among `proxies` seen from `camera` and makes room for them
+    pub fn begin(
+        &mut self,
+        device: &Device,
+        proxies: impl Iterator<Item = OcclusionProxy>,
+        camera: &CameraUniform,
+    ) {
+        self.queried.clear();
+        for proxy in proxies {
+            if proxy.contains(camera.view_position) {
+                self.occluded.remove(&proxy.renderable);
+            } else {
+                self.queried.push(proxy);
+            }
+        }
+
+        let needed = self.queried.len() as u32;
+        if needed > self.capacity {
+            self.capacity = needed.next_power_of_two();
+            self.query_set = Some(device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Occlusion Queries"),
+                ty: wgpu::QueryType::Occlusion,
+                count: self.capacity,
+            }));
+            self.resolve_buffer = Some(device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Occlusion Resolve Buffer"),
+                size: self.capacity as u64 * RESULT_SIZE,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }));
+        }
+    }
+
+    /// Whether to skip drawing the renderable
+    pub fn is_occluded(&self, renderable: usize) -> bool {
+        self.occluded.contains(&renderable)
+    }
+
+    /// For the render pass drawing the proxies, None when there is nothing to query
+    pub fn query_set(&self) -> Option<&wgpu::QuerySet> {
+        self.query_set.as_ref().filter(|_| !self.queried.is_empty())
+    }
+
+    /// Records copying this frame's results out, after the render pass
+    pub fn resolve(&mut self, device: &Device, encoder: &mut wgpu::CommandEncoder) {
+        let (Some(query_set), Some(resolve_buffer)) = (&self.query_set, &self.resolve_buffer)
+        else {
+            return;
+        };
+        if self.queried.is_empty() {
+            return;
+        }
+        let count = self.queried.len() as u32;
+        encoder.resolve_query_set(query_set, 0..count, resolve_buffer, 0);
+        self.readbacks.copy(
+            device,
+            encoder,
+            resolve_buffer,
+            0,
+            count as u64 * RESULT_SIZE,
+            self.queried.iter().map(|proxy| proxy.renderable).collect(),
+        );
+    }
+
+    pub fn after_submit(&mut self) {
+        self.readbacks.after_submit();
+    }
+
+    /// Takes in the results that arrived, the device has to be polled for them to make progress
+    pub fn collect(&mut self) {
+        let occluded = &mut self.occluded;
+        self.readbacks.collect(|renderables, data| {
+            let samples: &[u64] = bytemuck::cast_slice(data);
+            *occluded = renderables
+                .into_iter()
+                .zip(samples)
+                .filter(|(_, &samples)| samples == 0)
+                .map(|(renderable, _)| renderable)
+                .collect();
+        });
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.readbacks.is_pending()
+    }
+}
@@ -0,0 +1,27 @@
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct Object {
+    model: mat4x4<f32>,
+    color: vec4<f32>,
+    id: u32,
+}
+@group(1) @binding(0)
+var<uniform> object: Object;
+
+// The unit cube, stretched over an object's bounding box by the model matrix
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+    return camera.view_proj * object.model * vec4<f32>(position, 1.0);
+}
+
+// Only counts samples, the color is masked out
+@fragment
+fn fs_main() -> @location(0) vec4<f32> {
+    return vec4<f32>(0.0);
+}
@@ -0,0 +1,216 @@
+//! A headless engine rendering into an image, driven through plain numbers, paths and pixel buffers. It's the layer
+//! for bindings such as the Python module of the `python` feature, where every method maps to one Python method and
+//! [OffscreenRenderer::render] to a `(height, width, 4)` uint8 numpy array.
+
+use std::{io, path::Path};
+
+use cgmath::Vector3;
+
+use crate::{
+    assets,
+    background::Background,
+    camera::{
+        orbit_camera::OrbitCamera,
+        view_animation::{orbit_angles, StandardView},
+    },
+    render_engine::RenderEngine,
+    render_engine_builder::RenderEngineBuilder,
+    render_target::RenderTargetHandle,
+    scene::{Material, MaterialHandle, MeshHandle, Renderable, Transform},
+};
+
+/// A model added with [OffscreenRenderer::load_model]
+#[derive(Debug, Clone, Copy)]
+struct Model {
+    mesh: MeshHandle,
+    /// Every model has its own, so tweaking one leaves the others alone
+    material: MaterialHandle,
+    transform: Transform,
+}
+
+/// Renders the models loaded into it from an orbit camera, one image per [OffscreenRenderer::render]
+pub struct OffscreenRenderer {
+    engine: RenderEngine,
+    target: RenderTargetHandle,
+    models: Vec<Model>,
+    /// Around every model loaded so far, at their original places
+    bounds: Option<([f32; 3], [f32; 3])>,
+}
+
+impl OffscreenRenderer {
+    /// Creates a headless engine drawing `width` x `height` images, with the camera looking from the front, right
+    /// and above.
+    ///
+    /// # Panics
+    ///
+    /// When there is no adapter, e.g. on machines without a GPU or software renderer.
+    pub fn new(width: u32, height: u32) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        let mut engine = pollster::block_on(
+            RenderEngineBuilder::new().build_headless(wgpu::TextureFormat::Rgba8Unorm),
+        );
+        let (pitch, yaw) = orbit_angles(StandardView::Isometric.direction());
+        let camera = OrbitCamera::new(
+            3.0,
+            pitch,
+            yaw,
+            Vector3::new(0.0, 0.0, 0.0),
+            width as f32 / height as f32,
+        );
+        let target = engine.add_render_target(width, height, Some(camera));
+        OffscreenRenderer {
+            engine,
+            target,
+            models: Vec::new(),
+            bounds: None,
+        }
+    }
+
+    /// For everything not wrapped here
+    pub fn engine(&mut self) -> &mut RenderEngine {
+        &mut self.engine
+    }
+
+    /// Adds an OBJ model and points the camera at everything loaded so far, returns the model's index
+    pub fn load_model(&mut self, path: impl AsRef<Path>) -> io::Result<usize> {
+        let mesh = assets::parse_obj(&std::fs::read_to_string(path)?)?;
+        if let Some((min, max)) = mesh.bounds() {
+            let (all_min, all_max) = self.bounds.get_or_insert((min, max));
+            for axis in 0..3 {
+                all_min[axis] = all_min[axis].min(min[axis]);
+                all_max[axis] = all_max[axis].max(max[axis]);
+            }
+        }
+        let model = Model {
+            mesh: self.engine.add_mesh(mesh),
+            material: self.engine.add_material(Material::default()),
+            transform: Transform::default(),
+        };
+        self.models.push(model);
+        self.update_renderables();
+        self.frame_all();
+        Ok(self.models.len() - 1)
+    }
+
+    /// Removes every model, the camera stays where it is
+    pub fn clear(&mut self) {
+        for model in self.models.drain(..) {
+            self.engine.remove_mesh(model.mesh);
+        }
+        self.bounds = None;
+        self.update_renderables();
+    }
+
+    pub fn model_count(&self) -> usize {
+        self.models.len()
+    }
+
+    /// Moves, turns and scales a model, does nothing for an index that wasn't loaded
+    pub fn set_transform(&mut self, model: usize, transform: Transform) {
+        if let Some(model) = self.models.get_mut(model) {
+            model.transform = transform;
+            self.update_renderables();
+        }
+    }
+
+    /// Tints a model, multiplying its vertex colors with `color`
+    pub fn set_color(&mut self, model: usize, color: [f32; 4]) {
+        let Some(model) = self.models.get(model) else {
+            return;
+        };
+        let material = Material {
+            base_color: color,
+            ..self
+                .engine
+                .material(model.material)
+                .copied()
+                .unwrap_or_default()
+        };
+        self.engine.set_material(model.material, material);
+    }
+
+    /// Changes how rough and how reflective a model is, leaving out what's [None]
+    pub fn set_surface(&mut self, model: usize, roughness: Option<f32>, reflectivity: Option<f32>) {
+        let Some(model) = self.models.get(model) else {
+            return;
+        };
+        let mut material = self
+            .engine
+            .material(model.material)
+            .copied()
+            .unwrap_or_default();
+        if let Some(roughness) = roughness {
+            material.roughness = roughness.clamp(0.0, 1.0);
+        }
+        if let Some(reflectivity) = reflectivity {
+            material.reflectivity = reflectivity.clamp(0.0, 1.0);
+        }
+        self.engine.set_material(model.material, material);
+    }
+
+    pub fn set_background(&mut self, background: Background) {
+        self.engine.set_background(background);
+    }
+
+    pub fn camera(&self) -> &OrbitCamera {
+        self.engine
+            .render_target(self.target)
+            .and_then(|target| target.camera.as_ref())
+            .expect("Offscreen target has a camera!")
+    }
+
+    pub fn camera_mut(&mut self) -> &mut OrbitCamera {
+        self.engine
+            .render_target_mut(self.target)
+            .and_then(|target| target.camera.as_mut())
+            .expect("Offscreen target has a camera!")
+    }
+
+    /// Places the camera in one call, angles in radians
+    pub fn look_at(&mut self, target: [f32; 3], yaw: f32, pitch: f32, distance: f32) {
+        let camera = self.camera_mut();
+        camera.target = target.into();
+        camera.set_distance(distance);
+        camera.set_pitch(pitch);
+        camera.set_yaw(yaw);
+    }
+
+    /// Points the camera at every model loaded, from where it looks now
+    pub fn frame_all(&mut self) {
+        if let Some((min, max)) = self.bounds {
+            self.camera_mut().frame(min.into(), max.into());
+        }
+    }
+
+    /// Changes the size of the following images
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.engine.resize_render_target(self.target, width, height);
+    }
+
+    /// Height, width and channels of the images, the shape of a numpy array holding one
+    pub fn shape(&self) -> [usize; 3] {
+        let (width, height) = self
+            .engine
+            .render_target(self.target)
+            .expect("Offscreen target exists!")
+            .size();
+        [height as usize, width as usize, 4]
+    }
+
+    /// Draws the scene and reads it back as RGBA8 rows from the top, see [OffscreenRenderer::shape]
+    pub fn render(&mut self) -> Vec<u8> {
+        self.engine.update();
+        self.engine
+            .read_render_target(self.target)
+            .expect("Failed to read back offscreen image!")
+    }
+
+    fn update_renderables(&mut self) {
+        let renderables = self
+            .models
+            .iter()
+            .map(|model| Renderable::new(model.mesh, model.material, &model.transform))
+            .collect();
+        self.engine.set_renderables(renderables);
+    }
+}
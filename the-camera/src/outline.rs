@@ -0,0 +1,139 @@
+//! Draws a line around the selected renderables, see [crate::selection]. Reads the IDs the main pass wrote, so it's
+//! exact to the pixel and outlines whatever is visible of each renderable.
+
+use wgpu::{CommandEncoder, Device, Queue, RenderPipeline, TextureFormat, TextureView};
+
+use crate::wgpu_utils::{
+    binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+    binding_types,
+    uniform_buffer::UniformBuffer,
+};
+
+/// Selected draws past this many aren't outlined
+const MAX_OUTLINED: usize = 64;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct OutlineUBOContent {
+    color: [f32; 4],
+    count: u32,
+    _padding: [u32; 3],
+    ids: [[u32; 4]; MAX_OUTLINED / 4],
+}
+
+crate::assert_uniform_layout!(OutlineUBOContent {
+    color: ALIGN_VEC4,
+    count: ALIGN_SCALAR,
+    ids: ALIGN_VEC4,
+});
+
+pub(crate) struct OutlinePass {
+    pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayoutWithDesc,
+    ubo: UniformBuffer<OutlineUBOContent>,
+}
+
+impl OutlinePass {
+    /// `format` has to be the engine's swapchain format, outlines are drawn straight into the windows
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let _span = tracing::debug_span!("create_outline_pipeline").entered();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Outline Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("outline.wgsl").into()),
+        });
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_fragment(binding_types::uniform())
+            .next_binding_fragment(binding_types::utexture2D())
+            .create(device, "Outline Bind Group");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout.layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("outline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            // Outlines show around the visible part only, the IDs already hold what's in front
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        OutlinePass {
+            pipeline,
+            bind_group_layout,
+            ubo: UniformBuffer::new(device),
+        }
+    }
+
+    /// Records a pass outlining the draws with `ids` into `view`, `id_texture` being the IDs the main pass wrote for
+    /// it. Does nothing without any.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        view: &TextureView,
+        id_texture: &TextureView,
+        ids: impl IntoIterator<Item = u32>,
+        color: [f32; 4],
+    ) {
+        let mut content = OutlineUBOContent {
+            color,
+            count: 0,
+            _padding: [0; 3],
+            ids: [[0; 4]; MAX_OUTLINED / 4],
+        };
+        for id in ids.into_iter().take(MAX_OUTLINED) {
+            let index = content.count as usize;
+            content.ids[index / 4][index % 4] = id;
+            content.count += 1;
+        }
+        if content.count == 0 {
+            return;
+        }
+        self.ubo.update_content(queue, content);
+        // The ID texture is recreated with the window, so the bind group can't be kept
+        let bind_group = BindGroupBuilder::new(&self.bind_group_layout)
+            .resource(self.ubo.binding_resource())
+            .texture(id_texture)
+            .create(device, "Outline Bind Group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("outline"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,61 @@
+// Outlines the selected renderables, found by their IDs in the texture the main pass wrote them to
+
+struct Outline {
+    color: vec4<f32>,
+    count: u32,
+    // Four IDs to an element, uniform arrays are laid out 16 bytes apart
+    ids: array<vec4<u32>, 16>,
+}
+@group(0) @binding(0)
+var<uniform> outline: Outline;
+@group(0) @binding(1)
+var id_texture: texture_2d<u32>;
+
+// Pixels this far from a selected one are outlined
+const WIDTH: i32 = 2;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn is_selected(id: u32) -> bool {
+    // 0 is the ID of nothing
+    if id == 0u {
+        return false;
+    }
+    for (var index = 0u; index < outline.count; index++) {
+        if outline.ids[index / 4u][index % 4u] == id {
+            return true;
+        }
+    }
+    return false;
+}
+
+fn selected_at(pixel: vec2<i32>) -> bool {
+    let size = vec2<i32>(textureDimensions(id_texture));
+    if any(pixel < vec2<i32>(0)) || any(pixel >= size) {
+        return false;
+    }
+    return is_selected(textureLoad(id_texture, pixel, 0).r);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    // Only around the selection, not over it
+    if selected_at(pixel) {
+        discard;
+    }
+    var near = false;
+    for (var y = -WIDTH; y <= WIDTH; y++) {
+        for (var x = -WIDTH; x <= WIDTH; x++) {
+            near = near || selected_at(pixel + vec2<i32>(x, y));
+        }
+    }
+    if !near {
+        discard;
+    }
+    return outline.color;
+}
@@ -0,0 +1,403 @@
+//! Lines and triangles laid out in world space on the CPU and drawn over the windows every frame, used by the
+//! [crate::gizmo] handles, the [crate::debug] shapes, [crate::measure] results and [crate::annotation] pins.
+
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3, Vector4};
+use wgpu::{BindGroup, CommandEncoder, Device, Queue, RenderPipeline, TextureFormat, TextureView};
+use winit::window::WindowId;
+
+use crate::{
+    camera::{camera::Camera, orbit_camera::OrbitCamera},
+    debug_hud::{lit_runs, GLYPH_ADVANCE},
+    gizmo::Ray,
+    global_bindings::GlobalBindings,
+    wgpu_utils::binding_builder::{
+        BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc,
+    },
+};
+
+const CONE_SEGMENTS: usize = 8;
+/// Screen pixels per font pixel
+pub(crate) const TEXT_PIXEL: f32 = 2.0;
+const GLYPH_HEIGHT: f32 = 7.0;
+
+/// What overlays need to know about the camera of the window they're drawn into
+#[derive(Clone)]
+pub(crate) struct OverlayView {
+    inverse_view_proj: Matrix4<f32>,
+    /// Camera axes in world space, pointing right and up on screen
+    right: Vector3<f32>,
+    up: Vector3<f32>,
+    /// For the size of pixels, which depends on the projection
+    camera: OrbitCamera,
+    width: f32,
+    height: f32,
+}
+
+impl OverlayView {
+    /// None for windows without area
+    pub fn new(camera: &OrbitCamera, width: u32, height: u32) -> Option<Self> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let forward = (camera.target - camera.eye).normalize();
+        let right = forward.cross(camera.up).normalize();
+        Some(OverlayView {
+            inverse_view_proj: camera.build_view_projection_matrix().invert()?,
+            right,
+            up: right.cross(forward),
+            camera: *camera,
+            width: width as f32,
+            height: height as f32,
+        })
+    }
+
+    /// Through the pixel at `cursor`, counted in physical pixels from the top left
+    pub fn ray(&self, cursor: [f32; 2]) -> Ray {
+        let near = self.unproject(cursor, 0.0);
+        Ray {
+            origin: near,
+            direction: (self.unproject(cursor, 1.0) - near).normalize(),
+        }
+    }
+
+    /// The world position seen at `cursor` with a depth buffer value of `depth`, 0 on the near plane and 1 on the far
+    pub fn unproject(&self, cursor: [f32; 2], depth: f32) -> Vector3<f32> {
+        let x = cursor[0] / self.width * 2.0 - 1.0;
+        let y = 1.0 - cursor[1] / self.height * 2.0;
+        let point = self.inverse_view_proj * Vector4::new(x, y, depth, 1.0);
+        point.truncate() / point.w
+    }
+
+    pub fn right(&self) -> Vector3<f32> {
+        self.right
+    }
+
+    pub fn up(&self) -> Vector3<f32> {
+        self.up
+    }
+
+    /// How long a pixel is in world units at `point`
+    pub fn pixel_size(&self, point: Vector3<f32>) -> f32 {
+        self.camera.view_height(point) / self.height
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct OverlayVertex {
+    position: [f32; 3],
+    color: [f32; 4],
+    /// Faded where this point is hidden by the scene, with w 1. Ignored with w 0.
+    fade_anchor: [f32; 4],
+}
+
+/// World space lines and triangles to draw over a window
+#[derive(Default)]
+pub(crate) struct OverlayGeometry {
+    lines: Vec<OverlayVertex>,
+    triangles: Vec<OverlayVertex>,
+    /// Of everything added, see [OverlayGeometry::fade_behind]
+    fade_anchor: [f32; 4],
+}
+
+impl OverlayGeometry {
+    fn vertex(&self, position: Vector3<f32>, color: [f32; 4]) -> OverlayVertex {
+        OverlayVertex {
+            position: position.into(),
+            color,
+            fade_anchor: self.fade_anchor,
+        }
+    }
+
+    /// Fades what's added from now on while `anchor` is behind something in the scene, or never with None
+    pub fn fade_behind(&mut self, anchor: Option<Vector3<f32>>) {
+        self.fade_anchor = anchor.map_or([0.0; 4], |anchor| anchor.extend(1.0).into());
+    }
+
+    pub fn line(&mut self, from: Vector3<f32>, to: Vector3<f32>, color: [f32; 4]) {
+        self.lines.push(self.vertex(from, color));
+        self.lines.push(self.vertex(to, color));
+    }
+
+    pub fn triangle(&mut self, corners: [Vector3<f32>; 3], color: [f32; 4]) {
+        let vertices = corners.map(|corner| self.vertex(corner, color));
+        self.triangles.extend(vertices);
+    }
+
+    pub fn quad(&mut self, [a, b, c, d]: [Vector3<f32>; 4], color: [f32; 4]) {
+        self.triangle([a, b, c], color);
+        self.triangle([a, c, d], color);
+    }
+
+    /// From a disc at `base` spanned by `u` and `v` to `tip`
+    pub fn cone(
+        &mut self,
+        base: Vector3<f32>,
+        tip: Vector3<f32>,
+        (u, v): (Vector3<f32>, Vector3<f32>),
+        color: [f32; 4],
+    ) {
+        let rim = |segment: usize| {
+            let angle = segment as f32 / CONE_SEGMENTS as f32 * std::f32::consts::TAU;
+            base + u * angle.cos() + v * angle.sin()
+        };
+        for segment in 0..CONE_SEGMENTS {
+            let (a, b) = (rim(segment), rim(segment + 1));
+            self.triangle([tip, a, b], color);
+            self.triangle([base, b, a], color);
+        }
+    }
+
+    /// Around `center`, reaching `half_extents` along each of its axes
+    pub fn cube(&mut self, center: Vector3<f32>, half_extents: [Vector3<f32>; 3], color: [f32; 4]) {
+        for (index, &normal) in half_extents.iter().enumerate() {
+            let u = half_extents[(index + 1) % 3];
+            let v = half_extents[(index + 2) % 3];
+            for face in [center + normal, center - normal] {
+                self.quad(
+                    [face - u - v, face + u - v, face + u + v, face - u + v],
+                    color,
+                );
+            }
+        }
+    }
+
+    /// A line of text centered on `position`, facing the camera of `view` and keeping the same size on screen. Drawn
+    /// in the debug HUD's font, letters uppercase and characters the font lacks as `?`.
+    pub fn text(
+        &mut self,
+        view: &OverlayView,
+        position: Vector3<f32>,
+        text: &str,
+        color: [f32; 4],
+    ) {
+        let pixel = TEXT_PIXEL * view.pixel_size(position);
+        self.text_in_plane(position, (view.right(), view.up()), pixel, text, color);
+    }
+
+    /// A line of text centered on `position` in the plane of the unit vectors `right` and `up`, each font pixel
+    /// `pixel` world units across. Front facing seen with `right` pointing right and `up` up.
+    pub fn text_in_plane(
+        &mut self,
+        position: Vector3<f32>,
+        (right, up): (Vector3<f32>, Vector3<f32>),
+        pixel: f32,
+        text: &str,
+        color: [f32; 4],
+    ) {
+        let width = text.chars().count() as f32 * GLYPH_ADVANCE - 1.0;
+        let top_left = position - right * (width / 2.0 * pixel) + up * (GLYPH_HEIGHT / 2.0 * pixel);
+        for [column, row, length] in lit_runs(text) {
+            let corner = |x: f32, y: f32| top_left + right * (x * pixel) - up * (y * pixel);
+            self.quad(
+                [
+                    corner(column, row + 1.0),
+                    corner(column + length, row + 1.0),
+                    corner(column + length, row),
+                    corner(column, row),
+                ],
+                color,
+            );
+        }
+    }
+
+    /// Moves everything added by `matrix`, dividing by w. Taken into clip space, the geometry can be drawn
+    /// without a camera.
+    pub fn transform(&mut self, matrix: Matrix4<f32>) {
+        for vertex in self.lines.iter_mut().chain(&mut self.triangles) {
+            let position = matrix * Vector3::from(vertex.position).extend(1.0);
+            vertex.position = (position.truncate() / position.w).into();
+        }
+    }
+
+    pub fn triangles(&self) -> &[OverlayVertex] {
+        &self.triangles
+    }
+
+    /// Adds everything in `other`, drawn over what's already there
+    pub fn append(&mut self, mut other: OverlayGeometry) {
+        self.lines.append(&mut other.lines);
+        self.triangles.append(&mut other.triangles);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty() && self.triangles.is_empty()
+    }
+}
+
+/// Draws [OverlayGeometry] over the windows, on top of everything in the scene
+pub(crate) struct OverlayPass {
+    lines: RenderPipeline,
+    triangles: RenderPipeline,
+    /// The depth buffer of the window drawn into, for fading
+    depth_bind_group_layout: BindGroupLayoutWithDesc,
+    /// Rewritten every frame, one per window like the debug HUD's
+    vertex_buffers: HashMap<WindowId, wgpu::Buffer>,
+}
+
+impl OverlayPass {
+    /// `format` has to be the engine's swapchain format, overlays are drawn straight into the windows
+    pub fn new(device: &Device, format: TextureFormat, global_bindings: &GlobalBindings) -> Self {
+        let _span = tracing::debug_span!("create_overlay_pipelines").entered();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Overlay Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("overlay.wgsl").into()),
+        });
+        let depth_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_vertex(wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            })
+            .create(device, "Overlay Depth Bind Group");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                global_bindings.bind_group_layouts(),
+                &depth_bind_group_layout.layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label: &str, topology: wgpu::PrimitiveTopology| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: std::mem::size_of::<OverlayVertex>() as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &[
+                            wgpu::VertexAttribute {
+                                offset: 0,
+                                shader_location: 0,
+                                format: wgpu::VertexFormat::Float32x3,
+                            },
+                            wgpu::VertexAttribute {
+                                offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                                shader_location: 1,
+                                format: wgpu::VertexFormat::Float32x4,
+                            },
+                            wgpu::VertexAttribute {
+                                offset: std::mem::size_of::<[f32; 7]>() as wgpu::BufferAddress,
+                                shader_location: 2,
+                                format: wgpu::VertexFormat::Float32x4,
+                            },
+                        ],
+                    }],
+                    compilation_options: Default::default(),
+                },
+                primitive: wgpu::PrimitiveState {
+                    topology,
+                    strip_index_format: None,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    conservative: false,
+                    unclipped_depth: false,
+                },
+                // Overlays show what's behind objects too, so they're drawn over them
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some("fs_main"),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        OverlayPass {
+            lines: create_pipeline("overlay lines", wgpu::PrimitiveTopology::LineList),
+            triangles: create_pipeline("overlay triangles", wgpu::PrimitiveTopology::TriangleList),
+            depth_bind_group_layout,
+            vertex_buffers: HashMap::new(),
+        }
+    }
+
+    /// Records a pass drawing `geometry` into `view`, with the window's camera bound as `globals` and its depth buffer
+    /// as `depth`
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        window_id: WindowId,
+        view: &TextureView,
+        depth: &TextureView,
+        globals: &BindGroup,
+        geometry: &OverlayGeometry,
+    ) {
+        if geometry.is_empty() {
+            return;
+        }
+        let lines: &[u8] = bytemuck::cast_slice(&geometry.lines);
+        let triangles: &[u8] = bytemuck::cast_slice(&geometry.triangles);
+        let size = (lines.len() + triangles.len()) as u64;
+        let buffer = self
+            .vertex_buffers
+            .entry(window_id)
+            .or_insert_with(|| create_vertex_buffer(device, size));
+        if buffer.size() < size {
+            *buffer = create_vertex_buffer(device, size);
+        }
+        queue.write_buffer(buffer, 0, lines);
+        queue.write_buffer(buffer, lines.len() as u64, triangles);
+        // The depth buffer is recreated with the window, so the bind group can't be kept
+        let depth_bind_group = BindGroupBuilder::new(&self.depth_bind_group_layout)
+            .texture(depth)
+            .create(device, "Overlay Depth Bind Group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("overlay"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_bind_group(0, globals, &[]);
+        render_pass.set_bind_group(1, &depth_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, buffer.slice(..));
+        // Triangles first, so lines show through translucent ones
+        render_pass.set_pipeline(&self.triangles);
+        let first_triangle = geometry.lines.len() as u32;
+        render_pass.draw(
+            first_triangle..first_triangle + geometry.triangles.len() as u32,
+            0..1,
+        );
+        render_pass.set_pipeline(&self.lines);
+        render_pass.draw(0..first_triangle, 0..1);
+    }
+
+    /// Stops keeping a vertex buffer for the window
+    pub fn remove_window(&mut self, window_id: WindowId) {
+        self.vertex_buffers.remove(&window_id);
+    }
+}
+
+fn create_vertex_buffer(device: &Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Overlay Vertices"),
+        size: size.next_power_of_two(),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
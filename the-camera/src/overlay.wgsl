@@ -0,0 +1,58 @@
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// Of the window drawn into, written by its main pass. Bound as a float texture, GL can't load from depth textures.
+@group(1) @binding(0)
+var depth_texture: texture_2d<f32>;
+
+// Handles are laid out in world space on the CPU, already sized for the window they're drawn into
+
+// Alpha is multiplied by this while the fade anchor is hidden
+const FADED: f32 = 0.25;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) fade_anchor: vec4<f32>,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+// Whether something in the scene is in front of `anchor`
+fn is_hidden(anchor: vec3<f32>) -> bool {
+    // Moved a little towards the camera, so points on a surface aren't hidden by it
+    let clip = camera.view_proj * vec4<f32>(mix(anchor, camera.view_pos.xyz, 0.01), 1.0);
+    if clip.w <= 0.0 {
+        return false;
+    }
+    let ndc = clip.xyz / clip.w;
+    let size = vec2<f32>(textureDimensions(depth_texture));
+    let pixel = vec2<i32>(vec2<f32>(ndc.x + 1.0, 1.0 - ndc.y) / 2.0 * size);
+    if any(pixel < vec2<i32>(0)) || any(pixel >= vec2<i32>(size)) {
+        return false;
+    }
+    return ndc.z > textureLoad(depth_texture, pixel, 0).r;
+}
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    out.position = camera.view_proj * vec4<f32>(in.position, 1.0);
+    out.color = in.color;
+    if in.fade_anchor.w > 0.0 && is_hidden(in.fade_anchor.xyz) {
+        out.color.a *= FADED;
+    }
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
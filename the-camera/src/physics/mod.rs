@@ -0,0 +1,153 @@
+//! Glue for physics engines such as rapier: rigid body poses move the engine's [Transform]s and colliders are drawn
+//! as wireframes through [crate::debug]. Apps convert their physics engine's isometries and shapes into [BodyPose]
+//! and [ColliderShape] after every step:
+//!
+//! ```ignore
+//! for (handle, body) in bodies.iter() {
+//!     let pose = BodyPose::new(body.translation().into(), (*body.rotation()).into());
+//!     pose.apply(&mut transforms[nodes[&handle]]);
+//! }
+//! for (_, collider) in colliders.iter() {
+//!     physics::draw_collider(&shape_of(collider), &pose_of(collider), [0.2, 0.9, 0.4, 1.0]);
+//! }
+//! ```
+//!
+//! With the `rapier` feature the conversions come with it: [BodyPose] from rapier's isometries,
+//! [ColliderShape::from_rapier], [sync_rigid_bodies] moving transforms to the bodies of a `RigidBodySet` and
+//! [draw_rapier_colliders] drawing a whole `ColliderSet`.
+
+#[cfg(feature = "rapier")]
+mod rapier;
+
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3};
+
+use crate::{debug, scene::Transform};
+
+#[cfg(feature = "rapier")]
+pub use self::rapier::{draw_rapier_colliders, sync_rigid_bodies};
+
+/// Segments of each full circle in a wireframe
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// Where a rigid body or collider is, without scale like physics engines report it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BodyPose {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+}
+
+impl BodyPose {
+    pub fn new(translation: Vector3<f32>, rotation: Quaternion<f32>) -> Self {
+        BodyPose {
+            translation,
+            rotation,
+        }
+    }
+
+    /// Moves and turns `transform` to the pose, keeping its scale
+    pub fn apply(&self, transform: &mut Transform) {
+        transform.translation = self.translation;
+        transform.rotation = self.rotation;
+    }
+
+    fn point(&self, local: Vector3<f32>) -> Vector3<f32> {
+        self.translation + self.rotation.rotate_vector(local)
+    }
+}
+
+/// The collider shapes drawn as wireframes, named like rapier's. Round shapes are upright along their local y axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColliderShape {
+    Cuboid { half_extents: Vector3<f32> },
+    Ball { radius: f32 },
+    Capsule { half_height: f32, radius: f32 },
+    Cylinder { half_height: f32, radius: f32 },
+}
+
+/// Adds the wireframe of `shape` at `pose` to this update's debug shapes
+pub fn draw_collider(shape: &ColliderShape, pose: &BodyPose, color: [f32; 4]) {
+    let line = |from: Vector3<f32>, to: Vector3<f32>| {
+        debug::line(pose.point(from), pose.point(to), color);
+    };
+    // Part of the circle around `center` through `center + u` and `center + v`, from `start` of a full turn on for
+    // `turns` of one
+    let arc = |center: Vector3<f32>, u: Vector3<f32>, v: Vector3<f32>, start: f32, turns: f32| {
+        let segments = ((CIRCLE_SEGMENTS as f32 * turns).ceil() as usize).max(1);
+        let at = |segment: usize| {
+            let angle = (start + turns * segment as f32 / segments as f32) * std::f32::consts::TAU;
+            center + u * angle.cos() + v * angle.sin()
+        };
+        for segment in 0..segments {
+            line(at(segment), at(segment + 1));
+        }
+    };
+    let (x, y, z) = (Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z());
+    match *shape {
+        ColliderShape::Cuboid { half_extents: h } => {
+            let corner = |index: usize| {
+                Vector3::new(
+                    if index & 1 == 0 { -h.x } else { h.x },
+                    if index & 2 == 0 { -h.y } else { h.y },
+                    if index & 4 == 0 { -h.z } else { h.z },
+                )
+            };
+            // Every pair of corners differing in one coordinate
+            for index in 0..8 {
+                for bit in [1, 2, 4] {
+                    if index & bit == 0 {
+                        line(corner(index), corner(index | bit));
+                    }
+                }
+            }
+        }
+        ColliderShape::Ball { radius } => {
+            for (u, v) in [(x, y), (y, z), (z, x)] {
+                arc(
+                    Vector3::new(0.0, 0.0, 0.0),
+                    u * radius,
+                    v * radius,
+                    0.0,
+                    1.0,
+                );
+            }
+        }
+        ColliderShape::Capsule {
+            half_height,
+            radius,
+        } => {
+            let (top, bottom) = (y * half_height, -y * half_height);
+            for center in [top, bottom] {
+                arc(center, x * radius, z * radius, 0.0, 1.0);
+            }
+            for side in [x, z] {
+                let side = side * radius;
+                line(top + side, bottom + side);
+                line(top - side, bottom - side);
+                // The half circles closing the ends, over the top and under the bottom
+                let up = y * radius;
+                arc(top, side, up, 0.0, 0.5);
+                arc(bottom, side, up, 0.5, 0.5);
+            }
+        }
+        ColliderShape::Cylinder {
+            half_height,
+            radius,
+        } => {
+            let (top, bottom) = (y * half_height, -y * half_height);
+            for center in [top, bottom] {
+                arc(center, x * radius, z * radius, 0.0, 1.0);
+            }
+            for side in [x, z, -x, -z] {
+                line(top + side * radius, bottom + side * radius);
+            }
+        }
+    }
+}
+
+/// The pose of a collider attached to a body at `offset` from it, for shapes that don't sit at the body's center
+pub fn collider_pose(body: &BodyPose, offset: &BodyPose) -> BodyPose {
+    BodyPose {
+        translation: body.point(offset.translation),
+        rotation: (body.rotation * offset.rotation).normalize(),
+    }
+}
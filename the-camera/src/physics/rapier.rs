@@ -0,0 +1,110 @@
+//! Conversions from rapier's bodies and colliders, with the `rapier` feature
+
+use cgmath::{InnerSpace, Quaternion, Vector3};
+use rapier3d::{
+    geometry::{ColliderSet, Shape, TypedShape},
+    math::{Isometry, Real, Vector},
+    prelude::{RigidBodyHandle, RigidBodySet},
+};
+
+use super::{collider_pose, draw_collider, BodyPose, ColliderShape};
+use crate::scene::Transform;
+
+fn vector(vector: &Vector<Real>) -> Vector3<f32> {
+    Vector3::new(vector.x, vector.y, vector.z)
+}
+
+impl From<&Isometry<Real>> for BodyPose {
+    fn from(isometry: &Isometry<Real>) -> Self {
+        let rotation = isometry.rotation;
+        BodyPose::new(
+            vector(&isometry.translation.vector),
+            Quaternion::new(rotation.w, rotation.i, rotation.j, rotation.k),
+        )
+    }
+}
+
+impl ColliderShape {
+    /// The wireframe shape of a rapier shape and where it sits in the collider, None for shapes without one, e.g.
+    /// meshes and heightfields. Rounded cuboids and cylinders are drawn as large as their rounded corners reach.
+    pub fn from_rapier(shape: &dyn Shape) -> Option<(ColliderShape, BodyPose)> {
+        let centered = |shape| {
+            let pose = BodyPose::new(
+                Vector3::new(0.0, 0.0, 0.0),
+                Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            );
+            Some((shape, pose))
+        };
+        match shape.as_typed_shape() {
+            TypedShape::Cuboid(cuboid) => centered(ColliderShape::Cuboid {
+                half_extents: vector(&cuboid.half_extents),
+            }),
+            TypedShape::RoundCuboid(round) => centered(ColliderShape::Cuboid {
+                half_extents: vector(&round.inner_shape.half_extents)
+                    + Vector3::new(1.0, 1.0, 1.0) * round.border_radius,
+            }),
+            TypedShape::Ball(ball) => centered(ColliderShape::Ball {
+                radius: ball.radius,
+            }),
+            TypedShape::Cylinder(cylinder) => centered(ColliderShape::Cylinder {
+                half_height: cylinder.half_height,
+                radius: cylinder.radius,
+            }),
+            TypedShape::RoundCylinder(round) => centered(ColliderShape::Cylinder {
+                half_height: round.inner_shape.half_height + round.border_radius,
+                radius: round.inner_shape.radius + round.border_radius,
+            }),
+            // Rapier's capsules go between two points, ours stand upright around the center
+            TypedShape::Capsule(capsule) => {
+                let (a, b) = (
+                    vector(&capsule.segment.a.coords),
+                    vector(&capsule.segment.b.coords),
+                );
+                let axis = b - a;
+                let rotation = if axis.magnitude2() > 0.0 {
+                    Quaternion::from_arc(Vector3::unit_y(), axis.normalize(), None)
+                } else {
+                    Quaternion::new(1.0, 0.0, 0.0, 0.0)
+                };
+                let shape = ColliderShape::Capsule {
+                    half_height: axis.magnitude() / 2.0,
+                    radius: capsule.radius,
+                };
+                Some((shape, BodyPose::new((a + b) / 2.0, rotation)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Moves each transform to where its body in `bodies` is, keeping its scale. Transforms of bodies that were removed
+/// stay where they are.
+pub fn sync_rigid_bodies<'a>(
+    bodies: &RigidBodySet,
+    transforms: impl IntoIterator<Item = (RigidBodyHandle, &'a mut Transform)>,
+) {
+    for (handle, transform) in transforms {
+        if let Some(body) = bodies.get(handle) {
+            BodyPose::from(body.position()).apply(transform);
+        }
+    }
+}
+
+/// Adds the wireframes of every collider in `colliders` to this update's debug shapes, at the poses of the last
+/// step. Compound shapes are drawn part by part, shapes [ColliderShape::from_rapier] has no wireframe for are left
+/// out.
+pub fn draw_rapier_colliders(colliders: &ColliderSet, color: [f32; 4]) {
+    for (_, collider) in colliders.iter() {
+        draw_shape(collider.shape(), &collider.position().into(), color);
+    }
+}
+
+fn draw_shape(shape: &dyn Shape, pose: &BodyPose, color: [f32; 4]) {
+    if let TypedShape::Compound(compound) = shape.as_typed_shape() {
+        for (offset, part) in compound.shapes() {
+            draw_shape(part.as_ref(), &collider_pose(pose, &offset.into()), color);
+        }
+    } else if let Some((wireframe, offset)) = ColliderShape::from_rapier(shape) {
+        draw_collider(&wireframe, &collider_pose(pose, &offset), color);
+    }
+}
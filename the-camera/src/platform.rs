@@ -0,0 +1,132 @@
+//! What the engine needs from the windows it draws into, so shells other than winit, e.g. SDL2, tao or a custom one,
+//! can drive it. Windows are [SurfaceProvider]s and input arrives as [crate::input::InputEvent]s:
+//!
+//! ```ignore
+//! engine.add_window(Arc::new(MySdlWindow::new(...)));
+//! // For every SDL event
+//! engine.process_input_event(window_id, &InputEvent::CursorMoved([x, y]));
+//! ```
+//!
+//! winit's windows are providers already, and [crate::render_engine::RenderEngine::process_window_event] converts
+//! winit's events. Windows owned by an application that only hands out their raw handles, e.g. a game editor or a
+//! plugin host, are wrapped in a [RawWindow].
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use wgpu::rwh::{
+    DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, RawDisplayHandle,
+    RawWindowHandle, WindowHandle,
+};
+use winit::window::{CursorGrabMode, Window, WindowId};
+
+use crate::input::CursorMode;
+
+/// A window the engine can create a surface for. The raw window and display handles come from
+/// [wgpu::rwh], which most windowing libraries implement.
+pub trait SurfaceProvider: wgpu::WindowHandle + 'static {
+    /// Stays the same for as long as the window lives, events are matched to windows by it
+    fn id(&self) -> WindowId;
+
+    /// Of the area to draw into, in physical pixels. Zero while minimized on some platforms.
+    fn inner_size(&self) -> [u32; 2];
+
+    /// Asks for the window to be drawn again. The shell calls [crate::render_engine::RenderEngine::update] and
+    /// [crate::render_engine::RenderEngine::render_frame] when it's time.
+    fn request_redraw(&self);
+
+    /// Hides and grabs the cursor as `mode` says, as far as the platform can. Does nothing unless implemented.
+    fn set_cursor_mode(&self, _mode: CursorMode) {}
+}
+
+impl SurfaceProvider for Window {
+    fn id(&self) -> WindowId {
+        Window::id(self)
+    }
+
+    fn inner_size(&self) -> [u32; 2] {
+        let size = Window::inner_size(self);
+        [size.width, size.height]
+    }
+
+    fn request_redraw(&self) {
+        Window::request_redraw(self);
+    }
+
+    /// Platforms lacking one kind of grab get the other: X11 and Windows can't lock the cursor and macOS can't
+    /// confine it.
+    fn set_cursor_mode(&self, mode: CursorMode) {
+        let result = match mode {
+            CursorMode::Normal | CursorMode::Hidden => self.set_cursor_grab(CursorGrabMode::None),
+            CursorMode::Confined => self
+                .set_cursor_grab(CursorGrabMode::Confined)
+                .or_else(|_| self.set_cursor_grab(CursorGrabMode::Locked)),
+            CursorMode::Locked => self
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| self.set_cursor_grab(CursorGrabMode::Confined)),
+        };
+        if let Err(err) = result {
+            tracing::warn!("Failed to grab the cursor for {mode:?}: {err}");
+        }
+        self.set_cursor_visible(matches!(mode, CursorMode::Normal | CursorMode::Confined));
+    }
+}
+
+/// Hands out IDs from the top down, winit's own start low
+static NEXT_RAW_WINDOW_ID: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// A window known only by its raw handles. The application owning it keeps track of its size and tells the engine
+/// through [crate::render_engine::RenderEngine::resize], and draws frames when it wants to, redraw requests are
+/// ignored.
+#[derive(Debug)]
+pub struct RawWindow {
+    id: WindowId,
+    display: RawDisplayHandle,
+    window: RawWindowHandle,
+    size: [u32; 2],
+}
+
+// The handles are only passed to wgpu when the engine creates a surface, the engine stays on the application's thread
+unsafe impl Send for RawWindow {}
+unsafe impl Sync for RawWindow {}
+
+impl RawWindow {
+    /// `size` is the window's size in physical pixels when the engine starts drawing into it.
+    ///
+    /// # Safety
+    ///
+    /// The handles have to be valid until the engine is done with the window, i.e. until it's removed with
+    /// [crate::render_engine::RenderEngine::remove_window] or the engine is dropped.
+    pub unsafe fn new(display: RawDisplayHandle, window: RawWindowHandle, size: [u32; 2]) -> Self {
+        RawWindow {
+            id: WindowId::from(NEXT_RAW_WINDOW_ID.fetch_sub(1, Ordering::Relaxed)),
+            display,
+            window,
+            size,
+        }
+    }
+}
+
+impl HasWindowHandle for RawWindow {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        // Valid for as long as the engine uses it, see [RawWindow::new]
+        Ok(unsafe { WindowHandle::borrow_raw(self.window) })
+    }
+}
+
+impl HasDisplayHandle for RawWindow {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        Ok(unsafe { DisplayHandle::borrow_raw(self.display) })
+    }
+}
+
+impl SurfaceProvider for RawWindow {
+    fn id(&self) -> WindowId {
+        self.id
+    }
+
+    fn inner_size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    fn request_redraw(&self) {}
+}
@@ -0,0 +1,663 @@
+//! Small 2D charts drawn over every window, for graphing simulation metrics or profiler timings in the app itself.
+//!
+//! A [Plot] holds one or more [PlotSeries], rolling buffers keeping the latest values pushed into them, and draws
+//! them as lines or a histogram in a panel anchored to a window corner. Feed it once per update, e.g. the frame time:
+//!
+//! ```ignore
+//! let plot = engine.add_plot(Plot::new("FRAME MS", PlotKind::Line).with_series(PlotSeries::new("CPU", 240)));
+//! // Every update
+//! let frame_time = engine.frame_stats().cpu_frame_time.as_secs_f32() * 1000.0;
+//! engine.plot_mut(plot).unwrap().push(0, frame_time);
+//! ```
+//!
+//! Everything is laid out on the CPU as thick line segments in pixels, including the [crate::debug_hud] font, which
+//! are instanced and antialiased by their own pipeline. Unlike the HUD, lines keep their width at any angle.
+
+use std::collections::{HashMap, VecDeque};
+
+use wgpu::{BindGroup, CommandEncoder, Device, Queue, RenderPipeline, TextureFormat, TextureView};
+use winit::window::WindowId;
+
+use crate::{
+    debug_hud::{lit_runs, BACKGROUND, GLYPH_ADVANCE, LINE_HEIGHT, PADDING, PIXEL, TEXT},
+    lazy_pass::LazyPass,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        uniform_buffer::UniformBuffer,
+    },
+};
+
+/// Of the lines, in pixels
+const LINE_WIDTH: f32 = 2.0;
+const GRID_COLOR: [f32; 4] = [0.5, 0.5, 0.5, 0.6];
+/// The default colors of series, in the order they're added
+const SERIES_COLORS: [[f32; 4]; 4] = [
+    [0.2, 0.8, 0.2, 1.0],
+    [0.3, 0.6, 1.0, 1.0],
+    [0.9, 0.8, 0.1, 1.0],
+    [0.9, 0.3, 0.3, 1.0],
+];
+
+/// The latest values of one quantity, oldest first. Pushing into a full series drops its oldest value.
+#[derive(Debug, Clone)]
+pub struct PlotSeries {
+    /// Shown in the legend, with the latest value
+    pub label: String,
+    /// None for the next one of the plot's default colors
+    pub color: Option<[f32; 4]>,
+    values: VecDeque<f32>,
+    capacity: usize,
+}
+
+impl PlotSeries {
+    /// Keeps the latest `capacity` values
+    pub fn new(label: impl Into<String>, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        PlotSeries {
+            label: label.into(),
+            color: None,
+            values: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn push(&mut self, value: f32) {
+        if self.values.len() == self.capacity {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    pub fn extend(&mut self, values: impl IntoIterator<Item = f32>) {
+        for value in values {
+            self.push(value);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Oldest first
+    pub fn values(&self) -> impl ExactSizeIterator<Item = f32> + '_ {
+        self.values.iter().copied()
+    }
+
+    pub fn latest(&self) -> Option<f32> {
+        self.values.back().copied()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Drops the oldest values that don't fit anymore
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+        while self.values.len() > self.capacity {
+            self.values.pop_front();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlotKind {
+    /// Values over time, the newest on the right. Series that aren't full yet start further right.
+    #[default]
+    Line,
+    /// How many values of each series fall into each of `bins` bins between the ends of the range
+    Histogram { bins: u32 },
+}
+
+/// The window corner a plot is placed at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlotAnchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+/// A chart drawn over every window, added with [crate::render_engine::RenderEngine::add_plot]
+#[derive(Debug, Clone)]
+pub struct Plot {
+    pub title: String,
+    pub kind: PlotKind,
+    pub series: Vec<PlotSeries>,
+    /// The values at the bottom and top of a line chart or the ends of a histogram's bins, from the smallest to the
+    /// largest value shown when None
+    pub range: Option<[f32; 2]>,
+    pub anchor: PlotAnchor,
+    /// From the anchor corner into the window, in pixels
+    pub offset: [f32; 2],
+    /// Of the whole panel, in pixels
+    pub size: [f32; 2],
+    pub visible: bool,
+}
+
+impl Plot {
+    pub fn new(title: impl Into<String>, kind: PlotKind) -> Self {
+        Plot {
+            title: title.into(),
+            kind,
+            series: Vec::new(),
+            range: None,
+            anchor: PlotAnchor::default(),
+            offset: [8.0, 8.0],
+            size: [280.0, 140.0],
+            visible: true,
+        }
+    }
+
+    pub fn with_series(mut self, series: PlotSeries) -> Self {
+        self.series.push(series);
+        self
+    }
+
+    pub fn with_range(mut self, low: f32, high: f32) -> Self {
+        self.range = Some([low, high]);
+        self
+    }
+
+    pub fn with_anchor(mut self, anchor: PlotAnchor, offset: [f32; 2]) -> Self {
+        self.anchor = anchor;
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_size(mut self, width: f32, height: f32) -> Self {
+        self.size = [width, height];
+        self
+    }
+
+    /// Pushes a value into the series at `index`, does nothing for an index without one
+    pub fn push(&mut self, index: usize, value: f32) {
+        if let Some(series) = self.series.get_mut(index) {
+            series.push(value);
+        }
+    }
+
+    /// [Plot::range] or the smallest and largest finite value, spread apart when they're the same
+    fn value_range(&self) -> Option<[f32; 2]> {
+        let [low, high] = self.range.or_else(|| {
+            self.series
+                .iter()
+                .flat_map(PlotSeries::values)
+                .filter(|value| value.is_finite())
+                .fold(None, |range, value| match range {
+                    None => Some([value, value]),
+                    Some([low, high]) => Some([f32::min(low, value), f32::max(high, value)]),
+                })
+        })?;
+        if high > low {
+            Some([low, high])
+        } else {
+            let spread = low.abs().max(1.0) * 0.5;
+            Some([low - spread, high + spread])
+        }
+    }
+
+    fn color(&self, index: usize) -> [f32; 4] {
+        self.series[index]
+            .color
+            .unwrap_or(SERIES_COLORS[index % SERIES_COLORS.len()])
+    }
+
+    /// The panel, the legend and the chart, for a window `width` x `height` pixels
+    fn layout(&self, segments: &mut Segments, width: f32, height: f32) {
+        let [panel_width, panel_height] = self.size;
+        let [offset_x, offset_y] = self.offset;
+        let left = match self.anchor {
+            PlotAnchor::TopLeft | PlotAnchor::BottomLeft => offset_x,
+            PlotAnchor::TopRight | PlotAnchor::BottomRight => width - offset_x - panel_width,
+        };
+        let top = match self.anchor {
+            PlotAnchor::TopLeft | PlotAnchor::TopRight => offset_y,
+            PlotAnchor::BottomLeft | PlotAnchor::BottomRight => height - offset_y - panel_height,
+        };
+        segments.rect(left, top, panel_width, panel_height, BACKGROUND);
+
+        let line_height = LINE_HEIGHT * PIXEL;
+        let mut text_top = top + PADDING;
+        segments.text(left + PADDING, text_top, &self.title, TEXT);
+        text_top += line_height;
+        // The legend, wrapping where the panel ends
+        let mut text_left = left + PADDING;
+        let mut legend_lines = 0;
+        for (index, series) in self.series.iter().enumerate() {
+            let entry = match series.latest() {
+                Some(latest) => format!("{} {}", series.label, format_value(latest)),
+                None => series.label.clone(),
+            };
+            let entry_width = (entry.len() + 2) as f32 * GLYPH_ADVANCE * PIXEL;
+            if legend_lines == 0 || text_left + entry_width > left + panel_width - PADDING {
+                if legend_lines > 0 {
+                    text_top += line_height;
+                }
+                text_left = left + PADDING;
+                legend_lines += 1;
+            }
+            segments.text(text_left, text_top, &entry, self.color(index));
+            text_left += entry_width;
+        }
+        if legend_lines > 0 {
+            text_top += line_height;
+        }
+
+        let chart_left = left + PADDING;
+        let chart_right = left + panel_width - PADDING;
+        let chart_top = text_top;
+        // Histograms label their bins' ends under the chart
+        let chart_bottom = match self.kind {
+            PlotKind::Line => top + panel_height - PADDING,
+            PlotKind::Histogram { .. } => top + panel_height - PADDING - line_height,
+        };
+        if chart_right - chart_left < 1.0 || chart_bottom - chart_top < 1.0 {
+            return;
+        }
+        segments.rect(
+            chart_left,
+            chart_top,
+            chart_right - chart_left,
+            1.0,
+            GRID_COLOR,
+        );
+        segments.rect(
+            chart_left,
+            chart_bottom - 1.0,
+            chart_right - chart_left,
+            1.0,
+            GRID_COLOR,
+        );
+        let Some([low, high]) = self.value_range() else {
+            return;
+        };
+        let label_inset = LINE_WIDTH + 2.0;
+
+        match self.kind {
+            PlotKind::Line => {
+                let y = |value: f32| {
+                    let t = ((value - low) / (high - low)).clamp(0.0, 1.0);
+                    chart_bottom - t * (chart_bottom - chart_top)
+                };
+                for (index, series) in self.series.iter().enumerate() {
+                    let color = self.color(index);
+                    let step = (chart_right - chart_left) / (series.capacity.max(2) - 1) as f32;
+                    let first = series.capacity - series.values.len();
+                    let points = series.values().enumerate().map(|(index, value)| {
+                        [chart_left + (first + index) as f32 * step, y(value)]
+                    });
+                    let mut previous = None;
+                    for point in points {
+                        if let Some(previous) = previous {
+                            segments.line(previous, point, LINE_WIDTH, color);
+                        }
+                        previous = Some(point);
+                    }
+                }
+                segments.text(
+                    chart_left + label_inset,
+                    chart_top + label_inset,
+                    &format_value(high),
+                    TEXT,
+                );
+                let bottom_label = chart_bottom - label_inset - 7.0 * PIXEL;
+                segments.text(
+                    chart_left + label_inset,
+                    bottom_label,
+                    &format_value(low),
+                    TEXT,
+                );
+            }
+            PlotKind::Histogram { bins } => {
+                let bins = bins.max(1) as usize;
+                let counts: Vec<Vec<u32>> = self
+                    .series
+                    .iter()
+                    .map(|series| {
+                        let mut counts = vec![0; bins];
+                        for value in series.values() {
+                            let t = (value - low) / (high - low);
+                            if (0.0..=1.0).contains(&t) {
+                                counts[((t * bins as f32) as usize).min(bins - 1)] += 1;
+                            }
+                        }
+                        counts
+                    })
+                    .collect();
+                let most = counts.iter().flatten().copied().max().unwrap_or(0).max(1);
+                let bin_width = (chart_right - chart_left) / bins as f32;
+                for (index, counts) in counts.iter().enumerate() {
+                    // See-through, so series drawn over each other stay visible
+                    let [r, g, b, a] = self.color(index);
+                    let color = [r, g, b, a * 0.7];
+                    for (bin, &count) in counts.iter().enumerate() {
+                        if count == 0 {
+                            continue;
+                        }
+                        let bar_height = count as f32 / most as f32 * (chart_bottom - chart_top);
+                        segments.rect(
+                            chart_left + bin as f32 * bin_width + 0.5,
+                            chart_bottom - bar_height,
+                            (bin_width - 1.0).max(1.0),
+                            bar_height,
+                            color,
+                        );
+                    }
+                }
+                segments.text(
+                    chart_left + label_inset,
+                    chart_top + label_inset,
+                    &most.to_string(),
+                    TEXT,
+                );
+                let label_top = chart_bottom + (line_height - 7.0 * PIXEL) / 2.0;
+                segments.text(chart_left, label_top, &format_value(low), TEXT);
+                let high = format_value(high);
+                let high_width = (high.len() as f32 * GLYPH_ADVANCE - 1.0) * PIXEL;
+                segments.text(chart_right - high_width, label_top, &high, TEXT);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PlotHandle(pub(crate) usize);
+
+/// Fewer decimals for larger values, so labels stay short
+fn format_value(value: f32) -> String {
+    match value.abs() {
+        magnitude if magnitude >= 1000.0 => format!("{value:.0}"),
+        magnitude if magnitude >= 10.0 => format!("{value:.1}"),
+        _ => format!("{value:.2}"),
+    }
+}
+
+/// A line from `from` to `to` in pixels from the window's top left, drawn as a quad around it
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PlotSegment {
+    from: [f32; 2],
+    to: [f32; 2],
+    color: [f32; 4],
+    width: f32,
+    /// 1 for round ends, which join the segments of lines, 0 for flat ones ending at `from` and `to`
+    rounded: f32,
+}
+
+#[derive(Default)]
+struct Segments(Vec<PlotSegment>);
+
+impl Segments {
+    fn line(&mut self, from: [f32; 2], to: [f32; 2], width: f32, color: [f32; 4]) {
+        self.0.push(PlotSegment {
+            from,
+            to,
+            color,
+            width,
+            rounded: 1.0,
+        });
+    }
+
+    /// Along its middle, as wide as it's high
+    fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: [f32; 4]) {
+        let middle = y + height / 2.0;
+        self.0.push(PlotSegment {
+            from: [x, middle],
+            to: [x + width, middle],
+            color,
+            width: height,
+            rounded: 0.0,
+        });
+    }
+
+    /// One rect per run of lit pixels in a glyph row, the top left of the text at `x`, `y`
+    fn text(&mut self, x: f32, y: f32, text: &str, color: [f32; 4]) {
+        for [column, row, length] in lit_runs(text) {
+            self.rect(
+                x + column * PIXEL,
+                y + row * PIXEL,
+                length * PIXEL,
+                PIXEL,
+                color,
+            );
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ScreenUBOContent {
+    size: [f32; 2],
+    _padding: [f32; 2],
+}
+
+crate::assert_uniform_layout!(ScreenUBOContent { size: ALIGN_VEC2 });
+
+struct WindowBuffers {
+    screen: UniformBuffer<ScreenUBOContent>,
+    bind_group: BindGroup,
+    /// Rewritten every frame
+    segments: wgpu::Buffer,
+}
+
+/// Draws every plot into the windows, one set of buffers per window like the HUD's
+pub(crate) struct PlotPass {
+    pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayoutWithDesc,
+    windows: HashMap<WindowId, WindowBuffers>,
+}
+
+impl PlotPass {
+    /// `format` has to be the engine's swapchain format, the plots are drawn straight into the windows
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let _span = tracing::debug_span!("create_plot_pipeline").entered();
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_vertex(binding_types::uniform())
+            .create(device, "Plot Bind Group");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Plot Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("plot.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout.layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("plot"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<PlotSegment>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float32x2,
+                        1 => Float32x2,
+                        2 => Float32x4,
+                        3 => Float32,
+                        4 => Float32,
+                    ],
+                }],
+                compilation_options: Default::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        PlotPass {
+            pipeline,
+            bind_group_layout,
+            windows: HashMap::new(),
+        }
+    }
+
+    /// Records a pass drawing the visible `plots` into `view`, a window `width` x `height` pixels
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        window_id: WindowId,
+        view: &TextureView,
+        width: u32,
+        height: u32,
+        plots: &[Option<Plot>],
+    ) {
+        let mut segments = Segments::default();
+        for plot in plots.iter().flatten().filter(|plot| plot.visible) {
+            plot.layout(&mut segments, width as f32, height as f32);
+        }
+        if segments.0.is_empty() {
+            return;
+        }
+        let bytes: &[u8] = bytemuck::cast_slice(&segments.0);
+        let size = bytes.len() as u64;
+
+        let buffers = self.windows.entry(window_id).or_insert_with(|| {
+            let screen = UniformBuffer::new(device);
+            let bind_group = BindGroupBuilder::new(&self.bind_group_layout)
+                .resource(screen.binding_resource())
+                .create(device, "Plot Bind Group");
+            WindowBuffers {
+                screen,
+                bind_group,
+                segments: create_segment_buffer(device, size),
+            }
+        });
+        if buffers.segments.size() < size {
+            buffers.segments = create_segment_buffer(device, size);
+        }
+        queue.write_buffer(&buffers.segments, 0, bytes);
+        buffers.screen.update_content(
+            queue,
+            ScreenUBOContent {
+                size: [width as f32, height as f32],
+                _padding: [0.0; 2],
+            },
+        );
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("plots"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &buffers.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, buffers.segments.slice(..size));
+        render_pass.draw(0..6, 0..segments.0.len() as u32);
+    }
+
+    /// Stops keeping buffers for the window
+    pub fn remove_window(&mut self, window_id: WindowId) {
+        self.windows.remove(&window_id);
+    }
+}
+
+fn create_segment_buffer(device: &Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Plot Segments"),
+        size: size.next_power_of_two(),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// The engine's plots and the pass drawing them into the windows, which is created with the first plot
+#[derive(Default)]
+pub(crate) struct Plots {
+    pass: LazyPass<PlotPass>,
+    /// None where a plot was removed
+    plots: Vec<Option<Plot>>,
+}
+
+impl Plots {
+    pub fn add(&mut self, device: &Device, format: TextureFormat, plot: Plot) -> PlotHandle {
+        self.pass
+            .get_or_create(|| Some(PlotPass::new(device, format)));
+        self.plots.push(Some(plot));
+        PlotHandle(self.plots.len() - 1)
+    }
+
+    pub fn get(&self, handle: PlotHandle) -> Option<&Plot> {
+        self.plots.get(handle.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: PlotHandle) -> Option<&mut Plot> {
+        self.plots.get_mut(handle.0)?.as_mut()
+    }
+
+    pub fn remove(&mut self, handle: PlotHandle) -> Option<Plot> {
+        self.plots.get_mut(handle.0)?.take()
+    }
+
+    /// See [PlotPass::draw]
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        window_id: WindowId,
+        view: &TextureView,
+        width: u32,
+        height: u32,
+    ) {
+        if let Some(pass) = self.pass.get_mut() {
+            pass.draw(
+                device,
+                queue,
+                encoder,
+                window_id,
+                view,
+                width,
+                height,
+                &self.plots,
+            );
+        }
+    }
+
+    pub fn remove_window(&mut self, window_id: WindowId) {
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_window(window_id);
+        }
+    }
+
+    /// Creates the pass again on a new device
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(&mut self, device: &Device, format: TextureFormat) {
+        self.pass.recreate(|| Some(PlotPass::new(device, format)));
+    }
+}
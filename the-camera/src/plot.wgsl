@@ -0,0 +1,86 @@
+// Line segments laid out in pixels on the CPU, see plot.rs. Each instance is a quad around its segment, the
+// fragments are covered by how far inside the segment's outline they are, which antialiases the edges.
+
+struct Screen {
+    size: vec2<f32>,
+}
+@group(0) @binding(0)
+var<uniform> screen: Screen;
+
+struct Segment {
+    @location(0) start: vec2<f32>,
+    @location(1) end: vec2<f32>,
+    @location(2) color: vec4<f32>,
+    @location(3) width: f32,
+    @location(4) rounded: f32,
+}
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    // In pixels from the window's top left
+    @location(0) pixel: vec2<f32>,
+    @location(1) @interpolate(flat) start: vec2<f32>,
+    @location(2) @interpolate(flat) end: vec2<f32>,
+    @location(3) @interpolate(flat) color: vec4<f32>,
+    @location(4) @interpolate(flat) width: f32,
+    @location(5) @interpolate(flat) rounded: f32,
+}
+
+fn direction(start: vec2<f32>, end: vec2<f32>) -> vec2<f32> {
+    let span = length(end - start);
+    if span < 1e-6 {
+        return vec2<f32>(1.0, 0.0);
+    }
+    return (end - start) / span;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, segment: Segment) -> VertexOutput {
+    // Along the segment from its start to its end, and across it
+    let corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, -1.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let along = direction(segment.start, segment.end);
+    let across = vec2<f32>(-along.y, along.x);
+    // A pixel more all around for the antialiasing, round ends reach half the width past the ends
+    let half_width = segment.width * 0.5 + 1.0;
+    let overhang = segment.width * 0.5 * segment.rounded + 1.0;
+    let corner = corners[index];
+    let first = segment.start - along * overhang;
+    let last = segment.end + along * overhang;
+    let pixel = mix(first, last, corner.x) + across * half_width * corner.y;
+
+    var out: VertexOutput;
+    out.position = vec4<f32>(pixel.x / screen.size.x * 2.0 - 1.0, 1.0 - pixel.y / screen.size.y * 2.0, 0.0, 1.0);
+    out.pixel = pixel;
+    out.start = segment.start;
+    out.end = segment.end;
+    out.color = segment.color;
+    out.width = segment.width;
+    out.rounded = segment.rounded;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let along = direction(in.start, in.end);
+    let span = length(in.end - in.start);
+    let offset = in.pixel - in.start;
+    let u = dot(offset, along);
+    let v = dot(offset, vec2<f32>(-along.y, along.x));
+    // Signed distance to the outline in pixels, negative inside
+    var distance = max(abs(v) - in.width * 0.5, max(-u, u - span));
+    if in.rounded > 0.5 {
+        distance = length(offset - along * clamp(u, 0.0, span)) - in.width * 0.5;
+    }
+    let coverage = clamp(0.5 - distance, 0.0, 1.0);
+    if coverage <= 0.0 {
+        discard;
+    }
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
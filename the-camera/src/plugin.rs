@@ -0,0 +1,132 @@
+use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, Device, Queue, TextureFormat, TextureView};
+use winit::window::WindowId;
+
+use crate::{
+    depth_pyramid::DepthPyramid,
+    frame::FrameContext,
+    input::InputEvent,
+    jobs::JobSystem,
+    render_engine_builder::DeviceReport,
+    render_target::{RenderTarget, RenderTargetHandle},
+    selection::SelectionChange,
+};
+
+/// Shared GPU state handed to every plugin hook
+pub struct PluginContext<'a> {
+    pub device: &'a Device,
+    pub queue: &'a Queue,
+    /// Color format of every window, pipelines drawing into a [PassTarget] have to use it
+    pub format: TextureFormat,
+    /// Layout of the camera bind group, so plugin pipelines can reuse it at group 0
+    pub global_bind_group_layout: &'a BindGroupLayout,
+    /// What the device supports, e.g. to pick between code paths
+    pub device_report: &'a DeviceReport,
+    /// For spreading CPU work, e.g. simulation, over threads
+    pub jobs: &'a JobSystem,
+    pub(crate) render_targets: &'a [RenderTarget],
+}
+
+impl PluginContext<'_> {
+    /// For drawing into render targets from plugin passes
+    pub fn render_target(&self, handle: RenderTargetHandle) -> Option<&RenderTarget> {
+        self.render_targets.get(handle.0)
+    }
+}
+
+/// The frame of one window that plugins add their passes to
+pub struct PassTarget<'a> {
+    pub window_id: WindowId,
+    /// Of [PassTarget::color] and [PassTarget::depth], the window's [crate::viewport::Viewport::render_size]
+    pub width: u32,
+    pub height: u32,
+    /// Already contains the engine's main pass. Below full resolution it's the scene scaled up into the window after
+    /// the plugins, see [crate::upscaling].
+    pub color: &'a TextureView,
+    /// The depth buffer of the main pass, in [crate::texture::Texture::DEPTH_FORMAT]
+    pub depth: &'a TextureView,
+    /// Of [PassTarget::depth], while [crate::render_engine::RenderEngine::builds_depth_pyramids]
+    pub depth_pyramid: Option<&'a DepthPyramid>,
+    /// The window's camera, laid out as [PluginContext::global_bind_group_layout]
+    pub globals: &'a BindGroup,
+    pub frame: &'a FrameContext,
+}
+
+/// The finished frame of one window that plugins draw their overlays over, at the window's resolution
+pub struct OverlayTarget<'a> {
+    pub window_id: WindowId,
+    /// Of [OverlayTarget::color], the window's surface
+    pub width: u32,
+    pub height: u32,
+    /// The scaled up scene with the engine's own overlays, only the debug HUD and console go over it later
+    pub color: &'a TextureView,
+    pub frame: &'a FrameContext,
+}
+
+/// Extends the engine with its own GPU resources and passes, e.g. UI overlays, debug drawing or particles.
+///
+/// Register plugins with [crate::render_engine::RenderEngine::add_plugin]. Every hook has an empty default, so a
+/// plugin only implements the ones it needs. Hooks are called in registration order.
+pub trait EnginePlugin {
+    /// Creates the plugin's GPU resources. Called on registration and again after the engine recovered from a
+    /// device loss, since everything created on the old device is gone.
+    fn init(&mut self, _context: &PluginContext) {}
+
+    /// Labels the plugin's work in graphics debuggers and profiles
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// A window's surface changed size
+    fn on_resize(&mut self, _window_id: WindowId, _width: u32, _height: u32) {}
+
+    /// Sees input events before the app does. Returning true marks the event as consumed, e.g. when a UI
+    /// overlay has the mouse.
+    fn on_event(&mut self, _window_id: WindowId, _event: &InputEvent) -> bool {
+        false
+    }
+
+    /// Runs during the simulate phase of [crate::render_engine::RenderEngine::update], before the frame is
+    /// extracted. `frame` only has its index and delta time filled in at this point.
+    fn pre_update(&mut self, _context: &PluginContext, _frame: &FrameContext) {}
+
+    /// The selection changed since the last update, by clicking or through
+    /// [crate::render_engine::RenderEngine::selection_mut]
+    fn on_selection_changed(&mut self, _change: &SelectionChange) {}
+
+    /// Keeps the engine drawing frames while true, e.g. for as long as an animation runs. Otherwise windows are only
+    /// redrawn when something changed.
+    fn needs_redraw(&self) -> bool {
+        false
+    }
+
+    /// Records compute work into `encoder` during the compute phase of [crate::render_engine::RenderEngine::update],
+    /// e.g. with [crate::wgpu_utils::compute::ComputePassBuilder]. It runs once per frame, after the frame's uploads
+    /// and before anything is drawn. Not called on devices without compute shaders, like WebGL2.
+    fn compute(
+        &mut self,
+        _context: &PluginContext,
+        _encoder: &mut CommandEncoder,
+        _frame: &FrameContext,
+    ) {
+    }
+
+    /// Records the plugin's passes into `encoder` after the engine's main pass, before the frame is captured and
+    /// presented
+    fn build_passes(
+        &mut self,
+        _context: &PluginContext,
+        _encoder: &mut CommandEncoder,
+        _target: &PassTarget,
+    ) {
+    }
+
+    /// Records passes drawing over the whole window into `encoder`, after the scene was scaled up to it and the
+    /// engine's overlays were drawn, e.g. for UIs that should stay sharp at any render scale
+    fn build_overlay_passes(
+        &mut self,
+        _context: &PluginContext,
+        _encoder: &mut CommandEncoder,
+        _target: &OverlayTarget,
+    ) {
+    }
+}
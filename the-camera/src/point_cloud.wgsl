@@ -0,0 +1,99 @@
+// See clipping.rs, the same as in shader.wgsl
+struct Clipping {
+    planes: array<vec4<f32>, 4>,
+    cap_color: vec4<f32>,
+    count: u32,
+    caps: u32,
+}
+
+struct Audio {
+    bands: array<vec4<f32>, 4>,
+    level: f32,
+}
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    clipping: Clipping,
+    audio: Audio,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// One octree node, see point_cloud/pass.rs
+struct Chunk {
+    model: mat4x4<f32>,
+    // Edge length of the splats in world space
+    size: f32,
+}
+@group(1) @binding(0)
+var<uniform> chunk: Chunk;
+
+struct PointInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    @location(1) world_position: vec3<f32>,
+    // From -1 to 1 across the splat
+    @location(2) corner: vec2<f32>,
+};
+
+// Two triangles making up the quad of a point
+const CORNERS = array<vec2<f32>, 6>(
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(1.0, -1.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(-1.0, 1.0),
+);
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, point: PointInput) -> VertexOutput {
+    let center = (chunk.model * vec4<f32>(point.position, 1.0)).xyz;
+    // Facing the camera, with another up axis when looking straight up or down
+    let forward = normalize(center - camera.view_pos.xyz);
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(forward.y) > 0.99 {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let right = normalize(cross(forward, up));
+    up = cross(right, forward);
+
+    let corner = CORNERS[index];
+    let world_position = center + (right * corner.x + up * corner.y) * chunk.size * 0.5;
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.color = point.color.rgb;
+    out.world_position = world_position;
+    out.corner = corner;
+    return out;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    // Points can't be picked
+    @location(1) id: u32,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    if dot(in.corner, in.corner) > 1.0 {
+        discard;
+    }
+    for (var index = 0u; index < camera.clipping.count; index++) {
+        let plane = camera.clipping.planes[index];
+        if dot(plane.xyz, in.world_position) < plane.w {
+            discard;
+        }
+    }
+
+    var out: FragmentOutput;
+    out.color = vec4<f32>(in.color, 1.0);
+    out.id = 0u;
+    return out;
+}
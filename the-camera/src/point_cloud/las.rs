@@ -0,0 +1,448 @@
+//! Reading LAS 1.0 to 1.4 files, the usual format of LiDAR scans, a batch of points at a time. Every point record
+//! format from 0 to 10 is read for its position, intensity, classification and, where it has one, its color.
+//!
+//! LAZ files are LAS compressed with LASzip. With the `laz` feature [LasReader::open] decompresses them a point at a
+//! time as they're read.
+
+use std::io::{self, Read};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs::File, io::BufReader, path::Path};
+
+/// Up to and including the bounding box, the part of the header every version has
+const HEADER_SIZE: usize = 227;
+/// With the 64 bit point count of LAS 1.4
+const HEADER_SIZE_1_4: usize = 375;
+/// Point formats with the LASzip flags set are compressed
+const COMPRESSED: u8 = 0x80 | 0x40;
+/// Of every variable length record, in front of its data
+#[cfg(all(feature = "laz", not(target_arch = "wasm32")))]
+const VLR_HEADER_SIZE: usize = 54;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LasHeader {
+    pub version: (u8, u8),
+    pub point_format: u8,
+    pub point_count: u64,
+    /// Bounding box in the file's coordinates, usually meters of a projected coordinate system with Z up
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+    record_length: usize,
+    offset_to_points: usize,
+    scale: [f64; 3],
+    offset: [f64; 3],
+    /// LAZ, with the LASzip flags of the point format cleared
+    compressed: bool,
+}
+
+impl LasHeader {
+    /// Where the color of a record starts, None for formats without one
+    fn color_offset(&self) -> Option<usize> {
+        match self.point_format {
+            2 => Some(20),
+            3 | 5 => Some(28),
+            7 | 8 | 10 => Some(30),
+            _ => None,
+        }
+    }
+
+    /// Records are at least this long, the rest are extra bytes
+    fn min_record_length(&self) -> usize {
+        [20, 28, 26, 34, 57, 63, 30, 36, 38, 59, 67][self.point_format as usize]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LasPoint {
+    /// In the file's coordinates
+    pub position: [f64; 3],
+    pub intensity: u16,
+    pub classification: u8,
+    /// 16 bits per channel, though some writers only use the lower 8
+    pub color: Option<[u16; 3]>,
+}
+
+/// Reads the points of a LAS file from any stream, they don't have to fit into memory at once
+pub struct LasReader<R> {
+    reader: R,
+    header: LasHeader,
+    remaining: u64,
+    record: Vec<u8>,
+}
+
+impl<R: Read> LasReader<R> {
+    /// Reads the header and skips ahead to the points
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let (header, _) = read_header(&mut reader)?;
+        if header.compressed {
+            return Err(invalid(
+                "points are compressed, LAZ files have to be read through LasReader::open",
+            ));
+        }
+        Ok(LasReader::with_header(reader, header))
+    }
+
+    /// `reader` is at the first point
+    fn with_header(reader: R, header: LasHeader) -> Self {
+        LasReader {
+            reader,
+            remaining: header.point_count,
+            record: vec![0; header.record_length],
+            header,
+        }
+    }
+
+    pub fn header(&self) -> &LasHeader {
+        &self.header
+    }
+
+    /// Points not read yet
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// The next `count` points or those that are left, empty at the end. Fails on files cut short.
+    pub fn read_points(&mut self, count: usize) -> io::Result<Vec<LasPoint>> {
+        let count = (count as u64).min(self.remaining) as usize;
+        let mut points = Vec::with_capacity(count);
+        let header = &self.header;
+        let color_offset = header.color_offset();
+        let (classification_offset, mask) = match header.point_format {
+            0..=5 => (15, 0x1F),
+            _ => (16, 0xFF),
+        };
+        for _ in 0..count {
+            self.reader.read_exact(&mut self.record)?;
+            let record = &self.record;
+            let u16_at = |at: usize| u16::from_le_bytes([record[at], record[at + 1]]);
+            let coordinate = |axis: usize| {
+                let at = axis * 4;
+                let value = i32::from_le_bytes(record[at..at + 4].try_into().expect("4 bytes"));
+                f64::from(value) * header.scale[axis] + header.offset[axis]
+            };
+            points.push(LasPoint {
+                position: [coordinate(0), coordinate(1), coordinate(2)],
+                intensity: u16_at(12),
+                classification: record[classification_offset] & mask,
+                color: color_offset.map(|at| [u16_at(at), u16_at(at + 2), u16_at(at + 4)]),
+            });
+        }
+        self.remaining -= count as u64;
+        Ok(points)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl LasReader<Box<dyn Read + Send>> {
+    /// Opens a `.las` file, or a `.laz` file with the `laz` feature
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path)?);
+        // LASzip's record is all that's needed of them
+        #[cfg_attr(not(feature = "laz"), allow(unused_variables))]
+        let (header, records) = read_header(&mut file)?;
+        if header.compressed {
+            #[cfg(feature = "laz")]
+            return LasReader::decompressing(file, header, &records);
+            #[cfg(not(feature = "laz"))]
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "LAZ files are only read with the laz feature",
+            ));
+        }
+        Ok(LasReader::with_header(Box::new(file), header))
+    }
+}
+
+#[cfg(all(feature = "laz", not(target_arch = "wasm32")))]
+impl LasReader<Box<dyn Read + Send>> {
+    /// After the header was read from `file`, which is at the first point
+    fn decompressing(file: BufReader<File>, header: LasHeader, records: &[u8]) -> io::Result<Self> {
+        let vlr = laszip_record(records)
+            .ok_or_else(|| invalid("points are compressed, but there's no LASzip record"))?;
+        let vlr = laz::LazVlr::from_buffer(vlr).map_err(laz_error)?;
+        if vlr.items_size() != header.record_length as u64 {
+            return Err(invalid(format!(
+                "LASzip decompresses records of {} bytes, the header says {}",
+                vlr.items_size(),
+                header.record_length
+            )));
+        }
+        let decompressor = laz::LasZipDecompressor::new(file, vlr).map_err(laz_error)?;
+        let points = LazPoints {
+            decompressor,
+            point: vec![0; header.record_length],
+            read: header.record_length,
+        };
+        Ok(LasReader::with_header(Box::new(points), header))
+    }
+}
+
+/// The data of the variable length record LASzip describes its compression in
+#[cfg(all(feature = "laz", not(target_arch = "wasm32")))]
+fn laszip_record(mut records: &[u8]) -> Option<&[u8]> {
+    while records.len() >= VLR_HEADER_SIZE {
+        let user_id = records[2..18].split(|&byte| byte == 0).next()?;
+        let record_id = u16::from_le_bytes([records[18], records[19]]);
+        let length = u16::from_le_bytes([records[20], records[21]]) as usize;
+        let data = records.get(VLR_HEADER_SIZE..VLR_HEADER_SIZE + length)?;
+        if user_id == laz::LazVlr::USER_ID.as_bytes() && record_id == laz::LazVlr::RECORD_ID {
+            return Some(data);
+        }
+        records = &records[VLR_HEADER_SIZE + length..];
+    }
+    None
+}
+
+#[cfg(all(feature = "laz", not(target_arch = "wasm32")))]
+fn laz_error(err: laz::LasZipError) -> io::Error {
+    invalid(format!("LAZ: {err}"))
+}
+
+/// Decompressed point records, one point at a time
+#[cfg(all(feature = "laz", not(target_arch = "wasm32")))]
+struct LazPoints {
+    decompressor: laz::LasZipDecompressor<'static, BufReader<File>>,
+    point: Vec<u8>,
+    /// How much of [LazPoints::point] was read, all of it before the first one
+    read: usize,
+}
+
+#[cfg(all(feature = "laz", not(target_arch = "wasm32")))]
+impl Read for LazPoints {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.read == self.point.len() {
+            self.decompressor.decompress_one(&mut self.point)?;
+            self.read = 0;
+        }
+        let read = buffer.len().min(self.point.len() - self.read);
+        buffer[..read].copy_from_slice(&self.point[self.read..self.read + read]);
+        self.read += read;
+        Ok(read)
+    }
+}
+
+/// The header and the variable length records after it, the reader is left at the first point
+fn read_header(reader: &mut impl Read) -> io::Result<(LasHeader, Vec<u8>)> {
+    let mut bytes = vec![0; HEADER_SIZE];
+    reader.read_exact(&mut bytes)?;
+    if &bytes[0..4] != b"LASF" {
+        return Err(invalid("not a LAS file"));
+    }
+    let u16_at = |bytes: &[u8], at: usize| u16::from_le_bytes([bytes[at], bytes[at + 1]]);
+    let u32_at = |bytes: &[u8], at: usize| {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().expect("4 bytes"))
+    };
+    let f64_at = |bytes: &[u8], at: usize| {
+        f64::from_le_bytes(bytes[at..at + 8].try_into().expect("8 bytes"))
+    };
+    let version = (bytes[24], bytes[25]);
+    let header_size = u16_at(&bytes, 94) as usize;
+    let offset_to_points = u32_at(&bytes, 96) as usize;
+    if header_size < HEADER_SIZE || offset_to_points < header_size {
+        return Err(invalid("header is too short"));
+    }
+    bytes.resize(header_size, 0);
+    reader.read_exact(&mut bytes[HEADER_SIZE..])?;
+
+    let format = bytes[104];
+    let mut point_count = u64::from(u32_at(&bytes, 107));
+    // LAS 1.4 leaves the old count 0 when it doesn't fit
+    if version >= (1, 4) && header_size >= HEADER_SIZE_1_4 && point_count == 0 {
+        point_count = u64::from_le_bytes(bytes[247..255].try_into().expect("8 bytes"));
+    }
+    let header = LasHeader {
+        version,
+        point_format: format & !COMPRESSED,
+        point_count,
+        min: [
+            f64_at(&bytes, 187),
+            f64_at(&bytes, 203),
+            f64_at(&bytes, 219),
+        ],
+        max: [
+            f64_at(&bytes, 179),
+            f64_at(&bytes, 195),
+            f64_at(&bytes, 211),
+        ],
+        record_length: u16_at(&bytes, 105) as usize,
+        offset_to_points,
+        scale: [
+            f64_at(&bytes, 131),
+            f64_at(&bytes, 139),
+            f64_at(&bytes, 147),
+        ],
+        offset: [
+            f64_at(&bytes, 155),
+            f64_at(&bytes, 163),
+            f64_at(&bytes, 171),
+        ],
+        compressed: format & COMPRESSED != 0,
+    };
+    if header.point_format > 10 {
+        return Err(invalid(format!(
+            "point format {} isn't supported",
+            header.point_format
+        )));
+    }
+    if header.record_length < header.min_record_length() {
+        return Err(invalid(format!(
+            "records of {} bytes are too short for point format {}",
+            header.record_length, header.point_format
+        )));
+    }
+
+    // Apart from LASzip's, the variable length records, e.g. the coordinate system, aren't needed for drawing
+    let mut records = vec![0; offset_to_points - header_size];
+    reader.read_exact(&mut records)?;
+    Ok((header, records))
+}
+
+fn invalid(message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("LAS: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    /// A LAS 1.2 header of `count` points of the `format`, in centimeters from (10, 20, 30)
+    fn header(format: u8, record_length: u16, count: u32) -> Vec<u8> {
+        let mut header = vec![0; HEADER_SIZE];
+        header[0..4].copy_from_slice(b"LASF");
+        header[24..26].copy_from_slice(&[1, 2]);
+        header[94..96].copy_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        header[96..100].copy_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        header[104] = format;
+        header[105..107].copy_from_slice(&record_length.to_le_bytes());
+        header[107..111].copy_from_slice(&count.to_le_bytes());
+        for axis in 0..3 {
+            let at = 131 + 8 * axis;
+            header[at..at + 8].copy_from_slice(&0.01f64.to_le_bytes());
+            let offset = 10.0 * (axis + 1) as f64;
+            header[at + 24..at + 32].copy_from_slice(&offset.to_le_bytes());
+        }
+        header
+    }
+
+    /// The `index`th point of format 3, with the upper bits of the classification byte set
+    fn record(index: u16) -> Vec<u8> {
+        let mut record = vec![0; 34];
+        record[0..4].copy_from_slice(&i32::from(index).to_le_bytes());
+        record[4..8].copy_from_slice(&(-i32::from(index)).to_le_bytes());
+        record[12..14].copy_from_slice(&(index * 2).to_le_bytes());
+        record[15] = 0xE0 | 2;
+        for channel in 0..3 {
+            let at = 28 + 2 * channel;
+            record[at..at + 2].copy_from_slice(&(index + channel as u16).to_le_bytes());
+        }
+        record
+    }
+
+    fn file(count: u16) -> Vec<u8> {
+        let mut file = header(3, 34, count.into());
+        file.extend((0..count).flat_map(record));
+        file
+    }
+
+    #[test]
+    fn reads_points_in_batches() {
+        let mut reader = LasReader::new(Cursor::new(file(5))).unwrap();
+        assert_eq!(reader.header().version, (1, 2));
+        assert_eq!(reader.header().point_format, 3);
+        assert_eq!(reader.remaining(), 5);
+
+        let points = reader.read_points(3).unwrap();
+        assert_eq!(points.len(), 3);
+        let point = points[2];
+        assert!((point.position[0] - 10.02).abs() < 1e-9);
+        assert!((point.position[1] - 19.98).abs() < 1e-9);
+        assert!((point.position[2] - 30.0).abs() < 1e-9);
+        assert_eq!(point.intensity, 4);
+        assert_eq!(point.classification, 2);
+        assert_eq!(point.color, Some([2, 3, 4]));
+
+        assert_eq!(reader.read_points(10).unwrap().len(), 2);
+        assert!(reader.read_points(10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn reads_the_64_bit_count_of_las_1_4() {
+        // Format 6 has no color, its classification is a byte of its own
+        let mut file = header(6, 30, 0);
+        file[24..26].copy_from_slice(&[1, 4]);
+        file[94..96].copy_from_slice(&(HEADER_SIZE_1_4 as u16).to_le_bytes());
+        file[96..100].copy_from_slice(&(HEADER_SIZE_1_4 as u32).to_le_bytes());
+        file.resize(HEADER_SIZE_1_4, 0);
+        file[247..255].copy_from_slice(&2u64.to_le_bytes());
+        for classification in [200, 7] {
+            let mut record = vec![0; 30];
+            record[16] = classification;
+            file.extend(record);
+        }
+
+        let mut reader = LasReader::new(Cursor::new(file)).unwrap();
+        assert_eq!(reader.header().point_count, 2);
+        let points = reader.read_points(2).unwrap();
+        assert_eq!(points[0].classification, 200);
+        assert_eq!(points[1].color, None);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let open = |file: Vec<u8>| LasReader::new(Cursor::new(file)).err();
+        let mut other = file(1);
+        other[0..4].copy_from_slice(b"LASG");
+        assert!(open(other).is_some());
+        assert!(open(header(11, 34, 0)).is_some());
+        assert!(open(header(3, 20, 0)).is_some());
+        let err = open(header(3 | COMPRESSED, 34, 0)).unwrap();
+        assert!(err.to_string().contains("LasReader::open"), "{err}");
+
+        let mut truncated = file(2);
+        truncated.truncate(truncated.len() - 1);
+        let mut reader = LasReader::new(Cursor::new(truncated)).unwrap();
+        assert!(reader.read_points(2).is_err());
+    }
+
+    #[cfg(all(feature = "laz", not(target_arch = "wasm32")))]
+    #[test]
+    fn decompresses_laz_files() {
+        use std::io::Write;
+
+        let records: Vec<_> = (0..1000).map(record).collect();
+        let items = laz::LazItemRecordBuilder::default_for_point_format_id(3, 0).unwrap();
+        let vlr = laz::LazVlr::from_laz_items(items);
+        let mut vlr_data = Vec::new();
+        vlr.write_to(&mut vlr_data).unwrap();
+        let mut vlr_record = vec![0; VLR_HEADER_SIZE];
+        vlr_record[2..16].copy_from_slice(b"laszip encoded");
+        vlr_record[18..20].copy_from_slice(&22204u16.to_le_bytes());
+        vlr_record[20..22].copy_from_slice(&(vlr_data.len() as u16).to_le_bytes());
+        vlr_record.extend(&vlr_data);
+
+        let mut header = header(3 | COMPRESSED, 34, 1000);
+        let offset_to_points = (HEADER_SIZE + vlr_record.len()) as u32;
+        header[96..100].copy_from_slice(&offset_to_points.to_le_bytes());
+        header[100..104].copy_from_slice(&1u32.to_le_bytes());
+        let mut laz = Cursor::new(header);
+        laz.set_position(laz.get_ref().len() as u64);
+        laz.write_all(&vlr_record).unwrap();
+        let mut compressor = laz::LasZipCompressor::new(&mut laz, vlr).unwrap();
+        for record in &records {
+            compressor.compress_one(record).unwrap();
+        }
+        compressor.done().unwrap();
+        drop(compressor);
+
+        let path = std::env::temp_dir().join(format!("the-camera-{}.laz", std::process::id()));
+        std::fs::write(&path, laz.into_inner()).unwrap();
+        let points = LasReader::open(&path).and_then(|mut reader| reader.read_points(2000));
+        std::fs::remove_file(&path).unwrap();
+        let expected = LasReader::new(Cursor::new(file(1000)))
+            .unwrap()
+            .read_points(2000)
+            .unwrap();
+        assert_eq!(points.unwrap(), expected);
+    }
+}
@@ -0,0 +1,683 @@
+//! Point clouds too large to draw at once, like LiDAR scans of hundreds of millions of points.
+//!
+//! Points are sorted into an octree as they arrive. Each node keeps at most one point in every cell of a
+//! [GRID]³ grid over its box and hands the points landing in a taken cell down to its children, so every node is an
+//! evenly spread sample of its box and each level is twice as detailed as the one above. Points closer together than
+//! the cells of the deepest level are dropped. Drawing a node and its children adds their points up, nothing is
+//! stored twice.
+//!
+//! Every window and render target picks the nodes it draws from its camera: those in view, the ones covering most of
+//! the screen first, down to the level where the points would be about a pixel apart or until
+//! [PointCloud::point_budget] points are picked. Only picked nodes get uploaded, a few at a time, and the least
+//! recently drawn ones are freed again. Points are drawn as round splats as large as their node's spacing, so the
+//! coarse levels leave no gaps while finer ones still load.
+//!
+//! [PointCloud::open] streams LAS and LAZ files on a thread, the cloud fills in while they load, see [las].
+
+pub mod las;
+mod pass;
+
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use std::{
+    io,
+    path::Path,
+    sync::mpsc::{channel, Receiver, TryRecvError},
+};
+
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3, Vector4};
+use wgpu::{Device, Queue, TextureFormat};
+
+pub(crate) use self::pass::{PointCloudPass, PointCloudView};
+use crate::{camera::camera::CameraUniform, global_bindings::GlobalBindings, lazy_pass::LazyPass};
+
+/// Cells along each axis of a node's sampling grid
+pub const GRID: u32 = 128;
+/// Levels below the root, points closer together than the cells of the last one are dropped
+const MAX_DEPTH: u8 = 12;
+/// Nodes are refined until their spacing, relative to their distance to the camera, is below this. About a pixel of
+/// a 1080 pixel tall view with a 60° field of view.
+const DETAIL: f32 = 0.001;
+/// Points read from a file at a time, each batch is sorted into the octree before it's handed over
+#[cfg(not(target_arch = "wasm32"))]
+const BATCH_POINTS: usize = 1 << 20;
+/// Colors of points without one, from the lowest to the highest
+#[cfg(not(target_arch = "wasm32"))]
+const HEIGHT_COLORS: [[f32; 3]; 3] = [[0.1, 0.3, 0.8], [0.3, 0.75, 0.3], [0.95, 0.9, 0.65]];
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointVertex {
+    pub position: [f32; 3],
+    /// Drawn as it is, like the colors of mesh vertices. Alpha is ignored.
+    pub color: [u8; 4],
+}
+
+impl PointVertex {
+    pub(crate) fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<PointVertex>() as wgpu::BufferAddress,
+            // Every point is a quad of its own
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Unorm8x4,
+                },
+            ],
+        }
+    }
+}
+
+/// Refers to a cloud added with [crate::render_engine::RenderEngine::add_point_cloud]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PointCloudHandle(pub(crate) usize);
+
+/// A node of the octree by its depth and its position among the nodes of that depth
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct NodeKey {
+    depth: u8,
+    position: [u32; 3],
+}
+
+impl NodeKey {
+    const ROOT: NodeKey = NodeKey {
+        depth: 0,
+        position: [0; 3],
+    };
+
+    fn parent(self) -> Option<NodeKey> {
+        (self.depth > 0).then(|| NodeKey {
+            depth: self.depth - 1,
+            position: self.position.map(|position| position / 2),
+        })
+    }
+
+    /// Which of its parent's children it is
+    fn octant(self) -> usize {
+        let [x, y, z] = self.position.map(|position| (position % 2) as usize);
+        x | y << 1 | z << 2
+    }
+}
+
+/// The cube an octree divides up
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Cube {
+    min: [f32; 3],
+    size: f32,
+}
+
+impl Cube {
+    /// The smallest one around the box, grown a little so points on its faces are inside
+    fn around(min: [f32; 3], max: [f32; 3]) -> Self {
+        let size = (0..3)
+            .map(|axis| max[axis] - min[axis])
+            .fold(0.0, f32::max)
+            .max(f32::EPSILON)
+            * 1.001;
+        let center: [f32; 3] = std::array::from_fn(|axis| (min[axis] + max[axis]) / 2.0);
+        Cube {
+            min: center.map(|center| center - size / 2.0),
+            size,
+        }
+    }
+
+    /// Corner and edge length of a node's box
+    fn node(&self, key: NodeKey) -> ([f32; 3], f32) {
+        let size = self.size / (1u32 << key.depth) as f32;
+        let min = std::array::from_fn(|axis| self.min[axis] + key.position[axis] as f32 * size);
+        (min, size)
+    }
+}
+
+/// Decides which node each point goes into, remembering the cells points already took
+struct Sampler {
+    cube: Cube,
+    taken: HashMap<NodeKey, HashSet<u32>>,
+}
+
+impl Sampler {
+    fn new(cube: Cube) -> Self {
+        Sampler {
+            cube,
+            taken: HashMap::new(),
+        }
+    }
+
+    /// The shallowest node with the point's cell still free, None when it's taken down to the deepest level
+    fn node(&mut self, position: [f32; 3]) -> Option<NodeKey> {
+        // Points outside the cube, e.g. of a header with wrong bounds, end up on its faces
+        let scaled: [f64; 3] = std::array::from_fn(|axis| {
+            (f64::from(position[axis] - self.cube.min[axis]) / f64::from(self.cube.size))
+                .clamp(0.0, 1.0)
+        });
+        for depth in 0..=MAX_DEPTH {
+            let cells = GRID << depth;
+            let cell = scaled.map(|scaled| ((scaled * f64::from(cells)) as u32).min(cells - 1));
+            let key = NodeKey {
+                depth,
+                position: cell.map(|cell| cell / GRID),
+            };
+            let [x, y, z] = cell.map(|cell| cell % GRID);
+            if self
+                .taken
+                .entry(key)
+                .or_default()
+                .insert(x + GRID * (y + GRID * z))
+            {
+                return Some(key);
+            }
+        }
+        None
+    }
+
+    /// Sorts points into nodes, dropping those that don't fit anywhere
+    fn sort(
+        &mut self,
+        points: impl IntoIterator<Item = PointVertex>,
+    ) -> Vec<(NodeKey, Vec<PointVertex>)> {
+        let mut nodes: HashMap<NodeKey, Vec<PointVertex>> = HashMap::new();
+        for point in points {
+            if let Some(key) = self.node(point.position) {
+                nodes.entry(key).or_default().push(point);
+            }
+        }
+        nodes.into_iter().collect()
+    }
+}
+
+struct Node {
+    key: NodeKey,
+    points: Vec<PointVertex>,
+    children: [Option<usize>; 8],
+}
+
+/// What the loading thread hands over
+#[cfg(not(target_arch = "wasm32"))]
+enum Loaded {
+    Points(Vec<(NodeKey, Vec<PointVertex>)>, u64),
+    Failed(io::Error),
+}
+
+/// Points sorted into an octree for drawing the parts in view at the detail they need, see the [module
+/// docs](self)
+pub struct PointCloud {
+    cube: Cube,
+    /// The root first, parents before their children
+    nodes: Vec<Node>,
+    nodes_by_key: HashMap<NodeKey, usize>,
+    bounds: ([f32; 3], [f32; 3]),
+    origin: [f64; 3],
+    point_count: u64,
+    #[cfg(not(target_arch = "wasm32"))]
+    loader: Option<Receiver<Loaded>>,
+    /// Points the file being loaded has, without those dropped for being too close
+    #[cfg(not(target_arch = "wasm32"))]
+    expected: u64,
+    #[cfg(not(target_arch = "wasm32"))]
+    read: u64,
+    /// Places the cloud in the world
+    pub transform: Matrix4<f32>,
+    /// Most points a view draws of the cloud
+    pub point_budget: usize,
+    /// Splat sizes relative to the spacing of their node, larger ones close gaps and blur details
+    pub point_size: f32,
+}
+
+impl PointCloud {
+    fn new(min: [f32; 3], max: [f32; 3], origin: [f64; 3]) -> Self {
+        let cube = Cube::around(min, max);
+        PointCloud {
+            cube,
+            nodes: Vec::new(),
+            nodes_by_key: HashMap::new(),
+            bounds: (min, max),
+            origin,
+            point_count: 0,
+            #[cfg(not(target_arch = "wasm32"))]
+            loader: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            expected: 0,
+            #[cfg(not(target_arch = "wasm32"))]
+            read: 0,
+            transform: Matrix4::identity(),
+            point_budget: 5_000_000,
+            point_size: 1.5,
+        }
+    }
+
+    /// A cloud of the points given, sorted into the octree right away
+    pub fn from_points(points: &[PointVertex]) -> Self {
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for point in points {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(point.position[axis]);
+                max[axis] = max[axis].max(point.position[axis]);
+            }
+        }
+        if points.is_empty() {
+            (min, max) = ([0.0; 3], [0.0; 3]);
+        }
+        let mut cloud = PointCloud::new(min, max, [0.0; 3]);
+        let nodes = Sampler::new(cloud.cube).sort(points.iter().copied());
+        cloud.add(nodes);
+        cloud
+    }
+
+    /// Starts streaming a LAS or LAZ file into a cloud on a thread, the cloud shows what arrived so far. Fails right
+    /// away if the file can't be opened or has no valid header, later on errors are logged.
+    ///
+    /// Z up file coordinates become Y up ones, centered on the file's bounding box, see [PointCloud::origin]. Points
+    /// without a color are colored by their height.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut reader = las::LasReader::open(path)?;
+        let header = reader.header().clone();
+        let origin: [f64; 3] =
+            std::array::from_fn(|axis| (header.min[axis] + header.max[axis]) / 2.0);
+        let to_engine = move |[x, y, z]: [f64; 3]| {
+            [
+                (x - origin[0]) as f32,
+                (z - origin[2]) as f32,
+                (origin[1] - y) as f32,
+            ]
+        };
+        let (min, max) = (to_engine(header.min), to_engine(header.max));
+        // The Z axis got flipped into Y
+        let (min, max) = ([min[0], min[1], max[2]], [max[0], max[1], min[2]]);
+        let mut cloud = PointCloud::new(min, max, origin);
+        cloud.expected = header.point_count;
+
+        let (sender, loaded) = channel();
+        let path = path.to_path_buf();
+        let mut sampler = Sampler::new(cloud.cube);
+        let height = (
+            header.min[2],
+            (header.max[2] - header.min[2]).max(f64::EPSILON),
+        );
+        std::thread::spawn(move || {
+            let _span = tracing::info_span!("load_point_cloud", path = %path.display()).entered();
+            // Colors only using the lower 8 of their 16 bits are told apart by the first batch
+            let mut wide_colors = None;
+            while reader.remaining() > 0 {
+                let points = match reader.read_points(BATCH_POINTS) {
+                    Ok(points) => points,
+                    Err(err) => {
+                        let _ = sender.send(Loaded::Failed(err));
+                        return;
+                    }
+                };
+                let wide = *wide_colors.get_or_insert_with(|| {
+                    points
+                        .iter()
+                        .filter_map(|point| point.color)
+                        .any(|color| color.iter().any(|&channel| channel > 255))
+                });
+                let read = points.len() as u64;
+                let nodes = sampler.sort(points.into_iter().map(|point| PointVertex {
+                    position: to_engine(point.position),
+                    color: match point.color {
+                        Some(color) => {
+                            let [r, g, b] = color.map(|channel| match wide {
+                                true => (channel >> 8) as u8,
+                                false => channel.min(255) as u8,
+                            });
+                            [r, g, b, 255]
+                        }
+                        None => height_color((point.position[2] - height.0) / height.1),
+                    },
+                }));
+                // Fails once the cloud was dropped
+                if sender.send(Loaded::Points(nodes, read)).is_err() {
+                    return;
+                }
+            }
+        });
+        cloud.loader = Some(loaded);
+        Ok(cloud)
+    }
+
+    /// Adds the points the loading thread sorted since the last call, returns whether there were any
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn poll(&mut self) -> bool {
+        let Some(loader) = &self.loader else {
+            return false;
+        };
+        let mut batches = Vec::new();
+        loop {
+            match loader.try_recv() {
+                Ok(Loaded::Points(nodes, read)) => batches.push((nodes, read)),
+                Ok(Loaded::Failed(err)) => {
+                    tracing::error!("Failed to load the rest of a point cloud: {err}");
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    tracing::info!(
+                        "Loaded a point cloud, {} of {} points kept",
+                        self.point_count,
+                        self.read
+                    );
+                    self.loader = None;
+                    break;
+                }
+            }
+        }
+        let added = !batches.is_empty();
+        for (nodes, read) in batches {
+            self.read += read;
+            self.add(nodes);
+        }
+        added
+    }
+
+    /// Whether points are still being read, see [PointCloud::progress]
+    pub fn is_loading(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.loader.is_some();
+        #[cfg(target_arch = "wasm32")]
+        false
+    }
+
+    /// How much of the file was read, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.is_loading() && self.expected > 0 {
+            return self.read as f32 / self.expected as f32;
+        }
+        1.0
+    }
+
+    /// Points in the octree so far
+    pub fn point_count(&self) -> u64 {
+        self.point_count
+    }
+
+    /// Box around the points before [PointCloud::transform], for files the one their header states
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        self.bounds
+    }
+
+    /// Where the cloud's origin is in the coordinates of the file it was loaded from, e.g. for placing other data
+    /// along with it. Y up coordinates are at `(x - origin.x, z - origin.z, origin.y - y)`.
+    pub fn origin(&self) -> [f64; 3] {
+        self.origin
+    }
+
+    fn add(&mut self, nodes: Vec<(NodeKey, Vec<PointVertex>)>) {
+        for (key, points) in nodes {
+            let node = self.node_index(key);
+            self.point_count += points.len() as u64;
+            self.nodes[node].points.extend(points);
+        }
+    }
+
+    /// Creates the node and any missing parents of it
+    fn node_index(&mut self, key: NodeKey) -> usize {
+        if let Some(&index) = self.nodes_by_key.get(&key) {
+            return index;
+        }
+        let parent = key.parent().map(|parent| self.node_index(parent));
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            key,
+            points: Vec::new(),
+            children: [None; 8],
+        });
+        self.nodes_by_key.insert(key, index);
+        if let Some(parent) = parent {
+            self.nodes[parent].children[key.octant()] = Some(index);
+        }
+        index
+    }
+
+    pub(crate) fn node_points(&self, node: usize) -> &[PointVertex] {
+        &self.nodes[node].points
+    }
+
+    /// The nodes a camera should draw, most important first, each with the size of its splats
+    pub(crate) fn select(&self, camera: &CameraUniform) -> Vec<(usize, f32)> {
+        let Some(&root) = self.nodes_by_key.get(&NodeKey::ROOT) else {
+            return Vec::new();
+        };
+        let view_proj = Matrix4::from(camera.view_proj) * self.transform;
+        let eye = self
+            .transform
+            .invert()
+            .map(|inverse| inverse * Vector4::from(camera.view_position))
+            .unwrap_or(Vector4::from(camera.view_position))
+            .truncate();
+        // Distances are measured in the cloud's space, spacings have to be scaled along
+        let scale = (0..3)
+            .map(|column| self.transform[column].truncate().magnitude())
+            .fold(0.0, f32::max);
+
+        let mut picked = Vec::new();
+        let mut picked_points = 0;
+        let mut queue = BinaryHeap::from([Candidate {
+            priority: f32::INFINITY,
+            node: root,
+        }]);
+        while let Some(Candidate { node, .. }) = queue.pop() {
+            let points = self.nodes[node].points.len();
+            if picked_points + points > self.point_budget {
+                break;
+            }
+            let (min, size) = self.cube.node(self.nodes[node].key);
+            if !in_view(&view_proj, min, size) {
+                continue;
+            }
+            picked.push(node);
+            picked_points += points;
+
+            let center = Vector3::from(min) + Vector3::new(size, size, size) / 2.0;
+            let distance = ((center - eye).magnitude() - size * 0.87).max(size * 1e-3);
+            if size / GRID as f32 / distance <= DETAIL {
+                continue;
+            }
+            for child in self.nodes[node].children.into_iter().flatten() {
+                let (min, size) = self.cube.node(self.nodes[child].key);
+                let center = Vector3::from(min) + Vector3::new(size, size, size) / 2.0;
+                let distance = ((center - eye).magnitude() - size * 0.87).max(size * 1e-3);
+                queue.push(Candidate {
+                    priority: size / distance,
+                    node: child,
+                });
+            }
+        }
+
+        // Splats shrink to the spacing of the level below where every child of a node is drawn too
+        let picked_set: HashSet<usize> = picked.iter().copied().collect();
+        let mut levels = HashMap::new();
+        for &node in picked.iter().rev() {
+            let children: Vec<usize> = self.nodes[node].children.into_iter().flatten().collect();
+            let level = match children.iter().all(|child| picked_set.contains(child)) {
+                true => children
+                    .iter()
+                    .map(|child| levels[child] + 1)
+                    .min()
+                    .unwrap_or(0),
+                false => 0,
+            };
+            levels.insert(node, level);
+        }
+        picked
+            .into_iter()
+            .map(|node| {
+                let (_, size) = self.cube.node(self.nodes[node].key);
+                let spacing = size / GRID as f32 / (1u32 << levels[&node]) as f32;
+                (node, spacing * scale * self.point_size)
+            })
+            .collect()
+    }
+}
+
+/// The engine's point clouds and the pass streaming their nodes to the views, which is created with the first cloud
+#[derive(Default)]
+pub(crate) struct PointClouds {
+    pass: LazyPass<PointCloudPass>,
+    /// None where a cloud was removed
+    clouds: Vec<Option<PointCloud>>,
+}
+
+impl PointClouds {
+    pub fn add(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        cloud: PointCloud,
+    ) -> PointCloudHandle {
+        self.pass
+            .get_or_create(|| Some(PointCloudPass::new(device, format, global_bindings)));
+        self.clouds.push(Some(cloud));
+        PointCloudHandle(self.clouds.len() - 1)
+    }
+
+    pub fn get(&self, handle: PointCloudHandle) -> Option<&PointCloud> {
+        self.clouds.get(handle.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: PointCloudHandle) -> Option<&mut PointCloud> {
+        self.clouds.get_mut(handle.0)?.as_mut()
+    }
+
+    /// Frees the cloud's points on the GPU, loading stops as well
+    pub fn remove(&mut self, handle: PointCloudHandle) -> Option<PointCloud> {
+        let cloud = self.clouds.get_mut(handle.0)?.take()?;
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_cloud(handle.0);
+        }
+        Some(cloud)
+    }
+
+    /// Takes the points loaded since the last call into the clouds, returns whether there were any
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn poll(&mut self) -> bool {
+        let mut added = false;
+        for cloud in self.clouds.iter_mut().flatten() {
+            added |= cloud.poll();
+        }
+        added
+    }
+
+    /// Whether any cloud is still being read
+    pub fn is_loading(&self) -> bool {
+        self.clouds.iter().flatten().any(PointCloud::is_loading)
+    }
+
+    /// Picks the nodes `view` draws from the camera, true while detail is still streaming in
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        view: &mut Option<PointCloudView>,
+        camera: &CameraUniform,
+    ) -> bool {
+        self.pass
+            .get_mut()
+            .is_some_and(|pass| pass.prepare(device, queue, &self.clouds, view, camera))
+    }
+
+    /// Draws the nodes the views picked in the main pass, None before the first cloud
+    pub fn pass(&self) -> Option<&PointCloudPass> {
+        self.pass.get()
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.pass.get().map_or(0, PointCloudPass::size_in_bytes)
+    }
+
+    /// Creates the pass again on a new device, the nodes are uploaded again as the views pick them
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+    ) {
+        self.pass
+            .recreate(|| Some(PointCloudPass::new(device, format, global_bindings)));
+    }
+}
+
+/// A node waiting to be picked, larger ones on screen first
+struct Candidate {
+    priority: f32,
+    node: usize,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.total_cmp(&other.priority)
+    }
+}
+
+/// Whether any of the box is inside the view frustum, boxes entirely outside one of its planes aren't
+fn in_view(view_proj: &Matrix4<f32>, min: [f32; 3], size: f32) -> bool {
+    let corners = (0..8).map(|corner| {
+        let offset = |axis: usize| ((corner >> axis) & 1) as f32 * size;
+        view_proj
+            * Vector4::new(
+                min[0] + offset(0),
+                min[1] + offset(1),
+                min[2] + offset(2),
+                1.0,
+            )
+    });
+    let mut outside = [true; 6];
+    for clip in corners {
+        let planes = [
+            clip.x >= -clip.w,
+            clip.x <= clip.w,
+            clip.y >= -clip.w,
+            clip.y <= clip.w,
+            clip.z >= 0.0,
+            clip.z <= clip.w,
+        ];
+        for (outside, inside) in outside.iter_mut().zip(planes) {
+            *outside &= !inside;
+        }
+    }
+    !outside.contains(&true)
+}
+
+/// From the lowest point at 0 to the highest at 1
+#[cfg(not(target_arch = "wasm32"))]
+fn height_color(height: f64) -> [u8; 4] {
+    let scaled = (height.clamp(0.0, 1.0) * (HEIGHT_COLORS.len() - 1) as f64) as f32;
+    let index = (scaled as usize).min(HEIGHT_COLORS.len() - 2);
+    let amount = scaled - index as f32;
+    let [r, g, b] = std::array::from_fn(|channel| {
+        let (from, to) = (
+            HEIGHT_COLORS[index][channel],
+            HEIGHT_COLORS[index + 1][channel],
+        );
+        ((from + (to - from) * amount) * 255.0).round() as u8
+    });
+    [r, g, b, 255]
+}
@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use wgpu::{BindGroup, Device, Queue, RenderPass, RenderPipeline, TextureFormat};
+
+use super::{PointCloud, PointVertex};
+use crate::{
+    camera::camera::CameraUniform,
+    global_bindings::GlobalBindings,
+    profiler::RenderStats,
+    texture,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        dynamic_uniform_buffer::DynamicUniformBuffer,
+    },
+};
+
+/// Most points uploaded for a view in one frame, so streaming in detail doesn't stall the frames
+const MAX_UPLOAD_POINTS: usize = 1 << 20;
+/// Points kept on the GPU, as multiples of the clouds' budgets, before the least recently drawn ones are freed
+const RESIDENT_BUDGETS: usize = 2;
+
+/// Per node draw data, bound at group 1
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ChunkUBOContent {
+    model: [[f32; 4]; 4],
+    size: f32,
+    _padding: [f32; 3],
+}
+
+crate::assert_uniform_layout!(ChunkUBOContent {
+    model: ALIGN_VEC4,
+    size: ALIGN_SCALAR,
+});
+
+/// The uploaded points of a node, by cloud and node index
+type ChunkKey = (usize, usize);
+
+struct Chunk {
+    buffer: wgpu::Buffer,
+    len: usize,
+    /// [PointCloudPass::picks] when it was last picked
+    last_picked: u64,
+}
+
+/// What one window or render target draws of the point clouds
+pub(crate) struct PointCloudView {
+    ubo: DynamicUniformBuffer<ChunkUBOContent>,
+    bind_group: BindGroup,
+    /// Chunks with the dynamic offset of their uniforms
+    draws: Vec<(ChunkKey, u32)>,
+}
+
+/// The pipeline drawing point clouds in the main pass and the nodes uploaded for it, shared by every view
+pub(crate) struct PointCloudPass {
+    pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayoutWithDesc,
+    chunks: HashMap<ChunkKey, Chunk>,
+    resident_points: usize,
+    /// Counts the views' picks, for freeing the chunks picked longest ago
+    picks: u64,
+}
+
+impl PointCloudPass {
+    /// `format` has to be the engine's swapchain format, since the points are drawn in the main pass
+    pub fn new(device: &Device, format: TextureFormat, global_bindings: &GlobalBindings) -> Self {
+        let _span = tracing::debug_span!("create_point_cloud_pipeline").entered();
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_vertex(crate::wgpu_utils::binding_types::uniform_dynamic(
+                std::mem::size_of::<ChunkUBOContent>() as u64,
+            ))
+            .create(device, "Point Cloud Bind Group");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Point Cloud Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../point_cloud.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                global_bindings.bind_group_layouts(),
+                &bind_group_layout.layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("point cloud"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[PointVertex::desc()],
+                compilation_options: Default::default(),
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+                unclipped_depth: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: texture::Texture::ID_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                ],
+                compilation_options: Default::default(),
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        PointCloudPass {
+            pipeline,
+            bind_group_layout,
+            chunks: HashMap::new(),
+            resident_points: 0,
+            picks: 0,
+        }
+    }
+
+    /// Picks the nodes a view draws from its camera and uploads those missing, up to [MAX_UPLOAD_POINTS]. Returns
+    /// whether some are still missing, the view needs drawing again until they're all there.
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        clouds: &[Option<PointCloud>],
+        view: &mut Option<PointCloudView>,
+        camera: &CameraUniform,
+    ) -> bool {
+        if clouds.iter().all(Option::is_none) {
+            *view = None;
+            return false;
+        }
+        self.picks += 1;
+        let view = view.get_or_insert_with(|| {
+            let ubo = DynamicUniformBuffer::new(device);
+            PointCloudView {
+                bind_group: self.create_bind_group(device, &ubo),
+                ubo,
+                draws: Vec::new(),
+            }
+        });
+        view.ubo.clear();
+        view.draws.clear();
+
+        let mut uploaded = 0;
+        let mut missing = false;
+        for (cloud_index, cloud) in clouds.iter().enumerate() {
+            let Some(cloud) = cloud else {
+                continue;
+            };
+            let model = cloud.transform.into();
+            for (node, size) in cloud.select(camera) {
+                let key = (cloud_index, node);
+                let points = cloud.node_points(node);
+                let stale = self
+                    .chunks
+                    .get(&key)
+                    .is_none_or(|chunk| chunk.len < points.len());
+                if stale {
+                    if uploaded + points.len() <= MAX_UPLOAD_POINTS || uploaded == 0 {
+                        uploaded += points.len();
+                        self.upload(device, key, points);
+                    } else {
+                        missing = true;
+                    }
+                }
+                let Some(chunk) = self.chunks.get_mut(&key) else {
+                    continue;
+                };
+                chunk.last_picked = self.picks;
+                let offset = view.ubo.push(&ChunkUBOContent {
+                    model,
+                    size,
+                    _padding: [0.0; 3],
+                });
+                view.draws.push((key, offset));
+            }
+        }
+        if view.ubo.write(device, queue) {
+            view.bind_group = self.create_bind_group(device, &view.ubo);
+        }
+
+        let budget = clouds
+            .iter()
+            .flatten()
+            .map(|cloud| cloud.point_budget)
+            .sum::<usize>()
+            * RESIDENT_BUDGETS;
+        self.evict(budget);
+        missing
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &Device,
+        ubo: &DynamicUniformBuffer<ChunkUBOContent>,
+    ) -> BindGroup {
+        BindGroupBuilder::new(&self.bind_group_layout)
+            .resource(ubo.binding_resource())
+            .create(device, "Point Cloud Bind Group")
+    }
+
+    fn upload(&mut self, device: &Device, key: ChunkKey, points: &[PointVertex]) {
+        let buffer = wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Point Cloud Chunk"),
+                contents: bytemuck::cast_slice(points),
+                usage: wgpu::BufferUsages::VERTEX,
+            },
+        );
+        let chunk = Chunk {
+            buffer,
+            len: points.len(),
+            last_picked: self.picks,
+        };
+        self.resident_points += chunk.len;
+        if let Some(replaced) = self.chunks.insert(key, chunk) {
+            self.resident_points -= replaced.len;
+        }
+    }
+
+    /// Frees the chunks picked longest ago until at most `budget` points are left, keeping those just picked
+    fn evict(&mut self, budget: usize) {
+        if self.resident_points <= budget {
+            return;
+        }
+        let mut chunks: Vec<(u64, ChunkKey)> = self
+            .chunks
+            .iter()
+            .filter(|(_, chunk)| chunk.last_picked < self.picks)
+            .map(|(&key, chunk)| (chunk.last_picked, key))
+            .collect();
+        chunks.sort_unstable();
+        for (_, key) in chunks {
+            if self.resident_points <= budget {
+                break;
+            }
+            if let Some(chunk) = self.chunks.remove(&key) {
+                self.resident_points -= chunk.len;
+            }
+        }
+    }
+
+    /// Frees the chunks of a cloud that was removed
+    pub fn remove_cloud(&mut self, cloud: usize) {
+        self.chunks.retain(|&(chunk_cloud, _), chunk| {
+            let keep = chunk_cloud != cloud;
+            if !keep {
+                self.resident_points -= chunk.len;
+            }
+            keep
+        });
+    }
+
+    /// Bytes of the uploaded points
+    pub fn size_in_bytes(&self) -> u64 {
+        (self.resident_points * std::mem::size_of::<PointVertex>()) as u64
+    }
+
+    /// Draws what [PointCloudPass::prepare] picked for the view, in the main pass
+    pub fn draw(
+        &self,
+        render_pass: &mut RenderPass,
+        view: Option<&PointCloudView>,
+        globals: &BindGroup,
+        stats: &mut RenderStats,
+    ) {
+        let Some(view) = view.filter(|view| !view.draws.is_empty()) else {
+            return;
+        };
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, globals, &[]);
+        stats.pipeline_switches += 1;
+        stats.bind_group_switches += 1;
+        for (key, offset) in &view.draws {
+            let Some(chunk) = self.chunks.get(key) else {
+                continue;
+            };
+            render_pass.set_bind_group(1, &view.bind_group, &[*offset]);
+            render_pass.set_vertex_buffer(0, chunk.buffer.slice(..));
+            render_pass.draw(0..6, 0..chunk.len as u32);
+            stats.bind_group_switches += 1;
+            stats.draw(6, chunk.len as u32);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+//! Reading the values of scalar volumes under the cursor, for inspecting simulations and scans.
+//!
+//! While the tool is on, the depth under the cursor is read back whenever it moves and unprojected to the point it
+//! hits, on a [crate::isosurface::Slice], an isosurface or anything else drawn. Every
+//! [crate::isosurface::Isosurface] volume around that point is sampled there on the CPU in each update, so the values
+//! follow live volumes while the cursor rests. They're drawn next to the point in every window. Like
+//! [crate::measure], nothing is read where the depth buffer can't be (WebGL2).
+
+use cgmath::{SquareMatrix, Vector3};
+
+use crate::{
+    input::InputEvent,
+    isosurface::{Isosurface, IsosurfaceHandle},
+    overlay::{OverlayGeometry, OverlayView},
+    wgpu_utils::readback::ReadbackFuture,
+};
+
+/// Half the size of the marker, in pixels
+const MARKER_SIZE: f32 = 3.0;
+/// The label is drawn this many pixels above the point
+const LABEL_OFFSET: f32 = 12.0;
+
+/// What's under the cursor
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeReading {
+    /// Where the cursor hits the scene, in world units
+    pub position: Vector3<f32>,
+    /// Of every volume around the position, in the order they were added
+    pub values: Vec<(IsosurfaceHandle, f32)>,
+}
+
+/// See the [module docs](self), off until [ProbeTool::set_enabled]
+pub struct ProbeTool {
+    enabled: bool,
+    pub color: [f32; 4],
+    reading: Option<ProbeReading>,
+    /// The depth asked for last, with the cursor it's under and the view it was seen through. Older ones are dropped,
+    /// only the latest cursor matters.
+    hover: Option<(ReadbackFuture<f32>, [f32; 2], OverlayView)>,
+}
+
+impl Default for ProbeTool {
+    fn default() -> Self {
+        ProbeTool {
+            enabled: false,
+            color: [0.6, 1.0, 0.6, 1.0],
+            reading: None,
+            hover: None,
+        }
+    }
+}
+
+impl ProbeTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Turning the tool off forgets the reading
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.reading = None;
+            self.hover = None;
+        }
+    }
+
+    /// None while the cursor is over nothing or outside the windows
+    pub fn reading(&self) -> Option<&ProbeReading> {
+        self.reading.as_ref()
+    }
+
+    /// Returns where the cursor moved to while the tool is on. The engine reads the depth there and hands it to
+    /// [ProbeTool::hover].
+    pub(crate) fn process_event(&mut self, event: &InputEvent) -> Option<[f32; 2]> {
+        if !self.enabled {
+            return None;
+        }
+        match event {
+            InputEvent::CursorMoved(cursor) => Some(*cursor),
+            InputEvent::CursorLeft => {
+                self.reading = None;
+                self.hover = None;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Waits for the depth under `cursor` in a window seen through `view`, instead of the one asked for before
+    pub(crate) fn hover(
+        &mut self,
+        depth: ReadbackFuture<f32>,
+        cursor: [f32; 2],
+        view: OverlayView,
+    ) {
+        self.hover = Some((depth, cursor, view));
+    }
+
+    /// Whether the depth under the cursor arrived, to be read in the next update
+    pub(crate) fn has_hover_ready(&self) -> bool {
+        self.hover
+            .as_ref()
+            .is_some_and(|(depth, ..)| depth.is_ready())
+    }
+
+    /// Moves to the point under the cursor if its depth arrived and samples the volumes there
+    pub(crate) fn update(&mut self, volumes: &[Option<Isosurface>]) {
+        if let Some((depth, ..)) = &mut self.hover {
+            if let Some(depth) = depth.try_take() {
+                let (_, cursor, view) = self.hover.take().expect("Hover was just read!");
+                // Nothing was drawn there
+                self.reading = depth
+                    .filter(|&depth| depth < 1.0)
+                    .map(|depth| ProbeReading {
+                        position: view.unproject(cursor, depth),
+                        values: Vec::new(),
+                    });
+            }
+        }
+        let Some(reading) = &mut self.reading else {
+            return;
+        };
+        let position = reading.position;
+        reading.values = volumes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, volume)| {
+                let volume = volume.as_ref()?;
+                let local = (volume.transform.invert()? * position.extend(1.0)).truncate();
+                Some((IsosurfaceHandle(index), volume.sample(local.into())?))
+            })
+            .collect();
+    }
+
+    /// A marker at the point and the values above it, laid out for the window `view` looks through
+    pub(crate) fn geometry(&self, view: &OverlayView) -> OverlayGeometry {
+        let mut geometry = OverlayGeometry::default();
+        let Some(reading) = &self.reading else {
+            return geometry;
+        };
+        let (right, up) = (view.right(), view.up());
+        let point = reading.position;
+        let size = MARKER_SIZE * view.pixel_size(point);
+        geometry.cube(
+            point,
+            [right * size, up * size, right.cross(up) * size],
+            self.color,
+        );
+        if !reading.values.is_empty() {
+            let text = reading
+                .values
+                .iter()
+                .map(|(_, value)| format!("{value:.3}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+            let above = point + up * (LABEL_OFFSET * view.pixel_size(point));
+            geometry.text(view, above, &text, self.color);
+        }
+        geometry
+    }
+}
@@ -0,0 +1,231 @@
+//! Per pass GPU timings from timestamp queries, alongside the CPU side of a frame. Enabled with
+//! [crate::render_engine_builder::RenderEngineBuilder::gpu_profiling] and read with
+//! [crate::render_engine::RenderEngine::frame_stats].
+//!
+//! Counts of what the engine recorded, e.g. draw calls, are always kept, see
+//! [crate::render_engine::RenderEngine::stats].
+
+use std::ops::AddAssign;
+
+use web_time::Duration;
+
+use crate::wgpu_utils::readback::ReadbackRing;
+
+/// Timestamps of at most this many passes are resolved per submission, later ones go untimed
+const MAX_SCOPES: u32 = 64;
+
+const TIMESTAMP_SIZE: u64 = std::mem::size_of::<u64>() as u64;
+
+/// How long one pass took on the GPU
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassTiming {
+    /// e.g. "main_pass window", or the [crate::plugin::EnginePlugin::name] for plugin passes
+    pub label: String,
+    pub gpu_time: Duration,
+}
+
+/// Timings of recent frames
+#[derive(Debug, Clone, Default)]
+pub struct FrameStats {
+    /// Time between the last two updates
+    pub cpu_frame_time: Duration,
+    /// Time spent in the last update, from simulating to submitting the render targets
+    pub cpu_update_time: Duration,
+    /// Time spent drawing windows since the last update
+    pub cpu_render_time: Duration,
+    /// The frame [FrameStats::gpu_passes] belong to. GPU timings arrive a few frames late.
+    pub gpu_frame_index: u64,
+    /// Empty without GPU profiling or on devices without [wgpu::Features::TIMESTAMP_QUERY]
+    pub gpu_passes: Vec<PassTiming>,
+}
+
+impl FrameStats {
+    /// Sum of [FrameStats::gpu_passes]
+    pub fn gpu_time(&self) -> Duration {
+        self.gpu_passes.iter().map(|pass| pass.gpu_time).sum()
+    }
+}
+
+/// What the engine recorded during one frame, over all windows and render targets. Plugin passes aren't counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    pub draw_calls: u64,
+    /// Submitted ones, including those culled by the GPU
+    pub triangles: u64,
+    /// Written through the staging belt, e.g. object and camera uniforms
+    pub upload_bytes: u64,
+    pub bind_group_switches: u64,
+    pub pipeline_switches: u64,
+}
+
+impl RenderStats {
+    /// Counts a draw of `index_count` indices forming a triangle list
+    pub(crate) fn draw(&mut self, index_count: u32, instance_count: u32) {
+        self.draw_calls += 1;
+        self.triangles += (index_count / 3) as u64 * instance_count as u64;
+    }
+}
+
+impl AddAssign for RenderStats {
+    fn add_assign(&mut self, other: Self) {
+        self.draw_calls += other.draw_calls;
+        self.triangles += other.triangles;
+        self.upload_bytes += other.upload_bytes;
+        self.bind_group_switches += other.bind_group_switches;
+        self.pipeline_switches += other.pipeline_switches;
+    }
+}
+
+/// Hands out pairs of timestamp queries to passes and reads them back asynchronously.
+///
+/// Every submission resolves the scopes recorded since the previous one into a readback buffer of its own. Those
+/// complete in submission order, so a frame is complete once a readback of a later frame is.
+pub(crate) struct GpuProfiler {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    /// Nanoseconds per timestamp tick
+    period: f32,
+    inside_encoders: bool,
+    frame_index: u64,
+    labels: Vec<String>,
+    /// Each submission's timestamps, tagged with their frame and pass labels
+    readbacks: ReadbackRing<(u64, Vec<String>)>,
+    /// Passes of the frame currently being read back
+    collecting: (u64, Vec<PassTiming>),
+    finished: Option<(u64, Vec<PassTiming>)>,
+}
+
+impl GpuProfiler {
+    /// None if the device can't write timestamps
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("Profiler Queries"),
+            ty: wgpu::QueryType::Timestamp,
+            count: MAX_SCOPES * 2,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Profiler Resolve Buffer"),
+            size: (MAX_SCOPES * 2) as u64 * TIMESTAMP_SIZE,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        Some(GpuProfiler {
+            query_set,
+            resolve_buffer,
+            period: queue.get_timestamp_period(),
+            inside_encoders: device
+                .features()
+                .contains(wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS),
+            frame_index: 0,
+            labels: Vec::new(),
+            readbacks: ReadbackRing::new("Profiler Readback Buffer"),
+            collecting: (0, Vec::new()),
+            finished: None,
+        })
+    }
+
+    /// Starts timing the passes of `frame_index` and collects the readbacks that finished so far. The device has to
+    /// be polled for them to make progress.
+    pub fn begin_frame(&mut self, frame_index: u64) {
+        self.frame_index = frame_index;
+        let Self {
+            readbacks,
+            collecting,
+            finished,
+            period,
+            ..
+        } = self;
+        readbacks.collect(|(frame_index, labels), data| {
+            if frame_index != collecting.0 {
+                let (finished_index, passes) = std::mem::take(collecting);
+                if !passes.is_empty() {
+                    *finished = Some((finished_index, passes));
+                }
+                collecting.0 = frame_index;
+            }
+
+            let timestamps: &[u64] = bytemuck::cast_slice(data);
+            for (label, pair) in labels.into_iter().zip(timestamps.chunks_exact(2)) {
+                let ticks = pair[1].saturating_sub(pair[0]);
+                collecting.1.push(PassTiming {
+                    label,
+                    gpu_time: Duration::from_nanos((ticks as f64 * *period as f64) as u64),
+                });
+            }
+        });
+    }
+
+    /// The latest frame whose passes have all been read back, taken out so it is only reported once
+    pub fn take_finished(&mut self) -> Option<(u64, Vec<PassTiming>)> {
+        self.finished.take()
+    }
+
+    /// Reserves a begin and end query for a pass, None once [MAX_SCOPES] passes were timed in this submission
+    pub fn scope(&mut self, label: impl Into<String>) -> Option<u32> {
+        let index = self.labels.len() as u32;
+        if index >= MAX_SCOPES {
+            return None;
+        }
+        self.labels.push(label.into());
+        Some(index * 2)
+    }
+
+    /// Timestamp writes for a render pass timed with the queries of [GpuProfiler::scope]
+    pub fn render_pass_writes(&self, query: u32) -> wgpu::RenderPassTimestampWrites<'_> {
+        wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(query),
+            end_of_pass_write_index: Some(query + 1),
+        }
+    }
+
+    /// Times whatever `record` adds to `encoder`, if the device can write timestamps between passes. Used for
+    /// plugin passes the engine doesn't create itself.
+    pub fn time_encoder(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        label: impl Into<String>,
+        record: impl FnOnce(&mut wgpu::CommandEncoder),
+    ) {
+        let query = self.inside_encoders.then(|| self.scope(label)).flatten();
+        if let Some(query) = query {
+            encoder.write_timestamp(&self.query_set, query);
+        }
+        record(encoder);
+        if let Some(query) = query {
+            encoder.write_timestamp(&self.query_set, query + 1);
+        }
+    }
+
+    /// Records copying the timestamps of this submission's scopes into a readback buffer. Has to come last in the
+    /// submission, followed by [GpuProfiler::after_submit].
+    pub fn resolve(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        if self.labels.is_empty() {
+            return;
+        }
+        let count = self.labels.len() as u32 * 2;
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        self.readbacks.copy(
+            device,
+            encoder,
+            &self.resolve_buffer,
+            0,
+            count as u64 * TIMESTAMP_SIZE,
+            (self.frame_index, std::mem::take(&mut self.labels)),
+        );
+    }
+
+    /// Starts mapping the readbacks submitted since the last call
+    pub fn after_submit(&mut self) {
+        self.readbacks.after_submit();
+    }
+
+    /// Whether readbacks are in flight, which need the device polled
+    pub fn has_pending_readbacks(&self) -> bool {
+        self.readbacks.is_pending()
+    }
+}
@@ -0,0 +1,125 @@
+//! A Python module wrapping [OffscreenRenderer] for notebooks, only with the `python` feature. Built with maturin
+//! from the crate's directory, `maturin develop --release`, it's imported as `the_camera`:
+//!
+//! ```python
+//! import the_camera
+//!
+//! renderer = the_camera.Renderer(640, 480)
+//! bunny = renderer.load_model("bunny.obj")
+//! renderer.set_color(bunny, (1.0, 0.5, 0.2, 1.0))
+//! renderer.look_at((0, 0.1, 0), yaw=0.8, pitch=0.3, distance=0.4)
+//! image = renderer.render()  # numpy.ndarray, (480, 640, 4) uint8
+//! ```
+//!
+//! Colors are RGBA tuples from 0 to 1 and angles are in radians, like in the rest of the engine. A renderer has to
+//! stay on the thread that created it.
+
+// The wrappers pyo3 generates convert what the methods return into a PyErr even when it already is one
+#![allow(clippy::useless_conversion)]
+
+use cgmath::{Quaternion, Vector3};
+use numpy::{PyArray1, PyArray3, PyArrayMethods};
+use pyo3::{exceptions::PyIOError, prelude::*};
+
+use crate::{background::Background, offscreen::OffscreenRenderer, scene::Transform};
+
+/// Renders models from an orbit camera into numpy arrays
+#[pyclass(unsendable, name = "Renderer", module = "the_camera")]
+struct Renderer(OffscreenRenderer);
+
+#[pymethods]
+impl Renderer {
+    #[new]
+    #[pyo3(signature = (width = 800, height = 600))]
+    fn new(width: u32, height: u32) -> Self {
+        Renderer(OffscreenRenderer::new(width, height))
+    }
+
+    /// Adds an OBJ model, points the camera at everything loaded so far and returns the model's index
+    fn load_model(&mut self, path: std::path::PathBuf) -> PyResult<usize> {
+        self.0
+            .load_model(&path)
+            .map_err(|err| PyIOError::new_err(format!("{}: {err}", path.display())))
+    }
+
+    fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    fn model_count(&self) -> usize {
+        self.0.model_count()
+    }
+
+    /// The rotation is a quaternion as `(x, y, z, w)`
+    #[pyo3(signature = (model, translation = [0.0; 3], rotation = [0.0, 0.0, 0.0, 1.0], scale = [1.0; 3]))]
+    fn set_transform(
+        &mut self,
+        model: usize,
+        translation: [f32; 3],
+        rotation: [f32; 4],
+        scale: [f32; 3],
+    ) {
+        let [x, y, z, w] = rotation;
+        let transform = Transform {
+            translation: translation.into(),
+            rotation: Quaternion::new(w, x, y, z),
+            scale: Vector3::from(scale),
+        };
+        self.0.set_transform(model, transform);
+    }
+
+    fn set_color(&mut self, model: usize, color: [f32; 4]) {
+        self.0.set_color(model, color);
+    }
+
+    /// How rough and how reflective a model's surface is, both from 0 to 1
+    #[pyo3(signature = (model, roughness = None, reflectivity = None))]
+    fn set_surface(&mut self, model: usize, roughness: Option<f32>, reflectivity: Option<f32>) {
+        self.0.set_surface(model, roughness, reflectivity);
+    }
+
+    /// One color, or a vertical gradient when `bottom` is given too
+    #[pyo3(signature = (top, bottom = None))]
+    fn set_background(&mut self, top: [f32; 4], bottom: Option<[f32; 4]>) {
+        self.0.set_background(match bottom {
+            Some(bottom) => Background::Gradient { top, bottom },
+            None => Background::Color(top),
+        });
+    }
+
+    fn look_at(&mut self, target: [f32; 3], yaw: f32, pitch: f32, distance: f32) {
+        self.0.look_at(target, yaw, pitch, distance);
+    }
+
+    /// Points the camera at every model loaded, from where it looks now
+    fn frame_all(&mut self) {
+        self.0.frame_all();
+    }
+
+    /// Vertical field of view in radians
+    fn set_fov(&mut self, fov: f32) {
+        self.0.camera_mut().fovy = cgmath::Rad(fov);
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.0.resize(width, height);
+    }
+
+    /// `(height, width, 4)`, the shape of the arrays [Renderer::render] returns
+    #[getter]
+    fn shape(&self) -> (usize, usize, usize) {
+        let [height, width, channels] = self.0.shape();
+        (height, width, channels)
+    }
+
+    /// Draws the scene into a new `(height, width, 4)` uint8 RGBA array, rows from the top
+    fn render<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyArray3<u8>>> {
+        let shape = self.0.shape();
+        PyArray1::from_vec_bound(py, self.0.render()).reshape(shape)
+    }
+}
+
+#[pymodule]
+fn the_camera(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Renderer>()
+}
@@ -0,0 +1,238 @@
+use std::{
+    collections::VecDeque,
+    io,
+    path::PathBuf,
+    sync::mpsc::{channel, Sender},
+    thread::JoinHandle,
+};
+
+use crate::screenshot::{write_png, FrameReadback, ReadbackStatus};
+
+/// Number of readback buffers in the ring. Frames are dropped when all of them are still in flight.
+const RING_SIZE: usize = 4;
+
+/// Where a [FrameRecorder] writes its frames to
+pub enum RecordingOutput {
+    /// Numbered PNGs (`frame_00000.png`, `frame_00001.png`, ...) inside the given directory
+    ImageSequence(PathBuf),
+
+    /// Raw frames piped into an `ffmpeg` process which encodes them to the given file
+    #[cfg(feature = "ffmpeg")]
+    Ffmpeg { path: PathBuf, fps: u32 },
+}
+
+/// Captures every nth rendered frame through a ring of readback buffers.
+///
+/// Frames are handed to a writer thread in the order they were rendered, so neither the PNG encoding nor ffmpeg
+/// ever block the render loop.
+pub struct FrameRecorder {
+    output: RecordingOutput,
+    every_nth: u32,
+    frames_seen: u64,
+    frames_dropped: u64,
+
+    slots: Vec<Option<FrameReadback>>,
+    /// Slot indices in the order their copies were submitted
+    in_flight: VecDeque<usize>,
+    /// Slots copied into during the current frame, mapped after submission
+    copied_this_frame: Vec<usize>,
+
+    writer: Option<(Sender<RecordedFrame>, JoinHandle<()>)>,
+}
+
+struct RecordedFrame {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+impl FrameRecorder {
+    /// Creates a recorder which keeps every `every_nth` frame, 1 records everything
+    pub fn new(output: RecordingOutput, every_nth: u32) -> io::Result<Self> {
+        match &output {
+            RecordingOutput::ImageSequence(dir) => std::fs::create_dir_all(dir)?,
+            #[cfg(feature = "ffmpeg")]
+            RecordingOutput::Ffmpeg { .. } => {}
+        }
+
+        Ok(FrameRecorder {
+            output,
+            every_nth: every_nth.max(1),
+            frames_seen: 0,
+            frames_dropped: 0,
+            slots: (0..RING_SIZE).map(|_| None).collect(),
+            in_flight: VecDeque::new(),
+            copied_this_frame: Vec::new(),
+            writer: None,
+        })
+    }
+
+    /// Records a copy of `texture` on `encoder` if this frame should be kept.
+    ///
+    /// [FrameRecorder::after_submit] has to be called once the encoder has been submitted.
+    pub fn record(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) {
+        let frame_index = self.frames_seen;
+        self.frames_seen += 1;
+        if !frame_index.is_multiple_of(self.every_nth as u64) {
+            return;
+        }
+
+        let Some(slot_index) = (0..RING_SIZE).find(|index| {
+            self.slots[*index]
+                .as_ref()
+                .is_none_or(|slot| !slot.is_busy())
+        }) else {
+            self.frames_dropped += 1;
+            return;
+        };
+
+        let slot = &mut self.slots[slot_index];
+        // Reallocate when the window has been resized since the buffer was last used
+        if !slot.as_ref().is_some_and(|slot| slot.fits(texture)) {
+            *slot = Some(FrameReadback::new(
+                device,
+                texture.width(),
+                texture.height(),
+                texture.format(),
+            ));
+        }
+        if let Some(slot) = slot {
+            slot.copy_from(encoder, texture);
+            self.copied_this_frame.push(slot_index);
+        }
+    }
+
+    /// Starts mapping the buffers copied into this frame
+    pub fn after_submit(&mut self) {
+        for slot_index in self.copied_this_frame.drain(..) {
+            if let Some(slot) = &mut self.slots[slot_index] {
+                slot.start_mapping();
+                self.in_flight.push_back(slot_index);
+            }
+        }
+    }
+
+    /// Hands every finished frame to the writer, oldest first. Stops at the first frame that isn't mapped yet
+    /// so the sequence stays in order.
+    pub fn collect(&mut self) {
+        while let Some(&slot_index) = self.in_flight.front() {
+            let Some(slot) = &mut self.slots[slot_index] else {
+                self.in_flight.pop_front();
+                continue;
+            };
+            let frame = match slot.try_read() {
+                ReadbackStatus::Pending => return,
+                ReadbackStatus::Ready(rgba) => RecordedFrame {
+                    width: slot.width(),
+                    height: slot.height(),
+                    rgba,
+                },
+                ReadbackStatus::Idle | ReadbackStatus::Failed => {
+                    self.in_flight.pop_front();
+                    continue;
+                }
+            };
+            self.in_flight.pop_front();
+            self.write(frame);
+        }
+    }
+
+    /// Whether there are copies in flight, the device has to be polled for them to complete
+    pub fn has_pending(&self) -> bool {
+        !self.in_flight.is_empty()
+    }
+
+    /// Waits for all frames in flight, then flushes and closes the output
+    pub fn finish(mut self, device: &wgpu::Device) {
+        while self.has_pending() {
+            device.poll(wgpu::Maintain::Wait);
+            self.collect();
+        }
+        if let Some((sender, handle)) = self.writer.take() {
+            drop(sender);
+            let _ = handle.join();
+        }
+        if self.frames_dropped > 0 {
+            tracing::warn!(
+                "Recording dropped {} frames because the GPU readback couldn't keep up",
+                self.frames_dropped
+            );
+        }
+    }
+
+    fn write(&mut self, frame: RecordedFrame) {
+        if self.writer.is_none() {
+            // The first frame decides the video size, which ffmpeg needs up front
+            match spawn_writer(&self.output, frame.width, frame.height) {
+                Ok(writer) => self.writer = Some(writer),
+                Err(err) => {
+                    tracing::error!("Failed to start recording: {err}");
+                    return;
+                }
+            }
+        }
+        if let Some((sender, _)) = &self.writer {
+            let _ = sender.send(frame);
+        }
+    }
+}
+
+fn spawn_writer(
+    output: &RecordingOutput,
+    width: u32,
+    height: u32,
+) -> io::Result<(Sender<RecordedFrame>, JoinHandle<()>)> {
+    let (sender, receiver) = channel::<RecordedFrame>();
+
+    let handle = match output {
+        RecordingOutput::ImageSequence(dir) => {
+            let dir = dir.clone();
+            std::thread::spawn(move || {
+                for (index, frame) in receiver.into_iter().enumerate() {
+                    let path = dir.join(format!("frame_{index:05}.png"));
+                    if let Err(err) = write_png(&path, frame.width, frame.height, &frame.rgba) {
+                        tracing::error!("Failed to write {}: {err}", path.display());
+                    }
+                }
+            })
+        }
+        #[cfg(feature = "ffmpeg")]
+        RecordingOutput::Ffmpeg { path, fps } => {
+            use std::io::Write;
+
+            let mut child = std::process::Command::new("ffmpeg")
+                .args(["-y", "-f", "rawvideo", "-pix_fmt", "rgba"])
+                .args(["-s", &format!("{width}x{height}")])
+                .args(["-r", &fps.to_string()])
+                .args(["-i", "-", "-pix_fmt", "yuv420p"])
+                .arg(path)
+                .stdin(std::process::Stdio::piped())
+                .spawn()?;
+            let mut stdin = child.stdin.take().expect("ffmpeg stdin is piped");
+            std::thread::spawn(move || {
+                for frame in receiver {
+                    // ffmpeg reads fixed size raw frames, so anything recorded after a resize can't go in
+                    if (frame.width, frame.height) != (width, height) {
+                        continue;
+                    }
+                    if let Err(err) = stdin.write_all(&frame.rgba) {
+                        tracing::error!("Failed to pipe frame to ffmpeg: {err}");
+                        break;
+                    }
+                }
+                drop(stdin);
+                let _ = child.wait();
+            })
+        }
+    };
+
+    #[cfg(not(feature = "ffmpeg"))]
+    let _ = (width, height);
+
+    Ok((sender, handle))
+}
@@ -0,0 +1,61 @@
+// The level above the one drawn, see reflection_probe/pass.rs
+@group(0) @binding(0)
+var t_source: texture_cube<f32>;
+@group(0) @binding(1)
+var s_source: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) @interpolate(flat) face: u32,
+};
+
+// One triangle covering the face, the instance is the face drawn
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, @builtin(instance_index) face: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+    out.face = face;
+    return out;
+}
+
+// Towards the texel at `st` from -1 to 1 across the face, rows running down, as cubemaps are laid out
+fn face_direction(face: u32, st: vec2<f32>) -> vec3<f32> {
+    switch face {
+        case 0u: { return vec3<f32>(1.0, -st.y, -st.x); }
+        case 1u: { return vec3<f32>(-1.0, -st.y, st.x); }
+        case 2u: { return vec3<f32>(st.x, 1.0, st.y); }
+        case 3u: { return vec3<f32>(st.x, -1.0, -st.y); }
+        case 4u: { return vec3<f32>(st.x, -st.y, 1.0); }
+        default: { return vec3<f32>(-st.x, -st.y, -1.0); }
+    }
+}
+
+const TAPS = 16u;
+const GOLDEN_ANGLE = 2.39996323;
+
+// Averages the level above over a disc about two of this level's texels wide, every level blurs a little more of
+// the scene than the one before
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = f32(textureDimensions(t_source).x) / 2.0;
+    let st = in.clip_position.xy / size * 2.0 - 1.0;
+    let direction = normalize(face_direction(in.face, st));
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(direction.y) > 0.9 {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, direction));
+    let bitangent = cross(direction, tangent);
+
+    let radius = 4.0 / size;
+    var color = vec3<f32>(0.0);
+    for (var tap = 0u; tap < TAPS; tap++) {
+        // Spread evenly over the disc along a spiral
+        let distance = sqrt((f32(tap) + 0.5) / f32(TAPS)) * radius;
+        let angle = f32(tap) * GOLDEN_ANGLE;
+        let offset = (cos(angle) * tangent + sin(angle) * bitangent) * distance;
+        color += textureSampleLevel(t_source, s_source, direction + offset, 0.0).rgb;
+    }
+    return vec4<f32>(color / f32(TAPS), 1.0);
+}
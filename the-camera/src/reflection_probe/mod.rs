@@ -0,0 +1,239 @@
+//! Reflections of the scene around a spot, for materials with a [crate::scene::Material::reflectivity], a
+//! [crate::scene::Material::clearcoat] or an [crate::scene::Material::ior].
+//!
+//! A probe draws the scene into the six faces of a cubemap from its position, all six at once whenever it's added,
+//! changed or asked to with [crate::render_engine::RenderEngine::capture_reflection_probe], or a few faces every
+//! frame with [ProbeUpdate::Amortized]. Each mip level of the cube is blurred from the one before, rougher materials
+//! sample further down the chain.
+//!
+//! Every object samples the probe whose box it's in, the nearest box otherwise. The reflected rays are followed to
+//! where they leave that box and the cube is sampled towards that point from the probe, so reflections of the walls
+//! of a room line up with them wherever in the room the object is. Meshes carry no normals, the surfaces reflect as
+//! if flat across each triangle.
+//!
+//! At most [MAX_PROBES] probes are sampled at once, each face [PROBE_RESOLUTION] pixels square. What every view
+//! streams in on its own, i.e. point clouds, terrains, vegetation, voxel grids and the atmosphere, isn't captured.
+
+mod pass;
+
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3};
+use wgpu::{Device, Queue, TextureFormat};
+
+pub(crate) use self::pass::{ProbeTextures, ReflectionProbePass, NO_PROBE};
+pub use self::pass::{MAX_PROBES, PROBE_RESOLUTION};
+use crate::{
+    audio::AudioUniform,
+    camera::{
+        camera::{convert_matrix4_to_array, CameraUniform},
+        orbit_camera::OPENGL_TO_WGPU_MATRIX,
+    },
+    clipping::ClipUniform,
+    lazy_pass::LazyPass,
+    wgpu_utils::{resource_cache::SamplerCache, uploader::Uploader},
+};
+
+/// When a [ReflectionProbe] draws the scene again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeUpdate {
+    /// Only when it's added, changed or asked to, for scenes that stand still
+    OnDemand,
+    /// Continuously, this many of the six faces every frame, which keeps the engine drawing
+    Amortized { faces_per_frame: u32 },
+}
+
+/// A cubemap of the scene the objects around it reflect, added with
+/// [crate::render_engine::RenderEngine::add_reflection_probe]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReflectionProbe {
+    /// Where the scene is drawn from
+    pub position: [f32; 3],
+    /// Corners of the box reflections are projected onto, usually the walls of the room the probe is in. Objects
+    /// inside it sample this probe.
+    pub box_min: [f32; 3],
+    pub box_max: [f32; 3],
+    pub update: ProbeUpdate,
+    /// Distances from the position the scene is drawn between
+    pub near: f32,
+    pub far: f32,
+}
+
+impl ReflectionProbe {
+    /// A probe at the center of the box
+    pub fn new(box_min: [f32; 3], box_max: [f32; 3]) -> Self {
+        ReflectionProbe {
+            position: [0, 1, 2].map(|axis| (box_min[axis] + box_max[axis]) / 2.0),
+            box_min,
+            box_max,
+            update: ProbeUpdate::OnDemand,
+            near: 0.05,
+            far: 1000.0,
+        }
+    }
+
+    pub fn with_position(mut self, position: [f32; 3]) -> Self {
+        self.position = position;
+        self
+    }
+
+    pub fn with_update(mut self, update: ProbeUpdate) -> Self {
+        self.update = update;
+        self
+    }
+
+    pub fn with_range(mut self, near: f32, far: f32) -> Self {
+        self.near = near;
+        self.far = far;
+        self
+    }
+
+    /// How far the point is outside the box, 0 inside it
+    pub(crate) fn distance(&self, point: [f32; 3]) -> f32 {
+        let outside = [0, 1, 2].map(|axis| {
+            (self.box_min[axis] - point[axis])
+                .max(point[axis] - self.box_max[axis])
+                .max(0.0)
+        });
+        Vector3::from(outside).magnitude()
+    }
+
+    /// The camera drawing one face, see [cube_face_camera]
+    pub(crate) fn face_camera(&self, face: usize) -> CameraUniform {
+        cube_face_camera(self.position, self.near, self.far, face)
+    }
+}
+
+/// The camera drawing one face of a cube around `position`. The cube holds the world mirrored along Z, so the
+/// cameras turn the way cubemaps are laid out without mirroring what they draw, the shaders sample it mirrored back.
+pub(crate) fn cube_face_camera(
+    position: [f32; 3],
+    near: f32,
+    far: f32,
+    face: usize,
+) -> CameraUniform {
+    let (direction, up) = [
+        (Vector3::unit_x(), Vector3::unit_y()),
+        (-Vector3::unit_x(), Vector3::unit_y()),
+        (Vector3::unit_y(), Vector3::unit_z()),
+        (-Vector3::unit_y(), -Vector3::unit_z()),
+        (-Vector3::unit_z(), Vector3::unit_y()),
+        (Vector3::unit_z(), Vector3::unit_y()),
+    ][face];
+    let eye = Point3::from(position);
+    let view = Matrix4::look_to_rh(eye, direction, up);
+    let projection = cgmath::perspective(cgmath::Deg(90.0), 1.0, near, far);
+    CameraUniform {
+        view_position: [eye.x, eye.y, eye.z, 1.0],
+        view_proj: convert_matrix4_to_array(OPENGL_TO_WGPU_MATRIX * projection * view),
+    }
+}
+
+/// Refers to a probe added with [crate::render_engine::RenderEngine::add_reflection_probe]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReflectionProbeHandle(pub(crate) usize);
+
+/// The engine's reflection probes and the pass capturing them, which is created with the first probe
+#[derive(Default)]
+pub(crate) struct ReflectionProbes {
+    pass: LazyPass<ReflectionProbePass>,
+    /// None where a probe was removed
+    probes: Vec<Option<ReflectionProbe>>,
+}
+
+impl ReflectionProbes {
+    pub fn add(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        samplers: &SamplerCache,
+        textures: &ProbeTextures,
+        probe: ReflectionProbe,
+    ) -> ReflectionProbeHandle {
+        let pass = self
+            .pass
+            .get_or_create(|| Some(ReflectionProbePass::new(device, format, samplers, textures)));
+        if pass.is_some_and(|pass| pass.is_full()) {
+            tracing::warn!(
+                "Only {MAX_PROBES} reflection probes are sampled at once, the probe isn't captured"
+            );
+        }
+        self.probes.push(Some(probe));
+        ReflectionProbeHandle(self.probes.len() - 1)
+    }
+
+    pub fn get(&self, handle: ReflectionProbeHandle) -> Option<&ReflectionProbe> {
+        self.probes.get(handle.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: ReflectionProbeHandle) -> Option<&mut ReflectionProbe> {
+        self.probes.get_mut(handle.0)?.as_mut()
+    }
+
+    /// The probe's cube is freed by the next prepare
+    pub fn remove(&mut self, handle: ReflectionProbeHandle) -> Option<ReflectionProbe> {
+        self.probes.get_mut(handle.0)?.take()
+    }
+
+    /// See [ReflectionProbePass::capture]
+    pub fn capture(&mut self, handle: ReflectionProbeHandle) {
+        if let Some(pass) = self.pass.get_mut() {
+            pass.capture(handle.0);
+        }
+    }
+
+    /// See [ReflectionProbePass::nearest], [NO_PROBE] before the first probe
+    pub fn nearest(&self, point: [f32; 3]) -> u32 {
+        self.pass
+            .get()
+            .map_or(NO_PROBE, |pass| pass.nearest(&self.probes, point))
+    }
+
+    /// See [ReflectionProbePass::prepare], true while any probe catches up with the scene a few faces at a time
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        uploader: &mut Uploader,
+        textures: &mut ProbeTextures,
+        clipping: ClipUniform,
+        audio: AudioUniform,
+    ) -> bool {
+        let Some(pass) = self.pass.get_mut() else {
+            return false;
+        };
+        pass.prepare(
+            device,
+            queue,
+            uploader,
+            textures,
+            &self.probes,
+            clipping,
+            audio,
+        );
+        pass.is_moving(&self.probes)
+    }
+
+    /// Captures the faces due this frame, None before the first probe
+    pub fn pass(&self) -> Option<&ReflectionProbePass> {
+        self.pass.get()
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.pass
+            .get()
+            .map_or(0, ReflectionProbePass::size_in_bytes)
+    }
+
+    /// Creates the pass again on a new device, every probe is captured again with the next frame
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        samplers: &SamplerCache,
+        textures: &ProbeTextures,
+    ) {
+        self.pass
+            .recreate(|| Some(ReflectionProbePass::new(device, format, samplers, textures)));
+    }
+}
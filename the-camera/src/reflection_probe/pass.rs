@@ -0,0 +1,475 @@
+use wgpu::{BindGroup, CommandEncoder, Device, Queue, RenderPipeline, TextureFormat, TextureView};
+
+use super::{ProbeUpdate, ReflectionProbe};
+use crate::{
+    audio::AudioUniform,
+    clipping::ClipUniform,
+    global_bindings::{update_global_ubo, GlobalBindings, GlobalUBO},
+    occlusion::OcclusionQueries,
+    texture::Texture,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        resource_cache::SamplerCache,
+        uniform_buffer::UniformBuffer,
+        uploader::Uploader,
+    },
+};
+
+/// Probes sampled at once, the rest aren't captured. Has to match the cubes bound in shader.wgsl.
+pub const MAX_PROBES: usize = 4;
+/// Width and height of every face of a probe's cube, in pixels
+pub const PROBE_RESOLUTION: u32 = 128;
+/// Of every cube, down to faces of 8 pixels. Has to match `PROBE_MIPS` in shader.wgsl.
+const PROBE_MIPS: u32 = 5;
+/// Tells objects not to sample any probe, see [ReflectionProbePass::nearest]
+pub(crate) const NO_PROBE: u32 = u32::MAX;
+
+/// One probe as the materials see it
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct ProbeUniform {
+    /// W is 1 where the cube holds a probe, 0 for an unused one
+    position: [f32; 4],
+    box_min: [f32; 4],
+    box_max: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct ProbesUBOContent {
+    probes: [ProbeUniform; MAX_PROBES],
+}
+
+crate::assert_uniform_layout!(ProbesUBOContent { probes: ALIGN_VEC4 });
+
+/// The cube of every probe and where they are, bound with every material so any object can reflect any probe. The
+/// cubes are there from the start, so material bind groups never change with the probes.
+pub(crate) struct ProbeTextures {
+    cubes: [Texture; MAX_PROBES],
+    ubo: UniformBuffer<ProbesUBOContent>,
+}
+
+impl ProbeTextures {
+    /// `format` has to be the engine's swapchain format, the faces are copied from the main pass drawing them
+    pub fn new(device: &Device, samplers: &SamplerCache, format: TextureFormat) -> Self {
+        ProbeTextures {
+            cubes: std::array::from_fn(|_| {
+                Texture::create_cube_render_target(
+                    device,
+                    samplers,
+                    PROBE_RESOLUTION,
+                    PROBE_MIPS,
+                    format,
+                    "reflection_probe",
+                )
+            }),
+            ubo: UniformBuffer::new_with_data(device, &ProbesUBOContent::default()),
+        }
+    }
+
+    /// Adds the uniform, the sampler and the cubes to a bind group of [ProbeTextures::layout]
+    pub fn bind<'a>(&'a self, builder: BindGroupBuilder<'a>) -> BindGroupBuilder<'a> {
+        let builder = builder
+            .resource(self.ubo.binding_resource())
+            .sampler(&self.cubes[0].sampler);
+        self.cubes
+            .iter()
+            .fold(builder, |builder, cube| builder.texture(&cube.view))
+    }
+
+    /// Adds the bindings of [ProbeTextures::bind] to a layout for fragment shaders
+    pub fn layout(builder: BindGroupLayoutBuilder) -> BindGroupLayoutBuilder {
+        let builder = builder
+            .next_binding_fragment(binding_types::uniform())
+            .named("probes")
+            .next_binding_fragment(binding_types::sampler(wgpu::SamplerBindingType::Filtering))
+            .named("s_probe");
+        (0..MAX_PROBES).fold(builder, |builder, _| {
+            builder.next_binding_fragment(binding_types::textureCube())
+        })
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.cubes.iter().map(Texture::size_in_bytes).sum()
+    }
+}
+
+/// A probe that got a cube
+struct ProbeSlot {
+    /// Of the probe in the engine
+    index: usize,
+    /// The probe as it was captured last, None to capture it again
+    captured: Option<ReflectionProbe>,
+    /// Captured next by [ProbeUpdate::Amortized]
+    next_face: usize,
+    /// Every face's camera
+    faces: [(GlobalUBO, GlobalBindings); 6],
+}
+
+/// Draws the probes' faces into their cubes and blurs their mip levels
+pub(crate) struct ReflectionProbePass {
+    prefilter: RenderPipeline,
+    /// Of every cube, one for every level below the first sampling the level above it
+    prefilter_bind_groups: Vec<Vec<BindGroup>>,
+    /// Faces are drawn here, then copied into the cubes. The cubes can't be drawn into directly since every material
+    /// samples them.
+    color: Texture,
+    depth: Texture,
+    ids: Texture,
+    /// Never queried, the faces draw everything
+    occlusion: OcclusionQueries,
+    slots: [Option<ProbeSlot>; MAX_PROBES],
+    /// Cube and face of every face drawn this frame
+    due: Vec<(usize, usize)>,
+}
+
+impl ReflectionProbePass {
+    /// `format` has to be the engine's swapchain format, the faces are drawn by the main pass
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        samplers: &SamplerCache,
+        textures: &ProbeTextures,
+    ) -> Self {
+        let _span = tracing::debug_span!("create_reflection_probe_pipeline").entered();
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_fragment(binding_types::textureCube())
+            .named("t_source")
+            .next_binding_fragment(binding_types::sampler(wgpu::SamplerBindingType::Filtering))
+            .named("s_source")
+            .create(device, "Reflection Probe Prefilter Bind Group");
+        let prefilter = create_prefilter_pipeline(device, format, &bind_group_layout);
+        let prefilter_bind_groups = textures
+            .cubes
+            .iter()
+            .map(|cube| {
+                (1..PROBE_MIPS)
+                    .map(|mip| {
+                        let source = cube.view_with(&wgpu::TextureViewDescriptor {
+                            label: Some("Reflection Probe Source"),
+                            dimension: Some(wgpu::TextureViewDimension::Cube),
+                            base_mip_level: mip - 1,
+                            mip_level_count: Some(1),
+                            ..Default::default()
+                        });
+                        BindGroupBuilder::new(&bind_group_layout)
+                            .texture(&source)
+                            .sampler(&cube.sampler)
+                            .create(device, "Reflection Probe Prefilter Bind Group")
+                    })
+                    .collect()
+            })
+            .collect();
+
+        ReflectionProbePass {
+            prefilter,
+            prefilter_bind_groups,
+            color: Texture::create_render_target(
+                device,
+                samplers,
+                PROBE_RESOLUTION,
+                PROBE_RESOLUTION,
+                format,
+                "reflection_probe_face",
+            ),
+            depth: Texture::create_depth_texture_with_size(
+                device,
+                samplers,
+                PROBE_RESOLUTION,
+                PROBE_RESOLUTION,
+                "reflection_probe_depth",
+            ),
+            ids: Texture::create_id_texture(
+                device,
+                samplers,
+                PROBE_RESOLUTION,
+                PROBE_RESOLUTION,
+                "reflection_probe_ids",
+            ),
+            occlusion: OcclusionQueries::new(),
+            slots: std::array::from_fn(|_| None),
+            due: Vec::new(),
+        }
+    }
+
+    /// Gives new probes a cube while there are any left, picks the faces to draw this frame and uploads their
+    /// cameras, along with where the probes are for the materials
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        uploader: &mut Uploader,
+        textures: &mut ProbeTextures,
+        probes: &[Option<ReflectionProbe>],
+        clipping: ClipUniform,
+        audio: AudioUniform,
+    ) {
+        for slot in &mut self.slots {
+            if slot
+                .as_ref()
+                .is_some_and(|slot| !matches!(probes.get(slot.index), Some(Some(_))))
+            {
+                *slot = None;
+            }
+        }
+        for (index, probe) in probes.iter().enumerate() {
+            if probe.is_none() || self.slot_of(index).is_some() {
+                continue;
+            }
+            let Some(free) = self.slots.iter_mut().find(|slot| slot.is_none()) else {
+                break;
+            };
+            *free = Some(ProbeSlot {
+                index,
+                captured: None,
+                next_face: 0,
+                faces: std::array::from_fn(|_| {
+                    let ubo = GlobalUBO::new(device);
+                    let mut bindings = GlobalBindings::new(device);
+                    bindings.create_bind_group(device, &ubo);
+                    (ubo, bindings)
+                }),
+            });
+        }
+
+        self.due.clear();
+        let mut content = ProbesUBOContent::default();
+        for (cube, slot) in self.slots.iter_mut().enumerate() {
+            let Some(slot) = slot else {
+                continue;
+            };
+            let probe = probes[slot.index].expect("Probes without a cube were freed!");
+            let faces = if slot.captured != Some(probe) {
+                slot.captured = Some(probe);
+                6
+            } else {
+                match probe.update {
+                    ProbeUpdate::OnDemand => 0,
+                    ProbeUpdate::Amortized { faces_per_frame } => faces_per_frame.min(6) as usize,
+                }
+            };
+            for _ in 0..faces {
+                let face = slot.next_face;
+                slot.next_face = (face + 1) % 6;
+                update_global_ubo(
+                    &mut slot.faces[face].0,
+                    uploader,
+                    device,
+                    probe.face_camera(face),
+                    clipping,
+                    audio,
+                );
+                self.due.push((cube, face));
+            }
+
+            let [x, y, z] = probe.position;
+            let [min_x, min_y, min_z] = probe.box_min;
+            let [max_x, max_y, max_z] = probe.box_max;
+            content.probes[cube] = ProbeUniform {
+                position: [x, y, z, 1.0],
+                box_min: [min_x, min_y, min_z, 0.0],
+                box_max: [max_x, max_y, max_z, 0.0],
+            };
+        }
+        textures.ubo.update_content(queue, content);
+    }
+
+    fn slot_of(&self, index: usize) -> Option<usize> {
+        self.slots
+            .iter()
+            .position(|slot| slot.as_ref().is_some_and(|slot| slot.index == index))
+    }
+
+    /// Draws the probe again with the next frame, if it has a cube
+    pub fn capture(&mut self, index: usize) {
+        if let Some(cube) = self.slot_of(index) {
+            if let Some(slot) = &mut self.slots[cube] {
+                slot.captured = None;
+            }
+        }
+    }
+
+    /// Whether a probe draws some faces every frame
+    pub fn is_moving(&self, probes: &[Option<ReflectionProbe>]) -> bool {
+        self.slots.iter().flatten().any(|slot| {
+            matches!(
+                probes.get(slot.index),
+                Some(Some(ReflectionProbe {
+                    update: ProbeUpdate::Amortized {
+                        faces_per_frame: 1..
+                    },
+                    ..
+                }))
+            )
+        })
+    }
+
+    /// Whether a probe got no cube since all of them are taken
+    pub fn is_full(&self) -> bool {
+        self.slots.iter().all(Option::is_some)
+    }
+
+    /// The cube an object at `point` reflects, of the probe whose box it's in or the nearest one, [NO_PROBE]
+    /// without probes
+    pub fn nearest(&self, probes: &[Option<ReflectionProbe>], point: [f32; 3]) -> u32 {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(cube, slot)| {
+                let probe = probes.get(slot.as_ref()?.index)?.as_ref()?;
+                Some((cube, probe.distance(point)))
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map_or(NO_PROBE, |(cube, _)| cube as u32)
+    }
+
+    /// Cube and face of every face to draw this frame
+    pub fn due_faces(&self) -> &[(usize, usize)] {
+        &self.due
+    }
+
+    /// Bound while drawing the face
+    pub fn globals(&self, cube: usize, face: usize) -> &BindGroup {
+        let slot = self.slots[cube]
+            .as_ref()
+            .expect("Faces are drawn for probes with a cube!");
+        slot.faces[face].1.bind_groups()
+    }
+
+    pub fn occlusion(&self) -> &OcclusionQueries {
+        &self.occlusion
+    }
+
+    pub fn color_view(&self) -> &TextureView {
+        &self.color.view
+    }
+
+    pub fn depth_view(&self) -> &TextureView {
+        &self.depth.view
+    }
+
+    pub fn id_view(&self) -> &TextureView {
+        &self.ids.view
+    }
+
+    /// Copies what the main pass just drew into the face of the cube
+    pub fn copy_face(
+        &self,
+        encoder: &mut CommandEncoder,
+        textures: &ProbeTextures,
+        cube: usize,
+        face: usize,
+    ) {
+        encoder.copy_texture_to_texture(
+            self.color.texture.as_image_copy(),
+            wgpu::ImageCopyTexture {
+                texture: &textures.cubes[cube].texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: face as u32,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::Extent3d {
+                width: PROBE_RESOLUTION,
+                height: PROBE_RESOLUTION,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    /// Blurs every mip level of the cubes whose faces were drawn this frame from the level above
+    pub fn prefilter(&self, encoder: &mut CommandEncoder, textures: &ProbeTextures) {
+        let mut cubes: Vec<usize> = self.due.iter().map(|&(cube, _)| cube).collect();
+        cubes.dedup();
+        for cube in cubes {
+            let texture = &textures.cubes[cube];
+            for mip in 1..PROBE_MIPS {
+                for face in 0..6 {
+                    let target = texture.view_with(&wgpu::TextureViewDescriptor {
+                        label: Some("Reflection Probe Face"),
+                        dimension: Some(wgpu::TextureViewDimension::D2),
+                        base_mip_level: mip,
+                        mip_level_count: Some(1),
+                        base_array_layer: face,
+                        array_layer_count: Some(1),
+                        ..Default::default()
+                    });
+                    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("Reflection Probe Prefilter"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &target,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                                store: wgpu::StoreOp::Store,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                        occlusion_query_set: None,
+                        timestamp_writes: None,
+                    });
+                    render_pass.set_pipeline(&self.prefilter);
+                    render_pass.set_bind_group(
+                        0,
+                        &self.prefilter_bind_groups[cube][mip as usize - 1],
+                        &[],
+                    );
+                    // The instance tells the shader which face it draws
+                    render_pass.draw(0..3, face..face + 1);
+                }
+            }
+        }
+    }
+
+    /// Bytes of the textures the faces are drawn into
+    pub fn size_in_bytes(&self) -> u64 {
+        self.color.size_in_bytes() + self.depth.size_in_bytes() + self.ids.size_in_bytes()
+    }
+}
+
+/// One face of a cube's mip level, covered by a single triangle
+fn create_prefilter_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    bind_group_layout: &BindGroupLayoutWithDesc,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Reflection Probe Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../reflection_probe.wgsl").into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Reflection Probe Pipeline Layout"),
+        bind_group_layouts: &[&bind_group_layout.layout],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Reflection Probe Prefilter Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: None,
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+            compilation_options: Default::default(),
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
@@ -1,285 +1,4445 @@
-use std::iter;
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use cgmath::{Matrix4, Vector3};
+use web_time::{Duration, Instant};
 
-use cgmath::Vector3;
 use wgpu::{
-    Buffer, DepthStencilState, Device, Queue, RenderPipeline, Surface, SurfaceConfiguration,
+    rwh::{RawDisplayHandle, RawWindowHandle},
+    Adapter, CommandBuffer, DepthStencilState, Device, Instance, Queue, RenderPipeline, Surface,
     TextureFormat,
 };
-use winit::{event::DeviceEvent, window::Window};
+use winit::{
+    event::{DeviceEvent, WindowEvent},
+    event_loop::ControlFlow,
+    keyboard::KeyCode,
+    window::WindowId,
+};
 
+#[cfg(feature = "egui")]
+use crate::egui_pass::EguiPass;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::gltf::{ExportMaterial, ExportObject, ExportScene};
+#[cfg(feature = "hot-reload")]
+use crate::hot_reload::{AssetWatcher, WatchedAsset};
+#[cfg(feature = "meshlets")]
+use crate::meshlet::{MeshletCulling, MeshletInstance, MeshletView};
+#[cfg(feature = "rhai")]
+use crate::rhai_script::RhaiScript;
+#[cfg(feature = "sync")]
+use crate::sync::SceneSync;
+#[cfg(feature = "ffmpeg")]
+use crate::video::{GpuVideo, VideoHandle, VideoTexture};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::wgpu_utils::readback::{read_texture_to_vec, ReadFormat};
 use crate::{
-    camera::{camera_controller::CameraController, orbit_camera::OrbitCamera},
-    global_bindings::{update_global_ubo, GlobalBindings, GlobalUBO},
-    mesh::{Vertex, INDICES, VERTICES},
-    texture,
+    animation::{AnimatedModel, AnimationPlayer, AnimationPlayerHandle},
+    annotation::Annotation,
+    atmosphere::{Atmosphere, Sky},
+    audio::{AudioAnalyzer, AudioFeed, AUDIO_BANDS},
+    background::{Background, BackgroundPass},
+    boids::{Flock, FlockHandle, Flocks},
+    camera::{
+        camera::Camera,
+        camera_controller::CameraController,
+        orbit_camera::{OrbitCamera, Projection},
+        view_animation::{orbit_angles, StandardView, ViewAnimation, VIEW_ANIMATION_DURATION},
+    },
+    clipping::{ClipPlane, ClipUniform, MAX_CLIP_PLANES},
+    cloth::{Cloth, ClothHandle, Cloths},
+    colormap::{Colormap, ColormapLut, ColormapTextures},
+    console::{self, CommandResult, Console},
+    debug,
+    debug_hud::DebugHud,
+    depth_pyramid::DepthPyramidPass,
+    device_lost::DeviceLostFlag,
+    dynamic_resolution::{DynamicResolution, ResolutionController},
+    events::{EngineEvent, EventBus},
+    external_surface::ExternalSurface,
+    flipbook::{FlipbookHandle, FlipbookPlayback, FlipbookStorage, GpuFlipbook},
+    frame::{Draw, DrawTexture, FrameContext},
+    frame_pacing::{DeltaSmoother, FrameLimiter},
+    gizmo::Gizmo,
+    global_bindings::GlobalBindings,
+    heatmap::{Heatmap, HeatmapHandle, Heatmaps},
+    input::{CursorMode, Input, InputEvent},
+    irradiance_volume::{Irradiance, IrradianceVolume},
+    isosurface::{Isosurface, IsosurfaceHandle, Isosurfaces, Slice, SliceHandle},
+    jobs::JobSystem,
+    main_pass::MainPass,
+    material_bindings::MaterialBindings,
+    measure::MeasureTool,
+    mesh::{MeshData, MeshPool, Vertex},
+    object_bindings::{ObjectBindings, ObjectUBOContent},
+    occlusion::{OcclusionPass, OcclusionProxy},
+    outline::OutlinePass,
+    overlay::{OverlayGeometry, OverlayPass, OverlayView},
+    platform::{RawWindow, SurfaceProvider},
+    plot::{Plot, PlotHandle, Plots},
+    plugin::{EnginePlugin, OverlayTarget, PassTarget, PluginContext},
+    point_cloud::{PointCloud, PointCloudHandle, PointClouds},
+    probe::ProbeTool,
+    profiler::{FrameStats, GpuProfiler, RenderStats},
+    recording::{FrameRecorder, RecordingOutput},
+    reflection_probe::{ReflectionProbe, ReflectionProbeHandle, ReflectionProbes, NO_PROBE},
+    render_engine_builder::{DeviceReport, RenderEngineBuilder},
+    render_target::{RenderTarget, RenderTargetHandle},
+    rulers,
+    scene::{Material, MaterialHandle, MeshHandle, Pick, Renderable, TextureHandle},
+    screenshot::PendingCapture,
+    script::{FrameScript, Script, ScriptError},
+    selection::{Selection, SelectionChange},
+    simulation::{ParticleSimulation, SimulationHandle, Simulations},
+    terrain::{Terrain, TerrainHandle, Terrains},
+    texture::{self, GpuTexture, TextureData},
+    upscaling::{UpscalingPass, MIN_RENDER_SCALE},
+    vector_field::{Glyphs, GlyphsHandle, Streamlines, StreamlinesHandle, VectorFields},
+    vegetation::{Vegetation, VegetationHandle, Vegetations},
+    vertex_pulling::{self, VertexPulling},
+    view_cube::{ViewCube, ViewCubePass},
+    viewport::{SurfaceOptions, Viewport},
+    voxel_grid::{VoxelGrid, VoxelGridHandle, VoxelGrids},
+    water::{Water, WaterHandle, Waters},
+    wgpu_utils::{
+        debug_scope::GpuDebugScope,
+        frame_commands::{FrameCommands, SubmitStage},
+        readback::{readback_future, ReadbackFuture},
+        resource_cache::SamplerCache,
+        uploader::Uploader,
+    },
+    xr::{EyeView, StereoTargets},
 };
 
+type SelectionCallback = Box<dyn FnMut(&SelectionChange)>;
+
+/// Owns the device and queue plus everything shared between windows (pipelines, meshes), and one [Viewport] per
+/// window it draws into.
 pub struct RenderEngine {
+    instance: Instance,
+    adapter: Adapter,
     device: Device,
-    config: SurfaceConfiguration,
-    format: TextureFormat,
-    surface: Surface<'static>,
     queue: Queue,
+    device_report: DeviceReport,
+    device_lost: DeviceLostFlag,
+    /// Kept to request an equivalent device after the current one was lost
+    device_settings: RenderEngineBuilder,
+    format: TextureFormat,
     pipeline: RenderPipeline,
-    depth_texture: texture::Texture,
+    /// Only used for its layout when building pipelines, each viewport binds its own global bind group
+    global_bindings: GlobalBindings,
+    object_bindings: ObjectBindings,
+    material_bindings: MaterialBindings,
+    /// Shared by every texture, window and render target
+    samplers: SamplerCache,
+    background: BackgroundPass,
+    occlusion_pass: OcclusionPass,
+    /// Built in and added colormaps, shared by the glyphs, streamlines, slices, heatmaps and particles
+    colormaps: ColormapTextures,
+    /// None where the device can't build depth pyramids
+    depth_pyramid_pass: Option<DepthPyramidPass>,
+    /// Binds the mesh vertices for the pipeline fetching them itself, None when it reads vertex buffers
+    vertex_pulling: Option<VertexPulling>,
+    /// None where the device can't cull meshlets, meshes are drawn whole then
+    #[cfg(feature = "meshlets")]
+    meshlet_culling: Option<MeshletCulling>,
+    /// Carries the uploads of [RenderEngine::prepare] into the first submission of a frame
+    uploader: Uploader,
+    /// Shared with plugins, kept across device losses
+    jobs: Arc<JobSystem>,
+    /// Everything recorded for the frame until its first window is drawn, see [RenderEngine::submit_commands]
+    commands: FrameCommands,
 
-    vertex_buffer: Buffer,
-    index_buffer: Buffer,
+    meshes: MeshPool,
+    textures: Vec<GpuTexture>,
+    flipbooks: Vec<GpuFlipbook>,
+    materials: Vec<Material>,
+    /// When each material's flipbook playback started, on [RenderEngine::flipbook_clock]
+    playback_started: Vec<Duration>,
+    /// Time updated so far, flipbooks play by it
+    flipbook_clock: Duration,
+    #[cfg(feature = "ffmpeg")]
+    videos: Vec<GpuVideo>,
+    /// None where a player was removed
+    animation_players: Vec<Option<AnimatedModel>>,
+    /// Where the players moved the renderables of each animated mesh, in the mesh's space
+    animated_meshes: HashMap<MeshHandle, Matrix4<f32>>,
+    /// What gets drawn into every viewport, handed over by the app each frame
+    renderables: Vec<Renderable>,
+    point_clouds: PointClouds,
+    voxel_grids: VoxelGrids,
+    vector_fields: VectorFields,
+    isosurfaces: Isosurfaces,
+    simulations: Simulations,
+    cloths: Cloths,
+    flocks: Flocks,
+    waters: Waters,
+    terrains: Terrains,
+    vegetations: Vegetations,
+    reflection_probes: ReflectionProbes,
+    sky: Sky,
+    irradiance: Irradiance,
+    render_targets: Vec<RenderTarget>,
 
-    pub camera: OrbitCamera,
-    pub camera_controller: CameraController,
-    global_ubo: GlobalUBO,
-    global_bindings: GlobalBindings,
+    viewports: HashMap<WindowId, Viewport>,
+
+    plugins: Vec<Box<dyn EnginePlugin>>,
+    /// Drawn over the window events are forwarded from, see [RenderEngine::egui]
+    #[cfg(feature = "egui")]
+    egui: EguiPass,
+    /// Shows the engine's own window of frame timings in the UI
+    #[cfg(feature = "egui")]
+    egui_debug_window: bool,
+
+    /// Extracted by the last update, drawn by every render_frame until the next one
+    frame: FrameContext,
+    last_update: Instant,
+    frame_limiter: FrameLimiter,
+    delta_smoother: DeltaSmoother,
+    /// Only with GPU profiling enabled and supported
+    profiler: Option<GpuProfiler>,
+    /// Hidden until toggled, see [RenderEngine::toggle_debug_hud]
+    hud: DebugHud,
+    gizmo: Gizmo,
+    input: Input,
+    overlay: OverlayPass,
+    selection: Selection,
+    measure_tool: MeasureTool,
+    probe_tool: ProbeTool,
+    annotations: Vec<Annotation>,
+    clip_planes: Vec<ClipPlane>,
+    /// Fills in cuts of the clip planes, see [RenderEngine::set_clip_caps]
+    clip_cap_color: Option<[f32; 4]>,
+    /// Called with every change of the selection, see [RenderEngine::on_selection_changed]
+    selection_callbacks: Vec<SelectionCallback>,
+    events: EventBus,
+    /// Whether the standard view hotkeys turn the camera over a moment or snap it there
+    animate_standard_views: bool,
+    outline: OutlinePass,
+    upscaling_pass: UpscalingPass,
+    view_cube: ViewCube,
+    console: Console,
+    /// The `[frame]` section of the last script loaded, run at the start of every update
+    frame_script: Option<FrameScript>,
+    #[cfg(feature = "rhai")]
+    rhai_script: Option<RhaiScript>,
+    #[cfg(feature = "sync")]
+    scene_sync: Option<SceneSync>,
+    audio: AudioAnalyzer,
+    /// Drawn in orthographic views, see [RenderEngine::set_rulers_visible]
+    rulers_visible: bool,
+    /// See [RenderEngine::set_build_depth_pyramids]
+    build_depth_pyramids: bool,
+    /// See [RenderEngine::set_render_scale]
+    render_scale: f32,
+    /// See [RenderEngine::set_upscaling_sharpness]
+    upscaling_sharpness: f32,
+    /// See [RenderEngine::set_dynamic_resolution]
+    dynamic_resolution: Option<DynamicResolution>,
+    resolution_controller: ResolutionController,
+    view_cube_pass: ViewCubePass,
+    plots: Plots,
+    heatmaps: Heatmaps,
+    frame_stats: FrameStats,
+    /// Time spent in render_frame since the last update
+    render_time: Duration,
+    /// Of the last complete frame
+    stats: RenderStats,
+    /// Counted since the last update
+    render_stats: RenderStats,
+
+    /// None if the platform's file watching couldn't be set up
+    #[cfg(feature = "hot-reload")]
+    asset_watcher: Option<AssetWatcher>,
+    /// By canonical path, loaded with [RenderEngine::load_texture] and [RenderEngine::load_model]
+    #[cfg(feature = "hot-reload")]
+    watched_assets: HashMap<PathBuf, WatchedAsset>,
 }
 
 impl RenderEngine {
+    /// Creates the engine with `window` as its first viewport and default adapter selection. More windows can be
+    /// added with [RenderEngine::add_window], all of them are configured with `surface_options`.
+    ///
+    /// Use [RenderEngineBuilder] to control which adapter, features and limits are used.
     pub async fn new(
-        window: impl Into<wgpu::SurfaceTarget<'static>>,
-        width: u32,
-        height: u32,
+        window: Arc<impl SurfaceProvider>,
+        surface_options: SurfaceOptions,
     ) -> RenderEngine {
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            ..Default::default()
-        });
-        let surface = instance.create_surface(window).unwrap();
-
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
+        RenderEngineBuilder::new()
+            .surface_options(surface_options)
+            .build(window)
             .await
-            .expect("Failed to request adapter!");
-
-        let (device, queue) = {
-            adapter
-                .request_device(
-                    &wgpu::DeviceDescriptor {
-                        label: Some("WGPU Device"),
-                        required_features: wgpu::Features::default(),
-                        required_limits: wgpu::Limits {
-                            max_texture_dimension_2d: 4096, // Allow higher resolutions on native
-                            ..wgpu::Limits::downlevel_defaults()
-                        },
-                        memory_hints: wgpu::MemoryHints::default(),
-                    },
-                    None,
-                )
-                .await
-                .expect("Failed to request a device!")
+    }
+
+    /// Creates the engine drawing into a window another library or the host application owns, `size` being its size
+    /// in physical pixels. It's the only one in [RenderEngine::window_ids], its size has to be passed on to
+    /// [RenderEngine::resize] and frames drawn with [RenderEngine::update] and [RenderEngine::render_frame] whenever
+    /// the host wants them.
+    ///
+    /// Use [RenderEngineBuilder::build] with a [RawWindow] for the other settings.
+    ///
+    /// # Safety
+    ///
+    /// The handles have to be valid for as long as the engine lives, see [RawWindow::new].
+    pub async unsafe fn from_raw_handles(
+        display: RawDisplayHandle,
+        window: RawWindowHandle,
+        size: [u32; 2],
+    ) -> RenderEngine {
+        let window = RawWindow::new(display, window, size);
+        RenderEngineBuilder::new().build(Arc::new(window)).await
+    }
+
+    /// Sets up everything on top of a device obtained by the [RenderEngineBuilder]
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn from_device(
+        instance: Instance,
+        adapter: Adapter,
+        device: Device,
+        queue: Queue,
+        format: TextureFormat,
+        device_settings: RenderEngineBuilder,
+        device_report: DeviceReport,
+    ) -> RenderEngine {
+        let _span = tracing::info_span!("init").entered();
+        tracing::info!("Created device:\n{device_report}");
+        let device_lost = DeviceLostFlag::watch(&device);
+
+        let global_bindings = GlobalBindings::new(&device);
+        let object_bindings = ObjectBindings::new(&device);
+        let samplers = SamplerCache::new();
+        let material_bindings = MaterialBindings::new(&device, &queue, &samplers, format);
+
+        // The cube and a plain white material are always there, see [RenderEngine::cube_mesh]
+        let use_vertex_pulling = device_settings.use_vertex_pulling(&device_report);
+        let mut meshes = MeshPool::new(&device, use_vertex_pulling);
+        meshes.insert(&device, &queue, MeshData::cube());
+        let materials = vec![Material::default()];
+        let vertex_pulling = use_vertex_pulling.then(|| VertexPulling::new(&device, &meshes));
+
+        let pipeline = create_pipeline(
+            &device,
+            &load_shader_source(),
+            format,
+            &global_bindings,
+            &object_bindings,
+            &material_bindings,
+            vertex_pulling.as_ref(),
+        );
+        let background = BackgroundPass::new(&device, format, &global_bindings, &material_bindings);
+        let occlusion_pass =
+            OcclusionPass::new(&device, format, &global_bindings, &object_bindings);
+
+        #[cfg(feature = "hot-reload")]
+        let asset_watcher = match AssetWatcher::new() {
+            Ok(mut watcher) => {
+                if let Err(err) = watcher.watch(SHADER_PATH) {
+                    tracing::warn!("Not watching {SHADER_PATH}: {err}");
+                }
+                Some(watcher)
+            }
+            Err(err) => {
+                tracing::warn!("Failed to create asset watcher, assets won't be reloaded: {err}");
+                None
+            }
         };
 
-        let surface_capabilities = surface.get_capabilities(&adapter);
-        let format = surface_capabilities
-            .formats
-            .iter()
-            .copied()
-            .find(|f| !f.is_srgb())
-            .unwrap_or(surface_capabilities.formats[0]);
+        #[cfg(feature = "meshlets")]
+        let meshlet_culling = MeshletCulling::new(&device, &device_report);
+        let hud = DebugHud::new(&device, format);
+        let overlay = OverlayPass::new(&device, format, &global_bindings);
+        let outline = OutlinePass::new(&device, format);
+        let upscaling_pass = UpscalingPass::new(&device, format);
+        let view_cube_pass = ViewCubePass::new(&device, format);
+        let colormaps = ColormapTextures::new(&device);
+        let depth_pyramid_pass = DepthPyramidPass::new(&device, &device_report);
 
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: format,
-            width,
-            height,
-            present_mode: surface_capabilities.present_modes[0],
-            alpha_mode: surface_capabilities.alpha_modes[0],
-            view_formats: vec![],
-            desired_maximum_frame_latency: 2,
+        let frame_limiter = FrameLimiter::new(device_settings.fps_cap);
+        let jobs = Arc::new(JobSystem::new(
+            device_settings
+                .job_threads
+                .unwrap_or_else(JobSystem::default_threads),
+        ));
+        let profiler = device_settings
+            .gpu_profiling
+            .then(|| GpuProfiler::new(&device, &queue))
+            .flatten();
+        #[cfg(feature = "egui")]
+        let egui = {
+            let mut egui = EguiPass::new();
+            egui.init(&PluginContext {
+                device: &device,
+                queue: &queue,
+                format,
+                global_bind_group_layout: global_bindings.bind_group_layouts(),
+                device_report: &device_report,
+                jobs: &jobs,
+                render_targets: &[],
+            });
+            egui
         };
-        surface.configure(&device, &config);
-        let depth_texture =
-            texture::Texture::create_depth_texture(&device, &config, "depth_texture");
 
-        let mut camera = OrbitCamera::new(
-            1.0,
-            0.0,
-            0.0,
-            Vector3::new(0.0, 0.0, 0.0),
-            width as f32 / height as f32,
+        RenderEngine {
+            instance,
+            adapter,
+            device,
+            queue,
+            device_report,
+            device_lost,
+            device_settings,
+            format,
+            pipeline,
+            global_bindings,
+            object_bindings,
+            material_bindings,
+            samplers,
+            background,
+            occlusion_pass,
+            colormaps,
+            depth_pyramid_pass,
+            vertex_pulling,
+            #[cfg(feature = "meshlets")]
+            meshlet_culling,
+            uploader: Uploader::new(),
+            jobs,
+            commands: FrameCommands::new(),
+
+            meshes,
+            textures: Vec::new(),
+            flipbooks: Vec::new(),
+            playback_started: vec![Duration::ZERO; materials.len()],
+            materials,
+            flipbook_clock: Duration::ZERO,
+            #[cfg(feature = "ffmpeg")]
+            videos: Vec::new(),
+            animation_players: Vec::new(),
+            animated_meshes: HashMap::new(),
+            renderables: Vec::new(),
+            point_clouds: PointClouds::default(),
+            voxel_grids: VoxelGrids::default(),
+            vector_fields: VectorFields::default(),
+            isosurfaces: Isosurfaces::default(),
+            simulations: Simulations::default(),
+            cloths: Cloths::default(),
+            flocks: Flocks::default(),
+            waters: Waters::default(),
+            terrains: Terrains::default(),
+            vegetations: Vegetations::default(),
+            reflection_probes: ReflectionProbes::default(),
+            sky: Sky::default(),
+            irradiance: Irradiance::default(),
+            render_targets: Vec::new(),
+
+            viewports: HashMap::new(),
+
+            plugins: Vec::new(),
+            #[cfg(feature = "egui")]
+            egui,
+            #[cfg(feature = "egui")]
+            egui_debug_window: false,
+
+            frame: FrameContext::default(),
+            last_update: Instant::now(),
+            frame_limiter,
+            delta_smoother: DeltaSmoother::default(),
+            profiler,
+            hud,
+            gizmo: Gizmo::new(),
+            input: default_input(),
+            overlay,
+            selection: Selection::new(),
+            measure_tool: MeasureTool::new(),
+            probe_tool: ProbeTool::new(),
+            annotations: Vec::new(),
+            clip_planes: Vec::new(),
+            clip_cap_color: None,
+            selection_callbacks: Vec::new(),
+            events: EventBus::new(),
+            animate_standard_views: true,
+            outline,
+            upscaling_pass,
+            view_cube: ViewCube::new(),
+            console: Console::new(),
+            frame_script: None,
+            #[cfg(feature = "rhai")]
+            rhai_script: None,
+            #[cfg(feature = "sync")]
+            scene_sync: None,
+            audio: AudioAnalyzer::new(),
+            rulers_visible: true,
+            build_depth_pyramids: false,
+            render_scale: 1.0,
+            upscaling_sharpness: 0.5,
+            dynamic_resolution: None,
+            resolution_controller: ResolutionController::default(),
+            view_cube_pass,
+            plots: Plots::default(),
+            heatmaps: Heatmaps::default(),
+            frame_stats: FrameStats::default(),
+            render_time: Duration::ZERO,
+            stats: RenderStats::default(),
+            render_stats: RenderStats::default(),
+
+            #[cfg(feature = "hot-reload")]
+            asset_watcher,
+            #[cfg(feature = "hot-reload")]
+            watched_assets: HashMap::new(),
+        }
+    }
+
+    /// Creates a surface for another window and starts drawing into it with its own camera
+    pub fn add_window(&mut self, window: Arc<impl SurfaceProvider>) {
+        let window: Arc<dyn SurfaceProvider> = window;
+        let surface = self
+            .instance
+            .create_surface(window.clone())
+            .expect("Failed to create surface!");
+        assert!(
+            self.adapter.is_surface_supported(&surface),
+            "The engine's adapter can't present to this window!"
         );
-        camera.bounds.min_distance = Some(1.1);
-        let camera_controller = CameraController::new(0.005, 0.1);
+        self.insert_viewport(surface, window);
+    }
+
+    /// Starts drawing into `window` through `surface`, which has to be compatible with the adapter
+    pub(crate) fn insert_viewport(
+        &mut self,
+        surface: Surface<'static>,
+        window: Arc<dyn SurfaceProvider>,
+    ) {
+        let mut viewport = Viewport::new(
+            &self.device,
+            &self.samplers,
+            &self.adapter,
+            surface,
+            window.clone(),
+            self.format,
+            &self.device_settings.surface_options,
+        );
+        viewport.set_transparent(&self.device, self.background.background().is_transparent());
+        viewport.set_render_scale(&self.device, &self.samplers, self.render_scale);
+        self.device_settings
+            .camera_defaults
+            .apply(&mut viewport.camera, &mut viewport.camera_controller);
+        self.viewports.insert(window.id(), viewport);
+        // Not every platform reports the focus new windows get
+        self.input.focus(window.id());
+        window.request_redraw();
+        self.events.publish(EngineEvent::WindowAdded(window.id()));
+    }
 
-        let global_ubo = GlobalUBO::new(&device);
-        let mut global_bindings = GlobalBindings::new(&device);
-        global_bindings.create_bind_group(&device, &global_ubo);
+    /// Stops drawing into the window and drops its surface. Any recording running in it is finished first.
+    pub fn remove_window(&mut self, window_id: WindowId) {
+        self.hud.remove_window(window_id);
+        self.overlay.remove_window(window_id);
+        self.input.remove_window(window_id);
+        self.selection.remove_window(window_id);
+        self.measure_tool.remove_window(window_id);
+        self.view_cube.remove_window(window_id);
+        self.view_cube_pass.remove_window(window_id);
+        self.plots.remove_window(window_id);
+        self.heatmaps.remove_window(window_id);
+        #[cfg(feature = "egui")]
+        self.egui.forget_window(window_id);
+        if let Some(mut viewport) = self.viewports.remove(&window_id) {
+            if let Some(recorder) = viewport.recorder.take() {
+                recorder.finish(&self.device);
+            }
+            self.events.publish(EngineEvent::WindowRemoved(window_id));
+        }
+    }
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
+    /// Drops every window's surface, call it from `ApplicationHandler::suspended`. On Android the native windows
+    /// are destroyed while the app is in the background, the device, meshes and render targets stay alive.
+    pub fn suspend(&mut self) {
+        for viewport in self.viewports.values_mut() {
+            viewport.release_surface();
+        }
+    }
+
+    /// Creates the surfaces dropped by [RenderEngine::suspend] again and redraws, call it from
+    /// `ApplicationHandler::resumed`
+    pub fn resume(&mut self) {
+        for viewport in self.viewports.values_mut() {
+            viewport.restore_surface(&self.instance, &self.device);
+        }
+        self.request_redraw();
+    }
+
+    /// The adapter, features and limits the engine is running with
+    pub fn device_report(&self) -> &DeviceReport {
+        &self.device_report
+    }
+
+    /// For hosts drawing with the engine's device, e.g. to show an [ExternalSurface]. A new one replaces it after the
+    /// device was lost, see [crate::events::EngineEvent::DeviceLost].
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &Queue {
+        &self.queue
+    }
+
+    /// Submits what the last update recorded without waiting for a window to be drawn, so hosts sampling render
+    /// targets on the engine's device see this frame
+    pub fn flush(&mut self) {
+        self.submit_commands();
+    }
+
+    /// The windows drawn into, in no particular order
+    pub fn window_ids(&self) -> impl Iterator<Item = WindowId> + '_ {
+        self.viewports.keys().copied()
+    }
+
+    pub fn viewport(&self, window_id: WindowId) -> Option<&Viewport> {
+        self.viewports.get(&window_id)
+    }
+
+    pub fn viewport_mut(&mut self, window_id: WindowId) -> Option<&mut Viewport> {
+        self.viewports.get_mut(&window_id)
+    }
+
+    /// The UI drawn over the window events are forwarded from with [RenderEngine::process_egui_event], see
+    /// [crate::egui_pass]. What's built with it until that window's next [RenderEngine::render_frame] is drawn there.
+    #[cfg(feature = "egui")]
+    pub fn egui(&mut self) -> &egui::Context {
+        self.egui.context()
+    }
+
+    /// Hands a window event to egui, true if egui keeps it for itself. Shells call this before
+    /// [RenderEngine::process_window_event] and leave out the events egui kept.
+    #[cfg(feature = "egui")]
+    pub fn process_egui_event(
+        &mut self,
+        window: &Arc<winit::window::Window>,
+        event: &WindowEvent,
+    ) -> bool {
+        let consumed = self.egui.process_window_event(window, event);
+        if self.egui.needs_redraw() {
+            self.request_redraw();
+        }
+        consumed
+    }
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[global_bindings.bind_group_layouts()],
-            push_constant_ranges: &[],
+    /// Shows the engine's own window of frame timings and counters in the UI of [RenderEngine::egui]
+    #[cfg(feature = "egui")]
+    pub fn set_egui_debug_window(&mut self, visible: bool) {
+        self.egui_debug_window = visible;
+        self.request_redraw();
+    }
+
+    #[cfg(feature = "egui")]
+    pub fn egui_debug_window(&self) -> bool {
+        self.egui_debug_window
+    }
+
+    #[cfg(feature = "egui")]
+    fn show_egui_debug_window(&mut self) {
+        let stats = self.stats;
+        let cpu_time = self.frame_stats.cpu_frame_time;
+        let gpu_time =
+            (!self.frame_stats.gpu_passes.is_empty()).then(|| self.frame_stats.gpu_time());
+        let render_scale = self.render_scale;
+        let milliseconds = |time: Duration| format!("{:.2} ms", time.as_secs_f64() * 1000.0);
+        egui::Window::new("Engine").show(self.egui.context(), |ui| {
+            egui::Grid::new("engine_stats").show(ui, |ui| {
+                ui.label("CPU frame");
+                ui.label(milliseconds(cpu_time));
+                ui.end_row();
+                if let Some(gpu_time) = gpu_time {
+                    ui.label("GPU frame");
+                    ui.label(milliseconds(gpu_time));
+                    ui.end_row();
+                }
+                ui.label("Draw calls");
+                ui.label(stats.draw_calls.to_string());
+                ui.end_row();
+                ui.label("Triangles");
+                ui.label(stats.triangles.to_string());
+                ui.end_row();
+                ui.label("Render scale");
+                ui.label(format!("{:.0}%", render_scale * 100.0));
+                ui.end_row();
+            });
         });
+    }
 
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: Some("vs_main"),
-                buffers: &[Vertex::desc()],
-                compilation_options: Default::default(),
-            },
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleStrip,
-                strip_index_format: Some(wgpu::IndexFormat::Uint16),
-                front_face: wgpu::FrontFace::Cw,
-                cull_mode: None,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-                unclipped_depth: false,
-            },
-            depth_stencil: Some(DepthStencilState {
-                format: depth_texture.texture.format(),
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::Less,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-                compilation_options: Default::default(),
-            }),
-            multiview: None,
-            cache: None,
+    /// Initializes the plugin and hooks it into every following frame, after the plugins registered before it
+    pub fn add_plugin(&mut self, mut plugin: impl EnginePlugin + 'static) {
+        plugin.init(&PluginContext {
+            device: &self.device,
+            queue: &self.queue,
+            format: self.format,
+            global_bind_group_layout: self.global_bindings.bind_group_layouts(),
+            device_report: &self.device_report,
+            jobs: &self.jobs,
+            render_targets: &self.render_targets,
         });
+        self.plugins.push(Box::new(plugin));
+        self.request_redraw();
+    }
 
-        let vertex_buffer = wgpu::util::DeviceExt::create_buffer_init(
-            &device,
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Vertex Buffer"),
-                contents: bytemuck::cast_slice(VERTICES),
-                usage: wgpu::BufferUsages::VERTEX,
-            },
+    /// Uploads a mesh so renderables can refer to it
+    pub fn add_mesh(&mut self, data: MeshData) -> MeshHandle {
+        let mesh = MeshHandle(self.meshes.insert(&self.device, &self.queue, data));
+        self.events.publish(EngineEvent::MeshAdded(mesh));
+        mesh
+    }
+
+    /// Frees a mesh's GPU memory for the meshes added after it. Renderables still using the handle are skipped, until
+    /// [RenderEngine::add_mesh] hands out the same handle again.
+    pub fn remove_mesh(&mut self, mesh: MeshHandle) -> Option<MeshData> {
+        self.meshes.remove(mesh.0)
+    }
+
+    /// Uploads an image of tightly packed 8 bit RGBA pixels, e.g. a panorama for [Background::Skybox] or a
+    /// [Material::image]
+    pub fn add_texture(&mut self, width: u32, height: u32, rgba: Vec<u8>) -> TextureHandle {
+        let data = TextureData {
+            width,
+            height,
+            rgba,
+        };
+        self.textures.push(GpuTexture::new(
+            &self.device,
+            &self.queue,
+            &self.samplers,
+            data,
+            &self.material_bindings,
+        ));
+        let texture = TextureHandle(self.textures.len() - 1);
+        self.events.publish(EngineEvent::TextureAdded(texture));
+        texture
+    }
+
+    /// Reads a PNG or Radiance HDR image and uploads it like [RenderEngine::add_texture]. With the `hot-reload`
+    /// feature the file is watched and a changed image is uploaded into the same handle.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_texture(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> std::io::Result<TextureHandle> {
+        let path = path.as_ref();
+        let data = crate::assets::read_image(path)?;
+        let texture = self.add_texture(data.width, data.height, data.rgba);
+        #[cfg(feature = "hot-reload")]
+        self.watch_asset(path, WatchedAsset::Texture(texture));
+        Ok(texture)
+    }
+
+    /// Reads a model like [crate::assets::read_model] and uploads its parts, returning what to hand to
+    /// [RenderEngine::set_renderables]. With the `hot-reload` feature the file is watched. Parts of a changed model
+    /// are uploaded into the same mesh and material handles, and in [RenderEngine::renderables] parts that were
+    /// added, removed or moved are updated, placed like the model's first part. Read them back on
+    /// [EngineEvent::AssetReloaded] if the app keeps its own list.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_model(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> std::io::Result<Vec<Renderable>> {
+        let path = path.as_ref();
+        let mut model = crate::assets::read_model(path)?;
+        model.upload_textures(self);
+        // Each part gets a material of its own, so a reload can change it without touching other models
+        let renderables: Vec<_> = model
+            .parts
+            .into_iter()
+            .map(|part| Renderable {
+                mesh: self.add_mesh(part.mesh),
+                material: self.add_material(part.material),
+                model: part.transform,
+                occlusion_query: false,
+            })
+            .collect();
+        #[cfg(feature = "hot-reload")]
+        self.watch_asset(
+            path,
+            WatchedAsset::Model(
+                renderables
+                    .iter()
+                    .map(|renderable| (renderable.mesh, renderable.material, renderable.model))
+                    .collect(),
+            ),
         );
+        Ok(renderables)
+    }
 
-        let index_buffer = wgpu::util::DeviceExt::create_buffer_init(
-            &device,
-            &wgpu::util::BufferInitDescriptor {
-                label: Some("Index Buffer"),
-                contents: bytemuck::cast_slice(INDICES),
-                usage: wgpu::BufferUsages::INDEX,
-            },
+    pub fn background(&self) -> Background {
+        self.background.background()
+    }
+
+    /// Changes what every window and render target shows behind the scene. Switching to or from a transparent
+    /// background reconfigures the window surfaces for compositing, the windows themselves have to be created
+    /// transparent.
+    pub fn set_background(&mut self, background: Background) {
+        if background == self.background.background() {
+            return;
+        }
+        for viewport in self.viewports.values_mut() {
+            viewport.set_transparent(&self.device, background.is_transparent());
+        }
+        self.background.set_background(&self.queue, background);
+        self.request_redraw();
+    }
+
+    pub fn atmosphere(&self) -> Option<&Atmosphere> {
+        self.sky.atmosphere()
+    }
+
+    /// Draws the sky of the atmosphere over the background and hazes the scene by its distance in every window and
+    /// render target, see [crate::atmosphere]. Water surfaces reflect its sun. None goes back to the background.
+    /// Devices without compute shaders, e.g. WebGL2, don't draw it.
+    pub fn set_atmosphere(&mut self, atmosphere: Option<Atmosphere>) {
+        self.sky
+            .set(&self.device, self.format, &self.device_report, atmosphere);
+        self.request_redraw();
+    }
+
+    pub fn irradiance_volume(&self) -> Option<&IrradianceVolume> {
+        self.irradiance.volume()
+    }
+
+    /// Bakes the probes of the volume from the scene over the next frames, then lights the materials with
+    /// [Material::lit_by_probes] by them, see [crate::irradiance_volume]. Moving the volume or changing its grid bakes
+    /// it again, None turns the light off. Devices without compute shaders, e.g. WebGL2, don't bake it.
+    pub fn set_irradiance_volume(&mut self, volume: Option<IrradianceVolume>) {
+        self.irradiance.set(
+            &self.device,
+            self.format,
+            &self.samplers,
+            &self.global_bindings,
+            self.material_bindings.irradiance(),
+            &self.device_report,
+            volume,
         );
+        self.request_redraw();
+    }
 
-        RenderEngine {
-            device,
-            config,
-            format,
-            surface,
-            queue,
-            pipeline,
-            depth_texture,
+    /// Bakes every probe of the irradiance volume again from the scene as it is now, e.g. after it changed. Objects
+    /// are lit by the old probes until the new ones are done.
+    pub fn bake_irradiance_volume(&mut self) {
+        self.irradiance.bake();
+        self.request_redraw();
+    }
 
-            vertex_buffer,
-            index_buffer,
-            camera,
-            camera_controller,
+    /// Whether every window and render target builds a pyramid of its depth buffer after its main pass, see
+    /// [crate::depth_pyramid]. Off by default.
+    pub fn builds_depth_pyramids(&self) -> bool {
+        self.build_depth_pyramids
+    }
 
-            global_ubo,
-            global_bindings,
+    /// Starts or stops building the depth pyramids, culling vegetation hidden behind what was drawn the frame before
+    /// and handing the pyramids to plugins. Devices without compute shaders, e.g. WebGL2, don't build them.
+    pub fn set_build_depth_pyramids(&mut self, build: bool) {
+        if build && self.depth_pyramid_pass.is_none() {
+            tracing::warn!("Depth pyramids need compute shaders, none are built");
         }
+        self.build_depth_pyramids = build;
+        self.request_redraw();
     }
 
-    pub fn render_frame(&self) {
-        let surface_texture = self
-            .surface
-            .get_current_texture()
-            .expect("Failed to get surface texture!");
+    /// The fraction of their resolution windows draw the scene at, from [MIN_RENDER_SCALE] to 1 for full resolution,
+    /// see [crate::upscaling]
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
 
-        let surface_texture_view =
-            surface_texture
-                .texture
-                .create_view(&wgpu::TextureViewDescriptor {
-                    label: wgpu::Label::default(),
-                    aspect: wgpu::TextureAspect::default(),
-                    format: Some(self.format),
-                    dimension: None,
-                    base_mip_level: 0,
-                    mip_level_count: None,
-                    base_array_layer: 0,
-                    array_layer_count: None,
-                });
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render Encoder"),
-            });
+    /// Draws the scene of every window at `scale` of its resolution and scales it up to fill the window, trading
+    /// sharpness for fill rate. Overlays and the HUD are drawn at full resolution either way. Render targets are
+    /// always drawn at their own size. With [RenderEngine::set_dynamic_resolution] the scale is steered from there.
+    pub fn set_render_scale(&mut self, scale: f32) {
+        let scale = scale.clamp(MIN_RENDER_SCALE, 1.0);
+        if scale == self.render_scale {
+            return;
+        }
+        self.render_scale = scale;
+        // Timings of the frames drawn at the old scale don't say anything about the new one
+        self.resolution_controller.reset(self.frame.frame_index + 1);
+        for viewport in self.viewports.values_mut() {
+            viewport.set_render_scale(&self.device, &self.samplers, scale);
+        }
+        self.request_redraw();
+    }
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &surface_texture_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    //attach depth texture to stencil attatchement of render pass
-                    view: &self.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
+    /// How much the scene is sharpened after it was scaled up, from 0 for not at all to 1. 0.5 by default.
+    pub fn upscaling_sharpness(&self) -> f32 {
+        self.upscaling_sharpness
+    }
 
-            render_pass.set_bind_group(0, self.global_bindings.bind_groups(), &[]);
+    pub fn set_upscaling_sharpness(&mut self, sharpness: f32) {
+        self.upscaling_sharpness = sharpness.clamp(0.0, 1.0);
+        self.request_redraw();
+    }
+
+    /// What the render scale is steered by, None while it's left where it is
+    pub fn dynamic_resolution(&self) -> Option<&DynamicResolution> {
+        self.dynamic_resolution.as_ref()
+    }
+
+    /// Lowers the render scale while the GPU takes longer than the target frame time and raises it again once it's
+    /// well below, within the bounds, see [crate::dynamic_resolution]. None keeps the scale where it got to. Needs
+    /// [crate::render_engine_builder::RenderEngineBuilder::gpu_profiling] for the timings.
+    pub fn set_dynamic_resolution(&mut self, dynamic_resolution: Option<DynamicResolution>) {
+        if dynamic_resolution.is_some() && self.profiler.is_none() {
+            tracing::warn!(
+                "Dynamic resolution needs GPU profiling, the render scale stays as it is"
+            );
+        }
+        self.dynamic_resolution = dynamic_resolution;
+        self.resolution_controller.reset(self.frame.frame_index + 1);
+    }
 
-            render_pass.set_pipeline(&self.pipeline);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            render_pass.draw_indexed(0..36, 0, 0..1);
+    /// Cuts away everything on the far side of any of the planes, in every window and render target. At most
+    /// [MAX_CLIP_PLANES] are used, see [crate::clipping].
+    pub fn set_clip_planes(&mut self, planes: impl IntoIterator<Item = ClipPlane>) {
+        self.clip_planes = planes.into_iter().collect();
+        if self.clip_planes.len() > MAX_CLIP_PLANES {
+            tracing::warn!(
+                "Only {MAX_CLIP_PLANES} clip planes are supported, ignoring the other {}",
+                self.clip_planes.len() - MAX_CLIP_PLANES
+            );
+            self.clip_planes.truncate(MAX_CLIP_PLANES);
         }
+        self.request_redraw();
+    }
 
-        self.queue.submit(iter::once(encoder.finish()));
-        surface_texture.present();
+    pub fn clip_planes(&self) -> &[ClipPlane] {
+        &self.clip_planes
     }
 
-    pub fn process_event(&mut self, event: &DeviceEvent, window: &Window) {
-        self.camera_controller
-            .process_events(event, window, &mut self.camera);
+    /// Fills in where the clip planes cut meshes open with a flat `color`, or leaves the cuts open with None, the
+    /// default. Only closed meshes get a solid looking cap.
+    pub fn set_clip_caps(&mut self, color: Option<[f32; 4]>) {
+        self.clip_cap_color = color;
+        self.request_redraw();
     }
-    pub fn update(&mut self) {
-        self.camera.update_view_proj();
-        update_global_ubo(&mut self.global_ubo, &self.queue, self.camera.uniform);
+
+    pub fn clip_caps(&self) -> Option<[f32; 4]> {
+        self.clip_cap_color
+    }
+
+    pub fn add_material(&mut self, material: Material) -> MaterialHandle {
+        self.materials.push(material);
+        self.playback_started.push(self.flipbook_clock);
+        MaterialHandle(self.materials.len() - 1)
+    }
+
+    /// Changes a material for every renderable using it. A different [Material::flipbook] playback starts over
+    /// from its first frame.
+    pub fn set_material(&mut self, handle: MaterialHandle, material: Material) {
+        if let Some(slot) = self.materials.get_mut(handle.0) {
+            if slot.flipbook != material.flipbook {
+                self.playback_started[handle.0] = self.flipbook_clock;
+            }
+            if *slot != material {
+                *slot = material;
+                self.request_redraw();
+            }
+        }
+    }
+
+    /// Uploads a sequence of images of the same size for materials to play with [Material::flipbook]. Array storage
+    /// falls back to streaming frames where array textures can't hold them all, or on WebGL and OpenGL, which can't
+    /// sample single layers of them.
+    pub fn add_flipbook(
+        &mut self,
+        frames: Vec<TextureData>,
+        storage: FlipbookStorage,
+    ) -> FlipbookHandle {
+        let max_layers = self.device_report.limits.max_texture_array_layers as usize;
+        let storage = match storage {
+            FlipbookStorage::Array if frames.len() > max_layers => {
+                tracing::warn!(
+                    "Flipbook has {} frames, more than the {max_layers} array layers supported, streaming them instead",
+                    frames.len()
+                );
+                FlipbookStorage::Streamed
+            }
+            FlipbookStorage::Array
+                if self.device_report.adapter_info.backend == wgpu::Backend::Gl =>
+            {
+                FlipbookStorage::Streamed
+            }
+            storage => storage,
+        };
+        self.flipbooks.push(GpuFlipbook::new(
+            &self.device,
+            &self.queue,
+            &self.samplers,
+            frames,
+            storage,
+            &self.material_bindings,
+        ));
+        FlipbookHandle(self.flipbooks.len() - 1)
+    }
+
+    /// The flipbook a material plays, its number of frames and the seconds since the playback started
+    fn flipbook_playback(&self, material: usize) -> Option<(FlipbookPlayback, usize, f32)> {
+        let playback = self.materials.get(material)?.flipbook?;
+        let frame_count = self.flipbooks.get(playback.flipbook.0)?.frames.len();
+        let elapsed = self.flipbook_clock - self.playback_started[material];
+        Some((playback, frame_count, elapsed.as_secs_f32()))
+    }
+
+    /// The flipbook a material plays and the frame it's at, None without one
+    fn flipbook_frame(&self, material: usize) -> Option<(usize, usize)> {
+        let (playback, frame_count, elapsed) = self.flipbook_playback(material)?;
+        Some((playback.flipbook.0, playback.frame(elapsed, frame_count)))
+    }
+
+    /// Moves the flipbooks along, streaming in the frames they're at, and keeps the windows redrawing while any
+    /// flipbook shown by a renderable still has frames to come
+    fn advance_flipbooks(&mut self, frame: &FrameContext) {
+        if self.flipbooks.is_empty() {
+            return;
+        }
+        self.flipbook_clock += frame.delta_time;
+        let mut streamed = vec![false; self.flipbooks.len()];
+        for material in 0..self.materials.len() {
+            let Some((flipbook, shown)) = self.flipbook_frame(material) else {
+                continue;
+            };
+            // The first material decides which frame a streamed flipbook holds
+            if !std::mem::replace(&mut streamed[flipbook], true) {
+                self.flipbooks[flipbook].show(&self.queue, shown);
+            }
+        }
+        let playing = self.renderables.iter().any(|renderable| {
+            self.flipbook_playback(renderable.material.0).is_some_and(
+                |(playback, frame_count, elapsed)| playback.is_playing(elapsed, frame_count),
+            )
+        });
+        if playing {
+            self.request_redraw();
+        }
+    }
+
+    /// Starts playing a video for materials to show with [Material::video]
+    #[cfg(feature = "ffmpeg")]
+    pub fn add_video(&mut self, video: VideoTexture) -> VideoHandle {
+        self.videos.push(GpuVideo::new(
+            &self.device,
+            &self.queue,
+            &self.samplers,
+            video,
+            &self.material_bindings,
+        ));
+        self.request_redraw();
+        VideoHandle(self.videos.len() - 1)
+    }
+
+    #[cfg(feature = "ffmpeg")]
+    pub fn video(&self, handle: VideoHandle) -> Option<&VideoTexture> {
+        Some(&self.videos.get(handle.0)?.video)
+    }
+
+    /// For pausing a video, see [VideoTexture::paused]
+    #[cfg(feature = "ffmpeg")]
+    pub fn video_mut(&mut self, handle: VideoHandle) -> Option<&mut VideoTexture> {
+        self.request_redraw();
+        Some(&mut self.videos.get_mut(handle.0)?.video)
+    }
+
+    /// Uploads the frames that came due and keeps the windows redrawing while any video plays
+    #[cfg(feature = "ffmpeg")]
+    fn advance_videos(&mut self, frame: &FrameContext) {
+        let mut playing = false;
+        for video in &mut self.videos {
+            playing |= video.advance(&self.queue, frame.delta_time);
+        }
+        if playing {
+            self.request_redraw();
+        }
+    }
+
+    /// Plays a model's clips on the renderables [crate::model::ModelData::add_to] returned for it, from the next update
+    /// on. Parts following a joint move with it from wherever their renderables are placed.
+    pub fn add_animation_player(
+        &mut self,
+        player: AnimationPlayer,
+        renderables: &[Renderable],
+    ) -> AnimationPlayerHandle {
+        assert_eq!(
+            renderables.len(),
+            player.part_count(),
+            "Renderables don't match the model's parts!"
+        );
+        self.animation_players.push(Some(AnimatedModel {
+            player,
+            meshes: renderables
+                .iter()
+                .map(|renderable| renderable.mesh)
+                .collect(),
+        }));
+        self.request_redraw();
+        AnimationPlayerHandle(self.animation_players.len() - 1)
+    }
+
+    pub fn animation_player(&self, handle: AnimationPlayerHandle) -> Option<&AnimationPlayer> {
+        Some(&self.animation_players.get(handle.0)?.as_ref()?.player)
+    }
+
+    /// For picking clips, blending them and pausing, see [AnimationPlayer]
+    pub fn animation_player_mut(
+        &mut self,
+        handle: AnimationPlayerHandle,
+    ) -> Option<&mut AnimationPlayer> {
+        self.request_redraw();
+        Some(&mut self.animation_players.get_mut(handle.0)?.as_mut()?.player)
+    }
+
+    /// Stops moving the model's renderables, they're drawn where they are placed again
+    pub fn remove_animation_player(
+        &mut self,
+        handle: AnimationPlayerHandle,
+    ) -> Option<AnimationPlayer> {
+        let animated = self.animation_players.get_mut(handle.0)?.take()?;
+        for mesh in &animated.meshes {
+            self.animated_meshes.remove(mesh);
+        }
+        self.request_redraw();
+        Some(animated.player)
+    }
+
+    /// Moves the players along and where they put the meshes, keeping the windows redrawing while any is playing.
+    /// The poses are sampled on the job threads.
+    fn advance_animations(&mut self, frame: &FrameContext) {
+        let mut playing = false;
+        for animated in self.animation_players.iter_mut().flatten() {
+            if animated.player.is_playing() {
+                animated.player.advance(frame.delta_time.as_secs_f32());
+                playing = true;
+            }
+        }
+        // Players changed by hand move their meshes as well, even when they're not playing
+        let animated: Vec<_> = self.animation_players.iter().flatten().collect();
+        let offsets = self.jobs.map(&animated, |animated| {
+            animated.mesh_offsets().collect::<Vec<_>>()
+        });
+        self.animated_meshes.extend(offsets.into_iter().flatten());
+        if playing {
+            self.request_redraw();
+        }
+    }
+
+    /// Draws the points into every window and render target from the next frame on. Clouds still loading are drawn
+    /// with what they have so far.
+    pub fn add_point_cloud(&mut self, cloud: PointCloud) -> PointCloudHandle {
+        let handle = self
+            .point_clouds
+            .add(&self.device, self.format, &self.global_bindings, cloud);
+        self.request_redraw();
+        handle
+    }
+
+    pub fn point_cloud(&self, handle: PointCloudHandle) -> Option<&PointCloud> {
+        self.point_clouds.get(handle)
+    }
+
+    /// For moving the cloud and changing its point budget and size
+    pub fn point_cloud_mut(&mut self, handle: PointCloudHandle) -> Option<&mut PointCloud> {
+        self.request_redraw();
+        self.point_clouds.get_mut(handle)
+    }
+
+    /// Stops drawing the cloud and frees its points on the GPU, loading stops as well
+    pub fn remove_point_cloud(&mut self, handle: PointCloudHandle) -> Option<PointCloud> {
+        let cloud = self.point_clouds.remove(handle)?;
+        self.request_redraw();
+        Some(cloud)
+    }
+
+    /// Blends the grid over every window and render target from the next frame on, its voxels are uploaded when it's
+    /// first drawn
+    pub fn add_voxel_grid(&mut self, grid: VoxelGrid) -> VoxelGridHandle {
+        let handle = self.voxel_grids.add(&self.device, self.format, grid);
+        self.request_redraw();
+        handle
+    }
+
+    pub fn voxel_grid(&self, handle: VoxelGridHandle) -> Option<&VoxelGrid> {
+        self.voxel_grids.get(handle)
     }
-    pub fn resize(&mut self, width: u32, height: u32) {
-        self.config.width = width;
-        self.config.height = height;
-        self.surface.configure(&self.device, &self.config);
 
-        self.camera.resize_projection(width, height);
-        self.depth_texture =
-            texture::Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+    /// For moving the grid and changing its density and sampling
+    pub fn voxel_grid_mut(&mut self, handle: VoxelGridHandle) -> Option<&mut VoxelGrid> {
+        self.request_redraw();
+        self.voxel_grids.get_mut(handle)
     }
+
+    /// Stops drawing the grid and frees its voxels on the GPU
+    pub fn remove_voxel_grid(&mut self, handle: VoxelGridHandle) -> Option<VoxelGrid> {
+        let grid = self.voxel_grids.remove(handle)?;
+        self.request_redraw();
+        Some(grid)
+    }
+
+    /// Makes a lookup table usable by glyphs, streamlines, slices, heatmaps and particles. Colormaps are kept as long
+    /// as the engine, since anything drawn may refer to them.
+    pub fn add_colormap(&mut self, lut: ColormapLut) -> Colormap {
+        self.colormaps.add(lut)
+    }
+
+    /// The colors of a built-in or added colormap, e.g. for drawing a legend
+    pub fn colormap(&self, colormap: Colormap) -> Option<&ColormapLut> {
+        self.colormaps.lut(colormap)
+    }
+
+    /// Draws glyphs for the field into every window and render target from the next frame on
+    pub fn add_glyphs(&mut self, glyphs: Glyphs) -> GlyphsHandle {
+        let handle =
+            self.vector_fields
+                .add_glyphs(&self.device, self.format, &self.global_bindings, glyphs);
+        self.request_redraw();
+        handle
+    }
+
+    pub fn glyphs(&self, handle: GlyphsHandle) -> Option<&Glyphs> {
+        self.vector_fields.glyphs(handle)
+    }
+
+    /// For changing the field and how it's drawn, the glyphs are uploaded again on the next frame
+    pub fn glyphs_mut(&mut self, handle: GlyphsHandle) -> Option<&mut Glyphs> {
+        self.request_redraw();
+        self.vector_fields.glyphs_mut(handle)
+    }
+
+    /// Stops drawing the glyphs and frees them on the GPU
+    pub fn remove_glyphs(&mut self, handle: GlyphsHandle) -> Option<Glyphs> {
+        let glyphs = self.vector_fields.remove_glyphs(handle)?;
+        self.request_redraw();
+        Some(glyphs)
+    }
+
+    /// Traces the lines and draws them as tubes into every window and render target from the next frame on
+    pub fn add_streamlines(&mut self, streamlines: Streamlines) -> StreamlinesHandle {
+        let handle = self.vector_fields.add_streamlines(
+            &self.device,
+            self.format,
+            &self.global_bindings,
+            streamlines,
+        );
+        self.request_redraw();
+        handle
+    }
+
+    pub fn streamlines(&self, handle: StreamlinesHandle) -> Option<&Streamlines> {
+        self.vector_fields.streamlines(handle)
+    }
+
+    /// For changing the seeds, the field and how the lines are traced, they're traced again on the next frame
+    pub fn streamlines_mut(&mut self, handle: StreamlinesHandle) -> Option<&mut Streamlines> {
+        self.request_redraw();
+        self.vector_fields.streamlines_mut(handle)
+    }
+
+    /// Stops drawing the lines and frees their tubes on the GPU
+    pub fn remove_streamlines(&mut self, handle: StreamlinesHandle) -> Option<Streamlines> {
+        let streamlines = self.vector_fields.remove_streamlines(handle)?;
+        self.request_redraw();
+        Some(streamlines)
+    }
+
+    /// Extracts the surface every frame and draws it into every window and render target from the next frame on.
+    /// Devices without compute shaders, e.g. WebGL2, don't draw it.
+    pub fn add_isosurface(&mut self, surface: Isosurface) -> IsosurfaceHandle {
+        let handle = self.isosurfaces.add(
+            &self.device,
+            self.format,
+            &self.global_bindings,
+            &self.device_report,
+            surface,
+        );
+        self.request_redraw();
+        handle
+    }
+
+    pub fn isosurface(&self, handle: IsosurfaceHandle) -> Option<&Isosurface> {
+        self.isosurfaces.get(handle)
+    }
+
+    /// For moving the isovalue and replacing the values
+    pub fn isosurface_mut(&mut self, handle: IsosurfaceHandle) -> Option<&mut Isosurface> {
+        self.request_redraw();
+        self.isosurfaces.get_mut(handle)
+    }
+
+    /// Stops drawing the surface and the slices through it and frees its values and triangles on the GPU
+    pub fn remove_isosurface(&mut self, handle: IsosurfaceHandle) -> Option<Isosurface> {
+        let surface = self.isosurfaces.remove(handle)?;
+        self.request_redraw();
+        Some(surface)
+    }
+
+    /// Draws the slice into every window and render target from the next frame on, where isosurfaces are drawn
+    pub fn add_slice(&mut self, slice: Slice) -> SliceHandle {
+        let handle = self.isosurfaces.add_slice(
+            &self.device,
+            self.format,
+            &self.global_bindings,
+            &self.device_report,
+            slice,
+        );
+        self.request_redraw();
+        handle
+    }
+
+    pub fn slice(&self, handle: SliceHandle) -> Option<&Slice> {
+        self.isosurfaces.slice(handle)
+    }
+
+    /// For moving the plane through the volume and changing its colors
+    pub fn slice_mut(&mut self, handle: SliceHandle) -> Option<&mut Slice> {
+        self.request_redraw();
+        self.isosurfaces.slice_mut(handle)
+    }
+
+    pub fn remove_slice(&mut self, handle: SliceHandle) -> Option<Slice> {
+        let slice = self.isosurfaces.remove_slice(handle)?;
+        self.request_redraw();
+        Some(slice)
+    }
+
+    /// Steps the simulation every frame and draws its particles into every window and render target from the next
+    /// frame on. Devices without compute shaders, e.g. WebGL2, don't run it.
+    pub fn add_simulation(&mut self, simulation: ParticleSimulation) -> SimulationHandle {
+        let handle = self.simulations.add(
+            &self.device,
+            self.format,
+            &self.global_bindings,
+            &self.device_report,
+            simulation,
+        );
+        self.request_redraw();
+        handle
+    }
+
+    pub fn simulation(&self, handle: SimulationHandle) -> Option<&ParticleSimulation> {
+        self.simulations.get(handle)
+    }
+
+    /// For pausing, resetting and tuning the simulation
+    pub fn simulation_mut(&mut self, handle: SimulationHandle) -> Option<&mut ParticleSimulation> {
+        self.request_redraw();
+        self.simulations.get_mut(handle)
+    }
+
+    /// Stops the simulation and frees its particles on the GPU
+    pub fn remove_simulation(&mut self, handle: SimulationHandle) -> Option<ParticleSimulation> {
+        let simulation = self.simulations.remove(handle)?;
+        self.request_redraw();
+        Some(simulation)
+    }
+
+    /// Simulates the cloth every frame and draws it into every window and render target from the next frame on.
+    /// Devices without compute shaders, e.g. WebGL2, don't simulate it.
+    pub fn add_cloth(&mut self, cloth: Cloth) -> ClothHandle {
+        let handle = self.cloths.add(
+            &self.device,
+            self.format,
+            &self.global_bindings,
+            &self.device_report,
+            cloth,
+        );
+        self.request_redraw();
+        handle
+    }
+
+    pub fn cloth(&self, handle: ClothHandle) -> Option<&Cloth> {
+        self.cloths.get(handle)
+    }
+
+    /// For pinning vertices, moving the colliders and resetting the cloth
+    pub fn cloth_mut(&mut self, handle: ClothHandle) -> Option<&mut Cloth> {
+        self.request_redraw();
+        self.cloths.get_mut(handle)
+    }
+
+    /// Stops simulating the cloth and frees its vertices on the GPU
+    pub fn remove_cloth(&mut self, handle: ClothHandle) -> Option<Cloth> {
+        let cloth = self.cloths.remove(handle)?;
+        self.request_redraw();
+        Some(cloth)
+    }
+
+    /// Steers the flock every frame and draws its boids into every window and render target from the next frame on.
+    /// Devices without compute shaders, e.g. WebGL2, don't steer it.
+    pub fn add_flock(&mut self, flock: Flock) -> FlockHandle {
+        let handle = self.flocks.add(
+            &self.device,
+            self.format,
+            &self.global_bindings,
+            &self.device_report,
+            flock,
+        );
+        self.request_redraw();
+        handle
+    }
+
+    pub fn flock(&self, handle: FlockHandle) -> Option<&Flock> {
+        self.flocks.get(handle)
+    }
+
+    /// For pausing, resetting and tuning the rules of the flock
+    pub fn flock_mut(&mut self, handle: FlockHandle) -> Option<&mut Flock> {
+        self.request_redraw();
+        self.flocks.get_mut(handle)
+    }
+
+    /// Stops steering the flock and frees its boids on the GPU
+    pub fn remove_flock(&mut self, handle: FlockHandle) -> Option<Flock> {
+        let flock = self.flocks.remove(handle)?;
+        self.request_redraw();
+        Some(flock)
+    }
+
+    /// Draws the water surface into every window and render target from the next frame on, its waves running until
+    /// it's paused
+    pub fn add_water(&mut self, water: Water) -> WaterHandle {
+        let handle = self.waters.add(
+            &self.device,
+            self.format,
+            &self.global_bindings,
+            &self.material_bindings,
+            water,
+        );
+        self.request_redraw();
+        handle
+    }
+
+    pub fn water(&self, handle: WaterHandle) -> Option<&Water> {
+        self.waters.get(handle)
+    }
+
+    /// For pausing the waves, changing them and the colors
+    pub fn water_mut(&mut self, handle: WaterHandle) -> Option<&mut Water> {
+        self.request_redraw();
+        self.waters.get_mut(handle)
+    }
+
+    pub fn remove_water(&mut self, handle: WaterHandle) -> Option<Water> {
+        let water = self.waters.remove(handle)?;
+        self.request_redraw();
+        Some(water)
+    }
+
+    /// Draws the terrain into every window and render target from the next frame on. Its tiles are sampled as the
+    /// cameras get close to them, coarser ones are drawn in the meantime.
+    pub fn add_terrain(&mut self, terrain: Terrain) -> TerrainHandle {
+        let handle = self
+            .terrains
+            .add(&self.device, self.format, &self.global_bindings, terrain);
+        self.request_redraw();
+        handle
+    }
+
+    pub fn terrain(&self, handle: TerrainHandle) -> Option<&Terrain> {
+        self.terrains.get(handle)
+    }
+
+    /// For changing the detail and the colors. Moving or resizing the terrain samples all of its tiles again.
+    pub fn terrain_mut(&mut self, handle: TerrainHandle) -> Option<&mut Terrain> {
+        self.request_redraw();
+        self.terrains.get_mut(handle)
+    }
+
+    /// Stops drawing the terrain and frees its tiles on the GPU
+    pub fn remove_terrain(&mut self, handle: TerrainHandle) -> Option<Terrain> {
+        let terrain = self.terrains.remove(handle)?;
+        self.request_redraw();
+        Some(terrain)
+    }
+
+    /// Culls the vegetation for and draws it into every window and render target from the next frame on, its wind
+    /// blowing until it's paused. Devices without compute shaders or indirect draws, e.g. WebGL2, don't draw it.
+    pub fn add_vegetation(&mut self, vegetation: Vegetation) -> VegetationHandle {
+        let handle = self.vegetations.add(
+            &self.device,
+            self.format,
+            &self.global_bindings,
+            self.depth_pyramid_pass.as_ref(),
+            &self.device_report,
+            vegetation,
+        );
+        self.request_redraw();
+        handle
+    }
+
+    pub fn vegetation(&self, handle: VegetationHandle) -> Option<&Vegetation> {
+        self.vegetations.get(handle)
+    }
+
+    /// For pausing the wind, changing it, the blades and the distances
+    pub fn vegetation_mut(&mut self, handle: VegetationHandle) -> Option<&mut Vegetation> {
+        self.request_redraw();
+        self.vegetations.get_mut(handle)
+    }
+
+    /// Stops drawing the vegetation and frees its blades on the GPU
+    pub fn remove_vegetation(&mut self, handle: VegetationHandle) -> Option<Vegetation> {
+        let vegetation = self.vegetations.remove(handle)?;
+        self.request_redraw();
+        Some(vegetation)
+    }
+
+    /// Captures the scene around the probe with the next frame, for the reflective materials around it. Probes
+    /// past the first [crate::reflection_probe::MAX_PROBES] get a cube once one of those is removed.
+    pub fn add_reflection_probe(&mut self, probe: ReflectionProbe) -> ReflectionProbeHandle {
+        let handle = self.reflection_probes.add(
+            &self.device,
+            self.format,
+            &self.samplers,
+            self.material_bindings.probes(),
+            probe,
+        );
+        self.request_redraw();
+        handle
+    }
+
+    pub fn reflection_probe(&self, handle: ReflectionProbeHandle) -> Option<&ReflectionProbe> {
+        self.reflection_probes.get(handle)
+    }
+
+    /// For moving the probe or its box and changing how it's updated, a changed probe is captured again
+    pub fn reflection_probe_mut(
+        &mut self,
+        handle: ReflectionProbeHandle,
+    ) -> Option<&mut ReflectionProbe> {
+        self.request_redraw();
+        self.reflection_probes.get_mut(handle)
+    }
+
+    /// Frees the probe's cube for the next probe without one
+    pub fn remove_reflection_probe(
+        &mut self,
+        handle: ReflectionProbeHandle,
+    ) -> Option<ReflectionProbe> {
+        let probe = self.reflection_probes.remove(handle)?;
+        self.request_redraw();
+        Some(probe)
+    }
+
+    /// Draws all six faces of the probe again with the next frame, e.g. after the scene around a
+    /// [crate::reflection_probe::ProbeUpdate::OnDemand] probe changed
+    pub fn capture_reflection_probe(&mut self, handle: ReflectionProbeHandle) {
+        self.reflection_probes.capture(handle);
+        self.request_redraw();
+    }
+
+    /// A renderable's model matrix, moved by the animation player of its mesh
+    fn drawn_model(&self, renderable: &Renderable) -> Matrix4<f32> {
+        match self.animated_meshes.get(&renderable.mesh) {
+            Some(offset) => renderable.model * offset,
+            None => renderable.model,
+        }
+    }
+
+    /// What a material samples, a video over a flipbook over a render target. Handles from another engine sample
+    /// nothing.
+    fn draw_texture(&self, index: usize, material: &Material) -> Option<DrawTexture> {
+        #[cfg(feature = "ffmpeg")]
+        if let Some(video) = material.video.filter(|video| video.0 < self.videos.len()) {
+            return Some(DrawTexture::Video(video.0));
+        }
+        if let Some((flipbook, frame)) = self.flipbook_frame(index) {
+            return Some(DrawTexture::Flipbook(flipbook, frame));
+        }
+        let target = material
+            .texture
+            .map(|handle| handle.0)
+            .filter(|&target| target < self.render_targets.len())
+            .map(DrawTexture::RenderTarget);
+        target.or_else(|| {
+            material
+                .image
+                .map(|handle| handle.0)
+                .filter(|&texture| texture < self.textures.len())
+                .map(DrawTexture::Image)
+        })
+    }
+
+    /// Creates an offscreen target that materials can show with [Material::texture]. With a `camera` the scene is
+    /// drawn into it on every update, objects showing the target itself are left out of it.
+    pub fn add_render_target(
+        &mut self,
+        width: u32,
+        height: u32,
+        camera: Option<OrbitCamera>,
+    ) -> RenderTargetHandle {
+        self.render_targets.push(RenderTarget::new(
+            &self.device,
+            self.format,
+            width,
+            height,
+            camera,
+            &self.samplers,
+            &self.material_bindings,
+        ));
+        self.request_redraw();
+        RenderTargetHandle(self.render_targets.len() - 1)
+    }
+
+    /// Changes a target's size, what it showed is lost until the next update draws into it again. The aspect ratio of
+    /// its camera follows.
+    pub fn resize_render_target(&mut self, handle: RenderTargetHandle, width: u32, height: u32) {
+        let Some(target) = self.render_targets.get_mut(handle.0) else {
+            return;
+        };
+        let (width, height) = (width.max(1), height.max(1));
+        if target.size() == (width, height) {
+            return;
+        }
+        let eye = target.eye;
+        *target = RenderTarget::new(
+            &self.device,
+            self.format,
+            width,
+            height,
+            target.camera,
+            &self.samplers,
+            &self.material_bindings,
+        );
+        target.eye = eye;
+        if let Some(camera) = &mut target.camera {
+            camera.resize_projection(width, height);
+        }
+        self.request_redraw();
+    }
+
+    /// Adds a render target drawn from a camera set up like the windows' ones, for showing the engine in a host
+    /// application's UI, see [crate::external_surface]
+    pub fn add_external_surface(&mut self, width: u32, height: u32) -> ExternalSurface {
+        let (width, height) = (width.max(1), height.max(1));
+        let mut camera = OrbitCamera::new(
+            1.0,
+            0.0,
+            0.0,
+            Vector3::new(0.0, 0.0, 0.0),
+            width as f32 / height as f32,
+        );
+        camera.bounds.min_distance = Some(1.1);
+        let mut camera_controller = CameraController::new(0.005, 0.1);
+        self.device_settings
+            .camera_defaults
+            .apply(&mut camera, &mut camera_controller);
+        let target = self.add_render_target(width, height, Some(camera));
+        ExternalSurface::new(target, camera_controller)
+    }
+
+    /// Adds a render target for each eye of a headset, `width` x `height` pixels each. Nothing is drawn into them
+    /// until [RenderEngine::set_eye_views] says where the eyes are, see [crate::xr].
+    pub fn add_stereo_targets(&mut self, width: u32, height: u32) -> StereoTargets {
+        StereoTargets {
+            left: self.add_render_target(width, height, None),
+            right: self.add_render_target(width, height, None),
+            near: 0.05,
+            far: 1000.0,
+        }
+    }
+
+    /// Draws the eyes from `views`, left first, during the following updates
+    pub fn set_eye_views(&mut self, targets: &StereoTargets, views: [EyeView; 2]) {
+        for (handle, view) in [targets.left, targets.right].into_iter().zip(views) {
+            if let Some(target) = self.render_targets.get_mut(handle.0) {
+                target.eye = Some(view.uniform(targets.near, targets.far));
+            }
+        }
+    }
+
+    pub fn render_target(&self, handle: RenderTargetHandle) -> Option<&RenderTarget> {
+        self.render_targets.get(handle.0)
+    }
+
+    /// Changes to the target's camera show up with the next redraw, see [RenderEngine::request_redraw]
+    pub fn render_target_mut(&mut self, handle: RenderTargetHandle) -> Option<&mut RenderTarget> {
+        self.render_targets.get_mut(handle.0)
+    }
+
+    /// Copies the target's current content out as tightly packed RGBA8 pixels, blocking until the GPU is done.
+    /// None if the target doesn't exist or reading it back failed.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn read_render_target(&mut self, handle: RenderTargetHandle) -> Option<Vec<u8>> {
+        // The last update may not have been submitted yet, it waits for the next window drawn
+        self.submit_commands();
+        let texture = self.render_targets.get(handle.0)?.color_texture();
+        read_texture_to_vec(
+            &self.device,
+            &self.queue,
+            texture,
+            0,
+            wgpu::TextureAspect::All,
+            ReadFormat::Rgba8,
+        )
+        .wait(&self.device)
+    }
+
+    /// Moves the target's color texture into memory exported as a DMA-BUF, for hosts showing it through another
+    /// graphics API, see [crate::external_surface::ExternalSurface::export_dmabuf]. Resizing the target or losing
+    /// the device allocates a new texture that isn't exported.
+    #[cfg(all(feature = "dmabuf", target_os = "linux"))]
+    pub fn export_render_target(
+        &mut self,
+        handle: RenderTargetHandle,
+    ) -> std::io::Result<crate::dmabuf::DmaBuf> {
+        let target = self.render_targets.get_mut(handle.0).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "There is no such render target",
+            )
+        })?;
+        let (width, height) = target.size();
+        let (texture, dmabuf) =
+            crate::dmabuf::create_exported_texture(&self.device, width, height, self.format)?;
+        let color = texture::Texture::from_render_target(&self.device, &self.samplers, texture);
+        target.replace_color(&self.device, color, &self.material_bindings);
+        self.request_redraw();
+        Ok(dmabuf)
+    }
+
+    /// The renderables drawn, with their meshes, materials and the images their materials show right now, see
+    /// [crate::gltf::encode_glb]. Renderables with a handle that doesn't belong to this engine are left out.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn export_scene(&mut self) -> ExportScene {
+        let mut scene = ExportScene::default();
+        let mut meshes = HashMap::new();
+        let mut materials = HashMap::new();
+        let mut textures = HashMap::new();
+        for renderable in self.renderables.clone() {
+            let (Some(pooled), Some(material)) = (
+                self.meshes.get(renderable.mesh.0),
+                self.materials.get(renderable.material.0).cloned(),
+            ) else {
+                continue;
+            };
+            let mesh = *meshes.entry(renderable.mesh).or_insert_with(|| {
+                scene.meshes.push(pooled.data.clone());
+                scene.meshes.len() - 1
+            });
+            let transform = self.drawn_model(&renderable);
+            let material = match materials.get(&renderable.material) {
+                Some(&material) => material,
+                None => {
+                    let texture = self
+                        .draw_texture(renderable.material.0, &material)
+                        .and_then(|texture| match textures.get(&texture) {
+                            Some(&index) => Some(index),
+                            None => {
+                                scene.textures.push(self.texture_data(texture)?);
+                                textures.insert(texture, scene.textures.len() - 1);
+                                Some(scene.textures.len() - 1)
+                            }
+                        });
+                    scene.materials.push(ExportMaterial {
+                        base_color: material.base_color,
+                        texture,
+                    });
+                    materials.insert(renderable.material, scene.materials.len() - 1);
+                    scene.materials.len() - 1
+                }
+            };
+            scene.objects.push(ExportObject {
+                mesh,
+                material,
+                transform,
+            });
+        }
+        scene
+    }
+
+    /// The image a material samples right now
+    #[cfg(not(target_arch = "wasm32"))]
+    fn texture_data(&mut self, texture: DrawTexture) -> Option<TextureData> {
+        let data = match texture {
+            DrawTexture::RenderTarget(target) => {
+                let (width, height) = self.render_targets[target].size();
+                let rgba = self.read_render_target(RenderTargetHandle(target));
+                rgba.map(|rgba| TextureData {
+                    width,
+                    height,
+                    rgba,
+                })
+            }
+            DrawTexture::Image(texture) => Some(self.textures[texture].data.clone()),
+            DrawTexture::Flipbook(flipbook, frame) => {
+                Some(self.flipbooks[flipbook].frames[frame].clone())
+            }
+            #[cfg(feature = "ffmpeg")]
+            DrawTexture::Video(video) => self.videos[video].frame(),
+        };
+        if data.is_none() {
+            tracing::warn!("Couldn't read back {texture:?}, exporting its material without it");
+        }
+        data
+    }
+
+    /// The unit cube every engine starts out with
+    pub fn cube_mesh(&self) -> MeshHandle {
+        MeshHandle(0)
+    }
+
+    /// Plain white, leaving the vertex colors as they are
+    pub fn material(&self, handle: MaterialHandle) -> Option<&Material> {
+        self.materials.get(handle.0)
+    }
+
+    pub fn default_material(&self) -> MaterialHandle {
+        MaterialHandle(0)
+    }
+
+    /// What was handed to the last [RenderEngine::set_renderables]
+    pub fn renderables(&self) -> &[Renderable] {
+        &self.renderables
+    }
+
+    /// Replaces everything that gets drawn, usually called once per frame before [RenderEngine::update]. Every
+    /// window is redrawn if anything changed. Renderables with a handle that doesn't belong to this engine are skipped.
+    pub fn set_renderables(&mut self, renderables: Vec<Renderable>) {
+        if self.renderables != renderables {
+            self.renderables = renderables;
+            self.request_redraw();
+        }
+    }
+
+    /// Notes pinned to the scene, drawn over every window
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+
+    /// Replaces the annotations, redrawing every window if they changed
+    pub fn set_annotations(&mut self, annotations: Vec<Annotation>) {
+        if self.annotations != annotations {
+            self.annotations = annotations;
+            self.request_redraw();
+        }
+    }
+
+    pub fn add_annotation(&mut self, annotation: Annotation) {
+        self.annotations.push(annotation);
+        self.request_redraw();
+    }
+
+    /// Marks every window as out of date, so each gets a `RedrawRequested` event.
+    ///
+    /// The engine calls this itself when its own state changes, and cameras request a redraw of their window when
+    /// moved. Apps only need it for changes the engine can't see.
+    pub fn request_redraw(&self) {
+        for viewport in self.viewports.values() {
+            viewport.window.request_redraw();
+        }
+    }
+
+    /// Does the work that doesn't need a frame: writing out finished captures and picking up changed assets.
+    ///
+    /// Call it whenever the event loop is about to go idle and use the returned control flow. It only wakes the
+    /// loop up again while there is something to check on, so a static scene costs no CPU.
+    pub fn poll_background(&mut self) -> ControlFlow {
+        self.finish_captures();
+        if self.selection.has_click_ready()
+            || self.measure_tool.has_click_ready()
+            || self.probe_tool.has_hover_ready()
+        {
+            self.request_redraw();
+        }
+
+        // Recovery happens in the next update
+        if self.device_lost.is_lost() {
+            self.request_redraw();
+        }
+
+        #[cfg(feature = "hot-reload")]
+        self.reload_changed_assets();
+
+        // Loaded points come in the same way
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.point_clouds.poll() {
+            self.request_redraw();
+        }
+
+        // The asset watcher delivers changes through a channel without waking the event loop, so it has to be
+        // checked on regularly as well
+        if self.watches_assets()
+            || self.point_clouds.is_loading()
+            || self.viewports.values().any(Viewport::has_pending_readbacks)
+        {
+            ControlFlow::wait_duration(BACKGROUND_POLL_INTERVAL)
+        } else {
+            ControlFlow::Wait
+        }
+    }
+
+    pub fn render_frame(&mut self, window_id: WindowId) {
+        let _span = tracing::debug_span!("render_frame", ?window_id).entered();
+        let started = Instant::now();
+        self.finish_captures();
+        #[cfg(feature = "egui")]
+        if self.egui_debug_window && self.egui.window_id().is_none_or(|id| id == window_id) {
+            self.show_egui_debug_window();
+        }
+
+        let Some(viewport) = self.viewports.get_mut(&window_id) else {
+            return;
+        };
+        // Nothing to draw into until the window is restored, captures and recording frames wait until then
+        if viewport.is_minimized() {
+            return;
+        }
+        // Suspended, the surface comes back with resume
+        let Some(surface) = viewport.surface() else {
+            return;
+        };
+
+        let surface_texture = match surface.get_current_texture() {
+            Ok(surface_texture) => surface_texture,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                // Happens after display changes or driver hiccups, reconfiguring is enough to get it back
+                viewport.reconfigure(&self.device);
+                return;
+            }
+            Err(wgpu::SurfaceError::Timeout) => return,
+            Err(wgpu::SurfaceError::OutOfMemory) => {
+                self.device_lost.mark_lost();
+                return;
+            }
+        };
+
+        let surface_texture_view =
+            surface_texture
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor {
+                    label: wgpu::Label::default(),
+                    aspect: wgpu::TextureAspect::default(),
+                    format: Some(self.format),
+                    dimension: None,
+                    base_mip_level: 0,
+                    mip_level_count: None,
+                    base_array_layer: 0,
+                    array_layer_count: None,
+                });
+        // Below full resolution everything drawn into the scene goes into its own texture and is scaled up into the
+        // surface before the overlays
+        let scene_view = viewport
+            .scene
+            .as_ref()
+            .map_or(&surface_texture_view, |scene| &scene.view);
+        let render_size = viewport.render_size();
+        let main_pass_span = tracing::debug_span!("main_pass").entered();
+        // Runs ahead of the main pass, which draws what it wrote
+        #[cfg(feature = "meshlets")]
+        let meshlet_culling = self.meshlet_culling.as_ref().and_then(|culling| {
+            culling.cull(
+                &self.device,
+                &self.queue,
+                &self.device_report,
+                &mut viewport.meshlets,
+                &viewport.camera.uniform,
+            )
+        });
+        viewport.occlusion.begin(
+            &self.device,
+            self.frame.draws.iter().filter_map(|draw| draw.occlusion),
+            &viewport.camera.uniform,
+        );
+        // Detail streams in over the next frames
+        if self.point_clouds.prepare(
+            &self.device,
+            &self.queue,
+            &mut viewport.point_clouds,
+            &viewport.camera.uniform,
+        ) {
+            viewport.window.request_redraw();
+        }
+        if self.terrains.prepare(
+            &self.device,
+            &self.queue,
+            &self.jobs,
+            self.sky.sun(),
+            &mut viewport.terrain,
+            &viewport.camera.uniform,
+        ) {
+            viewport.window.request_redraw();
+        }
+        let vegetation_culling = self
+            .depth_pyramid_pass
+            .as_ref()
+            .and_then(|depth_pyramid_pass| {
+                self.vegetations.cull(
+                    &self.device,
+                    &self.queue,
+                    &mut viewport.vegetation,
+                    &viewport.camera.uniform,
+                    depth_pyramid_pass.cull_bind_group(viewport.depth_pyramid.as_ref()),
+                )
+            });
+        self.voxel_grids.prepare(
+            &self.device,
+            &self.queue,
+            &self.samplers,
+            &mut viewport.voxel_grids,
+            &viewport.camera.uniform,
+        );
+        self.sky.prepare(
+            &self.device,
+            &self.queue,
+            &mut viewport.atmosphere,
+            &viewport.camera.uniform,
+        );
+        let query = self
+            .profiler
+            .as_mut()
+            .and_then(|profiler| profiler.scope("main_pass window"));
+        let (main_pass, stats) = MainPass {
+            label: "main_pass window",
+            device: &self.device,
+            pipeline: &self.pipeline,
+            meshes: &self.meshes,
+            object_bindings: &self.object_bindings,
+            material_bindings: &self.material_bindings,
+            render_targets: &self.render_targets,
+            textures: &self.textures,
+            flipbooks: &self.flipbooks,
+            #[cfg(feature = "ffmpeg")]
+            videos: &self.videos,
+            background: &self.background,
+            occlusion_pass: &self.occlusion_pass,
+            point_cloud_pass: self.point_clouds.pass(),
+            point_clouds: viewport.point_clouds.as_ref(),
+            vector_field_pass: self.vector_fields.pass(),
+            isosurface_pass: self.isosurfaces.pass(),
+            simulation_pass: self.simulations.pass(),
+            cloth_pass: self.cloths.pass(),
+            boids_pass: self.flocks.pass(),
+            water_pass: self.waters.pass(),
+            terrain_pass: self.terrains.pass(),
+            terrain: viewport.terrain.as_ref(),
+            vegetation_pass: self.vegetations.pass(),
+            vegetation: viewport.vegetation.as_ref(),
+            irradiance_volume_pass: self.irradiance.pass(),
+            vertex_pulling: self.vertex_pulling.as_ref().map(VertexPulling::bind_group),
+            #[cfg(feature = "meshlets")]
+            meshlet_draws: viewport.meshlets.as_ref().map(MeshletView::draws),
+            occlusion: &viewport.occlusion,
+            #[cfg(feature = "parallel-encoding")]
+            jobs: &self.jobs,
+            color: scene_view,
+            depth: &viewport.depth_texture.view,
+            ids: &viewport.id_texture.view,
+            globals: viewport.global_bindings.bind_groups(),
+            drawing_into: None,
+            timestamps: self
+                .profiler
+                .as_ref()
+                .zip(query)
+                .map(|(profiler, query)| profiler.render_pass_writes(query)),
+        }
+        .encode_all(&self.frame.draws);
+        #[cfg(feature = "meshlets")]
+        self.commands.push(SubmitStage::Compute, meshlet_culling);
+        self.commands.push(SubmitStage::Compute, vegetation_culling);
+        self.commands.push(SubmitStage::Windows, main_pass);
+        self.render_stats += stats;
+        drop(main_pass_span);
+
+        // Plugin passes and readback copies go after the main pass
+        let encoder = self.commands.encoder(&self.device, SubmitStage::Windows);
+        // Of the main pass's depth alone, plugins see it
+        if let Some(depth_pyramid_pass) = &mut self.depth_pyramid_pass {
+            depth_pyramid_pass.build(
+                &self.device,
+                &self.queue,
+                encoder,
+                self.build_depth_pyramids,
+                &mut viewport.depth_pyramid,
+                &viewport.depth_texture.view,
+                render_size,
+                &viewport.camera.uniform,
+            );
+        }
+        // Before the grids, they're in front of the sky
+        self.sky.draw(
+            &self.device,
+            encoder,
+            viewport.atmosphere.as_ref(),
+            scene_view,
+            &viewport.depth_texture.view,
+        );
+        self.voxel_grids.draw(
+            &self.device,
+            encoder,
+            viewport.voxel_grids.as_ref(),
+            scene_view,
+            &viewport.depth_texture.view,
+        );
+
+        if self.selection.outline && !self.selection.is_empty() {
+            let selection = &self.selection;
+            let ids = self
+                .frame
+                .draws
+                .iter()
+                .enumerate()
+                .filter(|(_, draw)| selection.is_selected(draw.renderable))
+                .map(|(index, _)| index as u32 + 1);
+            self.outline.draw(
+                &self.device,
+                &self.queue,
+                encoder,
+                scene_view,
+                &viewport.id_texture.view,
+                ids,
+                selection.outline_color,
+            );
+        }
+
+        let context = PluginContext {
+            device: &self.device,
+            queue: &self.queue,
+            format: self.format,
+            global_bind_group_layout: self.global_bindings.bind_group_layouts(),
+            device_report: &self.device_report,
+            jobs: &self.jobs,
+            render_targets: &self.render_targets,
+        };
+        let target = PassTarget {
+            window_id,
+            width: render_size.0,
+            height: render_size.1,
+            color: scene_view,
+            depth: &viewport.depth_texture.view,
+            depth_pyramid: viewport.depth_pyramid.as_ref(),
+            globals: viewport.global_bindings.bind_groups(),
+            frame: &self.frame,
+        };
+        for (index, plugin) in self.plugins.iter_mut().enumerate() {
+            let _span = tracing::debug_span!("plugin_passes", plugin = index).entered();
+            let name = plugin.name().to_string();
+            let mut encoder = GpuDebugScope::new(encoder, &name);
+            match &mut self.profiler {
+                Some(profiler) => profiler.time_encoder(&mut encoder, name.as_str(), |encoder| {
+                    plugin.build_passes(&context, encoder, &target)
+                }),
+                None => plugin.build_passes(&context, &mut encoder, &target),
+            }
+        }
+        if let Some(scene) = &viewport.scene {
+            let _span = tracing::debug_span!("upscaling").entered();
+            self.upscaling_pass.draw(
+                &self.device,
+                &self.queue,
+                encoder,
+                &scene.view,
+                &surface_texture_view,
+                (viewport.config.width, viewport.config.height),
+                self.upscaling_sharpness,
+            );
+        }
+        self.heatmaps.draw(
+            &self.device,
+            &self.queue,
+            encoder,
+            window_id,
+            &surface_texture_view,
+            viewport.global_bindings.bind_groups(),
+            viewport.config.width,
+            viewport.config.height,
+        );
+        if let Some(view) = OverlayView::new(
+            &viewport.camera,
+            viewport.config.width,
+            viewport.config.height,
+        ) {
+            // Rulers under everything else, gizmo handles over debug shapes since it's what the cursor grabs
+            let mut geometry = OverlayGeometry::default();
+            if self.rulers_visible && viewport.camera.projection == Projection::Orthographic {
+                geometry = rulers::geometry(
+                    &viewport.camera,
+                    &view,
+                    viewport.config.width,
+                    viewport.config.height,
+                );
+            }
+            geometry.append(self.frame.debug_shapes.geometry(&view));
+            for annotation in &self.annotations {
+                annotation.geometry(&mut geometry, &view);
+            }
+            geometry.append(self.gizmo.geometry(window_id, &view));
+            geometry.append(self.selection.box_geometry(window_id, &view));
+            geometry.append(self.measure_tool.geometry(&view));
+            geometry.append(self.probe_tool.geometry(&view));
+            self.overlay.draw(
+                &self.device,
+                &self.queue,
+                encoder,
+                window_id,
+                &surface_texture_view,
+                &viewport.depth_texture.view,
+                viewport.global_bindings.bind_groups(),
+                &geometry,
+            );
+        }
+        if self.view_cube.visible {
+            let geometry = self.view_cube.geometry(window_id, &viewport.camera);
+            self.view_cube_pass.draw(
+                &self.device,
+                &self.queue,
+                encoder,
+                window_id,
+                &surface_texture_view,
+                viewport.config.width,
+                viewport.config.height,
+                &geometry,
+            );
+        }
+        self.plots.draw(
+            &self.device,
+            &self.queue,
+            encoder,
+            window_id,
+            &surface_texture_view,
+            viewport.config.width,
+            viewport.config.height,
+        );
+        let target = OverlayTarget {
+            window_id,
+            width: viewport.config.width,
+            height: viewport.config.height,
+            color: &surface_texture_view,
+            frame: &self.frame,
+        };
+        for (index, plugin) in self.plugins.iter_mut().enumerate() {
+            let _span = tracing::debug_span!("plugin_overlay_passes", plugin = index).entered();
+            let name = format!("{} overlay", plugin.name());
+            let mut encoder = GpuDebugScope::new(encoder, &name);
+            match &mut self.profiler {
+                Some(profiler) => profiler.time_encoder(&mut encoder, name.as_str(), |encoder| {
+                    plugin.build_overlay_passes(&context, encoder, &target)
+                }),
+                None => plugin.build_overlay_passes(&context, &mut encoder, &target),
+            }
+        }
+        #[cfg(feature = "egui")]
+        let encoder = {
+            let _span = tracing::debug_span!("egui").entered();
+            let callback_buffers = self.egui.prepare(
+                &self.device,
+                &self.queue,
+                encoder,
+                window_id,
+                viewport.config.width,
+                viewport.config.height,
+            );
+            // Paint callbacks run after the UI's uploads and before its pass, like everything else in the frame's
+            // submission
+            if !callback_buffers.is_empty() {
+                self.commands.push(SubmitStage::Windows, callback_buffers);
+            }
+            let encoder = self.commands.encoder(&self.device, SubmitStage::Windows);
+            self.egui.draw(
+                &mut GpuDebugScope::new(encoder, "egui"),
+                &surface_texture_view,
+            );
+            encoder
+        };
+        // Over everything, so plugin overlays don't hide it
+        self.hud.draw(
+            &self.device,
+            &self.queue,
+            encoder,
+            window_id,
+            &surface_texture_view,
+            viewport.config.width,
+            viewport.config.height,
+            &self.console,
+        );
+
+        let mut captures: Vec<PendingCapture> = viewport
+            .capture_requests
+            .drain(..)
+            .map(|path| PendingCapture::new(&self.device, encoder, &surface_texture.texture, path))
+            .collect();
+        if let Some(recorder) = &mut viewport.recorder {
+            recorder.record(&self.device, encoder, &surface_texture.texture);
+        }
+        viewport.record_depth_reads(&self.device, encoder);
+        let draws = &self.frame.draws;
+        viewport.record_picks(&self.device, encoder, || {
+            draws
+                .iter()
+                .map(|draw| Pick {
+                    renderable: draw.renderable,
+                    mesh: MeshHandle(draw.mesh),
+                })
+                .collect()
+        });
+        viewport.occlusion.resolve(&self.device, encoder);
+
+        tracing::debug_span!("submit").in_scope(|| {
+            self.submit_commands();
+            surface_texture.present();
+        });
+
+        let syncing = self.is_syncing();
+        let scripted = self.runs_script();
+        #[cfg(feature = "egui")]
+        let ui_animating = self.egui.needs_redraw();
+        #[cfg(not(feature = "egui"))]
+        let ui_animating = false;
+        let viewport = self
+            .viewports
+            .get_mut(&window_id)
+            .expect("Viewport drawn into exists!");
+        for capture in &mut captures {
+            capture.start_mapping();
+        }
+        viewport.pending_captures.extend(captures);
+        if let Some(recorder) = &mut viewport.recorder {
+            recorder.after_submit();
+        }
+        viewport.occlusion.after_submit();
+        viewport.after_depth_reads_submit();
+
+        // Recordings want every frame, even of a static camera, and so do scripts and music animating the scene.
+        // Synced instances only hear from the others while updating.
+        if viewport.recorder.is_some()
+            || scripted
+            || syncing
+            || self.audio.is_playing()
+            || self.plugins.iter().any(|plugin| plugin.needs_redraw())
+            || ui_animating
+        {
+            viewport.window.request_redraw();
+        }
+        self.render_time += started.elapsed();
+    }
+
+    /// Saves the next frame rendered into the window as a PNG at `path`.
+    ///
+    /// The frame is copied out on the GPU and written once the readback completes a few frames later, so this
+    /// never blocks the render loop.
+    pub fn capture_frame(&mut self, window_id: WindowId, path: impl Into<PathBuf>) {
+        // PNGs are written on a separate thread to a file system, neither exists on the web
+        if cfg!(target_arch = "wasm32") {
+            tracing::warn!("Screenshots are not supported on the web");
+            return;
+        }
+        let Some(viewport) = self.viewports.get_mut(&window_id) else {
+            return;
+        };
+        if !viewport
+            .config
+            .usage
+            .contains(wgpu::TextureUsages::COPY_SRC)
+        {
+            tracing::warn!("Screenshots are not supported, the surface can't be copied from");
+            return;
+        }
+        viewport.capture_requests.push(path.into());
+    }
+
+    /// The depth buffer value at pixel `x`, `y` of the window, counted in physical pixels from the top left, as of the
+    /// next frame drawn into it. 0 is on the near plane, 1 on the far plane or where nothing was drawn.
+    ///
+    /// The pixel is copied out on the GPU and read back a few frames later, the render loop never waits for it. Resolves
+    /// to None outside the window, once the window is closed, or on devices that can't copy depth, e.g. WebGL2.
+    pub fn read_depth_at(&mut self, window_id: WindowId, x: u32, y: u32) -> ReadbackFuture<f32> {
+        if !self
+            .device_report
+            .downlevel_flags
+            .contains(wgpu::DownlevelFlags::DEPTH_TEXTURE_AND_BUFFER_COPIES)
+        {
+            return ReadbackFuture::ready(None);
+        }
+        let Some(viewport) = self.viewports.get_mut(&window_id) else {
+            return ReadbackFuture::ready(None);
+        };
+        let (promise, future) = readback_future();
+        viewport.depth_requests.push(([x, y], promise));
+        viewport.window.request_redraw();
+        future
+    }
+
+    /// The renderable drawn at pixel `x`, `y` of the window, counted in physical pixels from the top left, as of the
+    /// next frame drawn into it. A mesh showing a render target is picked itself, not what the target sees.
+    ///
+    /// Like [RenderEngine::read_depth_at] this resolves a few frames later without stalling. Resolves to None where
+    /// only the background was drawn, outside the window, or once the window is closed.
+    pub fn pick(&mut self, window_id: WindowId, x: u32, y: u32) -> ReadbackFuture<Pick> {
+        let Some(viewport) = self.viewports.get_mut(&window_id) else {
+            return ReadbackFuture::ready(None);
+        };
+        let (promise, future) = readback_future();
+        viewport.pick_requests.push(([x, y], promise));
+        viewport.window.request_redraw();
+        future
+    }
+
+    /// Starts recording every `every_nth` frame of the window to `output` until [RenderEngine::stop_recording] is called.
+    ///
+    /// A recording that is already running in the window is finished first.
+    pub fn start_recording(
+        &mut self,
+        window_id: WindowId,
+        output: RecordingOutput,
+        every_nth: u32,
+    ) {
+        if cfg!(target_arch = "wasm32") {
+            tracing::warn!("Recording is not supported on the web");
+            return;
+        }
+        self.stop_recording(window_id);
+        let Some(viewport) = self.viewports.get_mut(&window_id) else {
+            return;
+        };
+        if !viewport
+            .config
+            .usage
+            .contains(wgpu::TextureUsages::COPY_SRC)
+        {
+            tracing::warn!("Recording is not supported, the surface can't be copied from");
+            return;
+        }
+        match FrameRecorder::new(output, every_nth) {
+            Ok(recorder) => viewport.recorder = Some(recorder),
+            Err(err) => tracing::error!("Failed to start recording: {err}"),
+        }
+    }
+
+    /// Waits for the frames still in flight and closes the window's recording
+    pub fn stop_recording(&mut self, window_id: WindowId) {
+        let recorder = self
+            .viewports
+            .get_mut(&window_id)
+            .and_then(|viewport| viewport.recorder.take());
+        if let Some(recorder) = recorder {
+            recorder.finish(&self.device);
+        }
+    }
+
+    pub fn is_recording(&self, window_id: WindowId) -> bool {
+        self.viewports
+            .get(&window_id)
+            .is_some_and(|viewport| viewport.recorder.is_some())
+    }
+
+    /// Writes out every capture and recorded frame whose readback buffer has been mapped in the meantime
+    fn finish_captures(&mut self) {
+        if !self.viewports.values().any(Viewport::has_pending_readbacks) {
+            return;
+        }
+        self.device.poll(wgpu::Maintain::Poll);
+        for viewport in self.viewports.values_mut() {
+            viewport.finish_captures(&self.jobs);
+        }
+    }
+
+    /// Hands an input event to the console, the view cube, the gizmo, the plugins, the selection and then
+    /// [RenderEngine::input], returns true if one of the first five consumed it. Consumed presses don't reach the
+    /// input, so nothing else sees the keys typed into the open console.
+    pub fn process_input_event(&mut self, window_id: WindowId, event: &InputEvent) -> bool {
+        if let InputEvent::Focused(focused) = event {
+            if let Some(viewport) = self.viewports.get(&window_id) {
+                viewport.apply_cursor_mode(*focused);
+            }
+        }
+        let consumed = self.dispatch_input_event(window_id, event);
+        if !(consumed && event.is_press()) {
+            self.input.process_input_event(window_id, event);
+        }
+        // Actions are run and the camera moved once per frame, there has to be one
+        if self.input.any_action_just_pressed() {
+            if let Some(viewport) = self.viewports.get(&window_id) {
+                viewport.window.request_redraw();
+            }
+        }
+        self.redraw_moving_camera();
+        consumed
+    }
+
+    /// [RenderEngine::process_input_event] for winit's window events, true if any of the input events it turned
+    /// into was consumed
+    pub fn process_window_event(&mut self, window_id: WindowId, event: &WindowEvent) -> bool {
+        let mut consumed = false;
+        for event in InputEvent::from_window_event(event) {
+            consumed |= self.process_input_event(window_id, &event);
+        }
+        consumed
+    }
+
+    /// Feeds winit's raw mouse motion to [RenderEngine::input]
+    pub fn process_event(&mut self, event: &DeviceEvent) {
+        self.input.process_device_event(event);
+        self.redraw_moving_camera();
+    }
+
+    /// Points the camera of every window at the center of the box from `min` to `max`, close enough that it fills
+    /// the view without being cut off
+    pub fn frame_bounds(&mut self, min: [f32; 3], max: [f32; 3]) {
+        for viewport in self.viewports.values_mut() {
+            viewport.camera.frame(min.into(), max.into());
+        }
+        self.request_redraw();
+    }
+
+    /// Hides or grabs the cursor while the window has focus. It's released whenever the window loses focus and
+    /// grabbed again once it's back.
+    pub fn set_cursor_mode(&mut self, window_id: WindowId, mode: CursorMode) {
+        let focused = self.input.focused_window() == Some(window_id);
+        if let Some(viewport) = self.viewports.get_mut(&window_id) {
+            viewport.cursor_mode = mode;
+            viewport.apply_cursor_mode(focused);
+        }
+    }
+
+    pub fn cursor_mode(&self, window_id: WindowId) -> CursorMode {
+        self.viewports
+            .get(&window_id)
+            .map_or(CursorMode::Normal, |viewport| viewport.cursor_mode)
+    }
+
+    /// The state of the keyboard and mouse, see [crate::input].
+    ///
+    /// The engine binds a few actions itself, applied to the focused window: `view_front`, `view_right`, `view_top`
+    /// and `view_isometric` on numpad 1, 3, 7 and 0 look from that side, from the opposite one with control held.
+    /// `toggle_projection` on numpad 5 switches between perspective and orthographic. Rebinding or unbinding them
+    /// is up to the app.
+    pub fn input(&self) -> &Input {
+        &self.input
+    }
+
+    pub fn input_mut(&mut self) -> &mut Input {
+        &mut self.input
+    }
+
+    /// Requests a frame in the focused window if its camera follows the input
+    fn redraw_moving_camera(&self) {
+        let viewport = self
+            .input
+            .focused_window()
+            .and_then(|window_id| self.viewports.get(&window_id));
+        if let Some(viewport) = viewport {
+            if viewport.camera_controller.is_moving(&self.input) {
+                viewport.window.request_redraw();
+            }
+        }
+    }
+
+    /// The console gets the event first, then the view cube, then the gizmo, then the plugins in order until one
+    /// consumes it, then the measure tool if it's on or the selection otherwise. The probe only follows the cursor, it
+    /// doesn't keep the event from the others.
+    fn dispatch_input_event(&mut self, window_id: WindowId, event: &InputEvent) -> bool {
+        let response = self.console.process_event(event);
+        if response.redraw {
+            self.request_redraw();
+        }
+        if let Some(line) = response.submitted {
+            let _ = self.run_command(&line);
+        }
+        if response.consumed {
+            return true;
+        }
+        if let Some(viewport) = self.viewports.get(&window_id) {
+            let response = self.view_cube.process_event(
+                window_id,
+                event,
+                &viewport.camera,
+                viewport.config.width,
+                viewport.config.height,
+            );
+            if response.redraw {
+                viewport.window.request_redraw();
+            }
+            if let Some(direction) = response.clicked {
+                self.animate_view(window_id, direction);
+            }
+            if response.consumed {
+                return true;
+            }
+        }
+        let view = self.viewports.get(&window_id).and_then(|viewport| {
+            OverlayView::new(
+                &viewport.camera,
+                viewport.config.width,
+                viewport.config.height,
+            )
+        });
+        let response = self.gizmo.process_event(window_id, event, view.as_ref());
+        if response.redraw {
+            self.request_redraw();
+        }
+        if response.consumed {
+            return true;
+        }
+        if self
+            .plugins
+            .iter_mut()
+            .any(|plugin| plugin.on_event(window_id, event))
+        {
+            return true;
+        }
+
+        if let Some((cursor, view)) = self.probe_tool.process_event(event).zip(view.clone()) {
+            let depth = self.read_depth_at(window_id, cursor[0] as u32, cursor[1] as u32);
+            self.probe_tool.hover(depth, cursor, view);
+        }
+        // Follows the cursor while off too, to know where it is once turned on
+        let click = self.measure_tool.process_event(window_id, event);
+        if self.measure_tool.is_enabled() {
+            if let Some((cursor, view)) = click.zip(view) {
+                let depth = self.read_depth_at(window_id, cursor[0] as u32, cursor[1] as u32);
+                self.measure_tool.add_click(depth, cursor, view);
+            }
+            return false;
+        }
+        let control =
+            self.input.pressed(KeyCode::ControlLeft) || self.input.pressed(KeyCode::ControlRight);
+        let shift =
+            self.input.pressed(KeyCode::ShiftLeft) || self.input.pressed(KeyCode::ShiftRight);
+        let response = self.selection.process_event(window_id, event, control);
+        if response.redraw {
+            self.request_redraw();
+        }
+        if let Some([x, y]) = response.pick {
+            let pick = self.pick(window_id, x, y);
+            self.selection.add_click(pick, shift);
+        }
+        if let Some(select_box) = response.select_box {
+            let inside = self.renderables_in_box(window_id, select_box);
+            self.selection.apply_box(inside, shift);
+        }
+        response.consumed
+    }
+
+    /// The renderables whose bounding box center is inside the pixel rectangle between two corners of the window
+    fn renderables_in_box(&self, window_id: WindowId, [start, end]: [[f32; 2]; 2]) -> Vec<usize> {
+        let Some(viewport) = self.viewports.get(&window_id) else {
+            return Vec::new();
+        };
+        let (width, height) = (viewport.config.width as f32, viewport.config.height as f32);
+        let (min, max) = (
+            [start[0].min(end[0]), start[1].min(end[1])],
+            [start[0].max(end[0]), start[1].max(end[1])],
+        );
+        let view_proj = viewport.camera.build_view_projection_matrix();
+        self.renderables
+            .iter()
+            .enumerate()
+            .filter(|(_, renderable)| {
+                let Some((low, high)) = self
+                    .meshes
+                    .get(renderable.mesh.0)
+                    .and_then(|mesh| mesh.bounds())
+                else {
+                    return false;
+                };
+                let center = (Vector3::from(low) + Vector3::from(high)) / 2.0;
+                let clip = view_proj * self.drawn_model(renderable) * center.extend(1.0);
+                // Behind the camera
+                if clip.w <= 0.0 {
+                    return false;
+                }
+                let x = (clip.x / clip.w + 1.0) / 2.0 * width;
+                let y = (1.0 - clip.y / clip.w) / 2.0 * height;
+                (min[0]..=max[0]).contains(&x) && (min[1]..=max[1]).contains(&y)
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Advances the engine by one frame and uploads everything the following [RenderEngine::render_frame] calls draw
+    pub fn update(&mut self) {
+        let now = self.frame_limiter.wait();
+        let delta_time = now - self.last_update;
+        let mut frame = FrameContext {
+            frame_index: self.frame.frame_index + 1,
+            delta_time,
+            smoothed_delta_time: self.delta_smoother.update(delta_time),
+            ..Default::default()
+        };
+        self.last_update = now;
+        // What the last update recorded for windows that weren't drawn in the end has to run before this one's
+        self.submit_commands();
+        self.collect_readbacks();
+        self.begin_frame_stats(&frame);
+        self.adjust_render_scale();
+        // Estimating the memory walks every texture, only worth it while the HUD shows it
+        let vram_bytes = if self.hud.is_visible() {
+            self.estimate_vram()
+        } else {
+            0
+        };
+        self.hud
+            .record_frame(&self.frame_stats, self.stats, vram_bytes);
+
+        let _span = tracing::debug_span!("update", frame = frame.frame_index).entered();
+        self.run_frame_script(frame.smoothed_delta_time);
+        #[cfg(feature = "sync")]
+        self.run_scene_sync();
+        tracing::debug_span!("simulate").in_scope(|| self.simulate(&frame));
+        tracing::debug_span!("extract").in_scope(|| self.extract(&mut frame));
+        tracing::debug_span!("prepare").in_scope(|| self.prepare(&frame));
+        let compute = tracing::debug_span!("compute").in_scope(|| self.compute(&frame));
+        tracing::debug_span!("render_targets").in_scope(|| self.render_targets(&frame, compute));
+        self.frame = frame;
+        self.input.end_frame();
+        self.frame_stats.cpu_update_time = now.elapsed();
+    }
+
+    /// Timings of the previous frames, see [crate::profiler]
+    pub fn frame_stats(&self) -> &FrameStats {
+        &self.frame_stats
+    }
+
+    /// What the previous frame recorded, over the render targets of its update and the windows drawn after it
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    /// Roughly the GPU memory taken up by meshes, textures, render targets and windows. Uniform and staging buffers,
+    /// pipelines and whatever the driver allocates on top aren't counted.
+    pub fn estimate_vram(&self) -> u64 {
+        self.meshes.size_in_bytes()
+            + self
+                .textures
+                .iter()
+                .map(|texture| texture.texture.size_in_bytes())
+                .sum::<u64>()
+            + self
+                .flipbooks
+                .iter()
+                .map(GpuFlipbook::size_in_bytes)
+                .sum::<u64>()
+            + self
+                .render_targets
+                .iter()
+                .map(RenderTarget::size_in_bytes)
+                .sum::<u64>()
+            + self
+                .viewports
+                .values()
+                .map(Viewport::size_in_bytes)
+                .sum::<u64>()
+            + self.point_clouds.size_in_bytes()
+            + self.terrains.size_in_bytes()
+            + self.reflection_probes.size_in_bytes()
+            + self.material_bindings.probes().size_in_bytes()
+            + self.irradiance.size_in_bytes()
+            + self.material_bindings.irradiance().size_in_bytes()
+            + self.vegetations.size_in_bytes()
+            + self.voxel_grids.size_in_bytes()
+            + self.vector_fields.size_in_bytes()
+            + self.isosurfaces.size_in_bytes()
+            + self.simulations.size_in_bytes()
+            + self.cloths.size_in_bytes()
+            + self.sky.size_in_bytes()
+            + self
+                .depth_pyramid_pass
+                .as_ref()
+                .map_or(0, DepthPyramidPass::size_in_bytes)
+            + self.upscaling_pass.size_in_bytes()
+            + self.flocks.size_in_bytes()
+            + self.heatmaps.size_in_bytes()
+            + self.videos_size_in_bytes()
+    }
+
+    #[cfg(feature = "ffmpeg")]
+    fn videos_size_in_bytes(&self) -> u64 {
+        self.videos.iter().map(GpuVideo::size_in_bytes).sum()
+    }
+
+    #[cfg(not(feature = "ffmpeg"))]
+    fn videos_size_in_bytes(&self) -> u64 {
+        0
+    }
+
+    /// Whether the diagnostics overlay is drawn over every window: FPS, a graph of recent frame times, CPU and GPU
+    /// time, draw calls and [RenderEngine::estimate_vram]. It shows the frames drawn so far, an idle engine doesn't
+    /// keep drawing to update it.
+    pub fn debug_hud_visible(&self) -> bool {
+        self.hud.is_visible()
+    }
+
+    pub fn set_debug_hud_visible(&mut self, visible: bool) {
+        self.hud.set_visible(visible);
+        self.request_redraw();
+    }
+
+    /// Shows the diagnostics overlay if it's hidden and hides it otherwise, the app does this on F3
+    pub fn toggle_debug_hud(&mut self) {
+        self.set_debug_hud_visible(!self.hud.is_visible());
+    }
+
+    /// Draws the chart over every window from the next frame on, under the HUD
+    pub fn add_plot(&mut self, plot: Plot) -> PlotHandle {
+        let handle = self.plots.add(&self.device, self.format, plot);
+        self.request_redraw();
+        handle
+    }
+
+    pub fn plot(&self, handle: PlotHandle) -> Option<&Plot> {
+        self.plots.get(handle)
+    }
+
+    /// For pushing values into the plot's series
+    pub fn plot_mut(&mut self, handle: PlotHandle) -> Option<&mut Plot> {
+        self.request_redraw();
+        self.plots.get_mut(handle)
+    }
+
+    pub fn remove_plot(&mut self, handle: PlotHandle) -> Option<Plot> {
+        let plot = self.plots.remove(handle)?;
+        self.request_redraw();
+        Some(plot)
+    }
+
+    /// Draws the heatmap over every window from the next frame on, under the overlays. Devices that can't blend
+    /// into half float textures don't draw it.
+    pub fn add_heatmap(&mut self, heatmap: Heatmap) -> HeatmapHandle {
+        let handle = self.heatmaps.add(
+            &self.device,
+            &self.adapter,
+            self.format,
+            &self.global_bindings,
+            heatmap,
+        );
+        self.request_redraw();
+        handle
+    }
+
+    pub fn heatmap(&self, handle: HeatmapHandle) -> Option<&Heatmap> {
+        self.heatmaps.get(handle)
+    }
+
+    /// For adding points and changing how the heatmap is drawn
+    pub fn heatmap_mut(&mut self, handle: HeatmapHandle) -> Option<&mut Heatmap> {
+        self.request_redraw();
+        self.heatmaps.get_mut(handle)
+    }
+
+    /// Stops drawing the heatmap, its points are freed on the GPU with the next frame
+    pub fn remove_heatmap(&mut self, handle: HeatmapHandle) -> Option<Heatmap> {
+        let heatmap = self.heatmaps.remove(handle)?;
+        self.request_redraw();
+        Some(heatmap)
+    }
+
+    /// The translate, rotate and scale handles drawn over every window, see [crate::gizmo]
+    pub fn gizmo(&self) -> &Gizmo {
+        &self.gizmo
+    }
+
+    pub fn gizmo_mut(&mut self) -> &mut Gizmo {
+        &mut self.gizmo
+    }
+
+    /// The selected renderables, outlined in every window
+    pub fn selection(&self) -> &Selection {
+        &self.selection
+    }
+
+    /// Changes made through this are reported like clicks are, in the next update
+    pub fn selection_mut(&mut self) -> &mut Selection {
+        &mut self.selection
+    }
+
+    /// The command console opened with the backtick key, see [crate::console]. Apps add their own commands with
+    /// [Console::register].
+    pub fn console(&self) -> &Console {
+        &self.console
+    }
+
+    pub fn console_mut(&mut self) -> &mut Console {
+        &mut self.console
+    }
+
+    /// Runs `line` as if it was entered into the console, its output goes to the console's log too
+    pub fn run_command(&mut self, line: &str) -> CommandResult {
+        let result = console::run(self, line);
+        self.request_redraw();
+        result
+    }
+
+    /// Runs the commands at the top of `script` right away and the ones in its `[frame]` section at the start of
+    /// every update from now on, see [crate::script]. Stops the script loaded before, and this one too if one of its
+    /// commands fails.
+    pub fn load_script(&mut self, script: Script) -> Result<(), ScriptError> {
+        self.stop_script();
+        let (startup, frame) = script.split();
+        startup.run(self)?;
+        self.frame_script = frame;
+        self.request_redraw();
+        Ok(())
+    }
+
+    /// Runs the statements at the top of `script` right away and its `frame` function at the start of every update
+    /// from now on, see [crate::rhai_script]. Stops the script loaded before, and this one too if it fails.
+    #[cfg(feature = "rhai")]
+    pub fn load_rhai_script(&mut self, mut script: RhaiScript) -> Result<(), ScriptError> {
+        self.stop_script();
+        script.run_startup(self)?;
+        if script.has_frame() {
+            self.rhai_script = Some(script);
+        }
+        self.request_redraw();
+        Ok(())
+    }
+
+    /// Stops running the `[frame]` section or `frame` function of the loaded script
+    pub fn stop_script(&mut self) {
+        self.frame_script = None;
+        #[cfg(feature = "rhai")]
+        {
+            self.rhai_script = None;
+        }
+    }
+
+    /// The loaded script runs every update
+    fn runs_script(&self) -> bool {
+        #[cfg(feature = "rhai")]
+        if self.rhai_script.is_some() {
+            return true;
+        }
+        self.frame_script.is_some()
+    }
+
+    /// Starts keeping the camera and selection in sync with other instances, or stops with None, see
+    /// [crate::sync]. The engine keeps drawing frames while it's on, to pick up what the others send.
+    #[cfg(feature = "sync")]
+    pub fn set_scene_sync(&mut self, scene_sync: Option<SceneSync>) {
+        self.scene_sync = scene_sync;
+        self.request_redraw();
+    }
+
+    #[cfg(feature = "sync")]
+    fn run_scene_sync(&mut self) {
+        let Some(mut scene_sync) = self.scene_sync.take() else {
+            return;
+        };
+        scene_sync.update(self);
+        self.scene_sync = Some(scene_sync);
+    }
+
+    fn is_syncing(&self) -> bool {
+        #[cfg(feature = "sync")]
+        return self.scene_sync.is_some();
+        #[cfg(not(feature = "sync"))]
+        false
+    }
+
+    /// Where the app pushes captured audio for shaders to react to, see [crate::audio]
+    pub fn audio_feed(&self) -> AudioFeed {
+        self.audio.feed()
+    }
+
+    /// Magnitudes of the frequency bands from the last update, lowest first and from 0 to 1
+    pub fn audio_bands(&self) -> &[f32; AUDIO_BANDS] {
+        self.audio.bands()
+    }
+
+    /// Loudness of the audio as a whole from the last update, from 0 to 1
+    pub fn audio_level(&self) -> f32 {
+        self.audio.level()
+    }
+
+    fn run_frame_script(&mut self, delta: Duration) {
+        #[cfg(feature = "rhai")]
+        self.run_rhai_script(delta);
+        let Some(mut script) = self.frame_script.take() else {
+            return;
+        };
+        match script.run(self, delta) {
+            // Unless it loaded another one
+            Ok(()) => {
+                self.frame_script.get_or_insert(script);
+            }
+            Err(err) => {
+                tracing::error!("Stopped the script, {err}");
+                self.console.print(&format!("Stopped the script, {err}"));
+            }
+        }
+    }
+
+    #[cfg(feature = "rhai")]
+    fn run_rhai_script(&mut self, delta: Duration) {
+        let Some(mut script) = self.rhai_script.take() else {
+            return;
+        };
+        match script.run_frame(self, delta) {
+            // Unless it loaded another one
+            Ok(()) => {
+                if self.frame_script.is_none() {
+                    self.rhai_script.get_or_insert(script);
+                }
+            }
+            Err(err) => {
+                tracing::error!("Stopped the script, {err}");
+                self.console.print(&format!("Stopped the script, {err}"));
+            }
+        }
+    }
+
+    /// Whether the cube showing which way the camera looks is drawn in the top right corner of every window, on by
+    /// default. Clicking its faces, edges and corners turns the camera to look from there.
+    pub fn view_cube_visible(&self) -> bool {
+        self.view_cube.visible
+    }
+
+    pub fn set_view_cube_visible(&mut self, visible: bool) {
+        self.view_cube.visible = visible;
+        self.request_redraw();
+    }
+
+    /// Whether windows looking through an orthographic camera get rulers along their top and left edge and a grid
+    /// across the view, on by default. See [crate::camera::orbit_camera::Projection].
+    pub fn rulers_visible(&self) -> bool {
+        self.rulers_visible
+    }
+
+    pub fn set_rulers_visible(&mut self, visible: bool) {
+        self.rulers_visible = visible;
+        self.request_redraw();
+    }
+
+    /// Turns the window's camera around its target over a moment until it looks from `direction`, pointing from the
+    /// target towards the eye. Moving the camera by hand stops the turn where it is.
+    pub fn animate_view(&mut self, window_id: WindowId, direction: Vector3<f32>) {
+        if let Some(viewport) = self.viewports.get_mut(&window_id) {
+            viewport.view_animation = Some(ViewAnimation::new(
+                &viewport.camera,
+                direction,
+                VIEW_ANIMATION_DURATION,
+            ));
+            viewport.window.request_redraw();
+        }
+    }
+
+    /// Points the window's camera at its target from `direction` right away, pointing from the target towards the
+    /// eye. Stops a turn started with [RenderEngine::animate_view].
+    pub fn snap_view(&mut self, window_id: WindowId, direction: Vector3<f32>) {
+        if let Some(viewport) = self.viewports.get_mut(&window_id) {
+            let (pitch, yaw) = orbit_angles(direction);
+            viewport.view_animation = None;
+            viewport.camera.set_pitch(pitch);
+            viewport.camera.set_yaw(yaw);
+            viewport.window.request_redraw();
+        }
+    }
+
+    /// Looks at the window camera's target from one of the standard views, turning there over a moment or snapping
+    /// as [RenderEngine::set_animate_standard_views] says
+    pub fn set_standard_view(&mut self, window_id: WindowId, view: StandardView) {
+        self.look_from(window_id, view.direction());
+    }
+
+    /// Whether [RenderEngine::set_standard_view] and its hotkeys turn the camera over a moment, on by default. Off,
+    /// the camera snaps to the view.
+    pub fn set_animate_standard_views(&mut self, animate: bool) {
+        self.animate_standard_views = animate;
+    }
+
+    pub fn animates_standard_views(&self) -> bool {
+        self.animate_standard_views
+    }
+
+    fn look_from(&mut self, window_id: WindowId, direction: Vector3<f32>) {
+        if self.animate_standard_views {
+            self.animate_view(window_id, direction);
+        } else {
+            self.snap_view(window_id, direction);
+        }
+    }
+
+    /// Runs the engine's own actions for the focused window, see [RenderEngine::input]
+    fn apply_view_actions(&mut self, window_id: WindowId) {
+        let opposite =
+            self.input.pressed(KeyCode::ControlLeft) || self.input.pressed(KeyCode::ControlRight);
+        for (action, _, view) in VIEW_ACTIONS {
+            if self.input.action_just_pressed(action) {
+                let direction = view.direction();
+                self.look_from(window_id, if opposite { -direction } else { direction });
+            }
+        }
+        if self.input.action_just_pressed(TOGGLE_PROJECTION) {
+            if let Some(viewport) = self.viewports.get_mut(&window_id) {
+                viewport.camera.toggle_projection();
+                tracing::debug!(projection = ?viewport.camera.projection, "Camera projection switched");
+                viewport.window.request_redraw();
+            }
+        }
+    }
+
+    /// Measures distances and angles between clicked points while it's on
+    pub fn measure_tool(&self) -> &MeasureTool {
+        &self.measure_tool
+    }
+
+    pub fn measure_tool_mut(&mut self) -> &mut MeasureTool {
+        &mut self.measure_tool
+    }
+
+    /// Reads the values of the volumes under the cursor while it's on
+    pub fn probe_tool(&self) -> &ProbeTool {
+        &self.probe_tool
+    }
+
+    pub fn probe_tool_mut(&mut self) -> &mut ProbeTool {
+        &mut self.probe_tool
+    }
+
+    /// Calls `callback` in every update that changed the selection, after the plugins were told
+    pub fn on_selection_changed(&mut self, callback: impl FnMut(&SelectionChange) + 'static) {
+        self.selection_callbacks.push(Box::new(callback));
+    }
+
+    /// What happens inside the engine, see [crate::events]
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub fn events_mut(&mut self) -> &mut EventBus {
+        &mut self.events
+    }
+
+    /// Picks up the GPU timings and occlusion results that arrived since the last update
+    fn collect_readbacks(&mut self) {
+        let pending = self
+            .profiler
+            .as_ref()
+            .is_some_and(GpuProfiler::has_pending_readbacks)
+            || self
+                .viewports
+                .values()
+                .any(|viewport| viewport.occlusion.is_pending())
+            || self
+                .render_targets
+                .iter()
+                .any(|target| target.occlusion.is_pending());
+        if !pending {
+            return;
+        }
+        self.device.poll(wgpu::Maintain::Poll);
+        for viewport in self.viewports.values_mut() {
+            viewport.occlusion.collect();
+        }
+        for target in &mut self.render_targets {
+            target.occlusion.collect();
+        }
+    }
+
+    /// Steps the render scale towards the target frame time of [RenderEngine::set_dynamic_resolution] by the GPU
+    /// timings that came in
+    fn adjust_render_scale(&mut self) {
+        let Some(settings) = &self.dynamic_resolution else {
+            return;
+        };
+        if self.profiler.is_none() {
+            return;
+        }
+        let scale =
+            self.resolution_controller
+                .update(settings, self.render_scale, &self.frame_stats);
+        if let Some(scale) = scale {
+            tracing::debug!("Render scale {:.2} -> {scale:.2}", self.render_scale);
+            self.set_render_scale(scale);
+        }
+    }
+
+    fn begin_frame_stats(&mut self, frame: &FrameContext) {
+        self.stats = std::mem::take(&mut self.render_stats);
+        self.frame_stats.cpu_frame_time = frame.delta_time;
+        self.frame_stats.cpu_render_time = std::mem::take(&mut self.render_time);
+
+        let Some(profiler) = &mut self.profiler else {
+            return;
+        };
+        profiler.begin_frame(frame.frame_index);
+        if let Some((frame_index, passes)) = profiler.take_finished() {
+            self.events.publish(EngineEvent::PassTimings {
+                frame_index,
+                passes: passes.clone(),
+            });
+            self.frame_stats.gpu_frame_index = frame_index;
+            self.frame_stats.gpu_passes = passes;
+        }
+    }
+
+    /// Limits how often [RenderEngine::update] starts a frame, None to run uncapped. [RenderEngine::update] sleeps
+    /// until the next frame is due, regardless of the surfaces' present mode.
+    ///
+    /// With several windows every call to update counts as a frame, so draw all windows from one update to keep
+    /// each at the target rate.
+    pub fn set_fps_cap(&mut self, target_fps: Option<f32>) {
+        self.frame_limiter.set_target_fps(target_fps);
+    }
+
+    pub fn fps_cap(&self) -> Option<f32> {
+        self.frame_limiter.target_fps()
+    }
+
+    /// The frame extracted by the last [RenderEngine::update]
+    pub fn frame(&self) -> &FrameContext {
+        &self.frame
+    }
+
+    fn simulate(&mut self, frame: &FrameContext) {
+        if self.device_lost.is_lost() {
+            self.recover_device();
+        }
+
+        #[cfg(feature = "hot-reload")]
+        self.reload_changed_assets();
+
+        self.audio.analyze(frame.delta_time);
+        self.advance_flipbooks(frame);
+        #[cfg(feature = "ffmpeg")]
+        self.advance_videos(frame);
+        self.advance_animations(frame);
+
+        let context = PluginContext {
+            device: &self.device,
+            queue: &self.queue,
+            format: self.format,
+            global_bind_group_layout: self.global_bindings.bind_group_layouts(),
+            device_report: &self.device_report,
+            jobs: &self.jobs,
+            render_targets: &self.render_targets,
+        };
+        for plugin in &mut self.plugins {
+            plugin.pre_update(&context, frame);
+        }
+
+        // Presses grabbing a gizmo handle never reach the input, so dragging one leaves the camera alone
+        let focused = self.input.focused_window();
+        if let Some(window_id) = focused {
+            self.apply_view_actions(window_id);
+        }
+        for (window_id, viewport) in &mut self.viewports {
+            if Some(*window_id) == focused {
+                // Moving the camera by hand takes over from a view animation
+                if viewport.camera_controller.is_moving(&self.input) {
+                    viewport.view_animation = None;
+                }
+                viewport
+                    .camera_controller
+                    .update(&self.input, &mut viewport.camera);
+            }
+            if let Some(animation) = &mut viewport.view_animation {
+                if animation.update(&mut viewport.camera, frame.smoothed_delta_time) {
+                    viewport.window.request_redraw();
+                } else {
+                    viewport.view_animation = None;
+                }
+            }
+            viewport.camera.update_view_proj();
+        }
+        for camera in self
+            .render_targets
+            .iter_mut()
+            .filter_map(|target| target.camera.as_mut())
+        {
+            camera.update_view_proj();
+        }
+
+        self.selection.resolve_clicks();
+        self.measure_tool.resolve_clicks();
+        self.probe_tool.update(self.isosurfaces.surfaces());
+        let change = self.selection.take_change();
+        if !change.is_empty() {
+            for plugin in &mut self.plugins {
+                plugin.on_selection_changed(&change);
+            }
+            for callback in &mut self.selection_callbacks {
+                callback(&change);
+            }
+            self.events.publish(EngineEvent::SelectionChanged(change));
+        }
+
+        #[cfg(feature = "meshlets")]
+        if let Some(meshlet_culling) = &mut self.meshlet_culling {
+            meshlet_culling.sync(&self.device, &self.queue, &self.meshes);
+        }
+    }
+
+    /// Copies what the GPU needs out of the viewports and renderables, only reading the engine
+    fn extract(&self, frame: &mut FrameContext) {
+        frame.debug_shapes = debug::take();
+        frame.clipping = ClipUniform::new(&self.clip_planes, self.clip_cap_color);
+        frame.audio = self.audio.uniform();
+        frame.cameras = self
+            .viewports
+            .iter()
+            .map(|(&window_id, viewport)| (window_id, viewport.camera.uniform))
+            .collect();
+        frame.target_cameras = self
+            .render_targets
+            .iter()
+            .enumerate()
+            .filter_map(|(index, target)| Some((index, target.camera_uniform()?)))
+            .collect();
+
+        // Renderables with handles from another engine are skipped rather than indexing out of bounds
+        for (index, renderable) in self.renderables.iter().enumerate() {
+            let Some(material) = self.materials.get(renderable.material.0) else {
+                continue;
+            };
+            let Some(mesh) = self.meshes.get(renderable.mesh.0) else {
+                continue;
+            };
+            let model = self.drawn_model(renderable);
+            let probe = if material.reflects() {
+                // The middle of the mesh, its origin may be anywhere
+                let center = mesh
+                    .bounds()
+                    .map_or(Vector3::new(0.0, 0.0, 0.0), |(min, max)| {
+                        (Vector3::from(min) + Vector3::from(max)) / 2.0
+                    });
+                let center = (model * center.extend(1.0)).truncate();
+                self.reflection_probes.nearest(center.into())
+            } else {
+                NO_PROBE
+            };
+            let slot = frame.objects.len();
+            frame.objects.push(ObjectUBOContent {
+                model: model.into(),
+                color: material.base_color,
+                // One past the draw's index, 0 is nothing
+                id: frame.draws.len() as u32 + 1,
+                reflectivity: material.reflectivity,
+                roughness: material.roughness,
+                clearcoat: material.clearcoat,
+                clearcoat_roughness: material.clearcoat_roughness,
+                ior: material.ior,
+                probe,
+                lit: material.lit_by_probes as u32,
+            });
+
+            // The bounding box gets an object slot of its own, right after the object's
+            let bounds = mesh.bounds().filter(|_| renderable.occlusion_query);
+            let occlusion = bounds.map(|bounds| {
+                let (model, min, max) = OcclusionProxy::transform(model, bounds);
+                frame.objects.push(ObjectUBOContent {
+                    model: model.into(),
+                    color: [0.0; 4],
+                    id: 0,
+                    reflectivity: 0.0,
+                    roughness: 0.0,
+                    clearcoat: 0.0,
+                    clearcoat_roughness: 0.0,
+                    ior: 1.0,
+                    probe: NO_PROBE,
+                    lit: 0,
+                });
+                OcclusionProxy {
+                    renderable: index,
+                    slot: slot + 1,
+                    min,
+                    max,
+                }
+            });
+            // Every meshlet of the mesh is culled on its own, they share the renderable's transform
+            #[cfg(feature = "meshlets")]
+            let meshlets = self
+                .meshlet_culling
+                .as_ref()
+                .and_then(|culling| culling.first_meshlet(renderable.mesh.0))
+                .map(|first_meshlet| {
+                    let transform = frame.meshlet_transforms.len() as u32;
+                    frame.meshlet_transforms.push(model.into());
+                    let first = frame.meshlet_instances.len() as u32;
+                    let count = mesh.meshlets().len() as u32;
+                    frame
+                        .meshlet_instances
+                        .extend((0..count).map(|meshlet| MeshletInstance {
+                            meshlet: first_meshlet + meshlet,
+                            transform,
+                        }));
+                    (first, count)
+                });
+            frame.draws.push(Draw {
+                renderable: index,
+                slot,
+                mesh: renderable.mesh.0,
+                texture: self.draw_texture(renderable.material.0, material),
+                occlusion,
+                #[cfg(feature = "meshlets")]
+                meshlets,
+            });
+        }
+    }
+
+    /// Uploads the extracted data
+    fn prepare(&mut self, frame: &FrameContext) {
+        if let Some(vertex_pulling) = &mut self.vertex_pulling {
+            vertex_pulling.update(&self.device, &self.meshes);
+        }
+        #[cfg(feature = "meshlets")]
+        if let Some(meshlet_culling) = &mut self.meshlet_culling {
+            meshlet_culling.upload(&self.device, &self.queue, frame);
+        }
+        self.vector_fields
+            .prepare(&self.device, &self.queue, &mut self.colormaps);
+        let moving = self.waters.prepare(
+            &self.device,
+            &self.queue,
+            self.background.background(),
+            self.sky.sun(),
+            &self.textures,
+            frame.delta_time,
+        );
+        // Moving waves are drawn again every frame
+        if moving {
+            self.request_redraw();
+        }
+        // So does vegetation in the wind
+        if self
+            .vegetations
+            .prepare(&self.device, &self.queue, frame.delta_time)
+        {
+            self.request_redraw();
+        }
+        if let Some(depth_pyramid_pass) = &mut self.depth_pyramid_pass {
+            depth_pyramid_pass.begin_frame();
+        }
+        self.upscaling_pass.begin_frame();
+        self.heatmaps
+            .prepare(&self.device, &self.queue, &mut self.colormaps);
+        self.object_bindings
+            .update(&self.device, &mut self.uploader, &frame.objects);
+        let catching_up = self.reflection_probes.prepare(
+            &self.device,
+            &self.queue,
+            &mut self.uploader,
+            self.material_bindings.probes_mut(),
+            frame.clipping,
+            frame.audio,
+        );
+        // Probes catching up with the scene a few faces at a time
+        if catching_up {
+            self.request_redraw();
+        }
+        let baking = self.irradiance.prepare(
+            &self.device,
+            &self.queue,
+            &mut self.uploader,
+            self.material_bindings.irradiance_mut(),
+            frame.clipping,
+            frame.audio,
+        );
+        // And irradiance probes, a few at a time
+        if baking {
+            self.request_redraw();
+        }
+        for (window_id, camera) in &frame.cameras {
+            if let Some(viewport) = self.viewports.get_mut(window_id) {
+                viewport.upload_camera(
+                    &mut self.uploader,
+                    &self.device,
+                    *camera,
+                    frame.clipping,
+                    frame.audio,
+                );
+            }
+        }
+        for (target, camera) in &frame.target_cameras {
+            self.render_targets[*target].upload_camera(
+                &mut self.uploader,
+                &self.device,
+                *camera,
+                frame.clipping,
+                frame.audio,
+            );
+        }
+    }
+
+    /// Lets plugins record their compute passes, None if there is nothing to run
+    fn compute(&mut self, frame: &FrameContext) -> Option<CommandBuffer> {
+        let has_compute = self
+            .device_report
+            .downlevel_flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+        let has_isosurfaces = self.isosurfaces.is_active();
+        let has_simulations = self.simulations.is_active();
+        let has_cloth = self.cloths.is_active();
+        let has_flocks = self.flocks.is_active();
+        let has_atmosphere = self.sky.is_stale();
+        if !has_compute
+            || (self.plugins.is_empty()
+                && !has_isosurfaces
+                && !has_simulations
+                && !has_cloth
+                && !has_flocks
+                && !has_atmosphere)
+        {
+            return None;
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Compute Encoder"),
+            });
+        self.isosurfaces.extract(
+            &self.device,
+            &self.queue,
+            &mut self.colormaps,
+            &mut GpuDebugScope::new(&mut encoder, "isosurfaces"),
+        );
+        // Running simulations move on every frame
+        let running = self.simulations.step(
+            &self.device,
+            &self.queue,
+            &mut self.colormaps,
+            &mut GpuDebugScope::new(&mut encoder, "simulations"),
+        );
+        if running {
+            self.request_redraw();
+        }
+        let simulating = self.cloths.step(
+            &self.device,
+            &self.queue,
+            &mut GpuDebugScope::new(&mut encoder, "cloth"),
+        );
+        if simulating {
+            self.request_redraw();
+        }
+        let flying = self.flocks.step(
+            &self.device,
+            &self.queue,
+            &mut GpuDebugScope::new(&mut encoder, "boids"),
+        );
+        if flying {
+            self.request_redraw();
+        }
+        self.sky.update(
+            &self.queue,
+            &mut GpuDebugScope::new(&mut encoder, "atmosphere"),
+        );
+        let context = PluginContext {
+            device: &self.device,
+            queue: &self.queue,
+            format: self.format,
+            global_bind_group_layout: self.global_bindings.bind_group_layouts(),
+            device_report: &self.device_report,
+            jobs: &self.jobs,
+            render_targets: &self.render_targets,
+        };
+        for (index, plugin) in self.plugins.iter_mut().enumerate() {
+            let _span = tracing::debug_span!("plugin_compute", index).entered();
+            let name = plugin.name().to_string();
+            plugin.compute(
+                &context,
+                &mut GpuDebugScope::new(&mut encoder, &name),
+                frame,
+            );
+        }
+        Some(encoder.finish())
+    }
+
+    /// Draws the scene into every render target with a camera, in the order they were added. A target showing
+    /// another target that comes later sees the previous frame's content.
+    ///
+    /// Adds the uploads of [RenderEngine::prepare] and the compute passes to the frame's commands first, all of it is
+    /// submitted together with the next window drawn, see [RenderEngine::submit_commands].
+    fn render_targets(&mut self, frame: &FrameContext, compute: Option<CommandBuffer>) {
+        self.commands
+            .push(SubmitStage::Upload, self.uploader.finish());
+        self.render_stats.upload_bytes += self.uploader.take_written_bytes();
+        self.commands.push(SubmitStage::Compute, compute);
+        // Before any view reflects or is lit by them
+        self.bake_irradiance_probes(frame);
+        self.capture_reflection_probes(frame);
+        for (target_index, camera) in &frame.target_cameras {
+            let target_index = *target_index;
+            self.render_targets[target_index].occlusion.begin(
+                &self.device,
+                frame.draws.iter().filter_map(|draw| draw.occlusion),
+                camera,
+            );
+            #[cfg(feature = "meshlets")]
+            if let Some(culling) = &self.meshlet_culling {
+                self.commands.push(
+                    SubmitStage::Compute,
+                    culling.cull(
+                        &self.device,
+                        &self.queue,
+                        &self.device_report,
+                        &mut self.render_targets[target_index].meshlets,
+                        camera,
+                    ),
+                );
+            }
+            if self.point_clouds.prepare(
+                &self.device,
+                &self.queue,
+                &mut self.render_targets[target_index].point_clouds,
+                camera,
+            ) {
+                self.request_redraw();
+            }
+            if self.terrains.prepare(
+                &self.device,
+                &self.queue,
+                &self.jobs,
+                self.sky.sun(),
+                &mut self.render_targets[target_index].terrain,
+                camera,
+            ) {
+                self.request_redraw();
+            }
+            if let Some(depth_pyramid_pass) = &self.depth_pyramid_pass {
+                let target = &mut self.render_targets[target_index];
+                self.commands.push(
+                    SubmitStage::Compute,
+                    self.vegetations.cull(
+                        &self.device,
+                        &self.queue,
+                        &mut target.vegetation,
+                        camera,
+                        depth_pyramid_pass.cull_bind_group(target.depth_pyramid.as_ref()),
+                    ),
+                );
+            }
+            self.voxel_grids.prepare(
+                &self.device,
+                &self.queue,
+                &self.samplers,
+                &mut self.render_targets[target_index].voxel_grids,
+                camera,
+            );
+            self.sky.prepare(
+                &self.device,
+                &self.queue,
+                &mut self.render_targets[target_index].atmosphere,
+                camera,
+            );
+            let target = &self.render_targets[target_index];
+            let label = format!("main_pass target {target_index}");
+            let query = self
+                .profiler
+                .as_mut()
+                .and_then(|profiler| profiler.scope(label.as_str()));
+            let (main_pass, stats) = MainPass {
+                label: &label,
+                device: &self.device,
+                pipeline: &self.pipeline,
+                meshes: &self.meshes,
+                object_bindings: &self.object_bindings,
+                material_bindings: &self.material_bindings,
+                render_targets: &self.render_targets,
+                textures: &self.textures,
+                flipbooks: &self.flipbooks,
+                #[cfg(feature = "ffmpeg")]
+                videos: &self.videos,
+                background: &self.background,
+                occlusion_pass: &self.occlusion_pass,
+                point_cloud_pass: self.point_clouds.pass(),
+                point_clouds: target.point_clouds.as_ref(),
+                vector_field_pass: self.vector_fields.pass(),
+                isosurface_pass: self.isosurfaces.pass(),
+                simulation_pass: self.simulations.pass(),
+                cloth_pass: self.cloths.pass(),
+                boids_pass: self.flocks.pass(),
+                water_pass: self.waters.pass(),
+                terrain_pass: self.terrains.pass(),
+                terrain: target.terrain.as_ref(),
+                vegetation_pass: self.vegetations.pass(),
+                vegetation: target.vegetation.as_ref(),
+                irradiance_volume_pass: self.irradiance.pass(),
+                vertex_pulling: self.vertex_pulling.as_ref().map(VertexPulling::bind_group),
+                #[cfg(feature = "meshlets")]
+                meshlet_draws: target.meshlets.as_ref().map(MeshletView::draws),
+                occlusion: &target.occlusion,
+                #[cfg(feature = "parallel-encoding")]
+                jobs: &self.jobs,
+                color: target.color_view(),
+                depth: target.depth_view(),
+                ids: target.id_view(),
+                globals: target.global_bindings.bind_groups(),
+                drawing_into: Some(target_index),
+                timestamps: self
+                    .profiler
+                    .as_ref()
+                    .zip(query)
+                    .map(|(profiler, query)| profiler.render_pass_writes(query)),
+            }
+            .encode_all(&frame.draws);
+            self.commands.push(SubmitStage::RenderTargets, main_pass);
+            self.render_stats += stats;
+            let encoder = self
+                .commands
+                .encoder(&self.device, SubmitStage::RenderTargets);
+            if let Some(depth_pyramid_pass) = &mut self.depth_pyramid_pass {
+                let target = &mut self.render_targets[target_index];
+                let size = target.size();
+                depth_pyramid_pass.build(
+                    &self.device,
+                    &self.queue,
+                    encoder,
+                    self.build_depth_pyramids,
+                    &mut target.depth_pyramid,
+                    &target.depth.view,
+                    size,
+                    camera,
+                );
+            }
+            let target = &self.render_targets[target_index];
+            self.sky.draw(
+                &self.device,
+                encoder,
+                target.atmosphere.as_ref(),
+                target.color_view(),
+                target.depth_view(),
+            );
+            self.voxel_grids.draw(
+                &self.device,
+                encoder,
+                target.voxel_grids.as_ref(),
+                target.color_view(),
+                target.depth_view(),
+            );
+        }
+        for &(target_index, _) in &frame.target_cameras {
+            self.render_targets[target_index].occlusion.resolve(
+                &self.device,
+                self.commands.encoder(&self.device, SubmitStage::Readback),
+            );
+        }
+
+        // Without a window to draw there's no render_frame to submit with
+        let drawing_windows = self
+            .viewports
+            .values()
+            .any(|viewport| !viewport.is_minimized() && viewport.surface().is_some());
+        if !drawing_windows {
+            self.submit_commands();
+        }
+    }
+
+    /// Draws the faces of the irradiance probes due this frame and projects them onto the probes' coefficients
+    fn bake_irradiance_probes(&mut self, frame: &FrameContext) {
+        let Some(irradiance_volume_pass) = self.irradiance.pass() else {
+            return;
+        };
+        if irradiance_volume_pass.due_faces().is_empty() {
+            return;
+        }
+        // Objects the probes light move around, the probes see the rest of the scene
+        let draws: Vec<Draw> = frame
+            .draws
+            .iter()
+            .filter(|draw| {
+                !self
+                    .renderables
+                    .get(draw.renderable)
+                    .and_then(|renderable| self.materials.get(renderable.material.0))
+                    .is_some_and(|material| material.lit_by_probes)
+            })
+            .copied()
+            .collect();
+        for face in irradiance_volume_pass.due_faces() {
+            let label = format!("main_pass irradiance face {face}");
+            let (main_pass, stats) = MainPass {
+                label: &label,
+                device: &self.device,
+                pipeline: &self.pipeline,
+                meshes: &self.meshes,
+                object_bindings: &self.object_bindings,
+                material_bindings: &self.material_bindings,
+                render_targets: &self.render_targets,
+                textures: &self.textures,
+                flipbooks: &self.flipbooks,
+                #[cfg(feature = "ffmpeg")]
+                videos: &self.videos,
+                background: &self.background,
+                occlusion_pass: &self.occlusion_pass,
+                point_cloud_pass: self.point_clouds.pass(),
+                point_clouds: None,
+                vector_field_pass: self.vector_fields.pass(),
+                isosurface_pass: self.isosurfaces.pass(),
+                simulation_pass: self.simulations.pass(),
+                cloth_pass: self.cloths.pass(),
+                boids_pass: self.flocks.pass(),
+                water_pass: self.waters.pass(),
+                terrain_pass: self.terrains.pass(),
+                terrain: None,
+                vegetation_pass: self.vegetations.pass(),
+                vegetation: None,
+                irradiance_volume_pass: None,
+                vertex_pulling: self.vertex_pulling.as_ref().map(VertexPulling::bind_group),
+                // Culled per view, the faces draw the meshes whole
+                #[cfg(feature = "meshlets")]
+                meshlet_draws: None,
+                occlusion: irradiance_volume_pass.occlusion(),
+                #[cfg(feature = "parallel-encoding")]
+                jobs: &self.jobs,
+                color: irradiance_volume_pass.color_view(),
+                depth: irradiance_volume_pass.depth_view(),
+                ids: irradiance_volume_pass.id_view(),
+                globals: irradiance_volume_pass.globals(face),
+                drawing_into: None,
+                timestamps: None,
+            }
+            .encode_all(&draws);
+            self.commands.push(SubmitStage::RenderTargets, main_pass);
+            self.render_stats += stats;
+            irradiance_volume_pass.copy_face(
+                self.commands
+                    .encoder(&self.device, SubmitStage::RenderTargets),
+                face,
+            );
+        }
+        irradiance_volume_pass.project(
+            self.commands
+                .encoder(&self.device, SubmitStage::RenderTargets),
+            self.material_bindings.irradiance(),
+        );
+    }
+
+    /// Draws the probes' faces due this frame into their cubes, then blurs the cubes' mip levels
+    fn capture_reflection_probes(&mut self, frame: &FrameContext) {
+        let Some(probes) = self.reflection_probes.pass() else {
+            return;
+        };
+        for &(cube, face) in probes.due_faces() {
+            let label = format!("main_pass reflection probe {cube} face {face}");
+            let (main_pass, stats) = MainPass {
+                label: &label,
+                device: &self.device,
+                pipeline: &self.pipeline,
+                meshes: &self.meshes,
+                object_bindings: &self.object_bindings,
+                material_bindings: &self.material_bindings,
+                render_targets: &self.render_targets,
+                textures: &self.textures,
+                flipbooks: &self.flipbooks,
+                #[cfg(feature = "ffmpeg")]
+                videos: &self.videos,
+                background: &self.background,
+                occlusion_pass: &self.occlusion_pass,
+                point_cloud_pass: self.point_clouds.pass(),
+                point_clouds: None,
+                vector_field_pass: self.vector_fields.pass(),
+                isosurface_pass: self.isosurfaces.pass(),
+                simulation_pass: self.simulations.pass(),
+                cloth_pass: self.cloths.pass(),
+                boids_pass: self.flocks.pass(),
+                water_pass: self.waters.pass(),
+                terrain_pass: self.terrains.pass(),
+                terrain: None,
+                vegetation_pass: self.vegetations.pass(),
+                vegetation: None,
+                irradiance_volume_pass: None,
+                vertex_pulling: self.vertex_pulling.as_ref().map(VertexPulling::bind_group),
+                // Culled per view, the faces draw the meshes whole
+                #[cfg(feature = "meshlets")]
+                meshlet_draws: None,
+                occlusion: probes.occlusion(),
+                #[cfg(feature = "parallel-encoding")]
+                jobs: &self.jobs,
+                color: probes.color_view(),
+                depth: probes.depth_view(),
+                ids: probes.id_view(),
+                globals: probes.globals(cube, face),
+                drawing_into: None,
+                timestamps: None,
+            }
+            .encode_all(&frame.draws);
+            self.commands.push(SubmitStage::RenderTargets, main_pass);
+            self.render_stats += stats;
+            probes.copy_face(
+                self.commands
+                    .encoder(&self.device, SubmitStage::RenderTargets),
+                self.material_bindings.probes(),
+                cube,
+                face,
+            );
+        }
+        probes.prefilter(
+            self.commands
+                .encoder(&self.device, SubmitStage::RenderTargets),
+            self.material_bindings.probes(),
+        );
+    }
+
+    /// Submits everything the frame recorded so far at once, then lets the subsystems that read back from it map
+    /// their buffers. The profiler's queries are resolved last since every pass before may have written some.
+    fn submit_commands(&mut self) {
+        if self.commands.is_empty() {
+            return;
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.resolve(
+                &self.device,
+                self.commands.encoder(&self.device, SubmitStage::Readback),
+            );
+        }
+        self.commands.submit(&self.queue);
+
+        self.uploader.recall();
+        for target in &mut self.render_targets {
+            target.occlusion.after_submit();
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.after_submit();
+        }
+    }
+
+    /// Resizing to zero, e.g. minimizing on Windows, suspends drawing into the window until it gets a size again.
+    /// Plugins are only told about real sizes.
+    pub fn resize(&mut self, window_id: WindowId, width: u32, height: u32) {
+        if let Some(viewport) = self.viewports.get_mut(&window_id) {
+            viewport.resize(&self.device, &self.samplers, width, height);
+            if viewport.is_minimized() {
+                return;
+            }
+            for plugin in &mut self.plugins {
+                plugin.on_resize(window_id, width, height);
+            }
+            viewport.window.request_redraw();
+            self.events.publish(EngineEvent::Resized {
+                window_id,
+                width,
+                height,
+            });
+        }
+    }
+
+    /// Replaces a lost device with a new one and rebuilds everything that lived on it: pipelines, mesh buffers
+    /// re-uploaded from their CPU-side data, every window's surface, depth buffer and bindings, and the plugins' own
+    /// resources. Cameras are kept, captures and recordings in flight are dropped since their buffers died with the
+    /// device.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn recover_device(&mut self) {
+        // The old surfaces still belong to the instance, so one of them can be used to find a compatible adapter.
+        // Headless engines take any adapter.
+        let surface = self.viewports.values().find_map(Viewport::surface);
+        if surface.is_none() && !self.viewports.is_empty() {
+            // Suspended, there's nothing to present to until the surfaces are back
+            return;
+        }
+        let (adapter, device, queue, device_report) =
+            pollster::block_on(self.device_settings.request_device(&self.instance, surface));
+        tracing::info!("Recovered from device loss:\n{device_report}");
+
+        self.device_lost = DeviceLostFlag::watch(&device);
+        self.global_bindings = GlobalBindings::new(&device);
+        self.object_bindings = ObjectBindings::new(&device);
+        // The staging buffers and recorded commands belonged to the old device
+        self.uploader = Uploader::new();
+        self.commands = FrameCommands::new();
+        self.profiler = self
+            .device_settings
+            .gpu_profiling
+            .then(|| GpuProfiler::new(&device, &queue))
+            .flatten();
+        // Samplers are shared by descriptor, those of the old device mustn't be handed out again
+        self.samplers.clear();
+        self.material_bindings =
+            MaterialBindings::new(&device, &queue, &self.samplers, self.format);
+        // The new adapter may not support it anymore, e.g. after falling back to another backend
+        let use_vertex_pulling = self.device_settings.use_vertex_pulling(&device_report);
+        self.meshes = self.meshes.recreate(&device, &queue, use_vertex_pulling);
+        self.vertex_pulling = use_vertex_pulling.then(|| VertexPulling::new(&device, &self.meshes));
+        #[cfg(feature = "meshlets")]
+        {
+            self.meshlet_culling = MeshletCulling::new(&device, &device_report);
+        }
+        self.pipeline = create_pipeline(
+            &device,
+            &load_shader_source(),
+            self.format,
+            &self.global_bindings,
+            &self.object_bindings,
+            &self.material_bindings,
+            self.vertex_pulling.as_ref(),
+        );
+        let background = self.background.background();
+        self.background = BackgroundPass::new(
+            &device,
+            self.format,
+            &self.global_bindings,
+            &self.material_bindings,
+        );
+        self.background.set_background(&queue, background);
+        self.occlusion_pass = OcclusionPass::new(
+            &device,
+            self.format,
+            &self.global_bindings,
+            &self.object_bindings,
+        );
+        self.hud.recreate(&device, self.format);
+        self.overlay = OverlayPass::new(&device, self.format, &self.global_bindings);
+        self.outline = OutlinePass::new(&device, self.format);
+        self.upscaling_pass = UpscalingPass::new(&device, self.format);
+        self.view_cube_pass = ViewCubePass::new(&device, self.format);
+        self.colormaps.recreate(&device);
+        // The views' pyramids are gone with them and built again after their next main pass
+        self.depth_pyramid_pass = DepthPyramidPass::new(&device, &self.device_report);
+        self.textures = std::mem::take(&mut self.textures)
+            .into_iter()
+            .map(|texture| {
+                GpuTexture::new(
+                    &device,
+                    &queue,
+                    &self.samplers,
+                    texture.data,
+                    &self.material_bindings,
+                )
+            })
+            .collect();
+        self.flipbooks = std::mem::take(&mut self.flipbooks)
+            .into_iter()
+            .map(|flipbook| {
+                GpuFlipbook::new(
+                    &device,
+                    &queue,
+                    &self.samplers,
+                    flipbook.frames,
+                    flipbook.storage,
+                    &self.material_bindings,
+                )
+            })
+            .collect();
+        #[cfg(feature = "ffmpeg")]
+        {
+            self.videos = std::mem::take(&mut self.videos)
+                .into_iter()
+                .map(|video| {
+                    video.recreate(&device, &queue, &self.samplers, &self.material_bindings)
+                })
+                .collect();
+        }
+        self.render_targets = self
+            .render_targets
+            .iter()
+            .map(|target| {
+                target.recreate(
+                    &device,
+                    self.format,
+                    &self.samplers,
+                    &self.material_bindings,
+                )
+            })
+            .collect();
+
+        self.viewports = std::mem::take(&mut self.viewports)
+            .into_iter()
+            .map(|(window_id, viewport)| {
+                let mut viewport = viewport.recreate(
+                    &self.instance,
+                    &device,
+                    &self.samplers,
+                    &adapter,
+                    self.format,
+                    &self.device_settings.surface_options,
+                );
+                viewport.set_transparent(&device, background.is_transparent());
+                (window_id, viewport)
+            })
+            .collect();
+
+        self.adapter = adapter;
+        self.device = device;
+        self.queue = queue;
+        self.device_report = device_report;
+        // The subsystems' passes are created again if anything was added to them, the others still once something is
+        self.point_clouds
+            .recreate(&self.device, self.format, &self.global_bindings);
+        self.voxel_grids.recreate(&self.device, self.format);
+        self.vector_fields
+            .recreate(&self.device, self.format, &self.global_bindings);
+        self.isosurfaces.recreate(
+            &self.device,
+            self.format,
+            &self.global_bindings,
+            &self.device_report,
+        );
+        self.plots.recreate(&self.device, self.format);
+        self.heatmaps.recreate(
+            &self.device,
+            &self.adapter,
+            self.format,
+            &self.global_bindings,
+        );
+        self.simulations.recreate(
+            &self.device,
+            self.format,
+            &self.global_bindings,
+            &self.device_report,
+        );
+        self.cloths.recreate(
+            &self.device,
+            self.format,
+            &self.global_bindings,
+            &self.device_report,
+        );
+        self.waters.recreate(
+            &self.device,
+            self.format,
+            &self.global_bindings,
+            &self.material_bindings,
+        );
+        self.sky
+            .recreate(&self.device, self.format, &self.device_report);
+        self.terrains
+            .recreate(&self.device, self.format, &self.global_bindings);
+        self.vegetations.recreate(
+            &self.device,
+            self.format,
+            &self.global_bindings,
+            self.depth_pyramid_pass.as_ref(),
+            &self.device_report,
+        );
+        self.reflection_probes.recreate(
+            &self.device,
+            self.format,
+            &self.samplers,
+            self.material_bindings.probes(),
+        );
+        self.irradiance.recreate(
+            &self.device,
+            self.format,
+            &self.samplers,
+            &self.global_bindings,
+            self.material_bindings.irradiance(),
+            &self.device_report,
+        );
+        self.flocks.recreate(
+            &self.device,
+            self.format,
+            &self.global_bindings,
+            &self.device_report,
+        );
+
+        let context = PluginContext {
+            device: &self.device,
+            queue: &self.queue,
+            format: self.format,
+            global_bind_group_layout: self.global_bindings.bind_group_layouts(),
+            device_report: &self.device_report,
+            jobs: &self.jobs,
+            render_targets: &self.render_targets,
+        };
+        for plugin in &mut self.plugins {
+            plugin.init(&context);
+        }
+        #[cfg(feature = "egui")]
+        self.egui.init(&context);
+        self.events.publish(EngineEvent::DeviceLost);
+    }
+
+    /// Requesting a new device means waiting on the browser, which the frame loop can't do. A lost WebGL context is
+    /// commonly answered by reloading the page anyway.
+    #[cfg(target_arch = "wasm32")]
+    fn recover_device(&mut self) {
+        panic!("GPU device lost, reload the page to continue!");
+    }
+
+    /// Swaps in new GPU objects for every watched asset that changed on disk since the last frame.
+    ///
+    /// The engine keeps owning the same fields and handles, so everything that refers to them picks up the new version
+    /// on the next draw. If the new version fails to load or validate the old one is kept and the error is printed.
+    #[cfg(feature = "hot-reload")]
+    fn reload_changed_assets(&mut self) {
+        let Some(watcher) = &self.asset_watcher else {
+            return;
+        };
+        let shader_path = std::path::Path::new(SHADER_PATH).canonicalize().ok();
+        for path in watcher.poll_changes() {
+            let reloaded = if Some(&path) == shader_path.as_ref() {
+                self.reload_shader(&path)
+            } else {
+                match self.watched_assets.get(&path) {
+                    Some(&WatchedAsset::Texture(texture)) => self.reload_texture(texture, &path),
+                    Some(WatchedAsset::Model(_)) => self.reload_model(&path),
+                    None => continue,
+                }
+            };
+            if reloaded {
+                tracing::info!("Reloaded {}", path.display());
+                self.request_redraw();
+                self.events.publish(EngineEvent::AssetReloaded(path));
+            }
+        }
+    }
+
+    /// Without a watcher there's nothing to poll for
+    #[cfg(feature = "hot-reload")]
+    fn watches_assets(&self) -> bool {
+        self.asset_watcher.is_some()
+    }
+
+    #[cfg(not(feature = "hot-reload"))]
+    fn watches_assets(&self) -> bool {
+        false
+    }
+
+    #[cfg(feature = "hot-reload")]
+    fn watch_asset(&mut self, path: &std::path::Path, asset: WatchedAsset) {
+        let Some(watcher) = &mut self.asset_watcher else {
+            return;
+        };
+        match watcher.watch(path) {
+            Ok(path) => {
+                self.watched_assets.insert(path, asset);
+            }
+            Err(err) => tracing::warn!("Not watching {}: {err}", path.display()),
+        }
+    }
+
+    #[cfg(feature = "hot-reload")]
+    fn reload_shader(&mut self, path: &std::path::Path) -> bool {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                tracing::error!("Failed to read {}: {err}", path.display());
+                return false;
+            }
+        };
+
+        // Catch validation errors ourselves, otherwise wgpu's default handler panics on a typo in the shader
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let pipeline = create_pipeline(
+            &self.device,
+            &source,
+            self.format,
+            &self.global_bindings,
+            &self.object_bindings,
+            &self.material_bindings,
+            self.vertex_pulling.as_ref(),
+        );
+        match pollster::block_on(self.device.pop_error_scope()) {
+            Some(err) => {
+                tracing::error!("Shader reload failed, keeping previous version: {err}");
+                false
+            }
+            None => {
+                self.pipeline = pipeline;
+                true
+            }
+        }
+    }
+
+    #[cfg(feature = "hot-reload")]
+    fn reload_texture(&mut self, texture: TextureHandle, path: &std::path::Path) -> bool {
+        let data = match crate::assets::read_image(path) {
+            Ok(data) => data,
+            Err(err) => {
+                tracing::error!(
+                    "Failed to read {}, keeping previous version: {err}",
+                    path.display()
+                );
+                return false;
+            }
+        };
+        self.textures[texture.0] = GpuTexture::new(
+            &self.device,
+            &self.queue,
+            &self.samplers,
+            data,
+            &self.material_bindings,
+        );
+        true
+    }
+
+    /// Parts that are still there keep their handles, added ones get new handles and removed ones are freed. The
+    /// engine's renderables are remapped to match.
+    #[cfg(feature = "hot-reload")]
+    fn reload_model(&mut self, path: &std::path::Path) -> bool {
+        use cgmath::SquareMatrix;
+
+        let mut model = match crate::assets::read_model(path) {
+            Ok(model) => model,
+            Err(err) => {
+                tracing::error!(
+                    "Failed to read {}, keeping previous version: {err}",
+                    path.display()
+                );
+                return false;
+            }
+        };
+        let Some(WatchedAsset::Model(old)) = self.watched_assets.remove(path) else {
+            return false;
+        };
+        model.upload_textures(self);
+
+        let mut renderables = std::mem::take(&mut self.renderables);
+        // Where the app placed the model, for the parts that weren't there before
+        let placements: Vec<Matrix4<f32>> = old
+            .first()
+            .and_then(|&(mesh, _, transform)| {
+                let inverse = transform.invert()?;
+                Some(
+                    renderables
+                        .iter()
+                        .filter(|renderable| renderable.mesh == mesh)
+                        .map(|renderable| renderable.model * inverse)
+                        .collect(),
+                )
+            })
+            .unwrap_or_default();
+
+        let mut parts = Vec::with_capacity(model.parts.len());
+        for (index, part) in model.parts.into_iter().enumerate() {
+            match old.get(index) {
+                Some(&(mesh, material, transform)) => {
+                    self.meshes
+                        .replace(&self.device, &self.queue, mesh.0, part.mesh);
+                    self.set_material(material, part.material);
+                    if let Some(inverse) = transform.invert() {
+                        for renderable in renderables
+                            .iter_mut()
+                            .filter(|renderable| renderable.mesh == mesh)
+                        {
+                            renderable.model = renderable.model * inverse * part.transform;
+                        }
+                    }
+                    parts.push((mesh, material, part.transform));
+                }
+                None => {
+                    let mesh = self.add_mesh(part.mesh);
+                    let material = self.add_material(part.material);
+                    renderables.extend(placements.iter().map(|placement| Renderable {
+                        mesh,
+                        material,
+                        model: placement * part.transform,
+                        occlusion_query: false,
+                    }));
+                    parts.push((mesh, material, part.transform));
+                }
+            }
+        }
+        for &(mesh, ..) in old.iter().skip(parts.len()) {
+            self.meshes.remove(mesh.0);
+            renderables.retain(|renderable| renderable.mesh != mesh);
+        }
+        self.renderables = renderables;
+        self.watched_assets
+            .insert(path.to_path_buf(), WatchedAsset::Model(parts));
+        true
+    }
+}
+
+/// Actions the engine binds itself and handles for the focused window, see [RenderEngine::input]. Held with control,
+/// the camera looks from the opposite side instead.
+const VIEW_ACTIONS: [(&str, KeyCode, StandardView); 4] = [
+    ("view_front", KeyCode::Numpad1, StandardView::Front),
+    ("view_right", KeyCode::Numpad3, StandardView::Right),
+    ("view_top", KeyCode::Numpad7, StandardView::Top),
+    ("view_isometric", KeyCode::Numpad0, StandardView::Isometric),
+];
+/// Switches the focused window's camera between perspective and orthographic
+const TOGGLE_PROJECTION: &str = "toggle_projection";
+
+/// With the engine's own actions bound
+fn default_input() -> Input {
+    let mut input = Input::new();
+    for (action, key, _) in VIEW_ACTIONS {
+        input.bind(action, key);
+    }
+    input.bind(TOGGLE_PROJECTION, KeyCode::Numpad5);
+    input
+}
+
+/// How often [RenderEngine::poll_background] wakes the event loop while waiting on readbacks or watching assets
+const BACKGROUND_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Location of the main shader on disk, watched when hot reloading is enabled
+#[cfg(feature = "hot-reload")]
+const SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader.wgsl");
+
+/// With hot reloading the shader is read from disk so edits show up without recompiling, otherwise it is baked in.
+fn load_shader_source() -> std::borrow::Cow<'static, str> {
+    #[cfg(feature = "hot-reload")]
+    if let Ok(source) = std::fs::read_to_string(SHADER_PATH) {
+        return source.into();
+    }
+    include_str!("shader.wgsl").into()
+}
+
+fn create_pipeline(
+    device: &Device,
+    shader_source: &str,
+    format: TextureFormat,
+    global_bindings: &GlobalBindings,
+    object_bindings: &ObjectBindings,
+    material_bindings: &MaterialBindings,
+    vertex_pulling: Option<&VertexPulling>,
+) -> RenderPipeline {
+    let _span = tracing::debug_span!("create_pipeline").entered();
+    let shader_source = match vertex_pulling {
+        Some(_) => format!("{shader_source}\n{}", vertex_pulling::SHADER_SOURCE),
+        None => shader_source.to_string(),
+    };
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Shader"),
+        source: wgpu::ShaderSource::Wgsl(shader_source.into()),
+    });
+
+    let mut bind_group_layouts = vec![
+        global_bindings.bind_group_layouts(),
+        object_bindings.bind_group_layouts(),
+        material_bindings.bind_group_layouts(),
+    ];
+    bind_group_layouts.extend(vertex_pulling.map(VertexPulling::bind_group_layouts));
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+    // Pulled vertices come out of a storage buffer, there's no vertex buffer to describe
+    let (entry_point, buffers) = match vertex_pulling {
+        Some(_) => ("vs_pulled", &[][..]),
+        None => ("vs_main", &[Vertex::desc()][..]),
+    };
+
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: None,
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some(entry_point),
+            buffers,
+            compilation_options: Default::default(),
+        },
+        primitive: wgpu::PrimitiveState {
+            // Meshes are indexed as separate triangles
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            // Meshes are wound counter-clockwise seen from outside, clip plane caps tell the inside by it
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+            unclipped_depth: false,
+        },
+        depth_stencil: Some(DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: texture::Texture::ID_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
+            compilation_options: Default::default(),
+        }),
+        multiview: None,
+        cache: None,
+    })
 }
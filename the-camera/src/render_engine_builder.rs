@@ -0,0 +1,396 @@
+use std::{fmt, sync::Arc};
+
+use wgpu::{
+    Adapter, AdapterInfo, Backends, Device, DownlevelFlags, Features, Instance, Limits,
+    PowerPreference, PresentMode, Queue, Surface, TextureFormat,
+};
+
+use crate::{
+    config::{CameraConfig, EngineConfig},
+    platform::SurfaceProvider,
+    render_engine::RenderEngine,
+    vertex_pulling::VertexPulling,
+    viewport::SurfaceOptions,
+};
+
+/// Configures how the [RenderEngine] picks its adapter and device.
+///
+/// Required features and limits make [RenderEngineBuilder::build] panic when the adapter can't provide them, optional
+/// features are enabled when available. What was actually obtained ends up in the engine's [DeviceReport].
+///
+/// The engine keeps a copy around so it can request an equivalent device again after the old one was lost.
+#[derive(Clone)]
+pub struct RenderEngineBuilder {
+    backends: Backends,
+    power_preference: PowerPreference,
+    adapter_name: Option<String>,
+    required_features: Features,
+    optional_features: Features,
+    limits: Limits,
+    push_constant_size: u32,
+    pub(crate) gpu_profiling: bool,
+    pub(crate) vertex_pulling: bool,
+    pub(crate) surface_options: SurfaceOptions,
+    /// Applied to the camera of every window the engine gets
+    pub(crate) camera_defaults: CameraConfig,
+    pub(crate) fps_cap: Option<f32>,
+    pub(crate) job_threads: Option<usize>,
+}
+
+impl Default for RenderEngineBuilder {
+    fn default() -> Self {
+        RenderEngineBuilder {
+            backends: Backends::all(),
+            power_preference: PowerPreference::default(),
+            adapter_name: None,
+            required_features: Features::default(),
+            optional_features: Features::empty(),
+            // WebGL2 can't provide the downlevel defaults, e.g. it has no storage buffers in vertex shaders
+            limits: if cfg!(target_arch = "wasm32") {
+                Limits::downlevel_webgl2_defaults()
+            } else {
+                Limits {
+                    max_texture_dimension_2d: 4096, // Allow higher resolutions on native
+                    ..Limits::downlevel_defaults()
+                }
+            },
+            push_constant_size: 0,
+            gpu_profiling: false,
+            vertex_pulling: false,
+            surface_options: SurfaceOptions::default(),
+            camera_defaults: CameraConfig::default(),
+            fps_cap: None,
+            job_threads: None,
+        }
+    }
+}
+
+impl RenderEngineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restrict adapter selection to these backends
+    pub fn backends(mut self, backends: Backends) -> Self {
+        self.backends = backends;
+        self
+    }
+
+    /// Prefer the integrated (LowPower) or discrete (HighPerformance) GPU
+    pub fn power_preference(mut self, power_preference: PowerPreference) -> Self {
+        self.power_preference = power_preference;
+        self
+    }
+
+    /// Pick the first adapter whose name contains `name` (case insensitive), e.g. "nvidia" or "llvmpipe".
+    /// Falls back to the power preference if no such adapter can present to the window. Ignored on the web.
+    pub fn adapter_name(mut self, name: impl Into<String>) -> Self {
+        self.adapter_name = Some(name.into());
+        self
+    }
+
+    /// Features the engine can't run without
+    pub fn required_features(mut self, features: Features) -> Self {
+        self.required_features |= features;
+        self
+    }
+
+    /// Features that are enabled if the adapter supports them, e.g. [Features::POLYGON_MODE_LINE] or
+    /// [Features::TIMESTAMP_QUERY]. Check [DeviceReport::features] for the ones that were enabled.
+    pub fn optional_features(mut self, features: Features) -> Self {
+        self.optional_features |= features;
+        self
+    }
+
+    /// Limits the device has to support
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Enables [Features::PUSH_CONSTANTS] with room for `size` bytes where the adapter has it. Check
+    /// [crate::wgpu_utils::push_constants::PushConstants::is_native] for whether it got them.
+    pub fn push_constant_size(mut self, size: u32) -> Self {
+        self.push_constant_size = self.push_constant_size.max(size);
+        self
+    }
+
+    /// Times every pass with GPU timestamp queries where the adapter has [Features::TIMESTAMP_QUERY], see
+    /// [crate::profiler]. Plugin passes are only timed with [Features::TIMESTAMP_QUERY_INSIDE_ENCODERS].
+    pub fn gpu_profiling(mut self, enabled: bool) -> Self {
+        self.gpu_profiling = enabled;
+        if enabled {
+            self.optional_features |=
+                Features::TIMESTAMP_QUERY | Features::TIMESTAMP_QUERY_INSIDE_ENCODERS;
+        }
+        self
+    }
+
+    /// Has the vertex shader fetch vertices out of a storage buffer by index instead of reading them through vertex
+    /// buffer layouts. Meant for experimenting with layouts fixed function vertex input can't express. Devices whose
+    /// vertex shaders can't read storage buffers, e.g. WebGL2, keep using vertex buffers.
+    pub fn vertex_pulling(mut self, enabled: bool) -> Self {
+        self.vertex_pulling = enabled;
+        self
+    }
+
+    /// Whether vertex pulling was asked for and the device can do it
+    pub(crate) fn use_vertex_pulling(&self, device_report: &DeviceReport) -> bool {
+        if !self.vertex_pulling {
+            return false;
+        }
+        let supported = VertexPulling::is_supported(device_report);
+        if !supported {
+            tracing::warn!("Vertex pulling is not supported by the device, using vertex buffers");
+        }
+        supported
+    }
+
+    pub fn surface_options(mut self, surface_options: SurfaceOptions) -> Self {
+        self.surface_options = surface_options;
+        self
+    }
+
+    /// How the camera of every window starts out
+    pub fn camera_defaults(mut self, camera_defaults: CameraConfig) -> Self {
+        self.camera_defaults = camera_defaults;
+        self
+    }
+
+    /// See [RenderEngine::set_fps_cap]
+    pub fn fps_cap(mut self, fps_cap: Option<f32>) -> Self {
+        self.fps_cap = fps_cap;
+        self
+    }
+
+    /// Threads of the engine's [crate::jobs::JobSystem], None for one per core. Ignored on wasm.
+    pub fn job_threads(mut self, threads: Option<usize>) -> Self {
+        self.job_threads = threads;
+        self
+    }
+
+    /// Takes over everything set in `config`. Builder calls after this one override it.
+    pub fn config(mut self, config: &EngineConfig) -> Self {
+        let graphics = &config.graphics;
+        if let Some(vsync) = graphics.vsync {
+            self.surface_options = self.surface_options.present_mode(if vsync {
+                PresentMode::AutoVsync
+            } else {
+                PresentMode::AutoNoVsync
+            });
+        }
+        if let Some(frames) = graphics.frame_latency {
+            self.surface_options = self.surface_options.frame_latency(frames);
+        }
+        if let Some(samples @ 2..) = graphics.msaa_samples {
+            tracing::warn!("MSAA is not supported yet, ignoring msaa_samples = {samples}");
+        }
+        if let Some(fps_cap) = graphics.fps_cap {
+            self.fps_cap = Some(fps_cap);
+        }
+        if let Some(backend) = graphics.backend {
+            self.backends = backend.backends();
+        }
+        if let Some(power_preference) = graphics.power_preference {
+            self.power_preference = power_preference.into();
+        }
+        if let Some(adapter) = &graphics.adapter {
+            self.adapter_name = Some(adapter.clone());
+        }
+        if let Some(gpu_profiling) = graphics.gpu_profiling {
+            self = self.gpu_profiling(gpu_profiling);
+        }
+        if let Some(vertex_pulling) = graphics.vertex_pulling {
+            self = self.vertex_pulling(vertex_pulling);
+        }
+        if let Some(threads) = config.jobs.threads {
+            self.job_threads = Some(threads);
+        }
+        self.camera_defaults = config.camera;
+        self
+    }
+
+    /// Creates the device and the engine with `window` as its first viewport
+    pub async fn build(self, window: Arc<impl SurfaceProvider>) -> RenderEngine {
+        let window: Arc<dyn SurfaceProvider> = window;
+        let instance = Instance::new(wgpu::InstanceDescriptor {
+            backends: self.backends,
+            ..Default::default()
+        });
+        let surface = instance.create_surface(window.clone()).unwrap();
+
+        let (adapter, device, queue, report) = self.request_device(&instance, Some(&surface)).await;
+
+        // The first window decides the swapchain format for every window, since they all share the pipelines
+        let format = self
+            .surface_options
+            .select_format(&surface.get_capabilities(&adapter));
+
+        let mut engine = RenderEngine::from_device(
+            instance,
+            adapter,
+            device,
+            queue,
+            format,
+            self.clone(),
+            report,
+        );
+        engine.insert_viewport(surface, window);
+        engine
+    }
+
+    /// Creates the device and an engine without any window, drawing only into render targets of `format`. Meant for
+    /// offline rendering and tests, see [crate::snapshot].
+    pub async fn build_headless(self, format: TextureFormat) -> RenderEngine {
+        let instance = Instance::new(wgpu::InstanceDescriptor {
+            backends: self.backends,
+            ..Default::default()
+        });
+
+        let (adapter, device, queue, report) = self.request_device(&instance, None).await;
+
+        RenderEngine::from_device(
+            instance,
+            adapter,
+            device,
+            queue,
+            format,
+            self.clone(),
+            report,
+        )
+    }
+
+    /// Picks an adapter that can present to `surface`, if there is one, and creates a device on it with the
+    /// configured features and limits
+    pub(crate) async fn request_device(
+        &self,
+        instance: &Instance,
+        surface: Option<&Surface<'_>>,
+    ) -> (Adapter, Device, Queue, DeviceReport) {
+        let adapter = self.select_adapter(instance, surface).await;
+
+        let descriptor = self.device_descriptor(&adapter);
+        // Vulkan devices are opened with the extensions render targets are exported with where there are some
+        #[cfg(all(feature = "dmabuf", target_os = "linux"))]
+        let exporting = crate::dmabuf::request_device(&adapter, &descriptor);
+        #[cfg(not(all(feature = "dmabuf", target_os = "linux")))]
+        let exporting = None;
+        let (device, queue) = match exporting {
+            Some(opened) => opened,
+            None => adapter
+                .request_device(&descriptor, None)
+                .await
+                .expect("Failed to request a device!"),
+        };
+
+        let report = self.device_report(&adapter, &device);
+        (adapter, device, queue, report)
+    }
+
+    /// The configured features and limits, checked against what `adapter` can do
+    pub(crate) fn device_descriptor(&self, adapter: &Adapter) -> wgpu::DeviceDescriptor<'static> {
+        let missing_features = self.required_features - adapter.features();
+        assert!(
+            missing_features.is_empty(),
+            "Adapter {} is missing required features {missing_features:?}!",
+            adapter.get_info().name
+        );
+        assert!(
+            self.limits.check_limits(&adapter.limits()),
+            "Adapter {} does not support the required limits!",
+            adapter.get_info().name
+        );
+        let mut features = self.required_features | (self.optional_features & adapter.features());
+        let mut limits = self.limits.clone();
+        let push_constants_available = adapter.features().contains(Features::PUSH_CONSTANTS)
+            && adapter.limits().max_push_constant_size >= self.push_constant_size;
+        if self.push_constant_size > 0 && push_constants_available {
+            features |= Features::PUSH_CONSTANTS;
+            limits.max_push_constant_size =
+                limits.max_push_constant_size.max(self.push_constant_size);
+        }
+
+        wgpu::DeviceDescriptor {
+            label: Some("WGPU Device"),
+            required_features: features,
+            required_limits: limits,
+            memory_hints: wgpu::MemoryHints::default(),
+        }
+    }
+
+    pub(crate) fn device_report(&self, adapter: &Adapter, device: &Device) -> DeviceReport {
+        DeviceReport {
+            adapter_info: adapter.get_info(),
+            features: device.features(),
+            missing_optional_features: self.optional_features - device.features(),
+            limits: device.limits(),
+            downlevel_flags: adapter.get_downlevel_capabilities().flags,
+        }
+    }
+
+    async fn select_adapter(&self, instance: &Instance, surface: Option<&Surface<'_>>) -> Adapter {
+        // Browsers only hand out the adapter they pick themselves
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(name) = &self.adapter_name {
+            let name = name.to_lowercase();
+            let adapter = instance
+                .enumerate_adapters(self.backends)
+                .into_iter()
+                .find(|adapter| {
+                    adapter.get_info().name.to_lowercase().contains(&name)
+                        && surface.is_none_or(|surface| adapter.is_surface_supported(surface))
+                });
+            match adapter {
+                Some(adapter) => return adapter,
+                None => tracing::warn!("No adapter matching \"{name}\", using the default instead"),
+            }
+        }
+
+        instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: self.power_preference,
+                compatible_surface: surface,
+                force_fallback_adapter: false,
+            })
+            .await
+            .expect("Failed to request adapter!")
+    }
+}
+
+/// What the [RenderEngineBuilder] actually obtained from the system
+#[derive(Debug, Clone)]
+pub struct DeviceReport {
+    pub adapter_info: AdapterInfo,
+    /// All features enabled on the device, required and optional
+    pub features: Features,
+    /// Optional features the adapter couldn't provide
+    pub missing_optional_features: Features,
+    pub limits: Limits,
+    /// Capabilities missing from weaker backends like WebGL2, e.g. compute shaders or indirect draws
+    pub downlevel_flags: DownlevelFlags,
+}
+
+impl fmt::Display for DeviceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let info = &self.adapter_info;
+        writeln!(
+            f,
+            "Adapter: {} ({:?}, {:?})",
+            info.name, info.backend, info.device_type
+        )?;
+        writeln!(f, "Driver: {} {}", info.driver, info.driver_info)?;
+        writeln!(f, "Features: {:?}", self.features)?;
+        if !self.missing_optional_features.is_empty() {
+            writeln!(
+                f,
+                "Unavailable optional features: {:?}",
+                self.missing_optional_features
+            )?;
+        }
+        write!(
+            f,
+            "Max texture size: {}, max bind groups: {}",
+            self.limits.max_texture_dimension_2d, self.limits.max_bind_groups
+        )
+    }
+}
@@ -0,0 +1,214 @@
+use wgpu::{Device, TextureFormat, TextureView};
+
+use crate::{
+    audio::AudioUniform,
+    camera::{camera::CameraUniform, orbit_camera::OrbitCamera},
+    clipping::ClipUniform,
+    depth_pyramid::DepthPyramid,
+    global_bindings::{update_global_ubo, GlobalBindings, GlobalUBO},
+    material_bindings::MaterialBindings,
+    occlusion::OcclusionQueries,
+    texture,
+    wgpu_utils::{resource_cache::SamplerCache, uploader::Uploader},
+};
+
+/// Refers to a target created with [crate::render_engine::RenderEngine::add_render_target]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderTargetHandle(pub(crate) usize);
+
+/// An offscreen color and depth texture pair that materials can sample, e.g. for security camera views, mirrors
+/// or UI thumbnails.
+///
+/// With a camera the engine draws the scene into it during every update, before any window. Plugins can draw
+/// into it either way through [RenderTarget::color_view] and [RenderTarget::depth_view].
+pub struct RenderTarget {
+    color: texture::Texture,
+    pub(crate) depth: texture::Texture,
+    /// The main pass writes object IDs for picking into every view, targets included
+    ids: texture::Texture,
+    width: u32,
+    height: u32,
+
+    /// Where the scene is drawn from, None to only show what custom passes draw or an eye's view
+    pub camera: Option<OrbitCamera>,
+    /// Drawn from without a camera, for one eye of a headset, see [crate::xr]
+    pub(crate) eye: Option<CameraUniform>,
+    global_ubo: GlobalUBO,
+    pub(crate) global_bindings: GlobalBindings,
+    /// Binds the color texture for materials
+    pub(crate) material_bind_group: wgpu::BindGroup,
+    pub(crate) occlusion: OcclusionQueries,
+    #[cfg(feature = "meshlets")]
+    pub(crate) meshlets: Option<crate::meshlet::MeshletView>,
+    /// Created with the first point cloud drawn into it
+    pub(crate) point_clouds: Option<crate::point_cloud::PointCloudView>,
+    /// Created with the first terrain drawn into it
+    pub(crate) terrain: Option<crate::terrain::TerrainView>,
+    /// Created with the first vegetation drawn into it
+    pub(crate) vegetation: Option<crate::vegetation::VegetationView>,
+    /// Created with the first voxel grid drawn into it
+    pub(crate) voxel_grids: Option<crate::voxel_grid::VoxelGridView>,
+    /// Created with the first frame drawn with an atmosphere
+    pub(crate) atmosphere: Option<crate::atmosphere::AtmosphereView>,
+    /// Built after every main pass while [crate::render_engine::RenderEngine::builds_depth_pyramids]
+    pub(crate) depth_pyramid: Option<DepthPyramid>,
+}
+
+impl RenderTarget {
+    /// `format` has to be the engine's swapchain format, so the scene pipelines can draw into it
+    pub(crate) fn new(
+        device: &Device,
+        format: TextureFormat,
+        width: u32,
+        height: u32,
+        camera: Option<OrbitCamera>,
+        samplers: &SamplerCache,
+        material_bindings: &MaterialBindings,
+    ) -> Self {
+        let color = texture::Texture::create_render_target(
+            device,
+            samplers,
+            width,
+            height,
+            format,
+            "render_target",
+        );
+        let depth = texture::Texture::create_depth_texture_with_size(
+            device,
+            samplers,
+            width,
+            height,
+            "render_target_depth",
+        );
+        let ids = texture::Texture::create_id_texture(
+            device,
+            samplers,
+            width,
+            height,
+            "render_target_ids",
+        );
+
+        let global_ubo = GlobalUBO::new(device);
+        let mut global_bindings = GlobalBindings::new(device);
+        global_bindings.create_bind_group(device, &global_ubo);
+        let material_bind_group = material_bindings.create_bind_group(device, &color);
+
+        RenderTarget {
+            color,
+            depth,
+            ids,
+            width,
+            height,
+
+            camera,
+            eye: None,
+            global_ubo,
+            global_bindings,
+            material_bind_group,
+            occlusion: OcclusionQueries::new(),
+            #[cfg(feature = "meshlets")]
+            meshlets: None,
+            point_clouds: None,
+            terrain: None,
+            vegetation: None,
+            voxel_grids: None,
+            atmosphere: None,
+            depth_pyramid: None,
+        }
+    }
+
+    pub fn color_view(&self) -> &TextureView {
+        &self.color.view
+    }
+
+    /// For hosts drawing with the engine's device, e.g. to show the target in their own UI
+    pub fn color_texture(&self) -> &wgpu::Texture {
+        &self.color.texture
+    }
+
+    /// Draws into `color` from now on, a texture of the same size and format created elsewhere
+    #[cfg(all(feature = "dmabuf", target_os = "linux"))]
+    pub(crate) fn replace_color(
+        &mut self,
+        device: &Device,
+        color: texture::Texture,
+        material_bindings: &MaterialBindings,
+    ) {
+        self.material_bind_group = material_bindings.create_bind_group(device, &color);
+        self.color = color;
+    }
+
+    pub fn depth_view(&self) -> &TextureView {
+        &self.depth.view
+    }
+
+    /// Of the depth buffer as the last main pass drew it, for plugins marching or culling against it. None while
+    /// [crate::render_engine::RenderEngine::builds_depth_pyramids] is off or before the first main pass.
+    pub fn depth_pyramid(&self) -> Option<&DepthPyramid> {
+        self.depth_pyramid.as_ref()
+    }
+
+    pub(crate) fn id_view(&self) -> &TextureView {
+        &self.ids.view
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Roughly what the target's textures take up in memory
+    pub fn size_in_bytes(&self) -> u64 {
+        self.color.size_in_bytes()
+            + self.depth.size_in_bytes()
+            + self.ids.size_in_bytes()
+            + self
+                .depth_pyramid
+                .as_ref()
+                .map_or(0, DepthPyramid::size_in_bytes)
+    }
+
+    /// What the scene is drawn from this update, if anything
+    pub(crate) fn camera_uniform(&self) -> Option<CameraUniform> {
+        self.camera.map(|camera| camera.uniform).or(self.eye)
+    }
+
+    pub(crate) fn upload_camera(
+        &mut self,
+        uploader: &mut Uploader,
+        device: &Device,
+        camera: CameraUniform,
+        clipping: ClipUniform,
+        audio: AudioUniform,
+    ) {
+        update_global_ubo(
+            &mut self.global_ubo,
+            uploader,
+            device,
+            camera,
+            clipping,
+            audio,
+        );
+    }
+
+    /// Creates the textures again on a new device, keeping size and camera or eye
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn recreate(
+        &self,
+        device: &Device,
+        format: TextureFormat,
+        samplers: &SamplerCache,
+        material_bindings: &MaterialBindings,
+    ) -> Self {
+        let mut target = RenderTarget::new(
+            device,
+            format,
+            self.width,
+            self.height,
+            self.camera,
+            samplers,
+            material_bindings,
+        );
+        target.eye = self.eye;
+        target
+    }
+}
@@ -0,0 +1,337 @@
+//! [rhai] scripts, for demos that need more than the line by line commands of [crate::script]: variables, loops and
+//! functions. They're loaded with [crate::render_engine::RenderEngine::load_rhai_script] or the console's `exec`
+//! command with a `.rhai` file.
+//!
+//! The statements at the top run once when the script is loaded. A `frame` function, if the script defines one, is
+//! called at the start of every update until another script is loaded, with the seconds since the script was loaded
+//! and since the last update:
+//!
+//! ```text
+//! let cube = add_cube(0, 1, 0);
+//! set_material_color(0, 1.0, 0.5, 0.2);
+//!
+//! fn frame(time, delta) {
+//!     set_camera_yaw(time * 20);
+//!     move_renderable(0, 0, 1 + sin(time), 0);
+//! }
+//! ```
+//!
+//! The camera functions act on the focused window like the console does, angles are in degrees:
+//! `camera_distance`, `camera_pitch`, `camera_yaw`, their `set_` counterparts, `set_camera_fov` and
+//! `set_camera_target(x, y, z)`. Materials are changed by index with `set_material_color(index, r, g, b)` with an
+//! optional alpha, `set_material_roughness` and `set_material_reflectivity`. The scene has `renderable_count`,
+//! `add_cube(x, y, z)` returning the new renderable's index, `move_renderable(index, x, y, z)` and
+//! `set_renderable_material(index, material)`. Everything else the console can do is there through
+//! `command("...")`, which returns what the command printed, and `print` writes to the console's log.
+
+use std::{cell::Cell, ptr::NonNull};
+
+use cgmath::{Deg, Matrix4, Vector3, Vector4};
+use rhai::{CallFnOptions, Dynamic, EvalAltResult, Position, Scope, AST};
+use web_time::Duration;
+
+use crate::{
+    console,
+    render_engine::RenderEngine,
+    scene::{MaterialHandle, Renderable},
+    script::ScriptError,
+    viewport::Viewport,
+};
+
+/// Scripts that never return, e.g. a loop without an exit, are stopped after this many steps of a call
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+thread_local! {
+    /// The engine the running script call was lent, see [lend]
+    static ENGINE: Cell<Option<NonNull<RenderEngine>>> = const { Cell::new(None) };
+}
+
+/// A compiled rhai script, loaded with [RenderEngine::load_rhai_script]
+pub struct RhaiScript {
+    engine: rhai::Engine,
+    ast: AST,
+    /// The script's own variables, they live as long as it does
+    scope: Scope<'static>,
+    /// Since the script was loaded
+    time: Duration,
+}
+
+impl RhaiScript {
+    pub fn compile(source: &str) -> Result<RhaiScript, ScriptError> {
+        let engine = script_engine();
+        let ast = engine
+            .compile(source)
+            .map_err(|err| script_error(err.1, err.0.to_string()))?;
+        Ok(RhaiScript {
+            engine,
+            ast,
+            scope: Scope::new(),
+            time: Duration::ZERO,
+        })
+    }
+
+    /// Whether [RhaiScript::run_frame] has a `frame` function to call
+    pub(crate) fn has_frame(&self) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|function| function.name == "frame" && function.params.len() == 2)
+    }
+
+    /// Runs the statements at the top of the script
+    pub(crate) fn run_startup(&mut self, engine: &mut RenderEngine) -> Result<(), ScriptError> {
+        lend(engine, || {
+            self.engine.run_ast_with_scope(&mut self.scope, &self.ast)
+        })
+        .map_err(eval_error)
+    }
+
+    /// Calls the script's `frame` function, `delta` after the last call
+    pub(crate) fn run_frame(
+        &mut self,
+        engine: &mut RenderEngine,
+        delta: Duration,
+    ) -> Result<(), ScriptError> {
+        self.time += delta;
+        let args = (self.time.as_secs_f64(), delta.as_secs_f64());
+        // The top of the script ran when it was loaded and keeps its variables in the scope
+        let options = CallFnOptions::new().eval_ast(false).rewind_scope(false);
+        lend(engine, || {
+            self.engine.call_fn_with_options::<Dynamic>(
+                options,
+                &mut self.scope,
+                &self.ast,
+                "frame",
+                args,
+            )
+        })
+        .map(|_| ())
+        .map_err(eval_error)
+    }
+}
+
+fn script_error(position: Position, message: String) -> ScriptError {
+    ScriptError {
+        line: position.line().unwrap_or(0),
+        message,
+    }
+}
+
+fn eval_error(mut err: Box<EvalAltResult>) -> ScriptError {
+    // The line goes in front instead
+    let position = err.take_position();
+    script_error(position, err.to_string())
+}
+
+/// Puts `engine` back into [ENGINE] when dropped, even if a script function panicked
+struct Lent(Option<NonNull<RenderEngine>>);
+
+impl Drop for Lent {
+    fn drop(&mut self) {
+        ENGINE.set(self.0);
+    }
+}
+
+/// Hands `engine` to the script functions for the duration of `call`
+fn lend<R>(engine: &mut RenderEngine, call: impl FnOnce() -> R) -> R {
+    let _lent = Lent(ENGINE.replace(Some(NonNull::from(engine))));
+    call()
+}
+
+/// The engine the running call was lent. It's taken out while `f` uses it, so a script loaded by `f`, e.g. through
+/// a console command, gets a pointer of its own and none of them alias.
+fn with_engine<R>(f: impl FnOnce(&mut RenderEngine) -> Result<R, String>) -> ScriptResult<R> {
+    let Some(mut engine) = ENGINE.take() else {
+        return Err("Script functions only work while the engine runs the script".into());
+    };
+    let _lent = Lent(Some(engine));
+    // SAFETY: the pointer comes from the `&mut RenderEngine` that [lend] holds for as long as it's set, and while
+    // this reference lives it's taken out of ENGINE, so nothing else can get to the engine through it
+    f(unsafe { engine.as_mut() }).map_err(Into::into)
+}
+
+fn focused_viewport(engine: &mut RenderEngine) -> Result<&mut Viewport, String> {
+    let window_id = engine
+        .input()
+        .focused_window()
+        .or_else(|| engine.window_ids().next())
+        .ok_or_else(|| "There is no window".to_string())?;
+    engine
+        .viewport_mut(window_id)
+        .ok_or_else(|| "The focused window has no viewport".to_string())
+}
+
+/// Numbers as scripts write them, with or without a decimal point
+fn number(value: &Dynamic) -> ScriptResult<f32> {
+    value
+        .as_float()
+        .map(|value| value as f32)
+        .or_else(|_| value.as_int().map(|value| value as f32))
+        .map_err(|type_name| format!("Expected a number, got {type_name}").into())
+}
+
+fn index(value: i64) -> ScriptResult<usize> {
+    usize::try_from(value).map_err(|_| format!("{value} is not an index").into())
+}
+
+/// Changes the material at `index` with `change`
+fn change_material(
+    index: i64,
+    change: impl FnOnce(&mut crate::scene::Material),
+) -> ScriptResult<()> {
+    let handle = MaterialHandle(self::index(index)?);
+    with_engine(|engine| {
+        let mut material = *engine
+            .material(handle)
+            .ok_or_else(|| format!("There is no material {index}"))?;
+        change(&mut material);
+        engine.set_material(handle, material);
+        Ok(())
+    })
+}
+
+/// Changes the renderable at `index` with `change`
+fn change_renderable(index: i64, change: impl FnOnce(&mut Renderable)) -> ScriptResult<()> {
+    let position = self::index(index)?;
+    with_engine(|engine| {
+        let mut renderables = engine.renderables().to_vec();
+        let renderable = renderables
+            .get_mut(position)
+            .ok_or_else(|| format!("There is no renderable {index}"))?;
+        change(renderable);
+        engine.set_renderables(renderables);
+        Ok(())
+    })
+}
+
+/// Changes the focused window's camera with `change`, stopping a view animation moving it
+fn change_camera(
+    change: impl FnOnce(&mut crate::camera::orbit_camera::OrbitCamera),
+) -> ScriptResult<()> {
+    with_engine(|engine| {
+        let viewport = focused_viewport(engine)?;
+        change(&mut viewport.camera);
+        viewport.view_animation = None;
+        engine.request_redraw();
+        Ok(())
+    })
+}
+
+fn read_camera(
+    read: impl FnOnce(&crate::camera::orbit_camera::OrbitCamera) -> f32,
+) -> ScriptResult<f64> {
+    with_engine(|engine| Ok(read(&focused_viewport(engine)?.camera) as f64))
+}
+
+/// An engine with every function of the module registered
+fn script_engine() -> rhai::Engine {
+    let mut engine = rhai::Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| {
+        tracing::info!("{text}");
+        let _ = with_engine(|engine| {
+            engine.console_mut().print(text);
+            Ok(())
+        });
+    });
+
+    engine.register_fn("command", |line: &str| {
+        with_engine(|engine| console::execute(engine, line))
+    });
+
+    engine
+        .register_fn("camera_distance", || read_camera(|camera| camera.distance))
+        .register_fn("camera_pitch", || {
+            read_camera(|camera| camera.pitch.to_degrees())
+        })
+        .register_fn("camera_yaw", || {
+            read_camera(|camera| camera.yaw.to_degrees())
+        })
+        .register_fn("set_camera_distance", |distance: Dynamic| {
+            let distance = number(&distance)?;
+            change_camera(|camera| camera.set_distance(distance))
+        })
+        .register_fn("set_camera_pitch", |pitch: Dynamic| {
+            let pitch = number(&pitch)?;
+            change_camera(|camera| camera.set_pitch(pitch.to_radians()))
+        })
+        .register_fn("set_camera_yaw", |yaw: Dynamic| {
+            let yaw = number(&yaw)?;
+            change_camera(|camera| camera.set_yaw(yaw.to_radians()))
+        })
+        .register_fn("set_camera_fov", |fov: Dynamic| {
+            let fov = number(&fov)?;
+            change_camera(|camera| camera.fovy = Deg(fov.clamp(1.0, 179.0)).into())
+        })
+        .register_fn("set_camera_target", |x: Dynamic, y: Dynamic, z: Dynamic| {
+            let target = Vector3::new(number(&x)?, number(&y)?, number(&z)?);
+            change_camera(|camera| {
+                camera.target = target;
+                // Moves the eye along
+                camera.set_distance(camera.distance);
+            })
+        });
+
+    engine
+        .register_fn(
+            "set_material_color",
+            |index: i64, r: Dynamic, g: Dynamic, b: Dynamic| {
+                let color = [number(&r)?, number(&g)?, number(&b)?, 1.0];
+                change_material(index, |material| material.base_color = color)
+            },
+        )
+        .register_fn(
+            "set_material_color",
+            |index: i64, r: Dynamic, g: Dynamic, b: Dynamic, a: Dynamic| {
+                let color = [number(&r)?, number(&g)?, number(&b)?, number(&a)?];
+                change_material(index, |material| material.base_color = color)
+            },
+        )
+        .register_fn(
+            "set_material_roughness",
+            |index: i64, roughness: Dynamic| {
+                let roughness = number(&roughness)?.clamp(0.0, 1.0);
+                change_material(index, |material| material.roughness = roughness)
+            },
+        )
+        .register_fn(
+            "set_material_reflectivity",
+            |index: i64, reflectivity: Dynamic| {
+                let reflectivity = number(&reflectivity)?.clamp(0.0, 1.0);
+                change_material(index, |material| material.reflectivity = reflectivity)
+            },
+        );
+
+    engine
+        .register_fn("renderable_count", || {
+            with_engine(|engine| Ok(engine.renderables().len() as i64))
+        })
+        .register_fn("add_cube", |x: Dynamic, y: Dynamic, z: Dynamic| {
+            let position = Vector3::new(number(&x)?, number(&y)?, number(&z)?);
+            with_engine(|engine| {
+                let mut renderables = engine.renderables().to_vec();
+                renderables.push(Renderable {
+                    mesh: engine.cube_mesh(),
+                    material: engine.default_material(),
+                    model: Matrix4::from_translation(position),
+                    occlusion_query: false,
+                });
+                let index = renderables.len() as i64 - 1;
+                engine.set_renderables(renderables);
+                Ok(index)
+            })
+        })
+        .register_fn(
+            "move_renderable",
+            |index: i64, x: Dynamic, y: Dynamic, z: Dynamic| {
+                let position = Vector4::new(number(&x)?, number(&y)?, number(&z)?, 1.0);
+                change_renderable(index, |renderable| renderable.model.w = position)
+            },
+        )
+        .register_fn("set_renderable_material", |index: i64, material: i64| {
+            let material = MaterialHandle(self::index(material)?);
+            change_renderable(index, |renderable| renderable.material = material)
+        });
+    engine
+}
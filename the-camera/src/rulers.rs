@@ -0,0 +1,140 @@
+//! Rulers along the top and left edge of windows looking through an orthographic camera, with a grid across the view
+//! lined up with their ticks, for technical plan views. Ticks and grid lines follow the camera's screen axes and are
+//! labeled with world coordinates along them, which are the world axes in the standard views. Their spacing adapts
+//! to the zoom, always 1, 2 or 5 times a power of ten.
+
+use cgmath::InnerSpace;
+
+use crate::{
+    camera::orbit_camera::OrbitCamera,
+    debug_hud::GLYPH_ADVANCE,
+    overlay::{OverlayGeometry, OverlayView, TEXT_PIXEL},
+};
+
+/// Width of the rulers, in pixels
+const RULER_SIZE: f32 = 24.0;
+/// Labeled ticks are at least this many pixels apart, so their labels fit between them
+const MIN_LABEL_SPACING: f32 = 90.0;
+/// Ticks between the labeled ones are left out when they'd be closer than this many pixels
+const MIN_TICK_SPACING: f32 = 6.0;
+/// In pixels
+const MAJOR_TICK: f32 = 10.0;
+const MINOR_TICK: f32 = 4.0;
+const RULER_COLOR: [f32; 4] = [0.12, 0.13, 0.15, 0.85];
+const TICK_COLOR: [f32; 4] = [0.85, 0.87, 0.9, 1.0];
+const MAJOR_GRID_COLOR: [f32; 4] = [0.85, 0.87, 0.9, 0.25];
+const MINOR_GRID_COLOR: [f32; 4] = [0.85, 0.87, 0.9, 0.1];
+
+/// The rulers and grid for a window `width` by `height` pixels seen through `camera` and `view`
+pub(crate) fn geometry(
+    camera: &OrbitCamera,
+    view: &OverlayView,
+    width: u32,
+    height: u32,
+) -> OverlayGeometry {
+    let mut geometry = OverlayGeometry::default();
+    let (width, height) = (width as f32, height as f32);
+    let (right, up) = (view.right(), view.up());
+    // Everything is laid out on the plane through the target facing the camera, pixels have the same size all over
+    let pixel = view.pixel_size(camera.target);
+    let at = |x: f32, y: f32| {
+        camera.target + right * ((x - width / 2.0) * pixel) + up * ((height / 2.0 - y) * pixel)
+    };
+
+    let major = nice_step(MIN_LABEL_SPACING * pixel);
+    let divisions = [10, 5, 2]
+        .into_iter()
+        .find(|&divisions| major / divisions as f32 / pixel >= MIN_TICK_SPACING)
+        .unwrap_or(1);
+    let minor = major / divisions as f32;
+    let decimals = (-major.log10().floor()).max(0.0) as usize;
+    let label = |value: f32| format!("{value:.decimals$}");
+
+    geometry.quad(
+        [
+            at(0.0, RULER_SIZE),
+            at(width, RULER_SIZE),
+            at(width, 0.0),
+            at(0.0, 0.0),
+        ],
+        RULER_COLOR,
+    );
+    geometry.quad(
+        [
+            at(0.0, height),
+            at(RULER_SIZE, height),
+            at(RULER_SIZE, RULER_SIZE),
+            at(0.0, RULER_SIZE),
+        ],
+        RULER_COLOR,
+    );
+
+    // Along the top, coordinates grow to the right
+    let offset = camera.target.dot(right) - width / 2.0 * pixel;
+    let ticks = |from: f32, to: f32| ((from / minor).ceil() as i64)..=((to / minor).floor() as i64);
+    for tick in ticks(offset + RULER_SIZE * pixel, offset + width * pixel) {
+        let value = tick as f32 * minor;
+        let x = (value - offset) / pixel;
+        let is_major = tick % divisions == 0;
+        let (length, color) = if is_major {
+            (MAJOR_TICK, MAJOR_GRID_COLOR)
+        } else {
+            (MINOR_TICK, MINOR_GRID_COLOR)
+        };
+        geometry.line(at(x, RULER_SIZE - length), at(x, RULER_SIZE), TICK_COLOR);
+        geometry.line(at(x, RULER_SIZE), at(x, height), color);
+        if is_major {
+            let text = label(value);
+            let half_width = text_width(&text) / 2.0;
+            geometry.text(
+                view,
+                at(x + 3.0 + half_width, (RULER_SIZE - MAJOR_TICK) / 2.0),
+                &text,
+                TICK_COLOR,
+            );
+        }
+    }
+
+    // Down the left, coordinates grow upwards and labels read from the bottom
+    let offset = camera.target.dot(up) - height / 2.0 * pixel;
+    for tick in ticks(offset, offset + (height - RULER_SIZE) * pixel) {
+        let value = tick as f32 * minor;
+        let y = height - (value - offset) / pixel;
+        let is_major = tick % divisions == 0;
+        let (length, color) = if is_major {
+            (MAJOR_TICK, MAJOR_GRID_COLOR)
+        } else {
+            (MINOR_TICK, MINOR_GRID_COLOR)
+        };
+        geometry.line(at(RULER_SIZE - length, y), at(RULER_SIZE, y), TICK_COLOR);
+        geometry.line(at(RULER_SIZE, y), at(width, y), color);
+        if is_major {
+            let text = label(value);
+            let half_width = text_width(&text) / 2.0;
+            let position = at((RULER_SIZE - MAJOR_TICK) / 2.0, y - 3.0 - half_width);
+            geometry.text_in_plane(
+                position,
+                (up, -right),
+                TEXT_PIXEL * pixel,
+                &text,
+                TICK_COLOR,
+            );
+        }
+    }
+    geometry
+}
+
+/// The smallest of 1, 2 or 5 times a power of ten that's at least `min`
+fn nice_step(min: f32) -> f32 {
+    let power = 10f32.powf(min.log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|factor| factor * power)
+        .find(|&step| step >= min)
+        .unwrap_or(10.0 * power)
+}
+
+/// Of a label in pixels, drawn the size [OverlayGeometry::text] draws it
+fn text_width(text: &str) -> f32 {
+    TEXT_PIXEL * (text.chars().count() as f32 * GLYPH_ADVANCE - 1.0)
+}
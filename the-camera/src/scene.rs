@@ -0,0 +1,161 @@
+use cgmath::{InnerSpace, Matrix3, Matrix4, One, Quaternion, Vector3};
+
+use crate::{flipbook::FlipbookPlayback, render_target::RenderTargetHandle};
+
+/// Refers to a mesh uploaded with [crate::render_engine::RenderEngine::add_mesh]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MeshHandle(pub(crate) usize);
+
+/// Refers to a material added with [crate::render_engine::RenderEngine::add_material]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialHandle(pub(crate) usize);
+
+/// Refers to an image uploaded with [crate::render_engine::RenderEngine::add_texture]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureHandle(pub(crate) usize);
+
+/// Surface parameters shared by every object drawn with the same [MaterialHandle]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material {
+    /// Multiplied with the vertex colors
+    pub base_color: [f32; 4],
+    /// Sampled with the mesh's texture coordinates and multiplied with the color
+    pub texture: Option<RenderTargetHandle>,
+    /// Sampled like the texture where there is none, e.g. the base color texture of a loaded model
+    pub image: Option<TextureHandle>,
+    /// Shown instead of the texture, one frame after the other
+    pub flipbook: Option<FlipbookPlayback>,
+    /// Shown instead of the texture and flipbook
+    #[cfg(feature = "ffmpeg")]
+    pub video: Option<crate::video::VideoHandle>,
+    /// How much of the nearest [crate::reflection_probe::ReflectionProbe] shows instead of the color, from 0 for
+    /// none to 1 for a mirror
+    pub reflectivity: f32,
+    /// From 0 for sharp reflections to 1 for blurred ones
+    pub roughness: f32,
+    /// Strength of a clear coat over the surface, like varnish or car paint, from 0 to 1. The coat reflects the
+    /// nearest reflection probe, barely where it's looked at head on and more towards the silhouette.
+    pub clearcoat: f32,
+    /// From 0 for a sharp coat to 1 for a blurred one
+    pub clearcoat_roughness: f32,
+    /// Index of refraction of the surface, 1 for none. Above 1 it reflects the nearest reflection probe the way glass
+    /// or plastic do, like the coat but at least by [Material::reflectivity]. Glass is around 1.5.
+    pub ior: f32,
+    /// Multiplies the color with the light of the [crate::irradiance_volume::IrradianceVolume] around it, for objects
+    /// moving through the scene. They're left out of the volume's bake.
+    pub lit_by_probes: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            base_color: [1.0; 4],
+            texture: None,
+            image: None,
+            flipbook: None,
+            #[cfg(feature = "ffmpeg")]
+            video: None,
+            reflectivity: 0.0,
+            roughness: 0.0,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.0,
+            ior: 1.0,
+            lit_by_probes: false,
+        }
+    }
+}
+
+impl Material {
+    /// Whether it shows a reflection probe, by its reflectivity, coat or index of refraction
+    pub(crate) fn reflects(&self) -> bool {
+        self.reflectivity > 0.0 || self.clearcoat > 0.0 || self.ior > 1.0
+    }
+}
+
+/// Position, orientation and size of an object in the world
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: Vector3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Transform {
+            translation,
+            ..Default::default()
+        }
+    }
+
+    /// Splits a model matrix back up, assuming it scales along its own axes without shearing
+    pub fn from_matrix(matrix: Matrix4<f32>) -> Self {
+        let [x, y, z] = [matrix.x, matrix.y, matrix.z].map(|column| column.truncate());
+        Transform {
+            translation: matrix.w.truncate(),
+            rotation: Quaternion::from(Matrix3::from_cols(
+                x.normalize(),
+                y.normalize(),
+                z.normalize(),
+            ))
+            .normalize(),
+            scale: Vector3::new(x.magnitude(), y.magnitude(), z.magnitude()),
+        }
+    }
+
+    /// The model matrix, scaling first, then rotating, then translating
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+/// One object to draw: which mesh, with which material, where. This is all the engine needs to know about the
+/// app's scene and gets handed over each frame with [crate::render_engine::RenderEngine::set_renderables].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Renderable {
+    pub mesh: MeshHandle,
+    pub material: MaterialHandle,
+    /// World space model matrix
+    pub model: Matrix4<f32>,
+    /// Tests the mesh's bounding box against the depth buffer and skips drawing it while the box is hidden. Worth it
+    /// for expensive meshes that are often behind others. Results are kept by the renderable's position in the list
+    /// given to [crate::render_engine::RenderEngine::set_renderables].
+    pub occlusion_query: bool,
+}
+
+impl Renderable {
+    pub fn new(mesh: MeshHandle, material: MaterialHandle, transform: &Transform) -> Self {
+        Renderable {
+            mesh,
+            material,
+            model: transform.matrix(),
+            occlusion_query: false,
+        }
+    }
+
+    /// See [Renderable::occlusion_query]
+    pub fn with_occlusion_query(mut self) -> Self {
+        self.occlusion_query = true;
+        self
+    }
+}
+
+/// What [crate::render_engine::RenderEngine::pick] found under the cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Pick {
+    /// Index into the renderables given to [crate::render_engine::RenderEngine::set_renderables] that frame
+    pub renderable: usize,
+    pub mesh: MeshHandle,
+}
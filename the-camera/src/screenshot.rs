@@ -0,0 +1,213 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver},
+};
+
+pub use crate::wgpu_utils::readback::padded_bytes_per_row;
+use crate::{
+    jobs::JobSystem,
+    wgpu_utils::readback::{convert_to_rgba8, unpad_rows},
+};
+
+/// A readback buffer that a frame can be copied into and then mapped asynchronously.
+///
+/// The buffer is kept around after reading so it can be reused for the next frame of the same size.
+pub struct FrameReadback {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    format: wgpu::TextureFormat,
+    mapped: Option<Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+/// State of a [FrameReadback] as reported by [FrameReadback::try_read]
+pub enum ReadbackStatus {
+    /// Nothing has been mapped
+    Idle,
+    /// The copy is still in flight
+    Pending,
+    /// The frame as tightly packed RGBA8 pixels
+    Ready(Vec<u8>),
+    /// Mapping failed or the format can't be converted, the reason has been printed already
+    Failed,
+}
+
+impl FrameReadback {
+    /// Allocates a readback buffer large enough for a `width` x `height` texture of `format`
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        // wgpu requires every row of a texture copy to start at a multiple of 256 bytes
+        let padded_bytes_per_row = padded_bytes_per_row(width, 4);
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Frame Readback Buffer"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        FrameReadback {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            format,
+            mapped: None,
+        }
+    }
+
+    /// Whether `texture` can be copied into this buffer
+    pub fn fits(&self, texture: &wgpu::Texture) -> bool {
+        texture.width() == self.width
+            && texture.height() == self.height
+            && texture.format() == self.format
+    }
+
+    /// Whether a copy is in flight, the buffer can't be reused until [FrameReadback::try_read] returned its data
+    pub fn is_busy(&self) -> bool {
+        self.mapped.is_some()
+    }
+
+    /// Records a copy of `texture` into the buffer on `encoder`.
+    ///
+    /// [FrameReadback::start_mapping] has to be called once the encoder has been submitted.
+    pub fn copy_from(&self, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) {
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &self.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            texture.size(),
+        );
+    }
+
+    /// Asks wgpu to map the buffer, must only be called after the copy has been submitted
+    pub fn start_mapping(&mut self) {
+        let (sender, receiver) = channel();
+        self.mapped = Some(receiver);
+        self.buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+    }
+
+    /// Takes the pixels out of the buffer if mapping has finished and unmaps it again for reuse.
+    ///
+    /// Only checks the mapping state, the caller has to poll the device for it to make progress.
+    pub fn try_read(&mut self) -> ReadbackStatus {
+        let Some(mapped) = self.mapped.as_ref() else {
+            return ReadbackStatus::Idle;
+        };
+        match mapped.try_recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => {
+                tracing::error!("Failed to map readback buffer: {err}");
+                self.mapped = None;
+                return ReadbackStatus::Failed;
+            }
+            Err(_) => return ReadbackStatus::Pending,
+        }
+        self.mapped = None;
+
+        let pixels = {
+            let mapped = self.buffer.slice(..).get_mapped_range();
+            unpad_rows(
+                &mapped,
+                self.width * 4,
+                self.padded_bytes_per_row,
+                self.height,
+            )
+        };
+        self.buffer.unmap();
+
+        match convert_to_rgba8(pixels, self.format) {
+            Some(pixels) => ReadbackStatus::Ready(pixels),
+            None => {
+                tracing::error!("Cannot read back {:?} frames", self.format);
+                ReadbackStatus::Failed
+            }
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+/// A frame that has been copied out of the surface texture and is waiting to be written to `path`.
+///
+/// Mapping happens asynchronously, so the engine keeps rendering and checks back on it every frame with [PendingCapture::try_finish].
+pub struct PendingCapture {
+    readback: FrameReadback,
+    path: PathBuf,
+}
+
+impl PendingCapture {
+    /// Records a copy of `texture` into a new readback buffer on `encoder`.
+    ///
+    /// [PendingCapture::start_mapping] has to be called once the encoder has been submitted.
+    pub fn new(
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        path: PathBuf,
+    ) -> Self {
+        let readback =
+            FrameReadback::new(device, texture.width(), texture.height(), texture.format());
+        readback.copy_from(encoder, texture);
+        PendingCapture { readback, path }
+    }
+
+    /// Asks wgpu to map the readback buffer, must only be called after the copy has been submitted
+    pub fn start_mapping(&mut self) {
+        self.readback.start_mapping();
+    }
+
+    /// Returns the capture back if its buffer is not mapped yet, otherwise writes the image and consumes it.
+    ///
+    /// The PNG encoding runs as a job so the render loop only pays for the memcpy out of the mapped buffer.
+    pub fn try_finish(mut self, jobs: &JobSystem) -> Option<Self> {
+        let pixels = match self.readback.try_read() {
+            ReadbackStatus::Pending => return Some(self),
+            ReadbackStatus::Ready(pixels) => pixels,
+            ReadbackStatus::Idle | ReadbackStatus::Failed => return None,
+        };
+
+        let (path, width, height) = (self.path, self.readback.width, self.readback.height);
+        jobs.spawn(move || match write_png(&path, width, height, &pixels) {
+            Ok(()) => tracing::info!("Saved screenshot to {}", path.display()),
+            Err(err) => tracing::error!("Failed to write {}: {err}", path.display()),
+        });
+        None
+    }
+}
+
+pub fn write_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    rgba: &[u8],
+) -> Result<(), png::EncodingError> {
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(rgba)
+}
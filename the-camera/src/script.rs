@@ -0,0 +1,176 @@
+//! Scripts of [console](crate::console) commands, for setting up demos and animating them without recompiling.
+//! They're loaded with [crate::render_engine::RenderEngine::load_script] or the console's `exec` command.
+//!
+//! Every line is one command, blank lines and lines starting with `#` are skipped. The lines at the top run once when
+//! the script is loaded, the ones after a `[frame]` line at the start of every update until another script is
+//! loaded:
+//!
+//! ```text
+//! camera distance 6
+//! material 0 color 1 0.5 0.2
+//!
+//! [frame]
+//! camera yaw $time
+//! ```
+//!
+//! `$time` is replaced with the seconds since the script was loaded and `$delta` with the seconds since the last
+//! update. Scripts can do whatever the console can, including the commands the app registered.
+
+use std::fmt;
+
+use web_time::Duration;
+
+use crate::{console, render_engine::RenderEngine};
+
+/// A command and where it is in the script, for errors
+#[derive(Debug, Clone, PartialEq)]
+struct Line {
+    number: usize,
+    text: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Script {
+    startup: Vec<Line>,
+    frame: Vec<Line>,
+}
+
+/// Why a script couldn't be loaded or stopped running
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptError {
+    /// Counted from 1
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Script, ScriptError> {
+        let mut script = Script::default();
+        let mut in_frame = false;
+        for (index, text) in source.lines().enumerate() {
+            let text = text.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+            if text.starts_with('[') {
+                let message = match text {
+                    "[frame]" if in_frame => "There can only be one [frame] section".to_string(),
+                    "[frame]" => {
+                        in_frame = true;
+                        continue;
+                    }
+                    _ => format!("Unknown section {text}"),
+                };
+                return Err(ScriptError {
+                    line: index + 1,
+                    message,
+                });
+            }
+            let line = Line {
+                number: index + 1,
+                text: text.to_string(),
+            };
+            if in_frame {
+                script.frame.push(line);
+            } else {
+                script.startup.push(line);
+            }
+        }
+        Ok(script)
+    }
+
+    /// Hands out the lines to run once and the ones to run every frame
+    pub(crate) fn split(self) -> (StartupScript, Option<FrameScript>) {
+        let frame = (!self.frame.is_empty()).then_some(FrameScript {
+            lines: self.frame,
+            time: Duration::ZERO,
+        });
+        (StartupScript(self.startup), frame)
+    }
+}
+
+/// The lines of a script before its `[frame]` section
+pub(crate) struct StartupScript(Vec<Line>);
+
+impl StartupScript {
+    /// Stops at the first command that fails
+    pub fn run(&self, engine: &mut RenderEngine) -> Result<(), ScriptError> {
+        for line in &self.0 {
+            // Printed to the console, like commands typed into it
+            console::run(engine, &line.text).map_err(|message| ScriptError {
+                line: line.number,
+                message,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// The `[frame]` section of the running script
+pub(crate) struct FrameScript {
+    lines: Vec<Line>,
+    /// Since the script was loaded
+    time: Duration,
+}
+
+impl FrameScript {
+    /// Runs every line once, stops at the first command that fails
+    pub fn run(&mut self, engine: &mut RenderEngine, delta: Duration) -> Result<(), ScriptError> {
+        self.time += delta;
+        let time = format!("{:.4}", self.time.as_secs_f32());
+        let delta = format!("{:.4}", delta.as_secs_f32());
+        for line in &self.lines {
+            let text = line.text.replace("$time", &time).replace("$delta", &delta);
+            // Every frame would flood the console's log
+            console::execute(engine, &text).map_err(|message| ScriptError {
+                line: line.number,
+                message,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_startup_and_frame_lines() {
+        let script = Script::parse(
+            "# Setup\ncamera distance 6\n\n  material 0 color 1 0.5 0.2\n[frame]\ncamera yaw $time\n",
+        )
+        .unwrap();
+        let line = |number: usize, text: &str| Line {
+            number,
+            text: text.to_string(),
+        };
+        assert_eq!(
+            script.startup,
+            [
+                line(2, "camera distance 6"),
+                line(4, "material 0 color 1 0.5 0.2")
+            ]
+        );
+        assert_eq!(script.frame, [line(6, "camera yaw $time")]);
+
+        let (_, frame) = Script::parse("camera distance 6").unwrap().split();
+        assert!(frame.is_none());
+    }
+
+    #[test]
+    fn rejects_unknown_and_repeated_sections() {
+        let err = Script::parse("[frame]\n[frame]").unwrap_err();
+        assert_eq!(err.line, 2);
+        let err = Script::parse("camera distance 6\n[startup]").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: Unknown section [startup]");
+    }
+}
@@ -0,0 +1,325 @@
+//! Which renderables are selected, chosen with the mouse and outlined in every window.
+//!
+//! A left click selects the renderable under the cursor and a click on the background clears the selection. Holding
+//! shift adds the clicked renderable or takes it out again. Dragging with control held draws a box and selects
+//! everything whose center is inside, with shift adding to the selection. Renderables are referred to by their
+//! position in the list given to [crate::render_engine::RenderEngine::set_renderables], like
+//! [crate::scene::Pick::renderable].
+//!
+//! Every change is reported to the plugins through [crate::plugin::EnginePlugin::on_selection_changed] and to the
+//! callbacks added with [crate::render_engine::RenderEngine::on_selection_changed], once per update.
+
+use std::collections::HashMap;
+
+use winit::{event::MouseButton, window::WindowId};
+
+use crate::{
+    input::InputEvent,
+    overlay::{OverlayGeometry, OverlayView},
+    scene::Pick,
+    wgpu_utils::readback::ReadbackFuture,
+};
+
+/// Presses released within this many pixels of where they started are clicks, not drags
+pub(crate) const CLICK_DISTANCE: f32 = 4.0;
+const BOX_COLOR: [f32; 4] = [0.3, 0.6, 1.0, 1.0];
+const BOX_FILL: [f32; 4] = [0.3, 0.6, 1.0, 0.15];
+/// Box corners are laid out this far along the rays through them, anything between the near and far plane works
+const BOX_DEPTH: f32 = 1.0;
+
+/// What changed in one update
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelectionChange {
+    pub added: Vec<usize>,
+    pub removed: Vec<usize>,
+}
+
+impl SelectionChange {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Gesture {
+    /// Pressed without control, a click if it's released nearby
+    Click {
+        start: [f32; 2],
+    },
+    Box {
+        start: [f32; 2],
+        end: [f32; 2],
+    },
+}
+
+/// What handling an input event did, see [Selection::process_event]
+#[derive(Debug, Default)]
+pub(crate) struct SelectionResponse {
+    /// Box selecting, the camera shouldn't follow the mouse
+    pub consumed: bool,
+    pub redraw: bool,
+    /// A click at this pixel, the engine picks there and hands the result to [Selection::add_click]
+    pub pick: Option<[u32; 2]>,
+    /// A box was let go of, the engine finds what's inside and hands it to [Selection::apply_box]
+    pub select_box: Option<[[f32; 2]; 2]>,
+}
+
+/// The selected renderables, see the [module docs](self)
+pub struct Selection {
+    selected: Vec<usize>,
+    mouse_select: bool,
+    /// Whether the outline pass draws around the selected renderables
+    pub outline: bool,
+    pub outline_color: [f32; 4],
+    cursors: HashMap<WindowId, [f32; 2]>,
+    gesture: Option<(WindowId, Gesture)>,
+    /// Clicks waiting for their pick, each with whether shift was held
+    clicks: Vec<(ReadbackFuture<Pick>, bool)>,
+    change: SelectionChange,
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Selection {
+            selected: Vec::new(),
+            mouse_select: true,
+            outline: true,
+            outline_color: [1.0, 0.6, 0.1, 1.0],
+            cursors: HashMap::new(),
+            gesture: None,
+            clicks: Vec::new(),
+            change: SelectionChange::default(),
+        }
+    }
+}
+
+impl Selection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// In the order they were selected
+    pub fn selected(&self) -> &[usize] {
+        &self.selected
+    }
+
+    pub fn is_selected(&self, renderable: usize) -> bool {
+        self.selected.contains(&renderable)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.selected.is_empty()
+    }
+
+    pub fn select(&mut self, renderable: usize) {
+        if !self.is_selected(renderable) {
+            self.selected.push(renderable);
+            self.note(renderable, true);
+        }
+    }
+
+    pub fn deselect(&mut self, renderable: usize) {
+        if let Some(index) = self
+            .selected
+            .iter()
+            .position(|&selected| selected == renderable)
+        {
+            self.selected.remove(index);
+            self.note(renderable, false);
+        }
+    }
+
+    /// Selects the renderable if it isn't, deselects it otherwise
+    pub fn toggle(&mut self, renderable: usize) {
+        if self.is_selected(renderable) {
+            self.deselect(renderable);
+        } else {
+            self.select(renderable);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for renderable in std::mem::take(&mut self.selected) {
+            self.note(renderable, false);
+        }
+    }
+
+    /// Selects exactly `renderables`
+    pub fn set(&mut self, renderables: impl IntoIterator<Item = usize>) {
+        self.clear();
+        for renderable in renderables {
+            self.select(renderable);
+        }
+    }
+
+    /// Whether clicks and boxes select, on by default. Off, the selection only changes through the methods above.
+    pub fn set_mouse_select(&mut self, mouse_select: bool) {
+        self.mouse_select = mouse_select;
+        if !mouse_select {
+            self.gesture = None;
+            self.clicks.clear();
+        }
+    }
+
+    /// Records a change for the next [Selection::take_change], undoing the opposite one if it's still pending
+    fn note(&mut self, renderable: usize, added: bool) {
+        let (undo, record) = if added {
+            (&mut self.change.removed, &mut self.change.added)
+        } else {
+            (&mut self.change.added, &mut self.change.removed)
+        };
+        if let Some(index) = undo.iter().position(|&noted| noted == renderable) {
+            undo.remove(index);
+        } else {
+            record.push(renderable);
+        }
+    }
+
+    /// What changed since the last call
+    pub(crate) fn take_change(&mut self) -> SelectionChange {
+        std::mem::take(&mut self.change)
+    }
+
+    /// Tracks clicks and boxes with the left mouse button. `control` is whether the key is held.
+    pub(crate) fn process_event(
+        &mut self,
+        window_id: WindowId,
+        event: &InputEvent,
+        control: bool,
+    ) -> SelectionResponse {
+        if !self.mouse_select {
+            return SelectionResponse::default();
+        }
+        match event {
+            &InputEvent::CursorMoved(cursor) => {
+                self.cursors.insert(window_id, cursor);
+                match &mut self.gesture {
+                    Some((gesture_window, Gesture::Box { end, .. }))
+                        if *gesture_window == window_id =>
+                    {
+                        *end = cursor;
+                        SelectionResponse {
+                            consumed: true,
+                            redraw: true,
+                            ..Default::default()
+                        }
+                    }
+                    _ => SelectionResponse::default(),
+                }
+            }
+            InputEvent::CursorLeft => {
+                self.cursors.remove(&window_id);
+                SelectionResponse::default()
+            }
+            InputEvent::MouseButton {
+                button: MouseButton::Left,
+                pressed: true,
+            } => {
+                let Some(&start) = self.cursors.get(&window_id) else {
+                    return SelectionResponse::default();
+                };
+                if control {
+                    self.gesture = Some((window_id, Gesture::Box { start, end: start }));
+                    return SelectionResponse {
+                        consumed: true,
+                        redraw: true,
+                        ..Default::default()
+                    };
+                }
+                self.gesture = Some((window_id, Gesture::Click { start }));
+                SelectionResponse::default()
+            }
+            InputEvent::MouseButton {
+                button: MouseButton::Left,
+                pressed: false,
+            } => match self.gesture.take() {
+                Some((gesture_window, Gesture::Box { start, end }))
+                    if gesture_window == window_id =>
+                {
+                    SelectionResponse {
+                        consumed: true,
+                        redraw: true,
+                        select_box: Some([start, end]),
+                        ..Default::default()
+                    }
+                }
+                Some((gesture_window, Gesture::Click { start })) if gesture_window == window_id => {
+                    let end = self.cursors.get(&window_id).copied().unwrap_or(start);
+                    let moved = (end[0] - start[0]).hypot(end[1] - start[1]);
+                    SelectionResponse {
+                        pick: (moved <= CLICK_DISTANCE).then_some([end[0] as u32, end[1] as u32]),
+                        ..Default::default()
+                    }
+                }
+                _ => SelectionResponse::default(),
+            },
+            _ => SelectionResponse::default(),
+        }
+    }
+
+    /// Waits for the pick of a click. `shift` is whether it was held.
+    pub(crate) fn add_click(&mut self, pick: ReadbackFuture<Pick>, shift: bool) {
+        self.clicks.push((pick, shift));
+    }
+
+    /// Whether the next click's pick arrived, to be applied in the next update
+    pub(crate) fn has_click_ready(&self) -> bool {
+        self.clicks.first().is_some_and(|(pick, _)| pick.is_ready())
+    }
+
+    /// Applies the clicks whose pick arrived, in the order they happened
+    pub(crate) fn resolve_clicks(&mut self) {
+        while let Some((pick, shift)) = self.clicks.first_mut() {
+            let Some(pick) = pick.try_take() else {
+                break;
+            };
+            let shift = *shift;
+            self.clicks.remove(0);
+            match (pick, shift) {
+                (Some(pick), true) => self.toggle(pick.renderable),
+                (Some(pick), false) => self.set([pick.renderable]),
+                (None, true) => (),
+                (None, false) => self.clear(),
+            }
+        }
+    }
+
+    /// Selects the renderables found inside a box, adding to the selection with `shift` held
+    pub(crate) fn apply_box(&mut self, inside: impl IntoIterator<Item = usize>, shift: bool) {
+        if !shift {
+            self.clear();
+        }
+        for renderable in inside {
+            self.select(renderable);
+        }
+    }
+
+    /// The box being dragged in the window, if any
+    pub(crate) fn box_geometry(&self, window_id: WindowId, view: &OverlayView) -> OverlayGeometry {
+        let mut geometry = OverlayGeometry::default();
+        let Some((gesture_window, Gesture::Box { start, end })) = self.gesture else {
+            return geometry;
+        };
+        if gesture_window != window_id {
+            return geometry;
+        }
+        let corners = [start, [end[0], start[1]], end, [start[0], end[1]]]
+            .map(|corner| view.ray(corner).at(BOX_DEPTH));
+        geometry.quad(corners, BOX_FILL);
+        for index in 0..4 {
+            geometry.line(corners[index], corners[(index + 1) % 4], BOX_COLOR);
+        }
+        geometry
+    }
+
+    /// Forgets a closed window
+    pub(crate) fn remove_window(&mut self, window_id: WindowId) {
+        self.cursors.remove(&window_id);
+        if self
+            .gesture
+            .is_some_and(|(gesture_window, _)| gesture_window == window_id)
+        {
+            self.gesture = None;
+        }
+    }
+}
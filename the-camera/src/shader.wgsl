@@ -1,35 +1,230 @@
+// See clipping.rs
+struct Clipping {
+    // Normal in xyz, distance in w, keeping points with dot(normal, point) >= distance
+    planes: array<vec4<f32>, 4>,
+    cap_color: vec4<f32>,
+    count: u32,
+    caps: u32,
+}
+
+// See audio.rs
+struct Audio {
+    bands: array<vec4<f32>, 4>,
+    level: f32,
+}
+
 struct Camera {
     view_pos: vec4<f32>,
     view_proj: mat4x4<f32>,
+    clipping: Clipping,
+    audio: Audio,
 }
 @group(0) @binding(0)
 var<uniform> camera: Camera;
 
+struct Object {
+    model: mat4x4<f32>,
+    color: vec4<f32>,
+    // Written into the ID attachment for picking
+    id: u32,
+    reflectivity: f32,
+    roughness: f32,
+    clearcoat: f32,
+    clearcoat_roughness: f32,
+    // Index of refraction, 1 for none
+    ior: f32,
+    // Cube of the reflection probe it reflects, see reflection_probe/pass.rs
+    probe: u32,
+    // Whether the irradiance volume lights it
+    lit: u32,
+}
+@group(1) @binding(0)
+var<uniform> object: Object;
+
+@group(2) @binding(0)
+var t_material: texture_2d<f32>;
+@group(2) @binding(1)
+var s_material: sampler;
+
+// See reflection_probe/pass.rs
+struct Probe {
+    // W is 1 where the cube holds a probe
+    position: vec4<f32>,
+    box_min: vec4<f32>,
+    box_max: vec4<f32>,
+}
+
+struct Probes {
+    probes: array<Probe, 4>,
+}
+@group(2) @binding(2)
+var<uniform> probes: Probes;
+@group(2) @binding(3)
+var s_probe: sampler;
+@group(2) @binding(4)
+var t_probe0: texture_cube<f32>;
+@group(2) @binding(5)
+var t_probe1: texture_cube<f32>;
+@group(2) @binding(6)
+var t_probe2: texture_cube<f32>;
+@group(2) @binding(7)
+var t_probe3: texture_cube<f32>;
+
+const PROBE_MIPS = 5u;
+
+// See irradiance_volume/pass.rs
+struct Irradiance {
+    min: vec3<f32>,
+    baked: u32,
+    max: vec3<f32>,
+    probe_radius: f32,
+    counts: vec3<u32>,
+}
+@group(2) @binding(8)
+var<uniform> irradiance: Irradiance;
+@group(2) @binding(9)
+var s_irradiance: sampler;
+@group(2) @binding(10)
+var t_irradiance_red: texture_2d<f32>;
+@group(2) @binding(11)
+var t_irradiance_green: texture_2d<f32>;
+@group(2) @binding(12)
+var t_irradiance_blue: texture_2d<f32>;
+
+const MAX_PROBES_PER_AXIS = 16.0;
+
 struct VertexInput {
     @location(0) position: vec3<f32>,
     @location(1) color: vec3<f32>,
+    @location(2) tex_coords: vec2<f32>,
 };
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
     @location(1) world_position: vec3<f32>,
+    @location(2) tex_coords: vec2<f32>,
 };
 
 @vertex
 fn vs_main(
     model: VertexInput,
 ) -> VertexOutput {
+    return transform_vertex(model);
+}
+
+// Shared with vs_pulled in vertex_pulling.wgsl
+fn transform_vertex(model: VertexInput) -> VertexOutput {
     var out: VertexOutput;
-    out.color = model.color;
-    let world_position: vec4<f32> = vec4<f32>(model.position, 1.0);
+    out.color = model.color * object.color.rgb;
+    out.tex_coords = model.tex_coords;
+    let world_position: vec4<f32> = object.model * vec4<f32>(model.position, 1.0);
 
     out.world_position = world_position.xyz;
     out.clip_position = camera.view_proj * world_position;   
     return out;
 }
 
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @location(1) id: u32,
+};
+
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(in.color, 1.0);
+fn fs_main(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> FragmentOutput {
+    // Sampled before anything is discarded, derivatives need every pixel of the quad
+    let texel = textureSample(t_material, s_material, in.tex_coords);
+    // Meshes carry no normals, the triangle's own faces the camera
+    let face_normal = normalize(cross(dpdx(in.world_position), dpdy(in.world_position)));
+    for (var index = 0u; index < camera.clipping.count; index++) {
+        let plane = camera.clipping.planes[index];
+        if dot(plane.xyz, in.world_position) < plane.w {
+            discard;
+        }
+    }
+
+    var out: FragmentOutput;
+    out.id = object.id;
+    // Back faces only show through a cut, filling them in makes the mesh look solid
+    if camera.clipping.count > 0u && camera.clipping.caps != 0u && !front_facing {
+        out.color = camera.clipping.cap_color;
+        return out;
+    }
+    var color = in.color * texel.rgb;
+    let to_eye = camera.view_pos.xyz - in.world_position;
+    let normal = face_normal * sign(dot(face_normal, to_eye));
+    if object.lit != 0u && irradiance.baked != 0u {
+        color *= ambient(in.world_position, normal);
+    }
+    if object.probe < 4u && probes.probes[object.probe].position.w > 0.0 {
+        let view = normalize(to_eye);
+        let ray = reflect(-view, normal);
+        let facing = saturate(dot(normal, view));
+        var specular = object.reflectivity;
+        if object.ior > 1.0 {
+            let f0 = pow((object.ior - 1.0) / (object.ior + 1.0), 2.0);
+            specular = max(specular, fresnel(f0, facing));
+        }
+        if specular > 0.0 {
+            color = mix(color, reflection(in.world_position, ray, object.roughness), specular);
+        }
+        // Over everything else, the coat's index of refraction is 1.5 like glTF's
+        if object.clearcoat > 0.0 {
+            let coat = object.clearcoat * fresnel(0.04, facing);
+            color = mix(color, reflection(in.world_position, ray, object.clearcoat_roughness), coat);
+        }
+    }
+    out.color = vec4<f32>(color, 1.0);
+    return out;
+}
+
+// Schlick's approximation of how much a surface reflecting `f0` head on reflects when looked at from `facing`, the
+// cosine between its normal and the view
+fn fresnel(f0: f32, facing: f32) -> f32 {
+    return f0 + (1.0 - f0) * pow(1.0 - facing, 5.0);
+}
+
+// What the object's probe shows along the ray, followed to where it leaves the probe's box and looked at from the
+// probe, blurred by `roughness`. Rays from outside the box are looked up as they are.
+fn reflection(position: vec3<f32>, ray: vec3<f32>, roughness: f32) -> vec3<f32> {
+    let probe = probes.probes[object.probe];
+    let exits = max((probe.box_max.xyz - position) / ray, (probe.box_min.xyz - position) / ray);
+    let distance = min(min(exits.x, exits.y), exits.z);
+    var direction = ray;
+    if distance > 0.0 {
+        direction = position + ray * distance - probe.position.xyz;
+    }
+    // The cubes hold the world mirrored along Z
+    direction.z = -direction.z;
+    let level = roughness * f32(PROBE_MIPS - 1u);
+    switch object.probe {
+        case 0u: { return textureSampleLevel(t_probe0, s_probe, direction, level).rgb; }
+        case 1u: { return textureSampleLevel(t_probe1, s_probe, direction, level).rgb; }
+        case 2u: { return textureSampleLevel(t_probe2, s_probe, direction, level).rgb; }
+        default: { return textureSampleLevel(t_probe3, s_probe, direction, level).rgb; }
+    }
+}
+
+// The light of the probes around the position falling onto a surface facing `normal`, interpolated between the
+// eight nearest. Outside the volume the nearest probes on its faces light it.
+fn ambient(position: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let counts = vec3<f32>(irradiance.counts);
+    let size = max(irradiance.max - irradiance.min, vec3<f32>(1e-6));
+    let cell = clamp((position - irradiance.min) / size * (counts - 1.0), vec3<f32>(0.0), counts - 1.0);
+    // The Z slices lie side by side along X, each is sampled bilinearly and the two nearest are mixed
+    let slice = floor(cell.z);
+    let next_slice = min(slice + 1.0, counts.z - 1.0);
+    let basis = vec4<f32>(1.0, normal);
+    let light = mix(slice_ambient(cell.xy, slice, basis), slice_ambient(cell.xy, next_slice, basis), cell.z - slice);
+    return max(light, vec3<f32>(0.0));
+}
+
+fn slice_ambient(cell: vec2<f32>, slice: f32, basis: vec4<f32>) -> vec3<f32> {
+    let uv = (vec2<f32>(slice * MAX_PROBES_PER_AXIS + cell.x, cell.y) + 0.5)
+        / vec2<f32>(MAX_PROBES_PER_AXIS * MAX_PROBES_PER_AXIS, MAX_PROBES_PER_AXIS);
+    return vec3<f32>(
+        dot(textureSampleLevel(t_irradiance_red, s_irradiance, uv, 0.0), basis),
+        dot(textureSampleLevel(t_irradiance_green, s_irradiance, uv, 0.0), basis),
+        dot(textureSampleLevel(t_irradiance_blue, s_irradiance, uv, 0.0), basis),
+    );
 }
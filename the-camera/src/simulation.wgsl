@@ -0,0 +1,249 @@
+// The steps of a particle simulation, see simulation/mod.rs. One pass of kernels per step: N-bodies run gravity and
+// integrate, SPH fluids clear, count, scan and scatter their grid before density, forces and integrate.
+
+// See simulation/pass.rs
+struct Parameters {
+    bounds_min: vec3<f32>,
+    time_step: f32,
+    bounds_max: vec3<f32>,
+    restitution: f32,
+    gravity: vec3<f32>,
+    smoothing_radius: f32,
+    count: u32,
+    // Cells of the hashing grid, a power of two
+    table_size: u32,
+    bounded: u32,
+    rest_density: f32,
+    stiffness: f32,
+    viscosity: f32,
+    softening: f32,
+    gravitational_constant: f32,
+}
+@group(0) @binding(0)
+var<uniform> parameters: Parameters;
+
+struct Particle {
+    position: vec3<f32>,
+    mass: f32,
+    velocity: vec3<f32>,
+    density: f32,
+    acceleration: vec3<f32>,
+}
+@group(0) @binding(1)
+var<storage, read_write> particles: array<Particle>;
+
+// The particle count of every cell, then where the cell starts in sorted, with the particle count after the last
+@group(0) @binding(2)
+var<storage, read_write> cells: array<atomic<u32>>;
+// Particles ordered by their cell
+@group(0) @binding(3)
+var<storage, read_write> sorted: array<u32>;
+// The cell of every particle and how many came before it in the cell
+@group(0) @binding(4)
+var<storage, read_write> keys: array<vec2<u32>>;
+
+const PI: f32 = 3.14159265;
+// Threads of the scan and the gravity tiles
+const WORKGROUP_SIZE: u32 = 256u;
+
+fn cell_of(position: vec3<f32>) -> vec3<i32> {
+    return vec3<i32>(floor(position / parameters.smoothing_radius));
+}
+
+// Far apart cells share an entry, neighbors are told apart by their distance anyway
+fn hash(cell: vec3<i32>) -> u32 {
+    let c = bitcast<vec3<u32>>(cell);
+    return ((c.x * 73856093u) ^ (c.y * 19349663u) ^ (c.z * 83492791u)) & (parameters.table_size - 1u);
+}
+
+@compute @workgroup_size(256)
+fn clear(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x <= parameters.table_size {
+        atomicStore(&cells[id.x], 0u);
+    }
+}
+
+@compute @workgroup_size(256)
+fn count(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= parameters.count {
+        return;
+    }
+    let cell = hash(cell_of(particles[id.x].position));
+    keys[id.x] = vec2<u32>(cell, atomicAdd(&cells[cell], 1u));
+}
+
+var<workgroup> sums: array<u32, WORKGROUP_SIZE>;
+
+// Turns the counts into where each cell starts, in one workgroup. Every thread sums up its run of cells, the runs
+// are scanned in shared memory and every thread then writes the starts of its run.
+@compute @workgroup_size(256)
+fn scan(@builtin(local_invocation_index) thread: u32) {
+    let run = parameters.table_size / WORKGROUP_SIZE;
+    let first = thread * run;
+    var sum = 0u;
+    for (var index = 0u; index < run; index++) {
+        sum += atomicLoad(&cells[first + index]);
+    }
+    sums[thread] = sum;
+    workgroupBarrier();
+    for (var offset = 1u; offset < WORKGROUP_SIZE; offset <<= 1u) {
+        var before = 0u;
+        if thread >= offset {
+            before = sums[thread - offset];
+        }
+        workgroupBarrier();
+        sums[thread] += before;
+        workgroupBarrier();
+    }
+
+    var start = sums[thread] - sum;
+    for (var index = 0u; index < run; index++) {
+        let cell_count = atomicLoad(&cells[first + index]);
+        atomicStore(&cells[first + index], start);
+        start += cell_count;
+    }
+    if thread == WORKGROUP_SIZE - 1u {
+        atomicStore(&cells[parameters.table_size], start);
+    }
+}
+
+@compute @workgroup_size(256)
+fn scatter(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= parameters.count {
+        return;
+    }
+    let key = keys[id.x];
+    sorted[atomicLoad(&cells[key.x]) + key.y] = id.x;
+}
+
+// Density and forces both walk the particles in the 27 cells around their own
+@compute @workgroup_size(256)
+fn density(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= parameters.count {
+        return;
+    }
+    let h = parameters.smoothing_radius;
+    let h2 = h * h;
+    let poly6 = 315.0 / (64.0 * PI * pow(h, 9.0));
+    let position = particles[id.x].position;
+    let center = cell_of(position);
+    var density = 0.0;
+    for (var z = -1; z <= 1; z++) {
+        for (var y = -1; y <= 1; y++) {
+            for (var x = -1; x <= 1; x++) {
+                let cell = hash(center + vec3<i32>(x, y, z));
+                let end = atomicLoad(&cells[cell + 1u]);
+                for (var index = atomicLoad(&cells[cell]); index < end; index++) {
+                    let other = particles[sorted[index]];
+                    let offset = position - other.position;
+                    let r2 = dot(offset, offset);
+                    if r2 < h2 {
+                        density += other.mass * poly6 * pow(h2 - r2, 3.0);
+                    }
+                }
+            }
+        }
+    }
+    particles[id.x].density = density;
+}
+
+@compute @workgroup_size(256)
+fn forces(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= parameters.count {
+        return;
+    }
+    let h = parameters.smoothing_radius;
+    let spiky = -45.0 / (PI * pow(h, 6.0));
+    let laplacian = 45.0 / (PI * pow(h, 6.0));
+    let particle = particles[id.x];
+    let pressure = max(parameters.stiffness * (particle.density - parameters.rest_density), 0.0);
+    let center = cell_of(particle.position);
+    var pressure_force = vec3<f32>(0.0);
+    var viscosity_force = vec3<f32>(0.0);
+    for (var z = -1; z <= 1; z++) {
+        for (var y = -1; y <= 1; y++) {
+            for (var x = -1; x <= 1; x++) {
+                let cell = hash(center + vec3<i32>(x, y, z));
+                let end = atomicLoad(&cells[cell + 1u]);
+                for (var index = atomicLoad(&cells[cell]); index < end; index++) {
+                    let other_index = sorted[index];
+                    let other = particles[other_index];
+                    let offset = particle.position - other.position;
+                    let r = length(offset);
+                    if other_index == id.x || r >= h || other.density <= 0.0 {
+                        continue;
+                    }
+                    // Particles on the same spot push apart along any direction
+                    var direction = vec3<f32>(0.0, 1.0, 0.0);
+                    if r > 1e-6 {
+                        direction = offset / r;
+                    }
+                    let other_pressure = max(parameters.stiffness * (other.density - parameters.rest_density), 0.0);
+                    pressure_force -= direction * other.mass * (pressure + other_pressure) / (2.0 * other.density)
+                        * spiky * (h - r) * (h - r);
+                    viscosity_force += (other.velocity - particle.velocity) * other.mass / other.density
+                        * laplacian * (h - r);
+                }
+            }
+        }
+    }
+    var acceleration = parameters.gravity;
+    if particle.density > 0.0 {
+        acceleration += (pressure_force + parameters.viscosity * viscosity_force) / particle.density;
+    }
+    particles[id.x].acceleration = acceleration;
+}
+
+var<workgroup> tile: array<vec4<f32>, WORKGROUP_SIZE>;
+
+// Adds up the pull of every body, a tile of positions and masses loaded into shared memory at a time
+@compute @workgroup_size(256)
+fn gravity(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) thread: u32,
+) {
+    // Threads past the bodies still load tiles for the others
+    let index = min(id.x, parameters.count - 1u);
+    let position = particles[index].position;
+    let softening2 = parameters.softening * parameters.softening;
+    var acceleration = vec3<f32>(0.0);
+    for (var start = 0u; start < parameters.count; start += WORKGROUP_SIZE) {
+        let other = start + thread;
+        if other < parameters.count {
+            tile[thread] = vec4<f32>(particles[other].position, particles[other].mass);
+        } else {
+            tile[thread] = vec4<f32>(0.0);
+        }
+        workgroupBarrier();
+        for (var offset = 0u; offset < WORKGROUP_SIZE; offset++) {
+            let body = tile[offset];
+            let to_body = body.xyz - position;
+            // The body itself and padding pull with zero, even without softening
+            let distance2 = max(dot(to_body, to_body) + softening2, 1e-12);
+            acceleration += to_body * body.w * inverseSqrt(distance2 * distance2 * distance2);
+        }
+        workgroupBarrier();
+    }
+    if id.x < parameters.count {
+        particles[id.x].acceleration = acceleration * parameters.gravitational_constant;
+    }
+}
+
+// Semi-implicit Euler, then bouncing off the bounds
+@compute @workgroup_size(256)
+fn integrate(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= parameters.count {
+        return;
+    }
+    var particle = particles[id.x];
+    particle.velocity += particle.acceleration * parameters.time_step;
+    particle.position += particle.velocity * parameters.time_step;
+    if parameters.bounded != 0u {
+        let below = particle.position < parameters.bounds_min;
+        let above = particle.position > parameters.bounds_max;
+        let outside = below | above;
+        particle.position = clamp(particle.position, parameters.bounds_min, parameters.bounds_max);
+        particle.velocity = select(particle.velocity, -particle.velocity * parameters.restitution, outside);
+    }
+    particles[id.x] = particle;
+}
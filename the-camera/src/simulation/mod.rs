@@ -0,0 +1,351 @@
+//! Particle simulations stepped on the GPU every frame, gravitating N-bodies or SPH fluids.
+//!
+//! The particles live in a storage buffer that is only written by the CPU when they're set or reset. Each step
+//! computes the acceleration of every particle and integrates its velocity and position with it, bouncing off
+//! [ParticleSimulation::bounds]. N-bodies add up the gravity of every other body, a workgroup's worth at a time
+//! through shared memory. SPH fluids find their neighbors in a spatial hashing grid of cells a
+//! [SphParameters::smoothing_radius] wide instead: particles are counted into hashed cells, the counts are summed up
+//! into where each cell starts and the particles are sorted into their cells, so every particle only walks the 27
+//! cells around it. Density, pressure and viscosity then follow Müller et al., "Particle-Based Fluid Simulation for
+//! Interactive Applications".
+//!
+//! The main pass draws the particles as shaded spheres facing the camera straight from the storage buffer, colored
+//! by their speed through a [Colormap].
+//!
+//! Devices without compute shaders or storage buffers in vertex shaders, e.g. WebGL2, can't run simulations.
+
+mod pass;
+
+use std::f32::consts::PI;
+
+use wgpu::{CommandEncoder, Device, Queue, TextureFormat};
+
+use crate::{
+    colormap::{Colormap, ColormapTextures},
+    global_bindings::GlobalBindings,
+    lazy_pass::LazyPass,
+    render_engine_builder::DeviceReport,
+};
+
+pub(crate) use self::pass::SimulationPass;
+
+/// One particle as it's stored on the GPU
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Particle {
+    pub position: [f32; 3],
+    pub mass: f32,
+    pub velocity: [f32; 3],
+    /// Of the fluid around an SPH particle, written by the steps
+    density: f32,
+    /// Of the last step, consumed by the integration
+    acceleration: [f32; 3],
+    _padding: f32,
+}
+
+impl Particle {
+    pub fn new(position: [f32; 3], velocity: [f32; 3], mass: f32) -> Self {
+        Particle {
+            position,
+            mass,
+            velocity,
+            density: 0.0,
+            acceleration: [0.0; 3],
+            _padding: 0.0,
+        }
+    }
+}
+
+/// Parameters of an SPH fluid, in seconds, meters and kilograms
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SphParameters {
+    /// How far particles reach their neighbors, about twice their spacing at rest
+    pub smoothing_radius: f32,
+    /// Density the fluid settles at, 1000 for water
+    pub rest_density: f32,
+    /// Pressure per density above the rest density. Stiffer fluids compress less but need smaller time steps.
+    pub stiffness: f32,
+    /// Evens out the velocities of neighbors
+    pub viscosity: f32,
+    pub gravity: [f32; 3],
+}
+
+impl Default for SphParameters {
+    fn default() -> Self {
+        SphParameters {
+            smoothing_radius: 0.1,
+            rest_density: 1000.0,
+            stiffness: 200.0,
+            viscosity: 2.0,
+            gravity: [0.0, -9.81, 0.0],
+        }
+    }
+}
+
+/// What moves the particles of a [ParticleSimulation]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SimulationKind {
+    /// Every particle attracts every other, for a few ten thousand particles at most
+    NBody {
+        /// Scales the attraction, 6.674e-11 for real masses in meters
+        gravitational_constant: f32,
+        /// Distance added to every pair so close encounters don't fling particles off
+        softening: f32,
+    },
+    Sph(SphParameters),
+}
+
+/// Particles simulated on the GPU and drawn into every window and render target, added with
+/// [crate::render_engine::RenderEngine::add_simulation]
+#[derive(Debug, Clone)]
+pub struct ParticleSimulation {
+    particles: Vec<Particle>,
+    /// Counts resets of the particles, which are uploaded again when it moved on
+    revision: u64,
+    pub kind: SimulationKind,
+    /// Simulated seconds of each step
+    pub time_step: f32,
+    /// Steps run every frame, more keep stiff simulations stable at the same pace
+    pub steps_per_frame: u32,
+    /// Box from the min to the max corner the particles bounce off, None to let them fly
+    pub bounds: Option<([f32; 3], [f32; 3])>,
+    /// Part of the velocity kept when bouncing off the bounds
+    pub restitution: f32,
+    /// Stops stepping, the particles are still drawn where they are
+    pub paused: bool,
+    /// Of the drawn spheres, in world units
+    pub radius: f32,
+    pub colormap: Colormap,
+    /// Speeds mapped to the ends of the colormap
+    pub speed_range: [f32; 2],
+    pub visible: bool,
+}
+
+impl ParticleSimulation {
+    pub fn new(kind: SimulationKind, particles: Vec<Particle>) -> Self {
+        ParticleSimulation {
+            particles,
+            revision: 0,
+            kind,
+            time_step: 1.0 / 480.0,
+            steps_per_frame: 8,
+            bounds: None,
+            restitution: 0.5,
+            paused: false,
+            radius: 0.02,
+            colormap: Colormap::VIRIDIS,
+            speed_range: [0.0, 2.0],
+            visible: true,
+        }
+    }
+
+    /// A block of fluid at rest filling the box from `min` to `max`, with particles half a smoothing radius apart
+    /// and as heavy as the rest density asks for. Bounded by the same box, which the fluid collapses in unless it's
+    /// set larger.
+    pub fn fluid_block(parameters: SphParameters, min: [f32; 3], max: [f32; 3]) -> Self {
+        let spacing = parameters.smoothing_radius * 0.5;
+        let mass = parameters.rest_density * spacing.powi(3);
+        let counts =
+            [0, 1, 2].map(|axis| ((max[axis] - min[axis]) / spacing).floor().max(1.0) as usize);
+        let mut particles = Vec::with_capacity(counts.iter().product());
+        for z in 0..counts[2] {
+            for y in 0..counts[1] {
+                for x in 0..counts[0] {
+                    let position = [x, y, z].map(|index| (index as f32 + 0.5) * spacing);
+                    let position = std::array::from_fn(|axis| min[axis] + position[axis]);
+                    particles.push(Particle::new(position, [0.0; 3], mass));
+                }
+            }
+        }
+        ParticleSimulation::new(SimulationKind::Sph(parameters), particles)
+            .with_bounds(min, max)
+            .with_radius(spacing * 0.5)
+            .with_colormap(Colormap::TURBO)
+    }
+
+    /// `count` bodies of the same mass orbiting the origin in a disc of `radius` in the XZ plane, each as fast as the
+    /// mass closer in than it asks for. Spread along a golden angle spiral, so the disc looks the same every time.
+    pub fn disc(count: usize, radius: f32) -> Self {
+        let gravitational_constant = 1.0;
+        let softening = radius * 0.05;
+        let mass = 1.0 / count.max(1) as f32;
+        let golden_angle = PI * (3.0 - 5f32.sqrt());
+        let particles = (0..count)
+            .map(|index| {
+                let fraction = (index as f32 + 0.5) / count as f32;
+                let distance = radius * fraction.sqrt();
+                let angle = index as f32 * golden_angle;
+                let (sin, cos) = angle.sin_cos();
+                // Bodies closer in weigh as much as the disc's area closer in
+                let inner_mass = count as f32 * fraction * mass;
+                let speed = (gravitational_constant * inner_mass
+                    / (distance * distance + softening * softening).sqrt())
+                .sqrt();
+                Particle::new(
+                    [distance * cos, 0.0, distance * sin],
+                    [-speed * sin, 0.0, speed * cos],
+                    mass,
+                )
+            })
+            .collect();
+        ParticleSimulation::new(
+            SimulationKind::NBody {
+                gravitational_constant,
+                softening,
+            },
+            particles,
+        )
+        .with_radius(radius * 0.005)
+        .with_colormap(Colormap::MAGMA)
+        .with_speed_range([0.0, 1.5 / radius.sqrt()])
+    }
+
+    pub fn with_time_step(mut self, time_step: f32) -> Self {
+        self.time_step = time_step;
+        self
+    }
+
+    pub fn with_steps_per_frame(mut self, steps_per_frame: u32) -> Self {
+        self.steps_per_frame = steps_per_frame;
+        self
+    }
+
+    pub fn with_bounds(mut self, min: [f32; 3], max: [f32; 3]) -> Self {
+        self.bounds = Some((min, max));
+        self
+    }
+
+    pub fn with_restitution(mut self, restitution: f32) -> Self {
+        self.restitution = restitution;
+        self
+    }
+
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    pub fn with_colormap(mut self, colormap: Colormap) -> Self {
+        self.colormap = colormap;
+        self
+    }
+
+    pub fn with_speed_range(mut self, speed_range: [f32; 2]) -> Self {
+        self.speed_range = speed_range;
+        self
+    }
+
+    /// The particles as they were last set, the GPU moves them on without reading them back
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// Starts over from `particles`, they're uploaded before the next step
+    pub fn set_particles(&mut self, particles: Vec<Particle>) {
+        self.particles = particles;
+        self.revision += 1;
+    }
+
+    /// Starts over from the particles as they were last set
+    pub fn reset(&mut self) {
+        self.revision += 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SimulationHandle(pub(crate) usize);
+
+/// The engine's particle simulations and the pass stepping and drawing them, which is created with the first
+/// simulation
+#[derive(Default)]
+pub(crate) struct Simulations {
+    /// Unsupported where the device can't run particle simulations
+    pass: LazyPass<SimulationPass>,
+    /// None where a simulation was removed
+    simulations: Vec<Option<ParticleSimulation>>,
+}
+
+impl Simulations {
+    pub fn add(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        device_report: &DeviceReport,
+        simulation: ParticleSimulation,
+    ) -> SimulationHandle {
+        if self
+            .pass
+            .get_or_create(|| SimulationPass::new(device, format, global_bindings, device_report))
+            .is_none()
+        {
+            tracing::warn!(
+                "Particle simulations need compute shaders and vertex storage, it isn't run"
+            );
+        }
+        self.simulations.push(Some(simulation));
+        SimulationHandle(self.simulations.len() - 1)
+    }
+
+    pub fn get(&self, handle: SimulationHandle) -> Option<&ParticleSimulation> {
+        self.simulations.get(handle.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: SimulationHandle) -> Option<&mut ParticleSimulation> {
+        self.simulations.get_mut(handle.0)?.as_mut()
+    }
+
+    /// Frees the simulation's particles on the GPU
+    pub fn remove(&mut self, handle: SimulationHandle) -> Option<ParticleSimulation> {
+        let simulation = self.simulations.get_mut(handle.0)?.take()?;
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_simulation(handle.0);
+        }
+        Some(simulation)
+    }
+
+    /// Draws the particles in the main pass, None before the first simulation and where the device can't run them
+    pub fn pass(&self) -> Option<&SimulationPass> {
+        self.pass.get()
+    }
+
+    /// Whether there are simulations to step in the compute phase
+    pub fn is_active(&self) -> bool {
+        self.pass.get().is_some() && self.simulations.iter().any(Option::is_some)
+    }
+
+    /// Records the steps of the simulations that aren't paused, true while any of them is running
+    pub fn step(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        colormaps: &mut ColormapTextures,
+        encoder: &mut CommandEncoder,
+    ) -> bool {
+        let Some(pass) = self.pass.get_mut() else {
+            return false;
+        };
+        pass.step(device, queue, &self.simulations, colormaps, encoder);
+        self.simulations
+            .iter()
+            .flatten()
+            .any(|simulation| !simulation.paused && !simulation.particles().is_empty())
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.pass.get().map_or(0, SimulationPass::size_in_bytes)
+    }
+
+    /// Creates the pass again on a new device, the simulations start over from their particles as they were last set
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        device_report: &DeviceReport,
+    ) {
+        self.pass
+            .recreate(|| SimulationPass::new(device, format, global_bindings, device_report));
+    }
+}
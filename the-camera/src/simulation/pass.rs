@@ -0,0 +1,504 @@
+use std::collections::HashMap;
+
+use wgpu::{
+    BindGroup, Buffer, CommandEncoder, Device, Queue, RenderPass, RenderPipeline, TextureFormat,
+};
+
+use super::{Particle, ParticleSimulation, SimulationKind};
+use crate::{
+    colormap::{Colormap, ColormapTextures},
+    global_bindings::GlobalBindings,
+    profiler::RenderStats,
+    render_engine_builder::DeviceReport,
+    texture,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        compute::{ComputeKernel, ComputeKernelBuilder, ComputePassBuilder},
+        uniform_buffer::UniformBuffer,
+    },
+};
+
+/// Has to match `WORKGROUP_SIZE` in simulation.wgsl, the scan needs at least as many cells
+const WORKGROUP_SIZE: u32 = 256;
+
+/// Per simulation parameters of the steps
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SimulationUBOContent {
+    bounds_min: [f32; 3],
+    time_step: f32,
+    bounds_max: [f32; 3],
+    restitution: f32,
+    gravity: [f32; 3],
+    smoothing_radius: f32,
+    count: u32,
+    table_size: u32,
+    bounded: u32,
+    rest_density: f32,
+    stiffness: f32,
+    viscosity: f32,
+    softening: f32,
+    gravitational_constant: f32,
+}
+
+crate::assert_uniform_layout!(SimulationUBOContent {
+    bounds_min: ALIGN_VEC4,
+    time_step: ALIGN_SCALAR,
+    bounds_max: ALIGN_VEC4,
+    restitution: ALIGN_SCALAR,
+    gravity: ALIGN_VEC4,
+    smoothing_radius: ALIGN_SCALAR,
+    count: ALIGN_SCALAR,
+    table_size: ALIGN_SCALAR,
+    bounded: ALIGN_SCALAR,
+    rest_density: ALIGN_SCALAR,
+    stiffness: ALIGN_SCALAR,
+    viscosity: ALIGN_SCALAR,
+    softening: ALIGN_SCALAR,
+    gravitational_constant: ALIGN_SCALAR,
+});
+
+/// Per simulation draw data, bound at group 1
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DrawUBOContent {
+    radius: f32,
+    low_speed: f32,
+    high_speed: f32,
+    _padding: f32,
+}
+
+crate::assert_uniform_layout!(DrawUBOContent {
+    radius: ALIGN_SCALAR,
+    low_speed: ALIGN_SCALAR,
+    high_speed: ALIGN_SCALAR,
+});
+
+struct GpuSimulation {
+    parameters: UniformBuffer<SimulationUBOContent>,
+    draw_ubo: UniformBuffer<DrawUBOContent>,
+    particles: Buffer,
+    /// The hashing grid, a few bytes for N-bodies which don't use it
+    cells: Buffer,
+    sorted: Buffer,
+    keys: Buffer,
+    count: u32,
+    table_size: u32,
+    /// [ParticleSimulation::revision] of the uploaded particles
+    revision: u64,
+    sph: bool,
+    step_bind_group: BindGroup,
+    draw_bind_group: BindGroup,
+    /// [ParticleSimulation::colormap] of the draw bind group
+    colormap: Colormap,
+    visible: bool,
+}
+
+/// Steps and draws every particle simulation, shared by every view
+pub(crate) struct SimulationPass {
+    clear: ComputeKernel,
+    count: ComputeKernel,
+    scan: ComputeKernel,
+    scatter: ComputeKernel,
+    density: ComputeKernel,
+    forces: ComputeKernel,
+    gravity: ComputeKernel,
+    integrate: ComputeKernel,
+    step_bind_group_layout: BindGroupLayoutWithDesc,
+    draw_bind_group_layout: BindGroupLayoutWithDesc,
+    pipeline: RenderPipeline,
+    simulations: HashMap<usize, GpuSimulation>,
+    /// Particle counts of the simulations too large for the device's storage buffers, so they're only reported once
+    too_large: HashMap<usize, usize>,
+}
+
+impl SimulationPass {
+    /// None where the device can't run the steps or draw what they write. `format` has to be the engine's swapchain
+    /// format, since the particles are drawn in the main pass.
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        device_report: &DeviceReport,
+    ) -> Option<Self> {
+        let required = wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::VERTEX_STORAGE;
+        if !device_report.downlevel_flags.contains(required) {
+            tracing::debug!("Particle simulations need compute shaders and vertex storage");
+            return None;
+        }
+        let _span = tracing::debug_span!("create_simulation_pipelines").entered();
+
+        let step_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_types::uniform())
+            .named("parameters")
+            .next_binding_compute(binding_types::buffer(false))
+            .named("particles")
+            .next_binding_compute(binding_types::buffer(false))
+            .named("cells")
+            .next_binding_compute(binding_types::buffer(false))
+            .named("sorted")
+            .next_binding_compute(binding_types::buffer(false))
+            .named("keys")
+            .create(device, "Simulation Step Bind Group");
+        let source = include_str!("../simulation.wgsl");
+        let kernel = |entry_point, label| {
+            ComputeKernelBuilder::new(source)
+                .entry_point(entry_point)
+                .workgroup_size([WORKGROUP_SIZE, 1, 1])
+                .bind_group_layout(&step_bind_group_layout.layout)
+                .create(device, label)
+        };
+        let clear = kernel("clear", "simulation grid clear");
+        let count = kernel("count", "simulation grid count");
+        let scan = kernel("scan", "simulation grid scan");
+        let scatter = kernel("scatter", "simulation grid scatter");
+        let density = kernel("density", "simulation density");
+        let forces = kernel("forces", "simulation forces");
+        let gravity = kernel("gravity", "simulation gravity");
+        let integrate = kernel("integrate", "simulation integration");
+
+        let draw_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_vertex(binding_types::uniform())
+            .next_binding_vertex(binding_types::buffer(true))
+            .next_binding_vertex(binding_types::texture2D())
+            .next_binding_vertex(binding_types::sampler(wgpu::SamplerBindingType::Filtering))
+            .create(device, "Simulation Bind Group");
+        let pipeline = create_pipeline(device, format, global_bindings, &draw_bind_group_layout);
+
+        Some(SimulationPass {
+            clear,
+            count,
+            scan,
+            scatter,
+            density,
+            forces,
+            gravity,
+            integrate,
+            step_bind_group_layout,
+            draw_bind_group_layout,
+            pipeline,
+            simulations: HashMap::new(),
+            too_large: HashMap::new(),
+        })
+    }
+
+    /// Uploads whatever changed about the simulations and records the steps of those that aren't paused
+    pub fn step(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        simulations: &[Option<ParticleSimulation>],
+        colormaps: &mut ColormapTextures,
+        encoder: &mut CommandEncoder,
+    ) {
+        let limit = device.limits().max_storage_buffer_binding_size as u64;
+        let mut stepped = Vec::new();
+        for (index, simulation) in simulations.iter().enumerate() {
+            let Some(simulation) = simulation else {
+                continue;
+            };
+            let count = simulation.particles.len();
+            if count == 0 {
+                self.simulations.remove(&index);
+                continue;
+            }
+            let sph = matches!(simulation.kind, SimulationKind::Sph(_));
+            let table_size = if sph {
+                (count as u32 * 2).next_power_of_two().max(WORKGROUP_SIZE)
+            } else {
+                WORKGROUP_SIZE
+            };
+            let size = count as u64 * std::mem::size_of::<Particle>() as u64;
+            if size > limit || (table_size as u64 + 1) * 4 > limit {
+                if self.too_large.insert(index, count) != Some(count) {
+                    tracing::error!(
+                        "Simulation of {count} particles is larger than the device's storage buffers of {limit} \
+                         bytes, it isn't run"
+                    );
+                }
+                self.simulations.remove(&index);
+                continue;
+            }
+
+            let stale = self
+                .simulations
+                .get(&index)
+                .is_none_or(|uploaded| uploaded.count != count as u32 || uploaded.sph != sph);
+            if stale {
+                let uploaded = self.upload(device, queue, colormaps, simulation, table_size);
+                self.simulations.insert(index, uploaded);
+            }
+            let uploaded = self
+                .simulations
+                .get_mut(&index)
+                .expect("Simulation was just uploaded!");
+            if uploaded.revision != simulation.revision || stale {
+                queue.write_buffer(
+                    &uploaded.particles,
+                    0,
+                    bytemuck::cast_slice(&simulation.particles),
+                );
+                uploaded.revision = simulation.revision;
+            }
+            if uploaded.colormap != simulation.colormap {
+                let (colormap, sampler) = colormaps.binding(device, queue, simulation.colormap);
+                uploaded.draw_bind_group = BindGroupBuilder::new(&self.draw_bind_group_layout)
+                    .resource(uploaded.draw_ubo.binding_resource())
+                    .buffer(&uploaded.particles)
+                    .texture(colormap)
+                    .sampler(sampler)
+                    .create(device, "Simulation Bind Group");
+                uploaded.colormap = simulation.colormap;
+            }
+            uploaded.visible = simulation.visible;
+            uploaded
+                .parameters
+                .update_content(queue, parameters(simulation, table_size));
+            uploaded.draw_ubo.update_content(
+                queue,
+                DrawUBOContent {
+                    radius: simulation.radius,
+                    low_speed: simulation.speed_range[0],
+                    high_speed: simulation.speed_range[1],
+                    _padding: 0.0,
+                },
+            );
+            if !simulation.paused {
+                stepped.push((index, simulation.steps_per_frame));
+            }
+        }
+
+        let mut pass = ComputePassBuilder::new("simulations");
+        for (index, steps) in stepped {
+            let uploaded = &self.simulations[&index];
+            let bind_group = &uploaded.step_bind_group;
+            let particles = [uploaded.count, 1, 1];
+            for _ in 0..steps {
+                if uploaded.sph {
+                    pass = pass
+                        .dispatch(&self.clear, &[bind_group], [uploaded.table_size + 1, 1, 1])
+                        .dispatch(&self.count, &[bind_group], particles)
+                        .dispatch_workgroups(&self.scan, &[bind_group], [1, 1, 1])
+                        .dispatch(&self.scatter, &[bind_group], particles)
+                        .dispatch(&self.density, &[bind_group], particles)
+                        .dispatch(&self.forces, &[bind_group], particles);
+                } else {
+                    pass = pass.dispatch(&self.gravity, &[bind_group], particles);
+                }
+                pass = pass.dispatch(&self.integrate, &[bind_group], particles);
+            }
+        }
+        pass.record(encoder);
+    }
+
+    fn upload(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        colormaps: &mut ColormapTextures,
+        simulation: &ParticleSimulation,
+        table_size: u32,
+    ) -> GpuSimulation {
+        let count = simulation.particles.len() as u64;
+        let sph = matches!(simulation.kind, SimulationKind::Sph(_));
+        let buffer = |label, size| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            })
+        };
+        let particles = buffer(
+            "Simulation Particles",
+            count * std::mem::size_of::<Particle>() as u64,
+        );
+        let cells = buffer("Simulation Cells", (table_size as u64 + 1) * 4);
+        // Only SPH fluids sort their particles
+        let sorted_count = if sph { count } else { 1 };
+        let sorted = buffer("Simulation Sorted Particles", sorted_count * 4);
+        let keys = buffer("Simulation Particle Cells", sorted_count * 8);
+
+        let parameters = UniformBuffer::new(device);
+        let draw_ubo = UniformBuffer::new(device);
+        let step_bind_group = BindGroupBuilder::new(&self.step_bind_group_layout)
+            .resource(parameters.binding_resource())
+            .buffer(&particles)
+            .buffer(&cells)
+            .buffer(&sorted)
+            .buffer(&keys)
+            .create(device, "Simulation Step Bind Group");
+        let (colormap, sampler) = colormaps.binding(device, queue, simulation.colormap);
+        let draw_bind_group = BindGroupBuilder::new(&self.draw_bind_group_layout)
+            .resource(draw_ubo.binding_resource())
+            .buffer(&particles)
+            .texture(colormap)
+            .sampler(sampler)
+            .create(device, "Simulation Bind Group");
+        GpuSimulation {
+            parameters,
+            draw_ubo,
+            particles,
+            cells,
+            sorted,
+            keys,
+            count: count as u32,
+            table_size,
+            revision: simulation.revision,
+            sph,
+            step_bind_group,
+            draw_bind_group,
+            colormap: simulation.colormap,
+            visible: simulation.visible,
+        }
+    }
+
+    pub fn remove_simulation(&mut self, index: usize) {
+        self.simulations.remove(&index);
+        self.too_large.remove(&index);
+    }
+
+    /// Bytes of the particles and the hashing grids
+    pub fn size_in_bytes(&self) -> u64 {
+        self.simulations
+            .values()
+            .map(|simulation| {
+                simulation.particles.size()
+                    + simulation.cells.size()
+                    + simulation.sorted.size()
+                    + simulation.keys.size()
+            })
+            .sum()
+    }
+
+    /// Draws the particles where the last steps left them, in the main pass
+    pub fn draw(&self, render_pass: &mut RenderPass, globals: &BindGroup, stats: &mut RenderStats) {
+        if !self
+            .simulations
+            .values()
+            .any(|simulation| simulation.visible)
+        {
+            return;
+        }
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, globals, &[]);
+        stats.pipeline_switches += 1;
+        stats.bind_group_switches += 1;
+        for simulation in self
+            .simulations
+            .values()
+            .filter(|simulation| simulation.visible)
+        {
+            render_pass.set_bind_group(1, &simulation.draw_bind_group, &[]);
+            render_pass.draw(0..6, 0..simulation.count);
+            stats.bind_group_switches += 1;
+            stats.draw(6, simulation.count);
+        }
+    }
+}
+
+fn parameters(simulation: &ParticleSimulation, table_size: u32) -> SimulationUBOContent {
+    let (bounds_min, bounds_max) = simulation.bounds.unwrap_or_default();
+    let mut content = SimulationUBOContent {
+        bounds_min,
+        time_step: simulation.time_step,
+        bounds_max,
+        restitution: simulation.restitution,
+        gravity: [0.0; 3],
+        // Keeps the cells of N-bodies finite, they're never looked at
+        smoothing_radius: 1.0,
+        count: simulation.particles.len() as u32,
+        table_size,
+        bounded: simulation.bounds.is_some() as u32,
+        rest_density: 0.0,
+        stiffness: 0.0,
+        viscosity: 0.0,
+        softening: 0.0,
+        gravitational_constant: 0.0,
+    };
+    match simulation.kind {
+        SimulationKind::NBody {
+            gravitational_constant,
+            softening,
+        } => {
+            content.gravitational_constant = gravitational_constant;
+            content.softening = softening;
+        }
+        SimulationKind::Sph(sph) => {
+            content.gravity = sph.gravity;
+            content.smoothing_radius = sph.smoothing_radius;
+            content.rest_density = sph.rest_density;
+            content.stiffness = sph.stiffness;
+            content.viscosity = sph.viscosity;
+        }
+    }
+    content
+}
+
+/// Camera facing quads, six vertices for every particle instance
+fn create_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    global_bindings: &GlobalBindings,
+    bind_group_layout: &BindGroupLayoutWithDesc,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Simulation Particles Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../simulation_particles.wgsl").into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Simulation Particles Pipeline Layout"),
+        bind_group_layouts: &[
+            global_bindings.bind_group_layouts(),
+            &bind_group_layout.layout,
+        ],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Simulation Particles Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+            unclipped_depth: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: texture::Texture::ID_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
+            compilation_options: Default::default(),
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
@@ -0,0 +1,118 @@
+// See clipping.rs, the same as in shader.wgsl
+struct Clipping {
+    planes: array<vec4<f32>, 4>,
+    cap_color: vec4<f32>,
+    count: u32,
+    caps: u32,
+}
+
+struct Audio {
+    bands: array<vec4<f32>, 4>,
+    level: f32,
+}
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    clipping: Clipping,
+    audio: Audio,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// One simulation, see simulation/pass.rs
+struct Draw {
+    radius: f32,
+    low_speed: f32,
+    high_speed: f32,
+}
+@group(1) @binding(0)
+var<uniform> draw: Draw;
+
+// The same as in simulation.wgsl
+struct Particle {
+    position: vec3<f32>,
+    mass: f32,
+    velocity: vec3<f32>,
+    density: f32,
+    acceleration: vec3<f32>,
+}
+@group(1) @binding(1)
+var<storage, read> particles: array<Particle>;
+@group(1) @binding(2)
+var colormap: texture_2d<f32>;
+@group(1) @binding(3)
+var colormap_sampler: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    @location(1) world_position: vec3<f32>,
+    // From -1 to 1 across the sphere
+    @location(2) corner: vec2<f32>,
+};
+
+// Two triangles making up the quad of a particle, like the splats of point_cloud.wgsl
+const CORNERS = array<vec2<f32>, 6>(
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(1.0, -1.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(-1.0, -1.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(-1.0, 1.0),
+);
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    let particle = particles[instance];
+    // Facing the camera, with another up axis when looking straight up or down
+    let forward = normalize(particle.position - camera.view_pos.xyz);
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(forward.y) > 0.99 {
+        up = vec3<f32>(0.0, 0.0, 1.0);
+    }
+    let right = normalize(cross(forward, up));
+    up = cross(right, forward);
+
+    // Between the centers of the first and the last texel, see colormap.rs
+    let speed = length(particle.velocity);
+    let t = clamp((speed - draw.low_speed) / max(draw.high_speed - draw.low_speed, 1e-20), 0.0, 1.0);
+    let texels = f32(textureDimensions(colormap).x);
+    let uv = vec2<f32>((t * (texels - 1.0) + 0.5) / texels, 0.5);
+
+    let corner = CORNERS[index];
+    let world_position = particle.position + (right * corner.x + up * corner.y) * draw.radius;
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.color = textureSampleLevel(colormap, colormap_sampler, uv, 0.0).rgb;
+    out.world_position = world_position;
+    out.corner = corner;
+    return out;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    // Particles can't be picked
+    @location(1) id: u32,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    let r2 = dot(in.corner, in.corner);
+    if r2 > 1.0 {
+        discard;
+    }
+    for (var index = 0u; index < camera.clipping.count; index++) {
+        let plane = camera.clipping.planes[index];
+        if dot(plane.xyz, in.world_position) < plane.w {
+            discard;
+        }
+    }
+
+    // Lit from the camera, brightest where the sphere faces it
+    let light = 0.35 + 0.65 * sqrt(1.0 - r2);
+    var out: FragmentOutput;
+    out.color = vec4<f32>(in.color * light, 1.0);
+    out.id = 0u;
+    return out;
+}
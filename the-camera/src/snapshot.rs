@@ -0,0 +1,340 @@
+//! Golden image tests: render a scene without a window from a fixed camera, read the pixels back and compare them
+//! against a reference PNG.
+//!
+//! ```no_run
+//! # use the_camera::{camera::orbit_camera::OrbitCamera, scene::{Renderable, Transform}, snapshot::SnapshotHarness};
+//! let camera = OrbitCamera::new(3.0, 0.3, 0.5, cgmath::Vector3::new(0.0, 0.0, 0.0), 1.0);
+//! let mut harness = SnapshotHarness::new(256, 256, camera, "tests/snapshots");
+//! let engine = harness.engine();
+//! let cube = Renderable::new(engine.cube_mesh(), engine.default_material(), &Transform::default());
+//! engine.set_renderables(vec![cube]);
+//! harness.assert_snapshot("cube");
+//! ```
+//!
+//! A missing reference is created from the first render. Set `UPDATE_SNAPSHOTS=1` to overwrite the references after
+//! an intended change. On a mismatch the render and a diff highlighting the differing pixels in red are written next
+//! to the reference as `<name>.actual.png` and `<name>.diff.png`. The engine's own golden images are in
+//! `tests/snapshots`.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    assets::decode_png, camera::orbit_camera::OrbitCamera, render_engine::RenderEngine,
+    render_engine_builder::RenderEngineBuilder, render_target::RenderTargetHandle,
+    screenshot::write_png, texture::TextureData,
+};
+
+/// Largest possible [color_delta], between red and cyan
+const MAX_COLOR_DELTA: f32 = 35215.0;
+
+/// How different a render may be from its reference and still pass
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+    /// Perceptual color difference from 0 (identical) to 1 (red and cyan) above which a pixel counts as
+    /// different. The default of 0.1 hides rounding differences between GPUs and drivers.
+    pub color_threshold: f32,
+    /// Fraction of pixels that may differ, e.g. along antialiased edges
+    pub max_differing_fraction: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance {
+            color_threshold: 0.1,
+            max_differing_fraction: 0.001,
+        }
+    }
+}
+
+/// The result of [compare_images]
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub differing_pixels: usize,
+    pub total_pixels: usize,
+    /// The reference faded to gray with the differing pixels in red
+    pub diff: TextureData,
+}
+
+impl Comparison {
+    pub fn passes(&self, tolerance: &Tolerance) -> bool {
+        self.differing_pixels as f32 <= self.total_pixels as f32 * tolerance.max_differing_fraction
+    }
+}
+
+/// Compares two images of the same size pixel by pixel, in a color space closer to how different colors look than
+/// RGB is.
+///
+/// # Panics
+///
+/// When the sizes differ.
+pub fn compare_images(
+    actual: &TextureData,
+    reference: &TextureData,
+    tolerance: &Tolerance,
+) -> Comparison {
+    assert_eq!(
+        (actual.width, actual.height),
+        (reference.width, reference.height),
+        "Images to compare have different sizes!"
+    );
+
+    let max_delta = MAX_COLOR_DELTA * tolerance.color_threshold * tolerance.color_threshold;
+    let mut differing_pixels = 0;
+    let mut diff = Vec::with_capacity(reference.rgba.len());
+    for (a, b) in actual
+        .rgba
+        .chunks_exact(4)
+        .zip(reference.rgba.chunks_exact(4))
+    {
+        if color_delta(a, b) > max_delta {
+            differing_pixels += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let [r, g, b] = blend_with_white(b);
+            let luma = (r * 0.299 + g * 0.587 + b * 0.114) as u8;
+            // Faded, so the red stands out
+            let faded = 255 - (255 - luma) / 4;
+            diff.extend_from_slice(&[faded, faded, faded, 255]);
+        }
+    }
+
+    Comparison {
+        differing_pixels,
+        total_pixels: reference.rgba.len() / 4,
+        diff: TextureData {
+            width: reference.width,
+            height: reference.height,
+            rgba: diff,
+        },
+    }
+}
+
+/// Squared distance of two RGBA8 pixels in YIQ space, weighted like pixelmatch does
+fn color_delta(a: &[u8], b: &[u8]) -> f32 {
+    let [r1, g1, b1] = blend_with_white(a);
+    let [r2, g2, b2] = blend_with_white(b);
+    let y = |r: f32, g: f32, b: f32| r * 0.298_895_3 + g * 0.586_622_5 + b * 0.114_482_23;
+    let i = |r: f32, g: f32, b: f32| r * 0.595_977_99 - g * 0.274_176_1 - b * 0.321_801_9;
+    let q = |r: f32, g: f32, b: f32| r * 0.211_470_17 - g * 0.522_617_1 + b * 0.311_146_94;
+
+    let dy = y(r1, g1, b1) - y(r2, g2, b2);
+    let di = i(r1, g1, b1) - i(r2, g2, b2);
+    let dq = q(r1, g1, b1) - q(r2, g2, b2);
+    0.5053 * dy * dy + 0.299 * di * di + 0.1957 * dq * dq
+}
+
+/// Transparent pixels are compared as they'd look on a white page
+fn blend_with_white(pixel: &[u8]) -> [f32; 3] {
+    let alpha = pixel[3] as f32 / 255.0;
+    [0, 1, 2].map(|channel| 255.0 + (pixel[channel] as f32 - 255.0) * alpha)
+}
+
+/// Renders a headless engine into an offscreen target and checks the result against references on disk
+pub struct SnapshotHarness {
+    engine: RenderEngine,
+    target: RenderTargetHandle,
+    reference_dir: PathBuf,
+    tolerance: Tolerance,
+}
+
+impl SnapshotHarness {
+    /// Creates a headless engine drawing `width` x `height` images from `camera`, with the references stored in
+    /// `reference_dir`.
+    ///
+    /// # Panics
+    ///
+    /// When there is no adapter, e.g. on CI machines without a GPU or software renderer.
+    pub fn new(
+        width: u32,
+        height: u32,
+        mut camera: OrbitCamera,
+        reference_dir: impl Into<PathBuf>,
+    ) -> Self {
+        camera.resize_projection(width, height);
+        let mut engine = pollster::block_on(
+            RenderEngineBuilder::new().build_headless(wgpu::TextureFormat::Rgba8Unorm),
+        );
+        let target = engine.add_render_target(width, height, Some(camera));
+
+        SnapshotHarness {
+            engine,
+            target,
+            reference_dir: reference_dir.into(),
+            tolerance: Tolerance::default(),
+        }
+    }
+
+    pub fn tolerance(mut self, tolerance: Tolerance) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Set up the scene through this
+    pub fn engine(&mut self) -> &mut RenderEngine {
+        &mut self.engine
+    }
+
+    /// Runs one update and reads the image back
+    pub fn render(&mut self) -> TextureData {
+        self.engine.update();
+        let target = self
+            .engine
+            .render_target(self.target)
+            .expect("Snapshot target exists!");
+        let (width, height) = target.size();
+        let rgba = self
+            .engine
+            .read_render_target(self.target)
+            .expect("Failed to read back snapshot!");
+        TextureData {
+            width,
+            height,
+            rgba,
+        }
+    }
+
+    /// Renders and compares against `<reference_dir>/<name>.png`, see the [module docs](self)
+    ///
+    /// # Panics
+    ///
+    /// When the render differs from the reference by more than the tolerance.
+    pub fn assert_snapshot(&mut self, name: &str) {
+        let actual = self.render();
+        let reference_path = self.reference_dir.join(format!("{name}.png"));
+        let update = std::env::var_os("UPDATE_SNAPSHOTS").is_some_and(|value| value != "0");
+
+        if update || !reference_path.exists() {
+            std::fs::create_dir_all(&self.reference_dir)
+                .expect("Failed to create snapshot directory!");
+            save(&reference_path, &actual);
+            tracing::warn!("Wrote snapshot reference {}", reference_path.display());
+            return;
+        }
+
+        let reference = load_png(&reference_path)
+            .unwrap_or_else(|err| panic!("Failed to read {}: {err}", reference_path.display()));
+        let actual_path = self.reference_dir.join(format!("{name}.actual.png"));
+        if (actual.width, actual.height) != (reference.width, reference.height) {
+            save(&actual_path, &actual);
+            panic!(
+                "Snapshot {name} is {}x{}, the reference is {}x{}. The render was saved to {}",
+                actual.width,
+                actual.height,
+                reference.width,
+                reference.height,
+                actual_path.display()
+            );
+        }
+
+        let comparison = compare_images(&actual, &reference, &self.tolerance);
+        if !comparison.passes(&self.tolerance) {
+            let diff_path = self.reference_dir.join(format!("{name}.diff.png"));
+            save(&actual_path, &actual);
+            save(&diff_path, &comparison.diff);
+            panic!(
+                "Snapshot {name} differs from the reference in {} of {} pixels. See {} and {}",
+                comparison.differing_pixels,
+                comparison.total_pixels,
+                actual_path.display(),
+                diff_path.display()
+            );
+        }
+    }
+}
+
+fn save(path: &Path, image: &TextureData) {
+    write_png(path, image.width, image.height, &image.rgba)
+        .unwrap_or_else(|err| panic!("Failed to write {}: {err}", path.display()));
+}
+
+/// Reads a PNG as 8 bit RGBA, whatever color type it was saved with
+pub fn load_png(path: &Path) -> io::Result<TextureData> {
+    decode_png(&std::fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, height: u32, pixel: [u8; 4]) -> TextureData {
+        TextureData {
+            width,
+            height,
+            rgba: pixel.repeat((width * height) as usize),
+        }
+    }
+
+    #[test]
+    fn identical_images_pass() {
+        let reference = image(4, 4, [10, 200, 30, 255]);
+        let comparison = compare_images(&reference, &reference, &Tolerance::default());
+        assert_eq!(comparison.differing_pixels, 0);
+        assert_eq!(comparison.total_pixels, 16);
+        assert!(comparison.passes(&Tolerance::default()));
+        assert!(comparison
+            .diff
+            .rgba
+            .chunks_exact(4)
+            .all(|pixel| pixel[0] != 255 || pixel[1] != 0));
+    }
+
+    #[test]
+    fn differing_pixels_are_counted_and_marked_red() {
+        let reference = image(4, 4, [255; 4]);
+        let mut actual = reference.clone();
+        actual.rgba[20..24].copy_from_slice(&[0, 0, 0, 255]);
+        let comparison = compare_images(&actual, &reference, &Tolerance::default());
+        assert_eq!(comparison.differing_pixels, 1);
+        assert!(!comparison.passes(&Tolerance::default()));
+        assert_eq!(comparison.diff.rgba[20..24], [255, 0, 0, 255]);
+        assert_eq!(comparison.diff.rgba[0..4], [255; 4]);
+
+        // One in 16 may differ
+        let lenient = Tolerance {
+            max_differing_fraction: 1.0 / 16.0,
+            ..Default::default()
+        };
+        assert!(comparison.passes(&lenient));
+    }
+
+    #[test]
+    fn small_color_changes_are_within_the_threshold() {
+        let reference = image(2, 2, [100, 100, 100, 255]);
+        let tolerance = Tolerance::default();
+        let close = image(2, 2, [102, 101, 99, 255]);
+        assert_eq!(
+            compare_images(&close, &reference, &tolerance).differing_pixels,
+            0
+        );
+        let far = image(2, 2, [160, 100, 100, 255]);
+        assert_eq!(
+            compare_images(&far, &reference, &tolerance).differing_pixels,
+            4
+        );
+        // Red and cyan differ by the largest delta, more than black and white do
+        let delta = color_delta(&[255, 0, 0, 255], &[0, 255, 255, 255]);
+        assert_eq!(delta.round(), MAX_COLOR_DELTA);
+        assert!(color_delta(&[0, 0, 0, 255], &[255; 4]) < delta);
+    }
+
+    #[test]
+    fn transparent_pixels_look_white() {
+        let transparent = image(1, 1, [0, 0, 0, 0]);
+        let white = image(1, 1, [255; 4]);
+        let comparison = compare_images(&transparent, &white, &Tolerance::default());
+        assert_eq!(comparison.differing_pixels, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "different sizes")]
+    fn images_of_different_sizes_panic() {
+        compare_images(
+            &image(2, 2, [0; 4]),
+            &image(2, 3, [0; 4]),
+            &Tolerance::default(),
+        );
+    }
+}
@@ -0,0 +1,159 @@
+//! Keeps the camera and selection of running engines in sync over websockets, e.g. a presenter and their audience
+//! or a native app and a wasm viewer, for reviewing a scene together. Only with the `sync` feature.
+//!
+//! One instance listens and every other one connects to it. Changes made in any of them reach all the others:
+//!
+//! ```ignore
+//! // The presenter
+//! engine.set_scene_sync(Some(SceneSync::new(WebSocketServer::bind("0.0.0.0:9001")?)));
+//! // Everyone else, natively or in the browser
+//! engine.set_scene_sync(Some(SceneSync::new(WebSocketClient::connect("ws://presenter:9001")?)));
+//! ```
+//!
+//! The camera synced is the one of the focused window, or any window without one. Every instance needs the same
+//! renderables in the same order, the selection is sent by position in that list.
+
+#[cfg(target_arch = "wasm32")]
+mod web;
+#[cfg(not(target_arch = "wasm32"))]
+mod websocket;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(target_arch = "wasm32")]
+pub use web::WebSocketClient;
+#[cfg(not(target_arch = "wasm32"))]
+pub use websocket::{WebSocketClient, WebSocketServer};
+
+use crate::{camera::orbit_camera::Projection, render_engine::RenderEngine};
+
+/// Carries messages between instances, one message is one string
+pub trait SyncTransport {
+    /// To every instance on the other end
+    fn send(&mut self, message: &str);
+
+    /// Everything that arrived since the last call, oldest first
+    fn receive(&mut self) -> Vec<String>;
+}
+
+/// How an orbit camera is placed, without what depends on the window like the aspect ratio
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CameraPose {
+    pub yaw: f32,
+    pub pitch: f32,
+    pub distance: f32,
+    pub target: [f32; 3],
+    /// Vertical field of view in radians
+    pub fov: f32,
+    pub orthographic: bool,
+}
+
+/// What goes over the wire, as TOML
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SyncMessage {
+    Camera(CameraPose),
+    Selection { renderables: Vec<usize> },
+}
+
+/// Sends what changed in this instance and applies what changed in the others, once per update. See the
+/// [module docs](self).
+pub struct SceneSync {
+    transport: Box<dyn SyncTransport>,
+    /// The state last sent or received, changes are what differs from it
+    camera: Option<CameraPose>,
+    selection: Vec<usize>,
+}
+
+impl SceneSync {
+    pub fn new(transport: impl SyncTransport + 'static) -> Self {
+        SceneSync {
+            transport: Box::new(transport),
+            camera: None,
+            selection: Vec::new(),
+        }
+    }
+
+    /// Applies incoming messages first, so a change arriving from elsewhere isn't sent straight back
+    pub(crate) fn update(&mut self, engine: &mut RenderEngine) {
+        for text in self.transport.receive() {
+            match toml::from_str::<SyncMessage>(&text) {
+                Ok(message) => self.apply(engine, message),
+                Err(err) => tracing::warn!("Ignoring an invalid sync message: {err}"),
+            }
+        }
+
+        if let Some(camera) = camera_pose(engine) {
+            if self.camera != Some(camera) {
+                self.camera = Some(camera);
+                self.send(&SyncMessage::Camera(camera));
+            }
+        }
+        let selection = engine.selection().selected();
+        if self.selection != selection {
+            self.selection = selection.to_vec();
+            self.send(&SyncMessage::Selection {
+                renderables: self.selection.clone(),
+            });
+        }
+    }
+
+    fn apply(&mut self, engine: &mut RenderEngine, message: SyncMessage) {
+        match message {
+            SyncMessage::Camera(pose) => {
+                self.camera = Some(pose);
+                let Some(window_id) = synced_window(engine) else {
+                    return;
+                };
+                let Some(viewport) = engine.viewport_mut(window_id) else {
+                    return;
+                };
+                let camera = &mut viewport.camera;
+                camera.target = pose.target.into();
+                camera.fovy = cgmath::Rad(pose.fov);
+                camera.projection = if pose.orthographic {
+                    Projection::Orthographic
+                } else {
+                    Projection::Perspective
+                };
+                camera.set_yaw(pose.yaw);
+                camera.set_pitch(pose.pitch);
+                camera.set_distance(pose.distance);
+                // As the bounds left it, so it isn't sent straight back when they moved it
+                self.camera = camera_pose(engine);
+                engine.request_redraw();
+            }
+            SyncMessage::Selection { renderables } => {
+                engine.selection_mut().set(renderables.iter().copied());
+                self.selection = renderables;
+                engine.request_redraw();
+            }
+        }
+    }
+
+    fn send(&mut self, message: &SyncMessage) {
+        match toml::to_string(message) {
+            Ok(text) => self.transport.send(&text),
+            Err(err) => tracing::error!("Failed to serialize a sync message: {err}"),
+        }
+    }
+}
+
+fn synced_window(engine: &RenderEngine) -> Option<winit::window::WindowId> {
+    engine
+        .input()
+        .focused_window()
+        .or_else(|| engine.window_ids().next())
+}
+
+fn camera_pose(engine: &RenderEngine) -> Option<CameraPose> {
+    let camera = &engine.viewport(synced_window(engine)?)?.camera;
+    Some(CameraPose {
+        yaw: camera.yaw,
+        pitch: camera.pitch,
+        distance: camera.distance,
+        target: camera.target.into(),
+        fov: camera.fovy.0,
+        orthographic: camera.projection == Projection::Orthographic,
+    })
+}
@@ -0,0 +1,85 @@
+//! The browser's own WebSocket, for wasm viewers connecting to a native [super::SyncTransport] server
+
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use wasm_bindgen::{closure::Closure, JsCast};
+use web_sys::{Event, MessageEvent, WebSocket};
+
+use super::SyncTransport;
+
+/// Connects to a websocket server relaying text messages, e.g. the native `WebSocketServer`
+pub struct WebSocketClient {
+    socket: WebSocket,
+    received: Rc<RefCell<VecDeque<String>>>,
+    /// Sent once the socket opened, the browser queues nothing before that
+    pending: Rc<RefCell<Vec<String>>>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_open: Closure<dyn FnMut(Event)>,
+}
+
+impl WebSocketClient {
+    /// `url` is `ws://host:port` or `wss://host:port`, connecting continues in the background
+    pub fn connect(url: &str) -> Result<Self, String> {
+        let socket = WebSocket::new(url).map_err(|err| format!("{err:?}"))?;
+        let received = Rc::new(RefCell::new(VecDeque::new()));
+        let pending: Rc<RefCell<Vec<String>>> = Rc::new(RefCell::new(Vec::new()));
+
+        let queue = received.clone();
+        let on_message = Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            if let Some(text) = event.data().as_string() {
+                queue.borrow_mut().push_back(text);
+            }
+        });
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        let opened = socket.clone();
+        let waiting = pending.clone();
+        let on_open = Closure::<dyn FnMut(Event)>::new(move |_: Event| {
+            for message in waiting.borrow_mut().drain(..) {
+                if let Err(err) = opened.send_with_str(&message) {
+                    tracing::warn!("Failed to send a scene sync message: {err:?}");
+                }
+            }
+        });
+        socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+
+        Ok(WebSocketClient {
+            socket,
+            received,
+            pending,
+            _on_message: on_message,
+            _on_open: on_open,
+        })
+    }
+
+    /// False while connecting and once the connection closed
+    pub fn is_connected(&self) -> bool {
+        self.socket.ready_state() == WebSocket::OPEN
+    }
+}
+
+impl SyncTransport for WebSocketClient {
+    fn send(&mut self, message: &str) {
+        match self.socket.ready_state() {
+            WebSocket::CONNECTING => self.pending.borrow_mut().push(message.to_string()),
+            WebSocket::OPEN => {
+                if let Err(err) = self.socket.send_with_str(message) {
+                    tracing::warn!("Failed to send a scene sync message: {err:?}");
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn receive(&mut self) -> Vec<String> {
+        self.received.borrow_mut().drain(..).collect()
+    }
+}
+
+impl Drop for WebSocketClient {
+    fn drop(&mut self) {
+        self.socket.set_onmessage(None);
+        self.socket.set_onopen(None);
+        let _ = self.socket.close();
+    }
+}
@@ -0,0 +1,630 @@
+//! Just enough of RFC 6455 for [super::SyncMessage]s: unfragmented text frames, pings and closing, over plain TCP.
+//! Sockets never block the update, what can't be written right away waits for the next one.
+
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use super::SyncTransport;
+
+/// Appended to the client's key before hashing it for the handshake's answer
+const HANDSHAKE_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+/// How long a new connection gets to finish its handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Larger frames close the connection, messages are a few hundred bytes
+const MAX_FRAME_LEN: u64 = 1 << 20;
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Listens for [WebSocketClient]s and browsers. Messages from one client are passed on to the others too.
+pub struct WebSocketServer {
+    listener: TcpListener,
+    /// Accepted sockets whose upgrade request didn't all arrive yet
+    handshakes: Vec<Handshake>,
+    clients: Vec<Connection>,
+}
+
+impl WebSocketServer {
+    /// Starts listening on `address`, e.g. `"0.0.0.0:9001"`
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        tracing::info!("Listening for scene sync on {}", listener.local_addr()?);
+        Ok(WebSocketServer {
+            listener,
+            handshakes: Vec::new(),
+            clients: Vec::new(),
+        })
+    }
+
+    /// How many clients are connected
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    fn accept(&mut self) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, address)) => match Handshake::new(stream, address) {
+                    Ok(handshake) => self.handshakes.push(handshake),
+                    Err(err) => tracing::warn!("Scene sync handshake with {address} failed: {err}"),
+                },
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    tracing::warn!("Failed to accept a scene sync client: {err}");
+                    break;
+                }
+            }
+        }
+
+        let mut index = 0;
+        while index < self.handshakes.len() {
+            let handshake = &mut self.handshakes[index];
+            let result = match handshake.advance() {
+                Ok(None) if handshake.started.elapsed() < HANDSHAKE_TIMEOUT => {
+                    index += 1;
+                    continue;
+                }
+                Ok(None) => Err(io::Error::new(ErrorKind::TimedOut, "Timed out")),
+                Ok(Some(answer)) => Ok(answer),
+                Err(err) => Err(err),
+            };
+            let Handshake {
+                stream, address, ..
+            } = self.handshakes.swap_remove(index);
+            match result.and_then(|(response, leftover)| {
+                Connection::with_outgoing(stream, leftover, response, false)
+            }) {
+                Ok(connection) => {
+                    tracing::info!("Scene sync client {address} connected");
+                    self.clients.push(connection);
+                }
+                Err(err) => tracing::warn!("Scene sync handshake with {address} failed: {err}"),
+            }
+        }
+    }
+}
+
+impl SyncTransport for WebSocketServer {
+    fn send(&mut self, message: &str) {
+        for client in &mut self.clients {
+            client.send(OPCODE_TEXT, message.as_bytes());
+        }
+    }
+
+    fn receive(&mut self) -> Vec<String> {
+        self.accept();
+        let mut received = Vec::new();
+        for index in 0..self.clients.len() {
+            for message in self.clients[index].receive() {
+                for (other, client) in self.clients.iter_mut().enumerate() {
+                    if other != index {
+                        client.send(OPCODE_TEXT, message.as_bytes());
+                    }
+                }
+                received.push(message);
+            }
+        }
+        self.clients.retain(|client| {
+            if !client.open {
+                tracing::info!("Scene sync client disconnected");
+            }
+            client.open
+        });
+        received
+    }
+}
+
+/// Connects to a [WebSocketServer] or any other websocket server relaying text messages
+pub struct WebSocketClient {
+    connection: Connection,
+}
+
+impl WebSocketClient {
+    /// `url` is `ws://host:port` with an optional path, blocks until the handshake is done
+    pub fn connect(url: &str) -> io::Result<Self> {
+        let invalid = || io::Error::new(ErrorKind::InvalidInput, format!("Not a ws:// URL: {url}"));
+        let rest = url.strip_prefix("ws://").ok_or_else(invalid)?;
+        let (host, path) = rest.split_once('/').unwrap_or((rest, ""));
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        let address = if host.contains(':') {
+            host.to_string()
+        } else {
+            format!("{host}:80")
+        };
+
+        let mut stream = TcpStream::connect(address)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let key = base64(&random_bytes::<16>());
+        write!(
+            stream,
+            "GET /{path} HTTP/1.1\r\nHost: {host}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: {key}\r\nSec-WebSocket-Version: 13\r\n\r\n"
+        )?;
+        let (response, leftover) = read_http_head(&mut stream)?;
+        let accept = header(&response, "sec-websocket-accept");
+        if !response.starts_with("HTTP/1.1 101") || accept != Some(accept_key(&key).as_str()) {
+            return Err(io::Error::new(
+                ErrorKind::ConnectionRefused,
+                format!("{url} didn't accept the websocket upgrade"),
+            ));
+        }
+        Ok(WebSocketClient {
+            connection: Connection::new(stream, leftover, true)?,
+        })
+    }
+
+    /// False once the server closed the connection or it broke
+    pub fn is_connected(&self) -> bool {
+        self.connection.open
+    }
+}
+
+impl SyncTransport for WebSocketClient {
+    fn send(&mut self, message: &str) {
+        self.connection.send(OPCODE_TEXT, message.as_bytes());
+    }
+
+    fn receive(&mut self) -> Vec<String> {
+        self.connection.receive()
+    }
+}
+
+/// One end of an established websocket
+struct Connection {
+    stream: TcpStream,
+    /// Received bytes not making up a whole frame yet
+    incoming: Vec<u8>,
+    /// Frames the socket didn't take yet
+    outgoing: Vec<u8>,
+    /// Clients mask what they send, servers don't
+    mask: bool,
+    open: bool,
+}
+
+impl Connection {
+    fn new(stream: TcpStream, incoming: Vec<u8>, mask: bool) -> io::Result<Self> {
+        Self::with_outgoing(stream, incoming, Vec::new(), mask)
+    }
+
+    /// Like [Connection::new], with `outgoing` sent first, e.g. the rest of the handshake
+    fn with_outgoing(
+        stream: TcpStream,
+        incoming: Vec<u8>,
+        outgoing: Vec<u8>,
+        mask: bool,
+    ) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        let mut connection = Connection {
+            stream,
+            incoming,
+            outgoing,
+            mask,
+            open: true,
+        };
+        connection.flush();
+        Ok(connection)
+    }
+
+    fn send(&mut self, opcode: u8, payload: &[u8]) {
+        if !self.open {
+            return;
+        }
+        self.outgoing.push(0x80 | opcode);
+        let mask_bit = if self.mask { 0x80 } else { 0 };
+        match payload.len() {
+            len @ 0..=125 => self.outgoing.push(mask_bit | len as u8),
+            len @ 126..=0xFFFF => {
+                self.outgoing.push(mask_bit | 126);
+                self.outgoing.extend((len as u16).to_be_bytes());
+            }
+            len => {
+                self.outgoing.push(mask_bit | 127);
+                self.outgoing.extend((len as u64).to_be_bytes());
+            }
+        }
+        if self.mask {
+            let key = random_bytes::<4>();
+            self.outgoing.extend(key);
+            self.outgoing.extend(
+                payload
+                    .iter()
+                    .enumerate()
+                    .map(|(index, byte)| byte ^ key[index % 4]),
+            );
+        } else {
+            self.outgoing.extend(payload);
+        }
+        self.flush();
+    }
+
+    fn flush(&mut self) {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return self.close(),
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => return,
+                Err(err) if err.kind() == ErrorKind::Interrupted => (),
+                Err(err) => {
+                    tracing::warn!("Scene sync connection broke: {err}");
+                    return self.close();
+                }
+            }
+        }
+    }
+
+    fn receive(&mut self) -> Vec<String> {
+        self.flush();
+        let mut buffer = [0; 4096];
+        while self.open {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => self.close(),
+                Ok(read) => self.incoming.extend_from_slice(&buffer[..read]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => (),
+                Err(err) => {
+                    tracing::warn!("Scene sync connection broke: {err}");
+                    self.close();
+                }
+            }
+        }
+
+        let mut messages = Vec::new();
+        while let Some((opcode, payload)) = self.next_frame() {
+            match opcode {
+                OPCODE_TEXT => match String::from_utf8(payload) {
+                    Ok(text) => messages.push(text),
+                    Err(_) => tracing::warn!("Ignoring a scene sync message that isn't UTF-8"),
+                },
+                OPCODE_PING => self.send(OPCODE_PONG, &payload),
+                OPCODE_CLOSE => {
+                    self.send(OPCODE_CLOSE, &[]);
+                    self.close();
+                }
+                OPCODE_PONG => (),
+                _ => tracing::warn!("Ignoring a websocket frame with opcode {opcode}"),
+            }
+        }
+        messages
+    }
+
+    /// Takes the first whole frame out of what was received, unmasked
+    fn next_frame(&mut self) -> Option<(u8, Vec<u8>)> {
+        let data = &self.incoming;
+        if data.len() < 2 {
+            return None;
+        }
+        let opcode = data[0] & 0x0F;
+        let masked = data[1] & 0x80 != 0;
+        let (len, mut offset) = match data[1] & 0x7F {
+            126 if data.len() >= 4 => (u16::from_be_bytes([data[2], data[3]]) as u64, 4),
+            127 if data.len() >= 10 => {
+                let bytes = data[2..10].try_into().expect("Slice has 8 bytes!");
+                (u64::from_be_bytes(bytes), 10)
+            }
+            126 | 127 => return None,
+            len => (len as u64, 2),
+        };
+        if len > MAX_FRAME_LEN {
+            tracing::warn!("Closing a scene sync connection sending a {len} byte frame");
+            self.close();
+            return None;
+        }
+        let key = if masked {
+            let key = data.get(offset..offset + 4)?;
+            offset += 4;
+            Some([key[0], key[1], key[2], key[3]])
+        } else {
+            None
+        };
+        let end = offset + len as usize;
+        if data.len() < end {
+            return None;
+        }
+        let mut payload: Vec<u8> = self.incoming.drain(..end).skip(offset).collect();
+        if let Some(key) = key {
+            for (index, byte) in payload.iter_mut().enumerate() {
+                *byte ^= key[index % 4];
+            }
+        }
+        Some((opcode, payload))
+    }
+
+    fn close(&mut self) {
+        self.open = false;
+        self.incoming.clear();
+        self.outgoing.clear();
+    }
+}
+
+/// A client's upgrade request being read without blocking, over as many updates as it takes to arrive
+struct Handshake {
+    stream: TcpStream,
+    address: SocketAddr,
+    /// The request so far
+    incoming: Vec<u8>,
+    started: Instant,
+}
+
+impl Handshake {
+    fn new(stream: TcpStream, address: SocketAddr) -> io::Result<Self> {
+        // Accepted sockets don't necessarily inherit the listener's mode
+        stream.set_nonblocking(true)?;
+        Ok(Handshake {
+            stream,
+            address,
+            incoming: Vec::new(),
+            started: Instant::now(),
+        })
+    }
+
+    /// Reads what arrived of the request. Once it's all there, the response to send and what came after the request.
+    fn advance(&mut self) -> io::Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let mut buffer = [0; 1024];
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(read) => self.incoming.extend_from_slice(&buffer[..read]),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::Interrupted => (),
+                Err(err) => return Err(err),
+            }
+        }
+        let Some((request, leftover)) = split_http_head(&mut self.incoming)? else {
+            return Ok(None);
+        };
+        let Some(key) = header(&request, "sec-websocket-key") else {
+            // Best effort, the socket is dropped either way
+            let _ = self.stream.write(b"HTTP/1.1 400 Bad Request\r\n\r\n");
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Not a websocket upgrade request",
+            ));
+        };
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(key)
+        );
+        Ok(Some((response.into_bytes(), leftover)))
+    }
+}
+
+/// Reads up to the blank line ending the HTTP head, along with whatever came after it
+fn read_http_head(stream: &mut TcpStream) -> io::Result<(String, Vec<u8>)> {
+    let mut data = Vec::new();
+    let mut buffer = [0; 1024];
+    loop {
+        if let Some(head) = split_http_head(&mut data)? {
+            return Ok(head);
+        }
+        match stream.read(&mut buffer)? {
+            0 => return Err(ErrorKind::UnexpectedEof.into()),
+            read => data.extend_from_slice(&buffer[..read]),
+        }
+    }
+}
+
+/// The HTTP head at the start of `data` and what came after it, once the blank line ending it arrived
+fn split_http_head(data: &mut Vec<u8>) -> io::Result<Option<(String, Vec<u8>)>> {
+    let Some(end) = data.windows(4).position(|window| window == b"\r\n\r\n") else {
+        if data.len() > 16 * 1024 {
+            return Err(io::Error::new(ErrorKind::InvalidData, "HTTP head too long"));
+        }
+        return Ok(None);
+    };
+    let leftover = data.split_off(end + 4);
+    Ok(Some((String::from_utf8_lossy(data).into_owned(), leftover)))
+}
+
+/// The value of header `name`, which has to be lowercase
+fn header<'a>(head: &'a str, name: &str) -> Option<&'a str> {
+    head.lines().skip(1).find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim().to_ascii_lowercase() == name).then(|| value.trim())
+    })
+}
+
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{key}{HANDSHAKE_GUID}").as_bytes()))
+}
+
+/// Not cryptographically secure, masks only keep proxies from misreading frames
+fn random_bytes<const N: usize>() -> [u8; N] {
+    use std::{
+        collections::hash_map::RandomState,
+        hash::{BuildHasher, Hasher},
+    };
+    let mut bytes = [0; N];
+    for chunk in bytes.chunks_mut(8) {
+        let random = RandomState::new().build_hasher().finish().to_le_bytes();
+        chunk.copy_from_slice(&random[..chunk.len()]);
+    }
+    bytes
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, &byte)| {
+            bits | (byte as u32) << (16 - 8 * index)
+        });
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * index) & 0x3F) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (index, word) in block.chunks(4).enumerate() {
+            words[index] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for index in 16..80 {
+            words[index] =
+                (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16])
+                    .rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (index, &word) in words.iter().enumerate() {
+            let (f, k) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (value, added) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(added);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (chunk, value) in digest.chunks_mut(4).zip(state) {
+        chunk.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    #[test]
+    fn accept_key_matches_rfc_6455() {
+        // The example handshake of RFC 6455, section 1.3
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn sha1_matches_fips_180_vectors() {
+        assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(&sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        // Two blocks, the padding doesn't fit after the message
+        assert_eq!(
+            hex(&sha1(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+        assert_eq!(
+            hex(&sha1(&[b'a'; 1000])),
+            "291e9a6c66994949b57ba5e650361e98fc36b1ba"
+        );
+    }
+
+    #[test]
+    fn base64_matches_rfc_4648_vectors() {
+        for (data, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(base64(data.as_bytes()), encoded);
+        }
+    }
+
+    #[test]
+    fn http_head_is_split_once_complete() {
+        let mut data = b"GET / HTTP/1.1\r\nSec-WebSocket-Key: abc\r\n".to_vec();
+        assert!(split_http_head(&mut data).unwrap().is_none());
+
+        data.extend_from_slice(b"Upgrade:  websocket \r\n\r\n\x81\x00");
+        let (head, leftover) = split_http_head(&mut data).unwrap().unwrap();
+        assert_eq!(leftover, b"\x81\x00");
+        assert_eq!(header(&head, "sec-websocket-key"), Some("abc"));
+        assert_eq!(header(&head, "upgrade"), Some("websocket"));
+        assert_eq!(header(&head, "connection"), None);
+    }
+
+    #[test]
+    fn http_head_without_end_is_rejected() {
+        let mut data = vec![b'a'; 17 * 1024];
+        assert!(split_http_head(&mut data).is_err());
+    }
+
+    #[test]
+    fn messages_reach_the_other_clients() {
+        let mut server = WebSocketServer::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", server.listener.local_addr().unwrap());
+        let connect = |url: String| std::thread::spawn(move || WebSocketClient::connect(&url));
+
+        // Each client blocks on its handshake while the server answers it
+        let mut clients = Vec::new();
+        for count in 1..=2 {
+            let client = connect(url.clone());
+            let started = Instant::now();
+            while server.client_count() < count && started.elapsed() < HANDSHAKE_TIMEOUT {
+                server.receive();
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            clients.push(client.join().unwrap().unwrap());
+        }
+        assert_eq!(server.client_count(), 2);
+
+        // Long enough for the 16 bit length
+        let message = "x".repeat(300);
+        clients[0].send(&message);
+        let mut received = Vec::new();
+        let mut relayed = Vec::new();
+        let started = Instant::now();
+        while (received.is_empty() || relayed.is_empty()) && started.elapsed() < HANDSHAKE_TIMEOUT {
+            received.extend(server.receive());
+            relayed.extend(clients[1].receive());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(received, std::slice::from_ref(&message));
+        assert_eq!(relayed, [message]);
+        assert!(clients[0].receive().is_empty());
+    }
+}
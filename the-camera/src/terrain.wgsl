@@ -0,0 +1,117 @@
+// See clipping.rs, the same as in shader.wgsl
+struct Clipping {
+    planes: array<vec4<f32>, 4>,
+    cap_color: vec4<f32>,
+    count: u32,
+    caps: u32,
+}
+
+struct Audio {
+    bands: array<vec4<f32>, 4>,
+    level: f32,
+}
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    clipping: Clipping,
+    audio: Audio,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// One tile, see terrain/pass.rs
+struct Tile {
+    origin: vec2<f32>,
+    size: f32,
+    resolution: u32,
+    morph: vec2<f32>,
+    base: f32,
+    snow_height: f32,
+    grass_color: vec4<f32>,
+    rock_color: vec4<f32>,
+    snow_color: vec4<f32>,
+    sun_direction: vec4<f32>,
+}
+@group(1) @binding(0)
+var<uniform> tile: Tile;
+
+// One texel for every vertex of the tile
+@group(2) @binding(0)
+var t_heights: texture_2d<f32>;
+@group(2) @binding(1)
+var t_normals: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+};
+
+// Two triangles for every cell of the grid
+const CORNERS = array<vec2<u32>, 6>(
+    vec2<u32>(0u, 0u),
+    vec2<u32>(1u, 0u),
+    vec2<u32>(1u, 1u),
+    vec2<u32>(0u, 0u),
+    vec2<u32>(1u, 1u),
+    vec2<u32>(0u, 1u),
+);
+
+fn world_xz(grid: vec2<f32>) -> vec2<f32> {
+    return tile.origin + grid / f32(tile.resolution) * tile.size;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let cell = vec2<u32>((index / 6u) % tile.resolution, index / 6u / tile.resolution);
+    let grid = cell + CORNERS[index % 6u];
+    let height = textureLoad(t_heights, grid, 0).r;
+    let rest = world_xz(vec2<f32>(grid));
+
+    // Vertices the parent doesn't have slide onto their even neighbor as the parent is about to take over, which
+    // leaves the parent's grid where it does. Heights and normals slide along.
+    let distance = length(vec3<f32>(rest.x, tile.base + height, rest.y) - camera.view_pos.xyz);
+    let morph = clamp((distance - tile.morph.x) / (tile.morph.y - tile.morph.x), 0.0, 1.0);
+    let even = grid - grid % 2u;
+    let even_height = textureLoad(t_heights, even, 0).r;
+    let normal = textureLoad(t_normals, grid, 0).xyz * 2.0 - 1.0;
+    let even_normal = textureLoad(t_normals, even, 0).xyz * 2.0 - 1.0;
+
+    let xz = world_xz(mix(vec2<f32>(grid), vec2<f32>(even), morph));
+    let world_position = vec3<f32>(xz.x, tile.base + mix(height, even_height, morph), xz.y);
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
+    out.normal = mix(normal, even_normal, morph);
+    return out;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    // Terrains can't be picked
+    @location(1) id: u32,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    for (var index = 0u; index < camera.clipping.count; index++) {
+        let plane = camera.clipping.planes[index];
+        if dot(plane.xyz, in.world_position) < plane.w {
+            discard;
+        }
+    }
+
+    let normal = normalize(in.normal);
+    // Rock shows on slopes steeper than about 35°, snow over the snow line on all but the steepest
+    var albedo = mix(tile.rock_color.rgb, tile.grass_color.rgb, smoothstep(0.75, 0.85, normal.y));
+    if in.world_position.y - tile.base > tile.snow_height {
+        albedo = mix(albedo, tile.snow_color.rgb, smoothstep(0.4, 0.6, normal.y));
+    }
+
+    let sun = normalize(tile.sun_direction.xyz);
+    var out: FragmentOutput;
+    out.color = vec4<f32>(albedo * (0.35 + 0.65 * max(dot(normal, sun), 0.0)), 1.0);
+    out.id = 0u;
+    return out;
+}
@@ -0,0 +1,474 @@
+//! Terrain too large to draw at full detail at once, e.g. the heightfield under a flyover of a whole region.
+//!
+//! A terrain is a square split up by a quadtree. Every tile of it, from the root covering the whole square down to
+//! [Terrain::levels] below, is a grid of [Terrain::tile_resolution] cells sampled from the terrain's heights, so each
+//! level is twice as detailed as the one above. Every window and render target picks the tiles it draws from its
+//! camera: those in view, split into their four children while the camera is closer than [Terrain::detail] times
+//! their size.
+//!
+//! Tiles are only sampled when picked, on the [crate::jobs::JobSystem], and uploaded as a height and a normal texture
+//! of their own once they're done. A tile is drawn until all of its children are there, and the least recently
+//! drawn ones are freed again. The vertex shader moves the vertices of a tile onto the grid of its parent as the
+//! camera gets to where the parent takes over, CDLOD's geomorphing, so neither popping nor cracks show between the
+//! levels.
+
+mod pass;
+
+use std::sync::Arc;
+
+use cgmath::{Matrix4, Vector4};
+use wgpu::{Device, Queue, TextureFormat};
+
+pub(crate) use self::pass::{TerrainPass, TerrainView};
+use crate::{
+    camera::camera::CameraUniform, global_bindings::GlobalBindings, jobs::JobSystem,
+    lazy_pass::LazyPass,
+};
+
+/// Height above [Terrain::center] at a point given by its world X and Z
+type Heights = Arc<dyn Fn(f32, f32) -> f32 + Send + Sync>;
+
+/// A heightfield drawn into every window and render target, added with
+/// [crate::render_engine::RenderEngine::add_terrain]
+#[derive(Clone)]
+pub struct Terrain {
+    /// Middle of the square, in the XZ plane at this height
+    pub center: [f32; 3],
+    /// Along both the X and Z axes
+    pub size: f32,
+    /// Levels of tiles below the one covering the whole terrain, every one halves the spacing of the vertices
+    pub levels: u8,
+    /// Grid cells along each side of a tile, rounded up to an even number for the geomorphing
+    pub tile_resolution: u32,
+    /// Tiles are split while the camera is closer than this many times their size, higher keeps more detail
+    pub detail: f32,
+    /// Of flat ground
+    pub grass_color: [f32; 3],
+    /// Of slopes
+    pub rock_color: [f32; 3],
+    /// Of whatever is higher than [Terrain::snow_height]
+    pub snow_color: [f32; 3],
+    /// Above [Terrain::center]
+    pub snow_height: f32,
+    /// Towards the sun lighting the ground, doesn't have to be normalized. The engine's
+    /// [crate::atmosphere::Atmosphere] has its own sun, which takes over while there is one.
+    pub sun_direction: [f32; 3],
+    pub visible: bool,
+    heights: Heights,
+}
+
+impl Terrain {
+    /// A terrain `size` wide around `center`, with `heights` above it at every world X and Z. Called for every
+    /// vertex of every tile, from the job system's threads.
+    pub fn new(
+        center: [f32; 3],
+        size: f32,
+        heights: impl Fn(f32, f32) -> f32 + Send + Sync + 'static,
+    ) -> Self {
+        Terrain {
+            center,
+            size,
+            levels: 8,
+            tile_resolution: 32,
+            detail: 2.5,
+            grass_color: [0.25, 0.4, 0.15],
+            rock_color: [0.4, 0.36, 0.32],
+            snow_color: [0.9, 0.92, 0.95],
+            snow_height: f32::INFINITY,
+            sun_direction: [0.3, 1.0, 0.4],
+            visible: true,
+            heights: Arc::new(heights),
+        }
+    }
+
+    /// Heights of a grid of `samples` along X and Z spread over the whole terrain, row by row along X. The terrain
+    /// is interpolated linearly between them.
+    pub fn from_heightmap(
+        center: [f32; 3],
+        size: f32,
+        samples: [u32; 2],
+        heights: Vec<f32>,
+    ) -> Self {
+        let [width, depth] = samples.map(|samples| samples.max(1) as usize);
+        assert_eq!(
+            heights.len(),
+            width * depth,
+            "Heightmap doesn't match its size!"
+        );
+        Terrain::new(center, size, move |x, z| {
+            // The two samples around a coordinate and how far it is between them
+            let around = |coordinate: f32, center: f32, samples: usize| {
+                let scaled =
+                    ((coordinate - center) / size + 0.5).clamp(0.0, 1.0) * (samples - 1) as f32;
+                let first = (scaled as usize).min(samples.saturating_sub(2));
+                (first, (first + 1).min(samples - 1), scaled - first as f32)
+            };
+            let (x0, x1, tx) = around(x, center[0], width);
+            let (z0, z1, tz) = around(z, center[2], depth);
+            let row =
+                |z: usize| heights[z * width + x0] * (1.0 - tx) + heights[z * width + x1] * tx;
+            row(z0) * (1.0 - tz) + row(z1) * tz
+        })
+    }
+
+    pub fn with_levels(mut self, levels: u8) -> Self {
+        self.levels = levels;
+        self
+    }
+
+    pub fn with_tile_resolution(mut self, tile_resolution: u32) -> Self {
+        self.tile_resolution = tile_resolution;
+        self
+    }
+
+    pub fn with_detail(mut self, detail: f32) -> Self {
+        self.detail = detail;
+        self
+    }
+
+    pub fn with_colors(
+        mut self,
+        grass_color: [f32; 3],
+        rock_color: [f32; 3],
+        snow_color: [f32; 3],
+    ) -> Self {
+        self.grass_color = grass_color;
+        self.rock_color = rock_color;
+        self.snow_color = snow_color;
+        self
+    }
+
+    pub fn with_snow_height(mut self, snow_height: f32) -> Self {
+        self.snow_height = snow_height;
+        self
+    }
+
+    pub fn with_sun_direction(mut self, sun_direction: [f32; 3]) -> Self {
+        self.sun_direction = sun_direction;
+        self
+    }
+
+    /// The height above [Terrain::center] at a world X and Z, e.g. for keeping a camera above the ground
+    pub fn height(&self, x: f32, z: f32) -> f32 {
+        (self.heights)(x, z)
+    }
+
+    /// Cells along each side of a tile
+    fn resolution(&self) -> u32 {
+        self.tile_resolution.clamp(2, 256).next_multiple_of(2)
+    }
+
+    /// Corner with the lowest X and Z and edge length of a tile
+    fn tile_bounds(&self, key: TileKey) -> ([f32; 2], f32) {
+        let size = self.size / (1u32 << key.level) as f32;
+        let min = [0, 2].map(|axis| self.center[axis] - self.size / 2.0);
+        let position = key.position.map(|position| position as f32 * size);
+        ([min[0] + position[0], min[1] + position[1]], size)
+    }
+
+    /// Samples a tile's heights and normals, on any thread
+    fn tile_sampler(&self, key: TileKey) -> impl FnOnce() -> TileSamples + Send + 'static {
+        let heights = self.heights.clone();
+        let resolution = self.resolution();
+        let (min, size) = self.tile_bounds(key);
+        let base = self.center[1];
+        move || TileSamples::new(&*heights, resolution, min, size, base)
+    }
+
+    /// The tiles a camera should draw, and those missing to draw it in more detail, the coarsest first. `heights`
+    /// has the lowest and highest world height of every tile sampled so far.
+    pub(crate) fn select(
+        &self,
+        camera: &CameraUniform,
+        heights: impl Fn(TileKey) -> Option<[f32; 2]>,
+    ) -> Selection {
+        let mut selection = Selection {
+            draws: Vec::new(),
+            missing: Vec::new(),
+        };
+        match heights(TileKey::ROOT) {
+            Some(range) => self.select_tile(camera, &heights, TileKey::ROOT, range, &mut selection),
+            None => selection.missing.push(TileKey::ROOT),
+        }
+        selection
+    }
+
+    fn select_tile(
+        &self,
+        camera: &CameraUniform,
+        heights: &impl Fn(TileKey) -> Option<[f32; 2]>,
+        key: TileKey,
+        range: [f32; 2],
+        selection: &mut Selection,
+    ) {
+        let (min, size) = self.tile_bounds(key);
+        let low = [min[0], range[0], min[1]];
+        let high = [min[0] + size, range[1], min[1] + size];
+        if !in_view(&Matrix4::from(camera.view_proj), low, high) {
+            return;
+        }
+        let refined = key.level < self.levels
+            && distance(camera.view_position, low, high) < size * self.detail;
+        if refined {
+            let children = key.children().map(|child| (child, heights(child)));
+            if children.iter().all(|(_, range)| range.is_some()) {
+                for (child, range) in children {
+                    if let Some(range) = range {
+                        self.select_tile(camera, heights, child, range, selection);
+                    }
+                }
+                return;
+            }
+            selection.missing.extend(
+                children
+                    .iter()
+                    .filter(|(_, range)| range.is_none())
+                    .map(|&(child, _)| child),
+            );
+        }
+        selection.draws.push(key);
+    }
+
+    /// Camera distances the vertices of a tile start and finish moving onto its parent's grid at, where the parent
+    /// takes over from the tile and its siblings
+    fn morph_range(&self, key: TileKey) -> [f32; 2] {
+        if key.level == 0 {
+            return [f32::MAX; 2];
+        }
+        let (_, size) = self.tile_bounds(key);
+        let end = size * 2.0 * self.detail;
+        [end * MORPH_START, end]
+    }
+}
+
+impl std::fmt::Debug for Terrain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Terrain")
+            .field("center", &self.center)
+            .field("size", &self.size)
+            .field("levels", &self.levels)
+            .field("tile_resolution", &self.tile_resolution)
+            .field("detail", &self.detail)
+            .field("visible", &self.visible)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Refers to a terrain added with [crate::render_engine::RenderEngine::add_terrain]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TerrainHandle(pub(crate) usize);
+
+/// Of the camera distance a tile's parent takes over at, where its vertices start moving onto the parent's grid
+const MORPH_START: f32 = 0.75;
+
+/// A tile of the quadtree by its level and its position among the tiles of that level, along X and Z
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct TileKey {
+    level: u8,
+    position: [u32; 2],
+}
+
+impl TileKey {
+    const ROOT: TileKey = TileKey {
+        level: 0,
+        position: [0; 2],
+    };
+
+    fn children(self) -> [TileKey; 4] {
+        [[0, 0], [1, 0], [0, 1], [1, 1]].map(|[x, z]| TileKey {
+            level: self.level + 1,
+            position: [self.position[0] * 2 + x, self.position[1] * 2 + z],
+        })
+    }
+}
+
+pub(crate) struct Selection {
+    pub draws: Vec<TileKey>,
+    /// Not sampled yet
+    pub missing: Vec<TileKey>,
+}
+
+/// What a tile's textures are made from
+pub(crate) struct TileSamples {
+    /// Cells along each side
+    resolution: u32,
+    /// Above [Terrain::center], row by row along X
+    heights: Vec<f32>,
+    /// Packed from -1..1 into 0..255
+    normals: Vec<[u8; 4]>,
+    /// Lowest and highest world height
+    range: [f32; 2],
+}
+
+impl TileSamples {
+    fn new(
+        heights: &dyn Fn(f32, f32) -> f32,
+        resolution: u32,
+        min: [f32; 2],
+        size: f32,
+        base: f32,
+    ) -> Self {
+        let step = size / resolution as f32;
+        // A border of one sample around the vertices, for the normals at the tile's edges
+        let samples = resolution as usize + 3;
+        let mut bordered = Vec::with_capacity(samples * samples);
+        for z in 0..samples {
+            for x in 0..samples {
+                bordered.push(heights(
+                    min[0] + (x as f32 - 1.0) * step,
+                    min[1] + (z as f32 - 1.0) * step,
+                ));
+            }
+        }
+
+        let vertices = resolution as usize + 1;
+        let mut tile = TileSamples {
+            resolution,
+            heights: Vec::with_capacity(vertices * vertices),
+            normals: Vec::with_capacity(vertices * vertices),
+            range: [f32::MAX, f32::MIN],
+        };
+        for z in 1..=vertices {
+            for x in 1..=vertices {
+                let height = bordered[z * samples + x];
+                let slope_x =
+                    (bordered[z * samples + x + 1] - bordered[z * samples + x - 1]) / (2.0 * step);
+                let slope_z = (bordered[(z + 1) * samples + x] - bordered[(z - 1) * samples + x])
+                    / (2.0 * step);
+                let length = (slope_x * slope_x + 1.0 + slope_z * slope_z).sqrt();
+                let pack = |value: f32| ((value / length * 0.5 + 0.5) * 255.0).round() as u8;
+                tile.heights.push(height);
+                tile.normals
+                    .push([pack(-slope_x), pack(1.0), pack(-slope_z), 255]);
+                tile.range = [
+                    tile.range[0].min(base + height),
+                    tile.range[1].max(base + height),
+                ];
+            }
+        }
+        tile
+    }
+}
+
+/// Whether any of the box is inside the view frustum, boxes entirely outside one of its planes aren't
+fn in_view(view_proj: &Matrix4<f32>, min: [f32; 3], max: [f32; 3]) -> bool {
+    let corners = (0..8).map(|corner| {
+        let pick = |axis: usize| match (corner >> axis) & 1 {
+            0 => min[axis],
+            _ => max[axis],
+        };
+        view_proj * Vector4::new(pick(0), pick(1), pick(2), 1.0)
+    });
+    let mut outside = [true; 6];
+    for clip in corners {
+        let planes = [
+            clip.x >= -clip.w,
+            clip.x <= clip.w,
+            clip.y >= -clip.w,
+            clip.y <= clip.w,
+            clip.z >= 0.0,
+            clip.z <= clip.w,
+        ];
+        for (outside, inside) in outside.iter_mut().zip(planes) {
+            *outside &= !inside;
+        }
+    }
+    !outside.contains(&true)
+}
+
+/// From the eye to the closest point of the box, 0 inside it
+fn distance(eye: [f32; 4], min: [f32; 3], max: [f32; 3]) -> f32 {
+    (0..3)
+        .map(|axis| {
+            let outside = (min[axis] - eye[axis]).max(eye[axis] - max[axis]).max(0.0);
+            outside * outside
+        })
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// The engine's terrains and the pass sampling and drawing their tiles, which is created with the first terrain
+#[derive(Default)]
+pub(crate) struct Terrains {
+    pass: LazyPass<TerrainPass>,
+    /// None where a terrain was removed
+    terrains: Vec<Option<Terrain>>,
+}
+
+impl Terrains {
+    pub fn add(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        terrain: Terrain,
+    ) -> TerrainHandle {
+        self.pass
+            .get_or_create(|| Some(TerrainPass::new(device, format, global_bindings)));
+        self.terrains.push(Some(terrain));
+        TerrainHandle(self.terrains.len() - 1)
+    }
+
+    pub fn get(&self, handle: TerrainHandle) -> Option<&Terrain> {
+        self.terrains.get(handle.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: TerrainHandle) -> Option<&mut Terrain> {
+        self.terrains.get_mut(handle.0)?.as_mut()
+    }
+
+    /// Frees the terrain's tiles on the GPU
+    pub fn remove(&mut self, handle: TerrainHandle) -> Option<Terrain> {
+        let terrain = self.terrains.get_mut(handle.0)?.take()?;
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_terrain(handle.0);
+        }
+        Some(terrain)
+    }
+
+    /// See [TerrainPass::prepare]
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        jobs: &JobSystem,
+        sun_direction: Option<[f32; 3]>,
+        view: &mut Option<TerrainView>,
+        camera: &CameraUniform,
+    ) -> bool {
+        let Some(pass) = self.pass.get_mut() else {
+            return false;
+        };
+        pass.prepare(
+            device,
+            queue,
+            jobs,
+            &self.terrains,
+            sun_direction,
+            view,
+            camera,
+        )
+    }
+
+    /// Draws the tiles in the main pass, None before the first terrain
+    pub fn pass(&self) -> Option<&TerrainPass> {
+        self.pass.get()
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.pass.get().map_or(0, TerrainPass::size_in_bytes)
+    }
+
+    /// Creates the pass again on a new device, the tiles are sampled again when the terrains are next drawn
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+    ) {
+        self.pass
+            .recreate(|| Some(TerrainPass::new(device, format, global_bindings)));
+    }
+}
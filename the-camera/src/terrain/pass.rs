@@ -0,0 +1,465 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use wgpu::{BindGroup, Device, Queue, RenderPass, RenderPipeline, TextureFormat};
+
+use super::{Terrain, TileKey, TileSamples};
+use crate::{
+    camera::camera::CameraUniform,
+    global_bindings::GlobalBindings,
+    jobs::{Job, JobSystem},
+    profiler::RenderStats,
+    texture,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        dynamic_uniform_buffer::DynamicUniformBuffer,
+    },
+};
+
+/// Most tiles being sampled at once, the rest wait for the next frames
+const MAX_PENDING_TILES: usize = 32;
+/// Tiles kept on the GPU before the least recently drawn ones are freed
+const RESIDENT_TILES: usize = 1024;
+
+/// Per tile draw data, bound at group 1
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TileUBOContent {
+    /// World X and Z of the corner with the lowest ones
+    origin: [f32; 2],
+    size: f32,
+    resolution: u32,
+    /// Camera distances the vertices start and finish moving onto the parent's grid at
+    morph: [f32; 2],
+    /// [Terrain::center]'s height, which the tile's are above
+    base: f32,
+    snow_height: f32,
+    grass_color: [f32; 4],
+    rock_color: [f32; 4],
+    snow_color: [f32; 4],
+    sun_direction: [f32; 4],
+}
+
+crate::assert_uniform_layout!(TileUBOContent {
+    origin: ALIGN_VEC2,
+    size: ALIGN_SCALAR,
+    resolution: ALIGN_SCALAR,
+    morph: ALIGN_VEC2,
+    base: ALIGN_SCALAR,
+    snow_height: ALIGN_SCALAR,
+    grass_color: ALIGN_VEC4,
+    rock_color: ALIGN_VEC4,
+    snow_color: ALIGN_VEC4,
+    sun_direction: ALIGN_VEC4,
+});
+
+/// A tile by terrain index and its place in the quadtree
+type TilesKey = (usize, TileKey);
+
+struct Tile {
+    /// Only kept alive for the bind group
+    _heights: wgpu::Texture,
+    _normals: wgpu::Texture,
+    bind_group: BindGroup,
+    resolution: u32,
+    /// Lowest and highest world height
+    range: [f32; 2],
+    /// [TerrainPass::picks] when it was last picked
+    last_picked: u64,
+}
+
+/// What a terrain's tiles are sampled for, they're sampled again when it changes
+#[derive(Clone, Copy, PartialEq)]
+struct Sampled {
+    center: [f32; 3],
+    size: f32,
+    resolution: u32,
+}
+
+/// What one window or render target draws of the terrains
+pub(crate) struct TerrainView {
+    ubo: DynamicUniformBuffer<TileUBOContent>,
+    bind_group: BindGroup,
+    /// Tiles with the dynamic offset of their uniforms
+    draws: Vec<(TilesKey, u32)>,
+}
+
+/// The pipeline drawing terrains in the main pass and the tiles sampled for it, shared by every view
+pub(crate) struct TerrainPass {
+    pipeline: RenderPipeline,
+    view_bind_group_layout: BindGroupLayoutWithDesc,
+    tile_bind_group_layout: BindGroupLayoutWithDesc,
+    tiles: HashMap<TilesKey, Tile>,
+    /// Jobs aren't Sync, the lock only lets the main pass share the pass between threads. It's never taken, the
+    /// jobs are only touched through `&mut self`.
+    pending: Mutex<HashMap<TilesKey, Job<TileSamples>>>,
+    sampled: HashMap<usize, Sampled>,
+    /// Counts the views' picks, for freeing the tiles picked longest ago
+    picks: u64,
+}
+
+impl TerrainPass {
+    /// `format` has to be the engine's swapchain format, since the terrains are drawn in the main pass
+    pub fn new(device: &Device, format: TextureFormat, global_bindings: &GlobalBindings) -> Self {
+        let _span = tracing::debug_span!("create_terrain_pipeline").entered();
+        let view_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_rendering(binding_types::uniform_dynamic(
+                std::mem::size_of::<TileUBOContent>() as u64,
+            ))
+            .create(device, "Terrain View Bind Group");
+        // Loaded from in the vertex shader, R32Float can't be filtered everywhere
+        let unfiltered = wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        };
+        let tile_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_vertex(unfiltered)
+            .next_binding_vertex(unfiltered)
+            .create(device, "Terrain Tile Bind Group");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Terrain Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../terrain.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                global_bindings.bind_group_layouts(),
+                &view_bind_group_layout.layout,
+                &tile_bind_group_layout.layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        // The grid is made up from the vertex indices, without any buffers
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("terrain"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                // Seen from below too, e.g. with the camera flying through a mountain
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                conservative: false,
+                unclipped_depth: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[
+                    Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                    Some(wgpu::ColorTargetState {
+                        format: texture::Texture::ID_FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    }),
+                ],
+                compilation_options: Default::default(),
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        TerrainPass {
+            pipeline,
+            view_bind_group_layout,
+            tile_bind_group_layout,
+            tiles: HashMap::new(),
+            pending: Mutex::default(),
+            sampled: HashMap::new(),
+            picks: 0,
+        }
+    }
+
+    /// Picks the tiles a view draws from its camera, uploads those sampled since and samples missing ones on `jobs`,
+    /// up to [MAX_PENDING_TILES] at a time. Returns whether some are still missing, the view needs drawing again
+    /// until they're all there. `sun_direction` of the atmosphere overrides those of the terrains.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        jobs: &JobSystem,
+        terrains: &[Option<Terrain>],
+        sun_direction: Option<[f32; 3]>,
+        view: &mut Option<TerrainView>,
+        camera: &CameraUniform,
+    ) -> bool {
+        self.forget_changed(terrains);
+        if terrains.iter().flatten().all(|terrain| !terrain.visible) {
+            *view = None;
+            return false;
+        }
+        self.picks += 1;
+        self.upload_sampled(device, queue);
+        let view = view.get_or_insert_with(|| {
+            let ubo = DynamicUniformBuffer::new(device);
+            TerrainView {
+                bind_group: self.create_bind_group(device, &ubo),
+                ubo,
+                draws: Vec::new(),
+            }
+        });
+        view.ubo.clear();
+        view.draws.clear();
+
+        for (terrain_index, terrain) in terrains.iter().enumerate() {
+            let Some(terrain) = terrain.as_ref().filter(|terrain| terrain.visible) else {
+                continue;
+            };
+            let selection = terrain.select(camera, |key| {
+                self.tiles.get(&(terrain_index, key)).map(|tile| tile.range)
+            });
+            let pending = self.pending.get_mut().expect("Terrain jobs poisoned!");
+            for key in selection.missing {
+                if pending.len() >= MAX_PENDING_TILES {
+                    break;
+                }
+                pending
+                    .entry((terrain_index, key))
+                    .or_insert_with(|| jobs.spawn(terrain.tile_sampler(key)));
+            }
+
+            let [r, g, b] = terrain.grass_color;
+            let grass_color = [r, g, b, 1.0];
+            let [r, g, b] = terrain.rock_color;
+            let rock_color = [r, g, b, 1.0];
+            let [r, g, b] = terrain.snow_color;
+            let snow_color = [r, g, b, 1.0];
+            let [x, y, z] = sun_direction.unwrap_or(terrain.sun_direction);
+            let sun_direction = [x, y, z, 0.0];
+            for key in selection.draws {
+                let Some(tile) = self.tiles.get_mut(&(terrain_index, key)) else {
+                    continue;
+                };
+                tile.last_picked = self.picks;
+                let (origin, size) = terrain.tile_bounds(key);
+                let offset = view.ubo.push(&TileUBOContent {
+                    origin,
+                    size,
+                    resolution: tile.resolution,
+                    morph: terrain.morph_range(key),
+                    base: terrain.center[1],
+                    snow_height: terrain.snow_height,
+                    grass_color,
+                    rock_color,
+                    snow_color,
+                    sun_direction,
+                });
+                view.draws.push(((terrain_index, key), offset));
+            }
+        }
+        if view.ubo.write(device, queue) {
+            view.bind_group = self.create_bind_group(device, &view.ubo);
+        }
+
+        self.evict();
+        !self
+            .pending
+            .get_mut()
+            .expect("Terrain jobs poisoned!")
+            .is_empty()
+    }
+
+    fn create_bind_group(
+        &self,
+        device: &Device,
+        ubo: &DynamicUniformBuffer<TileUBOContent>,
+    ) -> BindGroup {
+        BindGroupBuilder::new(&self.view_bind_group_layout)
+            .resource(ubo.binding_resource())
+            .create(device, "Terrain View Bind Group")
+    }
+
+    /// Drops the tiles of removed terrains and of those moved, resized or with another tile resolution since they
+    /// were sampled
+    fn forget_changed(&mut self, terrains: &[Option<Terrain>]) {
+        let current: HashMap<usize, Sampled> = terrains
+            .iter()
+            .enumerate()
+            .filter_map(|(index, terrain)| {
+                let terrain = terrain.as_ref()?;
+                let sampled = Sampled {
+                    center: terrain.center,
+                    size: terrain.size,
+                    resolution: terrain.resolution(),
+                };
+                Some((index, sampled))
+            })
+            .collect();
+        if current == self.sampled {
+            return;
+        }
+        let kept = |(terrain, _): &TilesKey, sampled: &HashMap<usize, Sampled>| {
+            sampled.get(terrain) == current.get(terrain)
+        };
+        self.tiles.retain(|key, _| kept(key, &self.sampled));
+        self.pending
+            .get_mut()
+            .expect("Terrain jobs poisoned!")
+            .retain(|key, _| kept(key, &self.sampled));
+        self.sampled = current;
+    }
+
+    /// Uploads the tiles whose jobs are done
+    fn upload_sampled(&mut self, device: &Device, queue: &Queue) {
+        let pending = self.pending.get_mut().expect("Terrain jobs poisoned!");
+        let done: Vec<(TilesKey, TileSamples)> = pending
+            .iter()
+            .filter_map(|(&key, job)| Some((key, job.try_take()?)))
+            .collect();
+        for (key, samples) in done {
+            pending.remove(&key);
+            let resolution = samples.resolution;
+            let heights = create_texture(
+                device,
+                queue,
+                resolution + 1,
+                wgpu::TextureFormat::R32Float,
+                bytemuck::cast_slice(&samples.heights),
+            );
+            let normals = create_texture(
+                device,
+                queue,
+                resolution + 1,
+                wgpu::TextureFormat::Rgba8Unorm,
+                bytemuck::cast_slice(&samples.normals),
+            );
+            let bind_group = BindGroupBuilder::new(&self.tile_bind_group_layout)
+                .texture(&heights.create_view(&wgpu::TextureViewDescriptor::default()))
+                .texture(&normals.create_view(&wgpu::TextureViewDescriptor::default()))
+                .create(device, "Terrain Tile Bind Group");
+            self.tiles.insert(
+                key,
+                Tile {
+                    _heights: heights,
+                    _normals: normals,
+                    bind_group,
+                    resolution,
+                    range: samples.range,
+                    last_picked: self.picks,
+                },
+            );
+        }
+    }
+
+    /// Frees the tiles picked longest ago until at most [RESIDENT_TILES] are left, keeping those just picked
+    fn evict(&mut self) {
+        if self.tiles.len() <= RESIDENT_TILES {
+            return;
+        }
+        let mut tiles: Vec<(u64, TilesKey)> = self
+            .tiles
+            .iter()
+            .filter(|(_, tile)| tile.last_picked < self.picks)
+            .map(|(&key, tile)| (tile.last_picked, key))
+            .collect();
+        tiles.sort_unstable_by_key(|&(last_picked, _)| last_picked);
+        let excess = self.tiles.len() - RESIDENT_TILES;
+        for (_, key) in tiles.into_iter().take(excess) {
+            self.tiles.remove(&key);
+        }
+    }
+
+    /// Frees the tiles of a terrain that was removed
+    pub fn remove_terrain(&mut self, terrain: usize) {
+        self.tiles
+            .retain(|&(tile_terrain, _), _| tile_terrain != terrain);
+        self.pending
+            .get_mut()
+            .expect("Terrain jobs poisoned!")
+            .retain(|&(tile_terrain, _), _| tile_terrain != terrain);
+        self.sampled.remove(&terrain);
+    }
+
+    /// Bytes of the uploaded tiles
+    pub fn size_in_bytes(&self) -> u64 {
+        self.tiles
+            .values()
+            .map(|tile| {
+                let vertices = (tile.resolution as u64 + 1).pow(2);
+                // A height and a normal for every vertex
+                vertices * (4 + 4)
+            })
+            .sum()
+    }
+
+    /// Draws what [TerrainPass::prepare] picked for the view, in the main pass
+    pub fn draw(
+        &self,
+        render_pass: &mut RenderPass,
+        view: Option<&TerrainView>,
+        globals: &BindGroup,
+        stats: &mut RenderStats,
+    ) {
+        let Some(view) = view.filter(|view| !view.draws.is_empty()) else {
+            return;
+        };
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, globals, &[]);
+        stats.pipeline_switches += 1;
+        stats.bind_group_switches += 1;
+        for (key, offset) in &view.draws {
+            let Some(tile) = self.tiles.get(key) else {
+                continue;
+            };
+            // Two triangles for every grid cell
+            let vertices = tile.resolution * tile.resolution * 6;
+            render_pass.set_bind_group(1, &view.bind_group, &[*offset]);
+            render_pass.set_bind_group(2, &tile.bind_group, &[]);
+            render_pass.draw(0..vertices, 0..1);
+            stats.bind_group_switches += 2;
+            stats.draw(vertices, 1);
+        }
+    }
+}
+
+/// A square texture of a tile, one texel per vertex
+fn create_texture(
+    device: &Device,
+    queue: &Queue,
+    size: u32,
+    format: wgpu::TextureFormat,
+    texels: &[u8],
+) -> wgpu::Texture {
+    wgpu::util::DeviceExt::create_texture_with_data(
+        device,
+        queue,
+        &wgpu::TextureDescriptor {
+            label: Some("Terrain Tile"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+        wgpu::util::TextureDataOrder::LayerMajor,
+        texels,
+    )
+}
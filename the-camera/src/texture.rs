@@ -1,20 +1,42 @@
+use std::sync::Arc;
+
+use crate::{
+    material_bindings::MaterialBindings,
+    wgpu_utils::resource_cache::{SamplerCache, TextureViewCache},
+};
+
 pub struct Texture {
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
-    pub sampler: wgpu::Sampler,
+    /// Shared with every texture sampled the same way, see [SamplerCache]
+    pub sampler: Arc<wgpu::Sampler>,
+    views: TextureViewCache,
 }
 
 impl Texture {
     pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+    /// Of the object ID attachment the main pass writes for picking
+    pub const ID_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R32Uint;
 
     pub fn create_depth_texture(
         device: &wgpu::Device,
+        samplers: &SamplerCache,
         config: &wgpu::SurfaceConfiguration,
         label: &str,
+    ) -> Self {
+        Self::create_depth_texture_with_size(device, samplers, config.width, config.height, label)
+    }
+
+    pub fn create_depth_texture_with_size(
+        device: &wgpu::Device,
+        samplers: &SamplerCache,
+        width: u32,
+        height: u32,
+        label: &str,
     ) -> Self {
         let size = wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
+            width,
+            height,
             depth_or_array_layers: 1,
         };
         let desc = wgpu::TextureDescriptor {
@@ -24,28 +46,385 @@ impl Texture {
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: Self::DEPTH_FORMAT,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            // Copied from for depth readbacks
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[Self::DEPTH_FORMAT],
         };
         let texture = device.create_texture(&desc);
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Linear,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            compare: Some(wgpu::CompareFunction::LessEqual),
-            lod_min_clamp: 0.0,
-            lod_max_clamp: 100.0,
+        let sampler = samplers.get(
+            device,
+            &wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                compare: Some(wgpu::CompareFunction::LessEqual),
+                lod_min_clamp: 0.0,
+                lod_max_clamp: 100.0,
+                ..Default::default()
+            },
+        );
+
+        Self::new(texture, view, sampler)
+    }
+
+    /// A color texture that can be rendered into and then sampled, e.g. by a material
+    pub fn create_render_target(
+        device: &wgpu::Device,
+        samplers: &SamplerCache,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        Self::from_render_target(device, samplers, texture)
+    }
+
+    /// Wraps a texture created elsewhere like [Texture::create_render_target] creates one, e.g. in exportable
+    /// memory
+    pub fn from_render_target(
+        device: &wgpu::Device,
+        samplers: &SamplerCache,
+        texture: wgpu::Texture,
+    ) -> Self {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = Self::color_sampler(device, samplers);
+
+        Self::new(texture, view, sampler)
+    }
+
+    /// Six square faces that are copied into and sampled as a cube, blending between its `mip_level_count` levels
+    pub fn create_cube_render_target(
+        device: &wgpu::Device,
+        samplers: &SamplerCache,
+        size: u32,
+        mip_level_count: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: 6,
+            },
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::Cube),
             ..Default::default()
         });
+        let sampler = samplers.get(
+            device,
+            &wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            },
+        );
+
+        Self::new(texture, view, sampler)
+    }
+
+    /// Filled by copies from a buffer or another texture and sampled linearly, e.g. with data computed on the GPU
+    pub fn create_copy_target(
+        device: &wgpu::Device,
+        samplers: &SamplerCache,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = samplers.get(
+            device,
+            &wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            },
+        );
+
+        Self::new(texture, view, sampler)
+    }
+
+    /// Holds the ID of the object drawn into each pixel, 0 where there is none
+    pub fn create_id_texture(
+        device: &wgpu::Device,
+        samplers: &SamplerCache,
+        width: u32,
+        height: u32,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::ID_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                // Read by the outline pass
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        // Integer textures can't be filtered, the sampler only exists to fill the field
+        let sampler = samplers.get(device, &wgpu::SamplerDescriptor::default());
+
+        Self::new(texture, view, sampler)
+    }
+
+    /// A single white texel, bound by materials without a texture so every material can use the same shader
+    pub fn create_white(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        samplers: &SamplerCache,
+    ) -> Self {
+        Self::from_rgba(device, queue, samplers, 1, 1, &[255; 4], "White Texture")
+    }
+
+    /// A color texture filled with tightly packed 8 bit RGBA pixels, row by row
+    pub fn from_rgba(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        samplers: &SamplerCache,
+        width: u32,
+        height: u32,
+        rgba: &[u8],
+        label: &str,
+    ) -> Self {
+        Self::from_rgba_layers(device, queue, samplers, width, height, 1, rgba, label)
+    }
+
+    /// An array texture of `layers` images, each tightly packed 8 bit RGBA pixels after the previous one. Can be
+    /// written to afterwards, e.g. by [crate::flipbook] replacing frames.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_rgba_layers(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        samplers: &SamplerCache,
+        width: u32,
+        height: u32,
+        layers: u32,
+        rgba: &[u8],
+        label: &str,
+    ) -> Self {
+        let texture = wgpu::util::DeviceExt::create_texture_with_data(
+            device,
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: layers,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            rgba,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = Self::color_sampler(device, samplers);
+
+        Self::new(texture, view, sampler)
+    }
 
+    /// A 3D texture of tightly packed 8 bit RGBA voxels, row by row and slice by slice, sampled with clamped linear
+    /// filtering
+    pub fn from_rgba_volume(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        samplers: &SamplerCache,
+        [width, height, depth]: [u32; 3],
+        rgba: &[u8],
+        label: &str,
+    ) -> Self {
+        let texture = wgpu::util::DeviceExt::create_texture_with_data(
+            device,
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: depth,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            rgba,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = Self::color_sampler(device, samplers);
+
+        Self::new(texture, view, sampler)
+    }
+
+    fn new(texture: wgpu::Texture, view: wgpu::TextureView, sampler: Arc<wgpu::Sampler>) -> Self {
         Self {
             texture,
             view,
             sampler,
+            views: TextureViewCache::new(),
+        }
+    }
+
+    /// A view of part of the texture, e.g. a single mip level or array layer, created once per descriptor
+    pub fn view_with(&self, desc: &wgpu::TextureViewDescriptor) -> Arc<wgpu::TextureView> {
+        self.views.get(&self.texture, desc)
+    }
+
+    /// Roughly what the texture takes up in memory over all mip levels and layers, for depth/stencil formats without
+    /// a copyable aspect it's a guess
+    pub fn size_in_bytes(&self) -> u64 {
+        let texture = &self.texture;
+        let format = texture.format();
+        let block_size = format
+            .block_copy_size(None)
+            .or_else(|| format.block_copy_size(Some(wgpu::TextureAspect::DepthOnly)))
+            .unwrap_or(4);
+        let (block_width, block_height) = format.block_dimensions();
+        let texels: u64 = (0..texture.mip_level_count())
+            .map(|mip| {
+                let size = texture
+                    .size()
+                    .mip_level_size(mip, texture.dimension())
+                    .physical_size(format);
+                (size.width / block_width) as u64
+                    * (size.height / block_height) as u64
+                    * size.depth_or_array_layers as u64
+            })
+            .sum();
+        texels * block_size as u64 * texture.sample_count() as u64
+    }
+
+    fn color_sampler(device: &wgpu::Device, samplers: &SamplerCache) -> Arc<wgpu::Sampler> {
+        samplers.get(
+            device,
+            &wgpu::SamplerDescriptor {
+                address_mode_u: wgpu::AddressMode::ClampToEdge,
+                address_mode_v: wgpu::AddressMode::ClampToEdge,
+                address_mode_w: wgpu::AddressMode::ClampToEdge,
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                mipmap_filter: wgpu::FilterMode::Nearest,
+                ..Default::default()
+            },
+        )
+    }
+}
+
+/// 8 bit RGBA pixels of an image added with [crate::render_engine::RenderEngine::add_texture]
+#[derive(Debug, Clone)]
+pub struct TextureData {
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed, row by row
+    pub rgba: Vec<u8>,
+}
+
+/// A texture uploaded to the GPU together with the data it was uploaded from
+pub struct GpuTexture {
+    pub data: TextureData,
+    pub texture: Texture,
+    /// Binds the texture at the material group
+    pub bind_group: wgpu::BindGroup,
+}
+
+impl GpuTexture {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        samplers: &SamplerCache,
+        data: TextureData,
+        material_bindings: &MaterialBindings,
+    ) -> Self {
+        assert_eq!(
+            data.rgba.len(),
+            data.width as usize * data.height as usize * 4,
+            "Texture data doesn't match its size!"
+        );
+        let texture = Texture::from_rgba(
+            device,
+            queue,
+            samplers,
+            data.width,
+            data.height,
+            &data.rgba,
+            "Image Texture",
+        );
+        let bind_group = material_bindings.create_bind_group(device, &texture);
+
+        GpuTexture {
+            data,
+            texture,
+            bind_group,
         }
     }
 }
@@ -0,0 +1,201 @@
+//! Draws windows at a fraction of their resolution and scales the image up to fill them, see
+//! [crate::render_engine::RenderEngine::set_render_scale].
+//!
+//! The main pass and everything drawn into the scene after it, the sky, voxel grids, the selection outline and plugin
+//! passes, go into a texture [crate::viewport::Viewport::render_size] pixels large. It's scaled up into the window
+//! after AMD's FidelityFX Super Resolution 1, by a Lanczos filter narrowed across the edges it finds so they stay
+//! sharp, then sharpened by how much contrast each pixel has around it. Heatmaps, overlays, the view cube, plots and
+//! the HUD are drawn over it at the window's resolution, so text and lines stay crisp.
+//!
+//! At half the scale only a quarter of the pixels are shaded, which is where most of a frame goes on GPUs short of
+//! fill rate, e.g. integrated ones driving a large display. [crate::dynamic_resolution] picks the scale from how long
+//! frames take on the GPU.
+
+use wgpu::{CommandEncoder, Device, Queue, RenderPipeline, TextureFormat, TextureView};
+
+use crate::wgpu_utils::{
+    binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+    binding_types,
+    transient::{TransientDesc, TransientTextures},
+    uniform_buffer::UniformBuffer,
+};
+
+/// The smallest fraction of a window's resolution its scene is drawn at
+pub const MIN_RENDER_SCALE: f32 = 0.25;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct UpscalingUBOContent {
+    sharpness: f32,
+    _padding: [u32; 3],
+}
+
+crate::assert_uniform_layout!(UpscalingUBOContent {
+    sharpness: ALIGN_SCALAR,
+});
+
+/// Scales the scenes of every window up, shared by all of them
+pub(crate) struct UpscalingPass {
+    upscale_pipeline: RenderPipeline,
+    sharpen_pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayoutWithDesc,
+    ubo: UniformBuffer<UpscalingUBOContent>,
+    /// The scene scaled up and not sharpened yet, windows of the same size share it
+    scratch: TransientTextures,
+    format: TextureFormat,
+}
+
+impl UpscalingPass {
+    /// `format` has to be the engine's swapchain format, the scenes are drawn in it and scaled up into the windows
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let _span = tracing::debug_span!("create_upscaling_pipelines").entered();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Upscaling Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("upscaling.wgsl").into()),
+        });
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_fragment(binding_types::uniform())
+            .next_binding_fragment(binding_types::texture2D())
+            .create(device, "Upscaling Bind Group");
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_group_layout.layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |label: &str, entry_point: &str| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&pipeline_layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: Some("vs_main"),
+                    buffers: &[],
+                    compilation_options: Default::default(),
+                },
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point: Some(entry_point),
+                    // Every pixel is written, transparent ones included
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                    compilation_options: Default::default(),
+                }),
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        UpscalingPass {
+            upscale_pipeline: pipeline("upscale", "fs_upscale"),
+            sharpen_pipeline: pipeline("sharpen", "fs_sharpen"),
+            bind_group_layout,
+            ubo: UniformBuffer::new(device),
+            scratch: TransientTextures::new("Upscaling"),
+            format,
+        }
+    }
+
+    /// Once per frame, lets go of scratch textures no window of that size needs anymore
+    pub fn begin_frame(&mut self) {
+        self.scratch.begin_frame();
+    }
+
+    /// Records scaling `scene` up into `view` of `width` by `height` pixels, sharpening it by `sharpness` from 0 to 1
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        scene: &TextureView,
+        view: &TextureView,
+        (width, height): (u32, u32),
+        sharpness: f32,
+    ) {
+        // Without sharpening the scene is scaled straight into the window
+        if sharpness <= 0.0 {
+            self.record(
+                device,
+                encoder,
+                &self.upscale_pipeline,
+                "upscale",
+                scene,
+                view,
+            );
+            return;
+        }
+        self.ubo.update_content(
+            queue,
+            UpscalingUBOContent {
+                sharpness,
+                _padding: [0; 3],
+            },
+        );
+        let upscaled = self.scratch.acquire(
+            device,
+            TransientDesc::attachment(width, height, self.format),
+            "Upscaled Scene",
+        );
+        self.record(
+            device,
+            encoder,
+            &self.upscale_pipeline,
+            "upscale",
+            scene,
+            &upscaled.view,
+        );
+        self.record(
+            device,
+            encoder,
+            &self.sharpen_pipeline,
+            "sharpen",
+            &upscaled.view,
+            view,
+        );
+        self.scratch.release(upscaled);
+    }
+
+    fn record(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        pipeline: &RenderPipeline,
+        label: &str,
+        source: &TextureView,
+        view: &TextureView,
+    ) {
+        // The scenes are recreated with their windows, so the bind groups can't be kept
+        let bind_group = BindGroupBuilder::new(&self.bind_group_layout)
+            .resource(self.ubo.binding_resource())
+            .texture(source)
+            .create(device, "Upscaling Bind Group");
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+
+    /// Bytes of the scratch textures, the scenes are counted with their windows
+    pub fn size_in_bytes(&self) -> u64 {
+        self.scratch.allocated_bytes()
+    }
+}
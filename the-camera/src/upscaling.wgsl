@@ -0,0 +1,150 @@
+// Scales the scene drawn at the render scale up to the window and sharpens it, after AMD's FidelityFX Super
+// Resolution 1: an edge adaptive Lanczos filter shaped by the edges it finds, then contrast adaptive
+// sharpening that keeps off edges it would ring at
+
+struct Upscaling {
+    // From 0 for none to 1 for as much as ringing allows
+    sharpness: f32,
+}
+@group(0) @binding(0)
+var<uniform> upscaling: Upscaling;
+// The scene for the upscale, the upscaled image for the sharpening
+@group(0) @binding(1)
+var t_source: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn load(texel: vec2<i32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(t_source));
+    return textureLoad(t_source, clamp(texel, vec2<i32>(0), size - 1), 0);
+}
+
+// Green counts twice, it's cheaper than a proper luminance and good enough for finding edges
+fn luma(color: vec4<f32>) -> f32 {
+    return color.r * 0.5 + color.g + color.b * 0.5;
+}
+
+// Adds the gradient around texel `c` with neighbours `l`, `r`, `u` and `d` to the direction, and how much of an edge
+// it is to the length, both weighted by `weight`
+fn accumulate_edge(
+    edge: ptr<function, vec3<f32>>,
+    weight: f32,
+    c: f32,
+    l: f32,
+    r: f32,
+    u: f32,
+    d: f32,
+) {
+    let gradient = vec2<f32>(r - l, d - u);
+    let steepest = max(abs(vec2<f32>(r - c, d - c)), abs(vec2<f32>(c - l, c - u)));
+    // A gradient as steep as its steeper half is an edge, one that changes direction is detail
+    let edginess = saturate(abs(gradient) / max(steepest, vec2<f32>(1.0 / 65536.0)));
+    *edge += vec3<f32>(gradient, dot(edginess * edginess, vec2<f32>(0.5))) * weight;
+}
+
+// Lanczos 2 approximated by a polynomial, its negative lobe shrinking as `lobe` goes from 1/2 to 1/4
+fn lanczos2(distance_squared: f32, lobe: f32) -> f32 {
+    let d2 = min(distance_squared, 1.0 / lobe);
+    let window = lobe * d2 - 1.0;
+    let base = 0.4 * d2 - 1.0;
+    return (25.0 / 16.0 * base * base - 9.0 / 16.0) * window * window;
+}
+
+@fragment
+fn fs_upscale(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_source));
+    let position = in.uv * size - 0.5;
+    let origin = vec2<i32>(floor(position));
+    let fraction = position - floor(position);
+
+    // The 4x4 texels around the position, the corners are too far away to be weighed
+    var taps: array<vec4<f32>, 16>;
+    var lumas: array<f32, 16>;
+    for (var index = 0; index < 16; index++) {
+        taps[index] = load(origin + vec2<i32>(index % 4 - 1, index / 4 - 1));
+        lumas[index] = luma(taps[index]);
+    }
+
+    // The edge through the four texels nearest to the position, bilinearly weighted
+    var edge = vec3<f32>(0.0);
+    let weights = vec4<f32>(
+        (1.0 - fraction.x) * (1.0 - fraction.y),
+        fraction.x * (1.0 - fraction.y),
+        (1.0 - fraction.x) * fraction.y,
+        fraction.x * fraction.y,
+    );
+    for (var corner = 0; corner < 4; corner++) {
+        let c = 5 + corner % 2 + corner / 2 * 4;
+        accumulate_edge(&edge, weights[corner], lumas[c], lumas[c - 1], lumas[c + 1], lumas[c - 4], lumas[c + 4]);
+    }
+    var direction = vec2<f32>(1.0, 0.0);
+    if dot(edge.xy, edge.xy) > 1.0 / 32768.0 {
+        direction = normalize(edge.xy);
+    }
+    let strength = edge.z * edge.z;
+
+    // Across an edge the kernel narrows and along it it widens, so the edge stays sharp and its steps are blurred
+    // away. Diagonal edges are narrowed the most, the texels lie furthest apart across them.
+    let stretch = 1.0 / max(abs(direction.x), abs(direction.y));
+    let scale = vec2<f32>(1.0 + (stretch - 1.0) * strength, 1.0 - 0.5 * strength);
+    let lobe = 0.5 - (0.5 - 0.21) * strength;
+
+    var sum = vec4<f32>(0.0);
+    var total = 0.0;
+    var lowest = taps[5];
+    var highest = taps[5];
+    for (var index = 0; index < 16; index++) {
+        let x = index % 4;
+        let y = index / 4;
+        if (x == 0 || x == 3) && (y == 0 || y == 3) {
+            continue;
+        }
+        let offset = vec2<f32>(f32(x - 1), f32(y - 1)) - fraction;
+        let rotated = vec2<f32>(dot(offset, direction), dot(offset, vec2<f32>(-direction.y, direction.x))) * scale;
+        let weight = lanczos2(dot(rotated, rotated), lobe);
+        sum += taps[index] * weight;
+        total += weight;
+        if (x == 1 || x == 2) && (y == 1 || y == 2) {
+            lowest = min(lowest, taps[index]);
+            highest = max(highest, taps[index]);
+        }
+    }
+    // The negative lobes ring around edges, kept within the four nearest texels
+    return clamp(sum / total, lowest, highest);
+}
+
+// Sharpening past this makes the lobe turn a flat area noisy
+const SHARPEN_LIMIT: f32 = 0.25 - 1.0 / 16.0;
+
+@fragment
+fn fs_sharpen(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    let up = load(pixel + vec2<i32>(0, -1)).rgb;
+    let left = load(pixel + vec2<i32>(-1, 0)).rgb;
+    let center = load(pixel);
+    let right = load(pixel + vec2<i32>(1, 0)).rgb;
+    let down = load(pixel + vec2<i32>(0, 1)).rgb;
+
+    // The negative lobe of the sharpening kernel as large as it can be without pushing the center out of 0 to 1
+    let lowest = min(min(up, down), min(left, right));
+    let highest = max(max(up, down), max(left, right));
+    let hit_low = min(lowest, center.rgb) / max(4.0 * highest, vec3<f32>(1.0 / 65536.0));
+    let hit_high = (1.0 - max(highest, center.rgb)) / min(4.0 * lowest - 4.0, vec3<f32>(-1.0 / 65536.0));
+    let lobes = max(-hit_low, hit_high);
+    let lobe = max(-SHARPEN_LIMIT, min(max(lobes.r, max(lobes.g, lobes.b)), 0.0)) * upscaling.sharpness;
+
+    let color = (lobe * (up + left + right + down) + center.rgb) / (4.0 * lobe + 1.0);
+    return vec4<f32>(color, center.a);
+}
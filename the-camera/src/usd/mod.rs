@@ -0,0 +1,561 @@
+//! Loading a practical subset of USD, the scene format of many DCC pipelines and of AR Quick Look on iOS: meshes
+//! with their transforms and display colors, and the color and opacity of UsdPreviewSurface materials bound to them.
+//! Text `.usda` layers are read, on their own or as the root layer of a `.usdz` package.
+//!
+//! Left out are binary `.usdc` layers, composition (sublayers, references, payloads, variants and instancing),
+//! textures, which materials can't sample yet, and `GeomSubset` materials. Animated values show their first sample.
+
+mod usda;
+mod usdz;
+
+use std::{collections::HashMap, io};
+
+use cgmath::{Deg, InnerSpace, Matrix4, Quaternion, SquareMatrix};
+
+use self::usda::{Layer, Prim, Property, Specifier, Value};
+use crate::{
+    assets::encode_srgb,
+    mesh::Vertex,
+    model::{ModelData, ModelPart, PolygonBuilder},
+    scene::Material,
+};
+
+/// Reads a `.usda`, `.usd` or `.usdz` file, telling them apart by their first bytes
+pub fn decode(bytes: &[u8]) -> io::Result<ModelData> {
+    if bytes.starts_with(b"PK\x03\x04") {
+        let (name, layer) = usdz::root_layer(bytes)?;
+        return decode(layer).map_err(|err| io::Error::new(err.kind(), format!("{name}: {err}")));
+    }
+    if bytes.starts_with(b"PXR-USDC") {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Binary USD layers aren't supported, convert them to text with usdcat first",
+        ));
+    }
+    let source = std::str::from_utf8(bytes)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    parse_usda(source)
+}
+
+/// Reads the meshes of a text USD layer, in a Y up space whatever the layer's up axis is
+pub fn parse_usda(source: &str) -> io::Result<ModelData> {
+    let layer = usda::parse(source)?;
+    let mut stage = Stage {
+        prims: HashMap::new(),
+        materials: HashMap::new(),
+    };
+    for prim in &layer.prims {
+        stage.index(prim, String::new());
+    }
+
+    let mut model = ModelData::default();
+    let root = up_axis_rotation(&layer);
+    for prim in &layer.prims {
+        stage.walk(prim, format!("/{}", prim.name), root, None, &mut model)?;
+    }
+    if model.parts.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "USD layer has no meshes",
+        ));
+    }
+    Ok(model)
+}
+
+/// Turns Z up layers so Z ends up where the engine's Y is
+fn up_axis_rotation(layer: &Layer) -> Matrix4<f32> {
+    match layer.metadata("upAxis").and_then(Value::as_str) {
+        Some("Z") => Matrix4::from_angle_x(Deg(-90.0)),
+        _ => Matrix4::identity(),
+    }
+}
+
+struct Stage<'a> {
+    /// Every prim by its path, for resolving material bindings and shader connections
+    prims: HashMap<String, &'a Prim>,
+    materials: HashMap<String, Material>,
+}
+
+impl<'a> Stage<'a> {
+    fn index(&mut self, prim: &'a Prim, parent: String) {
+        let path = format!("{parent}/{}", prim.name);
+        for child in &prim.children {
+            self.index(child, path.clone());
+        }
+        self.prims.insert(path, prim);
+    }
+
+    /// Adds the meshes at and below `prim`, `binding` is the material bound to its closest ancestor
+    fn walk(
+        &mut self,
+        prim: &Prim,
+        path: String,
+        parent: Matrix4<f32>,
+        binding: Option<String>,
+        model: &mut ModelData,
+    ) -> io::Result<()> {
+        // Overs and classes only mean something composed onto other layers
+        if prim.specifier != Specifier::Def
+            || prim.metadata("active") == Some(&Value::Number(0.0))
+            || prim.value("visibility").and_then(Value::as_str) == Some("invisible")
+        {
+            return Ok(());
+        }
+        let (local, reset) = local_transform(prim, &path);
+        let transform = if reset { local } else { parent * local };
+        let binding = prim
+            .value("material:binding")
+            .and_then(Value::as_path)
+            .map(|target| resolve(&path, target))
+            .or(binding);
+
+        match prim.type_name.as_deref() {
+            Some("Mesh") => self.mesh(prim, &path, transform, binding.as_deref(), model)?,
+            Some(
+                kind @ ("Cube" | "Sphere" | "Cylinder" | "Cone" | "Capsule" | "Points"
+                | "BasisCurves" | "NurbsPatch"),
+            ) => tracing::warn!("Skipping {path}, {kind} prims aren't supported"),
+            _ => (),
+        }
+        for child in &prim.children {
+            let child_path = format!("{path}/{}", child.name);
+            self.walk(child, child_path, transform, binding.clone(), model)?;
+        }
+        Ok(())
+    }
+
+    fn mesh(
+        &mut self,
+        prim: &Prim,
+        path: &str,
+        transform: Matrix4<f32>,
+        binding: Option<&str>,
+        model: &mut ModelData,
+    ) -> io::Result<()> {
+        let invalid = |message: &str| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{path}: {message}"))
+        };
+        let points: Vec<[f32; 3]> = floats(prim.value("points"))
+            .ok_or_else(|| invalid("points need three coordinates each"))?;
+        let counts = indices(prim.value("faceVertexCounts"))
+            .ok_or_else(|| invalid("invalid faceVertexCounts"))?;
+        let corners = indices(prim.value("faceVertexIndices"))
+            .ok_or_else(|| invalid("invalid faceVertexIndices"))?;
+        if counts.iter().sum::<usize>() != corners.len() {
+            return Err(invalid(
+                "faceVertexCounts don't add up to the faceVertexIndices",
+            ));
+        }
+        if corners.iter().any(|&point| point >= points.len()) {
+            return Err(invalid("face vertex index out of range"));
+        }
+
+        // Named st by convention, other names are taken if there's no st
+        let tex_coords = prim
+            .properties
+            .get_key_value("primvars:st")
+            .or_else(|| {
+                prim.properties.iter().find(|(name, property)| {
+                    name.starts_with("primvars:") && property.type_name.starts_with("texCoord2")
+                })
+            })
+            .map(|(name, _)| {
+                Primvar::<2>::read(prim, name, points.len(), counts.len(), corners.len())
+            })
+            .transpose()
+            .map_err(|message| invalid(&message))?;
+        // A bound material replaces the display color
+        let (colors, material) = match binding {
+            Some(binding) => (None, self.material(binding)),
+            None => (
+                Primvar::<3>::read(
+                    prim,
+                    "primvars:displayColor",
+                    points.len(),
+                    counts.len(),
+                    corners.len(),
+                )
+                .ok(),
+                Material::default(),
+            ),
+        };
+        // Mirroring transforms turn faces inside out as well, the engine draws counter-clockwise faces
+        let left_handed = prim.value("orientation").and_then(Value::as_str) == Some("leftHanded");
+        let flip = left_handed != (transform.determinant() < 0.0);
+
+        // Corners sharing position, texture coordinates and color share a vertex
+        let mut polygons = PolygonBuilder::<(usize, Option<usize>, Option<usize>)>::default();
+        let mut corner = 0;
+        for (face, &count) in counts.iter().enumerate() {
+            let keys: Vec<_> = (corner..corner + count)
+                .map(|corner| {
+                    let point = corners[corner];
+                    let tex_coord = tex_coords
+                        .as_ref()
+                        .and_then(|st| st.element(face, point, corner));
+                    let color = colors
+                        .as_ref()
+                        .and_then(|colors| colors.element(face, point, corner));
+                    (point, tex_coord, color)
+                })
+                .collect();
+            corner += count;
+            polygons.add(&keys, flip, |&(point, tex_coord, color)| {
+                // USD counts V from the bottom of the image, wgpu from the top
+                let [u, v] = tex_coord
+                    .and_then(|element| tex_coords.as_ref()?.values.get(element).copied())
+                    .unwrap_or([0.0, 1.0]);
+                let color = color
+                    .and_then(|element| colors.as_ref()?.values.get(element).copied())
+                    .map_or([1.0; 3], |color| color.map(encode_srgb));
+                Vertex::new(points[point], color, [u, 1.0 - v])
+            });
+        }
+
+        // Meshes with more vertices than 16 bit indices reach come out as several parts
+        model
+            .parts
+            .extend(polygons.finish().into_iter().map(|(mesh, _)| ModelPart {
+                name: path.to_string(),
+                mesh,
+                material,
+                texture: None,
+                transform,
+                skin: None,
+                joint: None,
+            }));
+        Ok(())
+    }
+
+    /// The color and opacity of the UsdPreviewSurface a material's surface output connects to
+    fn material(&mut self, path: &str) -> Material {
+        if let Some(material) = self.materials.get(path) {
+            return *material;
+        }
+        let material = self.preview_surface(path).unwrap_or_else(|| {
+            tracing::warn!("{path} isn't a UsdPreviewSurface material, drawing it white");
+            Material::default()
+        });
+        self.materials.insert(path.to_string(), material);
+        material
+    }
+
+    fn preview_surface(&self, path: &str) -> Option<Material> {
+        let material = self.prims.get(path)?;
+        let output = material
+            .properties
+            .get("outputs:surface")?
+            .connection
+            .as_ref()?
+            .as_path()?;
+        let shader_path = resolve(path, output);
+        let shader = self.prims.get(&shader_path)?;
+        if shader.value("info:id").and_then(Value::as_str) != Some("UsdPreviewSurface") {
+            return None;
+        }
+
+        let input = |name: &str| -> Option<&Property> {
+            let input = shader.properties.get(name)?;
+            if input.connection.is_some() {
+                tracing::warn!(
+                    "{shader_path}: textures aren't supported, {name} keeps its own value"
+                );
+            }
+            Some(input)
+        };
+        // The specification's fallbacks
+        let [r, g, b] = input("inputs:diffuseColor")
+            .and_then(Property::value)
+            .and_then(Value::as_floats)
+            .unwrap_or([0.18; 3]);
+        let opacity = input("inputs:opacity")
+            .and_then(Property::value)
+            .and_then(Value::as_f32)
+            .unwrap_or(1.0);
+        Some(Material {
+            base_color: [encode_srgb(r), encode_srgb(g), encode_srgb(b), opacity],
+            ..Default::default()
+        })
+    }
+}
+
+/// A per-mesh value like texture coordinates or colors, stored once, per face, per point or per face corner. Indexed
+/// primvars list which of their values each element takes.
+struct Primvar<const N: usize> {
+    values: Vec<[f32; N]>,
+    indices: Option<Vec<usize>>,
+    interpolation: Interpolation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interpolation {
+    Constant,
+    Uniform,
+    Vertex,
+    FaceVarying,
+}
+
+impl<const N: usize> Primvar<N> {
+    fn read(
+        prim: &Prim,
+        name: &str,
+        points: usize,
+        faces: usize,
+        corners: usize,
+    ) -> Result<Self, String> {
+        let property = prim
+            .properties
+            .get(name)
+            .ok_or_else(|| format!("no {name}"))?;
+        let values = floats(property.value()).ok_or_else(|| format!("invalid {name}"))?;
+        let indices = match prim.value(&format!("{name}:indices")) {
+            Some(value) => {
+                Some(indices(Some(value)).ok_or_else(|| format!("invalid {name}:indices"))?)
+            }
+            None => None,
+        };
+        let elements = indices.as_ref().map_or(values.len(), Vec::len);
+        // Without an interpolation the count of elements tells
+        let interpolation = match property.metadata("interpolation").and_then(Value::as_str) {
+            Some("constant") => Interpolation::Constant,
+            Some("uniform") => Interpolation::Uniform,
+            Some("vertex" | "varying") => Interpolation::Vertex,
+            Some("faceVarying") => Interpolation::FaceVarying,
+            Some(other) => return Err(format!("unknown interpolation {other} of {name}")),
+            None if elements == corners => Interpolation::FaceVarying,
+            None if elements == points => Interpolation::Vertex,
+            None if elements == faces && elements > 1 => Interpolation::Uniform,
+            None => Interpolation::Constant,
+        };
+        Ok(Primvar {
+            values,
+            indices,
+            interpolation,
+        })
+    }
+
+    /// Which of the values a face corner takes, None if there isn't one for it
+    fn element(&self, face: usize, point: usize, corner: usize) -> Option<usize> {
+        let element = match self.interpolation {
+            Interpolation::Constant => 0,
+            Interpolation::Uniform => face,
+            Interpolation::Vertex => point,
+            Interpolation::FaceVarying => corner,
+        };
+        let element = match &self.indices {
+            Some(indices) => *indices.get(element)?,
+            None => element,
+        };
+        (element < self.values.len()).then_some(element)
+    }
+}
+
+/// The prim's own transform from its `xformOpOrder`, and whether it ignores its parents' ones
+fn local_transform(prim: &Prim, path: &str) -> (Matrix4<f32>, bool) {
+    let mut transform = Matrix4::identity();
+    let mut reset = false;
+    let Some(order) = prim.value("xformOpOrder") else {
+        return (transform, reset);
+    };
+    for op in order.items().iter().filter_map(Value::as_str) {
+        if op == "!resetXformStack!" {
+            reset = true;
+            continue;
+        }
+        let (invert, name) = match op.strip_prefix("!invert!") {
+            Some(name) => (true, name),
+            None => (false, op),
+        };
+        let Some(value) = prim.value(name) else {
+            tracing::warn!("{path}: {name} is in the xformOpOrder but has no value");
+            continue;
+        };
+        let kind = name
+            .strip_prefix("xformOp:")
+            .and_then(|op| op.split(':').next())
+            .unwrap_or(name);
+        let Some(matrix) = transform_op(kind, value) else {
+            tracing::warn!(
+                "{path}: skipping {name}, {kind} isn't supported or its value is invalid"
+            );
+            continue;
+        };
+        transform = transform
+            * if invert {
+                matrix.invert().unwrap_or_else(Matrix4::identity)
+            } else {
+                matrix
+            };
+    }
+    (transform, reset)
+}
+
+fn transform_op(kind: &str, value: &Value) -> Option<Matrix4<f32>> {
+    let rotation = |axis: char, degrees: f32| match axis {
+        'X' => Some(Matrix4::from_angle_x(Deg(degrees))),
+        'Y' => Some(Matrix4::from_angle_y(Deg(degrees))),
+        'Z' => Some(Matrix4::from_angle_z(Deg(degrees))),
+        _ => None,
+    };
+    match kind {
+        "translate" => Some(Matrix4::from_translation(value.as_floats::<3>()?.into())),
+        "scale" => {
+            let [x, y, z] = value.as_floats()?;
+            Some(Matrix4::from_nonuniform_scale(x, y, z))
+        }
+        // Real part first
+        "orient" => {
+            let [w, x, y, z] = value.as_floats()?;
+            Some(Quaternion::new(w, x, y, z).normalize().into())
+        }
+        // Rows with the translation last, USD multiplies row vectors with them
+        "transform" => {
+            let rows = value.items();
+            let row = |index: usize| rows.get(index)?.as_floats::<4>();
+            Some(Matrix4::from_cols(
+                row(0)?.into(),
+                row(1)?.into(),
+                row(2)?.into(),
+                row(3)?.into(),
+            ))
+        }
+        "rotateX" | "rotateY" | "rotateZ" => rotation(kind.chars().last()?, value.as_f32()?),
+        // Around the first axis named first, e.g. X then Y then Z for rotateXYZ
+        _ if kind.len() == 9 && kind.starts_with("rotate") => {
+            let angles: [f32; 3] = value.as_floats()?;
+            kind[6..]
+                .chars()
+                .zip(angles)
+                .try_fold(Matrix4::identity(), |matrix, (axis, degrees)| {
+                    Some(rotation(axis, degrees)? * matrix)
+                })
+        }
+        _ => None,
+    }
+}
+
+/// Resolves a relationship or connection target to a prim path, dropping any property after the last prim
+fn resolve(from: &str, target: &str) -> String {
+    let (prims, last) = target.rsplit_once('/').unwrap_or(("", target));
+    let last = match last {
+        "." | ".." => last,
+        _ => last.split('.').next().unwrap_or(last),
+    };
+    let mut segments: Vec<&str> = if target.starts_with('/') {
+        Vec::new()
+    } else {
+        from.split('/')
+            .filter(|segment| !segment.is_empty())
+            .collect()
+    };
+    for segment in prims.split('/').chain([last]) {
+        match segment {
+            "" | "." => (),
+            ".." => {
+                segments.pop();
+            }
+            _ => segments.push(segment),
+        }
+    }
+    format!("/{}", segments.join("/"))
+}
+
+/// An array of number tuples like `point3f[]`
+fn floats<const N: usize>(value: Option<&Value>) -> Option<Vec<[f32; N]>> {
+    value?.items().iter().map(Value::as_floats).collect()
+}
+
+fn indices(value: Option<&Value>) -> Option<Vec<usize>> {
+    value?
+        .items()
+        .iter()
+        .map(|item| match *item {
+            Value::Number(number) if number >= 0.0 && number.fract() == 0.0 => {
+                Some(number as usize)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A unit quad in the XY plane, moved along X and colored red, in a layer with `up_axis` up
+    fn quad_layer(up_axis: &str) -> String {
+        format!(
+            r#"#usda 1.0
+(
+    upAxis = "{up_axis}"
+)
+
+def Xform "Root"
+{{
+    double3 xformOp:translate = (2, 0, 0)
+    uniform token[] xformOpOrder = ["xformOp:translate"]
+
+    def Mesh "Quad"
+    {{
+        point3f[] points = [(0, 0, 0), (1, 0, 0), (1, 1, 0), (0, 1, 0)]
+        int[] faceVertexCounts = [4]
+        int[] faceVertexIndices = [0, 1, 2, 3]
+        color3f[] primvars:displayColor = [(1, 0, 0)]
+    }}
+}}
+"#
+        )
+    }
+
+    #[test]
+    fn reads_meshes_with_their_transforms_and_colors() {
+        let model = parse_usda(&quad_layer("Y")).unwrap();
+        assert_eq!(model.parts.len(), 1);
+        let part = &model.parts[0];
+        assert_eq!(part.name, "/Root/Quad");
+        assert_eq!(part.mesh.vertices.len(), 4);
+        assert_eq!(part.mesh.indices.len(), 6);
+        assert_eq!(
+            part.transform,
+            Matrix4::from_translation([2.0, 0.0, 0.0].into())
+        );
+        // Vertex colors are sRGB encoded
+        for vertex in &part.mesh.vertices {
+            assert_eq!(vertex.color(), [1.0, 0.0, 0.0].map(encode_srgb));
+        }
+    }
+
+    #[test]
+    fn turns_z_up_layers_y_up() {
+        let model = parse_usda(&quad_layer("Z")).unwrap();
+        let up = model.parts[0].transform * cgmath::Vector4::unit_z();
+        assert!(
+            (up - cgmath::Vector4::unit_y()).magnitude() < 1e-6,
+            "{up:?}"
+        );
+    }
+
+    #[test]
+    fn decodes_packages_and_rejects_the_rest() {
+        let layer = quad_layer("Y");
+        let package = usdz::tests::package(&[("quad.usda", layer.as_bytes())]);
+        assert_eq!(decode(&package).unwrap().parts.len(), 1);
+
+        let err = decode(b"PXR-USDC\0\0\0\0").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        let err = parse_usda("#usda 1.0\ndef Xform \"Empty\" {}").unwrap_err();
+        assert!(err.to_string().contains("no meshes"), "{err}");
+        let broken = layer.replace("[0, 1, 2, 3]", "[0, 1, 2, 4]");
+        assert!(parse_usda(&broken).is_err());
+    }
+
+    #[test]
+    fn resolves_relative_targets() {
+        assert_eq!(resolve("/Root/Mesh", "/Looks/Red"), "/Looks/Red");
+        assert_eq!(resolve("/Root/Mesh", "../Looks/Red"), "/Root/Looks/Red");
+        assert_eq!(
+            resolve("/Root/Looks/Red", "Surface.outputs:surface"),
+            "/Root/Looks/Red/Surface"
+        );
+    }
+}
@@ -0,0 +1,721 @@
+//! Reading the text form of USD layers into a tree of prims, keeping the values as written. Nothing gets composed:
+//! references, payloads and variant sets are read past.
+
+use std::{collections::HashMap, io};
+
+/// Deeper layers are rejected rather than risking the stack
+const MAX_DEPTH: usize = 128;
+
+/// A value as written in the layer, numbers are kept as f64 whatever type they were declared with
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    None,
+    Number(f64),
+    /// Strings and tokens alike
+    String(String),
+    /// `</Prim/Path.property>`, without the angle brackets
+    Path(String),
+    /// `@file@`, without the at signs
+    Asset(String),
+    /// `(1, 2, 3)`, for vectors, colors, quaternions and matrix rows
+    Tuple(Vec<Value>),
+    /// `[...]`
+    List(Vec<Value>),
+    /// `{ ... }`, time samples are keyed by their time
+    Dictionary(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            Value::Number(number) => Some(*number as f32),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// The path of a relationship or connection, the first one if there's a list of them
+    pub fn as_path(&self) -> Option<&str> {
+        match self {
+            Value::Path(path) => Some(path),
+            Value::List(values) => values.first()?.as_path(),
+            _ => None,
+        }
+    }
+
+    /// What a tuple or list holds, nothing for other values
+    pub fn items(&self) -> &[Value] {
+        match self {
+            Value::Tuple(values) | Value::List(values) => values,
+            _ => &[],
+        }
+    }
+
+    /// A tuple of `N` numbers
+    pub fn as_floats<const N: usize>(&self) -> Option<[f32; N]> {
+        let Value::Tuple(values) = self else {
+            return None;
+        };
+        if values.len() != N {
+            return None;
+        }
+        let mut floats = [0.0; N];
+        for (float, value) in floats.iter_mut().zip(values) {
+            *float = value.as_f32()?;
+        }
+        Some(floats)
+    }
+}
+
+/// An attribute or relationship of a prim
+#[derive(Debug, Clone, Default)]
+pub struct Property {
+    /// As declared, e.g. `point3f[]`, or `rel` for relationships
+    pub type_name: String,
+    pub default: Option<Value>,
+    /// Where `.connect` points
+    pub connection: Option<Value>,
+    pub time_samples: Option<Value>,
+    pub metadata: Vec<(String, Value)>,
+}
+
+impl Property {
+    /// The default value, or the first time sample of animated ones
+    pub fn value(&self) -> Option<&Value> {
+        let first_sample = || match self.time_samples.as_ref()? {
+            Value::Dictionary(samples) => samples.first().map(|(_, value)| value),
+            _ => None,
+        };
+        self.default
+            .as_ref()
+            .filter(|value| **value != Value::None)
+            .or_else(first_sample)
+    }
+
+    pub fn metadata(&self, key: &str) -> Option<&Value> {
+        find(&self.metadata, key)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Specifier {
+    Def,
+    /// Changes another layer's prim of the same path
+    Over,
+    /// Only there to be inherited from
+    Class,
+}
+
+#[derive(Debug, Clone)]
+pub struct Prim {
+    pub specifier: Specifier,
+    /// E.g. `Xform` or `Mesh`, None for typeless prims
+    pub type_name: Option<String>,
+    pub name: String,
+    pub metadata: Vec<(String, Value)>,
+    pub properties: HashMap<String, Property>,
+    pub children: Vec<Prim>,
+}
+
+impl Prim {
+    pub fn metadata(&self, key: &str) -> Option<&Value> {
+        find(&self.metadata, key)
+    }
+
+    /// The value of an attribute, see [Property::value]
+    pub fn value(&self, property: &str) -> Option<&Value> {
+        self.properties.get(property)?.value()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Layer {
+    /// E.g. `upAxis` or `defaultPrim`
+    pub metadata: Vec<(String, Value)>,
+    pub prims: Vec<Prim>,
+}
+
+impl Layer {
+    pub fn metadata(&self, key: &str) -> Option<&Value> {
+        find(&self.metadata, key)
+    }
+}
+
+/// Parses a `.usda` file, starting with its `#usda 1.0` header
+pub fn parse(source: &str) -> io::Result<Layer> {
+    if !source.starts_with("#usda") {
+        return Err(invalid(1, "not a text USD layer"));
+    }
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+    };
+    let mut layer = Layer::default();
+    if parser.eat(&Token::Punct('(')) {
+        layer.metadata = parser.metadata(0)?;
+    }
+    while parser.peek().is_some() {
+        layer.prims.push(parser.prim(0)?);
+    }
+    Ok(layer)
+}
+
+fn find<'a>(entries: &'a [(String, Value)], key: &str) -> Option<&'a Value> {
+    entries
+        .iter()
+        .find(|(entry, _)| entry == key)
+        .map(|(_, value)| value)
+}
+
+fn invalid(line: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("USD line {line}: {message}"),
+    )
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Keywords, type names, property names and unquoted tokens
+    Word(String),
+    Number(f64),
+    String(String),
+    Path(String),
+    Asset(String),
+    Punct(char),
+}
+
+fn tokenize(source: &str) -> io::Result<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    let mut line = 1;
+    while let Some(c) = chars.next() {
+        let start_line = line;
+        let token = match c {
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '#' => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+                continue;
+            }
+            '(' | ')' | '[' | ']' | '{' | '}' | '=' | ',' | ':' | ';' => Token::Punct(c),
+            '"' | '\'' => {
+                // Triple quoted strings span lines, two quotes alone are an empty string
+                let triple = match chars.next_if_eq(&c) {
+                    Some(_) if chars.next_if_eq(&c).is_none() => {
+                        tokens.push((Token::String(String::new()), start_line));
+                        continue;
+                    }
+                    Some(_) => true,
+                    None => false,
+                };
+                let mut string = String::new();
+                loop {
+                    let next = chars
+                        .next()
+                        .ok_or_else(|| invalid(start_line, "unterminated string"))?;
+                    match next {
+                        '\\' => match chars.next() {
+                            Some('n') => string.push('\n'),
+                            Some('t') => string.push('\t'),
+                            Some(escaped) => string.push(escaped),
+                            None => return Err(invalid(start_line, "unterminated string")),
+                        },
+                        '\n' if !triple => return Err(invalid(line, "unterminated string")),
+                        _ if next == c && !triple => break,
+                        _ if next == c && chars.clone().take(2).eq([c, c]) => {
+                            chars.nth(1);
+                            break;
+                        }
+                        _ => {
+                            line += usize::from(next == '\n');
+                            string.push(next);
+                        }
+                    }
+                }
+                Token::String(string)
+            }
+            '<' => {
+                let mut path = String::new();
+                loop {
+                    match chars.next() {
+                        Some('>') => break,
+                        Some('\n') | None => return Err(invalid(start_line, "unterminated path")),
+                        Some(c) => path.push(c),
+                    }
+                }
+                Token::Path(path)
+            }
+            '@' => {
+                // `@@@` quotes asset paths that contain at signs themselves
+                let triple = chars.clone().take(2).eq(['@', '@']);
+                if triple {
+                    chars.nth(1);
+                }
+                let mut asset = String::new();
+                loop {
+                    match chars.next() {
+                        Some('@') if !triple => break,
+                        Some('@') if chars.clone().take(2).eq(['@', '@']) => {
+                            chars.nth(1);
+                            break;
+                        }
+                        Some('\n') | None => {
+                            return Err(invalid(start_line, "unterminated asset path"))
+                        }
+                        Some(c) => asset.push(c),
+                    }
+                }
+                Token::Asset(asset)
+            }
+            c if c.is_ascii_digit() || matches!(c, '-' | '+' | '.') => {
+                let mut number = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'))
+                {
+                    number.push(c);
+                }
+                let parsed = match number.as_str() {
+                    "-inf" => Some(f64::NEG_INFINITY),
+                    "+inf" => Some(f64::INFINITY),
+                    _ => number.parse().ok(),
+                };
+                Token::Number(
+                    parsed.ok_or_else(|| invalid(line, &format!("invalid number {number}")))?,
+                )
+            }
+            // Namespaced names like `xformOp:translate` and `inputs:diffuseColor.connect` are single words, so are
+            // `!invert!` and `!resetXformStack!` in transform op orders
+            c if c.is_alphabetic() || matches!(c, '_' | '!') => {
+                let mut word = c.to_string();
+                while let Some(c) =
+                    chars.next_if(|c| c.is_alphanumeric() || matches!(c, '_' | ':' | '.' | '!'))
+                {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+            _ => return Err(invalid(line, &format!("unexpected {c:?}"))),
+        };
+        tokens.push((token, start_line));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> io::Result<Token> {
+        let (token, _) = self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| self.invalid("unexpected end of file"))?;
+        self.position += 1;
+        Ok(token.clone())
+    }
+
+    /// Skips `token` if it comes next
+    fn eat(&mut self, token: &Token) -> bool {
+        let next = self.peek() == Some(token);
+        self.position += usize::from(next);
+        next
+    }
+
+    fn expect(&mut self, c: char) -> io::Result<()> {
+        match self.next()? {
+            Token::Punct(next) if next == c => Ok(()),
+            token => Err(self.unexpected(&token, &format!("'{c}'"))),
+        }
+    }
+
+    fn word(&mut self) -> io::Result<String> {
+        match self.next()? {
+            Token::Word(word) => Ok(word),
+            token => Err(self.unexpected(&token, "a name")),
+        }
+    }
+
+    /// At the token read last
+    fn invalid(&self, message: &str) -> io::Error {
+        let line = self
+            .tokens
+            .get(self.position.saturating_sub(1))
+            .map_or(1, |&(_, line)| line);
+        invalid(line, message)
+    }
+
+    fn unexpected(&self, token: &Token, expected: &str) -> io::Error {
+        self.invalid(&format!("expected {expected}, found {token:?}"))
+    }
+
+    /// `def Mesh "name" (metadata) { ... }`, the specifier comes next
+    fn prim(&mut self, depth: usize) -> io::Result<Prim> {
+        if depth > MAX_DEPTH {
+            return Err(self.invalid("prims nested too deeply"));
+        }
+        let specifier = match self.word()?.as_str() {
+            "def" => Specifier::Def,
+            "over" => Specifier::Over,
+            "class" => Specifier::Class,
+            word => return Err(self.invalid(&format!("expected a prim, found {word}"))),
+        };
+        let type_name = match self.peek() {
+            Some(Token::Word(_)) => Some(self.word()?),
+            _ => None,
+        };
+        let name = match self.next()? {
+            Token::String(name) => name,
+            token => return Err(self.unexpected(&token, "the prim's name")),
+        };
+        let mut prim = Prim {
+            specifier,
+            type_name,
+            name,
+            metadata: Vec::new(),
+            properties: HashMap::new(),
+            children: Vec::new(),
+        };
+        if self.eat(&Token::Punct('(')) {
+            prim.metadata = self.metadata(depth)?;
+        }
+        self.expect('{')?;
+        loop {
+            match self.peek() {
+                Some(Token::Punct('}')) => {
+                    self.position += 1;
+                    return Ok(prim);
+                }
+                Some(Token::Punct(';')) => self.position += 1,
+                Some(Token::Word(word)) => match word.as_str() {
+                    "def" | "over" | "class" => prim.children.push(self.prim(depth + 1)?),
+                    // Variants would need composing, only what's outside of them is read
+                    "variantSet" => {
+                        self.position += 1;
+                        self.next()?;
+                        self.expect('=')?;
+                        self.skip_block()?;
+                    }
+                    "reorder" => {
+                        self.position += 2;
+                        self.expect('=')?;
+                        self.value(depth)?;
+                    }
+                    _ => self.property(&mut prim, depth)?,
+                },
+                Some(token) => return Err(self.unexpected(&token.clone(), "a property or prim")),
+                None => return Err(self.invalid("unterminated prim")),
+            }
+        }
+    }
+
+    /// `custom uniform float3[] name.connect = value (metadata)` and `rel name = </path>`, with everything but the type
+    /// and name optional
+    fn property(&mut self, prim: &mut Prim, depth: usize) -> io::Result<()> {
+        let mut word = self.word()?;
+        while matches!(
+            word.as_str(),
+            "custom" | "uniform" | "varying" | "prepend" | "append" | "add" | "delete"
+        ) {
+            word = self.word()?;
+        }
+        let mut type_name = word;
+        if type_name != "rel" && self.eat(&Token::Punct('[')) {
+            self.expect(']')?;
+            type_name.push_str("[]");
+        }
+        let name = self.word()?;
+        let (name, field) = match name.rsplit_once('.') {
+            Some((name, field @ ("connect" | "timeSamples"))) => (name.to_string(), Some(field)),
+            _ => (name, None),
+        };
+        let value = if self.eat(&Token::Punct('=')) {
+            Some(self.value(depth)?)
+        } else {
+            None
+        };
+        let metadata = if self.eat(&Token::Punct('(')) {
+            self.metadata(depth)?
+        } else {
+            Vec::new()
+        };
+
+        let property = prim.properties.entry(name).or_default();
+        if property.type_name.is_empty() {
+            property.type_name = type_name;
+        }
+        match field {
+            Some("connect") => property.connection = value,
+            Some(_) => property.time_samples = value,
+            None => property.default = value,
+        }
+        property.metadata.extend(metadata);
+        Ok(())
+    }
+
+    /// `key = value` entries and doc strings up to the closing parenthesis, the opening one was read
+    fn metadata(&mut self, depth: usize) -> io::Result<Vec<(String, Value)>> {
+        let mut metadata = Vec::new();
+        loop {
+            match self.next()? {
+                Token::Punct(')') => return Ok(metadata),
+                Token::Punct(';') => (),
+                Token::String(doc) => metadata.push(("doc".to_string(), Value::String(doc))),
+                Token::Word(mut key) => {
+                    // List editing like `prepend references = ...`
+                    if matches!(
+                        key.as_str(),
+                        "prepend" | "append" | "add" | "delete" | "reorder"
+                    ) {
+                        key = self.word()?;
+                    }
+                    self.expect('=')?;
+                    metadata.push((key, self.value(depth)?));
+                }
+                token => return Err(self.unexpected(&token, "metadata")),
+            }
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> io::Result<Value> {
+        if depth > MAX_DEPTH {
+            return Err(self.invalid("values nested too deeply"));
+        }
+        Ok(match self.next()? {
+            Token::Number(number) => Value::Number(number),
+            Token::String(string) => Value::String(string),
+            Token::Path(path) => Value::Path(path),
+            Token::Asset(asset) => {
+                // References name a prim of the asset after it
+                if let Some(Token::Path(_)) = self.peek() {
+                    self.position += 1;
+                }
+                Value::Asset(asset)
+            }
+            Token::Word(word) => match word.as_str() {
+                "None" => Value::None,
+                "true" => Value::Number(1.0),
+                "false" => Value::Number(0.0),
+                "inf" => Value::Number(f64::INFINITY),
+                "nan" => Value::Number(f64::NAN),
+                _ => Value::String(word),
+            },
+            Token::Punct('(') => Value::Tuple(self.sequence(')', depth + 1)?),
+            Token::Punct('[') => Value::List(self.sequence(']', depth + 1)?),
+            Token::Punct('{') => self.dictionary(depth + 1)?,
+            token => return Err(self.unexpected(&token, "a value")),
+        })
+    }
+
+    /// Comma separated values up to `close`, the opening bracket was read
+    fn sequence(&mut self, close: char, depth: usize) -> io::Result<Vec<Value>> {
+        let mut values = Vec::new();
+        while !self.eat(&Token::Punct(close)) {
+            values.push(self.value(depth)?);
+            if !self.eat(&Token::Punct(',')) {
+                self.expect(close)?;
+                break;
+            }
+        }
+        Ok(values)
+    }
+
+    /// Time samples `0: value, 10: value` or typed entries `string key = value`, the opening brace was read
+    fn dictionary(&mut self, depth: usize) -> io::Result<Value> {
+        let mut entries = Vec::new();
+        loop {
+            let key = match self.next()? {
+                Token::Punct('}') => return Ok(Value::Dictionary(entries)),
+                Token::Punct(',' | ';') => continue,
+                Token::Number(time) => {
+                    self.expect(':')?;
+                    time.to_string()
+                }
+                Token::Word(_) => {
+                    if self.eat(&Token::Punct('[')) {
+                        self.expect(']')?;
+                    }
+                    let key = match self.next()? {
+                        Token::Word(key) | Token::String(key) => key,
+                        token => return Err(self.unexpected(&token, "a dictionary key")),
+                    };
+                    self.expect('=')?;
+                    key
+                }
+                token => return Err(self.unexpected(&token, "a dictionary entry")),
+            };
+            entries.push((key, self.value(depth)?));
+        }
+    }
+
+    /// Reads past a `{ ... }` block, nested ones included
+    fn skip_block(&mut self) -> io::Result<()> {
+        self.expect('{')?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()? {
+                Token::Punct('{') => depth += 1,
+                Token::Punct('}') => depth -= 1,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYER: &str = r#"#usda 1.0
+(
+    defaultPrim = "Root"
+    upAxis = "Z"
+)
+
+def Xform "Root" (
+    kind = "component"
+)
+{
+    # Comments run to the end of the line
+    double3 xformOp:translate.timeSamples = {
+        0: (0, 0, 0),
+        10: (1, 2, 3),
+    }
+    uniform token[] xformOpOrder = ["xformOp:translate"]
+
+    def Mesh "Tri"
+    {
+        point3f[] points = [(0, 0, 0), (1, 0, 0), (0, 1, 0)]
+        int[] faceVertexCounts = [3]
+        int[] faceVertexIndices = [0, 1, 2]
+        color3f[] primvars:displayColor = [(1, 0.5, 0)] (
+            interpolation = "constant"
+        )
+        rel material:binding = </Root/Looks/Red>
+        string doc = """two
+lines"""
+        asset file = @./textures/wood.png@
+        bool doubleSided = true
+    }
+
+    over "Overridden"
+    {
+    }
+}
+"#;
+
+    #[test]
+    fn parses_prims_and_properties() {
+        let layer = parse(LAYER).unwrap();
+        assert_eq!(layer.metadata("upAxis"), Some(&Value::String("Z".into())));
+        assert_eq!(layer.prims.len(), 1);
+
+        let root = &layer.prims[0];
+        assert_eq!(root.specifier, Specifier::Def);
+        assert_eq!(root.type_name.as_deref(), Some("Xform"));
+        assert_eq!(root.name, "Root");
+        assert_eq!(
+            root.metadata("kind").and_then(Value::as_str),
+            Some("component")
+        );
+        assert_eq!(root.properties["xformOpOrder"].type_name, "token[]");
+        assert_eq!(root.children.len(), 2);
+        assert_eq!(root.children[1].specifier, Specifier::Over);
+        assert_eq!(root.children[1].type_name, None);
+
+        let mesh = &root.children[0];
+        assert_eq!(mesh.type_name.as_deref(), Some("Mesh"));
+        let points: Vec<_> = mesh
+            .value("points")
+            .unwrap()
+            .items()
+            .iter()
+            .map(Value::as_floats::<3>)
+            .collect();
+        assert_eq!(
+            points,
+            [
+                Some([0.0, 0.0, 0.0]),
+                Some([1.0, 0.0, 0.0]),
+                Some([0.0, 1.0, 0.0])
+            ]
+        );
+        assert_eq!(mesh.properties["points"].type_name, "point3f[]");
+        let color = &mesh.properties["primvars:displayColor"];
+        assert_eq!(
+            color.metadata("interpolation").and_then(Value::as_str),
+            Some("constant")
+        );
+        assert_eq!(
+            mesh.value("material:binding").and_then(Value::as_path),
+            Some("/Root/Looks/Red")
+        );
+        assert_eq!(
+            mesh.value("doc").and_then(Value::as_str),
+            Some("two\nlines")
+        );
+        assert_eq!(
+            mesh.value("file"),
+            Some(&Value::Asset("./textures/wood.png".into()))
+        );
+        assert_eq!(mesh.value("doubleSided").and_then(Value::as_f32), Some(1.0));
+    }
+
+    #[test]
+    fn animated_values_start_at_their_first_sample() {
+        let layer = parse(LAYER).unwrap();
+        let translate = &layer.prims[0].properties["xformOp:translate"];
+        assert_eq!(translate.default, None);
+        let Some(Value::Dictionary(samples)) = &translate.time_samples else {
+            panic!("{translate:?} has no time samples");
+        };
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[1].0, "10");
+        assert_eq!(
+            translate.value().and_then(Value::as_floats::<3>),
+            Some([0.0; 3])
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_layers() {
+        // The header comes first
+        assert!(parse("def Xform \"Root\" {}").is_err());
+        for source in [
+            "#usda 1.0\ndef Xform \"Root\" {",
+            "#usda 1.0\ndef Xform \"Root\" { float a = \"open }",
+            "#usda 1.0\ndef Xform \"Root\" { float a = }",
+            "#usda 1.0\ncustom \"Root\" {}",
+        ] {
+            assert!(parse(source).is_err(), "{source}");
+        }
+        let err = parse("#usda 1.0\n\ndef Xform \"Root\" { float a = $ }").unwrap_err();
+        assert!(err.to_string().contains("line 3"), "{err}");
+
+        let nested = format!(
+            "#usda 1.0\ndef Xform \"Root\" {{ float a = {} }}",
+            "[".repeat(MAX_DEPTH + 2)
+        );
+        let err = parse(&nested).unwrap_err();
+        assert!(err.to_string().contains("nested too deeply"), "{err}");
+        let prims = "def \"Child\" {".repeat(MAX_DEPTH + 2);
+        let err = parse(&format!("#usda 1.0\n{prims}")).unwrap_err();
+        assert!(err.to_string().contains("nested too deeply"), "{err}");
+    }
+}
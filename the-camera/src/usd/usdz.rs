@@ -0,0 +1,149 @@
+//! Finding the root layer of a USDZ package. Packages are zip archives whose files are stored uncompressed, so
+//! they can be read in place without inflating anything.
+
+use std::io;
+
+const END_OF_DIRECTORY: u32 = 0x0605_4b50;
+const DIRECTORY_ENTRY: u32 = 0x0201_4b50;
+const LOCAL_HEADER: u32 = 0x0403_4b50;
+
+/// The package's first file, its root layer by the specification, along with its name
+pub fn root_layer(bytes: &[u8]) -> io::Result<(&str, &[u8])> {
+    let truncated = || invalid("truncated archive");
+    let u16_at = |offset: usize| -> io::Result<usize> {
+        let bytes = bytes.get(offset..offset + 2).ok_or_else(truncated)?;
+        Ok(usize::from(u16::from_le_bytes([bytes[0], bytes[1]])))
+    };
+    let u32_at = |offset: usize| -> io::Result<u32> {
+        let bytes = bytes.get(offset..offset + 4).ok_or_else(truncated)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    };
+
+    // The end of directory record is last, followed by a comment of up to 64 KiB
+    let end = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .take(0x10000)
+        .find(|&offset| u32_at(offset).ok() == Some(END_OF_DIRECTORY))
+        .ok_or_else(|| invalid("not a zip archive"))?;
+    let entry_count = u16_at(end + 10)?;
+    let mut entry = u32_at(end + 16)? as usize;
+
+    // The directory lists the files in any order, the root layer is the one stored first
+    let mut first: Option<(usize, &str, usize, usize)> = None;
+    for _ in 0..entry_count {
+        if u32_at(entry)? != DIRECTORY_ENTRY {
+            return Err(invalid("corrupt central directory"));
+        }
+        let method = u16_at(entry + 10)?;
+        let size = u32_at(entry + 20)? as usize;
+        let name_length = u16_at(entry + 28)?;
+        let extra_length = u16_at(entry + 30)?;
+        let comment_length = u16_at(entry + 32)?;
+        let header = u32_at(entry + 42)? as usize;
+        let name = bytes
+            .get(entry + 46..entry + 46 + name_length)
+            .ok_or_else(truncated)?;
+        let name = std::str::from_utf8(name).map_err(|_| invalid("file name isn't UTF-8"))?;
+        if first.is_none_or(|(first, ..)| header < first) {
+            first = Some((header, name, method, size));
+        }
+        entry += 46 + name_length + extra_length + comment_length;
+    }
+    let (header, name, method, size) = first.ok_or_else(|| invalid("empty package"))?;
+
+    let extension = name
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase());
+    if !matches!(extension.as_deref(), Some("usda" | "usdc" | "usd")) {
+        return Err(invalid(&format!("the first file {name} isn't a USD layer")));
+    }
+    if method != 0 {
+        return Err(invalid(&format!(
+            "{name} is compressed, packages have to store files as they are"
+        )));
+    }
+    if u32_at(header)? != LOCAL_HEADER {
+        return Err(invalid("corrupt file header"));
+    }
+    let start = header + 30 + u16_at(header + 26)? + u16_at(header + 28)?;
+    let layer = bytes.get(start..start + size).ok_or_else(truncated)?;
+    Ok((name, layer))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("USDZ: {message}"))
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// A zip archive storing `files` uncompressed, in that order, with the central directory listing them backwards
+    pub(in crate::usd) fn package(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        let mut directory = Vec::new();
+        for &(name, contents) in files {
+            let header = archive.len() as u32;
+            let size = (contents.len() as u32).to_le_bytes();
+            let name_length = (name.len() as u16).to_le_bytes();
+            archive.extend(LOCAL_HEADER.to_le_bytes());
+            archive.extend([0; 14]);
+            archive.extend(size);
+            archive.extend(size);
+            archive.extend(name_length);
+            archive.extend([0; 2]);
+            archive.extend(name.as_bytes());
+            archive.extend(contents);
+
+            let mut entry = DIRECTORY_ENTRY.to_le_bytes().to_vec();
+            entry.extend([0; 16]);
+            entry.extend(size);
+            entry.extend(size);
+            entry.extend(name_length);
+            entry.extend([0; 12]);
+            entry.extend(header.to_le_bytes());
+            entry.extend(name.as_bytes());
+            directory.splice(0..0, entry);
+        }
+        let directory_offset = archive.len() as u32;
+        let count = (files.len() as u16).to_le_bytes();
+        archive.extend(&directory);
+        archive.extend(END_OF_DIRECTORY.to_le_bytes());
+        archive.extend([0; 4]);
+        archive.extend(count);
+        archive.extend(count);
+        archive.extend((directory.len() as u32).to_le_bytes());
+        archive.extend(directory_offset.to_le_bytes());
+        archive.extend([0; 2]);
+        archive
+    }
+
+    #[test]
+    fn finds_the_file_stored_first() {
+        let archive = package(&[
+            ("scene.usda", b"#usda 1.0\n"),
+            ("textures/wood.png", b"\x89PNG"),
+        ]);
+        let (name, layer) = root_layer(&archive).unwrap();
+        assert_eq!(name, "scene.usda");
+        assert_eq!(layer, b"#usda 1.0\n");
+    }
+
+    #[test]
+    fn rejects_other_archives() {
+        assert!(root_layer(b"PK\x03\x04 not really").is_err());
+        assert!(root_layer(&package(&[])).is_err());
+        // The root layer has to come first
+        assert!(root_layer(&package(&[("wood.png", b"\x89PNG"), ("scene.usda", b"")])).is_err());
+
+        let mut compressed = package(&[("scene.usda", b"#usda 1.0\n")]);
+        let entry = compressed.len() - 22 - 46 - "scene.usda".len();
+        compressed[entry + 10] = 8;
+        let err = root_layer(&compressed).unwrap_err();
+        assert!(err.to_string().contains("compressed"), "{err}");
+
+        let mut truncated = package(&[("scene.usda", b"#usda 1.0\n")]);
+        truncated.drain(40..44);
+        assert!(root_layer(&truncated).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+// See clipping.rs, the same as in shader.wgsl
+struct Clipping {
+    planes: array<vec4<f32>, 4>,
+    cap_color: vec4<f32>,
+    count: u32,
+    caps: u32,
+}
+
+struct Audio {
+    bands: array<vec4<f32>, 4>,
+    level: f32,
+}
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    clipping: Clipping,
+    audio: Audio,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// One glyph or streamline set, see vector_field/pass.rs
+struct Glyphs {
+    model: mat4x4<f32>,
+    // Magnitudes getting the first and the last color of the colormap
+    magnitude_range: vec2<f32>,
+    // Glyph length per unit of magnitude, unused by tubes
+    scale: f32,
+}
+@group(1) @binding(0)
+var<uniform> glyphs: Glyphs;
+@group(1) @binding(1)
+var colormap: texture_2d<f32>;
+@group(1) @binding(2)
+var colormap_sampler: sampler;
+
+struct ShapeInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+};
+
+struct SampleInput {
+    @location(2) position: vec3<f32>,
+    @location(3) vector: vec3<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    // From 0 at the low to 1 at the high end of the magnitude range
+    @location(0) t: f32,
+    @location(1) world_position: vec3<f32>,
+    @location(2) normal: vec3<f32>,
+};
+
+@vertex
+fn vs_glyph(shape: ShapeInput, sample: SampleInput) -> VertexOutput {
+    // The shape's +Y turned onto the vector, with another side axis for vectors along Z
+    let magnitude = length(sample.vector);
+    let along = sample.vector / magnitude;
+    var side = vec3<f32>(0.0, 0.0, 1.0);
+    if abs(along.z) > 0.99 {
+        side = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let x = normalize(cross(along, side));
+    let z = cross(x, along);
+    let rotation = mat3x3<f32>(x, along, z);
+
+    let local = sample.position + rotation * shape.position * magnitude * glyphs.scale;
+    let world_position = (glyphs.model * vec4<f32>(local, 1.0)).xyz;
+    let model = mat3x3<f32>(glyphs.model[0].xyz, glyphs.model[1].xyz, glyphs.model[2].xyz);
+
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.t = normalized(magnitude);
+    out.world_position = world_position;
+    out.normal = model * rotation * shape.normal;
+    return out;
+}
+
+struct TubeInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) magnitude: f32,
+};
+
+@vertex
+fn vs_tube(tube: TubeInput) -> VertexOutput {
+    let world_position = (glyphs.model * vec4<f32>(tube.position, 1.0)).xyz;
+    let model = mat3x3<f32>(glyphs.model[0].xyz, glyphs.model[1].xyz, glyphs.model[2].xyz);
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.t = normalized(tube.magnitude);
+    out.world_position = world_position;
+    out.normal = model * tube.normal;
+    return out;
+}
+
+fn normalized(magnitude: f32) -> f32 {
+    let range = glyphs.magnitude_range;
+    return clamp((magnitude - range.x) / max(range.y - range.x, 1e-20), 0.0, 1.0);
+}
+
+// Between the centers of the first and the last texel, see colormap.rs
+fn color(t: f32) -> vec3<f32> {
+    let texels = f32(textureDimensions(colormap).x);
+    return textureSample(colormap, colormap_sampler, vec2<f32>((t * (texels - 1.0) + 0.5) / texels, 0.5)).rgb;
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    // Glyphs and tubes can't be picked
+    @location(1) id: u32,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    // Sampled before any fragment is discarded, while control flow is uniform
+    let base = color(in.t);
+    for (var index = 0u; index < camera.clipping.count; index++) {
+        let plane = camera.clipping.planes[index];
+        if dot(plane.xyz, in.world_position) < plane.w {
+            discard;
+        }
+    }
+
+    // Lit from the camera, so every shape reads as a shape from every side
+    let to_camera = normalize(camera.view_pos.xyz - in.world_position);
+    let light = 0.35 + 0.65 * abs(dot(normalize(in.normal), to_camera));
+    var out: FragmentOutput;
+    out.color = vec4<f32>(base * light, 1.0);
+    out.id = 0u;
+    return out;
+}
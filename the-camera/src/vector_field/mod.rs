@@ -0,0 +1,468 @@
+//! Vector fields, like the velocities of a CFD solution or a fluid simulation, and glyphs drawing them.
+//!
+//! A [VectorField] is either a regular grid of vectors, laid out like the texels of a 3D texture, or vectors at
+//! scattered points, e.g. the particles of a simulation. [Glyphs] draw an arrow or a cone at every sample, pointing
+//! along its vector, as long as the vector times [Glyphs::scale] and colored by its magnitude. They're instanced and
+//! drawn in the main pass like meshes, so they're hidden by and hide the rest of the scene.
+//!
+//! [Streamlines] follow a grid's vectors from seeds on a plane, a sphere or picked points and draw the lines as tubes,
+//! colored the same way.
+
+mod pass;
+mod streamlines;
+
+use cgmath::{Matrix4, SquareMatrix, Vector3, Vector4};
+use wgpu::{Device, Queue, TextureFormat};
+
+use crate::{
+    colormap::{Colormap, ColormapTextures},
+    global_bindings::GlobalBindings,
+    lazy_pass::LazyPass,
+};
+
+pub(crate) use self::pass::VectorFieldPass;
+pub use self::streamlines::{Seeds, Streamlines, StreamlinesHandle, TraceDirection};
+
+/// A vector at a position, in the field's own units
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct VectorSample {
+    pub position: [f32; 3],
+    pub vector: [f32; 3],
+}
+
+#[derive(Debug, Clone)]
+pub enum VectorField {
+    /// Vectors at the corners of the cells of a grid filling a box, X first, then Y, then Z. Build with
+    /// [VectorField::grid].
+    Grid {
+        size: [u32; 3],
+        min: [f32; 3],
+        max: [f32; 3],
+        vectors: Vec<[f32; 3]>,
+    },
+    /// Vectors at scattered positions
+    Points(Vec<VectorSample>),
+}
+
+impl VectorField {
+    /// A grid of `size` vectors from the `min` to the `max` corner, X first, then Y, then Z
+    pub fn grid(size: [u32; 3], min: [f32; 3], max: [f32; 3], vectors: Vec<[f32; 3]>) -> Self {
+        assert_eq!(
+            vectors.len(),
+            size.iter().map(|&size| size as usize).product::<usize>(),
+            "Vectors don't match the grid's size!"
+        );
+        VectorField::Grid {
+            size,
+            min,
+            max,
+            vectors,
+        }
+    }
+
+    /// A grid of `size` vectors from the `min` to the `max` corner, each one what `vector` returns for its position
+    pub fn from_fn(
+        size: [u32; 3],
+        min: [f32; 3],
+        max: [f32; 3],
+        mut vector: impl FnMut([f32; 3]) -> [f32; 3],
+    ) -> Self {
+        let mut vectors = Vec::with_capacity(size.iter().map(|&size| size as usize).product());
+        for z in 0..size[2] {
+            for y in 0..size[1] {
+                for x in 0..size[0] {
+                    vectors.push(vector(grid_position(size, min, max, [x, y, z])));
+                }
+            }
+        }
+        Self::grid(size, min, max, vectors)
+    }
+
+    /// The vector at a position, filtered linearly between the grid's samples. None outside of the grid's box and
+    /// for scattered points, which have nothing to filter between.
+    pub fn sample(&self, position: [f32; 3]) -> Option<[f32; 3]> {
+        let VectorField::Grid {
+            size,
+            min,
+            max,
+            vectors,
+        } = self
+        else {
+            return None;
+        };
+        if size.contains(&0) {
+            return None;
+        }
+        let mut cell = [0; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            let extent = max[axis] - min[axis];
+            let t = if extent > 0.0 {
+                (position[axis] - min[axis]) / extent
+            } else {
+                0.0
+            };
+            if !(0.0..=1.0).contains(&t) {
+                return None;
+            }
+            let last = size[axis] - 1;
+            let texel = t * last as f32;
+            cell[axis] = (texel as u32).min(last.saturating_sub(1));
+            fraction[axis] = if last == 0 {
+                0.0
+            } else {
+                texel - cell[axis] as f32
+            };
+        }
+
+        let mut sum = Vector3::new(0.0, 0.0, 0.0);
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut index = [0; 3];
+            for axis in 0..3 {
+                let up = corner >> axis & 1 == 1;
+                index[axis] = (cell[axis] + up as u32).min(size[axis] - 1);
+                weight *= if up {
+                    fraction[axis]
+                } else {
+                    1.0 - fraction[axis]
+                };
+            }
+            let vector = vectors[grid_index(*size, index)];
+            sum += Vector3::from(vector) * weight;
+        }
+        Some(sum.into())
+    }
+
+    /// Box around every sample, in the field's own units. None without samples.
+    pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        match self {
+            VectorField::Grid { size, min, max, .. } => {
+                (!size.contains(&0)).then_some((*min, *max))
+            }
+            VectorField::Points(samples) => samples.iter().fold(None, |bounds, sample| {
+                let position = sample.position;
+                Some(match bounds {
+                    None => (position, position),
+                    Some((min, max)) => (
+                        std::array::from_fn(|axis| f32::min(min[axis], position[axis])),
+                        std::array::from_fn(|axis| f32::max(max[axis], position[axis])),
+                    ),
+                })
+            }),
+        }
+    }
+
+    /// Length of the longest vector
+    pub fn max_magnitude(&self) -> f32 {
+        let longest = |vectors: &mut dyn Iterator<Item = [f32; 3]>| {
+            vectors.map(magnitude).fold(0.0, f32::max)
+        };
+        match self {
+            VectorField::Grid { vectors, .. } => longest(&mut vectors.iter().copied()),
+            VectorField::Points(samples) => {
+                longest(&mut samples.iter().map(|sample| sample.vector))
+            }
+        }
+    }
+
+    /// Every `stride`th sample, along each axis of a grid
+    pub fn samples(&self, stride: u32) -> Vec<VectorSample> {
+        let stride = stride.max(1);
+        match self {
+            VectorField::Grid {
+                size,
+                min,
+                max,
+                vectors,
+            } => {
+                let mut samples = Vec::new();
+                for z in (0..size[2]).step_by(stride as usize) {
+                    for y in (0..size[1]).step_by(stride as usize) {
+                        for x in (0..size[0]).step_by(stride as usize) {
+                            samples.push(VectorSample {
+                                position: grid_position(*size, *min, *max, [x, y, z]),
+                                vector: vectors[grid_index(*size, [x, y, z])],
+                            });
+                        }
+                    }
+                }
+                samples
+            }
+            VectorField::Points(samples) => {
+                samples.iter().step_by(stride as usize).copied().collect()
+            }
+        }
+    }
+
+    /// Roughly how far apart the samples are. For scattered points it's guessed from their count and bounds.
+    fn spacing(&self) -> f32 {
+        match self {
+            VectorField::Grid { size, min, max, .. } => (0..3)
+                .filter(|&axis| size[axis] > 1)
+                .map(|axis| (max[axis] - min[axis]) / (size[axis] - 1) as f32)
+                .fold(f32::INFINITY, f32::min),
+            VectorField::Points(samples) => {
+                let Some((min, max)) = self.bounds() else {
+                    return 0.0;
+                };
+                let extent = Vector3::from(max) - Vector3::from(min);
+                let diagonal = cgmath::InnerSpace::magnitude(extent);
+                diagonal / (samples.len() as f32).cbrt()
+            }
+        }
+    }
+}
+
+fn grid_index(size: [u32; 3], [x, y, z]: [u32; 3]) -> usize {
+    (z as usize * size[1] as usize + y as usize) * size[0] as usize + x as usize
+}
+
+fn grid_position(size: [u32; 3], min: [f32; 3], max: [f32; 3], index: [u32; 3]) -> [f32; 3] {
+    std::array::from_fn(|axis| {
+        let t = if size[axis] > 1 {
+            index[axis] as f32 / (size[axis] - 1) as f32
+        } else {
+            0.5
+        };
+        min[axis] + (max[axis] - min[axis]) * t
+    })
+}
+
+/// Box around a box in the field's units once it's transformed into the scene
+fn transform_bounds(
+    transform: Matrix4<f32>,
+    (local_min, local_max): ([f32; 3], [f32; 3]),
+) -> ([f32; 3], [f32; 3]) {
+    let mut min = Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+    let mut max = -min;
+    for corner in 0..8 {
+        let local = Vector4::new(
+            if corner & 1 == 0 {
+                local_min[0]
+            } else {
+                local_max[0]
+            },
+            if corner & 2 == 0 {
+                local_min[1]
+            } else {
+                local_max[1]
+            },
+            if corner & 4 == 0 {
+                local_min[2]
+            } else {
+                local_max[2]
+            },
+            1.0,
+        );
+        let world = (transform * local).truncate();
+        min = min.zip(world, f32::min);
+        max = max.zip(world, f32::max);
+    }
+    (min.into(), max.into())
+}
+
+fn magnitude(vector: [f32; 3]) -> f32 {
+    cgmath::InnerSpace::magnitude(Vector3::from(vector))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum GlyphShape {
+    /// A shaft with a cone on top
+    #[default]
+    Arrow,
+    /// Just a cone, less busy for dense fields
+    Cone,
+}
+
+/// Glyphs drawing a vector field, added with [crate::render_engine::RenderEngine::add_glyphs]
+#[derive(Debug, Clone)]
+pub struct Glyphs {
+    pub field: VectorField,
+    /// Where the field is placed in the scene
+    pub transform: Matrix4<f32>,
+    pub shape: GlyphShape,
+    /// Glyph length per unit of magnitude. [Glyphs::new] picks one making the longest glyph as long as the samples
+    /// are apart.
+    pub scale: f32,
+    /// Only every `stride`th sample gets a glyph, along each axis of a grid
+    pub stride: u32,
+    /// Magnitudes mapped to the ends of the colormap, from 0 to the longest vector when None
+    pub magnitude_range: Option<[f32; 2]>,
+    pub colormap: Colormap,
+}
+
+impl Glyphs {
+    pub fn new(field: VectorField) -> Self {
+        let longest = field.max_magnitude();
+        let spacing = field.spacing();
+        let scale = if longest > 0.0 && spacing.is_finite() && spacing > 0.0 {
+            spacing / longest
+        } else {
+            1.0
+        };
+        Glyphs {
+            field,
+            transform: Matrix4::identity(),
+            shape: GlyphShape::default(),
+            scale,
+            stride: 1,
+            magnitude_range: None,
+            colormap: Colormap::COOL_WARM,
+        }
+    }
+
+    pub fn with_shape(mut self, shape: GlyphShape) -> Self {
+        self.shape = shape;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn with_stride(mut self, stride: u32) -> Self {
+        self.stride = stride;
+        self
+    }
+
+    pub fn with_magnitude_range(mut self, low: f32, high: f32) -> Self {
+        self.magnitude_range = Some([low, high]);
+        self
+    }
+
+    pub fn with_colormap(mut self, colormap: Colormap) -> Self {
+        self.colormap = colormap;
+        self
+    }
+
+    /// Box around the field's samples in the scene, for framing them
+    pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        Some(transform_bounds(self.transform, self.field.bounds()?))
+    }
+
+    fn magnitude_range(&self) -> [f32; 2] {
+        self.magnitude_range
+            .unwrap_or_else(|| [0.0, self.field.max_magnitude()])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlyphsHandle(pub(crate) usize);
+
+/// The engine's glyph and streamline sets and the pass drawing them, which is created with the first set
+#[derive(Default)]
+pub(crate) struct VectorFields {
+    pass: LazyPass<VectorFieldPass>,
+    /// None where a set was removed
+    glyphs: Vec<Option<Glyphs>>,
+    /// None where a set was removed
+    streamlines: Vec<Option<Streamlines>>,
+}
+
+impl VectorFields {
+    pub fn add_glyphs(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        glyphs: Glyphs,
+    ) -> GlyphsHandle {
+        self.create_pass(device, format, global_bindings);
+        self.glyphs.push(Some(glyphs));
+        GlyphsHandle(self.glyphs.len() - 1)
+    }
+
+    pub fn glyphs(&self, handle: GlyphsHandle) -> Option<&Glyphs> {
+        self.glyphs.get(handle.0)?.as_ref()
+    }
+
+    /// The glyphs are uploaded again by the next prepare
+    pub fn glyphs_mut(&mut self, handle: GlyphsHandle) -> Option<&mut Glyphs> {
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_glyphs(handle.0);
+        }
+        self.glyphs.get_mut(handle.0)?.as_mut()
+    }
+
+    /// Frees the glyphs on the GPU
+    pub fn remove_glyphs(&mut self, handle: GlyphsHandle) -> Option<Glyphs> {
+        let glyphs = self.glyphs.get_mut(handle.0)?.take()?;
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_glyphs(handle.0);
+        }
+        Some(glyphs)
+    }
+
+    pub fn add_streamlines(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        streamlines: Streamlines,
+    ) -> StreamlinesHandle {
+        self.create_pass(device, format, global_bindings);
+        self.streamlines.push(Some(streamlines));
+        StreamlinesHandle(self.streamlines.len() - 1)
+    }
+
+    pub fn streamlines(&self, handle: StreamlinesHandle) -> Option<&Streamlines> {
+        self.streamlines.get(handle.0)?.as_ref()
+    }
+
+    /// The lines are traced again by the next prepare
+    pub fn streamlines_mut(&mut self, handle: StreamlinesHandle) -> Option<&mut Streamlines> {
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_streamlines(handle.0);
+        }
+        self.streamlines.get_mut(handle.0)?.as_mut()
+    }
+
+    /// Frees the lines' tubes on the GPU
+    pub fn remove_streamlines(&mut self, handle: StreamlinesHandle) -> Option<Streamlines> {
+        let streamlines = self.streamlines.get_mut(handle.0)?.take()?;
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_streamlines(handle.0);
+        }
+        Some(streamlines)
+    }
+
+    fn create_pass(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+    ) {
+        self.pass
+            .get_or_create(|| Some(VectorFieldPass::new(device, format, global_bindings)));
+    }
+
+    /// See [VectorFieldPass::prepare]
+    pub fn prepare(&mut self, device: &Device, queue: &Queue, colormaps: &mut ColormapTextures) {
+        if let Some(pass) = self.pass.get_mut() {
+            pass.prepare(device, queue, colormaps, &self.glyphs, &self.streamlines);
+        }
+    }
+
+    pub fn pass(&self) -> Option<&VectorFieldPass> {
+        self.pass.get()
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.pass.get().map_or(0, VectorFieldPass::size_in_bytes)
+    }
+
+    /// Creates the pass again on a new device, every set is uploaded again by the next prepare
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+    ) {
+        self.pass
+            .recreate(|| Some(VectorFieldPass::new(device, format, global_bindings)));
+    }
+}
@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+
+use wgpu::{BindGroup, Buffer, Device, Queue, RenderPass, RenderPipeline, TextureFormat};
+
+use super::{
+    streamlines::{Streamlines, TubeVertex},
+    GlyphShape, Glyphs, VectorSample,
+};
+use crate::{
+    colormap::{ColormapBinding, ColormapTextures},
+    global_bindings::GlobalBindings,
+    profiler::RenderStats,
+    texture,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        uniform_buffer::UniformBuffer,
+    },
+};
+
+/// Corners around the round shapes
+const SEGMENTS: u32 = 12;
+/// Outlines of the shapes from the bottom to the tip, as radius and height. They're spun around +Y and are one unit
+/// tall, the shader stretches them along the vectors.
+const ARROW_OUTLINE: &[[f32; 2]] = &[
+    [0.0, 0.0],
+    [0.035, 0.0],
+    [0.035, 0.7],
+    [0.1, 0.7],
+    [0.0, 1.0],
+];
+const CONE_OUTLINE: &[[f32; 2]] = &[[0.0, 0.0], [0.15, 0.0], [0.0, 1.0]];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ShapeVertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+}
+
+/// Per glyph or streamline set draw data, bound at group 1
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct VectorFieldUBOContent {
+    model: [[f32; 4]; 4],
+    magnitude_range: [f32; 2],
+    /// Glyph length per unit of magnitude, unused by tubes
+    scale: f32,
+    _padding: f32,
+}
+
+crate::assert_uniform_layout!(VectorFieldUBOContent {
+    model: ALIGN_VEC4,
+    magnitude_range: ALIGN_VEC2,
+    scale: ALIGN_SCALAR,
+});
+
+struct GpuGlyphs {
+    instances: Buffer,
+    count: u32,
+    shape: GlyphShape,
+    _ubo: UniformBuffer<VectorFieldUBOContent>,
+    bind_group: BindGroup,
+}
+
+struct GpuTubes {
+    vertices: Buffer,
+    indices: Buffer,
+    count: u32,
+    _ubo: UniformBuffer<VectorFieldUBOContent>,
+    bind_group: BindGroup,
+}
+
+/// The pipelines drawing glyphs and streamline tubes in the main pass and the sets uploaded for them, shared by every
+/// view
+pub(crate) struct VectorFieldPass {
+    glyph_pipeline: RenderPipeline,
+    tube_pipeline: RenderPipeline,
+    bind_group_layout: BindGroupLayoutWithDesc,
+    arrow: (Buffer, u32),
+    cone: (Buffer, u32),
+    /// By glyph set index, None for sets without a glyph
+    glyphs: HashMap<usize, Option<GpuGlyphs>>,
+    /// By streamline set index, None for sets without a line
+    tubes: HashMap<usize, Option<GpuTubes>>,
+}
+
+impl VectorFieldPass {
+    /// `format` has to be the engine's swapchain format, since everything is drawn in the main pass
+    pub fn new(device: &Device, format: TextureFormat, global_bindings: &GlobalBindings) -> Self {
+        let _span = tracing::debug_span!("create_vector_field_pipelines").entered();
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_vertex(binding_types::uniform())
+            .next_binding_fragment(binding_types::texture2D())
+            .next_binding_fragment(binding_types::sampler(wgpu::SamplerBindingType::Filtering))
+            .create(device, "Vector Field Bind Group");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Vector Field Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("../vector_field.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                global_bindings.bind_group_layouts(),
+                &bind_group_layout.layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let glyph_pipeline = create_pipeline(
+            device,
+            &pipeline_layout,
+            &shader,
+            format,
+            "vs_glyph",
+            &[
+                wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<ShapeVertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3],
+                },
+                wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<VectorSample>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![2 => Float32x3, 3 => Float32x3],
+                },
+            ],
+        );
+        let tube_pipeline = create_pipeline(
+            device,
+            &pipeline_layout,
+            &shader,
+            format,
+            "vs_tube",
+            &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<TubeVertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32],
+            }],
+        );
+
+        VectorFieldPass {
+            glyph_pipeline,
+            tube_pipeline,
+            bind_group_layout,
+            arrow: create_shape(device, ARROW_OUTLINE, "Arrow Glyph"),
+            cone: create_shape(device, CONE_OUTLINE, "Cone Glyph"),
+            glyphs: HashMap::new(),
+            tubes: HashMap::new(),
+        }
+    }
+
+    /// Uploads the glyph and streamline sets added or changed since the last call, tracing the streamlines
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        colormaps: &mut ColormapTextures,
+        glyphs: &[Option<Glyphs>],
+        streamlines: &[Option<Streamlines>],
+    ) {
+        for (index, glyphs) in glyphs.iter().enumerate() {
+            let Some(glyphs) = glyphs else {
+                continue;
+            };
+            self.glyphs.entry(index).or_insert_with(|| {
+                let colormap = colormaps.binding(device, queue, glyphs.colormap);
+                upload_glyphs(device, &self.bind_group_layout, colormap, glyphs)
+            });
+        }
+        for (index, streamlines) in streamlines.iter().enumerate() {
+            let Some(streamlines) = streamlines else {
+                continue;
+            };
+            self.tubes.entry(index).or_insert_with(|| {
+                let _span = tracing::debug_span!("trace_streamlines").entered();
+                let colormap = colormaps.binding(device, queue, streamlines.colormap);
+                upload_tubes(device, &self.bind_group_layout, colormap, streamlines)
+            });
+        }
+    }
+
+    /// Frees a set that was removed or changed, changed ones are uploaded again by the next
+    /// [VectorFieldPass::prepare]
+    pub fn remove_glyphs(&mut self, index: usize) {
+        self.glyphs.remove(&index);
+    }
+
+    /// Like [VectorFieldPass::remove_glyphs], changed lines are traced again
+    pub fn remove_streamlines(&mut self, index: usize) {
+        self.tubes.remove(&index);
+    }
+
+    /// Bytes of the uploaded glyphs and tubes
+    pub fn size_in_bytes(&self) -> u64 {
+        let glyphs: u64 = self
+            .glyphs
+            .values()
+            .flatten()
+            .map(|set| set.instances.size())
+            .sum();
+        let tubes: u64 = self
+            .tubes
+            .values()
+            .flatten()
+            .map(|set| set.vertices.size() + set.indices.size())
+            .sum();
+        glyphs + tubes
+    }
+
+    /// Draws every uploaded set, in the main pass
+    pub fn draw(&self, render_pass: &mut RenderPass, globals: &BindGroup, stats: &mut RenderStats) {
+        if self.glyphs.values().any(Option::is_some) {
+            render_pass.set_pipeline(&self.glyph_pipeline);
+            render_pass.set_bind_group(0, globals, &[]);
+            stats.pipeline_switches += 1;
+            stats.bind_group_switches += 1;
+            for set in self.glyphs.values().flatten() {
+                let (shape, vertices) = match set.shape {
+                    GlyphShape::Arrow => &self.arrow,
+                    GlyphShape::Cone => &self.cone,
+                };
+                render_pass.set_bind_group(1, &set.bind_group, &[]);
+                render_pass.set_vertex_buffer(0, shape.slice(..));
+                render_pass.set_vertex_buffer(1, set.instances.slice(..));
+                render_pass.draw(0..*vertices, 0..set.count);
+                stats.bind_group_switches += 1;
+                stats.draw(*vertices, set.count);
+            }
+        }
+        if self.tubes.values().any(Option::is_some) {
+            render_pass.set_pipeline(&self.tube_pipeline);
+            render_pass.set_bind_group(0, globals, &[]);
+            stats.pipeline_switches += 1;
+            stats.bind_group_switches += 1;
+            for set in self.tubes.values().flatten() {
+                render_pass.set_bind_group(1, &set.bind_group, &[]);
+                render_pass.set_vertex_buffer(0, set.vertices.slice(..));
+                render_pass.set_index_buffer(set.indices.slice(..), wgpu::IndexFormat::Uint32);
+                render_pass.draw_indexed(0..set.count, 0, 0..1);
+                stats.bind_group_switches += 1;
+                stats.draw(set.count, 1);
+            }
+        }
+    }
+}
+
+fn create_pipeline(
+    device: &Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: TextureFormat,
+    vertex_entry_point: &str,
+    buffers: &[wgpu::VertexBufferLayout],
+) -> RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(vertex_entry_point),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some(vertex_entry_point),
+            buffers,
+            compilation_options: Default::default(),
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // The tubes are open at their ends
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+            unclipped_depth: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_main"),
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: texture::Texture::ID_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
+            compilation_options: Default::default(),
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
+
+fn create_bind_group(
+    device: &Device,
+    bind_group_layout: &BindGroupLayoutWithDesc,
+    (colormap, sampler): ColormapBinding,
+    content: &VectorFieldUBOContent,
+) -> (UniformBuffer<VectorFieldUBOContent>, BindGroup) {
+    let ubo = UniformBuffer::new_with_data(device, content);
+    let bind_group = BindGroupBuilder::new(bind_group_layout)
+        .resource(ubo.binding_resource())
+        .texture(colormap)
+        .sampler(sampler)
+        .create(device, "Vector Field Bind Group");
+    (ubo, bind_group)
+}
+
+fn ubo_content(
+    transform: cgmath::Matrix4<f32>,
+    magnitude_range: [f32; 2],
+    scale: f32,
+) -> VectorFieldUBOContent {
+    VectorFieldUBOContent {
+        model: transform.into(),
+        magnitude_range,
+        scale,
+        _padding: 0.0,
+    }
+}
+
+fn upload_tubes(
+    device: &Device,
+    bind_group_layout: &BindGroupLayoutWithDesc,
+    colormap: ColormapBinding,
+    streamlines: &Streamlines,
+) -> Option<GpuTubes> {
+    let (vertices, indices) = streamlines.tubes();
+    if indices.is_empty() {
+        return None;
+    }
+    let create_buffer = |label, contents, usage| {
+        wgpu::util::DeviceExt::create_buffer_init(
+            device,
+            &wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents,
+                usage,
+            },
+        )
+    };
+    let content = ubo_content(streamlines.transform, streamlines.magnitude_range(), 0.0);
+    let (ubo, bind_group) = create_bind_group(device, bind_group_layout, colormap, &content);
+    Some(GpuTubes {
+        vertices: create_buffer(
+            "Streamline Tube Vertices",
+            bytemuck::cast_slice(&vertices),
+            wgpu::BufferUsages::VERTEX,
+        ),
+        indices: create_buffer(
+            "Streamline Tube Indices",
+            bytemuck::cast_slice(&indices),
+            wgpu::BufferUsages::INDEX,
+        ),
+        count: indices.len() as u32,
+        _ubo: ubo,
+        bind_group,
+    })
+}
+
+fn upload_glyphs(
+    device: &Device,
+    bind_group_layout: &BindGroupLayoutWithDesc,
+    colormap: ColormapBinding,
+    glyphs: &Glyphs,
+) -> Option<GpuGlyphs> {
+    // Zero vectors have no direction to point in and no length to draw
+    let samples: Vec<VectorSample> = glyphs
+        .field
+        .samples(glyphs.stride)
+        .into_iter()
+        .filter(|sample| super::magnitude(sample.vector) > 0.0)
+        .collect();
+    if samples.is_empty() {
+        return None;
+    }
+    let instances = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some("Glyph Instances"),
+            contents: bytemuck::cast_slice(&samples),
+            usage: wgpu::BufferUsages::VERTEX,
+        },
+    );
+    let content = ubo_content(glyphs.transform, glyphs.magnitude_range(), glyphs.scale);
+    let (ubo, bind_group) = create_bind_group(device, bind_group_layout, colormap, &content);
+    Some(GpuGlyphs {
+        instances,
+        count: samples.len() as u32,
+        shape: glyphs.shape,
+        _ubo: ubo,
+        bind_group,
+    })
+}
+
+/// Spins an outline around +Y into flat shaded triangles, facing outwards counter clockwise
+fn create_shape(device: &Device, outline: &[[f32; 2]], label: &str) -> (Buffer, u32) {
+    let mut vertices = Vec::new();
+    for segment in outline.windows(2) {
+        let ([r0, y0], [r1, y1]) = (segment[0], segment[1]);
+        // Perpendicular to the segment, pointing away from the axis
+        let (normal_r, normal_y) = (y1 - y0, r0 - r1);
+        let length = normal_r.hypot(normal_y);
+        let (normal_r, normal_y) = (normal_r / length, normal_y / length);
+        let ring = |corner: u32, r: f32, y: f32| {
+            let angle = corner as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+            let (sin, cos) = angle.sin_cos();
+            ShapeVertex {
+                position: [r * cos, y, r * sin],
+                normal: [normal_r * cos, normal_y, normal_r * sin],
+            }
+        };
+        for corner in 0..SEGMENTS {
+            let (a0, a1) = (ring(corner, r0, y0), ring(corner + 1, r0, y0));
+            let (b0, b1) = (ring(corner, r1, y1), ring(corner + 1, r1, y1));
+            vertices.extend([a0, b0, a1]);
+            vertices.extend([a1, b0, b1]);
+        }
+    }
+    let buffer = wgpu::util::DeviceExt::create_buffer_init(
+        device,
+        &wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: bytemuck::cast_slice(&vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        },
+    );
+    (buffer, vertices.len() as u32)
+}
@@ -0,0 +1,322 @@
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3};
+
+use super::{VectorField, VectorSample};
+use crate::colormap::Colormap;
+
+/// Corners around the tubes
+const TUBE_SIDES: u32 = 8;
+
+/// Where streamlines start, in the field's own units
+#[derive(Debug, Clone, PartialEq)]
+pub enum Seeds {
+    /// `count` × `count` seeds spread over a square `size` wide around `center`, facing along `normal`
+    Plane {
+        center: [f32; 3],
+        normal: [f32; 3],
+        size: f32,
+        count: u32,
+    },
+    /// `count` seeds spread evenly over a sphere
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+        count: u32,
+    },
+    /// Seeds at exactly these points. A point picked in a view is found by unprojecting the depth from
+    /// [crate::render_engine::RenderEngine::read_depth_at], the way [crate::measure] places its points, and moved into
+    /// the field's units with the inverse of [Streamlines::transform].
+    Points(Vec<[f32; 3]>),
+}
+
+impl Seeds {
+    pub fn positions(&self) -> Vec<[f32; 3]> {
+        match self {
+            &Seeds::Plane {
+                center,
+                normal,
+                size,
+                count,
+            } => {
+                let normal = Vector3::from(normal).normalize();
+                // Any two axes across the normal, with another helper for normals along X
+                let helper = if normal.x.abs() < 0.9 {
+                    Vector3::unit_x()
+                } else {
+                    Vector3::unit_y()
+                };
+                let u = normal.cross(helper).normalize();
+                let v = normal.cross(u);
+                let offset = |index: u32| {
+                    if count > 1 {
+                        (index as f32 / (count - 1) as f32 - 0.5) * size
+                    } else {
+                        0.0
+                    }
+                };
+                let mut positions = Vec::with_capacity(count as usize * count as usize);
+                for j in 0..count {
+                    for i in 0..count {
+                        let position = Vector3::from(center) + u * offset(i) + v * offset(j);
+                        positions.push(position.into());
+                    }
+                }
+                positions
+            }
+            &Seeds::Sphere {
+                center,
+                radius,
+                count,
+            } => {
+                // On a Fibonacci spiral from pole to pole, every seed covers about as much of the sphere
+                let golden_angle = std::f32::consts::PI * (3.0 - 5f32.sqrt());
+                (0..count)
+                    .map(|index| {
+                        let y = 1.0 - 2.0 * (index as f32 + 0.5) / count as f32;
+                        let ring = (1.0 - y * y).sqrt();
+                        let (sin, cos) = (index as f32 * golden_angle).sin_cos();
+                        let direction = Vector3::new(cos * ring, y, sin * ring);
+                        (Vector3::from(center) + direction * radius).into()
+                    })
+                    .collect()
+            }
+            Seeds::Points(points) => points.clone(),
+        }
+    }
+}
+
+/// Which way streamlines are traced from their seeds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TraceDirection {
+    /// Along the vectors
+    Forward,
+    /// Against them, to where what passes the seeds comes from
+    Backward,
+    #[default]
+    Both,
+}
+
+/// Lines following a vector field from seeds, drawn as tubes. Added with
+/// [crate::render_engine::RenderEngine::add_streamlines].
+///
+/// Lines are traced on the CPU with fourth order Runge-Kutta steps of the same length, until they leave the field,
+/// reach a point where it's zero or take [Streamlines::max_steps]. Only grids can be traced, see
+/// [VectorField::sample].
+#[derive(Debug, Clone)]
+pub struct Streamlines {
+    pub field: VectorField,
+    /// Where the field is placed in the scene
+    pub transform: Matrix4<f32>,
+    pub seeds: Seeds,
+    pub direction: TraceDirection,
+    /// Length of each step, in the field's units. [Streamlines::new] picks a quarter of the grid's spacing.
+    pub step: f32,
+    /// Steps each way from a seed, at most
+    pub max_steps: u32,
+    /// Of the tubes, in the field's units
+    pub radius: f32,
+    /// Magnitudes mapped to the ends of the colormap, from 0 to the longest vector when None
+    pub magnitude_range: Option<[f32; 2]>,
+    pub colormap: Colormap,
+}
+
+impl Streamlines {
+    pub fn new(field: VectorField, seeds: Seeds) -> Self {
+        let spacing = field.spacing();
+        let spacing = if spacing.is_finite() && spacing > 0.0 {
+            spacing
+        } else {
+            1.0
+        };
+        Streamlines {
+            field,
+            transform: Matrix4::identity(),
+            seeds,
+            direction: TraceDirection::default(),
+            step: spacing * 0.25,
+            max_steps: 2000,
+            radius: spacing * 0.05,
+            magnitude_range: None,
+            colormap: Colormap::COOL_WARM,
+        }
+    }
+
+    pub fn with_direction(mut self, direction: TraceDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn with_step(mut self, step: f32) -> Self {
+        self.step = step;
+        self
+    }
+
+    pub fn with_max_steps(mut self, max_steps: u32) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn with_radius(mut self, radius: f32) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    pub fn with_magnitude_range(mut self, low: f32, high: f32) -> Self {
+        self.magnitude_range = Some([low, high]);
+        self
+    }
+
+    pub fn with_colormap(mut self, colormap: Colormap) -> Self {
+        self.colormap = colormap;
+        self
+    }
+
+    /// Box around the field in the scene, for framing it
+    pub fn bounds(&self) -> Option<([f32; 3], [f32; 3])> {
+        Some(super::transform_bounds(
+            self.transform,
+            self.field.bounds()?,
+        ))
+    }
+
+    /// The lines from every seed, in the field's units and with the field's vector at each point. Seeds where the
+    /// line doesn't get anywhere have none.
+    pub fn trace(&self) -> Vec<Vec<VectorSample>> {
+        self.seeds
+            .positions()
+            .into_iter()
+            .filter_map(|seed| {
+                let seed = Vector3::from(seed);
+                let mut line = match self.direction {
+                    TraceDirection::Forward => self.trace_from(seed, 1.0),
+                    TraceDirection::Backward => {
+                        let mut line = self.trace_from(seed, -1.0);
+                        line.reverse();
+                        line
+                    }
+                    TraceDirection::Both => {
+                        let mut line = self.trace_from(seed, -1.0);
+                        line.reverse();
+                        // Both halves start at the seed
+                        line.pop();
+                        line.extend(self.trace_from(seed, 1.0));
+                        line
+                    }
+                };
+                line.dedup_by(|a, b| a.position == b.position);
+                (line.len() > 1).then_some(line)
+            })
+            .collect()
+    }
+
+    /// Points from `seed` on, along the vectors or against them with a `sign` of -1
+    fn trace_from(&self, seed: Vector3<f32>, sign: f32) -> Vec<VectorSample> {
+        // Steps follow the direction only, so they're all as long whatever the magnitude
+        let direction = |position: Vector3<f32>| {
+            let vector = Vector3::from(self.field.sample(position.into())?);
+            (vector.magnitude2() > 0.0).then(|| vector.normalize() * sign)
+        };
+        let step = self.step;
+        let mut line = Vec::new();
+        let mut position = seed;
+        for _ in 0..=self.max_steps {
+            let Some(vector) = self.field.sample(position.into()) else {
+                break;
+            };
+            line.push(VectorSample {
+                position: position.into(),
+                vector,
+            });
+            let next = (|| {
+                let k1 = direction(position)?;
+                let k2 = direction(position + k1 * step * 0.5)?;
+                let k3 = direction(position + k2 * step * 0.5)?;
+                let k4 = direction(position + k3 * step)?;
+                Some(position + (k1 + k2 * 2.0 + k3 * 2.0 + k4) * step / 6.0)
+            })();
+            let Some(next) = next else {
+                break;
+            };
+            position = next;
+        }
+        line
+    }
+
+    pub(super) fn magnitude_range(&self) -> [f32; 2] {
+        self.magnitude_range
+            .unwrap_or_else(|| [0.0, self.field.max_magnitude()])
+    }
+
+    /// Tubes around the traced lines, in the field's units
+    pub(super) fn tubes(&self) -> (Vec<TubeVertex>, Vec<u32>) {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for line in self.trace() {
+            let first = vertices.len() as u32;
+            extrude(&line, self.radius, &mut vertices);
+            for ring in 0..line.len() as u32 - 1 {
+                for side in 0..TUBE_SIDES {
+                    let a0 = first + ring * (TUBE_SIDES + 1) + side;
+                    let (a1, b0, b1) = (a0 + 1, a0 + TUBE_SIDES + 1, a0 + TUBE_SIDES + 2);
+                    indices.extend([a0, b0, a1, a1, b0, b1]);
+                }
+            }
+        }
+        (vertices, indices)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(super) struct TubeVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    /// Of the field where the line passes, for coloring
+    pub magnitude: f32,
+}
+
+/// A ring of [TUBE_SIDES] + 1 vertices around every point of the line, the last one closing the ring. The rings are
+/// turned along with the line, so the tube doesn't twist.
+fn extrude(line: &[VectorSample], radius: f32, vertices: &mut Vec<TubeVertex>) {
+    let position = |index: usize| Vector3::from(line[index].position);
+    let mut normal: Option<Vector3<f32>> = None;
+    for index in 0..line.len() {
+        let before = position(index.saturating_sub(1));
+        let after = position((index + 1).min(line.len() - 1));
+        let tangent = (after - before).normalize();
+        let across = match normal {
+            // What's left of the last ring's normal across the new tangent
+            Some(normal) => {
+                let across = normal - tangent * normal.dot(tangent);
+                if across.magnitude2() > 1e-12 {
+                    across.normalize()
+                } else {
+                    normal
+                }
+            }
+            None => {
+                let helper = if tangent.x.abs() < 0.9 {
+                    Vector3::unit_x()
+                } else {
+                    Vector3::unit_y()
+                };
+                tangent.cross(helper).normalize()
+            }
+        };
+        normal = Some(across);
+        let bitangent = across.cross(tangent);
+        let magnitude = super::magnitude(line[index].vector);
+        for side in 0..=TUBE_SIDES {
+            let angle = side as f32 / TUBE_SIDES as f32 * std::f32::consts::TAU;
+            let (sin, cos) = angle.sin_cos();
+            let outwards = across * cos + bitangent * sin;
+            vertices.push(TubeVertex {
+                position: (position(index) + outwards * radius).into(),
+                normal: outwards.into(),
+                magnitude,
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamlinesHandle(pub(crate) usize);
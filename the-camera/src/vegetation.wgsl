@@ -0,0 +1,156 @@
+// See clipping.rs, the same as in shader.wgsl
+struct Clipping {
+    planes: array<vec4<f32>, 4>,
+    cap_color: vec4<f32>,
+    count: u32,
+    caps: u32,
+}
+
+struct Audio {
+    bands: array<vec4<f32>, 4>,
+    level: f32,
+}
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    clipping: Clipping,
+    audio: Audio,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+// One vegetation, see vegetation/pass.rs
+struct Vegetation {
+    base_color: vec4<f32>,
+    tip_color: vec4<f32>,
+    wind: vec4<f32>,
+    blade_size: vec2<f32>,
+    time: f32,
+    count: u32,
+    lod_distance: f32,
+    max_distance: f32,
+    crossfade: f32,
+}
+@group(1) @binding(0)
+var<uniform> vegetation: Vegetation;
+
+struct Blade {
+    position: vec3<f32>,
+    facing: f32,
+    scale: f32,
+}
+@group(1) @binding(1)
+var<storage, read> blades: array<Blade>;
+// The near or the far list of culled blades, one instance each
+@group(1) @binding(2)
+var<storage, read> culled: array<u32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    // From the root to the tip
+    @location(2) height: f32,
+    // How much of the blade is left at its distance
+    @location(3) @interpolate(flat) fade: f32,
+    @location(4) @interpolate(flat) far: u32,
+};
+
+// See vegetation/pass.rs
+const NEAR_SEGMENTS = 4u;
+const FAR_SEGMENTS = 1u;
+
+// Two triangles for every segment, the last one degenerates into a triangle at the tip
+const CORNERS = array<vec2<u32>, 6>(
+    vec2<u32>(0u, 0u),
+    vec2<u32>(1u, 0u),
+    vec2<u32>(1u, 1u),
+    vec2<u32>(0u, 0u),
+    vec2<u32>(1u, 1u),
+    vec2<u32>(0u, 1u),
+);
+
+fn blade_vertex(index: u32, instance: u32, segments: u32, far: bool) -> VertexOutput {
+    let blade = blades[culled[instance]];
+    let corner = CORNERS[index % 6u];
+    let t = f32(index / 6u + corner.y) / f32(segments);
+    let height = vegetation.blade_size.x * blade.scale;
+    let width = vegetation.blade_size.y * blade.scale * (1.0 - t);
+
+    // Gusts run across the field along the wind, with a flutter of every blade on its own on top
+    let wind = vegetation.wind.xy;
+    let phase = 0.5 * (dot(blade.position.xz, wind) - vegetation.time * vegetation.wind.w);
+    let gust = 0.5 + 0.5 * sin(phase);
+    let flutter = 0.15 * sin(vegetation.time * 7.0 + blade.facing * 13.0);
+    let bend = vegetation.wind.z * (0.3 + 0.7 * gust + flutter);
+    // Bent over from the root, the tips sink about as far as they lean so the blades keep their length
+    let lean = bend * t * t * height;
+    let across = vec3<f32>(cos(blade.facing), 0.0, sin(blade.facing));
+    let world_position = blade.position
+        + across * (f32(corner.x) - 0.5) * width
+        + vec3<f32>(wind.x * lean, t * height - 0.5 * bend * lean, wind.y * lean);
+
+    let to_eye = camera.view_pos.xyz - blade.position;
+    let distance = length(to_eye);
+    let half_band = 0.5 * vegetation.crossfade;
+    let lod = smoothstep(vegetation.lod_distance - half_band, vegetation.lod_distance + half_band, distance);
+    var out: VertexOutput;
+    if far {
+        let fade_out = 0.1 * vegetation.max_distance;
+        out.fade = lod * (1.0 - smoothstep(vegetation.max_distance - fade_out, vegetation.max_distance, distance));
+    } else {
+        out.fade = 1.0 - lod;
+    }
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
+    // Facing a little upwards, so they're lit like the ground they cover
+    out.normal = vec3<f32>(-across.z, 0.5, across.x);
+    out.height = t;
+    out.far = u32(far);
+    return out;
+}
+
+@vertex
+fn vs_near(@builtin(vertex_index) index: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    return blade_vertex(index, instance, NEAR_SEGMENTS, false);
+}
+
+@vertex
+fn vs_far(@builtin(vertex_index) index: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    return blade_vertex(index, instance, FAR_SEGMENTS, true);
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    // Vegetation can't be picked
+    @location(1) id: u32,
+};
+
+const BAYER = array<u32, 16>(0u, 8u, 2u, 10u, 12u, 4u, 14u, 6u, 3u, 11u, 1u, 9u, 15u, 7u, 13u, 5u);
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    for (var index = 0u; index < camera.clipping.count; index++) {
+        let plane = camera.clipping.planes[index];
+        if dot(plane.xyz, in.world_position) < plane.w {
+            discard;
+        }
+    }
+
+    // The near blades keep the pixels under their fade, the far ones the complementary pixels over it
+    let pixel = vec2<u32>(in.clip_position.xy) % 4u;
+    let threshold = (f32(BAYER[pixel.y * 4u + pixel.x]) + 0.5) / 16.0;
+    if in.far == 0u && threshold >= in.fade || in.far != 0u && 1.0 - threshold > in.fade {
+        discard;
+    }
+
+    // Lit from the camera on both sides
+    let normal = normalize(in.normal);
+    let to_eye = normalize(camera.view_pos.xyz - in.world_position);
+    let albedo = mix(vegetation.base_color.rgb, vegetation.tip_color.rgb, in.height);
+    var out: FragmentOutput;
+    out.color = vec4<f32>(albedo * (0.35 + 0.65 * abs(dot(normal, to_eye))), 1.0);
+    out.id = 0u;
+    return out;
+}
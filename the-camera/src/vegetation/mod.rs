@@ -0,0 +1,314 @@
+//! Grass and other small plants scattered by the hundred thousand over a field or a terrain.
+//!
+//! [Vegetation::scatter] spreads blades over a rectangle along a low discrepancy sequence, fewer where its
+//! [DensityMap] is lower, and puts each on the ground the caller gives. The blades are uploaded to a storage buffer
+//! once. Before every view's main pass a compute shader culls them against the view's frustum and
+//! [Vegetation::max_distance] and sorts those left into two lists: blades of a few segments up to
+//! [Vegetation::lod_distance] and single triangles beyond it. Each list is drawn with one indirect draw, instanced
+//! straight from the list.
+//!
+//! Blades around the LOD distance are in both lists and crossfade with complementary dither patterns, the far ones
+//! fade out the same way towards the maximum distance, so nothing pops. The wind bends every blade in the vertex
+//! shader, in gusts running across the field along [Vegetation::wind_direction].
+//!
+//! Devices without compute shaders, indirect draws or storage buffers in vertex shaders, e.g. WebGL2, can't cull
+//! the blades and don't draw them.
+
+mod pass;
+
+use std::{f32::consts::TAU, time::Duration};
+
+use wgpu::{BindGroup, CommandBuffer, Device, Queue, TextureFormat};
+
+pub(crate) use self::pass::{VegetationPass, VegetationView};
+use crate::{
+    camera::camera::CameraUniform, depth_pyramid::DepthPyramidPass,
+    global_bindings::GlobalBindings, lazy_pass::LazyPass, render_engine_builder::DeviceReport,
+};
+
+/// One blade as it's stored on the GPU
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Blade {
+    /// Of its root
+    pub position: [f32; 3],
+    /// Turn about the Y axis in radians, the blade is flat along X without it
+    pub facing: f32,
+    /// Of [Vegetation::blade_size]
+    pub scale: f32,
+    _padding: [f32; 3],
+}
+
+impl Blade {
+    pub fn new(position: [f32; 3], facing: f32, scale: f32) -> Self {
+        Blade {
+            position,
+            facing,
+            scale,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+/// How densely [Vegetation::scatter] places blades, from 0 for none to 1 for all of them
+#[derive(Debug, Clone, PartialEq)]
+pub struct DensityMap {
+    /// Values along X and Z, spread over the whole rectangle
+    pub size: [u32; 2],
+    /// Row by row along X
+    pub values: Vec<f32>,
+}
+
+impl DensityMap {
+    pub fn new(size: [u32; 2], values: Vec<f32>) -> Self {
+        DensityMap { size, values }
+    }
+
+    /// Interpolated linearly between the values, from 0 to 1 across the rectangle along both axes
+    pub fn sample(&self, u: f32, v: f32) -> f32 {
+        let [width, depth] = self.size.map(|size| size as usize);
+        if width == 0 || depth == 0 || self.values.len() < width * depth {
+            return 0.0;
+        }
+        // The two values around a coordinate and how far it is between them
+        let around = |coordinate: f32, values: usize| {
+            let scaled = coordinate.clamp(0.0, 1.0) * (values - 1) as f32;
+            let first = (scaled as usize).min(values.saturating_sub(2));
+            (first, (first + 1).min(values - 1), scaled - first as f32)
+        };
+        let (x0, x1, tx) = around(u, width);
+        let (z0, z1, tz) = around(v, depth);
+        let row =
+            |z: usize| self.values[z * width + x0] * (1.0 - tx) + self.values[z * width + x1] * tx;
+        row(z0) * (1.0 - tz) + row(z1) * tz
+    }
+}
+
+/// Blades culled on the GPU and drawn into every window and render target, added with
+/// [crate::render_engine::RenderEngine::add_vegetation]
+#[derive(Debug, Clone)]
+pub struct Vegetation {
+    blades: Vec<Blade>,
+    /// Counts changes of the blades, which are uploaded again when it moved on
+    revision: u64,
+    /// Height and width at the root of a blade of scale 1, in world units
+    pub blade_size: [f32; 2],
+    /// At the roots
+    pub base_color: [f32; 3],
+    pub tip_color: [f32; 3],
+    /// Where the wind blows to along the X and Z axes, doesn't have to be normalized
+    pub wind_direction: [f32; 2],
+    /// How far the gusts bend the tips over, as a fraction of the blades' heights
+    pub wind_strength: f32,
+    /// Of the gusts running across the field, in world units per second
+    pub wind_speed: f32,
+    /// Blades further from the camera are drawn as single triangles
+    pub lod_distance: f32,
+    /// Blades further from the camera aren't drawn
+    pub max_distance: f32,
+    /// Stops the wind, the blades stay bent where they are
+    pub paused: bool,
+    pub visible: bool,
+}
+
+impl Vegetation {
+    pub fn new(blades: Vec<Blade>) -> Self {
+        Vegetation {
+            blades,
+            revision: 0,
+            blade_size: [0.5, 0.04],
+            base_color: [0.12, 0.25, 0.05],
+            tip_color: [0.55, 0.7, 0.25],
+            wind_direction: [1.0, 0.3],
+            wind_strength: 0.35,
+            wind_speed: 2.0,
+            lod_distance: 15.0,
+            max_distance: 60.0,
+            paused: false,
+            visible: true,
+        }
+    }
+
+    /// About `density` blades per square unit over the rectangle `size` wide around `center`, thinned out by
+    /// `density_map` where there is one. `ground` is the world height of the roots at every world X and Z, e.g.
+    /// `|x, z| terrain.center[1] + terrain.height(x, z)`. Spread along low discrepancy sequences, so the same field
+    /// grows every time.
+    pub fn scatter(
+        center: [f32; 2],
+        size: [f32; 2],
+        density: f32,
+        density_map: Option<&DensityMap>,
+        ground: impl Fn(f32, f32) -> f32,
+    ) -> Self {
+        let count = (size[0] * size[1] * density).max(0.0) as usize;
+        // Roberts' R3 sequence, the fractions of multiples of the inverse powers of the root of x^4 = x + 1. The
+        // third coordinate decides whether the density map keeps a blade.
+        let phi = 1.220_744_084_605_759_5_f64;
+        let steps = [1.0 / phi, 1.0 / (phi * phi), 1.0 / (phi * phi * phi)];
+        let golden_ratio = (1.0 + 5f64.sqrt()) / 2.0;
+        let spacing = density.max(1e-6).sqrt().recip();
+        let blades = (0..count)
+            .filter_map(|index| {
+                // In f64, the fractions of large multiples lose their digits in f32
+                let [u, v, keep] = steps.map(|step| (0.5 + step * index as f64).fract() as f32);
+                if density_map.is_some_and(|map| keep >= map.sample(u, v)) {
+                    return None;
+                }
+                // Jittered by about the spacing of the blades, which would line up in rows otherwise
+                let facing = (index as f64 * golden_ratio).fract() as f32;
+                let scale = (index as f64 * golden_ratio * golden_ratio).fract() as f32;
+                let x = center[0] + (u - 0.5) * size[0] + (facing - 0.5) * spacing;
+                let z = center[1] + (v - 0.5) * size[1] + (scale - 0.5) * spacing;
+                let (facing, scale) = (facing * TAU, 0.6 + 0.8 * scale);
+                Some(Blade::new([x, ground(x, z), z], facing, scale))
+            })
+            .collect();
+        Vegetation::new(blades)
+    }
+
+    pub fn with_blade_size(mut self, height: f32, width: f32) -> Self {
+        self.blade_size = [height, width];
+        self
+    }
+
+    pub fn with_colors(mut self, base_color: [f32; 3], tip_color: [f32; 3]) -> Self {
+        self.base_color = base_color;
+        self.tip_color = tip_color;
+        self
+    }
+
+    pub fn with_wind(mut self, direction: [f32; 2], strength: f32, speed: f32) -> Self {
+        self.wind_direction = direction;
+        self.wind_strength = strength;
+        self.wind_speed = speed;
+        self
+    }
+
+    pub fn with_distances(mut self, lod_distance: f32, max_distance: f32) -> Self {
+        self.lod_distance = lod_distance;
+        self.max_distance = max_distance;
+        self
+    }
+
+    pub fn blades(&self) -> &[Blade] {
+        &self.blades
+    }
+
+    /// Replaces the blades, they're uploaded before the next frame
+    pub fn set_blades(&mut self, blades: Vec<Blade>) {
+        self.blades = blades;
+        self.revision += 1;
+    }
+}
+
+/// Refers to vegetation added with [crate::render_engine::RenderEngine::add_vegetation]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VegetationHandle(pub(crate) usize);
+
+/// The engine's vegetation and the pass culling and drawing it, which is created with the first vegetation
+#[derive(Default)]
+pub(crate) struct Vegetations {
+    /// Unsupported where the device can't cull vegetation or build depth pyramids
+    pass: LazyPass<VegetationPass>,
+    /// None where vegetation was removed
+    vegetations: Vec<Option<Vegetation>>,
+}
+
+impl Vegetations {
+    /// `depth_pyramid_pass` is None where the device can't build depth pyramids, the vegetation isn't drawn then
+    pub fn add(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        depth_pyramid_pass: Option<&DepthPyramidPass>,
+        device_report: &DeviceReport,
+        vegetation: Vegetation,
+    ) -> VegetationHandle {
+        let create = || {
+            VegetationPass::new(
+                device,
+                format,
+                global_bindings,
+                depth_pyramid_pass?,
+                device_report,
+            )
+        };
+        if self.pass.get_or_create(create).is_none() {
+            tracing::warn!("Vegetation needs compute shaders, indirect draws and vertex storage, it isn't drawn");
+        }
+        self.vegetations.push(Some(vegetation));
+        VegetationHandle(self.vegetations.len() - 1)
+    }
+
+    pub fn get(&self, handle: VegetationHandle) -> Option<&Vegetation> {
+        self.vegetations.get(handle.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: VegetationHandle) -> Option<&mut Vegetation> {
+        self.vegetations.get_mut(handle.0)?.as_mut()
+    }
+
+    /// Frees the vegetation's blades on the GPU
+    pub fn remove(&mut self, handle: VegetationHandle) -> Option<Vegetation> {
+        let vegetation = self.vegetations.get_mut(handle.0)?.take()?;
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_vegetation(handle.0);
+        }
+        Some(vegetation)
+    }
+
+    /// See [VegetationPass::prepare], true while any vegetation moves in the wind
+    pub fn prepare(&mut self, device: &Device, queue: &Queue, delta_time: Duration) -> bool {
+        let Some(pass) = self.pass.get_mut() else {
+            return false;
+        };
+        pass.prepare(device, queue, &self.vegetations, delta_time);
+        pass.is_moving(&self.vegetations)
+    }
+
+    /// See [VegetationPass::cull]
+    pub fn cull(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        view: &mut Option<VegetationView>,
+        camera: &CameraUniform,
+        depth_pyramid: &BindGroup,
+    ) -> Option<CommandBuffer> {
+        self.pass
+            .get()?
+            .cull(device, queue, view, camera, depth_pyramid)
+    }
+
+    /// Draws the culled blades in the main pass, None before the first vegetation and where the device can't cull it
+    pub fn pass(&self) -> Option<&VegetationPass> {
+        self.pass.get()
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.pass.get().map_or(0, VegetationPass::size_in_bytes)
+    }
+
+    /// Creates the pass again on a new device, the blades are uploaded again by the next prepare and the wind starts
+    /// over
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        depth_pyramid_pass: Option<&DepthPyramidPass>,
+        device_report: &DeviceReport,
+    ) {
+        self.pass.recreate(|| {
+            VegetationPass::new(
+                device,
+                format,
+                global_bindings,
+                depth_pyramid_pass?,
+                device_report,
+            )
+        });
+    }
+}
@@ -0,0 +1,527 @@
+use std::{collections::HashMap, num::NonZeroU64, time::Duration};
+
+use wgpu::{
+    util::DrawIndirectArgs, BindGroup, Buffer, CommandBuffer, Device, Queue, RenderPass,
+    RenderPipeline, TextureFormat,
+};
+
+use super::{Blade, Vegetation};
+use crate::{
+    camera::camera::CameraUniform,
+    depth_pyramid::DepthPyramidPass,
+    global_bindings::GlobalBindings,
+    profiler::RenderStats,
+    render_engine_builder::DeviceReport,
+    texture,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        compute::{ComputeKernel, ComputeKernelBuilder, ComputePassBuilder},
+        uniform_buffer::UniformBuffer,
+    },
+};
+
+/// Has to match `NEAR_SEGMENTS` in vegetation.wgsl, two triangles for every segment of a blade
+const NEAR_VERTICES: u32 = 4 * 6;
+/// Has to match `FAR_SEGMENTS` in vegetation.wgsl
+const FAR_VERTICES: u32 = 6;
+
+/// Per vegetation parameters of the culling and the draws
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct VegetationUBOContent {
+    base_color: [f32; 4],
+    tip_color: [f32; 4],
+    /// Normalized direction along X and Z, strength and speed
+    wind: [f32; 4],
+    blade_size: [f32; 2],
+    /// Seconds the wind has been blowing for
+    time: f32,
+    count: u32,
+    lod_distance: f32,
+    max_distance: f32,
+    /// Width of the distance bands blades crossfade over
+    crossfade: f32,
+    _padding: f32,
+}
+
+crate::assert_uniform_layout!(VegetationUBOContent {
+    base_color: ALIGN_VEC4,
+    tip_color: ALIGN_VEC4,
+    wind: ALIGN_VEC4,
+    blade_size: ALIGN_VEC2,
+    time: ALIGN_SCALAR,
+    count: ALIGN_SCALAR,
+    lod_distance: ALIGN_SCALAR,
+    max_distance: ALIGN_SCALAR,
+    crossfade: ALIGN_SCALAR,
+});
+
+struct GpuVegetation {
+    ubo: UniformBuffer<VegetationUBOContent>,
+    blades: Buffer,
+    count: u32,
+    /// [Vegetation::revision] of the uploaded blades
+    revision: u64,
+    /// Tells the views' buffers for other blades apart, see [VegetationPass::uploads]
+    upload: u64,
+    /// Seconds the wind has been blowing for, which only moves on while the vegetation isn't paused
+    time: Duration,
+    visible: bool,
+}
+
+/// What one view culled of one vegetation
+struct CulledVegetation {
+    /// The near and the far draw, whose instance counts the culling counts up
+    draws: Buffer,
+    cull_bind_group: BindGroup,
+    /// Drawing the near and the far blades
+    draw_bind_groups: [BindGroup; 2],
+    /// [GpuVegetation::upload] the buffers were made for
+    upload: u64,
+    visible: bool,
+}
+
+/// What one window or render target culled of the vegetation
+pub(crate) struct VegetationView {
+    camera: UniformBuffer<CameraUniform>,
+    vegetations: HashMap<usize, CulledVegetation>,
+}
+
+/// Culls and draws all vegetation, shared by every view
+pub(crate) struct VegetationPass {
+    cull: ComputeKernel,
+    cull_bind_group_layout: BindGroupLayoutWithDesc,
+    draw_bind_group_layout: BindGroupLayoutWithDesc,
+    /// Drawing the near and the far blades
+    pipelines: [RenderPipeline; 2],
+    vegetations: HashMap<usize, GpuVegetation>,
+    /// Counts the blades uploaded, so views know when theirs are stale
+    uploads: u64,
+    /// Blade counts of the vegetation too large for the device's storage buffers, so they're only reported once
+    too_large: HashMap<usize, usize>,
+}
+
+impl VegetationPass {
+    /// None where the device can't cull the blades or draw what the culling writes. `format` has to be the engine's
+    /// swapchain format, since the blades are drawn in the main pass. The blades are culled against the views' depth
+    /// pyramids as well.
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        depth_pyramid_pass: &DepthPyramidPass,
+        device_report: &DeviceReport,
+    ) -> Option<Self> {
+        let required = wgpu::DownlevelFlags::COMPUTE_SHADERS
+            | wgpu::DownlevelFlags::INDIRECT_EXECUTION
+            | wgpu::DownlevelFlags::VERTEX_STORAGE;
+        if !device_report.downlevel_flags.contains(required) {
+            tracing::debug!("Vegetation needs compute shaders, indirect draws and vertex storage");
+            return None;
+        }
+        let _span = tracing::debug_span!("create_vegetation_pipelines").entered();
+
+        let cull_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_compute(binding_types::uniform())
+            .named("vegetation")
+            .next_binding_compute(binding_types::uniform())
+            .named("camera")
+            .next_binding_compute(binding_types::buffer(true))
+            .named("blades")
+            .next_binding_compute(binding_types::buffer(false))
+            .named("culled")
+            .next_binding_compute(binding_types::buffer(false))
+            .named("draws")
+            .create(device, "Vegetation Cull Bind Group");
+        let cull = ComputeKernelBuilder::new(include_str!("../vegetation_cull.wgsl"))
+            .bind_group_layout(&cull_bind_group_layout.layout)
+            .bind_group_layout(&depth_pyramid_pass.cull_bind_group_layout().layout)
+            .create(device, "vegetation culling");
+
+        let draw_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_rendering(binding_types::uniform())
+            .next_binding_vertex(binding_types::buffer(true))
+            .next_binding_vertex(binding_types::buffer(true))
+            .create(device, "Vegetation Bind Group");
+        let pipelines = ["vs_near", "vs_far"].map(|entry_point| {
+            create_pipeline(
+                device,
+                format,
+                global_bindings,
+                &draw_bind_group_layout,
+                entry_point,
+            )
+        });
+
+        Some(VegetationPass {
+            cull,
+            cull_bind_group_layout,
+            draw_bind_group_layout,
+            pipelines,
+            vegetations: HashMap::new(),
+            uploads: 0,
+            too_large: HashMap::new(),
+        })
+    }
+
+    /// Uploads whatever changed about the vegetation, moves the wind of what isn't paused on by `delta_time` and
+    /// frees the blades of removed vegetation
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        vegetations: &[Option<Vegetation>],
+        delta_time: Duration,
+    ) {
+        self.vegetations
+            .retain(|&index, _| matches!(vegetations.get(index), Some(Some(_))));
+        let limit = device.limits().max_storage_buffer_binding_size as u64;
+        for (index, vegetation) in vegetations.iter().enumerate() {
+            let Some(vegetation) = vegetation else {
+                continue;
+            };
+            let count = vegetation.blades.len();
+            if count == 0 {
+                self.vegetations.remove(&index);
+                continue;
+            }
+            if count as u64 * std::mem::size_of::<Blade>() as u64 > limit {
+                if self.too_large.insert(index, count) != Some(count) {
+                    tracing::error!(
+                        "Vegetation of {count} blades is larger than the device's storage buffers of {limit} bytes, \
+                         it isn't drawn"
+                    );
+                }
+                self.vegetations.remove(&index);
+                continue;
+            }
+
+            let stale = self.vegetations.get(&index).is_none_or(|uploaded| {
+                uploaded.count != count as u32 || uploaded.revision != vegetation.revision
+            });
+            if stale {
+                self.uploads += 1;
+                let blades = wgpu::util::DeviceExt::create_buffer_init(
+                    device,
+                    &wgpu::util::BufferInitDescriptor {
+                        label: Some("Blades"),
+                        contents: bytemuck::cast_slice(&vegetation.blades),
+                        usage: wgpu::BufferUsages::STORAGE,
+                    },
+                );
+                let time = self
+                    .vegetations
+                    .remove(&index)
+                    .map_or(Duration::ZERO, |uploaded| uploaded.time);
+                self.vegetations.insert(
+                    index,
+                    GpuVegetation {
+                        ubo: UniformBuffer::new(device),
+                        blades,
+                        count: count as u32,
+                        revision: vegetation.revision,
+                        upload: self.uploads,
+                        time,
+                        visible: vegetation.visible,
+                    },
+                );
+            }
+            let uploaded = self
+                .vegetations
+                .get_mut(&index)
+                .expect("Vegetation was just uploaded!");
+            if !vegetation.paused {
+                uploaded.time += delta_time;
+            }
+            uploaded.visible = vegetation.visible;
+
+            let [r, g, b] = vegetation.base_color;
+            let base_color = [r, g, b, 1.0];
+            let [r, g, b] = vegetation.tip_color;
+            let tip_color = [r, g, b, 1.0];
+            let [x, z] = vegetation.wind_direction;
+            let length = (x * x + z * z).sqrt().max(1e-20);
+            let lod_distance = vegetation.lod_distance.max(0.0);
+            uploaded.ubo.update_content(
+                queue,
+                VegetationUBOContent {
+                    base_color,
+                    tip_color,
+                    wind: [
+                        x / length,
+                        z / length,
+                        vegetation.wind_strength,
+                        vegetation.wind_speed,
+                    ],
+                    blade_size: vegetation.blade_size,
+                    time: uploaded.time.as_secs_f32(),
+                    count: count as u32,
+                    lod_distance,
+                    max_distance: vegetation.max_distance.max(lod_distance),
+                    crossfade: lod_distance * CROSSFADE,
+                    _padding: 0.0,
+                },
+            );
+        }
+    }
+
+    /// Whether the wind moves any vegetation, so it has to be drawn again every frame
+    pub fn is_moving(&self, vegetations: &[Option<Vegetation>]) -> bool {
+        vegetations.iter().flatten().any(|vegetation| {
+            vegetation.visible
+                && !vegetation.paused
+                && vegetation.wind_strength != 0.0
+                && !vegetation.blades.is_empty()
+        })
+    }
+
+    /// Culls the blades for a view seen from `camera`, writing the draws of `view`. Blades behind everything the view
+    /// drew into its last `depth_pyramid` are culled too, see [DepthPyramidPass::cull_bind_group]. None without any
+    /// to cull.
+    pub fn cull(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        view: &mut Option<VegetationView>,
+        camera: &CameraUniform,
+        depth_pyramid: &BindGroup,
+    ) -> Option<CommandBuffer> {
+        if !self
+            .vegetations
+            .values()
+            .any(|vegetation| vegetation.visible)
+        {
+            *view = None;
+            return None;
+        }
+        let view = view.get_or_insert_with(|| VegetationView {
+            camera: UniformBuffer::new(device),
+            vegetations: HashMap::new(),
+        });
+        view.camera.update_content(queue, *camera);
+        view.vegetations
+            .retain(|index, _| self.vegetations.contains_key(index));
+
+        for (&index, vegetation) in &self.vegetations {
+            let stale = view
+                .vegetations
+                .get(&index)
+                .is_none_or(|culled| culled.upload != vegetation.upload);
+            if stale {
+                let culled = self.create_culled(device, &view.camera, vegetation);
+                view.vegetations.insert(index, culled);
+            }
+            let culled = view
+                .vegetations
+                .get_mut(&index)
+                .expect("Vegetation was just culled!");
+            culled.visible = vegetation.visible;
+            if vegetation.visible {
+                // Starts counting from no instances again
+                let draws = [NEAR_VERTICES, FAR_VERTICES].map(|vertex_count| DrawIndirectArgs {
+                    vertex_count,
+                    instance_count: 0,
+                    first_vertex: 0,
+                    first_instance: 0,
+                });
+                for (lod, draw) in draws.iter().enumerate() {
+                    queue.write_buffer(&culled.draws, lod as u64 * DRAW_SIZE, draw.as_bytes());
+                }
+            }
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Vegetation Culling Encoder"),
+        });
+        let mut pass = ComputePassBuilder::new("vegetation culling");
+        for (index, vegetation) in &self.vegetations {
+            if let (true, Some(culled)) = (vegetation.visible, view.vegetations.get(index)) {
+                pass = pass.dispatch(
+                    &self.cull,
+                    &[&culled.cull_bind_group, depth_pyramid],
+                    [vegetation.count, 1, 1],
+                );
+            }
+        }
+        pass.record(&mut encoder);
+        Some(encoder.finish())
+    }
+
+    /// The lists and draws one view culls `vegetation` into
+    fn create_culled(
+        &self,
+        device: &Device,
+        camera: &UniformBuffer<CameraUniform>,
+        vegetation: &GpuVegetation,
+    ) -> CulledVegetation {
+        let list_size = culled_list_size(device, vegetation.count);
+        // The near then the far blades' indices, each list starting at a storage buffer offset
+        let culled = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Culled Blades"),
+            size: list_size * 2,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let draws = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Blade Draws"),
+            size: DRAW_SIZE * 2,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let cull_bind_group = BindGroupBuilder::new(&self.cull_bind_group_layout)
+            .resource(vegetation.ubo.binding_resource())
+            .resource(camera.binding_resource())
+            .buffer(&vegetation.blades)
+            .buffer(&culled)
+            .buffer(&draws)
+            .create(device, "Vegetation Cull Bind Group");
+        let draw_bind_groups = [0, 1].map(|lod| {
+            BindGroupBuilder::new(&self.draw_bind_group_layout)
+                .resource(vegetation.ubo.binding_resource())
+                .buffer(&vegetation.blades)
+                .resource(wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &culled,
+                    offset: lod * list_size,
+                    size: NonZeroU64::new(list_size),
+                }))
+                .create(device, "Vegetation Bind Group")
+        });
+        CulledVegetation {
+            draws,
+            cull_bind_group,
+            draw_bind_groups,
+            upload: vegetation.upload,
+            visible: vegetation.visible,
+        }
+    }
+
+    pub fn remove_vegetation(&mut self, index: usize) {
+        self.vegetations.remove(&index);
+        self.too_large.remove(&index);
+    }
+
+    /// Bytes of the uploaded blades
+    pub fn size_in_bytes(&self) -> u64 {
+        self.vegetations
+            .values()
+            .map(|vegetation| vegetation.blades.size())
+            .sum()
+    }
+
+    /// Draws what [VegetationPass::cull] left of the blades for the view, in the main pass
+    pub fn draw(
+        &self,
+        render_pass: &mut RenderPass,
+        view: Option<&VegetationView>,
+        globals: &BindGroup,
+        stats: &mut RenderStats,
+    ) {
+        let Some(view) = view else {
+            return;
+        };
+        for (lod, pipeline) in self.pipelines.iter().enumerate() {
+            let mut culled = view
+                .vegetations
+                .values()
+                .filter(|culled| culled.visible)
+                .peekable();
+            if culled.peek().is_none() {
+                return;
+            }
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, globals, &[]);
+            stats.pipeline_switches += 1;
+            stats.bind_group_switches += 1;
+            for culled in culled {
+                render_pass.set_bind_group(1, &culled.draw_bind_groups[lod], &[]);
+                render_pass.draw_indirect(&culled.draws, lod as u64 * DRAW_SIZE);
+                stats.bind_group_switches += 1;
+                // The CPU doesn't know how many blades were culled
+                stats.draw(0, 0);
+            }
+        }
+    }
+}
+
+/// Of the LOD distance, the width of the band the near and the far blades crossfade over
+const CROSSFADE: f32 = 0.2;
+
+const DRAW_SIZE: u64 = std::mem::size_of::<DrawIndirectArgs>() as u64;
+
+/// Bytes of one list of culled blade indices, so the next one starts at a storage buffer offset
+fn culled_list_size(device: &Device, count: u32) -> u64 {
+    let alignment = device.limits().min_storage_buffer_offset_alignment as u64;
+    (count as u64 * 4).next_multiple_of(alignment)
+}
+
+/// Blades of the near or far LOD, by `entry_point`, for every culled instance
+fn create_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    global_bindings: &GlobalBindings,
+    bind_group_layout: &BindGroupLayoutWithDesc,
+    entry_point: &str,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Vegetation Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("../vegetation.wgsl").into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Vegetation Pipeline Layout"),
+        bind_group_layouts: &[
+            global_bindings.bind_group_layouts(),
+            &bind_group_layout.layout,
+        ],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Vegetation Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some(entry_point),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // Blades are seen from both sides
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+            unclipped_depth: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: texture::Texture::ID_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
+            compilation_options: Default::default(),
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
@@ -0,0 +1,143 @@
+// One vegetation, see vegetation/pass.rs
+struct Vegetation {
+    base_color: vec4<f32>,
+    tip_color: vec4<f32>,
+    wind: vec4<f32>,
+    blade_size: vec2<f32>,
+    time: f32,
+    count: u32,
+    lod_distance: f32,
+    max_distance: f32,
+    crossfade: f32,
+}
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+
+struct Blade {
+    position: vec3<f32>,
+    facing: f32,
+    scale: f32,
+}
+
+// Laid out like wgpu's DrawIndirectArgs
+struct DrawArgs {
+    vertex_count: u32,
+    instance_count: atomic<u32>,
+    first_vertex: u32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> vegetation: Vegetation;
+@group(0) @binding(1)
+var<uniform> camera: Camera;
+@group(0) @binding(2)
+var<storage, read> blades: array<Blade>;
+// The near blades' indices, then the far ones' from the second list's offset
+@group(0) @binding(3)
+var<storage, read_write> culled: array<u32>;
+@group(0) @binding(4)
+var<storage, read_write> draws: array<DrawArgs, 2>;
+
+// What the view drew last frame, see depth_pyramid.rs
+struct DepthPyramid {
+    view_proj: mat4x4<f32>,
+    size: vec2<u32>,
+    levels: u32,
+}
+@group(1) @binding(0)
+var<uniform> depth_pyramid: DepthPyramid;
+@group(1) @binding(1)
+var t_farthest: texture_2d<f32>;
+
+// Whether the sphere is entirely outside one of the frustum's planes, taken from the rows of the view projection
+fn outside_frustum(center: vec3<f32>, radius: f32) -> bool {
+    let m = transpose(camera.view_proj);
+    // Depth runs from 0 to 1, so the near plane is the third row on its own
+    var planes = array<vec4<f32>, 6>(
+        m[3] + m[0],
+        m[3] - m[0],
+        m[3] + m[1],
+        m[3] - m[1],
+        m[2],
+        m[3] - m[2],
+    );
+    for (var i = 0u; i < 6u; i++) {
+        let plane = planes[i];
+        if dot(plane.xyz, center) + plane.w < -radius * length(plane.xyz) {
+            return true;
+        }
+    }
+    return false;
+}
+
+// Whether the box around the sphere was behind the farthest depth of every pixel it covered last frame. Read from the
+// level where it covers at most three texels each way, boxes reaching behind the camera or off the view are kept.
+fn occluded(center: vec3<f32>, radius: f32) -> bool {
+    if depth_pyramid.levels == 0u {
+        return false;
+    }
+    var lower = vec3<f32>(1.0);
+    var upper = vec3<f32>(-1.0);
+    for (var corner = 0u; corner < 8u; corner++) {
+        let side = vec3<u32>(corner, corner >> 1u, corner >> 2u) & vec3<u32>(1u);
+        let clip = depth_pyramid.view_proj * vec4<f32>(center + (vec3<f32>(side) * 2.0 - 1.0) * radius, 1.0);
+        if clip.w <= 0.0 {
+            return false;
+        }
+        let ndc = clip.xyz / clip.w;
+        lower = min(lower, ndc);
+        upper = max(upper, ndc);
+    }
+    if any(lower.xy < vec2<f32>(-1.0)) || any(upper.xy > vec2<f32>(1.0)) || lower.z < 0.0 {
+        return false;
+    }
+
+    // Rows run down from the top
+    let uv_min = vec2<f32>(lower.x, -upper.y) * 0.5 + 0.5;
+    let uv_max = vec2<f32>(upper.x, -lower.y) * 0.5 + 0.5;
+    let extent = (uv_max - uv_min) * vec2<f32>(depth_pyramid.size);
+    let level = min(u32(log2(max(max(extent.x, extent.y), 1.0))), depth_pyramid.levels - 1u);
+    let level_size = max(depth_pyramid.size >> vec2<u32>(level), vec2<u32>(1u));
+    let first = min(vec2<u32>(uv_min * vec2<f32>(level_size)), level_size - 1u);
+    let last = min(vec2<u32>(uv_max * vec2<f32>(level_size)), level_size - 1u);
+    var farthest = 0.0;
+    for (var y = first.y; y <= last.y; y++) {
+        for (var x = first.x; x <= last.x; x++) {
+            farthest = max(farthest, textureLoad(t_farthest, vec2<u32>(x, y), i32(level)).r);
+        }
+    }
+    return lower.z > farthest;
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= vegetation.count {
+        return;
+    }
+    let blade = blades[index];
+    // A sphere around the blade however far the wind bends it
+    let height = vegetation.blade_size.x * blade.scale;
+    let center = blade.position + vec3<f32>(0.0, 0.5 * height, 0.0);
+    if outside_frustum(center, height) || occluded(center, height) {
+        return;
+    }
+    let distance = length(center - camera.view_pos.xyz);
+    if distance > vegetation.max_distance {
+        return;
+    }
+
+    // Both lists take the blades in the band they crossfade over
+    let half_band = 0.5 * vegetation.crossfade;
+    if distance < vegetation.lod_distance + half_band {
+        culled[atomicAdd(&draws[0].instance_count, 1u)] = index;
+    }
+    if distance > vegetation.lod_distance - half_band {
+        let far = arrayLength(&culled) / 2u;
+        culled[far + atomicAdd(&draws[1].instance_count, 1u)] = index;
+    }
+}
@@ -0,0 +1,78 @@
+//! Vertex pulling, see [crate::render_engine_builder::RenderEngineBuilder::vertex_pulling].
+//!
+//! The mesh pool's vertex buffer is bound as a storage buffer at group 3 and `vs_pulled` in vertex_pulling.wgsl
+//! fetches each vertex by its index instead of having the input assembler do it. Index buffers and base vertices
+//! work as before, the vertex index of an indexed draw already includes the base vertex.
+
+use wgpu::{BindGroup, Device};
+
+use crate::{
+    mesh::MeshPool,
+    render_engine_builder::DeviceReport,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+    },
+};
+
+/// Appended to the main shader for the pulling pipeline
+pub(crate) const SHADER_SOURCE: &str = include_str!("vertex_pulling.wgsl");
+
+/// Binds the mesh pool's vertices for `vs_pulled`
+pub(crate) struct VertexPulling {
+    bind_group_layout: BindGroupLayoutWithDesc,
+    bind_group: BindGroup,
+    /// The arena only ever replaces its buffer by a larger one, so a new size means a new buffer
+    bound_size: wgpu::BufferAddress,
+}
+
+impl VertexPulling {
+    /// Whether vertex shaders on the device can read storage buffers, which WebGL2 and some GLES drivers can't
+    pub fn is_supported(device_report: &DeviceReport) -> bool {
+        device_report
+            .downlevel_flags
+            .contains(wgpu::DownlevelFlags::VERTEX_STORAGE)
+            && device_report.limits.max_storage_buffers_per_shader_stage > 0
+    }
+
+    /// `meshes` has to have been created with `vertex_pulling`
+    pub fn new(device: &Device, meshes: &MeshPool) -> Self {
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_vertex(binding_types::buffer(true))
+            .create(device, "Vertex Pulling Bind Group");
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, meshes);
+
+        VertexPulling {
+            bind_group_layout,
+            bind_group,
+            bound_size: meshes.vertex_buffer().size(),
+        }
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        layout: &BindGroupLayoutWithDesc,
+        meshes: &MeshPool,
+    ) -> BindGroup {
+        BindGroupBuilder::new(layout)
+            .buffer(meshes.vertex_buffer())
+            .create(device, "Vertex Pulling Bind Group")
+    }
+
+    /// Binds the vertex buffer again after the mesh pool grew
+    pub fn update(&mut self, device: &Device, meshes: &MeshPool) {
+        let size = meshes.vertex_buffer().size();
+        if size != self.bound_size {
+            self.bind_group = Self::create_bind_group(device, &self.bind_group_layout, meshes);
+            self.bound_size = size;
+        }
+    }
+
+    pub fn bind_group_layouts(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout.layout
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+}
@@ -0,0 +1,17 @@
+// Appended to shader.wgsl when vertex pulling is enabled
+
+// Laid out like mesh::Vertex: position, color and texture coordinates, 8 floats per vertex
+@group(3) @binding(0)
+var<storage, read> vertices: array<f32>;
+
+const VERTEX_FLOATS: u32 = 8u;
+
+@vertex
+fn vs_pulled(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let base = index * VERTEX_FLOATS;
+    var model: VertexInput;
+    model.position = vec3<f32>(vertices[base], vertices[base + 1u], vertices[base + 2u]);
+    model.color = vec3<f32>(vertices[base + 3u], vertices[base + 4u], vertices[base + 5u]);
+    model.tex_coords = vec2<f32>(vertices[base + 6u], vertices[base + 7u]);
+    return transform_vertex(model);
+}
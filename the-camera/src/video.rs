@@ -0,0 +1,319 @@
+//! Playing video files on materials, only with the `ffmpeg` feature. An `ffmpeg` process found on the PATH decodes
+//! the video into raw frames on a background thread, `ffprobe` tells its size and frame rate up front. Materials show
+//! one with [crate::scene::Material::video].
+//!
+//! Audio is left out, so there's nothing to keep in sync: frames come due by the video's frame rate as the engine
+//! updates, ones the decoder delivers late are skipped and the video carries on from the newest one.
+
+use std::{
+    io::{self, Read},
+    path::Path,
+    process::{Command, Stdio},
+    sync::{
+        mpsc::{channel, sync_channel, Receiver, Sender, TryRecvError},
+        Mutex, PoisonError,
+    },
+    time::Duration,
+};
+
+use crate::{
+    material_bindings::MaterialBindings,
+    texture::{Texture, TextureData},
+    wgpu_utils::resource_cache::SamplerCache,
+};
+
+/// Textures the frames are uploaded into one after the other, so an upload never waits on draws still sampling the
+/// previous frame
+const RING_SIZE: usize = 3;
+/// Decoded frames waiting to be shown, the decoder blocks when they pile up
+const QUEUED_FRAMES: usize = 4;
+/// When ffprobe can't tell
+const DEFAULT_FRAME_RATE: f32 = 30.0;
+
+/// Refers to a video added with [crate::render_engine::RenderEngine::add_video]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VideoHandle(pub(crate) usize);
+
+/// A video file being decoded, ready for [crate::render_engine::RenderEngine::add_video]
+pub struct VideoTexture {
+    width: u32,
+    height: u32,
+    frame_rate: f32,
+    looping: bool,
+    /// Stops advancing, holding the frame shown
+    pub paused: bool,
+    /// Seconds played so far
+    position: f32,
+    /// Frames taken from the decoder so far
+    taken: u64,
+    finished: bool,
+    /// Only taken from through `&mut self`, so never actually locked. The lock makes videos `Sync`, which the main
+    /// pass needs with the `parallel-encoding` feature.
+    frames: Mutex<Receiver<Vec<u8>>>,
+    /// Hands shown frames back to the decoder to fill again
+    recycled: Sender<Vec<u8>>,
+}
+
+impl VideoTexture {
+    /// Starts decoding the file, played from the start once added to the engine. Looping videos start over after
+    /// the last frame, others hold it.
+    pub fn open(path: impl AsRef<Path>, looping: bool) -> io::Result<Self> {
+        let path = path.as_ref();
+        let (width, height, frame_rate) = probe(path)?;
+
+        let mut decoder = Command::new("ffmpeg");
+        decoder.args(["-v", "error"]);
+        if looping {
+            decoder.args(["-stream_loop", "-1"]);
+        }
+        let mut child = decoder
+            .arg("-i")
+            .arg(path)
+            .args(["-an", "-f", "rawvideo", "-pix_fmt", "rgba", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let mut stdout = child.stdout.take().expect("ffmpeg stdout is piped");
+
+        let (sender, frames) = sync_channel(QUEUED_FRAMES);
+        let (recycled, recycle) = channel::<Vec<u8>>();
+        let frame_size = width as usize * height as usize * 4;
+        std::thread::spawn(move || {
+            loop {
+                let mut frame = recycle.try_recv().unwrap_or_else(|_| vec![0; frame_size]);
+                if stdout.read_exact(&mut frame).is_err() {
+                    break;
+                }
+                // Fails once the video was dropped
+                if sender.send(frame).is_err() {
+                    break;
+                }
+            }
+            let _ = child.kill();
+            let _ = child.wait();
+        });
+
+        Ok(VideoTexture {
+            width,
+            height,
+            frame_rate,
+            looping,
+            paused: false,
+            position: 0.0,
+            taken: 0,
+            finished: false,
+            frames: Mutex::new(frames),
+            recycled,
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Frames per second
+    pub fn frame_rate(&self) -> f32 {
+        self.frame_rate
+    }
+
+    pub fn is_looping(&self) -> bool {
+        self.looping
+    }
+
+    /// Seconds played so far, counting up across loops
+    pub fn position(&self) -> f32 {
+        self.position
+    }
+
+    /// Whether the decoder delivered the last frame, or stopped on an error
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Whether later updates may show another frame
+    pub fn is_playing(&self) -> bool {
+        !self.paused && !self.finished
+    }
+
+    /// Moves the video along by `delta`, returning the newest frame that came due in the meantime
+    fn advance(&mut self, delta: Duration) -> Option<Vec<u8>> {
+        if !self.is_playing() {
+            return None;
+        }
+        self.position += delta.as_secs_f32();
+        let due = (self.position * self.frame_rate) as u64 + 1;
+        let frames = self
+            .frames
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut newest = None;
+        while self.taken < due {
+            match frames.try_recv() {
+                Ok(frame) => {
+                    self.taken += 1;
+                    if let Some(skipped) = newest.replace(frame) {
+                        let _ = self.recycled.send(skipped);
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+        newest
+    }
+
+    fn recycle(&self, frame: Vec<u8>) {
+        let _ = self.recycled.send(frame);
+    }
+}
+
+/// The size and frame rate of the file's first video stream
+fn probe(path: &Path) -> io::Result<(u32, u32, f32)> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args(["-show_entries", "stream=width,height,r_frame_rate"])
+        .args(["-of", "csv=p=0"])
+        .arg(path)
+        .output()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let invalid = || {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{}: no video stream found {}",
+                path.display(),
+                stderr.trim()
+            ),
+        )
+    };
+    // e.g. 1920,1080,30000/1001
+    let mut fields = text.trim().split(',');
+    let mut dimension =
+        || -> Option<u32> { fields.next()?.trim().parse().ok().filter(|&size| size > 0) };
+    let (width, height) = (
+        dimension().ok_or_else(invalid)?,
+        dimension().ok_or_else(invalid)?,
+    );
+    let frame_rate = fields
+        .next()
+        .and_then(|rate| {
+            let (numerator, denominator) = rate.trim().split_once('/')?;
+            Some(numerator.parse::<f32>().ok()? / denominator.parse::<f32>().ok()?)
+        })
+        .filter(|rate| rate.is_finite() && *rate > 0.0)
+        .unwrap_or(DEFAULT_FRAME_RATE);
+    Ok((width, height, frame_rate))
+}
+
+/// A video together with the ring of textures its frames are uploaded into
+pub(crate) struct GpuVideo {
+    pub video: VideoTexture,
+    textures: Vec<Texture>,
+    bind_groups: Vec<wgpu::BindGroup>,
+    /// Ring slot holding the frame shown
+    current: usize,
+    /// Kept for uploading again after a device loss, handed back to the decoder once replaced
+    shown: Option<Vec<u8>>,
+}
+
+impl GpuVideo {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        samplers: &SamplerCache,
+        video: VideoTexture,
+        material_bindings: &MaterialBindings,
+    ) -> Self {
+        let (width, height) = video.size();
+        // Black until the first frame arrives
+        let black = [0, 0, 0, 255].repeat(width as usize * height as usize);
+        let textures: Vec<_> = (0..RING_SIZE)
+            .map(|_| {
+                Texture::from_rgba(
+                    device,
+                    queue,
+                    samplers,
+                    width,
+                    height,
+                    &black,
+                    "Video Texture",
+                )
+            })
+            .collect();
+        let bind_groups = textures
+            .iter()
+            .map(|texture| material_bindings.create_bind_group(device, texture))
+            .collect();
+        GpuVideo {
+            video,
+            textures,
+            bind_groups,
+            current: 0,
+            shown: None,
+        }
+    }
+
+    /// The same video on another device, showing the frame it was at
+    pub fn recreate(
+        mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        samplers: &SamplerCache,
+        material_bindings: &MaterialBindings,
+    ) -> Self {
+        let shown = self.shown.take();
+        let mut video = GpuVideo::new(device, queue, samplers, self.video, material_bindings);
+        if let Some(frame) = shown {
+            video.upload(queue, frame);
+        }
+        video
+    }
+
+    /// Advances the video and uploads the frame that came due, returns whether it's still playing
+    pub fn advance(&mut self, queue: &wgpu::Queue, delta: Duration) -> bool {
+        if let Some(frame) = self.video.advance(delta) {
+            self.upload(queue, frame);
+        }
+        self.video.is_playing()
+    }
+
+    fn upload(&mut self, queue: &wgpu::Queue, frame: Vec<u8>) {
+        self.current = (self.current + 1) % RING_SIZE;
+        let texture = &self.textures[self.current].texture;
+        queue.write_texture(
+            texture.as_image_copy(),
+            &frame,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(texture.width() * 4),
+                rows_per_image: Some(texture.height()),
+            },
+            texture.size(),
+        );
+        if let Some(replaced) = self.shown.replace(frame) {
+            self.video.recycle(replaced);
+        }
+    }
+
+    /// The frame shown, None before the first one arrived
+    pub fn frame(&self) -> Option<TextureData> {
+        let (width, height) = self.video.size();
+        Some(TextureData {
+            width,
+            height,
+            rgba: self.shown.clone()?,
+        })
+    }
+
+    pub fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_groups[self.current]
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.textures.iter().map(Texture::size_in_bytes).sum()
+    }
+}
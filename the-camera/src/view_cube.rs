@@ -0,0 +1,422 @@
+//! A cube in the top right corner of every window that turns with its camera, like in CAD packages, showing which
+//! way the scene is seen from. Its faces are labeled with the views they stand for. Clicking a face, edge or corner
+//! turns the camera to look from that side, see [crate::render_engine::RenderEngine::animate_view].
+
+use std::collections::HashMap;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Vector4};
+use wgpu::{CommandEncoder, Device, Queue, RenderPipeline, TextureFormat, TextureView};
+use winit::{event::MouseButton, window::WindowId};
+
+use crate::{
+    camera::orbit_camera::{OrbitCamera, OPENGL_TO_WGPU_DEPTH},
+    input::InputEvent,
+    overlay::{OverlayGeometry, OverlayVertex},
+};
+
+/// Of the square the cube is drawn in, in pixels
+const SIZE: f32 = 140.0;
+/// Between the square and the window border, in pixels
+const MARGIN: f32 = 12.0;
+/// Half the width of what the cube's camera sees. The cube reaches from -1 to 1, so its corners fit from any side.
+const EXTENT: f32 = 1.8;
+/// Faces are split into a middle and a border of edges and corners where their coordinates pass this
+const EDGE: f32 = 0.6;
+/// Font pixels in cube units, the longest label fills most of a face
+const LABEL_PIXEL: f32 = 0.05;
+const FACE_COLOR: [f32; 4] = [0.82, 0.84, 0.88, 0.9];
+const BORDER_COLOR: [f32; 4] = [0.62, 0.65, 0.7, 0.9];
+const HOVER_COLOR: [f32; 4] = [0.3, 0.6, 1.0, 0.95];
+const LABEL_COLOR: [f32; 4] = [0.15, 0.17, 0.2, 1.0];
+
+struct Face {
+    normal: [f32; 3],
+    /// Pointing right and up on the face when it's looked at
+    right: [f32; 3],
+    up: [f32; 3],
+    label: &'static str,
+}
+
+const FACES: [Face; 6] = [
+    Face {
+        normal: [0.0, 0.0, 1.0],
+        right: [1.0, 0.0, 0.0],
+        up: [0.0, 1.0, 0.0],
+        label: "FRONT",
+    },
+    Face {
+        normal: [0.0, 0.0, -1.0],
+        right: [-1.0, 0.0, 0.0],
+        up: [0.0, 1.0, 0.0],
+        label: "BACK",
+    },
+    Face {
+        normal: [1.0, 0.0, 0.0],
+        right: [0.0, 0.0, -1.0],
+        up: [0.0, 1.0, 0.0],
+        label: "RIGHT",
+    },
+    Face {
+        normal: [-1.0, 0.0, 0.0],
+        right: [0.0, 0.0, 1.0],
+        up: [0.0, 1.0, 0.0],
+        label: "LEFT",
+    },
+    Face {
+        normal: [0.0, 1.0, 0.0],
+        right: [1.0, 0.0, 0.0],
+        up: [0.0, 0.0, -1.0],
+        label: "TOP",
+    },
+    Face {
+        normal: [0.0, -1.0, 0.0],
+        right: [1.0, 0.0, 0.0],
+        up: [0.0, 0.0, 1.0],
+        label: "BOTTOM",
+    },
+];
+
+/// What handling an input event did, see [ViewCube::process_event]
+#[derive(Debug, Default)]
+pub(crate) struct ViewCubeResponse {
+    /// Pressed on the cube, the camera shouldn't follow the mouse
+    pub consumed: bool,
+    pub redraw: bool,
+    /// The camera should turn to look from this side, pointing from the target towards the eye
+    pub clicked: Option<Vector3<f32>>,
+}
+
+/// Which part of the cube the cursor is over, with the direction it stands for, see the [module docs](self)
+pub(crate) struct ViewCube {
+    pub visible: bool,
+    /// A face, edge or corner as the signs of its direction
+    hovered: HashMap<WindowId, [i32; 3]>,
+    press: Option<(WindowId, [i32; 3])>,
+}
+
+impl ViewCube {
+    pub fn new() -> Self {
+        ViewCube {
+            visible: true,
+            hovered: HashMap::new(),
+            press: None,
+        }
+    }
+
+    /// Tracks the cursor over the cube in windows `width` by `height` pixels, seen with `camera`
+    pub fn process_event(
+        &mut self,
+        window_id: WindowId,
+        event: &InputEvent,
+        camera: &OrbitCamera,
+        width: u32,
+        height: u32,
+    ) -> ViewCubeResponse {
+        if !self.visible {
+            return ViewCubeResponse::default();
+        }
+        match event {
+            &InputEvent::CursorMoved(cursor) => {
+                let hovered = hit(camera, width, height, cursor);
+                let previous = match hovered {
+                    Some(part) => self.hovered.insert(window_id, part),
+                    None => self.hovered.remove(&window_id),
+                };
+                ViewCubeResponse {
+                    redraw: previous != hovered,
+                    ..Default::default()
+                }
+            }
+            InputEvent::CursorLeft => ViewCubeResponse {
+                redraw: self.hovered.remove(&window_id).is_some(),
+                ..Default::default()
+            },
+            InputEvent::MouseButton {
+                button: MouseButton::Left,
+                pressed: true,
+            } => {
+                self.press = self.hovered.get(&window_id).map(|&part| (window_id, part));
+                ViewCubeResponse {
+                    consumed: self.press.is_some(),
+                    ..Default::default()
+                }
+            }
+            InputEvent::MouseButton {
+                button: MouseButton::Left,
+                pressed: false,
+            } => match self.press.take() {
+                // Only if it's let go of over the same part
+                Some((press_window, part)) if press_window == window_id => ViewCubeResponse {
+                    consumed: true,
+                    clicked: (self.hovered.get(&window_id) == Some(&part))
+                        .then(|| part.map(|sign| sign as f32).into()),
+                    ..Default::default()
+                },
+                _ => ViewCubeResponse::default(),
+            },
+            _ => ViewCubeResponse::default(),
+        }
+    }
+
+    /// The cube turned like `camera` in clip space, with the part under the cursor highlighted
+    pub fn geometry(&self, window_id: WindowId, camera: &OrbitCamera) -> OverlayGeometry {
+        let hovered = self.hovered.get(&window_id).copied();
+        let mut geometry = OverlayGeometry::default();
+        // The middle, edges and corners of each face, with their coordinates along right and up
+        let spans: [(i32, f32, f32); 3] = [(-1, -1.0, -EDGE), (0, -EDGE, EDGE), (1, EDGE, 1.0)];
+        for face in &FACES {
+            let (normal, right, up) = (
+                Vector3::from(face.normal),
+                Vector3::from(face.right),
+                Vector3::from(face.up),
+            );
+            for (across, left, right_end) in spans {
+                for (along, bottom, top) in spans {
+                    let part = (normal + right * across as f32 + up * along as f32)
+                        .map(|sign| sign as i32)
+                        .into();
+                    let color = if hovered == Some(part) {
+                        HOVER_COLOR
+                    } else if across == 0 && along == 0 {
+                        FACE_COLOR
+                    } else {
+                        BORDER_COLOR
+                    };
+                    let corner = |x: f32, y: f32| normal + right * x + up * y;
+                    geometry.quad(
+                        [
+                            corner(left, bottom),
+                            corner(right_end, bottom),
+                            corner(right_end, top),
+                            corner(left, top),
+                        ],
+                        color,
+                    );
+                }
+            }
+        }
+        // Drawn after every face, the ones on faces turned away are culled with them
+        for face in &FACES {
+            geometry.text_in_plane(
+                face.normal.into(),
+                (face.right.into(), face.up.into()),
+                LABEL_PIXEL,
+                face.label,
+                LABEL_COLOR,
+            );
+        }
+        geometry.transform(view_proj(camera));
+        geometry
+    }
+
+    /// Forgets a closed window
+    pub fn remove_window(&mut self, window_id: WindowId) {
+        self.hovered.remove(&window_id);
+        if self
+            .press
+            .is_some_and(|(press_window, _)| press_window == window_id)
+        {
+            self.press = None;
+        }
+    }
+}
+
+/// Top left corner of the square the cube is drawn in, None if the window is too small to fit it
+fn corner(width: u32, height: u32) -> Option<[f32; 2]> {
+    let left = width as f32 - SIZE - MARGIN;
+    (left >= MARGIN && height as f32 >= SIZE + 2.0 * MARGIN).then_some([left, MARGIN])
+}
+
+/// Looks at the cube from the direction `camera` looks at its target from, without perspective
+fn view_proj(camera: &OrbitCamera) -> Matrix4<f32> {
+    let direction = (camera.eye - camera.target).normalize();
+    let view = Matrix4::look_at_rh(
+        Point3::from_vec(direction * 3.0),
+        Point3::origin(),
+        camera.up,
+    );
+    OPENGL_TO_WGPU_DEPTH * cgmath::ortho(-EXTENT, EXTENT, -EXTENT, EXTENT, 1.0, 5.0) * view
+}
+
+/// The part of the cube under `cursor`, by casting a ray through the square it's drawn in
+fn hit(camera: &OrbitCamera, width: u32, height: u32, cursor: [f32; 2]) -> Option<[i32; 3]> {
+    let [left, top] = corner(width, height)?;
+    let (x, y) = ((cursor[0] - left) / SIZE, (cursor[1] - top) / SIZE);
+    if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
+        return None;
+    }
+    let inverse = view_proj(camera).invert()?;
+    let unproject = |depth: f32| {
+        let point = inverse * Vector4::new(x * 2.0 - 1.0, 1.0 - y * 2.0, depth, 1.0);
+        point.truncate() / point.w
+    };
+    let origin = unproject(0.0);
+    let direction = unproject(1.0) - origin;
+
+    // Where the ray enters the box from -1 to 1
+    let (mut enter, mut exit) = (f32::MIN, f32::MAX);
+    for axis in 0..3 {
+        if direction[axis].abs() < 1e-6 {
+            if origin[axis].abs() > 1.0 {
+                return None;
+            }
+            continue;
+        }
+        let a = (-1.0 - origin[axis]) / direction[axis];
+        let b = (1.0 - origin[axis]) / direction[axis];
+        enter = enter.max(a.min(b));
+        exit = exit.min(a.max(b));
+    }
+    if enter > exit {
+        return None;
+    }
+    let point = origin + direction * enter;
+    Some(
+        point
+            .map(|coordinate| {
+                if coordinate > EDGE {
+                    1
+                } else if coordinate < -EDGE {
+                    -1
+                } else {
+                    0
+                }
+            })
+            .into(),
+    )
+}
+
+/// Draws the [ViewCube] into the corner of the windows, over everything else
+pub(crate) struct ViewCubePass {
+    pipeline: RenderPipeline,
+    /// Rewritten every frame, one per window like the overlay's
+    vertex_buffers: HashMap<WindowId, wgpu::Buffer>,
+}
+
+impl ViewCubePass {
+    /// `format` has to be the engine's swapchain format, the cube is drawn straight into the windows
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let _span = tracing::debug_span!("create_view_cube_pipeline").entered();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("View Cube Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("view_cube.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("view cube"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<OverlayVertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &[
+                        wgpu::VertexAttribute {
+                            offset: 0,
+                            shader_location: 0,
+                            format: wgpu::VertexFormat::Float32x3,
+                        },
+                        wgpu::VertexAttribute {
+                            offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                            shader_location: 1,
+                            format: wgpu::VertexFormat::Float32x4,
+                        },
+                    ],
+                }],
+                compilation_options: Default::default(),
+            },
+            // The cube is convex, so culling the faces turned away is all the depth test it needs
+            primitive: wgpu::PrimitiveState {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        ViewCubePass {
+            pipeline,
+            vertex_buffers: HashMap::new(),
+        }
+    }
+
+    /// Records a pass drawing `geometry` from [ViewCube::geometry] into the corner of `view`, a window `width` by
+    /// `height` pixels. Does nothing if the window is too small.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        encoder: &mut CommandEncoder,
+        window_id: WindowId,
+        view: &TextureView,
+        width: u32,
+        height: u32,
+        geometry: &OverlayGeometry,
+    ) {
+        let Some([left, top]) = corner(width, height) else {
+            return;
+        };
+        let bytes: &[u8] = bytemuck::cast_slice(geometry.triangles());
+        let size = bytes.len() as u64;
+        let buffer = self
+            .vertex_buffers
+            .entry(window_id)
+            .or_insert_with(|| create_vertex_buffer(device, size));
+        if buffer.size() < size {
+            *buffer = create_vertex_buffer(device, size);
+        }
+        queue.write_buffer(buffer, 0, bytes);
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("view_cube"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_viewport(left, top, SIZE, SIZE, 0.0, 1.0);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_vertex_buffer(0, buffer.slice(..));
+        render_pass.draw(0..geometry.triangles().len() as u32, 0..1);
+    }
+
+    /// Stops keeping a vertex buffer for the window
+    pub fn remove_window(&mut self, window_id: WindowId) {
+        self.vertex_buffers.remove(&window_id);
+    }
+}
+
+fn create_vertex_buffer(device: &Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("View Cube Vertices"),
+        size: size.next_power_of_two(),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
@@ -0,0 +1,580 @@
+use std::{path::PathBuf, sync::Arc};
+
+use cgmath::Vector3;
+use wgpu::{
+    Adapter, CompositeAlphaMode, Device, Instance, PresentMode, Surface, SurfaceCapabilities,
+    SurfaceConfiguration, TextureFormat,
+};
+
+use crate::{
+    audio::AudioUniform,
+    camera::{
+        camera::CameraUniform, camera_controller::CameraController, orbit_camera::OrbitCamera,
+        view_animation::ViewAnimation,
+    },
+    clipping::ClipUniform,
+    global_bindings::{update_global_ubo, GlobalBindings, GlobalUBO},
+    input::CursorMode,
+    jobs::JobSystem,
+    occlusion::OcclusionQueries,
+    platform::SurfaceProvider,
+    recording::FrameRecorder,
+    scene::Pick,
+    screenshot::PendingCapture,
+    texture,
+    wgpu_utils::{
+        readback::{ReadbackPromise, ReadbackRing},
+        resource_cache::SamplerCache,
+        uploader::Uploader,
+    },
+};
+
+/// How the engine configures window surfaces, passed to [crate::render_engine::RenderEngine::new].
+///
+/// Anything the surface doesn't support falls back to a mode/format that is always available, so these are
+/// preferences rather than requirements.
+#[derive(Debug, Clone)]
+pub struct SurfaceOptions {
+    present_mode: Option<PresentMode>,
+    desired_maximum_frame_latency: u32,
+    preferred_format: Option<TextureFormat>,
+}
+
+impl Default for SurfaceOptions {
+    fn default() -> Self {
+        SurfaceOptions {
+            present_mode: None,
+            desired_maximum_frame_latency: 2,
+            preferred_format: None,
+        }
+    }
+}
+
+impl SurfaceOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Present mode to use, e.g. [PresentMode::Mailbox] or [PresentMode::Immediate] for uncapped frame rates.
+    /// Falls back to [PresentMode::Fifo] (vsync) when unsupported.
+    pub fn present_mode(mut self, present_mode: PresentMode) -> Self {
+        self.present_mode = Some(present_mode);
+        self
+    }
+
+    /// How many frames the CPU may queue up ahead of the GPU. Lower values reduce input latency, higher values
+    /// smooth out uneven frame times. Typically 1 to 3.
+    pub fn frame_latency(mut self, frames: u32) -> Self {
+        self.desired_maximum_frame_latency = frames;
+        self
+    }
+
+    /// Swapchain format to use if the surface supports it. Without it the first non-sRGB format is picked.
+    pub fn preferred_format(mut self, format: TextureFormat) -> Self {
+        self.preferred_format = Some(format);
+        self
+    }
+
+    /// Picks the swapchain format for a surface with the given capabilities
+    pub fn select_format(&self, capabilities: &SurfaceCapabilities) -> TextureFormat {
+        if let Some(format) = self.preferred_format {
+            if capabilities.formats.contains(&format) {
+                return format;
+            }
+            tracing::warn!("Surface format {format:?} is not supported, using the default instead");
+        }
+        capabilities
+            .formats
+            .iter()
+            .copied()
+            .find(|f| !f.is_srgb())
+            .unwrap_or(capabilities.formats[0])
+    }
+
+    fn select_present_mode(&self, capabilities: &SurfaceCapabilities) -> PresentMode {
+        match self.present_mode {
+            // The Auto modes are resolved by wgpu itself and always succeed
+            Some(mode @ (PresentMode::AutoVsync | PresentMode::AutoNoVsync)) => mode,
+            Some(mode) if capabilities.present_modes.contains(&mode) => mode,
+            Some(mode) => {
+                tracing::warn!("Present mode {mode:?} is not supported, falling back to Fifo");
+                PresentMode::Fifo
+            }
+            None => capabilities.present_modes[0],
+        }
+    }
+}
+
+/// Everything the engine needs to draw into one window: its surface and swapchain config, depth buffer, camera and
+/// the global bindings holding that camera. The device, queue and pipelines are shared through the [crate::render_engine::RenderEngine].
+pub struct Viewport {
+    pub window: Arc<dyn SurfaceProvider>,
+    /// None while the app is suspended, e.g. in the background on Android where the native window is destroyed
+    surface: Option<Surface<'static>>,
+    pub(crate) config: SurfaceConfiguration,
+    /// What the surface supports for compositing with the desktop
+    alpha_modes: Vec<CompositeAlphaMode>,
+    /// Of [Viewport::render_size], like the IDs and the scene
+    pub(crate) depth_texture: texture::Texture,
+    /// Which object is drawn into each pixel, for [crate::render_engine::RenderEngine::pick]
+    pub(crate) id_texture: texture::Texture,
+    /// See [crate::render_engine::RenderEngine::set_render_scale]
+    render_scale: f32,
+    /// Drawn into below full resolution and scaled up into the surface, see [crate::upscaling]. None at full
+    /// resolution, where the scene is drawn straight into the surface.
+    pub(crate) scene: Option<texture::Texture>,
+    /// The window has no area, e.g. minimized on Windows. Nothing is drawn until it gets a size again.
+    minimized: bool,
+
+    pub camera: OrbitCamera,
+    pub camera_controller: CameraController,
+    /// Turning the camera to a new view, stopped when the user moves it
+    pub(crate) view_animation: Option<ViewAnimation>,
+    /// Only applied while the window has focus
+    pub(crate) cursor_mode: CursorMode,
+    global_ubo: GlobalUBO,
+    pub(crate) global_bindings: GlobalBindings,
+    pub(crate) occlusion: OcclusionQueries,
+    /// Created with the first meshlets culled for the window
+    #[cfg(feature = "meshlets")]
+    pub(crate) meshlets: Option<crate::meshlet::MeshletView>,
+    /// Created with the first point cloud drawn into it
+    pub(crate) point_clouds: Option<crate::point_cloud::PointCloudView>,
+    /// Created with the first terrain drawn into it
+    pub(crate) terrain: Option<crate::terrain::TerrainView>,
+    /// Created with the first vegetation drawn into it
+    pub(crate) vegetation: Option<crate::vegetation::VegetationView>,
+    /// Created with the first voxel grid drawn into it
+    pub(crate) voxel_grids: Option<crate::voxel_grid::VoxelGridView>,
+    /// Created with the first frame drawn with an atmosphere
+    pub(crate) atmosphere: Option<crate::atmosphere::AtmosphereView>,
+    /// Built after every main pass while [crate::render_engine::RenderEngine::builds_depth_pyramids]
+    pub(crate) depth_pyramid: Option<crate::depth_pyramid::DepthPyramid>,
+    /// Pixels whose depth is copied out with the next frame
+    pub(crate) depth_requests: Vec<([u32; 2], ReadbackPromise<f32>)>,
+    depth_reads: ReadbackRing<ReadbackPromise<f32>>,
+    /// Pixels whose object ID is copied out with the next frame
+    pub(crate) pick_requests: Vec<([u32; 2], ReadbackPromise<Pick>)>,
+    /// Each pick carries what was drawn that frame, by ID
+    picks: ReadbackRing<(ReadbackPromise<Pick>, Arc<[Pick]>)>,
+
+    pub(crate) capture_requests: Vec<PathBuf>,
+    pub(crate) pending_captures: Vec<PendingCapture>,
+    pub(crate) recorder: Option<FrameRecorder>,
+}
+
+impl Viewport {
+    /// Configures `surface` for `window` using the engine wide swapchain `format` and `options`
+    pub fn new(
+        device: &Device,
+        samplers: &SamplerCache,
+        adapter: &Adapter,
+        surface: Surface<'static>,
+        window: Arc<dyn SurfaceProvider>,
+        format: TextureFormat,
+        options: &SurfaceOptions,
+    ) -> Self {
+        let [width, height] = window.inner_size();
+        // Surfaces can't be configured with zero extent, e.g. for a minimized window or a canvas that hasn't been
+        // laid out yet. Everything is created at 1x1 instead and rendering waits for the first real size.
+        let minimized = width == 0 || height == 0;
+        let (width, height) = (width.max(1), height.max(1));
+
+        let surface_capabilities = surface.get_capabilities(adapter);
+        // The pipelines are shared between all windows, so every surface has to use the same format
+        assert!(
+            surface_capabilities.formats.contains(&format),
+            "Window surface does not support the engine's swapchain format {format:?}!"
+        );
+
+        // Copying out of the surface is needed for screenshots, but not every backend allows it
+        let usage = wgpu::TextureUsages::RENDER_ATTACHMENT
+            | (surface_capabilities.usages & wgpu::TextureUsages::COPY_SRC);
+        let config = wgpu::SurfaceConfiguration {
+            usage,
+            format,
+            width,
+            height,
+            present_mode: options.select_present_mode(&surface_capabilities),
+            alpha_mode: select_alpha_mode(&surface_capabilities.alpha_modes, false),
+            view_formats: vec![],
+            desired_maximum_frame_latency: options.desired_maximum_frame_latency,
+        };
+        surface.configure(device, &config);
+        let depth_texture =
+            texture::Texture::create_depth_texture(device, samplers, &config, "depth_texture");
+        let id_texture =
+            texture::Texture::create_id_texture(device, samplers, width, height, "id_texture");
+
+        let mut camera = OrbitCamera::new(
+            1.0,
+            0.0,
+            0.0,
+            Vector3::new(0.0, 0.0, 0.0),
+            width as f32 / height as f32,
+        );
+        camera.bounds.min_distance = Some(1.1);
+        let camera_controller = CameraController::new(0.005, 0.1);
+
+        // Each window binds its own camera. The layout is created from the same descriptor as the one the
+        // pipelines were built with, which makes the two compatible.
+        let global_ubo = GlobalUBO::new(device);
+        let mut global_bindings = GlobalBindings::new(device);
+        global_bindings.create_bind_group(device, &global_ubo);
+
+        Viewport {
+            window,
+            surface: Some(surface),
+            config,
+            alpha_modes: surface_capabilities.alpha_modes,
+            depth_texture,
+            id_texture,
+            render_scale: 1.0,
+            scene: None,
+            minimized,
+
+            camera,
+            camera_controller,
+            view_animation: None,
+            cursor_mode: CursorMode::Normal,
+            global_ubo,
+            global_bindings,
+            occlusion: OcclusionQueries::new(),
+            #[cfg(feature = "meshlets")]
+            meshlets: None,
+            point_clouds: None,
+            terrain: None,
+            vegetation: None,
+            voxel_grids: None,
+            atmosphere: None,
+            depth_pyramid: None,
+            depth_requests: Vec::new(),
+            depth_reads: ReadbackRing::new("Depth Readback Buffer"),
+            pick_requests: Vec::new(),
+            picks: ReadbackRing::new("Pick Readback Buffer"),
+
+            capture_requests: Vec::new(),
+            pending_captures: Vec::new(),
+            recorder: None,
+        }
+    }
+
+    /// Grabs and hides the cursor as [Viewport::cursor_mode] says while the window has focus, and gives it back
+    /// otherwise
+    pub(crate) fn apply_cursor_mode(&self, focused: bool) {
+        let mode = if focused {
+            self.cursor_mode
+        } else {
+            CursorMode::Normal
+        };
+        self.window.set_cursor_mode(mode);
+    }
+
+    /// Whether the window currently has no area to draw into
+    pub fn is_minimized(&self) -> bool {
+        self.minimized
+    }
+
+    /// Pixels the scene is drawn at before it's scaled up to the window's size, the window's size at full resolution
+    pub fn render_size(&self) -> (u32, u32) {
+        let scale = |size: u32| ((size as f32 * self.render_scale).round() as u32).clamp(1, size);
+        (scale(self.config.width), scale(self.config.height))
+    }
+
+    /// Draws the scene at `scale` of the window's resolution from the next frame on, see
+    /// [crate::render_engine::RenderEngine::set_render_scale]
+    pub(crate) fn set_render_scale(
+        &mut self,
+        device: &Device,
+        samplers: &SamplerCache,
+        scale: f32,
+    ) {
+        if scale != self.render_scale {
+            self.render_scale = scale;
+            self.create_attachments(device, samplers);
+        }
+    }
+
+    /// The depth and ID buffers and the scene, at the render size
+    fn create_attachments(&mut self, device: &Device, samplers: &SamplerCache) {
+        let (width, height) = self.render_size();
+        self.depth_texture = texture::Texture::create_depth_texture_with_size(
+            device,
+            samplers,
+            width,
+            height,
+            "depth_texture",
+        );
+        self.id_texture =
+            texture::Texture::create_id_texture(device, samplers, width, height, "id_texture");
+        self.scene = ((width, height) != (self.config.width, self.config.height)).then(|| {
+            texture::Texture::create_render_target(
+                device,
+                samplers,
+                width,
+                height,
+                self.config.format,
+                "scene_texture",
+            )
+        });
+    }
+
+    /// Roughly what the window's depth and ID buffers, scene, depth pyramid and swapchain take up in memory. How many
+    /// images the swapchain really holds is up to the driver, one per frame of latency plus the one on screen is
+    /// assumed.
+    pub fn size_in_bytes(&self) -> u64 {
+        let surface_texel_size = self.config.format.block_copy_size(None).unwrap_or(4);
+        let swapchain = self.config.width as u64
+            * self.config.height as u64
+            * surface_texel_size as u64
+            * (self.config.desired_maximum_frame_latency as u64 + 1);
+        swapchain
+            + self.depth_texture.size_in_bytes()
+            + self.id_texture.size_in_bytes()
+            + self
+                .scene
+                .as_ref()
+                .map_or(0, texture::Texture::size_in_bytes)
+            + self
+                .depth_pyramid
+                .as_ref()
+                .map_or(0, crate::depth_pyramid::DepthPyramid::size_in_bytes)
+    }
+
+    /// None while the app is suspended
+    pub fn surface(&self) -> Option<&Surface<'static>> {
+        self.surface.as_ref()
+    }
+
+    /// Switches between an opaque surface and one the desktop shows through where the background is transparent
+    pub(crate) fn set_transparent(&mut self, device: &Device, transparent: bool) {
+        let alpha_mode = select_alpha_mode(&self.alpha_modes, transparent);
+        if transparent && alpha_mode == CompositeAlphaMode::Opaque {
+            tracing::warn!(
+                "Window surface doesn't support transparency, drawing a black background instead"
+            );
+        }
+        if alpha_mode != self.config.alpha_mode {
+            self.config.alpha_mode = alpha_mode;
+            self.reconfigure(device);
+        }
+    }
+
+    /// Drops the surface when the app gets suspended. Everything else, including the camera and the depth buffer,
+    /// stays alive so resuming doesn't have to rebuild the scene.
+    pub(crate) fn release_surface(&mut self) {
+        self.surface = None;
+    }
+
+    /// Creates the surface again after the app resumed and configures it with the config it had before
+    pub(crate) fn restore_surface(&mut self, instance: &Instance, device: &Device) {
+        if self.surface.is_some() {
+            return;
+        }
+        let surface = instance
+            .create_surface(self.window.clone())
+            .expect("Failed to create surface!");
+        if !self.minimized {
+            surface.configure(device, &self.config);
+        }
+        self.surface = Some(surface);
+    }
+
+    /// Uploads the camera state and clip planes extracted for this frame to this window's global uniform buffer
+    pub(crate) fn upload_camera(
+        &mut self,
+        uploader: &mut Uploader,
+        device: &Device,
+        camera: CameraUniform,
+        clipping: ClipUniform,
+        audio: AudioUniform,
+    ) {
+        update_global_ubo(
+            &mut self.global_ubo,
+            uploader,
+            device,
+            camera,
+            clipping,
+            audio,
+        );
+    }
+
+    /// Applies the current config to the surface again, e.g. after it reported being lost or outdated
+    pub fn reconfigure(&self, device: &Device) {
+        if self.minimized {
+            return;
+        }
+        if let Some(surface) = &self.surface {
+            surface.configure(device, &self.config);
+        }
+    }
+
+    /// Rebuilds the viewport's surface and GPU objects on a new device, keeping its camera and controller
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn recreate(
+        self,
+        instance: &Instance,
+        device: &Device,
+        samplers: &SamplerCache,
+        adapter: &Adapter,
+        format: TextureFormat,
+        options: &SurfaceOptions,
+    ) -> Self {
+        let Viewport {
+            window,
+            surface,
+            camera,
+            camera_controller,
+            cursor_mode,
+            render_scale,
+            ..
+        } = self;
+        // Some backends only allow a single surface per window at a time
+        drop(surface);
+        let surface = instance
+            .create_surface(window.clone())
+            .expect("Failed to create surface!");
+
+        let mut viewport =
+            Viewport::new(device, samplers, adapter, surface, window, format, options);
+        viewport.camera = camera;
+        viewport.camera_controller = camera_controller;
+        viewport.cursor_mode = cursor_mode;
+        viewport.set_render_scale(device, samplers, render_scale);
+        viewport
+    }
+
+    /// Reconfigures the surface for the new size. A zero size suspends drawing instead, the old surface
+    /// configuration is kept until the window is restored.
+    pub fn resize(&mut self, device: &Device, samplers: &SamplerCache, width: u32, height: u32) {
+        self.minimized = width == 0 || height == 0;
+        if self.minimized {
+            return;
+        }
+
+        self.config.width = width;
+        self.config.height = height;
+        if let Some(surface) = &self.surface {
+            surface.configure(device, &self.config);
+        }
+
+        self.camera.resize_projection(width, height);
+        self.create_attachments(device, samplers);
+    }
+
+    /// The pixel of the depth and ID buffers under the window's pixel, None outside the window
+    fn render_pixel(&self, [x, y]: [u32; 2]) -> Option<[u32; 2]> {
+        let (width, height) = self.render_size();
+        (x < self.config.width && y < self.config.height).then(|| {
+            [
+                (x as u64 * width as u64 / self.config.width as u64) as u32,
+                (y as u64 * height as u64 / self.config.height as u64) as u32,
+            ]
+        })
+    }
+
+    /// Records copying out the depth of every requested pixel still inside the window, the others resolve to None
+    pub(crate) fn record_depth_reads(
+        &mut self,
+        device: &Device,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        for (pixel, promise) in std::mem::take(&mut self.depth_requests) {
+            if let Some(pixel) = self.render_pixel(pixel) {
+                self.depth_reads.copy_texel(
+                    device,
+                    encoder,
+                    &self.depth_texture.texture,
+                    wgpu::TextureAspect::DepthOnly,
+                    pixel,
+                    promise,
+                );
+            }
+        }
+    }
+
+    /// Records copying out the object ID of every requested pixel still inside the window, `drawn` resolves them
+    pub(crate) fn record_picks(
+        &mut self,
+        device: &Device,
+        encoder: &mut wgpu::CommandEncoder,
+        drawn: impl FnOnce() -> Arc<[Pick]>,
+    ) {
+        if self.pick_requests.is_empty() {
+            return;
+        }
+        let drawn = drawn();
+        for (pixel, promise) in std::mem::take(&mut self.pick_requests) {
+            if let Some(pixel) = self.render_pixel(pixel) {
+                self.picks.copy_texel(
+                    device,
+                    encoder,
+                    &self.id_texture.texture,
+                    wgpu::TextureAspect::All,
+                    pixel,
+                    (promise, drawn.clone()),
+                );
+            }
+        }
+    }
+
+    /// Call after submitting the copies of [Viewport::record_depth_reads] and [Viewport::record_picks]
+    pub(crate) fn after_depth_reads_submit(&mut self) {
+        self.depth_reads.after_submit();
+        self.picks.after_submit();
+    }
+
+    /// Whether any capture, recorded frame, depth readback or pick is still waiting on the GPU
+    pub(crate) fn has_pending_readbacks(&self) -> bool {
+        self.depth_reads.is_pending()
+            || self.picks.is_pending()
+            || !self.pending_captures.is_empty()
+            || self
+                .recorder
+                .as_ref()
+                .is_some_and(FrameRecorder::has_pending)
+    }
+
+    /// Writes out every capture and recorded frame whose readback buffer has been mapped in the meantime and resolves
+    /// the depth readbacks and picks that arrived
+    pub(crate) fn finish_captures(&mut self, jobs: &JobSystem) {
+        self.depth_reads.collect(|promise, data| {
+            promise.resolve(bytemuck::pod_read_unaligned(&data[..4]));
+        });
+        // Dropping the promise of a pixel without an object resolves it to None
+        self.picks.collect(|(promise, drawn), data| {
+            let id: u32 = bytemuck::pod_read_unaligned(&data[..4]);
+            if let Some(&pick) = (id as usize)
+                .checked_sub(1)
+                .and_then(|index| drawn.get(index))
+            {
+                promise.resolve(pick);
+            }
+        });
+        if let Some(recorder) = &mut self.recorder {
+            recorder.collect();
+        }
+        self.pending_captures = std::mem::take(&mut self.pending_captures)
+            .into_iter()
+            .filter_map(|capture| capture.try_finish(jobs))
+            .collect();
+    }
+}
+
+/// Premultiplied alpha matches what the main pass writes, the other modes are fallbacks
+fn select_alpha_mode(alpha_modes: &[CompositeAlphaMode], transparent: bool) -> CompositeAlphaMode {
+    let preferred: &[CompositeAlphaMode] = if transparent {
+        &[
+            CompositeAlphaMode::PreMultiplied,
+            CompositeAlphaMode::PostMultiplied,
+            CompositeAlphaMode::Inherit,
+        ]
+    } else {
+        &[CompositeAlphaMode::Opaque]
+    };
+    preferred
+        .iter()
+        .copied()
+        .find(|mode| alpha_modes.contains(mode))
+        .unwrap_or(alpha_modes[0])
+}
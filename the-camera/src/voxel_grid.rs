@@ -0,0 +1,545 @@
+//! Dense voxel grids of color and density, raymarched over the scene. Meant for looking at radiance fields, e.g.
+//! Plenoxels or Instant-NGP snapshots baked into a grid, with the engine's cameras.
+//!
+//! Grids are read from `.vxgr` files of a little endian binary layout, which exporters can write in a few lines:
+//!
+//! | Offset | Type     | Content                                                                         |
+//! |--------|----------|---------------------------------------------------------------------------------|
+//! | 0      | 4 bytes  | `VXGR`                                                                          |
+//! | 4      | u32      | Version, 1                                                                      |
+//! | 8      | 3 × u32  | Voxels along X, Y and Z                                                         |
+//! | 20     | 3 × f32  | Minimum corner of the grid's box                                                |
+//! | 32     | 3 × f32  | Maximum corner                                                                  |
+//! | 44     | f32      | Density of a voxel with a density byte of 255, per unit of the box              |
+//! | 48     | 4 × u8   | RGBA of every voxel, X first, then Y, then Z. A is the density relative to 255. |
+//!
+//! Colors are taken as they are, like the colors of materials. Voxels are filtered linearly and blended over what
+//! the main pass drew, front to back along every pixel's ray up to the first surface in the depth buffer.
+
+use std::{cmp::Ordering, collections::HashMap, io};
+
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3, Vector4};
+use wgpu::{BindGroup, CommandEncoder, Device, Queue, RenderPipeline, TextureFormat, TextureView};
+
+use crate::{
+    camera::camera::CameraUniform,
+    lazy_pass::LazyPass,
+    texture,
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        dynamic_uniform_buffer::DynamicUniformBuffer,
+        resource_cache::SamplerCache,
+    },
+};
+
+const MAGIC: &[u8; 4] = b"VXGR";
+const VERSION: u32 = 1;
+const HEADER_SIZE: usize = 48;
+
+pub struct VoxelGrid {
+    size: [u32; 3],
+    voxels: Vec<[u8; 4]>,
+    /// Box the voxels fill, in the grid's own units
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    /// Where the box is placed in the scene
+    pub transform: Matrix4<f32>,
+    /// Density of a voxel with a density byte of 255, per unit of the box. Higher makes the grid more opaque.
+    pub density_scale: f32,
+    /// Samples taken along a ray per voxel crossed, more is smoother and slower
+    pub samples_per_voxel: f32,
+}
+
+impl VoxelGrid {
+    /// `voxels` are RGBA with the density in A, X first, then Y, then Z
+    pub fn new(
+        size: [u32; 3],
+        min: [f32; 3],
+        max: [f32; 3],
+        density_scale: f32,
+        voxels: Vec<[u8; 4]>,
+    ) -> Self {
+        assert_eq!(
+            voxels.len(),
+            size.iter().map(|&size| size as usize).product::<usize>(),
+            "Voxels don't match the grid's size!"
+        );
+        VoxelGrid {
+            size,
+            voxels,
+            min,
+            max,
+            transform: Matrix4::identity(),
+            density_scale,
+            samples_per_voxel: 2.0,
+        }
+    }
+
+    /// Reads a grid in the layout described in the [module docs](self)
+    pub fn decode(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != MAGIC {
+            return Err(invalid("not a voxel grid"));
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().expect("4 bytes"));
+        let f32_at = |at: usize| f32::from_le_bytes(bytes[at..at + 4].try_into().expect("4 bytes"));
+        let version = u32_at(4);
+        if version != VERSION {
+            return Err(invalid(format!("version {version} isn't supported")));
+        }
+        let size = [u32_at(8), u32_at(12), u32_at(16)];
+        let count = size
+            .iter()
+            .try_fold(1usize, |count, &size| count.checked_mul(size as usize));
+        if size.contains(&0)
+            || count.and_then(|count| count.checked_mul(4)) != Some(bytes.len() - HEADER_SIZE)
+        {
+            return Err(invalid(format!(
+                "{} bytes of voxels don't match a size of {size:?}",
+                bytes.len() - HEADER_SIZE
+            )));
+        }
+        let min = [f32_at(20), f32_at(24), f32_at(28)];
+        let max = [f32_at(32), f32_at(36), f32_at(40)];
+        // NaN corners make empty boxes as well
+        if (0..3).any(|axis| min[axis].partial_cmp(&max[axis]) != Some(Ordering::Less)) {
+            return Err(invalid("the box is empty"));
+        }
+        let voxels = bytemuck::cast_slice(&bytes[HEADER_SIZE..]).to_vec();
+        Ok(VoxelGrid::new(size, min, max, f32_at(44), voxels))
+    }
+
+    /// Voxels along X, Y and Z
+    pub fn size(&self) -> [u32; 3] {
+        self.size
+    }
+
+    /// Axis aligned box around the transformed grid, in world space
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        let mut min = Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+        let mut max = -min;
+        for corner in 0..8 {
+            let local = Vector4::new(
+                if corner & 1 == 0 {
+                    self.min[0]
+                } else {
+                    self.max[0]
+                },
+                if corner & 2 == 0 {
+                    self.min[1]
+                } else {
+                    self.max[1]
+                },
+                if corner & 4 == 0 {
+                    self.min[2]
+                } else {
+                    self.max[2]
+                },
+                1.0,
+            );
+            let world = (self.transform * local).truncate();
+            min = min.zip(world, f32::min);
+            max = max.zip(world, f32::max);
+        }
+        (min.into(), max.into())
+    }
+
+    /// The box's center in world space
+    fn center(&self) -> Vector3<f32> {
+        let (min, max) = self.bounds();
+        (Vector3::from(min) + Vector3::from(max)) / 2.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct VoxelGridHandle(pub(crate) usize);
+
+/// Per grid draw data, bound at group 0
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GridUBOContent {
+    /// Of the view, rays are unprojected from its depth buffer
+    inverse_view_proj: [[f32; 4]; 4],
+    world_to_grid: [[f32; 4]; 4],
+    min: [f32; 3],
+    density_scale: f32,
+    max: [f32; 3],
+    /// Between samples, in units of the box
+    step: f32,
+}
+
+crate::assert_uniform_layout!(GridUBOContent {
+    inverse_view_proj: ALIGN_VEC4,
+    world_to_grid: ALIGN_VEC4,
+    min: ALIGN_VEC4,
+    density_scale: ALIGN_SCALAR,
+    max: ALIGN_VEC4,
+    step: ALIGN_SCALAR,
+});
+
+/// What one window or render target draws of the grids
+pub(crate) struct VoxelGridView {
+    ubo: DynamicUniformBuffer<GridUBOContent>,
+    /// Grids from back to front with the dynamic offset of their uniforms
+    draws: Vec<(usize, u32)>,
+}
+
+/// The voxels of a grid on the GPU
+struct GpuGrid {
+    texture: texture::Texture,
+    bind_group: BindGroup,
+}
+
+/// Raymarches the grids after the main pass, the textures are shared by every view
+pub(crate) struct VoxelGridPass {
+    pipeline: RenderPipeline,
+    view_bind_group_layout: BindGroupLayoutWithDesc,
+    grid_bind_group_layout: BindGroupLayoutWithDesc,
+    /// None for grids too large for the device, which aren't drawn
+    grids: HashMap<usize, Option<GpuGrid>>,
+}
+
+impl VoxelGridPass {
+    /// `format` has to be the engine's swapchain format, grids are drawn into the views' color attachments
+    pub fn new(device: &Device, format: TextureFormat) -> Self {
+        let _span = tracing::debug_span!("create_voxel_grid_pipeline").entered();
+        let view_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_fragment(binding_types::uniform_dynamic(
+                std::mem::size_of::<GridUBOContent>() as u64,
+            ))
+            .next_binding_fragment(wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            })
+            .create(device, "Voxel Grid View Bind Group");
+        let grid_bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_fragment(binding_types::texture3D())
+            .next_binding_fragment(binding_types::sampler(wgpu::SamplerBindingType::Filtering))
+            .create(device, "Voxel Grid Bind Group");
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Voxel Grid Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("voxel_grid.wgsl").into()),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[
+                &view_bind_group_layout.layout,
+                &grid_bind_group_layout.layout,
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("voxel grid"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            // Rays stop at the depth read in the shader, the depth buffer is bound as a texture
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: Default::default(),
+            }),
+            multiview: None,
+            cache: None,
+        });
+
+        VoxelGridPass {
+            pipeline,
+            view_bind_group_layout,
+            grid_bind_group_layout,
+            grids: HashMap::new(),
+        }
+    }
+
+    /// Uploads grids drawn for the first time and sorts the view's grids back to front from its camera
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        samplers: &SamplerCache,
+        grids: &[Option<VoxelGrid>],
+        view: &mut Option<VoxelGridView>,
+        camera: &CameraUniform,
+    ) {
+        if grids.iter().all(Option::is_none) {
+            *view = None;
+            return;
+        }
+        let view = view.get_or_insert_with(|| VoxelGridView {
+            ubo: DynamicUniformBuffer::new(device),
+            draws: Vec::new(),
+        });
+        view.ubo.clear();
+        view.draws.clear();
+
+        let Some(inverse_view_proj) = Matrix4::from(camera.view_proj).invert() else {
+            return;
+        };
+        let eye = Vector4::from(camera.view_position).truncate();
+        let mut sorted: Vec<(usize, &VoxelGrid)> = grids
+            .iter()
+            .enumerate()
+            .filter_map(|(index, grid)| Some((index, grid.as_ref()?)))
+            .collect();
+        sorted.sort_by(|(_, a), (_, b)| {
+            let a = (a.center() - eye).magnitude2();
+            let b = (b.center() - eye).magnitude2();
+            b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+        });
+        for (index, grid) in sorted {
+            let Some(world_to_grid) = grid.transform.invert() else {
+                continue;
+            };
+            let limit = device.limits().max_texture_dimension_3d;
+            let uploaded = self.grids.entry(index).or_insert_with(|| {
+                if grid.size.iter().any(|&size| size > limit) {
+                    tracing::error!(
+                        "Voxel grid of {:?} voxels is larger than the device's 3D textures of {limit} voxels a side, \
+                         it isn't drawn",
+                        grid.size
+                    );
+                    return None;
+                }
+                let texture = texture::Texture::from_rgba_volume(
+                    device,
+                    queue,
+                    samplers,
+                    grid.size,
+                    bytemuck::cast_slice(&grid.voxels),
+                    "Voxel Grid",
+                );
+                let bind_group = BindGroupBuilder::new(&self.grid_bind_group_layout)
+                    .texture(&texture.view)
+                    .sampler(&texture.sampler)
+                    .create(device, "Voxel Grid Bind Group");
+                Some(GpuGrid {
+                    texture,
+                    bind_group,
+                })
+            });
+            if uploaded.is_none() {
+                continue;
+            }
+            let voxel = (0..3)
+                .map(|axis| (grid.max[axis] - grid.min[axis]) / grid.size[axis] as f32)
+                .fold(f32::INFINITY, f32::min);
+            let offset = view.ubo.push(&GridUBOContent {
+                inverse_view_proj: inverse_view_proj.into(),
+                world_to_grid: world_to_grid.into(),
+                min: grid.min,
+                density_scale: grid.density_scale,
+                max: grid.max,
+                step: voxel / grid.samples_per_voxel.max(0.1),
+            });
+            view.draws.push((index, offset));
+        }
+        view.ubo.write(device, queue);
+    }
+
+    /// Records a pass blending what [VoxelGridPass::prepare] sorted for the view over `color`, with the view's depth
+    /// buffer as `depth`
+    pub fn draw(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        view: Option<&VoxelGridView>,
+        color: &TextureView,
+        depth: &TextureView,
+    ) {
+        let Some(view) = view.filter(|view| !view.draws.is_empty()) else {
+            return;
+        };
+        // The depth buffer is recreated with the window, so the bind group can't be kept
+        let view_bind_group = BindGroupBuilder::new(&self.view_bind_group_layout)
+            .resource(view.ubo.binding_resource())
+            .texture(depth)
+            .create(device, "Voxel Grid View Bind Group");
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("voxel grids"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: color,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        for (index, offset) in &view.draws {
+            let Some(Some(grid)) = self.grids.get(index) else {
+                continue;
+            };
+            render_pass.set_bind_group(0, &view_bind_group, &[*offset]);
+            render_pass.set_bind_group(1, &grid.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    /// Frees the texture of a grid that was removed
+    pub fn remove_grid(&mut self, grid: usize) {
+        self.grids.remove(&grid);
+    }
+
+    /// Bytes of the uploaded voxels
+    pub fn size_in_bytes(&self) -> u64 {
+        self.grids
+            .values()
+            .flatten()
+            .map(|grid| grid.texture.size_in_bytes())
+            .sum()
+    }
+}
+
+fn invalid(message: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Voxel grid: {message}"))
+}
+
+/// The engine's voxel grids and the pass blending them over the views, which is created with the first grid
+#[derive(Default)]
+pub(crate) struct VoxelGrids {
+    pass: LazyPass<VoxelGridPass>,
+    /// None where a grid was removed
+    grids: Vec<Option<VoxelGrid>>,
+}
+
+impl VoxelGrids {
+    pub fn add(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        grid: VoxelGrid,
+    ) -> VoxelGridHandle {
+        self.pass
+            .get_or_create(|| Some(VoxelGridPass::new(device, format)));
+        self.grids.push(Some(grid));
+        VoxelGridHandle(self.grids.len() - 1)
+    }
+
+    pub fn get(&self, handle: VoxelGridHandle) -> Option<&VoxelGrid> {
+        self.grids.get(handle.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: VoxelGridHandle) -> Option<&mut VoxelGrid> {
+        self.grids.get_mut(handle.0)?.as_mut()
+    }
+
+    /// Frees the grid's voxels on the GPU
+    pub fn remove(&mut self, handle: VoxelGridHandle) -> Option<VoxelGrid> {
+        let grid = self.grids.get_mut(handle.0)?.take()?;
+        if let Some(pass) = self.pass.get_mut() {
+            pass.remove_grid(handle.0);
+        }
+        Some(grid)
+    }
+
+    /// See [VoxelGridPass::prepare]
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        samplers: &SamplerCache,
+        view: &mut Option<VoxelGridView>,
+        camera: &CameraUniform,
+    ) {
+        if let Some(pass) = self.pass.get_mut() {
+            pass.prepare(device, queue, samplers, &self.grids, view, camera);
+        }
+    }
+
+    /// See [VoxelGridPass::draw]
+    pub fn draw(
+        &self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        view: Option<&VoxelGridView>,
+        color: &TextureView,
+        depth: &TextureView,
+    ) {
+        if let Some(pass) = self.pass.get() {
+            pass.draw(device, encoder, view, color, depth);
+        }
+    }
+
+    pub fn size_in_bytes(&self) -> u64 {
+        self.pass.get().map_or(0, VoxelGridPass::size_in_bytes)
+    }
+
+    /// Creates the pass again on a new device, the voxels are uploaded again when the grids are next drawn
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(&mut self, device: &Device, format: TextureFormat) {
+        self.pass
+            .recreate(|| Some(VoxelGridPass::new(device, format)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(size: [u32; 3], min: [f32; 3], max: [f32; 3], voxels: &[[u8; 4]]) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(VERSION.to_le_bytes());
+        bytes.extend(size.iter().flat_map(|size| size.to_le_bytes()));
+        bytes.extend(
+            min.iter()
+                .chain(&max)
+                .flat_map(|corner| corner.to_le_bytes()),
+        );
+        bytes.extend(2.5f32.to_le_bytes());
+        bytes.extend(voxels.iter().flatten());
+        bytes
+    }
+
+    #[test]
+    fn decodes_grids() {
+        let voxels = [[255, 0, 0, 255], [0, 255, 0, 128]];
+        let grid = VoxelGrid::decode(&file([2, 1, 1], [0.0; 3], [2.0, 1.0, 1.0], &voxels)).unwrap();
+        assert_eq!(grid.size(), [2, 1, 1]);
+        assert_eq!(grid.voxels, voxels);
+        assert_eq!((grid.min, grid.max), ([0.0; 3], [2.0, 1.0, 1.0]));
+        assert_eq!(grid.density_scale, 2.5);
+    }
+
+    #[test]
+    fn rejects_malformed_grids() {
+        let voxels = [[0; 4]; 2];
+        let valid = file([2, 1, 1], [0.0; 3], [1.0; 3], &voxels);
+        assert!(VoxelGrid::decode(&valid[..HEADER_SIZE - 1]).is_err());
+        let mut version = valid.clone();
+        version[4] = 2;
+        assert!(VoxelGrid::decode(&version).is_err());
+        assert!(VoxelGrid::decode(&valid[..valid.len() - 1]).is_err());
+        assert!(VoxelGrid::decode(&file([2, 0, 1], [0.0; 3], [1.0; 3], &[])).is_err());
+        // Sizes whose voxel count overflows
+        assert!(VoxelGrid::decode(&file([u32::MAX; 3], [0.0; 3], [1.0; 3], &voxels)).is_err());
+        let err = VoxelGrid::decode(&file([2, 1, 1], [0.0; 3], [1.0, f32::NAN, 1.0], &voxels));
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn bounds_follow_the_transform() {
+        let mut grid = VoxelGrid::new([1; 3], [-1.0; 3], [1.0; 3], 1.0, vec![[0; 4]]);
+        grid.transform =
+            Matrix4::from_translation(Vector3::new(5.0, 0.0, 0.0)) * Matrix4::from_scale(2.0);
+        assert_eq!(grid.bounds(), ([3.0, -2.0, -2.0], [7.0, 2.0, 2.0]));
+    }
+}
@@ -0,0 +1,90 @@
+// One grid seen from one view, see voxel_grid.rs
+struct Grid {
+    inverse_view_proj: mat4x4<f32>,
+    world_to_grid: mat4x4<f32>,
+    min: vec3<f32>,
+    density_scale: f32,
+    max: vec3<f32>,
+    // Between samples, in units of the box
+    step: f32,
+}
+@group(0) @binding(0)
+var<uniform> grid: Grid;
+// Of the view drawn into, written by its main pass. Bound as a float texture, GL can't load from depth textures.
+@group(0) @binding(1)
+var depth_texture: texture_2d<f32>;
+
+@group(1) @binding(0)
+var voxels: texture_3d<f32>;
+@group(1) @binding(1)
+var voxel_sampler: sampler;
+
+// Rays through thick grids stop after this many samples
+const MAX_SAMPLES: u32 = 1024u;
+// Rays stop once less than this much of what's behind shows through
+const OPAQUE: f32 = 0.01;
+// Rays without a surface to stop at end 1 / FAR times as far from the camera as the near plane
+const FAR: f32 = 0.0001;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // A triangle covering the whole view
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// A world position in the grid's space
+fn to_grid(world: vec4<f32>) -> vec3<f32> {
+    let local = grid.world_to_grid * vec4<f32>(world.xyz / world.w, 1.0);
+    return local.xyz / local.w;
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(position.xy);
+    let size = vec2<f32>(textureDimensions(depth_texture));
+    let ndc = vec2<f32>(position.x / size.x * 2.0 - 1.0, 1.0 - position.y / size.y * 2.0);
+    // Unprojected depths move along the pixel's ray, from the near plane to the first surface drawn
+    let near_plane = grid.inverse_view_proj * vec4<f32>(ndc, 0.0, 1.0);
+    let along = grid.inverse_view_proj * vec4<f32>(0.0, 0.0, 1.0, 0.0);
+    var depth = textureLoad(depth_texture, pixel, 0).r;
+    // Where nothing was drawn the depth may unproject past infinity, the ray stops far out then
+    let far_w = near_plane.w * FAR;
+    if near_plane.w + depth * along.w < far_w {
+        depth = (far_w - near_plane.w) / along.w;
+    }
+    let start = to_grid(near_plane);
+    let ray = to_grid(near_plane + depth * along) - start;
+
+    // Where the ray enters and leaves the box, as fractions of it
+    let inverse = 1.0 / ray;
+    let a = (grid.min - start) * inverse;
+    let b = (grid.max - start) * inverse;
+    let near = min(a, b);
+    let far = max(a, b);
+    let enter = max(max(max(near.x, near.y), near.z), 0.0);
+    let leave = min(min(min(far.x, far.y), far.z), 1.0);
+    if enter >= leave {
+        discard;
+    }
+
+    let ray_length = length(ray);
+    let step = grid.step / ray_length;
+    var color = vec3<f32>(0.0);
+    var transmittance = 1.0;
+    var t = enter + step * 0.5;
+    for (var index = 0u; index < MAX_SAMPLES && t < leave; index++) {
+        let uvw = (start + ray * t - grid.min) / (grid.max - grid.min);
+        let voxel = textureSampleLevel(voxels, voxel_sampler, uvw, 0.0);
+        // The last step may be cut short by the box or a surface
+        let distance = (min(t + step * 0.5, leave) - max(t - step * 0.5, enter)) * ray_length;
+        let alpha = 1.0 - exp(-voxel.a * grid.density_scale * distance);
+        color += transmittance * alpha * voxel.rgb;
+        transmittance *= 1.0 - alpha;
+        if transmittance < OPAQUE {
+            break;
+        }
+        t += step;
+    }
+    return vec4<f32>(color, 1.0 - transmittance);
+}
@@ -0,0 +1,527 @@
+//! Animated water surfaces, e.g. a sea around a model or a pond in a scene, moved by Gerstner waves.
+//!
+//! A water surface is a flat grid the vertex shader displaces by a sum of [GerstnerWave]s, which move the surface
+//! in circles so crests sharpen and troughs flatten like on real water. The normals are rebuilt from the derivatives
+//! of the same waves for every pixel, so small waves still catch the light where the grid is too coarse for them.
+//!
+//! The fragment shader mixes the water's own color with what the surface reflects by Schlick's fresnel term, the
+//! reflection only showing at grazing angles. There are no planar or screen space reflections of the scene, the
+//! water reflects the [Background] instead: the sky panorama of a skybox, the colors of a gradient or the plain
+//! color. A highlight of [Water::sun_direction], or of the atmosphere's sun, goes on top.
+//!
+//! The waves need nothing but a vertex shader, so water is drawn on every device. It's drawn in the main pass like
+//! any other opaque object, though it can't be picked.
+
+use std::{collections::HashMap, f32::consts::TAU, time::Duration};
+
+use wgpu::{BindGroup, Device, Queue, RenderPass, RenderPipeline, TextureFormat};
+
+use crate::{
+    background::Background,
+    global_bindings::GlobalBindings,
+    lazy_pass::LazyPass,
+    material_bindings::MaterialBindings,
+    profiler::RenderStats,
+    scene::TextureHandle,
+    texture::{self, GpuTexture},
+    wgpu_utils::{
+        binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+        binding_types,
+        uniform_buffer::UniformBuffer,
+    },
+};
+
+/// Waves of a surface that are drawn, the rest are ignored
+pub const MAX_WAVES: usize = 8;
+
+/// Gravity the waves' speeds follow from, in meters per second squared
+const GRAVITY: f32 = 9.81;
+
+/// One wave running across a [Water] surface, in world units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GerstnerWave {
+    /// Where the wave runs to along the X and Z axes, doesn't have to be normalized
+    pub direction: [f32; 2],
+    /// Distance between two crests. Longer waves run faster, like on deep water.
+    pub wavelength: f32,
+    /// Height of the crests above the surface at rest
+    pub amplitude: f32,
+    /// From 0 for round waves to 1 for sharp crests. The steepness is split between the waves, so their crests
+    /// never loop over however many there are.
+    pub steepness: f32,
+}
+
+impl GerstnerWave {
+    pub fn new(direction: [f32; 2], wavelength: f32, amplitude: f32) -> Self {
+        GerstnerWave {
+            direction,
+            wavelength,
+            amplitude,
+            steepness: 0.5,
+        }
+    }
+
+    pub fn with_steepness(mut self, steepness: f32) -> Self {
+        self.steepness = steepness;
+        self
+    }
+}
+
+/// A water surface drawn into every window and render target, added with
+/// [crate::render_engine::RenderEngine::add_water]
+#[derive(Debug, Clone)]
+pub struct Water {
+    /// Middle of the surface at rest, which lies in the XZ plane at this height
+    pub center: [f32; 3],
+    /// Along the X and Z axes
+    pub size: [f32; 2],
+    /// Grid cells along each side, more show the shorter waves in the silhouette too
+    pub resolution: u32,
+    pub waves: Vec<GerstnerWave>,
+    /// Looking straight down into the water
+    pub deep_color: [f32; 3],
+    /// Shining through the crests
+    pub shallow_color: [f32; 3],
+    /// Towards the sun the highlight reflects, doesn't have to be normalized. The engine's
+    /// [crate::atmosphere::Atmosphere] has its own sun, which takes over while there is one.
+    pub sun_direction: [f32; 3],
+    /// Stops the waves, the surface is still drawn where they are
+    pub paused: bool,
+    pub visible: bool,
+}
+
+impl Water {
+    /// A surface `size` wide around `center`, with a few waves of a light breeze from along the X axis
+    pub fn new(center: [f32; 3], size: [f32; 2]) -> Self {
+        Water {
+            center,
+            size,
+            resolution: 256,
+            waves: vec![
+                GerstnerWave::new([1.0, 0.2], 6.0, 0.12),
+                GerstnerWave::new([0.8, -0.6], 3.1, 0.06),
+                GerstnerWave::new([0.3, 1.0], 1.7, 0.03),
+                GerstnerWave::new([-0.5, 0.9], 0.9, 0.012),
+            ],
+            deep_color: [0.01, 0.08, 0.12],
+            shallow_color: [0.05, 0.3, 0.3],
+            sun_direction: [0.3, 1.0, 0.4],
+            paused: false,
+            visible: true,
+        }
+    }
+
+    pub fn with_resolution(mut self, resolution: u32) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    /// Replaces the waves of the light breeze
+    pub fn with_waves(mut self, waves: Vec<GerstnerWave>) -> Self {
+        self.waves = waves;
+        self
+    }
+
+    pub fn with_colors(mut self, deep_color: [f32; 3], shallow_color: [f32; 3]) -> Self {
+        self.deep_color = deep_color;
+        self.shallow_color = shallow_color;
+        self
+    }
+
+    pub fn with_sun_direction(mut self, sun_direction: [f32; 3]) -> Self {
+        self.sun_direction = sun_direction;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WaterHandle(pub(crate) usize);
+
+/// Per surface draw data, bound at group 1
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct WaterUBOContent {
+    center: [f32; 3],
+    /// Seconds the waves have been running for
+    time: f32,
+    size: [f32; 2],
+    resolution: u32,
+    wave_count: u32,
+    deep_color: [f32; 4],
+    shallow_color: [f32; 4],
+    sun_direction: [f32; 4],
+    /// The background colors of [Background::Gradient], both the same for the other kinds
+    sky_top: [f32; 4],
+    sky_bottom: [f32; 4],
+    /// 1 where the water reflects the skybox bound at group 2
+    skybox: u32,
+    _padding: [u32; 3],
+    /// Direction along X and Z, wave number and amplitude
+    waves: [[f32; 4]; MAX_WAVES],
+    /// Angular frequency and steepness divided up, the rest is padding
+    wave_motions: [[f32; 4]; MAX_WAVES],
+}
+
+crate::assert_uniform_layout!(WaterUBOContent {
+    center: ALIGN_VEC4,
+    time: ALIGN_SCALAR,
+    size: ALIGN_VEC2,
+    resolution: ALIGN_SCALAR,
+    wave_count: ALIGN_SCALAR,
+    deep_color: ALIGN_VEC4,
+    shallow_color: ALIGN_VEC4,
+    sun_direction: ALIGN_VEC4,
+    sky_top: ALIGN_VEC4,
+    sky_bottom: ALIGN_VEC4,
+    skybox: ALIGN_SCALAR,
+    waves: ALIGN_VEC4,
+    wave_motions: ALIGN_VEC4,
+});
+
+struct GpuWater {
+    ubo: UniformBuffer<WaterUBOContent>,
+    bind_group: BindGroup,
+    /// Seconds the waves have been running for, which only move on while the surface isn't paused
+    time: Duration,
+    resolution: u32,
+    visible: bool,
+}
+
+/// Draws every water surface in the main pass, shared by every view
+pub(crate) struct WaterPass {
+    bind_group_layout: BindGroupLayoutWithDesc,
+    pipeline: RenderPipeline,
+    waters: HashMap<usize, GpuWater>,
+    /// The skybox the surfaces reflect, None for the other backgrounds
+    skybox: Option<TextureHandle>,
+}
+
+impl WaterPass {
+    /// `format` has to be the engine's swapchain format, since the water is drawn in the main pass
+    pub fn new(
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        material_bindings: &MaterialBindings,
+    ) -> Self {
+        let _span = tracing::debug_span!("create_water_pipeline").entered();
+        let bind_group_layout = BindGroupLayoutBuilder::new()
+            .next_binding_rendering(binding_types::uniform())
+            .create(device, "Water Bind Group");
+        let pipeline = create_pipeline(
+            device,
+            format,
+            global_bindings,
+            &bind_group_layout,
+            material_bindings,
+        );
+        WaterPass {
+            bind_group_layout,
+            pipeline,
+            waters: HashMap::new(),
+            skybox: None,
+        }
+    }
+
+    /// Moves the waves of the surfaces that aren't paused on by `delta_time`, uploads whatever changed about them and
+    /// frees the GPU data of removed ones. `sun_direction` of the atmosphere overrides those of the surfaces.
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        waters: &[Option<Water>],
+        background: Background,
+        sun_direction: Option<[f32; 3]>,
+        textures: &[GpuTexture],
+        delta_time: Duration,
+    ) {
+        self.waters
+            .retain(|&index, _| matches!(waters.get(index), Some(Some(_))));
+        let (sky_top, sky_bottom, skybox) = match background {
+            Background::Color(color) => (color, color, None),
+            Background::Gradient { top, bottom } => (top, bottom, None),
+            // A skybox whose texture doesn't exist is left out of the background, so it's reflected as black
+            Background::Skybox(texture) => (
+                [0.0, 0.0, 0.0, 1.0],
+                [0.0, 0.0, 0.0, 1.0],
+                Some(texture).filter(|&TextureHandle(texture)| texture < textures.len()),
+            ),
+            // Reflects the black the surface falls back to where it can't be composited
+            Background::Transparent => ([0.0, 0.0, 0.0, 1.0], [0.0, 0.0, 0.0, 1.0], None),
+        };
+        self.skybox = skybox;
+
+        for (index, water) in waters.iter().enumerate() {
+            let Some(water) = water else {
+                continue;
+            };
+            let gpu_water = self.waters.entry(index).or_insert_with(|| {
+                let ubo = UniformBuffer::new(device);
+                let bind_group = BindGroupBuilder::new(&self.bind_group_layout)
+                    .resource(ubo.binding_resource())
+                    .create(device, "Water Bind Group");
+                GpuWater {
+                    ubo,
+                    bind_group,
+                    time: Duration::ZERO,
+                    resolution: 0,
+                    visible: false,
+                }
+            });
+            if !water.paused {
+                gpu_water.time += delta_time;
+            }
+            gpu_water.resolution = water.resolution.max(1);
+            gpu_water.visible = water.visible;
+
+            let mut waves = [[0.0; 4]; MAX_WAVES];
+            let mut wave_motions = [[0.0; 4]; MAX_WAVES];
+            let drawn = &water.waves[..water.waves.len().min(MAX_WAVES)];
+            for (wave, (data, motion)) in drawn
+                .iter()
+                .zip(waves.iter_mut().zip(wave_motions.iter_mut()))
+            {
+                let [x, z] = wave.direction;
+                let length = (x * x + z * z).sqrt().max(1e-20);
+                let wave_number = TAU / wave.wavelength.max(1e-6);
+                *data = [x / length, z / length, wave_number, wave.amplitude];
+                // Deep water dispersion, and the steepness of GPU Gems 1, chapter 1, which keeps the crests from
+                // looping over when the waves add up
+                let frequency = (GRAVITY * wave_number).sqrt();
+                let steepness = wave.steepness.clamp(0.0, 1.0)
+                    / (wave_number * wave.amplitude.abs() * drawn.len() as f32).max(1e-20);
+                *motion = [frequency, steepness, 0.0, 0.0];
+            }
+            let [r, g, b] = water.deep_color;
+            let deep_color = [r, g, b, 1.0];
+            let [r, g, b] = water.shallow_color;
+            let shallow_color = [r, g, b, 1.0];
+            let [x, y, z] = sun_direction.unwrap_or(water.sun_direction);
+            let sun_direction = [x, y, z, 0.0];
+            gpu_water.ubo.update_content(
+                queue,
+                WaterUBOContent {
+                    center: water.center,
+                    time: gpu_water.time.as_secs_f32(),
+                    size: water.size,
+                    resolution: gpu_water.resolution,
+                    wave_count: drawn.len() as u32,
+                    deep_color,
+                    shallow_color,
+                    sun_direction,
+                    sky_top,
+                    sky_bottom,
+                    skybox: skybox.is_some() as u32,
+                    _padding: [0; 3],
+                    waves,
+                    wave_motions,
+                },
+            );
+        }
+    }
+
+    /// Draws the visible surfaces, in the main pass. `textures` hold the skybox they reflect.
+    pub fn draw(
+        &self,
+        render_pass: &mut RenderPass,
+        globals: &BindGroup,
+        textures: &[GpuTexture],
+        material_bindings: &MaterialBindings,
+        stats: &mut RenderStats,
+    ) {
+        if !self.waters.values().any(|water| water.visible) {
+            return;
+        }
+        let sky = self
+            .skybox
+            .and_then(|TextureHandle(texture)| textures.get(texture))
+            .map_or(material_bindings.white_bind_group(), |texture| {
+                &texture.bind_group
+            });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, globals, &[]);
+        render_pass.set_bind_group(2, sky, &[]);
+        stats.pipeline_switches += 1;
+        stats.bind_group_switches += 2;
+        for water in self.waters.values().filter(|water| water.visible) {
+            // Two triangles for every grid cell
+            let vertices = water.resolution * water.resolution * 6;
+            render_pass.set_bind_group(1, &water.bind_group, &[]);
+            render_pass.draw(0..vertices, 0..1);
+            stats.bind_group_switches += 1;
+            stats.draw(vertices, 1);
+        }
+    }
+
+    /// Whether any surface's waves move on, so it has to be drawn again every frame
+    pub fn is_moving(&self, waters: &[Option<Water>]) -> bool {
+        waters
+            .iter()
+            .flatten()
+            .any(|water| water.visible && !water.paused && !water.waves.is_empty())
+    }
+}
+
+/// The grid is made up from the vertex indices, without any buffers
+fn create_pipeline(
+    device: &Device,
+    format: TextureFormat,
+    global_bindings: &GlobalBindings,
+    bind_group_layout: &BindGroupLayoutWithDesc,
+    material_bindings: &MaterialBindings,
+) -> RenderPipeline {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Water Shader"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("water.wgsl").into()),
+    });
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("Water Pipeline Layout"),
+        bind_group_layouts: &[
+            global_bindings.bind_group_layouts(),
+            &bind_group_layout.layout,
+            material_bindings.bind_group_layouts(),
+        ],
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Water Pipeline"),
+        layout: Some(&pipeline_layout),
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: Some("vs_main"),
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            // Seen from below too, e.g. with the camera under the waves
+            cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+            unclipped_depth: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: Some("fs_main"),
+            targets: &[
+                Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+                Some(wgpu::ColorTargetState {
+                    format: texture::Texture::ID_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                }),
+            ],
+            compilation_options: Default::default(),
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
+
+/// The engine's water surfaces and the pass drawing them, which is created with the first surface
+#[derive(Default)]
+pub(crate) struct Waters {
+    pass: LazyPass<WaterPass>,
+    /// None where a water surface was removed
+    waters: Vec<Option<Water>>,
+}
+
+impl Waters {
+    pub fn add(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        material_bindings: &MaterialBindings,
+        water: Water,
+    ) -> WaterHandle {
+        self.pass.get_or_create(|| {
+            Some(WaterPass::new(
+                device,
+                format,
+                global_bindings,
+                material_bindings,
+            ))
+        });
+        self.waters.push(Some(water));
+        WaterHandle(self.waters.len() - 1)
+    }
+
+    pub fn get(&self, handle: WaterHandle) -> Option<&Water> {
+        self.waters.get(handle.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, handle: WaterHandle) -> Option<&mut Water> {
+        self.waters.get_mut(handle.0)?.as_mut()
+    }
+
+    /// The surface is freed on the GPU by the next prepare
+    pub fn remove(&mut self, handle: WaterHandle) -> Option<Water> {
+        self.waters.get_mut(handle.0)?.take()
+    }
+
+    /// See [WaterPass::prepare], true while any surface's waves move on
+    #[allow(clippy::too_many_arguments)]
+    pub fn prepare(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        background: Background,
+        sun_direction: Option<[f32; 3]>,
+        textures: &[GpuTexture],
+        delta_time: Duration,
+    ) -> bool {
+        let Some(pass) = self.pass.get_mut() else {
+            return false;
+        };
+        pass.prepare(
+            device,
+            queue,
+            &self.waters,
+            background,
+            sun_direction,
+            textures,
+            delta_time,
+        );
+        pass.is_moving(&self.waters)
+    }
+
+    /// Draws the surfaces in the main pass, None before the first one
+    pub fn pass(&self) -> Option<&WaterPass> {
+        self.pass.get()
+    }
+
+    /// Creates the pass again on a new device, the waves start over from where they were at rest
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn recreate(
+        &mut self,
+        device: &Device,
+        format: TextureFormat,
+        global_bindings: &GlobalBindings,
+        material_bindings: &MaterialBindings,
+    ) {
+        self.pass.recreate(|| {
+            Some(WaterPass::new(
+                device,
+                format,
+                global_bindings,
+                material_bindings,
+            ))
+        });
+    }
+}
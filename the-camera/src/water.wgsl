@@ -0,0 +1,167 @@
+// See clipping.rs, the same as in shader.wgsl
+struct Clipping {
+    planes: array<vec4<f32>, 4>,
+    cap_color: vec4<f32>,
+    count: u32,
+    caps: u32,
+}
+
+struct Audio {
+    bands: array<vec4<f32>, 4>,
+    level: f32,
+}
+
+struct Camera {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+    clipping: Clipping,
+    audio: Audio,
+}
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+const MAX_WAVES: u32 = 8u;
+const PI: f32 = 3.14159265;
+
+// One surface, see water.rs
+struct Water {
+    center: vec3<f32>,
+    time: f32,
+    size: vec2<f32>,
+    resolution: u32,
+    wave_count: u32,
+    deep_color: vec4<f32>,
+    shallow_color: vec4<f32>,
+    sun_direction: vec4<f32>,
+    sky_top: vec4<f32>,
+    sky_bottom: vec4<f32>,
+    skybox: u32,
+    // Direction along X and Z, wave number and amplitude
+    waves: array<vec4<f32>, MAX_WAVES>,
+    // Angular frequency and steepness
+    wave_motions: array<vec4<f32>, MAX_WAVES>,
+}
+@group(1) @binding(0)
+var<uniform> water: Water;
+
+// The skybox's panorama, or a white texel for the other backgrounds
+@group(2) @binding(0)
+var t_sky: texture_2d<f32>;
+@group(2) @binding(1)
+var s_sky: sampler;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    // Where the vertex is at rest, which the waves are evaluated at again for the normals
+    @location(1) rest: vec2<f32>,
+    // From -1 in the troughs to 1 on the crests
+    @location(2) crest: f32,
+};
+
+// Two triangles for every cell of the grid
+const CORNERS = array<vec2<u32>, 6>(
+    vec2<u32>(0u, 0u),
+    vec2<u32>(1u, 0u),
+    vec2<u32>(1u, 1u),
+    vec2<u32>(0u, 0u),
+    vec2<u32>(1u, 1u),
+    vec2<u32>(0u, 1u),
+);
+
+// The phase of a wave at a point of the surface at rest
+fn phase(wave: vec4<f32>, motion: vec4<f32>, rest: vec2<f32>) -> f32 {
+    return wave.z * dot(wave.xy, rest) - motion.x * water.time;
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let cell = vec2<u32>((index / 6u) % water.resolution, index / 6u / water.resolution);
+    let grid = vec2<f32>(cell + CORNERS[index % 6u]) / f32(water.resolution);
+    let rest = water.center.xz + (grid - 0.5) * water.size;
+
+    // Every wave moves the surface in circles, forwards on the crests and back in the troughs
+    var offset = vec3<f32>(0.0);
+    var height = 0.0;
+    for (var wave_index = 0u; wave_index < water.wave_count; wave_index++) {
+        let wave = water.waves[wave_index];
+        let motion = water.wave_motions[wave_index];
+        let f = phase(wave, motion, rest);
+        let sway = motion.y * wave.w * cos(f);
+        offset += vec3<f32>(wave.x * sway, wave.w * sin(f), wave.y * sway);
+        height += abs(wave.w);
+    }
+
+    let world_position = vec3<f32>(rest.x, water.center.y, rest.y) + offset;
+    var out: VertexOutput;
+    out.clip_position = camera.view_proj * vec4<f32>(world_position, 1.0);
+    out.world_position = world_position;
+    out.rest = rest;
+    out.crest = offset.y / max(height, 1e-20);
+    return out;
+}
+
+// The derivatives of the waves, from GPU Gems 1, chapter 1
+fn normal_at(rest: vec2<f32>) -> vec3<f32> {
+    var normal = vec3<f32>(0.0, 1.0, 0.0);
+    for (var wave_index = 0u; wave_index < water.wave_count; wave_index++) {
+        let wave = water.waves[wave_index];
+        let motion = water.wave_motions[wave_index];
+        let f = phase(wave, motion, rest);
+        let slope = wave.z * wave.w;
+        normal -= vec3<f32>(wave.x * slope * cos(f), motion.y * slope * sin(f), wave.y * slope * cos(f));
+    }
+    return normalize(normal);
+}
+
+// What the surface reflects along `direction`, which points up
+fn sky(direction: vec3<f32>) -> vec3<f32> {
+    if water.skybox != 0u {
+        // The same as the skybox in background.wgsl
+        let uv = vec2<f32>(
+            atan2(direction.z, direction.x) / (2.0 * PI) + 0.5,
+            acos(clamp(direction.y, -1.0, 1.0)) / PI,
+        );
+        return textureSampleLevel(t_sky, s_sky, uv, 0.0).rgb;
+    }
+    return mix(water.sky_bottom.rgb, water.sky_top.rgb, direction.y);
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    // Water can't be picked
+    @location(1) id: u32,
+};
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    for (var index = 0u; index < camera.clipping.count; index++) {
+        let plane = camera.clipping.planes[index];
+        if dot(plane.xyz, in.world_position) < plane.w {
+            discard;
+        }
+    }
+
+    let to_camera = normalize(camera.view_pos.xyz - in.world_position);
+    var normal = normal_at(in.rest);
+    // Seen from below
+    if dot(normal, to_camera) < 0.0 {
+        normal = -normal;
+    }
+    // Schlick's approximation for water, which reflects 2% looking straight down
+    let facing = clamp(dot(normal, to_camera), 0.0, 1.0);
+    let fresnel = 0.02 + 0.98 * pow(1.0 - facing, 5.0);
+    var reflected = reflect(-to_camera, normal);
+    // Waves facing away reflect other waves rather than what's under the surface, the sky stands in for them
+    reflected.y = abs(reflected.y);
+
+    let sun = normalize(water.sun_direction.xyz);
+    let body = mix(water.deep_color.rgb, water.shallow_color.rgb, clamp(in.crest * 0.5 + 0.5, 0.0, 1.0))
+        * (0.4 + 0.6 * max(dot(normal, sun), 0.0));
+    let highlight = pow(max(dot(reflected, sun), 0.0), 400.0) * 4.0;
+
+    var out: FragmentOutput;
+    out.color = vec4<f32>(mix(body, sky(reflected), fresnel) + highlight, 1.0);
+    out.id = 0u;
+    return out;
+}
@@ -2,6 +2,50 @@
 pub struct BindGroupLayoutWithDesc {
     pub layout: wgpu::BindGroupLayout,
     pub entries: Vec<wgpu::BindGroupLayoutEntry>,
+    /// What each entry is for, shown when a [BindGroupBuilder] is given the wrong kind of resource for it
+    pub names: Vec<&'static str>,
+}
+
+impl BindGroupLayoutWithDesc {
+    fn slot_name(&self, index: usize) -> String {
+        match self.names.get(index) {
+            Some(name) if !name.is_empty() => {
+                format!("{name} (binding {})", self.entries[index].binding)
+            }
+            _ => format!("binding {}", self.entries[index].binding),
+        }
+    }
+}
+
+/// Panics in debug builds, logs in release builds where wgpu's own validation reports it again less clearly
+fn binding_error(message: String) {
+    if cfg!(debug_assertions) {
+        panic!("{message}");
+    }
+    tracing::error!("{message}");
+}
+
+/// Which [wgpu::BindingResource] a layout entry takes, for messages
+fn expected_resource(ty: &wgpu::BindingType) -> &'static str {
+    match ty {
+        wgpu::BindingType::Buffer { .. } => "buffer",
+        wgpu::BindingType::Sampler(_) => "sampler",
+        wgpu::BindingType::Texture { .. } | wgpu::BindingType::StorageTexture { .. } => {
+            "texture view"
+        }
+        _ => "other",
+    }
+}
+
+fn resource_kind(resource: &wgpu::BindingResource) -> &'static str {
+    match resource {
+        wgpu::BindingResource::Buffer(_) | wgpu::BindingResource::BufferArray(_) => "buffer",
+        wgpu::BindingResource::Sampler(_) | wgpu::BindingResource::SamplerArray(_) => "sampler",
+        wgpu::BindingResource::TextureView(_) | wgpu::BindingResource::TextureViewArray(_) => {
+            "texture view"
+        }
+        _ => "other",
+    }
 }
 
 /// Tool to create bind group layouts
@@ -10,6 +54,7 @@ pub struct BindGroupLayoutBuilder {
     /// we will also need to increment the bind index with each layout we add. We would find the binding index/group here in the
     /// shader: " **@group(0) @binding(0)** "
     entries: Vec<wgpu::BindGroupLayoutEntry>,
+    names: Vec<&'static str>,
     next_binding_index: u32,
 }
 
@@ -18,6 +63,7 @@ impl BindGroupLayoutBuilder {
     pub fn new() -> Self {
         BindGroupLayoutBuilder {
             entries: Vec::new(),
+            names: Vec::new(),
             next_binding_index: 0,
         }
     }
@@ -27,6 +73,15 @@ impl BindGroupLayoutBuilder {
     pub fn add_binding(mut self, binding: wgpu::BindGroupLayoutEntry) -> Self {
         self.next_binding_index += 1;
         self.entries.push(binding);
+        self.names.push("");
+        self
+    }
+
+    /// Names the binding added last, e.g. `.next_binding_vertex(ty).named("vertices")`, for binding errors
+    pub fn named(mut self, name: &'static str) -> Self {
+        if let Some(last) = self.names.last_mut() {
+            *last = name;
+        }
         self
     }
 
@@ -77,14 +132,54 @@ impl BindGroupLayoutBuilder {
     }
 
     /// Creates a bind group layout with a description/label passed in for debugging and identification
+    ///
+    /// Entries the shader stages they're visible to can't take are reported with their names first, see
+    /// [BindGroupLayoutBuilder::named].
     pub fn create(self, device: &wgpu::Device, label: &str) -> BindGroupLayoutWithDesc {
-        BindGroupLayoutWithDesc {
+        let layout = BindGroupLayoutWithDesc {
             layout: device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &self.entries,
                 label: Some(label),
             }),
             entries: self.entries,
+            names: self.names,
+        };
+        for index in 0..layout.entries.len() {
+            if let Some(problem) = stage_problem(device, &layout.entries[index]) {
+                binding_error(format!("{label}: {} {problem}!", layout.slot_name(index)));
+            }
         }
+        layout
+    }
+}
+
+/// Why `entry` can't be visible to its shader stages on `device`, if it can't
+fn stage_problem(
+    device: &wgpu::Device,
+    entry: &wgpu::BindGroupLayoutEntry,
+) -> Option<&'static str> {
+    if entry.visibility.is_empty() {
+        return Some("isn't visible to any shader stage");
+    }
+    let vertex = entry.visibility.contains(wgpu::ShaderStages::VERTEX);
+    match entry.ty {
+        // WebGPU doesn't let vertex shaders write anything but their outputs
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: false },
+            ..
+        } if vertex => Some("is a writable storage buffer, which vertex shaders can't have"),
+        wgpu::BindingType::StorageTexture { access, .. }
+            if vertex && access != wgpu::StorageTextureAccess::ReadOnly =>
+        {
+            Some("is a writable storage texture, which vertex shaders can't have")
+        }
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { .. },
+            ..
+        } if device.limits().max_storage_buffers_per_shader_stage == 0 => {
+            Some("is a storage buffer, which the device doesn't support, e.g. WebGL2")
+        }
+        _ => None,
     }
 }
 
@@ -104,11 +199,26 @@ impl<'a> BindGroupBuilder<'a> {
         }
     }
 
+    /// Binds the next entry of the layout, checking `resource` is the kind of resource it takes
     // Uses same binding index as binding group layout at the same ordering
     pub fn resource(mut self, resource: wgpu::BindingResource<'a>) -> Self {
-        // assert_eq!(self.entries.len(), self.layout_with_desc.entries.len());
+        let index = self.entries.len();
+        let Some(entry) = self.layout_with_desc.entries.get(index) else {
+            binding_error(format!(
+                "Bound {} resources, the layout only has {} entries!",
+                index + 1,
+                self.layout_with_desc.entries.len()
+            ));
+            return self;
+        };
+        if let Some(problem) = resource_problem(entry, &resource) {
+            binding_error(format!(
+                "{} {problem}!",
+                self.layout_with_desc.slot_name(index)
+            ));
+        }
         self.entries.push(wgpu::BindGroupEntry {
-            binding: self.layout_with_desc.entries[self.entries.len()].binding,
+            binding: entry.binding,
             resource,
         });
         self
@@ -125,7 +235,13 @@ impl<'a> BindGroupBuilder<'a> {
 
     /// Creates a Bind group with the given label and layouts+entries stored by the Builder
     pub fn create(&self, device: &wgpu::Device, label: &str) -> wgpu::BindGroup {
-        assert_eq!(self.entries.len(), self.layout_with_desc.entries.len());
+        let layout = self.layout_with_desc;
+        if self.entries.len() < layout.entries.len() {
+            let missing: Vec<_> = (self.entries.len()..layout.entries.len())
+                .map(|index| layout.slot_name(index))
+                .collect();
+            binding_error(format!("{label}: nothing bound to {}!", missing.join(", ")));
+        }
         let descriptor = wgpu::BindGroupDescriptor {
             layout: &self.layout_with_desc.layout,
             entries: &self.entries,
@@ -134,3 +250,45 @@ impl<'a> BindGroupBuilder<'a> {
         device.create_bind_group(&descriptor)
     }
 }
+
+/// Why `resource` can't be bound to `entry`, if it can't
+fn resource_problem(
+    entry: &wgpu::BindGroupLayoutEntry,
+    resource: &wgpu::BindingResource,
+) -> Option<String> {
+    let expected = expected_resource(&entry.ty);
+    let given = resource_kind(resource);
+    if expected != given {
+        return Some(format!("takes a {expected}, was given a {given}"));
+    }
+    let (
+        wgpu::BindingType::Buffer {
+            ty,
+            min_binding_size,
+            ..
+        },
+        wgpu::BindingResource::Buffer(binding),
+    ) = (entry.ty, resource)
+    else {
+        return None;
+    };
+
+    let (usage, kind) = match ty {
+        wgpu::BufferBindingType::Uniform => (wgpu::BufferUsages::UNIFORM, "uniform"),
+        wgpu::BufferBindingType::Storage { .. } => (wgpu::BufferUsages::STORAGE, "storage"),
+    };
+    if !binding.buffer.usage().contains(usage) {
+        return Some(format!(
+            "takes a {kind} buffer, was given one without {usage:?} usage"
+        ));
+    }
+    let size = binding
+        .size
+        .map_or(binding.buffer.size() - binding.offset, |size| size.get());
+    match min_binding_size {
+        Some(min) if size < min.get() => Some(format!(
+            "takes at least {min} bytes, was given {size}, is the layout's content type the buffer's?"
+        )),
+        _ => None,
+    }
+}
@@ -20,6 +20,15 @@ pub fn uniform() -> wgpu::BindingType {
     }
 }
 
+/// A uniform bound with a dynamic offset at draw time, see [super::dynamic_uniform_buffer::DynamicUniformBuffer]
+pub fn uniform_dynamic(size: u64) -> wgpu::BindingType {
+    wgpu::BindingType::Buffer {
+        ty: wgpu::BufferBindingType::Uniform,
+        has_dynamic_offset: true,
+        min_binding_size: wgpu::BufferSize::new(size),
+    }
+}
+
 pub fn sampler(filtering: wgpu::SamplerBindingType) -> wgpu::BindingType {
     wgpu::BindingType::Sampler(filtering)
 }
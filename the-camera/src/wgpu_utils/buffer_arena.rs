@@ -0,0 +1,177 @@
+use std::ops::Range;
+
+/// A range of a [BufferArena]'s buffer, handed out by [BufferArena::allocate]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ArenaAllocation {
+    pub offset: wgpu::BufferAddress,
+    pub size: wgpu::BufferAddress,
+}
+
+impl ArenaAllocation {
+    pub fn range(&self) -> Range<wgpu::BufferAddress> {
+        self.offset..self.offset + self.size
+    }
+}
+
+/// Suballocates many small pieces of data, e.g. the vertices of every mesh, from one large buffer.
+///
+/// Freed ranges go back into a free list and are reused first fit, merged with free neighbours. When nothing fits, the
+/// buffer is replaced by one at least twice the size and the old content is copied over, so always bind
+/// [BufferArena::buffer] fresh rather than holding on to it.
+pub struct BufferArena {
+    buffer: wgpu::Buffer,
+    label: String,
+    usage: wgpu::BufferUsages,
+    alignment: wgpu::BufferAddress,
+    /// Sorted by offset, never adjacent to each other
+    free: Vec<Range<wgpu::BufferAddress>>,
+}
+
+impl BufferArena {
+    /// Every allocation starts and ends at a multiple of `alignment`, which has to be a multiple of
+    /// [wgpu::COPY_BUFFER_ALIGNMENT]. For vertices, pick the vertex size so offsets can be turned into base vertices.
+    ///
+    /// # Panics
+    ///
+    /// When `alignment` isn't a non-zero multiple of [wgpu::COPY_BUFFER_ALIGNMENT].
+    pub fn new(
+        device: &wgpu::Device,
+        label: &str,
+        usage: wgpu::BufferUsages,
+        alignment: wgpu::BufferAddress,
+        initial_size: wgpu::BufferAddress,
+    ) -> Self {
+        assert!(
+            alignment > 0 && alignment.is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT),
+            "Arena alignment has to be a multiple of {}!",
+            wgpu::COPY_BUFFER_ALIGNMENT
+        );
+        let size = initial_size.max(1).next_multiple_of(alignment);
+        let usage = usage | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC;
+
+        BufferArena {
+            buffer: Self::create_buffer(device, label, usage, size),
+            label: label.to_string(),
+            usage,
+            alignment,
+            free: std::iter::once(0..size).collect(),
+        }
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        label: &str,
+        usage: wgpu::BufferUsages,
+        size: wgpu::BufferAddress,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size,
+            usage,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Reserves `size` bytes, rounded up to the alignment. Grows the buffer when no free range is large enough.
+    pub fn allocate(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        size: wgpu::BufferAddress,
+    ) -> ArenaAllocation {
+        let size = size.max(1).next_multiple_of(self.alignment);
+        let index = match self
+            .free
+            .iter()
+            .position(|range| range.end - range.start >= size)
+        {
+            Some(index) => index,
+            None => {
+                self.grow(device, queue, size);
+                self.free.len() - 1
+            }
+        };
+
+        let range = &mut self.free[index];
+        let offset = range.start;
+        range.start += size;
+        if range.is_empty() {
+            self.free.remove(index);
+        }
+        ArenaAllocation { offset, size }
+    }
+
+    /// Replaces the buffer with one that has at least `needed` free bytes at the end, keeping the content
+    fn grow(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, needed: wgpu::BufferAddress) {
+        let old_size = self.buffer.size();
+        // The free range at the end grows along with the buffer
+        let free_at_end = match self.free.last() {
+            Some(range) if range.end == old_size => range.end - range.start,
+            _ => 0,
+        };
+        let new_size = (old_size + needed - free_at_end)
+            .next_power_of_two()
+            .max(old_size * 2)
+            .next_multiple_of(self.alignment);
+        tracing::debug!("Growing {} from {old_size} to {new_size} bytes", self.label);
+
+        let buffer = Self::create_buffer(device, &self.label, self.usage, new_size);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Arena Grow Encoder"),
+        });
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &buffer, 0, old_size);
+        // Not left to the frame's commands, queue writes into the new buffer would run before the copy and be
+        // overwritten by it
+        queue.submit(Some(encoder.finish()));
+        self.buffer = buffer;
+
+        match self.free.last_mut() {
+            Some(range) if range.end == old_size => range.end = new_size,
+            _ => self.free.push(old_size..new_size),
+        }
+    }
+
+    /// Writes `data` to the start of `allocation`
+    ///
+    /// # Panics
+    ///
+    /// When `data` doesn't fit into the allocation. Its length has to be a multiple of
+    /// [wgpu::COPY_BUFFER_ALIGNMENT].
+    pub fn write(&self, queue: &wgpu::Queue, allocation: &ArenaAllocation, data: &[u8]) {
+        assert!(
+            data.len() as u64 <= allocation.size,
+            "Data doesn't fit into the arena allocation!"
+        );
+        queue.write_buffer(&self.buffer, allocation.offset, data);
+    }
+
+    /// Returns the range to the free list. The content stays in place until the range is handed out again.
+    pub fn free(&mut self, allocation: ArenaAllocation) {
+        let range = allocation.range();
+        let index = self.free.partition_point(|free| free.start < range.start);
+
+        let merges_previous = index > 0 && self.free[index - 1].end == range.start;
+        let merges_next = self
+            .free
+            .get(index)
+            .is_some_and(|next| next.start == range.end);
+        match (merges_previous, merges_next) {
+            (true, true) => {
+                self.free[index - 1].end = self.free[index].end;
+                self.free.remove(index);
+            }
+            (true, false) => self.free[index - 1].end = range.end,
+            (false, true) => self.free[index].start = range.start,
+            (false, false) => self.free.insert(index, range),
+        }
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    /// Bytes not handed out, possibly split into several ranges
+    pub fn free_size(&self) -> wgpu::BufferAddress {
+        self.free.iter().map(|range| range.end - range.start).sum()
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use wgpu::{BindGroup, BindGroupLayout, CommandEncoder, ComputePipeline, Device};
+
+use super::debug_scope::GpuDebugScope;
+
+/// Number of workgroups of `workgroup_size` needed to cover `items` invocations in each dimension
+pub fn workgroup_count(items: [u32; 3], workgroup_size: [u32; 3]) -> [u32; 3] {
+    [0, 1, 2].map(|axis| items[axis].div_ceil(workgroup_size[axis].max(1)))
+}
+
+/// A compute shader entry point ready to be dispatched
+pub struct ComputeKernel {
+    label: String,
+    pipeline: ComputePipeline,
+    workgroup_size: [u32; 3],
+}
+
+impl ComputeKernel {
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn pipeline(&self) -> &ComputePipeline {
+        &self.pipeline
+    }
+
+    /// Has to match the shader's `@workgroup_size`
+    pub fn workgroup_size(&self) -> [u32; 3] {
+        self.workgroup_size
+    }
+
+    /// See [workgroup_count]
+    pub fn workgroup_count(&self, items: [u32; 3]) -> [u32; 3] {
+        workgroup_count(items, self.workgroup_size)
+    }
+}
+
+/// Tool to create [ComputeKernel]s from WGSL
+pub struct ComputeKernelBuilder<'a> {
+    source: &'a str,
+    entry_point: &'a str,
+    workgroup_size: [u32; 3],
+    bind_group_layouts: Vec<&'a BindGroupLayout>,
+    constants: HashMap<String, f64>,
+}
+
+impl<'a> ComputeKernelBuilder<'a> {
+    /// Kernel running `source`'s `main` with a workgroup size of 64 unless told otherwise
+    pub fn new(source: &'a str) -> Self {
+        ComputeKernelBuilder {
+            source,
+            entry_point: "main",
+            workgroup_size: [64, 1, 1],
+            bind_group_layouts: Vec::new(),
+            constants: HashMap::new(),
+        }
+    }
+
+    pub fn entry_point(mut self, entry_point: &'a str) -> Self {
+        self.entry_point = entry_point;
+        self
+    }
+
+    /// The `@workgroup_size` the shader declares, used to size dispatches
+    pub fn workgroup_size(mut self, workgroup_size: [u32; 3]) -> Self {
+        self.workgroup_size = workgroup_size;
+        self
+    }
+
+    /// Adds the layout of the next bind group, starting at group 0
+    pub fn bind_group_layout(mut self, layout: &'a BindGroupLayout) -> Self {
+        self.bind_group_layouts.push(layout);
+        self
+    }
+
+    /// Sets a pipeline-overridable constant declared with `override` in the shader
+    pub fn constant(mut self, name: &str, value: f64) -> Self {
+        self.constants.insert(name.to_string(), value);
+        self
+    }
+
+    /// Compiles the shader and creates the pipeline with the given label for debugging and identification
+    pub fn create(self, device: &Device, label: &str) -> ComputeKernel {
+        let _span = tracing::debug_span!("create_compute_kernel", label).entered();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(self.source.into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &self.bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(label),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: Some(self.entry_point),
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &self.constants,
+                ..Default::default()
+            },
+            cache: None,
+        });
+
+        ComputeKernel {
+            label: label.to_string(),
+            pipeline,
+            workgroup_size: self.workgroup_size,
+        }
+    }
+}
+
+/// How many workgroups one dispatch runs
+enum Workgroups<'a> {
+    Direct([u32; 3]),
+    /// Three `u32`s at the offset, e.g. written by an earlier kernel
+    Indirect(&'a wgpu::Buffer, wgpu::BufferAddress),
+}
+
+struct Dispatch<'a> {
+    kernel: &'a ComputeKernel,
+    bind_groups: Vec<&'a BindGroup>,
+    workgroups: Workgroups<'a>,
+}
+
+/// Records several dispatches into one compute pass, in order
+pub struct ComputePassBuilder<'a> {
+    label: &'a str,
+    dispatches: Vec<Dispatch<'a>>,
+}
+
+impl<'a> ComputePassBuilder<'a> {
+    pub fn new(label: &'a str) -> Self {
+        ComputePassBuilder {
+            label,
+            dispatches: Vec::new(),
+        }
+    }
+
+    /// Runs `kernel` once per item, `bind_groups` are bound starting at group 0
+    pub fn dispatch(
+        self,
+        kernel: &'a ComputeKernel,
+        bind_groups: &[&'a BindGroup],
+        items: [u32; 3],
+    ) -> Self {
+        let workgroups = kernel.workgroup_count(items);
+        self.dispatch_workgroups(kernel, bind_groups, workgroups)
+    }
+
+    /// Like [ComputePassBuilder::dispatch], counting workgroups rather than items
+    pub fn dispatch_workgroups(
+        mut self,
+        kernel: &'a ComputeKernel,
+        bind_groups: &[&'a BindGroup],
+        workgroups: [u32; 3],
+    ) -> Self {
+        self.dispatches.push(Dispatch {
+            kernel,
+            bind_groups: bind_groups.to_vec(),
+            workgroups: Workgroups::Direct(workgroups),
+        });
+        self
+    }
+
+    /// Takes the workgroup count from `buffer` at `offset` when the pass runs. The buffer needs
+    /// [wgpu::BufferUsages::INDIRECT].
+    pub fn dispatch_indirect(
+        mut self,
+        kernel: &'a ComputeKernel,
+        bind_groups: &[&'a BindGroup],
+        buffer: &'a wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+    ) -> Self {
+        self.dispatches.push(Dispatch {
+            kernel,
+            bind_groups: bind_groups.to_vec(),
+            workgroups: Workgroups::Indirect(buffer, offset),
+        });
+        self
+    }
+
+    pub fn record(self, encoder: &mut CommandEncoder) {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some(self.label),
+            timestamp_writes: None,
+        });
+        for dispatch in &self.dispatches {
+            let mut pass = GpuDebugScope::new(&mut pass, &dispatch.kernel.label);
+            pass.set_pipeline(&dispatch.kernel.pipeline);
+            for (index, bind_group) in dispatch.bind_groups.iter().enumerate() {
+                pass.set_bind_group(index as u32, *bind_group, &[]);
+            }
+            match dispatch.workgroups {
+                Workgroups::Direct([x, y, z]) => {
+                    // Empty dispatches are valid but pointless
+                    if ![x, y, z].contains(&0) {
+                        pass.dispatch_workgroups(x, y, z);
+                    }
+                }
+                Workgroups::Indirect(buffer, offset) => {
+                    pass.dispatch_workgroups_indirect(buffer, offset)
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::ops::{Deref, DerefMut};
+
+use wgpu::{CommandEncoder, ComputePass, RenderPass};
+
+/// Anything commands can be grouped in for graphics debuggers
+pub trait DebugGroups {
+    fn push_debug_group(&mut self, label: &str);
+    fn pop_debug_group(&mut self);
+    fn insert_debug_marker(&mut self, label: &str);
+}
+
+impl DebugGroups for CommandEncoder {
+    fn push_debug_group(&mut self, label: &str) {
+        CommandEncoder::push_debug_group(self, label);
+    }
+
+    fn pop_debug_group(&mut self) {
+        CommandEncoder::pop_debug_group(self);
+    }
+
+    fn insert_debug_marker(&mut self, label: &str) {
+        CommandEncoder::insert_debug_marker(self, label);
+    }
+}
+
+impl DebugGroups for RenderPass<'_> {
+    fn push_debug_group(&mut self, label: &str) {
+        RenderPass::push_debug_group(self, label);
+    }
+
+    fn pop_debug_group(&mut self) {
+        RenderPass::pop_debug_group(self);
+    }
+
+    fn insert_debug_marker(&mut self, label: &str) {
+        RenderPass::insert_debug_marker(self, label);
+    }
+}
+
+impl DebugGroups for ComputePass<'_> {
+    fn push_debug_group(&mut self, label: &str) {
+        ComputePass::push_debug_group(self, label);
+    }
+
+    fn pop_debug_group(&mut self) {
+        ComputePass::pop_debug_group(self);
+    }
+
+    fn insert_debug_marker(&mut self, label: &str) {
+        ComputePass::insert_debug_marker(self, label);
+    }
+}
+
+/// Groups everything recorded through it under `label` in captures of RenderDoc, Xcode and the like. The group is
+/// closed when the scope is dropped, so groups always nest properly.
+///
+/// Derefs to the encoder or pass it wraps:
+/// ```ignore
+/// let mut encoder = GpuDebugScope::new(&mut encoder, "particles");
+/// encoder.insert_debug_marker("spawn");
+/// let pass = encoder.begin_compute_pass(&Default::default());
+/// ```
+pub struct GpuDebugScope<'a, Target: DebugGroups> {
+    target: &'a mut Target,
+}
+
+impl<'a, Target: DebugGroups> GpuDebugScope<'a, Target> {
+    pub fn new(target: &'a mut Target, label: &str) -> Self {
+        target.push_debug_group(label);
+        GpuDebugScope { target }
+    }
+
+    /// Opens a group nested in this one
+    pub fn scope(&mut self, label: &str) -> GpuDebugScope<'_, Target> {
+        GpuDebugScope::new(self.target, label)
+    }
+}
+
+impl<Target: DebugGroups> Deref for GpuDebugScope<'_, Target> {
+    type Target = Target;
+
+    fn deref(&self) -> &Target {
+        self.target
+    }
+}
+
+impl<Target: DebugGroups> DerefMut for GpuDebugScope<'_, Target> {
+    fn deref_mut(&mut self) -> &mut Target {
+        self.target
+    }
+}
+
+impl<Target: DebugGroups> Drop for GpuDebugScope<'_, Target> {
+    fn drop(&mut self) {
+        self.target.pop_debug_group();
+    }
+}
@@ -0,0 +1,121 @@
+use std::{marker::PhantomData, num::NonZeroU64};
+
+use super::{uniform_layout, uploader::Uploader};
+
+/// Many `Content`s suballocated from one uniform buffer, e.g. the transforms of every object drawn in a frame.
+///
+/// All of them share a single bind group, each draw picks its own with the dynamic offset from
+/// [DynamicUniformBuffer::push] instead of switching bind groups. Entries are spaced by the device's
+/// `min_uniform_buffer_offset_alignment`, so small `Content`s waste the rest of their stride.
+///
+/// Meant to be refilled every frame: [DynamicUniformBuffer::clear], push everything, then
+/// [DynamicUniformBuffer::upload] before drawing.
+pub struct DynamicUniformBuffer<Content> {
+    buffer: wgpu::Buffer,
+    stride: u64,
+    capacity: usize,
+    staged: Vec<u8>,
+    content_type: PhantomData<Content>,
+}
+
+impl<Content: bytemuck::Pod> DynamicUniformBuffer<Content> {
+    fn name() -> &'static str {
+        let type_name = std::any::type_name::<Content>();
+        let pos = type_name.rfind(':').map_or(0, |pos| pos + 1);
+        &type_name[pos..]
+    }
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        uniform_layout::validate_uniform_size(
+            device,
+            std::mem::size_of::<Content>() as u64,
+            Self::name(),
+        );
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let stride = (std::mem::size_of::<Content>() as u64).next_multiple_of(alignment);
+        let capacity = 1;
+
+        DynamicUniformBuffer {
+            buffer: Self::create_buffer(device, stride, capacity),
+            stride,
+            capacity,
+            staged: Vec::new(),
+            content_type: PhantomData,
+        }
+    }
+
+    fn create_buffer(device: &wgpu::Device, stride: u64, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("DynamicUniformBuffer: {}", Self::name())),
+            size: stride * capacity as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Forgets everything pushed so far, keeping the buffer
+    pub fn clear(&mut self) {
+        self.staged.clear();
+    }
+
+    /// Stages `content` for the next upload and returns the dynamic offset to bind it with
+    pub fn push(&mut self, content: &Content) -> u32 {
+        let offset = self.staged.len();
+        self.staged.extend_from_slice(bytemuck::bytes_of(content));
+        self.staged.resize(offset + self.stride as usize, 0);
+        offset as u32
+    }
+
+    /// Stages everything pushed since the last clear in `uploader`, growing the buffer to the next power of two if it
+    /// doesn't fit. Returns whether it was reallocated, in which case bind groups holding it have to be rebuilt.
+    pub fn upload(&mut self, device: &wgpu::Device, uploader: &mut Uploader) -> bool {
+        let len = self.len();
+        let reallocated = len > self.capacity;
+        if reallocated {
+            self.capacity = len.next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.stride, self.capacity);
+        }
+        uploader.write_buffer(device, &self.buffer, 0, &self.staged);
+        reallocated
+    }
+
+    /// Like [DynamicUniformBuffer::upload], but writes through the queue right away, for buffers filled while
+    /// drawing after the frame's uploads were submitted
+    pub fn write(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        let len = self.len();
+        let reallocated = len > self.capacity;
+        if reallocated {
+            self.capacity = len.next_power_of_two();
+            self.buffer = Self::create_buffer(device, self.stride, self.capacity);
+        }
+        queue.write_buffer(&self.buffer, 0, &self.staged);
+        reallocated
+    }
+
+    /// Number of entries pushed since the last clear
+    pub fn len(&self) -> usize {
+        self.staged.len() / self.stride as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.staged.is_empty()
+    }
+
+    /// The dynamic offset of the `index`th entry pushed
+    pub fn offset(&self, index: usize) -> u32 {
+        (index as u64 * self.stride) as u32
+    }
+
+    pub fn binding_type(&self) -> wgpu::BindingType {
+        super::binding_types::uniform_dynamic(std::mem::size_of::<Content>() as u64)
+    }
+
+    /// A window of one `Content` at the start of the buffer, moved by the dynamic offset
+    pub fn binding_resource(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: NonZeroU64::new(std::mem::size_of::<Content>() as u64),
+        })
+    }
+}
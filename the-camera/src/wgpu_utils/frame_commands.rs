@@ -0,0 +1,96 @@
+use wgpu::{CommandBuffer, CommandEncoder, Device, Queue, SubmissionIndex};
+
+/// Where in a frame's submission commands run, earlier stages first whatever order they were recorded in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SubmitStage {
+    /// Writes everything after it reads, e.g. [super::uploader::Uploader::finish] or mipmap generation
+    Upload,
+    /// Compute passes reading the uploads, e.g. plugin compute and culling
+    Compute,
+    /// Main passes of the render targets, which windows may sample
+    RenderTargets,
+    /// Main and plugin passes of windows, together with the copies out of their surfaces
+    Windows,
+    /// Query resolves and other copies reading what every pass before wrote
+    Readback,
+}
+
+impl SubmitStage {
+    const ALL: [SubmitStage; 5] = [
+        SubmitStage::Upload,
+        SubmitStage::Compute,
+        SubmitStage::RenderTargets,
+        SubmitStage::Windows,
+        SubmitStage::Readback,
+    ];
+}
+
+#[derive(Default)]
+struct StageCommands {
+    buffers: Vec<CommandBuffer>,
+    encoder: Option<CommandEncoder>,
+}
+
+impl StageCommands {
+    /// Ends the open encoder, so what's added next runs after it
+    fn close_encoder(&mut self) {
+        if let Some(encoder) = self.encoder.take() {
+            self.buffers.push(encoder.finish());
+        }
+    }
+}
+
+/// Collects a frame's commands from every subsystem to submit them together, in [SubmitStage] order.
+///
+/// Subsystems either record into a stage's shared [FrameCommands::encoder] or [FrameCommands::push] command buffers
+/// they finished themselves, e.g. on other threads. Within a stage everything runs in the order it was added.
+/// Queue writes like [wgpu::Queue::write_buffer] still run before the whole submission, not at their stage.
+#[derive(Default)]
+pub struct FrameCommands {
+    stages: [StageCommands; SubmitStage::ALL.len()],
+}
+
+impl FrameCommands {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The stage's shared encoder, created on first use
+    pub fn encoder(&mut self, device: &Device, stage: SubmitStage) -> &mut CommandEncoder {
+        self.stages[stage as usize].encoder.get_or_insert_with(|| {
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some(&format!("{stage:?} Encoder")),
+            })
+        })
+    }
+
+    /// Runs `buffers` after everything added to `stage` so far
+    pub fn push(&mut self, stage: SubmitStage, buffers: impl IntoIterator<Item = CommandBuffer>) {
+        let stage = &mut self.stages[stage as usize];
+        stage.close_encoder();
+        stage.buffers.extend(buffers);
+    }
+
+    /// Whether nothing was added since the last submit
+    pub fn is_empty(&self) -> bool {
+        self.stages
+            .iter()
+            .all(|stage| stage.buffers.is_empty() && stage.encoder.is_none())
+    }
+
+    /// Submits everything added since the last call in one go, None if there was nothing
+    pub fn submit(&mut self, queue: &Queue) -> Option<SubmissionIndex> {
+        if self.is_empty() {
+            return None;
+        }
+        let buffers: Vec<_> = self
+            .stages
+            .iter_mut()
+            .flat_map(|stage| {
+                stage.close_encoder();
+                stage.buffers.drain(..)
+            })
+            .collect();
+        Some(queue.submit(buffers))
+    }
+}
@@ -0,0 +1,183 @@
+use std::ops::Range;
+
+use wgpu::{util::DrawIndexedIndirectArgs, DownlevelFlags, RenderPass};
+
+use super::uploader::Uploader;
+
+const ARGS_SIZE: u64 = std::mem::size_of::<DrawIndexedIndirectArgs>() as u64;
+
+/// Arguments of many indexed draws in one buffer, filled on the CPU or by a compute shader, and drawn with a single
+/// `multi_draw_indexed_indirect` where the device has [wgpu::Features::MULTI_DRAW_INDIRECT].
+///
+/// Without it every draw is issued on its own from the same buffer. Without indirect execution at all, e.g. on
+/// WebGL2, draws pushed from the CPU are issued directly and GPU written ones can't be drawn, check
+/// [IndirectBuffer::is_indirect].
+///
+/// `first_instance` has to be 0 unless the device has [wgpu::Features::INDIRECT_FIRST_INSTANCE].
+pub struct IndirectBuffer {
+    buffer: wgpu::Buffer,
+    usage: wgpu::BufferUsages,
+    capacity: usize,
+    len: usize,
+    staged: Vec<DrawIndexedIndirectArgs>,
+    multi_draw: bool,
+    indirect: bool,
+}
+
+impl IndirectBuffer {
+    /// `downlevel_flags` come from the adapter, see [crate::render_engine_builder::DeviceReport::downlevel_flags]
+    pub fn new(device: &wgpu::Device, downlevel_flags: DownlevelFlags, capacity: usize) -> Self {
+        let mut usage = wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::COPY_DST;
+        if downlevel_flags.contains(DownlevelFlags::COMPUTE_SHADERS) {
+            usage |= wgpu::BufferUsages::STORAGE;
+        }
+        let capacity = capacity.max(1);
+
+        IndirectBuffer {
+            buffer: Self::create_buffer(device, usage, capacity),
+            usage,
+            capacity,
+            len: 0,
+            staged: Vec::new(),
+            multi_draw: device
+                .features()
+                .contains(wgpu::Features::MULTI_DRAW_INDIRECT),
+            indirect: downlevel_flags.contains(DownlevelFlags::INDIRECT_EXECUTION),
+        }
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        usage: wgpu::BufferUsages,
+        capacity: usize,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Indirect Buffer"),
+            size: capacity as u64 * ARGS_SIZE,
+            usage,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Whether draws come from the buffer, rather than being issued from the CPU copies
+    pub fn is_indirect(&self) -> bool {
+        self.indirect
+    }
+
+    /// Whether all draws go out in one call
+    pub fn is_multi_draw(&self) -> bool {
+        self.multi_draw
+    }
+
+    pub fn clear(&mut self) {
+        self.staged.clear();
+    }
+
+    /// Stages one draw for the next [IndirectBuffer::upload] and returns its index
+    pub fn push(&mut self, args: DrawIndexedIndirectArgs) -> usize {
+        self.staged.push(args);
+        self.staged.len() - 1
+    }
+
+    /// Writes the draws pushed since the last clear. Returns whether the buffer was reallocated, in which case bind
+    /// groups holding it have to be rebuilt.
+    pub fn upload(&mut self, device: &wgpu::Device, uploader: &mut Uploader) -> bool {
+        let reallocated = self.reserve(device, self.staged.len());
+        self.len = self.staged.len();
+        if self.indirect {
+            let bytes: Vec<u8> = self
+                .staged
+                .iter()
+                .flat_map(|args| args.as_bytes())
+                .copied()
+                .collect();
+            uploader.write_buffer(device, &self.buffer, 0, &bytes);
+        }
+        reallocated
+    }
+
+    /// Makes room for `count` draws a compute shader writes into [IndirectBuffer::binding_resource], replacing
+    /// anything pushed. Returns whether the buffer was reallocated.
+    pub fn set_gpu_written(&mut self, device: &wgpu::Device, count: usize) -> bool {
+        self.staged.clear();
+        let reallocated = self.reserve(device, count);
+        self.len = count;
+        reallocated
+    }
+
+    fn reserve(&mut self, device: &wgpu::Device, count: usize) -> bool {
+        if count <= self.capacity {
+            return false;
+        }
+        self.capacity = count.next_power_of_two();
+        self.buffer = Self::create_buffer(device, self.usage, self.capacity);
+        true
+    }
+
+    /// Number of draws [IndirectBuffer::draw] issues
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// For compute shaders writing the arguments as `array<DrawIndexedIndirectArgs>`, five `u32`s each with
+    /// `base_vertex` as `i32`
+    pub fn binding_type(&self) -> wgpu::BindingType {
+        super::binding_types::buffer(false)
+    }
+
+    pub fn binding_resource(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+
+    /// Issues every draw with the pipeline, bind groups, vertex and index buffers already set on `render_pass`
+    ///
+    /// # Panics
+    ///
+    /// When the draws were written on the GPU but the device can't execute indirect draws.
+    pub fn draw(&self, render_pass: &mut RenderPass) {
+        self.draw_range(render_pass, 0..self.len);
+    }
+
+    /// Like [IndirectBuffer::draw], for only the draws in `range`
+    ///
+    /// # Panics
+    ///
+    /// Like [IndirectBuffer::draw], or when `range` goes past [IndirectBuffer::len].
+    pub fn draw_range(&self, render_pass: &mut RenderPass, range: Range<usize>) {
+        assert!(
+            range.end <= self.len,
+            "Draw range past the end of the indirect buffer!"
+        );
+        if range.is_empty() {
+            return;
+        }
+        if !self.indirect {
+            assert_eq!(
+                self.staged.len(),
+                self.len,
+                "GPU written draws need indirect execution!"
+            );
+            for args in &self.staged[range] {
+                render_pass.draw_indexed(
+                    args.first_index..args.first_index + args.index_count,
+                    args.base_vertex,
+                    args.first_instance..args.first_instance + args.instance_count,
+                );
+            }
+        } else if self.multi_draw {
+            render_pass.multi_draw_indexed_indirect(
+                &self.buffer,
+                range.start as u64 * ARGS_SIZE,
+                range.len() as u32,
+            );
+        } else {
+            for index in range {
+                render_pass.draw_indexed_indirect(&self.buffer, index as u64 * ARGS_SIZE);
+            }
+        }
+    }
+}
@@ -1,3 +1,17 @@
 pub mod binding_builder;
 pub mod binding_types;
+pub mod buffer_arena;
+pub mod compute;
+pub mod debug_scope;
+pub mod dynamic_uniform_buffer;
+pub mod frame_commands;
+pub mod indirect;
+pub mod per_frame;
+pub mod push_constants;
+pub mod readback;
+pub mod resource_cache;
+pub mod storage_buffer;
+pub mod transient;
 pub mod uniform_buffer;
+pub mod uniform_layout;
+pub mod uploader;
@@ -0,0 +1,62 @@
+/// How many frames the CPU may prepare while the GPU is still working on earlier ones
+pub const FRAMES_IN_FLIGHT: usize = 2;
+
+/// One copy of a resource per frame in flight, e.g. a buffer rewritten every frame and the bind group holding it.
+///
+/// Each frame writes into the next copy, so it never touches one the GPU may still be reading from. That matters for
+/// resources that aren't written through the queue, which orders writes for us: mapped buffers and async readbacks.
+pub struct PerFrame<T> {
+    frames: Vec<T>,
+    current: usize,
+}
+
+impl<T> PerFrame<T> {
+    /// [FRAMES_IN_FLIGHT] copies, created by calling `create` with each copy's index
+    pub fn new(create: impl FnMut(usize) -> T) -> Self {
+        Self::with_count(FRAMES_IN_FLIGHT, create)
+    }
+
+    /// # Panics
+    ///
+    /// When `count` is zero.
+    pub fn with_count(count: usize, create: impl FnMut(usize) -> T) -> Self {
+        assert!(count > 0, "PerFrame needs at least one copy!");
+        PerFrame {
+            frames: (0..count).map(create).collect(),
+            current: 0,
+        }
+    }
+
+    /// Moves on to the copy for the next frame and returns it. Call once at the start of every frame.
+    pub fn advance(&mut self) -> &mut T {
+        self.current = (self.current + 1) % self.frames.len();
+        &mut self.frames[self.current]
+    }
+
+    /// The copy of the current frame
+    pub fn current(&self) -> &T {
+        &self.frames[self.current]
+    }
+
+    pub fn current_mut(&mut self) -> &mut T {
+        &mut self.frames[self.current]
+    }
+
+    /// Index of the current copy, from 0 to [PerFrame::len]
+    pub fn index(&self) -> usize {
+        self.current
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Every copy, e.g. to rebuild them all after a change they all depend on
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.frames.iter_mut()
+    }
+}
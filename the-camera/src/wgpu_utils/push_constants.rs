@@ -0,0 +1,167 @@
+use wgpu::{BindGroupLayout, Device, PipelineLayout, RenderPass, ShaderStages};
+
+use super::{
+    binding_builder::{BindGroupBuilder, BindGroupLayoutBuilder, BindGroupLayoutWithDesc},
+    dynamic_uniform_buffer::DynamicUniformBuffer,
+    uploader::Uploader,
+};
+
+/// Where the data ends up when push constants aren't available
+struct Fallback<Content> {
+    group: u32,
+    bind_group_layout: BindGroupLayoutWithDesc,
+    ubo: DynamicUniformBuffer<Content>,
+    bind_group: wgpu::BindGroup,
+}
+
+/// A small `Content` set per draw, e.g. an object index.
+///
+/// Uses push constants when the device has [wgpu::Features::PUSH_CONSTANTS] with room for `Content`, see
+/// [crate::render_engine_builder::RenderEngineBuilder::push_constant_size]. Otherwise, e.g. on the web, each draw
+/// gets a slot in a dynamic uniform buffer bound at a group of its own.
+///
+/// Staging works the same either way:
+/// 1. [PushConstants::clear], then [PushConstants::push] the content of every draw
+/// 2. [PushConstants::upload] before recording the passes
+/// 3. [PushConstants::set] before each draw with the index `push` returned
+///
+/// The pipeline has to be created with [PushConstants::pipeline_layout] and its shader has to declare the data with
+/// [PushConstants::wgsl_declaration].
+pub struct PushConstants<Content> {
+    stages: ShaderStages,
+    staged: Vec<Content>,
+    fallback: Option<Fallback<Content>>,
+}
+
+impl<Content: bytemuck::Pod> PushConstants<Content> {
+    /// `stages` see the data. Without push constants it is bound at `fallback_group`, which has to come right after
+    /// the pipeline's other bind groups.
+    ///
+    /// # Panics
+    ///
+    /// When the size of `Content` isn't a multiple of 4 bytes.
+    pub fn new(device: &Device, stages: ShaderStages, fallback_group: u32) -> Self {
+        let size = std::mem::size_of::<Content>();
+        assert!(
+            (size as u32).is_multiple_of(wgpu::PUSH_CONSTANT_ALIGNMENT),
+            "Push constant size has to be a multiple of 4 bytes!"
+        );
+
+        let native = device.features().contains(wgpu::Features::PUSH_CONSTANTS)
+            && size <= device.limits().max_push_constant_size as usize;
+        let fallback = (!native).then(|| {
+            let ubo = DynamicUniformBuffer::new(device);
+            let bind_group_layout = BindGroupLayoutBuilder::new()
+                .next_binding(stages, ubo.binding_type())
+                .create(device, "Push Constant Fallback Bind Group");
+            let bind_group = BindGroupBuilder::new(&bind_group_layout)
+                .resource(ubo.binding_resource())
+                .create(device, "Push Constant Fallback Bind Group");
+            Fallback {
+                group: fallback_group,
+                bind_group_layout,
+                ubo,
+                bind_group,
+            }
+        });
+
+        PushConstants {
+            stages,
+            staged: Vec::new(),
+            fallback,
+        }
+    }
+
+    /// Whether real push constants are used
+    pub fn is_native(&self) -> bool {
+        self.fallback.is_none()
+    }
+
+    /// A pipeline layout with `bind_group_layouts` and either the push constant range or the fallback bind group
+    ///
+    /// # Panics
+    ///
+    /// When the fallback group isn't the one after `bind_group_layouts`.
+    pub fn pipeline_layout(
+        &self,
+        device: &Device,
+        bind_group_layouts: &[&BindGroupLayout],
+        label: &str,
+    ) -> PipelineLayout {
+        let mut layouts = bind_group_layouts.to_vec();
+        let mut ranges = Vec::new();
+        match &self.fallback {
+            Some(fallback) => {
+                assert_eq!(
+                    fallback.group as usize,
+                    layouts.len(),
+                    "The push constant fallback group has to follow the other bind groups!"
+                );
+                layouts.push(&fallback.bind_group_layout.layout);
+            }
+            None => ranges.push(wgpu::PushConstantRange {
+                stages: self.stages,
+                range: 0..std::mem::size_of::<Content>() as u32,
+            }),
+        }
+
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &layouts,
+            push_constant_ranges: &ranges,
+        })
+    }
+
+    /// The WGSL declaring a global `name` of the shader struct `ty`, for splicing into the shader source
+    pub fn wgsl_declaration(&self, name: &str, ty: &str) -> String {
+        match &self.fallback {
+            Some(fallback) => format!(
+                "@group({}) @binding(0) var<uniform> {name}: {ty};",
+                fallback.group
+            ),
+            None => format!("var<push_constant> {name}: {ty};"),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.staged.clear();
+    }
+
+    /// Stages the content of one draw, returns the index to [PushConstants::set] it with
+    pub fn push(&mut self, content: Content) -> usize {
+        self.staged.push(content);
+        self.staged.len() - 1
+    }
+
+    /// Does nothing with push constants, which are recorded straight into the pass
+    pub fn upload(&mut self, device: &Device, uploader: &mut Uploader) {
+        let Some(fallback) = &mut self.fallback else {
+            return;
+        };
+        fallback.ubo.clear();
+        for content in &self.staged {
+            fallback.ubo.push(content);
+        }
+        if fallback.ubo.upload(device, uploader) {
+            fallback.bind_group = BindGroupBuilder::new(&fallback.bind_group_layout)
+                .resource(fallback.ubo.binding_resource())
+                .create(device, "Push Constant Fallback Bind Group");
+        }
+    }
+
+    /// Makes the `index`th pushed content visible to the following draws
+    pub fn set(&self, render_pass: &mut RenderPass, index: usize) {
+        match &self.fallback {
+            Some(fallback) => render_pass.set_bind_group(
+                fallback.group,
+                &fallback.bind_group,
+                &[fallback.ubo.offset(index)],
+            ),
+            None => render_pass.set_push_constants(
+                self.stages,
+                0,
+                bytemuck::bytes_of(&self.staged[index]),
+            ),
+        }
+    }
+}
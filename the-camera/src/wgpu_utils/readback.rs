@@ -0,0 +1,517 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    ops::Range,
+    pin::Pin,
+    sync::{
+        mpsc::{channel, Receiver},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Waker},
+};
+
+struct Pending<Tag> {
+    buffer: wgpu::Buffer,
+    size: wgpu::BufferAddress,
+    tag: Tag,
+    /// Behind a mutex so views owning a ring can be shared with the `parallel-encoding` threads
+    mapped: Option<Mutex<Receiver<Result<(), wgpu::BufferAsyncError>>>>,
+}
+
+/// Reads GPU data back without stalling: copies go into buffers of their own, which are mapped once the GPU is done
+/// and reused afterwards.
+///
+/// Each frame: [ReadbackRing::copy] things into it while recording, [ReadbackRing::after_submit] once the commands
+/// are submitted, and [ReadbackRing::collect] the results later. The device has to be polled in between. Every copy
+/// carries a `Tag` saying what it was for.
+pub struct ReadbackRing<Tag> {
+    label: String,
+    pending: VecDeque<Pending<Tag>>,
+    free: Vec<wgpu::Buffer>,
+}
+
+impl<Tag> ReadbackRing<Tag> {
+    pub fn new(label: &str) -> Self {
+        ReadbackRing {
+            label: label.to_string(),
+            pending: VecDeque::new(),
+            free: Vec::new(),
+        }
+    }
+
+    /// Records copying `size` bytes of `source` from `offset`. `source` needs [wgpu::BufferUsages::COPY_SRC] and
+    /// offset and size have to be multiples of [wgpu::COPY_BUFFER_ALIGNMENT].
+    pub fn copy(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        size: wgpu::BufferAddress,
+        tag: Tag,
+    ) {
+        self.record(device, size, tag, |buffer| {
+            encoder.copy_buffer_to_buffer(source, offset, buffer, 0, size)
+        });
+    }
+
+    /// Records copying the texel at `position` of `texture`'s `aspect`, e.g. [wgpu::TextureAspect::DepthOnly] of a
+    /// depth buffer. `texture` needs [wgpu::TextureUsages::COPY_SRC].
+    pub fn copy_texel(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        aspect: wgpu::TextureAspect,
+        [x, y]: [u32; 2],
+        tag: Tag,
+    ) {
+        let texel_size = texture
+            .format()
+            .block_copy_size(Some(aspect))
+            .expect("Texture aspect can't be copied!");
+        // Buffer sizes have to be a multiple of 4 bytes, the copy itself is only one texel
+        let size = (texel_size as u64).next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+        self.record(device, size, tag, |buffer| {
+            encoder.copy_texture_to_buffer(
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d { x, y, z: 0 },
+                    aspect,
+                },
+                wgpu::ImageCopyBuffer {
+                    buffer,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: None,
+                        rows_per_image: None,
+                    },
+                },
+                wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+            )
+        });
+    }
+
+    /// Lets `record` copy up to `size` bytes into the start of the buffer it gets, e.g. out of a texture
+    pub fn record(
+        &mut self,
+        device: &wgpu::Device,
+        size: wgpu::BufferAddress,
+        tag: Tag,
+        record: impl FnOnce(&wgpu::Buffer),
+    ) {
+        let buffer = match self.free.iter().position(|buffer| buffer.size() >= size) {
+            Some(index) => self.free.swap_remove(index),
+            None => device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&self.label),
+                size: size.next_power_of_two(),
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+        };
+        record(&buffer);
+        self.pending.push_back(Pending {
+            buffer,
+            size,
+            tag,
+            mapped: None,
+        });
+    }
+
+    /// Starts mapping the copies recorded since the last call, once they were submitted
+    pub fn after_submit(&mut self) {
+        for pending in self
+            .pending
+            .iter_mut()
+            .filter(|pending| pending.mapped.is_none())
+        {
+            let (sender, receiver) = channel();
+            pending.mapped = Some(Mutex::new(receiver));
+            pending
+                .buffer
+                .slice(..pending.size)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = sender.send(result);
+                });
+        }
+    }
+
+    /// Hands the copies that arrived to `read`, in the order they were recorded. Copies that failed to map are
+    /// dropped with a warning.
+    pub fn collect(&mut self, mut read: impl FnMut(Tag, &[u8])) {
+        while let Some(pending) = self.pending.front() {
+            let Some(mapped) = &pending.mapped else {
+                break;
+            };
+            let Ok(result) = mapped.lock().expect("Readback state poisoned!").try_recv() else {
+                break;
+            };
+            let pending = self.pending.pop_front().expect("Readback is pending!");
+            if result.is_err() {
+                tracing::warn!("Failed to map {}", self.label);
+                continue;
+            }
+
+            let data = pending.buffer.slice(..pending.size).get_mapped_range();
+            read(pending.tag, &data);
+            drop(data);
+            pending.buffer.unmap();
+            self.free.push(pending.buffer);
+        }
+    }
+
+    /// Whether copies are in flight, which need the device polled
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+}
+
+struct Shared<T> {
+    /// Some once resolved, holding None if the promise was dropped without a value
+    value: Option<Option<T>>,
+    waker: Option<Waker>,
+}
+
+/// A value the GPU hands back later, e.g. from a [ReadbackRing] copy. Resolves to None if the promise was dropped
+/// without a value, e.g. because its window was closed.
+///
+/// The engine only makes progress on readbacks while it runs, so blocking on this from the thread driving the event
+/// loop never finishes. Check [ReadbackFuture::is_ready] from there, or await it elsewhere.
+pub struct ReadbackFuture<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+/// The engine's end of a [ReadbackFuture]
+pub struct ReadbackPromise<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+/// A future and the promise resolving it
+pub fn readback_future<T>() -> (ReadbackPromise<T>, ReadbackFuture<T>) {
+    let shared = Arc::new(Mutex::new(Shared {
+        value: None,
+        waker: None,
+    }));
+    (
+        ReadbackPromise {
+            shared: shared.clone(),
+        },
+        ReadbackFuture { shared },
+    )
+}
+
+impl<T> ReadbackFuture<T> {
+    /// A future that is already resolved
+    pub fn ready(value: Option<T>) -> Self {
+        ReadbackFuture {
+            shared: Arc::new(Mutex::new(Shared {
+                value: Some(value),
+                waker: None,
+            })),
+        }
+    }
+
+    /// Whether awaiting it returns right away
+    pub fn is_ready(&self) -> bool {
+        self.shared
+            .lock()
+            .expect("Readback state poisoned!")
+            .value
+            .is_some()
+    }
+
+    /// The result if it arrived, Some(None) if it never will. Only returned once, like awaiting it.
+    pub fn try_take(&mut self) -> Option<Option<T>> {
+        self.shared
+            .lock()
+            .expect("Readback state poisoned!")
+            .value
+            .take()
+    }
+
+    /// Blocks until the GPU is idle and returns the value, None as well if it isn't resolved by then.
+    ///
+    /// Only for futures wgpu resolves on its own, like those of [read_buffer_to_vec] and [read_texture_to_vec]. The
+    /// engine's readbacks also need it to keep running.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn wait(self, device: &wgpu::Device) -> Option<T> {
+        device.poll(wgpu::Maintain::Wait);
+        self.shared
+            .lock()
+            .expect("Readback state poisoned!")
+            .value
+            .take()
+            .flatten()
+    }
+}
+
+impl<T> Future for ReadbackFuture<T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut shared = self.shared.lock().expect("Readback state poisoned!");
+        match shared.value.take() {
+            Some(value) => Poll::Ready(value),
+            None => {
+                shared.waker = Some(context.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<T> ReadbackPromise<T> {
+    pub fn resolve(self, value: T) {
+        self.settle(Some(value));
+    }
+
+    fn settle(&self, value: Option<T>) {
+        let mut shared = self.shared.lock().expect("Readback state poisoned!");
+        if shared.value.is_none() {
+            shared.value = Some(value);
+        }
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Drop for ReadbackPromise<T> {
+    fn drop(&mut self) {
+        self.settle(None);
+    }
+}
+
+/// How [read_texture_to_vec] hands back texels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadFormat {
+    /// As stored in the texture, e.g. for compute results
+    Native,
+    /// Converted to RGBA8, e.g. to write images. Only for the formats [convert_to_rgba8] knows.
+    Rgba8,
+}
+
+/// Copies `range` of `buffer` out and maps it asynchronously. `buffer` needs [wgpu::BufferUsages::COPY_SRC].
+///
+/// The copy is submitted on its own, so it sees everything submitted before. Resolves once wgpu maps the copy, which
+/// takes polling the device, or to None if `range` doesn't fit or mapping failed.
+pub fn read_buffer_to_vec(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    range: Range<wgpu::BufferAddress>,
+) -> ReadbackFuture<Vec<u8>> {
+    if !buffer.usage().contains(wgpu::BufferUsages::COPY_SRC) {
+        tracing::error!("Cannot read back a buffer without COPY_SRC usage");
+        return ReadbackFuture::ready(None);
+    }
+    // Copies have to start and end at multiples of 4 bytes, the extra bytes are cut off again after mapping
+    let align = wgpu::COPY_BUFFER_ALIGNMENT;
+    let start = range.start - range.start % align;
+    let end = range.end.next_multiple_of(align);
+    if range.start > range.end || end > buffer.size() {
+        tracing::error!(
+            "Cannot read back bytes {range:?} of a {} byte buffer",
+            buffer.size()
+        );
+        return ReadbackFuture::ready(None);
+    }
+    if start == end {
+        return ReadbackFuture::ready(Some(Vec::new()));
+    }
+
+    let readback = create_readback_buffer(device, end - start);
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Buffer Readback Encoder"),
+    });
+    encoder.copy_buffer_to_buffer(buffer, start, &readback, 0, end - start);
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let skip = (range.start - start) as usize;
+    let len = (range.end - range.start) as usize;
+    map_to_vec(readback, move |data| data[skip..skip + len].to_vec())
+}
+
+/// Copies mip level `mip_level` of `texture`'s `aspect` out and maps it asynchronously, e.g.
+/// [wgpu::TextureAspect::DepthOnly] of a depth buffer. `texture` needs [wgpu::TextureUsages::COPY_SRC].
+///
+/// Texels come back tightly packed, one row after another and one layer after another, without the row padding
+/// texture copies need. Submits and resolves like [read_buffer_to_vec], to None as well if the texture can't be read
+/// back in `format`.
+pub fn read_texture_to_vec(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    mip_level: u32,
+    aspect: wgpu::TextureAspect,
+    format: ReadFormat,
+) -> ReadbackFuture<Vec<u8>> {
+    let texture_format = texture.format();
+    if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+        tracing::error!("Cannot read back a texture without COPY_SRC usage");
+        return ReadbackFuture::ready(None);
+    }
+    if mip_level >= texture.mip_level_count() {
+        tracing::error!(
+            "Cannot read back mip level {mip_level} of a texture with {}",
+            texture.mip_level_count()
+        );
+        return ReadbackFuture::ready(None);
+    }
+    let Some(block_size) = texture_format.block_copy_size(Some(aspect)) else {
+        tracing::error!("Cannot read back {aspect:?} of {texture_format:?} textures");
+        return ReadbackFuture::ready(None);
+    };
+    if format == ReadFormat::Rgba8 && convert_to_rgba8(Vec::new(), texture_format).is_none() {
+        tracing::error!("Cannot convert {texture_format:?} textures to RGBA8");
+        return ReadbackFuture::ready(None);
+    }
+
+    // Compressed formats are copied in whole blocks, which a row of the copy is made of
+    let (block_width, block_height) = texture_format.block_dimensions();
+    let size = texture
+        .size()
+        .mip_level_size(mip_level, texture.dimension())
+        .physical_size(texture_format);
+    let row_bytes = size.width / block_width * block_size;
+    let rows_per_image = size.height / block_height;
+    let rows = rows_per_image * size.depth_or_array_layers;
+    let padded_bytes_per_row = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+    let readback = create_readback_buffer(device, (padded_bytes_per_row * rows) as u64);
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Texture Readback Encoder"),
+    });
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level,
+            origin: wgpu::Origin3d::ZERO,
+            aspect,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &readback,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(rows_per_image),
+            },
+        },
+        size,
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    map_to_vec(readback, move |data| {
+        let texels = unpad_rows(data, row_bytes, padded_bytes_per_row, rows);
+        match format {
+            ReadFormat::Native => texels,
+            ReadFormat::Rgba8 => {
+                convert_to_rgba8(texels, texture_format).expect("Format was checked before!")
+            }
+        }
+    })
+}
+
+fn create_readback_buffer(device: &wgpu::Device, size: wgpu::BufferAddress) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Readback Buffer"),
+        size,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    })
+}
+
+/// Maps all of `buffer` and resolves the future with what `read` makes of its content. Dropping the buffer has to
+/// wait until then, so the callback keeps it alive.
+fn map_to_vec(
+    buffer: wgpu::Buffer,
+    read: impl FnOnce(&[u8]) -> Vec<u8> + Send + 'static,
+) -> ReadbackFuture<Vec<u8>> {
+    let (promise, future) = readback_future();
+    let buffer = Arc::new(buffer);
+    let mapped = buffer.clone();
+    buffer
+        .slice(..)
+        .map_async(wgpu::MapMode::Read, move |result| {
+            if let Err(err) = result {
+                // Dropping the promise resolves the future to None
+                tracing::error!("Failed to map readback buffer: {err}");
+                return;
+            }
+            let data = read(&mapped.slice(..).get_mapped_range());
+            mapped.unmap();
+            promise.resolve(data);
+        });
+    future
+}
+
+/// Width of a row in bytes, rounded up to [wgpu::COPY_BYTES_PER_ROW_ALIGNMENT]
+pub fn padded_bytes_per_row(width: u32, bytes_per_pixel: u32) -> u32 {
+    let unpadded = width * bytes_per_pixel;
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded.div_ceil(align) * align
+}
+
+/// Drops the padding at the end of every row so the rows are tightly packed
+pub fn unpad_rows(data: &[u8], row_bytes: u32, padded_bytes_per_row: u32, rows: u32) -> Vec<u8> {
+    let row_bytes = row_bytes as usize;
+    let mut texels = Vec::with_capacity(row_bytes * rows as usize);
+    for row in data
+        .chunks(padded_bytes_per_row as usize)
+        .take(rows as usize)
+    {
+        texels.extend_from_slice(&row[..row_bytes]);
+    }
+    texels
+}
+
+/// Converts tightly packed texels of `format` to RGBA8, returns [None] for formats we can't convert.
+///
+/// 8 bit formats keep their values, so sRGB ones stay sRGB encoded. Float formats are clamped to 0..1 without any
+/// tonemapping or encoding.
+pub fn convert_to_rgba8(mut texels: Vec<u8>, format: wgpu::TextureFormat) -> Option<Vec<u8>> {
+    use wgpu::TextureFormat;
+
+    let unorm = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+    match format {
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => Some(texels),
+        TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => {
+            for texel in texels.chunks_exact_mut(4) {
+                texel.swap(0, 2);
+            }
+            Some(texels)
+        }
+        TextureFormat::R8Unorm => Some(texels.iter().flat_map(|&v| [v, v, v, 255]).collect()),
+        TextureFormat::Rgba16Float => Some(
+            texels
+                .chunks_exact(2)
+                .map(|half| unorm(f16_to_f32(u16::from_le_bytes([half[0], half[1]]))))
+                .collect(),
+        ),
+        TextureFormat::Rgba32Float => Some(
+            texels
+                .chunks_exact(4)
+                .map(|float| unorm(f32::from_le_bytes([float[0], float[1], float[2], float[3]])))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// Widens a half float, there's no `f16` on stable Rust
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 == 0 { 1.0 } else { -1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32 / 1024.0;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-14),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa) * 2f32.powi(exponent - 15),
+    }
+}
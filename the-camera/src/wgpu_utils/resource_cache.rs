@@ -0,0 +1,149 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Everything in a [wgpu::SamplerDescriptor] but the label, floats by their bits
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct SamplerKey {
+    address_modes: [wgpu::AddressMode; 3],
+    filters: [wgpu::FilterMode; 3],
+    lod_clamp: [u32; 2],
+    compare: Option<wgpu::CompareFunction>,
+    anisotropy_clamp: u16,
+    border_color: Option<wgpu::SamplerBorderColor>,
+}
+
+impl From<&wgpu::SamplerDescriptor<'_>> for SamplerKey {
+    fn from(desc: &wgpu::SamplerDescriptor) -> Self {
+        SamplerKey {
+            address_modes: [
+                desc.address_mode_u,
+                desc.address_mode_v,
+                desc.address_mode_w,
+            ],
+            filters: [desc.mag_filter, desc.min_filter, desc.mipmap_filter],
+            lod_clamp: [desc.lod_min_clamp.to_bits(), desc.lod_max_clamp.to_bits()],
+            compare: desc.compare,
+            anisotropy_clamp: desc.anisotropy_clamp,
+            border_color: desc.border_color,
+        }
+    }
+}
+
+/// Samplers shared by everything on a device with the same descriptor, e.g. every image texture's.
+///
+/// Devices only allow a few thousand samplers, so creating one per texture or per resize runs out eventually. Labels
+/// aren't part of the key, a sampler keeps the label it was first created with. Has to be cleared when the device
+/// is replaced.
+#[derive(Default)]
+pub struct SamplerCache {
+    samplers: Mutex<HashMap<SamplerKey, Arc<wgpu::Sampler>>>,
+}
+
+impl SamplerCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The sampler for `desc`, created on first use
+    pub fn get(&self, device: &wgpu::Device, desc: &wgpu::SamplerDescriptor) -> Arc<wgpu::Sampler> {
+        let mut samplers = self.samplers.lock().expect("Sampler cache poisoned!");
+        samplers
+            .entry(SamplerKey::from(desc))
+            .or_insert_with(|| Arc::new(device.create_sampler(desc)))
+            .clone()
+    }
+
+    /// Number of distinct samplers created
+    pub fn len(&self) -> usize {
+        self.samplers.lock().expect("Sampler cache poisoned!").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops the cache's references, samplers still held elsewhere live on
+    pub fn clear(&self) {
+        self.samplers
+            .lock()
+            .expect("Sampler cache poisoned!")
+            .clear();
+    }
+}
+
+/// Everything in a [wgpu::TextureViewDescriptor] but the label
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct ViewKey {
+    format: Option<wgpu::TextureFormat>,
+    dimension: Option<wgpu::TextureViewDimension>,
+    aspect: wgpu::TextureAspect,
+    mips: (u32, Option<u32>),
+    layers: (u32, Option<u32>),
+}
+
+impl From<&wgpu::TextureViewDescriptor<'_>> for ViewKey {
+    fn from(desc: &wgpu::TextureViewDescriptor) -> Self {
+        ViewKey {
+            format: desc.format,
+            dimension: desc.dimension,
+            aspect: desc.aspect,
+            mips: (desc.base_mip_level, desc.mip_level_count),
+            layers: (desc.base_array_layer, desc.array_layer_count),
+        }
+    }
+}
+
+/// Views of one texture by descriptor, e.g. a single mip level per pass of a downsampling chain.
+///
+/// wgpu textures can't be told apart, so each texture keeps its own cache, see [crate::texture::Texture::view_with].
+/// Views are only dropped with the cache.
+#[derive(Default)]
+pub struct TextureViewCache {
+    views: Mutex<HashMap<ViewKey, Arc<wgpu::TextureView>>>,
+}
+
+impl TextureViewCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The view of `texture` for `desc`, created on first use. `texture` has to be the same every call.
+    pub fn get(
+        &self,
+        texture: &wgpu::Texture,
+        desc: &wgpu::TextureViewDescriptor,
+    ) -> Arc<wgpu::TextureView> {
+        let mut views = self.views.lock().expect("Texture view cache poisoned!");
+        views
+            .entry(ViewKey::from(desc))
+            .or_insert_with(|| Arc::new(texture.create_view(desc)))
+            .clone()
+    }
+
+    /// A view of the single mip level `mip`, e.g. to render into it
+    pub fn mip(&self, texture: &wgpu::Texture, mip: u32) -> Arc<wgpu::TextureView> {
+        self.get(
+            texture,
+            &wgpu::TextureViewDescriptor {
+                base_mip_level: mip,
+                mip_level_count: Some(1),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// A 2D view of the single array layer `layer`, e.g. a cube face
+    pub fn layer(&self, texture: &wgpu::Texture, layer: u32) -> Arc<wgpu::TextureView> {
+        self.get(
+            texture,
+            &wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            },
+        )
+    }
+}
@@ -0,0 +1,196 @@
+use std::marker::PhantomData;
+
+/// Whether shaders may write to a storage buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageAccess {
+    ReadOnly,
+    ReadWrite,
+}
+
+impl StorageAccess {
+    /// The binding type to put in a bind group layout for a buffer with this access
+    pub fn binding_type(self) -> wgpu::BindingType {
+        super::binding_types::buffer(self == StorageAccess::ReadOnly)
+    }
+
+    fn usage(self) -> wgpu::BufferUsages {
+        let usage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST;
+        match self {
+            StorageAccess::ReadOnly => usage,
+            // Written on the GPU, so it has to be possible to copy the results out again
+            StorageAccess::ReadWrite => usage | wgpu::BufferUsages::COPY_SRC,
+        }
+    }
+}
+
+fn name<Content>() -> &'static str {
+    let type_name = std::any::type_name::<Content>();
+    let pos = type_name.rfind(':').map_or(0, |pos| pos + 1);
+    &type_name[pos..]
+}
+
+/// A single `Content` in a storage buffer, for data that is too large for a uniform or written by shaders. WebGL2
+/// has no storage buffers, the downlevel limits of the web build allow none.
+pub struct StorageBuffer<Content> {
+    buffer: wgpu::Buffer,
+    access: StorageAccess,
+    content_type: PhantomData<Content>,
+}
+
+impl<Content: bytemuck::Pod> StorageBuffer<Content> {
+    pub fn new(device: &wgpu::Device, access: StorageAccess) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("StorageBuffer: {}", name::<Content>())),
+            size: std::mem::size_of::<Content>() as u64,
+            usage: access.usage(),
+            mapped_at_creation: false,
+        });
+
+        StorageBuffer {
+            buffer,
+            access,
+            content_type: PhantomData,
+        }
+    }
+
+    pub fn update_content(&self, queue: &wgpu::Queue, content: &Content) {
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(content));
+    }
+
+    pub fn binding_type(&self) -> wgpu::BindingType {
+        self.access.binding_type()
+    }
+
+    pub fn binding_resource(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+}
+
+/// Called with the new buffer after a [StorageVec] had to reallocate, to rebuild the bind groups holding the old one
+pub type ReallocateCallback = Box<dyn FnMut(&wgpu::Device, &wgpu::Buffer)>;
+
+/// A growable array of `Content` in a storage buffer, e.g. a light list, per instance data or the input and output
+/// of a compute workload. Shaders see it as `array<Content>`, so `Content` has to follow WGSL's storage layout rules.
+///
+/// The buffer grows to the next power of two when more elements are written than fit. Everything bound to the old
+/// buffer then has to be rebuilt, which [StorageVec::on_reallocate] callbacks are there for.
+pub struct StorageVec<Content> {
+    buffer: wgpu::Buffer,
+    access: StorageAccess,
+    len: usize,
+    capacity: usize,
+    on_reallocate: Vec<ReallocateCallback>,
+    content_type: PhantomData<Content>,
+}
+
+impl<Content: bytemuck::Pod> StorageVec<Content> {
+    /// Room for `capacity` elements up front, at least one since bindings can't be empty
+    pub fn with_capacity(device: &wgpu::Device, access: StorageAccess, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        StorageVec {
+            buffer: Self::create_buffer(device, access, capacity),
+            access,
+            len: 0,
+            capacity,
+            on_reallocate: Vec::new(),
+            content_type: PhantomData,
+        }
+    }
+
+    pub fn new(device: &wgpu::Device, access: StorageAccess) -> Self {
+        Self::with_capacity(device, access, 1)
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        access: StorageAccess,
+        capacity: usize,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("StorageVec: {}", name::<Content>())),
+            size: (capacity * std::mem::size_of::<Content>()) as u64,
+            usage: access.usage(),
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Registers `callback` to run every time the buffer is replaced by a larger one
+    pub fn on_reallocate(&mut self, callback: impl FnMut(&wgpu::Device, &wgpu::Buffer) + 'static) {
+        self.on_reallocate.push(Box::new(callback));
+    }
+
+    /// Replaces the whole content, growing the buffer if needed. Returns whether it was reallocated.
+    pub fn update(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        contents: &[Content],
+    ) -> bool {
+        let reallocated = self.reserve(device, contents.len());
+        self.len = contents.len();
+        if !contents.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(contents));
+        }
+        reallocated
+    }
+
+    /// Overwrites elements starting at `index`.
+    ///
+    /// # Panics
+    ///
+    /// When the write goes past [StorageVec::len], use [StorageVec::update] to change the length.
+    pub fn write(&self, queue: &wgpu::Queue, index: usize, contents: &[Content]) {
+        assert!(
+            index + contents.len() <= self.len,
+            "Write past the end of the storage buffer!"
+        );
+        let offset = (index * std::mem::size_of::<Content>()) as u64;
+        queue.write_buffer(&self.buffer, offset, bytemuck::cast_slice(contents));
+    }
+
+    /// Makes room for `capacity` elements, dropping the current content if the buffer has to grow. Returns whether
+    /// it was reallocated.
+    pub fn reserve(&mut self, device: &wgpu::Device, capacity: usize) -> bool {
+        if capacity <= self.capacity {
+            return false;
+        }
+        self.capacity = capacity.next_power_of_two();
+        self.buffer = Self::create_buffer(device, self.access, self.capacity);
+        self.len = 0;
+        for callback in &mut self.on_reallocate {
+            callback(device, &self.buffer);
+        }
+        true
+    }
+
+    /// Number of elements written by the last [StorageVec::update]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn binding_type(&self) -> wgpu::BindingType {
+        self.access.binding_type()
+    }
+
+    /// Binds the whole buffer, shaders should use [StorageVec::len] rather than `arrayLength` to know how many
+    /// elements are valid
+    pub fn binding_resource(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+}
@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+/// Frames a released texture is kept around without being acquired again before it is destroyed
+const KEEP_FRAMES: u64 = 3;
+
+/// What a transient attachment is created with. Textures are only shared between requests that match exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TransientDesc {
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+    pub sample_count: u32,
+}
+
+impl TransientDesc {
+    /// A single sampled attachment that can be drawn into and sampled afterwards
+    pub fn attachment(width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+        TransientDesc {
+            width,
+            height,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            sample_count: 1,
+        }
+    }
+
+    /// Roughly what the texture takes up in memory, for depth/stencil formats without a copyable aspect it's a guess
+    fn size_in_bytes(&self) -> u64 {
+        let texel_size = self
+            .format
+            .block_copy_size(None)
+            .or_else(|| {
+                self.format
+                    .block_copy_size(Some(wgpu::TextureAspect::DepthOnly))
+            })
+            .unwrap_or(4);
+        self.width as u64 * self.height as u64 * texel_size as u64 * self.sample_count as u64
+    }
+}
+
+/// An intermediate attachment handed out by [TransientTextures::acquire]. Give it back with
+/// [TransientTextures::release] after the last pass using it was recorded.
+pub struct TransientTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    desc: TransientDesc,
+    #[cfg(debug_assertions)]
+    id: u64,
+}
+
+impl TransientTexture {
+    pub fn desc(&self) -> &TransientDesc {
+        &self.desc
+    }
+}
+
+struct FreeTexture {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    /// The frame it was released in, to destroy textures nobody asks for anymore
+    released: u64,
+}
+
+/// Pools the intermediate attachments of multi pass effects, e.g. the ping-pong targets of a blur or a bloom chain,
+/// so passes that don't overlap end up aliasing the same texture instead of each allocating their own.
+///
+/// Passes [TransientTextures::acquire] what they write and [TransientTextures::release] it once the last pass
+/// reading it was recorded. A later pass asking for the same [TransientDesc] gets the texture back, even within the
+/// same frame, since wgpu orders the accesses of one submission. Released textures nobody acquires for a few frames
+/// are destroyed.
+///
+/// A render graph can drive this from the first and last pass using each attachment. Call
+/// [TransientTextures::begin_frame] once per frame, in debug builds it warns about textures acquired before the
+/// previous frame that were never released, which would otherwise only show as memory growing.
+pub struct TransientTextures {
+    label: String,
+    free: HashMap<TransientDesc, Vec<FreeTexture>>,
+    frame: u64,
+    /// Created and not destroyed, whether in use or free
+    allocated_bytes: u64,
+    /// Acquired and not released yet, with the label and frame they were acquired with
+    #[cfg(debug_assertions)]
+    acquired: HashMap<u64, (String, u64)>,
+    #[cfg(debug_assertions)]
+    next_id: u64,
+}
+
+impl TransientTextures {
+    pub fn new(label: &str) -> Self {
+        TransientTextures {
+            label: label.to_string(),
+            free: HashMap::new(),
+            frame: 0,
+            allocated_bytes: 0,
+            #[cfg(debug_assertions)]
+            acquired: HashMap::new(),
+            #[cfg(debug_assertions)]
+            next_id: 0,
+        }
+    }
+
+    /// Reports textures still acquired since before the previous frame as leaked and destroys free textures
+    /// that weren't used for a while
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+
+        #[cfg(debug_assertions)]
+        self.acquired.retain(|_, (label, frame)| {
+            let leaked = *frame + 1 < self.frame;
+            if leaked {
+                tracing::warn!(
+                    "Transient texture {label} of {} was never released",
+                    self.label
+                );
+            }
+            !leaked
+        });
+
+        let frame = self.frame;
+        let allocated_bytes = &mut self.allocated_bytes;
+        self.free.retain(|desc, textures| {
+            textures.retain(|free| {
+                let keep = frame - free.released <= KEEP_FRAMES;
+                if !keep {
+                    free.texture.destroy();
+                    *allocated_bytes -= desc.size_in_bytes();
+                }
+                keep
+            });
+            !textures.is_empty()
+        });
+    }
+
+    /// A texture matching `desc`, reusing a released one when there is one. `label` names newly created textures
+    /// and leaks.
+    pub fn acquire(
+        &mut self,
+        device: &wgpu::Device,
+        desc: TransientDesc,
+        label: &str,
+    ) -> TransientTexture {
+        let (texture, view) = match self.free.get_mut(&desc).and_then(Vec::pop) {
+            Some(free) => (free.texture, free.view),
+            None => {
+                tracing::debug!("Creating transient texture {label} for {}", self.label);
+                let texture = device.create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: desc.width,
+                        height: desc.height,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: desc.sample_count,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: desc.format,
+                    usage: desc.usage,
+                    view_formats: &[],
+                });
+                let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+                self.allocated_bytes += desc.size_in_bytes();
+                (texture, view)
+            }
+        };
+
+        #[cfg(debug_assertions)]
+        let id = {
+            let id = self.next_id;
+            self.next_id += 1;
+            self.acquired.insert(id, (label.to_string(), self.frame));
+            id
+        };
+        TransientTexture {
+            texture,
+            view,
+            desc,
+            #[cfg(debug_assertions)]
+            id,
+        }
+    }
+
+    /// Hands the texture on to later passes. Passes recorded before this may still read it.
+    pub fn release(&mut self, texture: TransientTexture) {
+        #[cfg(debug_assertions)]
+        self.acquired.remove(&texture.id);
+        self.free
+            .entry(texture.desc)
+            .or_default()
+            .push(FreeTexture {
+                texture: texture.texture,
+                view: texture.view,
+                released: self.frame,
+            });
+    }
+
+    /// Memory taken up by every texture created and not destroyed yet, acquired or free
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocated_bytes
+    }
+
+    /// Destroys every free texture, e.g. after a resize made them all the wrong size
+    pub fn clear(&mut self) {
+        for (desc, textures) in self.free.drain() {
+            for free in textures {
+                free.texture.destroy();
+                self.allocated_bytes -= desc.size_in_bytes();
+            }
+        }
+    }
+}
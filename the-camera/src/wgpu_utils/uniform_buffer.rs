@@ -1,5 +1,7 @@
 use std::marker::PhantomData;
 
+use super::{uniform_layout, uploader::Uploader};
+
 pub struct UniformBuffer<Content> {
     buffer: wgpu::Buffer,
     content_type: PhantomData<Content>, // basically stops the buffer outliving its content type i think
@@ -14,14 +16,30 @@ impl<Content: bytemuck::Pod> UniformBuffer<Content> {
         &type_name[(pos + 1)..]
     }
 
-    /// Create a new wgpu buffer that is a uniform, using the type Content we passed in so can be anything really
-    pub fn new(device: &wgpu::Device) -> Self {
-        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+    /// Every WGSL struct bound as a uniform is padded to 16 bytes, a smaller `Content` leaves the shader reading past
+    /// it. Checked when the buffer is created, so a bad `Content` fails to build instead of rendering garbage.
+    const LAYOUT_CHECK: () = assert!(
+        std::mem::size_of::<Content>().is_multiple_of(uniform_layout::UNIFORM_ALIGNMENT),
+        "UniformBuffer Content has to be padded to a multiple of 16 bytes, see assert_uniform_layout!"
+    );
+
+    fn create_buffer(device: &wgpu::Device, mapped_at_creation: bool) -> wgpu::Buffer {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::LAYOUT_CHECK;
+        let size = std::mem::size_of::<Content>() as u64;
+        uniform_layout::validate_uniform_size(device, size, Self::name());
+
+        device.create_buffer(&wgpu::BufferDescriptor {
             label: Some(&format!("UniformBuffer: {}", Self::name())),
-            size: std::mem::size_of::<Content>() as u64,
+            size,
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+            mapped_at_creation,
+        })
+    }
+
+    /// Create a new wgpu buffer that is a uniform, using the type Content we passed in so can be anything really
+    pub fn new(device: &wgpu::Device) -> Self {
+        let buffer = Self::create_buffer(device, false);
 
         UniformBuffer {
             buffer,
@@ -32,12 +50,7 @@ impl<Content: bytemuck::Pod> UniformBuffer<Content> {
 
     /// Similar to new, but we instantiate the buffer with data
     pub fn new_with_data(device: &wgpu::Device, initial_content: &Content) -> Self {
-        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some(&format!("UniformBuffer: {}", Self::name())),
-            size: std::mem::size_of::<Content>() as u64,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: true,
-        });
+        let buffer = Self::create_buffer(device, true);
 
         let mapped_memory = buffer.slice(..);
         mapped_memory
@@ -64,6 +77,21 @@ impl<Content: bytemuck::Pod> UniformBuffer<Content> {
         self.previous_content = new_content.to_vec();
     }
 
+    /// Like [UniformBuffer::update_content], batching the write into `uploader` instead of writing through the queue
+    pub fn stage_content(
+        &mut self,
+        uploader: &mut Uploader,
+        device: &wgpu::Device,
+        content: Content,
+    ) {
+        let new_content = bytemuck::bytes_of(&content);
+        if self.previous_content == new_content {
+            return;
+        }
+        uploader.write_buffer(device, &self.buffer, 0, new_content);
+        self.previous_content = new_content.to_vec();
+    }
+
     /// Return the binding resource, usually to be passed to the gpu through the command queue
     pub fn binding_resource(&self) -> wgpu::BindingResource {
         self.buffer.as_entire_binding()
@@ -0,0 +1,66 @@
+//! Checks that Rust structs have the layout WGSL expects of them in uniform buffers.
+//!
+//! wgpu copies `Content`s byte for byte, so a field the shader expects at another offset reads whatever lies there,
+//! and only some backends' validation notices. In uniform buffers a WGSL struct is padded to a multiple of 16 bytes
+//! and `vec3`, `vec4`, matrices and nested structs start at multiples of 16, while `#[repr(C)]` only aligns fields to
+//! their Rust type. vec3s are the usual trap, a `[f32; 3]` can start anywhere a `f32` can.
+
+/// Size multiple and largest alignment of structs in the uniform address space
+pub const UNIFORM_ALIGNMENT: usize = 16;
+
+/// WGSL alignment of `f32`, `u32` and `i32` fields
+pub const ALIGN_SCALAR: usize = 4;
+/// WGSL alignment of `vec2` fields
+pub const ALIGN_VEC2: usize = 8;
+/// WGSL alignment of `vec3`, `vec4`, matrix, array and struct fields in uniform buffers
+pub const ALIGN_VEC4: usize = 16;
+
+/// Fails the build unless `$ty` is padded to a multiple of 16 bytes and every listed field starts at a multiple of
+/// its WGSL alignment, one of the `ALIGN_` constants in this module or a number:
+///
+/// ```ignore
+/// assert_uniform_layout!(GradientUBOContent { top: ALIGN_VEC4, bottom: ALIGN_VEC4 });
+/// ```
+///
+/// Fields left out aren't checked, explicit padding doesn't need to be listed.
+#[macro_export]
+macro_rules! assert_uniform_layout {
+    ($ty:ty { $($field:ident: $align:expr),* $(,)? }) => {
+        const _: () = {
+            #[allow(unused_imports)]
+            use $crate::wgpu_utils::uniform_layout::*;
+            assert!(
+                ::std::mem::size_of::<$ty>().is_multiple_of(UNIFORM_ALIGNMENT),
+                concat!(
+                    "Uniform struct ",
+                    stringify!($ty),
+                    " has to be padded to a multiple of 16 bytes like WGSL does!"
+                )
+            );
+            $(
+                assert!(
+                    ::std::mem::offset_of!($ty, $field).is_multiple_of($align),
+                    concat!(
+                        "Field ",
+                        stringify!($ty),
+                        "::",
+                        stringify!($field),
+                        " isn't at a multiple of its WGSL alignment, add padding before it!"
+                    )
+                );
+            )*
+        };
+    };
+}
+
+/// Panics with the type's name if a uniform binding of `size` bytes can't work on `device`.
+///
+/// Zero sized bindings aren't allowed and the device limits how large one can be, 16 KiB on WebGL2.
+pub fn validate_uniform_size(device: &wgpu::Device, size: u64, name: &str) {
+    assert!(size > 0, "Uniform buffer content {name} is zero sized!");
+    let max = device.limits().max_uniform_buffer_binding_size as u64;
+    assert!(
+        size <= max,
+        "Uniform buffer content {name} is {size} bytes, the device binds at most {max}!"
+    );
+}
@@ -0,0 +1,70 @@
+use wgpu::util::StagingBelt;
+
+/// Size of the staging buffers, writes larger than this get a chunk of their own
+const CHUNK_SIZE: u64 = 256 * 1024;
+
+/// Batches CPU to GPU buffer writes into copies from reusable staging buffers, recorded into one command buffer.
+///
+/// Each frame: write everything, then submit [Uploader::finish] before the command buffers using the data, then
+/// [Uploader::recall] so the staging buffers can be reused once the GPU is done with them.
+pub struct Uploader {
+    belt: StagingBelt,
+    encoder: Option<wgpu::CommandEncoder>,
+    written: u64,
+}
+
+impl Default for Uploader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Uploader {
+    pub fn new() -> Self {
+        Uploader {
+            belt: StagingBelt::new(CHUNK_SIZE),
+            encoder: None,
+            written: 0,
+        }
+    }
+
+    /// Copies `data` into `target` at `offset` when the command buffer from [Uploader::finish] runs. `target` needs
+    /// [wgpu::BufferUsages::COPY_DST] and offset and length have to be multiples of [wgpu::COPY_BUFFER_ALIGNMENT].
+    pub fn write_buffer(
+        &mut self,
+        device: &wgpu::Device,
+        target: &wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        data: &[u8],
+    ) {
+        let Some(size) = wgpu::BufferSize::new(data.len() as u64) else {
+            return;
+        };
+        let encoder = self.encoder.get_or_insert_with(|| {
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Upload Encoder"),
+            })
+        });
+        self.belt
+            .write_buffer(encoder, target, offset, size, device)
+            .copy_from_slice(data);
+        self.written += size.get();
+    }
+
+    /// Bytes written since the last call
+    pub fn take_written_bytes(&mut self) -> u64 {
+        std::mem::take(&mut self.written)
+    }
+
+    /// The copies written since the last call, None if there were none. Has to be submitted before anything reading
+    /// the written buffers.
+    pub fn finish(&mut self) -> Option<wgpu::CommandBuffer> {
+        self.belt.finish();
+        self.encoder.take().map(wgpu::CommandEncoder::finish)
+    }
+
+    /// Call after submitting [Uploader::finish], staging buffers become reusable once the GPU has copied out of them
+    pub fn recall(&mut self) {
+        self.belt.recall();
+    }
+}
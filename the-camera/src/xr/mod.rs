@@ -0,0 +1,109 @@
+//! Stereo rendering for VR and AR headsets. The engine draws both eyes into a pair of render targets during every
+//! update, each from the view the headset reported for it, and picks up the controllers' poses as input:
+//!
+//! ```ignore
+//! let stereo = engine.add_stereo_targets(width, height);
+//! // Every frame, with the views located for the predicted display time
+//! engine.set_eye_views(&stereo, [left_view, right_view]);
+//! engine.process_input_event(window_id, &InputEvent::ControllerPose { hand: Hand::Left, pose: Some(pose) });
+//! engine.update();
+//! // Copy stereo.left and stereo.right into the swapchain images and submit them
+//! ```
+//!
+//! The types follow OpenXR's, `XrView` and `XrPosef` map onto [EyeView] and [Pose] field by field.
+//!
+//! With the `xr` feature the engine runs the OpenXR session itself. [RenderEngineBuilder::build_xr] creates the
+//! device through the runtime along with an [XrSession] that does the above every frame:
+//!
+//! ```ignore
+//! let (mut engine, mut session) = RenderEngineBuilder::new().build_xr()?;
+//! while !session.is_exiting() {
+//!     if let Some(frame) = session.begin_frame(&mut engine)? {
+//!         engine.update();
+//!         session.end_frame(&mut engine, frame)?;
+//!     }
+//! }
+//! ```
+//!
+//! [RenderEngineBuilder::build_xr]: crate::render_engine_builder::RenderEngineBuilder::build_xr
+
+#[cfg(feature = "xr")]
+mod session;
+
+use cgmath::{Matrix4, Quaternion, Vector3};
+
+use crate::{
+    camera::{
+        camera::{convert_matrix4_to_array, CameraUniform},
+        orbit_camera::OPENGL_TO_WGPU_DEPTH,
+    },
+    render_target::RenderTargetHandle,
+};
+
+#[cfg(feature = "xr")]
+pub use session::{XrError, XrFrame, XrSession};
+
+/// Where something tracked is in the app's reference space, y up and in meters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pose {
+    pub position: Vector3<f32>,
+    pub orientation: Quaternion<f32>,
+}
+
+/// Angles in radians from the view direction to the edges of an eye's view, negative to the left and down
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fov {
+    pub left: f32,
+    pub right: f32,
+    pub up: f32,
+    pub down: f32,
+}
+
+/// Where an eye is and what it sees
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EyeView {
+    pub pose: Pose,
+    pub fov: Fov,
+}
+
+impl EyeView {
+    /// The camera uniform drawing from this eye, with the near and far planes `near` and `far` meters away
+    pub(crate) fn uniform(&self, near: f32, far: f32) -> CameraUniform {
+        let Pose {
+            position,
+            orientation,
+        } = self.pose;
+        // Headsets look down -z like the engine's cameras
+        let view = Matrix4::from(orientation.conjugate()) * Matrix4::from_translation(-position);
+        // Views are usually lopsided, wider towards the outside of each eye
+        let projection = cgmath::frustum(
+            near * self.fov.left.tan(),
+            near * self.fov.right.tan(),
+            near * self.fov.down.tan(),
+            near * self.fov.up.tan(),
+            near,
+            far,
+        );
+        CameraUniform {
+            view_position: [position.x, position.y, position.z, 1.0],
+            view_proj: convert_matrix4_to_array(OPENGL_TO_WGPU_DEPTH * projection * view),
+        }
+    }
+}
+
+/// A tracked controller
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Hand {
+    Left,
+    Right,
+}
+
+/// The render targets both eyes are drawn into, see [crate::render_engine::RenderEngine::add_stereo_targets]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StereoTargets {
+    pub left: RenderTargetHandle,
+    pub right: RenderTargetHandle,
+    /// Distance of the near clipping plane in meters
+    pub near: f32,
+    pub far: f32,
+}
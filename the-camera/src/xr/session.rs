@@ -0,0 +1,674 @@
+//! The OpenXR session behind [crate::render_engine_builder::RenderEngineBuilder::build_xr], see the
+//! [module docs](super).
+//!
+//! The runtime picks the GPU the headset is connected to and creates the Vulkan instance and device itself, through
+//! `XR_KHR_vulkan_enable2`, so the engine's device is built from those raw handles instead of by wgpu. Both eyes
+//! share one swapchain with a layer each, which the eyes' render targets are copied into at the end of every frame.
+
+use std::{error::Error, ffi::CStr, fmt};
+
+use ash::vk::{self, Handle};
+use cgmath::{Quaternion, Vector3};
+use openxr as xr;
+use wgpu::hal::api::Vulkan;
+
+use super::{EyeView, Fov, Hand, Pose, StereoTargets};
+use crate::{
+    input::InputEvent,
+    render_engine::RenderEngine,
+    render_engine_builder::{DeviceReport, RenderEngineBuilder},
+};
+
+const VIEW_TYPE: xr::ViewConfigurationType = xr::ViewConfigurationType::PRIMARY_STEREO;
+
+/// The oldest Vulkan the engine's device goes with
+const VULKAN_VERSION: u32 = vk::API_VERSION_1_1;
+
+/// Swapchain formats the engine can draw in, the preferred first
+const FORMATS: [(vk::Format, wgpu::TextureFormat); 2] = [
+    (
+        vk::Format::R8G8B8A8_SRGB,
+        wgpu::TextureFormat::Rgba8UnormSrgb,
+    ),
+    (
+        vk::Format::B8G8R8A8_SRGB,
+        wgpu::TextureFormat::Bgra8UnormSrgb,
+    ),
+];
+
+/// Why a session couldn't be set up or a frame went wrong
+#[derive(Debug)]
+pub enum XrError {
+    /// No runtime is installed, or it couldn't be loaded
+    Loader(String),
+    /// A call into the runtime failed, e.g. `ERROR_FORM_FACTOR_UNAVAILABLE` while no headset is connected
+    Runtime(xr::sys::Result),
+    Vulkan(vk::Result),
+    /// The runtime or the GPU lacks something the engine needs
+    Unsupported(String),
+}
+
+impl fmt::Display for XrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XrError::Loader(message) => write!(f, "Failed to load the OpenXR runtime: {message}"),
+            XrError::Runtime(result) => write!(f, "OpenXR: {result}"),
+            XrError::Vulkan(result) => write!(f, "Vulkan: {result}"),
+            XrError::Unsupported(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl Error for XrError {}
+
+impl From<xr::sys::Result> for XrError {
+    fn from(result: xr::sys::Result) -> Self {
+        XrError::Runtime(result)
+    }
+}
+
+impl From<vk::Result> for XrError {
+    fn from(result: vk::Result) -> Self {
+        XrError::Vulkan(result)
+    }
+}
+
+fn unsupported(message: impl fmt::Display) -> XrError {
+    XrError::Unsupported(message.to_string())
+}
+
+impl From<xr::Posef> for Pose {
+    fn from(pose: xr::Posef) -> Self {
+        let xr::Quaternionf { x, y, z, w } = pose.orientation;
+        let xr::Vector3f {
+            x: px,
+            y: py,
+            z: pz,
+        } = pose.position;
+        Pose {
+            position: Vector3::new(px, py, pz),
+            orientation: Quaternion::new(w, x, y, z),
+        }
+    }
+}
+
+impl From<xr::Fovf> for Fov {
+    fn from(fov: xr::Fovf) -> Self {
+        Fov {
+            left: fov.angle_left,
+            right: fov.angle_right,
+            up: fov.angle_up,
+            down: fov.angle_down,
+        }
+    }
+}
+
+impl From<xr::View> for EyeView {
+    fn from(view: xr::View) -> Self {
+        EyeView {
+            pose: view.pose.into(),
+            fov: view.fov.into(),
+        }
+    }
+}
+
+/// The Vulkan objects the runtime created for the engine, wrapped for wgpu
+pub(crate) struct XrDevice {
+    pub instance: wgpu::Instance,
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub report: DeviceReport,
+}
+
+/// A running OpenXR session drawing the engine's [StereoTargets], created with
+/// [RenderEngineBuilder::build_xr]
+pub struct XrSession {
+    /// Wrap the swapchain's images, which the runtime owns, so they go before it
+    images: Vec<wgpu::Texture>,
+    swapchain: xr::Swapchain<xr::Vulkan>,
+    resolution: (u32, u32),
+    targets: StereoTargets,
+
+    action_set: xr::ActionSet,
+    controllers: [(Hand, xr::Space); 2],
+    stage: xr::Space,
+    frame_stream: xr::FrameStream<xr::Vulkan>,
+    frame_waiter: xr::FrameWaiter,
+    session: xr::Session<xr::Vulkan>,
+    blend_mode: xr::EnvironmentBlendMode,
+    events: xr::EventDataBuffer,
+    /// Between the runtime saying the session is ready and it stopping it
+    running: bool,
+    exiting: bool,
+    instance: xr::Instance,
+}
+
+/// A frame the headset waits for, from [XrSession::begin_frame] to [XrSession::end_frame]
+pub struct XrFrame {
+    state: xr::FrameState,
+    views: Vec<xr::View>,
+}
+
+impl RenderEngineBuilder {
+    /// Connects to the OpenXR runtime and creates the device on the GPU the headset is connected to, with an
+    /// [XrSession] drawing the engine's stereo targets into the headset, see [crate::xr]. Fails while no runtime is
+    /// installed or no headset is connected.
+    ///
+    /// A lost device isn't recovered from in a session, the engine goes on with a device of its own and the headset
+    /// shows nothing. Drop the session before the engine, its swapchain lives on the engine's device.
+    pub fn build_xr(self) -> Result<(RenderEngine, XrSession), XrError> {
+        // SAFETY: the loader is only used through the instance created from it
+        let entry = unsafe { xr::Entry::load() }.map_err(|err| XrError::Loader(err.to_string()))?;
+        if !entry.enumerate_extensions()?.khr_vulkan_enable2 {
+            return Err(unsupported("The OpenXR runtime can't drive Vulkan"));
+        }
+        let mut extensions = xr::ExtensionSet::default();
+        extensions.khr_vulkan_enable2 = true;
+        let instance = entry.create_instance(
+            &xr::ApplicationInfo {
+                application_name: "the-camera",
+                application_version: 0,
+                engine_name: "the-camera",
+                engine_version: 0,
+                api_version: xr::Version::new(1, 0, 0),
+            },
+            &extensions,
+            &[],
+        )?;
+        let system = instance.system(xr::FormFactor::HEAD_MOUNTED_DISPLAY)?;
+        let blend_mode = *instance
+            .enumerate_environment_blend_modes(system, VIEW_TYPE)?
+            .first()
+            .ok_or_else(|| unsupported("The headset has no way to show images"))?;
+
+        let (device, vk_handles) = open_device(&self, &instance, system)?;
+        // SAFETY: the handles are the ones the runtime created the device from, for one queue of that family
+        let (session, frame_waiter, frame_stream) = unsafe {
+            instance.create_session::<xr::Vulkan>(
+                system,
+                &xr::vulkan::SessionCreateInfo {
+                    instance: vk_handles.instance.as_raw() as _,
+                    physical_device: vk_handles.physical_device.as_raw() as _,
+                    device: vk_handles.device.as_raw() as _,
+                    queue_family_index: vk_handles.queue_family_index,
+                    queue_index: 0,
+                },
+            )
+        }?;
+
+        let swapchain_formats = session.enumerate_swapchain_formats()?;
+        let (vk_format, format) = FORMATS
+            .into_iter()
+            .find(|(vk_format, _)| swapchain_formats.contains(&(vk_format.as_raw() as _)))
+            .ok_or_else(|| unsupported("The headset takes none of the engine's formats"))?;
+        let views = instance.enumerate_view_configuration_views(system, VIEW_TYPE)?;
+        let view = views
+            .first()
+            .ok_or_else(|| unsupported("The headset has no views"))?;
+        let resolution = (
+            view.recommended_image_rect_width,
+            view.recommended_image_rect_height,
+        );
+        let swapchain = session.create_swapchain(&xr::SwapchainCreateInfo {
+            create_flags: xr::SwapchainCreateFlags::EMPTY,
+            usage_flags: xr::SwapchainUsageFlags::COLOR_ATTACHMENT
+                | xr::SwapchainUsageFlags::TRANSFER_DST,
+            format: vk_format.as_raw() as _,
+            sample_count: 1,
+            width: resolution.0,
+            height: resolution.1,
+            face_count: 1,
+            array_size: 2,
+            mip_count: 1,
+        })?;
+        let images = swapchain
+            .enumerate_images()?
+            .into_iter()
+            .map(|image| wrap_swapchain_image(&device.device, image, resolution, format))
+            .collect();
+
+        let action_set = instance.create_action_set("controllers", "Controllers", 0)?;
+        let left = action_set.create_action::<xr::Posef>("left_grip", "Left grip", &[])?;
+        let right = action_set.create_action::<xr::Posef>("right_grip", "Right grip", &[])?;
+        // Every runtime maps its controllers onto the simple profile
+        instance.suggest_interaction_profile_bindings(
+            instance.string_to_path("/interaction_profiles/khr/simple_controller")?,
+            &[
+                xr::Binding::new(
+                    &left,
+                    instance.string_to_path("/user/hand/left/input/grip/pose")?,
+                ),
+                xr::Binding::new(
+                    &right,
+                    instance.string_to_path("/user/hand/right/input/grip/pose")?,
+                ),
+            ],
+        )?;
+        session.attach_action_sets(&[&action_set])?;
+        let controllers = [
+            (
+                Hand::Left,
+                left.create_space(session.clone(), xr::Path::NULL, xr::Posef::IDENTITY)?,
+            ),
+            (
+                Hand::Right,
+                right.create_space(session.clone(), xr::Path::NULL, xr::Posef::IDENTITY)?,
+            ),
+        ];
+        // Seated setups only have a local space, its origin is where the headset was at the start
+        let stage = session
+            .create_reference_space(xr::ReferenceSpaceType::STAGE, xr::Posef::IDENTITY)
+            .or_else(|_| {
+                session.create_reference_space(xr::ReferenceSpaceType::LOCAL, xr::Posef::IDENTITY)
+            })?;
+
+        let mut engine = RenderEngine::from_device(
+            device.instance,
+            device.adapter,
+            device.device,
+            device.queue,
+            format,
+            self,
+            device.report,
+        );
+        let targets = engine.add_stereo_targets(resolution.0, resolution.1);
+        let session = XrSession {
+            images,
+            swapchain,
+            resolution,
+            targets,
+            action_set,
+            controllers,
+            stage,
+            frame_stream,
+            frame_waiter,
+            session,
+            blend_mode,
+            events: xr::EventDataBuffer::new(),
+            running: false,
+            exiting: false,
+            instance,
+        };
+        Ok((engine, session))
+    }
+}
+
+/// The raw handles the session is created with
+struct VulkanHandles {
+    instance: vk::Instance,
+    physical_device: vk::PhysicalDevice,
+    device: vk::Device,
+    queue_family_index: u32,
+}
+
+/// Has the runtime create the Vulkan instance and device with what wgpu needs of them
+fn open_device(
+    builder: &RenderEngineBuilder,
+    xr_instance: &xr::Instance,
+    system: xr::SystemId,
+) -> Result<(XrDevice, VulkanHandles), XrError> {
+    let requirements = xr_instance.graphics_requirements::<xr::Vulkan>(system)?;
+    let version = xr::Version::new(1, 1, 0);
+    if requirements.min_api_version_supported > version
+        || requirements.max_api_version_supported.major() < 1
+    {
+        return Err(unsupported(format!(
+            "The OpenXR runtime needs Vulkan {} to {}",
+            requirements.min_api_version_supported, requirements.max_api_version_supported
+        )));
+    }
+
+    // SAFETY: the Vulkan loader is only used through the instance created from it, and every handle handed to wgpu
+    // is created by the runtime with the extensions and features wgpu asked for
+    unsafe {
+        let entry = ash::Entry::load().map_err(|err| XrError::Loader(err.to_string()))?;
+        let flags = wgpu::InstanceFlags::empty();
+        let extensions =
+            wgpu::hal::vulkan::Instance::desired_extensions(&entry, VULKAN_VERSION, flags)
+                .map_err(unsupported)?;
+        let extension_names: Vec<_> = extensions.iter().map(|name| name.as_ptr()).collect();
+        let application_info = vk::ApplicationInfo::default()
+            .application_name(c"the-camera")
+            .engine_name(c"the-camera")
+            .api_version(VULKAN_VERSION);
+        let instance_info = vk::InstanceCreateInfo::default()
+            .application_info(&application_info)
+            .enabled_extension_names(&extension_names);
+        let get_instance_proc_addr = entry.static_fn().get_instance_proc_addr;
+        let raw_instance = xr_instance
+            .create_vulkan_instance(
+                system,
+                std::mem::transmute::<
+                    vk::PFN_vkGetInstanceProcAddr,
+                    xr::sys::platform::VkGetInstanceProcAddr,
+                >(get_instance_proc_addr),
+                &instance_info as *const _ as *const _,
+            )?
+            .map_err(vk::Result::from_raw)?;
+        let vk_instance =
+            ash::Instance::load(entry.static_fn(), vk::Instance::from_raw(raw_instance as _));
+        let hal_instance = wgpu::hal::vulkan::Instance::from_raw(
+            entry,
+            vk_instance.clone(),
+            VULKAN_VERSION,
+            0,
+            None,
+            extensions,
+            flags,
+            false,
+            None,
+        )
+        .map_err(unsupported)?;
+        let physical_device = vk::PhysicalDevice::from_raw(
+            xr_instance.vulkan_graphics_device(system, raw_instance)? as _,
+        );
+        let exposed = hal_instance
+            .expose_adapter(physical_device)
+            .ok_or_else(|| unsupported("wgpu can't use the headset's GPU"))?;
+
+        let instance = wgpu::Instance::from_hal::<Vulkan>(hal_instance);
+        let adapter = instance.create_adapter_from_hal(exposed);
+        let descriptor = builder.device_descriptor(&adapter);
+        let queue_family_index = vk_instance
+            .get_physical_device_queue_family_properties(physical_device)
+            .iter()
+            .position(|family| family.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+            .ok_or_else(|| unsupported("The headset's GPU has no graphics queue"))?
+            as u32;
+
+        let (open_device, raw_device) = adapter.as_hal::<Vulkan, _, _>(|hal_adapter| {
+            let hal_adapter = hal_adapter.expect("The adapter was created from a Vulkan one!");
+            let features = descriptor.required_features;
+            let extensions: Vec<&'static CStr> = hal_adapter.required_device_extensions(features);
+            let mut physical_features = hal_adapter.physical_device_features(&extensions, features);
+            let family_infos = [vk::DeviceQueueCreateInfo::default()
+                .queue_family_index(queue_family_index)
+                .queue_priorities(&[1.0])];
+            let extension_names: Vec<_> = extensions.iter().map(|name| name.as_ptr()).collect();
+            let device_info = physical_features.add_to_device_create(
+                vk::DeviceCreateInfo::default()
+                    .queue_create_infos(&family_infos)
+                    .enabled_extension_names(&extension_names),
+            );
+            let raw_device = xr_instance
+                .create_vulkan_device(
+                    system,
+                    std::mem::transmute::<
+                        vk::PFN_vkGetInstanceProcAddr,
+                        xr::sys::platform::VkGetInstanceProcAddr,
+                    >(get_instance_proc_addr),
+                    physical_device.as_raw() as _,
+                    &device_info as *const _ as *const _,
+                )?
+                .map_err(vk::Result::from_raw)?;
+            let vk_device =
+                ash::Device::load(vk_instance.fp_v1_0(), vk::Device::from_raw(raw_device as _));
+            let open_device = hal_adapter
+                .device_from_raw(
+                    vk_device,
+                    None,
+                    &extensions,
+                    features,
+                    &descriptor.memory_hints,
+                    queue_family_index,
+                    0,
+                )
+                .map_err(unsupported)?;
+            Ok::<_, XrError>((open_device, vk::Device::from_raw(raw_device as _)))
+        })?;
+        let (device, queue) = adapter
+            .create_device_from_hal::<Vulkan>(open_device, &descriptor, None)
+            .map_err(unsupported)?;
+
+        let report = builder.device_report(&adapter, &device);
+        let handles = VulkanHandles {
+            instance: vk_instance.handle(),
+            physical_device,
+            device: raw_device,
+            queue_family_index,
+        };
+        let device = XrDevice {
+            instance,
+            adapter,
+            device,
+            queue,
+            report,
+        };
+        Ok((device, handles))
+    }
+}
+
+/// A swapchain image as a wgpu texture with a layer per eye, still owned by the runtime
+fn wrap_swapchain_image(
+    device: &wgpu::Device,
+    image: u64,
+    (width, height): (u32, u32),
+    format: wgpu::TextureFormat,
+) -> wgpu::Texture {
+    let size = wgpu::Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 2,
+    };
+    // SAFETY: the runtime created the image as described, and wgpu leaves destroying it to the runtime
+    unsafe {
+        let hal_texture = wgpu::hal::vulkan::Device::texture_from_raw(
+            vk::Image::from_raw(image),
+            &wgpu::hal::TextureDescriptor {
+                label: Some("xr swapchain"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::hal::TextureUses::COLOR_TARGET | wgpu::hal::TextureUses::COPY_DST,
+                memory_flags: wgpu::hal::MemoryFlags::empty(),
+                view_formats: Vec::new(),
+            },
+            Some(Box::new(|| ())),
+        );
+        device.create_texture_from_hal::<Vulkan>(
+            hal_texture,
+            &wgpu::TextureDescriptor {
+                label: Some("xr swapchain"),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+        )
+    }
+}
+
+impl XrSession {
+    /// The render targets the eyes are drawn into, at the resolution the runtime recommends
+    pub fn targets(&self) -> StereoTargets {
+        self.targets
+    }
+
+    /// Set the near and far planes of the eyes' views, in meters
+    pub fn set_clip_planes(&mut self, near: f32, far: f32) {
+        self.targets.near = near;
+        self.targets.far = far;
+    }
+
+    /// Once the runtime ended the session, e.g. because the user quit from the headset's menu. The app should exit.
+    pub fn is_exiting(&self) -> bool {
+        self.exiting
+    }
+
+    /// Asks the runtime to end the session, [XrSession::is_exiting] turns true once it did
+    pub fn request_exit(&self) -> Result<(), XrError> {
+        Ok(self.session.request_exit()?)
+    }
+
+    /// Waits until the headset wants the next frame, then points the eyes at where they'll be when it's shown and
+    /// hands the controllers' poses to the engine's [crate::input::Input]. None if nothing should be drawn, e.g.
+    /// while the headset is taken off, in which case the app skips [RenderEngine::update] for it.
+    pub fn begin_frame(&mut self, engine: &mut RenderEngine) -> Result<Option<XrFrame>, XrError> {
+        self.poll_events()?;
+        if !self.running {
+            // The runtime doesn't want frames yet, this keeps the app from spinning
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            return Ok(None);
+        }
+        let state = self.frame_waiter.wait()?;
+        self.frame_stream.begin()?;
+        if !state.should_render {
+            self.frame_stream
+                .end(state.predicted_display_time, self.blend_mode, &[])?;
+            return Ok(None);
+        }
+
+        let time = state.predicted_display_time;
+        let (_, views) = self.session.locate_views(VIEW_TYPE, time, &self.stage)?;
+        let [left, right] = views[..] else {
+            return Err(unsupported("The headset has more or less than two views"));
+        };
+        engine.set_eye_views(&self.targets, [left.into(), right.into()]);
+
+        self.session
+            .sync_actions(&[xr::ActiveActionSet::new(&self.action_set)])?;
+        let tracked =
+            xr::SpaceLocationFlags::POSITION_VALID | xr::SpaceLocationFlags::ORIENTATION_VALID;
+        for (hand, space) in &self.controllers {
+            let location = space.locate(&self.stage, time)?;
+            let pose = location
+                .location_flags
+                .contains(tracked)
+                .then(|| location.pose.into());
+            engine.input_mut().process_input_event(
+                winit::window::WindowId::dummy(),
+                &InputEvent::ControllerPose { hand: *hand, pose },
+            );
+        }
+        Ok(Some(XrFrame { state, views }))
+    }
+
+    /// Hands what the last [RenderEngine::update] drew into the eyes' targets to the headset
+    pub fn end_frame(&mut self, engine: &mut RenderEngine, frame: XrFrame) -> Result<(), XrError> {
+        let index = self.swapchain.acquire_image()?;
+        self.swapchain.wait_image(xr::Duration::INFINITE)?;
+        engine.flush();
+
+        let image = &self.images[index as usize];
+        let mut encoder = engine
+            .device()
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("xr") });
+        let (width, height) = self.resolution;
+        for (layer, handle) in [self.targets.left, self.targets.right]
+            .into_iter()
+            .enumerate()
+        {
+            let Some(target) = engine.render_target(handle) else {
+                continue;
+            };
+            encoder.copy_texture_to_texture(
+                target.color_texture().as_image_copy(),
+                wgpu::ImageCopyTexture {
+                    texture: image,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+            // The runtime takes the image back as a color attachment, an empty pass leaves it in that layout
+            let view = image.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer as u32,
+                array_layer_count: Some(1),
+                ..Default::default()
+            });
+            encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("xr release"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+        }
+        engine.queue().submit([encoder.finish()]);
+        self.swapchain.release_image()?;
+
+        let rect = xr::Rect2Di {
+            offset: xr::Offset2Di { x: 0, y: 0 },
+            extent: xr::Extent2Di {
+                width: width as i32,
+                height: height as i32,
+            },
+        };
+        let views: Vec<_> = frame
+            .views
+            .iter()
+            .enumerate()
+            .map(|(layer, view)| {
+                xr::CompositionLayerProjectionView::new()
+                    .pose(view.pose)
+                    .fov(view.fov)
+                    .sub_image(
+                        xr::SwapchainSubImage::new()
+                            .swapchain(&self.swapchain)
+                            .image_array_index(layer as u32)
+                            .image_rect(rect),
+                    )
+            })
+            .collect();
+        self.frame_stream.end(
+            frame.state.predicted_display_time,
+            self.blend_mode,
+            &[&xr::CompositionLayerProjection::new()
+                .space(&self.stage)
+                .views(&views)],
+        )?;
+        Ok(())
+    }
+
+    /// Follows the session through the states the runtime moves it through
+    fn poll_events(&mut self) -> Result<(), XrError> {
+        while let Some(event) = self.instance.poll_event(&mut self.events)? {
+            match event {
+                xr::Event::SessionStateChanged(change) => match change.state() {
+                    xr::SessionState::READY => {
+                        self.session.begin(VIEW_TYPE)?;
+                        self.running = true;
+                    }
+                    xr::SessionState::STOPPING => {
+                        self.session.end()?;
+                        self.running = false;
+                    }
+                    xr::SessionState::EXITING | xr::SessionState::LOSS_PENDING => {
+                        self.running = false;
+                        self.exiting = true;
+                    }
+                    _ => {}
+                },
+                xr::Event::InstanceLossPending(_) => {
+                    self.running = false;
+                    self.exiting = true;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,21 @@
+//! Golden images of small scenes, see [the_camera::snapshot]. They need an adapter, a software one like llvmpipe does.
+
+use the_camera::{
+    camera::orbit_camera::OrbitCamera,
+    scene::{Renderable, Transform},
+    snapshot::SnapshotHarness,
+};
+
+#[test]
+fn cube() {
+    let camera = OrbitCamera::new(3.0, 0.3, 0.5, cgmath::Vector3::new(0.0, 0.0, 0.0), 1.0);
+    let mut harness = SnapshotHarness::new(64, 64, camera, "tests/snapshots");
+    let engine = harness.engine();
+    let cube = Renderable::new(
+        engine.cube_mesh(),
+        engine.default_material(),
+        &Transform::default(),
+    );
+    engine.set_renderables(vec![cube]);
+    harness.assert_snapshot("cube");
+}